@@ -0,0 +1,150 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Days shown by `neywa logs --activity` without `--today`
+const DEFAULT_DAYS: i64 = 7;
+
+/// What a run did, collected while it streams
+#[derive(Debug, Clone, Default)]
+pub struct RunTrace {
+    /// Every tool call, as "Tool: detail"
+    pub tools: Vec<String>,
+    pub response: String,
+    pub error: Option<String>,
+}
+
+/// One run, in full (unlike the truncated #logs posts)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivityRecord {
+    pub ts: DateTime<Local>,
+    pub user_id: u64,
+    pub user_name: String,
+    pub channel_id: u64,
+    pub backend: String,
+    /// "chat" or "plan"
+    pub kind: String,
+    pub prompt: String,
+    #[serde(default)]
+    pub tools: Vec<String>,
+    #[serde(default)]
+    pub response: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// "ok", "error" or "cancelled"
+    pub outcome: String,
+    pub cancelled: bool,
+    pub duration_ms: u64,
+}
+
+/// Directory holding daily activity files
+fn activity_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("neywa")
+        .join("activity")
+}
+
+/// Activity file for a given day (rotated daily: YYYY-MM-DD.jsonl)
+fn activity_file_for(date: NaiveDate) -> PathBuf {
+    activity_dir().join(format!("{}.jsonl", date.format("%Y-%m-%d")))
+}
+
+/// Serializes writers
+static WRITER: Mutex<()> = Mutex::new(());
+
+/// Append a record synchronously
+fn append(record: &ActivityRecord) -> Result<()> {
+    let path = activity_file_for(record.ts.date_naive());
+    let _guard = WRITER.lock().unwrap_or_else(|e| e.into_inner());
+    std::fs::create_dir_all(activity_dir())?;
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open activity log {:?}", path))?;
+    writeln!(file, "{}", serde_json::to_string(record)?)?;
+    Ok(())
+}
+
+/// Record a run in the background. Best-effort, like the audit log.
+pub fn record(record: ActivityRecord) {
+    let write = move || {
+        if let Err(e) = append(&record) {
+            tracing::warn!("Failed to write activity log: {}", e);
+        }
+    };
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) => {
+            handle.spawn_blocking(write);
+        }
+        Err(_) => write(),
+    }
+}
+
+/// Records of one day, oldest first
+fn records_on(date: NaiveDate) -> Vec<ActivityRecord> {
+    std::fs::read_to_string(activity_file_for(date))
+        .map(|content| {
+            content
+                .lines()
+                .filter_map(|line| serde_json::from_str::<ActivityRecord>(line).ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Print a block of text indented under a heading line
+fn print_block(label: &str, text: &str) {
+    println!("  {}:", label);
+    for line in text.trim().lines() {
+        println!("    {}", line);
+    }
+}
+
+/// `neywa logs --activity` - print full run records, today only or the last week
+pub fn print(today_only: bool) -> Result<()> {
+    let today = Local::now().date_naive();
+    let days = if today_only { 1 } else { DEFAULT_DAYS };
+    let records: Vec<ActivityRecord> = (0..days)
+        .rev()
+        .flat_map(|back| records_on(today - chrono::Duration::days(back)))
+        .collect();
+
+    if records.is_empty() {
+        println!("No activity recorded in {:?}", activity_dir());
+        return Ok(());
+    }
+
+    for r in &records {
+        println!(
+            "{} {} [{}] {}({}) #{} → {} ({:.1}s)",
+            r.ts.format("%Y-%m-%d %H:%M:%S"),
+            r.kind,
+            r.backend,
+            r.user_name,
+            r.user_id,
+            r.channel_id,
+            r.outcome,
+            r.duration_ms as f64 / 1000.0
+        );
+        print_block("prompt", &r.prompt);
+        if !r.tools.is_empty() {
+            println!("  tools:");
+            for tool in &r.tools {
+                println!("    - {}", tool);
+            }
+        }
+        if let Some(error) = &r.error {
+            print_block("error", error);
+        }
+        if !r.response.trim().is_empty() {
+            print_block("response", &r.response);
+        }
+        println!();
+    }
+    Ok(())
+}
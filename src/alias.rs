@@ -0,0 +1,171 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A user-defined `!name` shortcut
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Alias {
+    /// Prompt (or command line, for command aliases) the alias stands for
+    pub expansion: String,
+    /// Expands to another `!command` rather than a prompt
+    #[serde(default)]
+    pub command: bool,
+}
+
+/// Aliases for one guild, by name (without `!`)
+pub type Aliases = BTreeMap<String, Alias>;
+
+/// Longest accepted alias name
+const MAX_NAME_CHARS: usize = 32;
+
+/// Expand `!name [extra]` using `aliases`. Extra text is appended to the expansion.
+/// Expansion happens once: the result is never expanded again, so aliases can't loop.
+/// Names that are built-in commands are never expanded.
+pub fn expand(aliases: &Aliases, content: &str, is_builtin: impl Fn(&str) -> bool) -> Option<String> {
+    let rest = content.strip_prefix('!')?;
+    let (name, extra) = match rest.split_once(char::is_whitespace) {
+        Some((name, extra)) => (name, extra.trim()),
+        None => (rest, ""),
+    };
+    if is_builtin(name) {
+        return None;
+    }
+    let alias = aliases.get(&name.to_lowercase())?;
+    if extra.is_empty() {
+        Some(alias.expansion.clone())
+    } else {
+        Some(format!("{} {}", alias.expansion, extra))
+    }
+}
+
+/// Check an alias name: short, `[a-z0-9_-]`, and not a built-in command
+pub fn validate_name(name: &str, is_builtin: impl Fn(&str) -> bool) -> Result<String, String> {
+    let name = name.trim_start_matches('!').to_lowercase();
+    if name.is_empty() || name.chars().count() > MAX_NAME_CHARS {
+        return Err(format!("Alias names must be 1-{} characters.", MAX_NAME_CHARS));
+    }
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        return Err("Alias names may only use letters, digits, `-` and `_`.".to_string());
+    }
+    if name == "alias" || is_builtin(&name) {
+        return Err(format!("`!{}` is a built-in command and can't be redefined.", name));
+    }
+    Ok(name)
+}
+
+/// Parse the arguments of `!alias add`: `<name> [--command] <text>`, where the
+/// text may be wrapped in double quotes
+pub fn parse_add(args: &str, is_builtin: impl Fn(&str) -> bool) -> Result<(String, Alias), String> {
+    const USAGE: &str = "Usage: `!alias add <name> \"<prompt>\"` or `!alias add <name> --command \"!<command>\"`";
+    let (name, rest) = args.trim().split_once(char::is_whitespace).ok_or(USAGE)?;
+    let name = validate_name(name, &is_builtin)?;
+
+    let rest = rest.trim();
+    let (command, text) = match rest.strip_prefix("--command") {
+        Some(text) => (true, text.trim()),
+        None => (false, rest),
+    };
+    let text = text
+        .strip_prefix('"')
+        .and_then(|t| t.strip_suffix('"'))
+        .unwrap_or(text)
+        .trim();
+    if text.is_empty() {
+        return Err(USAGE.to_string());
+    }
+    if command && !text.starts_with('!') {
+        return Err("Command aliases must expand to a `!command`.".to_string());
+    }
+    if !command && text.starts_with('!') {
+        return Err("That looks like a command; add `--command` to alias it.".to_string());
+    }
+    Ok((name, Alias { expansion: text.to_string(), command }))
+}
+
+/// `!alias list` output
+pub fn describe(aliases: &Aliases) -> String {
+    if aliases.is_empty() {
+        return "No aliases yet. Add one with `!alias add <name> \"<prompt>\"`.".to_string();
+    }
+    let mut lines = vec![format!("**Aliases** ({})", aliases.len())];
+    for (name, alias) in aliases {
+        let kind = if alias.command { "command" } else { "prompt" };
+        lines.push(format!("`!{}` → {} ({})", name, alias.expansion, kind));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn builtin(name: &str) -> bool {
+        matches!(name, "help" | "stop" | "status")
+    }
+
+    fn aliases() -> Aliases {
+        let mut aliases = Aliases::new();
+        aliases.insert("deploy".to_string(), Alias { expansion: "Deploy to staging".to_string(), command: false });
+        aliases.insert("loop".to_string(), Alias { expansion: "!loop again".to_string(), command: true });
+        aliases.insert("st".to_string(), Alias { expansion: "!status".to_string(), command: true });
+        aliases
+    }
+
+    #[test]
+    fn expand_appends_extra_text() {
+        let aliases = aliases();
+        assert_eq!(expand(&aliases, "!deploy", builtin), Some("Deploy to staging".to_string()));
+        assert_eq!(expand(&aliases, "!Deploy  the api ", builtin), Some("Deploy to staging the api".to_string()));
+        assert_eq!(expand(&aliases, "!st", builtin), Some("!status".to_string()));
+        assert_eq!(expand(&aliases, "!unknown", builtin), None);
+        assert_eq!(expand(&aliases, "deploy", builtin), None);
+    }
+
+    #[test]
+    fn expand_is_single_depth() {
+        // An alias expanding to itself comes back as-is rather than recursing
+        let aliases = aliases();
+        let once = expand(&aliases, "!loop", builtin).unwrap();
+        assert_eq!(once, "!loop again");
+    }
+
+    #[test]
+    fn builtins_are_never_shadowed() {
+        let mut aliases = aliases();
+        aliases.insert("help".to_string(), Alias { expansion: "not help".to_string(), command: false });
+        assert_eq!(expand(&aliases, "!help", builtin), None);
+        assert!(validate_name("help", builtin).is_err());
+        assert!(validate_name("!Stop", builtin).is_err());
+        assert!(validate_name("alias", builtin).is_err());
+        assert!(parse_add("status \"hi\"", builtin).is_err());
+    }
+
+    #[test]
+    fn validate_name_normalizes_and_checks_charset() {
+        assert_eq!(validate_name("!Deploy_2", builtin), Ok("deploy_2".to_string()));
+        assert!(validate_name("", builtin).is_err());
+        assert!(validate_name("has space", builtin).is_err());
+        assert!(validate_name("émoji", builtin).is_err());
+        assert!(validate_name(&"a".repeat(MAX_NAME_CHARS + 1), builtin).is_err());
+    }
+
+    #[test]
+    fn parse_add_handles_quotes_and_command_flag() {
+        assert_eq!(
+            parse_add("deploy \"Deploy to \"staging\"\"", builtin),
+            Ok(("deploy".to_string(), Alias { expansion: "Deploy to \"staging\"".to_string(), command: false }))
+        );
+        assert_eq!(
+            parse_add("d Deploy now", builtin),
+            Ok(("d".to_string(), Alias { expansion: "Deploy now".to_string(), command: false }))
+        );
+        assert_eq!(
+            parse_add("st --command \"!status\"", builtin),
+            Ok(("st".to_string(), Alias { expansion: "!status".to_string(), command: true }))
+        );
+        // --command must point at a command, and a command needs --command
+        assert!(parse_add("st --command \"status\"", builtin).is_err());
+        assert!(parse_add("st \"!status\"", builtin).is_err());
+        assert!(parse_add("st \"\"", builtin).is_err());
+        assert!(parse_add("st", builtin).is_err());
+    }
+}
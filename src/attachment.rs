@@ -0,0 +1,503 @@
+use anyhow::Result;
+use serenity::model::channel::Attachment as DiscordAttachment;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+
+/// Attachments downloaded at the same time
+const MAX_PARALLEL_DOWNLOADS: usize = 3;
+
+/// Images larger than this are downscaled before the AI sees them
+/// (Claude rejects images over 5 MB)
+const MAX_IMAGE_BYTES: u64 = 3_500_000;
+
+/// Longest edge of a downscaled image, in pixels
+const MAX_IMAGE_EDGE: u32 = 2000;
+
+/// Image formats recognized by their magic bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageKind {
+    Png,
+    Jpeg,
+    Gif,
+    Webp,
+    /// Declared as `image/*` by Discord but not one of the formats above
+    Other,
+}
+
+impl ImageKind {
+    /// Canonical file extension (None for unrecognized formats)
+    fn extension(&self) -> Option<&'static str> {
+        match self {
+            ImageKind::Png => Some("png"),
+            ImageKind::Jpeg => Some("jpg"),
+            ImageKind::Gif => Some("gif"),
+            ImageKind::Webp => Some("webp"),
+            ImageKind::Other => None,
+        }
+    }
+}
+
+/// A downloaded Discord attachment
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub path: String,
+    pub size: u64,
+    /// Set when the file is an image the AI can view (by magic bytes or content type,
+    /// converted if needed)
+    pub image: Option<ImageKind>,
+    /// Audio such as a Discord voice message, transcribed rather than passed as a path
+    pub audio: bool,
+}
+
+/// Identify an image from its leading bytes
+pub fn sniff_image(bytes: &[u8]) -> Option<ImageKind> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some(ImageKind::Png)
+    } else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some(ImageKind::Jpeg)
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some(ImageKind::Gif)
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some(ImageKind::Webp)
+    } else {
+        None
+    }
+}
+
+/// Decide whether a file is an image. Magic bytes win; the content type
+/// Discord reports is the fallback. The file extension is never trusted.
+pub fn detect_image(content_type: Option<&str>, bytes: &[u8]) -> Option<ImageKind> {
+    sniff_image(bytes).or_else(|| {
+        content_type
+            .filter(|ct| ct.starts_with("image/"))
+            .map(|_| ImageKind::Other)
+    })
+}
+
+/// Whether an attachment is audio (voice messages are `audio/ogg`), by Discord's content type or, failing that, its extension
+pub fn is_audio(content_type: Option<&str>, filename: &str) -> bool {
+    if let Some(ct) = content_type {
+        return ct.starts_with("audio/");
+    }
+    let ext = Path::new(filename)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    matches!(ext.as_str(), "ogg" | "oga" | "opus" | "mp3" | "m4a" | "wav" | "flac")
+}
+
+/// Where attachments are downloaded to
+pub fn attachments_root() -> PathBuf {
+    std::env::temp_dir().join("neywa_attachments")
+}
+
+/// Directory holding one downloaded attachment (per attachment ID, so
+/// same-named files from different messages never collide)
+fn attachment_dir(attachment_id: u64) -> PathBuf {
+    attachments_root().join(attachment_id.to_string())
+}
+
+/// Download one attachment, streaming it to disk
+async fn download(file: &DiscordAttachment) -> Result<Attachment> {
+    let dir = attachment_dir(file.id.get());
+    tokio::fs::create_dir_all(&dir).await?;
+
+    let mut response = reqwest::get(&file.url).await?.error_for_status()?;
+    let part_path = dir.join(format!("{}.part", file.filename));
+    let mut out = tokio::fs::File::create(&part_path).await?;
+    let mut head: Vec<u8> = Vec::new();
+    let mut size = 0u64;
+    while let Some(chunk) = response.chunk().await? {
+        if head.len() < 16 {
+            head.extend_from_slice(&chunk[..chunk.len().min(16 - head.len())]);
+        }
+        size += chunk.len() as u64;
+        out.write_all(&chunk).await?;
+    }
+    out.flush().await?;
+
+    let image = detect_image(file.content_type.as_deref(), &head);
+
+    // Make sure images carry a matching extension so the Read tool treats them as images
+    let mut file_path = dir.join(&file.filename);
+    if let Some(ext) = image.and_then(|kind| kind.extension()) {
+        let current = file_path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let matches = current == ext || (ext == "jpg" && current == "jpeg");
+        if !matches {
+            file_path = dir.join(format!("{}.{}", file.filename, ext));
+        }
+    }
+    tokio::fs::rename(&part_path, &file_path).await?;
+
+    let (file_path, size, image) = match image {
+        Some(kind) => prepare_image(file_path, size, kind).await,
+        None => (file_path, size, None),
+    };
+
+    Ok(Attachment {
+        path: file_path.to_string_lossy().to_string(),
+        size,
+        image,
+        audio: image.is_none() && is_audio(file.content_type.as_deref(), &file.filename),
+    })
+}
+
+/// Get an image into a form the AI can view: unrecognized formats (HEIC, BMP,
+/// ...) become PNG and oversized ones are downscaled to JPEG. GIFs are left
+/// alone since converting would drop their animation. Without a converter the
+/// original is kept, and an unrecognized format is passed on as a plain file.
+async fn prepare_image(path: PathBuf, size: u64, kind: ImageKind) -> (PathBuf, u64, Option<ImageKind>) {
+    let oversized = size > MAX_IMAGE_BYTES;
+    let target = match kind {
+        ImageKind::Other => ImageKind::Png,
+        ImageKind::Png | ImageKind::Jpeg | ImageKind::Webp if oversized => ImageKind::Jpeg,
+        _ => return (path, size, Some(kind)),
+    };
+    let fallback = (kind != ImageKind::Other).then_some(kind);
+    let ext = target.extension().unwrap_or("png");
+    let out = path.with_extension(format!("view.{}", ext));
+    match convert_image(&path, &out, ext, oversized).await {
+        Ok(()) => match tokio::fs::metadata(&out).await {
+            Ok(meta) => (out, meta.len(), Some(target)),
+            Err(_) => (path, size, fallback),
+        },
+        Err(e) => {
+            tracing::warn!("Couldn't convert image {}: {}", path.display(), e);
+            (path, size, fallback)
+        }
+    }
+}
+
+/// Convert an image with `sips` (macOS) or ImageMagick, optionally capping
+/// its longest edge at MAX_IMAGE_EDGE
+async fn convert_image(input: &Path, output: &Path, format: &str, resize: bool) -> Result<()> {
+    let mut cmd = if let Some(sips) = crate::claude::find_cli("sips") {
+        let mut cmd = Command::new(sips);
+        if resize {
+            cmd.arg("-Z").arg(MAX_IMAGE_EDGE.to_string());
+        }
+        let format = if format == "jpg" { "jpeg" } else { format };
+        cmd.args(["-s", "format", format]).arg(input).arg("--out").arg(output);
+        cmd
+    } else {
+        let magick = crate::claude::find_cli("magick")
+            .or_else(|| crate::claude::find_cli("convert"))
+            .ok_or_else(|| anyhow::anyhow!("no image converter found (sips or ImageMagick)"))?;
+        let mut cmd = Command::new(magick);
+        // [0] takes the first frame of multi-page formats
+        cmd.arg(format!("{}[0]", input.display()));
+        if resize {
+            cmd.arg("-resize").arg(format!("{0}x{0}>", MAX_IMAGE_EDGE));
+        }
+        cmd.arg(output);
+        cmd
+    };
+    let output = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .output()
+        .await?;
+    if !output.status.success() {
+        anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+/// Paths of the images among `attachments`, for backends that take images as arguments
+pub fn image_paths(attachments: &[Attachment]) -> Vec<String> {
+    attachments
+        .iter()
+        .filter(|a| a.image.is_some())
+        .map(|a| a.path.clone())
+        .collect()
+}
+
+/// Result of downloading a message's attachments
+#[derive(Debug, Default)]
+pub struct Downloads {
+    pub files: Vec<Attachment>,
+    /// (filename, error) for attachments that failed
+    pub failed: Vec<(String, String)>,
+}
+
+/// Download attachments concurrently (at most 3 at a time), keeping their
+/// original order. One failure doesn't stop the others, and files over
+/// `max_bytes` are reported as failed without being fetched. Returns None if
+/// cancelled, after removing everything written so far.
+pub async fn download_all(
+    files: &[DiscordAttachment],
+    max_bytes: u64,
+    cancel_token: &CancellationToken,
+) -> Option<Downloads> {
+    let semaphore = Arc::new(Semaphore::new(MAX_PARALLEL_DOWNLOADS));
+    let mut tasks = JoinSet::new();
+    for (index, file) in files.iter().cloned().enumerate() {
+        let semaphore = semaphore.clone();
+        tasks.spawn(async move {
+            if file.size as u64 > max_bytes {
+                let error = anyhow::anyhow!(
+                    "too large: {}, the limit is {}",
+                    format_bytes(file.size as u64),
+                    format_bytes(max_bytes)
+                );
+                return (index, file.filename, Err(error));
+            }
+            let _permit = semaphore.acquire_owned().await;
+            let result = download(&file).await;
+            (index, file.filename, result)
+        });
+    }
+
+    let mut results = Vec::with_capacity(files.len());
+    loop {
+        tokio::select! {
+            _ = cancel_token.cancelled() => {
+                tasks.abort_all();
+                while tasks.join_next().await.is_some() {}
+                for file in files {
+                    cleanup(&attachment_dir(file.id.get())).await;
+                }
+                return None;
+            }
+            next = tasks.join_next() => match next {
+                Some(Ok(result)) => results.push(result),
+                Some(Err(e)) => tracing::warn!("Attachment download task failed: {}", e),
+                None => break,
+            },
+        }
+    }
+
+    results.sort_by_key(|(index, _, _)| *index);
+    let mut downloads = Downloads::default();
+    for (index, filename, result) in results {
+        match result {
+            Ok(file) => downloads.files.push(file),
+            Err(e) => {
+                tracing::warn!("Failed to download attachment {}: {}", filename, e);
+                cleanup(&attachment_dir(files[index].id.get())).await;
+                downloads.failed.push((filename, e.to_string()));
+            }
+        }
+    }
+    Some(downloads)
+}
+
+/// Remove an attachment directory (best-effort)
+async fn cleanup(dir: &Path) {
+    let _ = tokio::fs::remove_dir_all(dir).await;
+}
+
+/// What a cleanup removed
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Cleanup {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// Every file under `dir`, with its size and modification time
+fn list_files(dir: &Path, out: &mut Vec<(PathBuf, u64, SystemTime)>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let Ok(meta) = entry.metadata() else {
+            continue;
+        };
+        if meta.is_dir() {
+            list_files(&entry.path(), out);
+        } else {
+            let modified = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            out.push((entry.path(), meta.len(), modified));
+        }
+    }
+}
+
+/// Delete downloaded attachments older than `retention`, then the oldest ones
+/// until the directory holds at most `max_bytes`. Empty per-attachment
+/// directories are removed too.
+pub fn clean(retention: Duration, max_bytes: u64) -> Cleanup {
+    let root = attachments_root();
+    let mut files = Vec::new();
+    list_files(&root, &mut files);
+    // Oldest first, so eviction below starts with them
+    files.sort_by_key(|(_, _, modified)| *modified);
+
+    let now = SystemTime::now();
+    let mut total: u64 = files.iter().map(|(_, size, _)| size).sum();
+    let mut removed = Cleanup::default();
+    for (path, size, modified) in files {
+        let expired = now.duration_since(modified).is_ok_and(|age| age > retention);
+        if !expired && total <= max_bytes {
+            continue;
+        }
+        if std::fs::remove_file(&path).is_ok() {
+            total = total.saturating_sub(size);
+            removed.files += 1;
+            removed.bytes += size;
+        }
+    }
+
+    if let Ok(entries) = std::fs::read_dir(&root) {
+        for entry in entries.flatten() {
+            // Fails (and is skipped) unless the directory is empty
+            let _ = std::fs::remove_dir(entry.path());
+        }
+    }
+    removed
+}
+
+/// Human-readable byte size
+pub fn format_bytes(bytes: u64) -> String {
+    if bytes >= 1024 * 1024 * 1024 {
+        format!("{:.1} GB", bytes as f64 / (1024.0 * 1024.0 * 1024.0))
+    } else if bytes >= 1024 * 1024 {
+        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+    } else if bytes >= 1024 {
+        format!("{:.1} KB", bytes as f64 / 1024.0)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+/// Prompt block describing attachments. Images get an explicit instruction to
+/// view them, kept separate from generic files. Sizes help prioritize.
+/// `images_inline` is set when the images are also passed to the backend
+/// directly (Codex `--image`), so there's nothing to open.
+pub fn prompt_block(attachments: &[Attachment], failed: &[(String, String)], images_inline: bool) -> String {
+    let describe = |a: &&Attachment| format!("{} ({})", a.path, format_bytes(a.size));
+    let images: Vec<String> = attachments
+        .iter()
+        .filter(|a| a.image.is_some())
+        .map(|a| describe(&a))
+        .collect();
+    let files: Vec<String> = attachments
+        .iter()
+        .filter(|a| a.image.is_none())
+        .map(|a| describe(&a))
+        .collect();
+
+    let mut block = String::new();
+    if !images.is_empty() {
+        let instruction = if images_inline {
+            "These images are attached to this message. Look at each one carefully before answering."
+        } else {
+            "These are images. View and analyze each one first by opening it with the Read tool (it displays images visually), then answer with what you see in mind."
+        };
+        block.push_str(&format!("\n\n[Attached images: {}]\n[{}]", images.join(", "), instruction));
+    }
+    if !files.is_empty() {
+        block.push_str(&format!("\n\n[Attached files: {}]", files.join(", ")));
+    }
+    if !failed.is_empty() {
+        let names: Vec<&str> = failed.iter().map(|(name, _)| name.as_str()).collect();
+        block.push_str(&format!("\n\n[Failed to download: {}]", names.join(", ")));
+    }
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, size: u64, image: Option<ImageKind>) -> Attachment {
+        Attachment { path: path.to_string(), size, image, audio: false }
+    }
+
+    #[test]
+    fn sniff_magic_bytes() {
+        assert_eq!(sniff_image(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), Some(ImageKind::Png));
+        assert_eq!(sniff_image(&[0xFF, 0xD8, 0xFF, 0xE0, 0, 0x10]), Some(ImageKind::Jpeg));
+        assert_eq!(sniff_image(b"GIF89a\x01\0"), Some(ImageKind::Gif));
+        assert_eq!(sniff_image(b"RIFF\x24\0\0\0WEBPVP8 "), Some(ImageKind::Webp));
+        assert_eq!(sniff_image(b"RIFF\x24\0\0\0WAVEfmt "), None);
+        assert_eq!(sniff_image(b"%PDF-1.7"), None);
+        assert_eq!(sniff_image(b""), None);
+    }
+
+    #[test]
+    fn detect_prefers_bytes_over_content_type() {
+        // A PNG mislabelled as text is still an image
+        assert_eq!(detect_image(Some("text/plain"), b"\x89PNG\r\n\x1a\n"), Some(ImageKind::Png));
+        // Unknown bytes fall back to the declared type
+        assert_eq!(detect_image(Some("image/heic"), b"\0\0\0\x18ftypheic"), Some(ImageKind::Other));
+        assert_eq!(detect_image(Some("application/pdf"), b"%PDF-1.7"), None);
+        assert_eq!(detect_image(None, b"%PDF-1.7"), None);
+    }
+
+    #[test]
+    fn prompt_block_separates_images_and_files() {
+        let attachments = [
+            file("/tmp/a/shot.png", 2048, Some(ImageKind::Png)),
+            file("/tmp/b/notes.txt", 10, None),
+        ];
+        let block = prompt_block(&attachments, &[], false);
+        assert!(block.contains("[Attached images: /tmp/a/shot.png (2.0 KB)]"), "{}", block);
+        assert!(block.contains("Read tool"), "{}", block);
+        assert!(block.contains("[Attached files: /tmp/b/notes.txt (10 B)]"), "{}", block);
+        assert!(!block.contains("Failed to download"));
+
+        let failed = [("big.zip".to_string(), "too large".to_string())];
+        let block = prompt_block(&[], &failed, false);
+        assert_eq!(block, "\n\n[Failed to download: big.zip]");
+        assert_eq!(prompt_block(&[], &[], false), "");
+    }
+
+    #[test]
+    fn prompt_block_keeps_several_images_in_order() {
+        let attachments = [
+            file("/tmp/1/first.png", 1024, Some(ImageKind::Png)),
+            file("/tmp/2/log.txt", 5, None),
+            file("/tmp/3/second.jpg", 2048, Some(ImageKind::Jpeg)),
+            file("/tmp/5/third.webp", 4096, Some(ImageKind::Webp)),
+        ];
+        let failed = [("broken.gif".to_string(), "HTTP 404".to_string())];
+        let block = prompt_block(&attachments, &failed, true);
+        assert_eq!(
+            block,
+            "\n\n[Attached images: /tmp/1/first.png (1.0 KB), /tmp/3/second.jpg (2.0 KB), /tmp/5/third.webp (4.0 KB)]\
+            \n[These images are attached to this message. Look at each one carefully before answering.]\
+            \n\n[Attached files: /tmp/2/log.txt (5 B)]\
+            \n\n[Failed to download: broken.gif]"
+        );
+        assert_eq!(image_paths(&attachments), vec!["/tmp/1/first.png", "/tmp/3/second.jpg", "/tmp/5/third.webp"]);
+    }
+
+    fn discord_attachment(id: u64, filename: &str, size: u32) -> DiscordAttachment {
+        serde_json::from_value(serde_json::json!({
+            "id": id.to_string(),
+            "filename": filename,
+            "size": size,
+            "url": format!("https://cdn.example/{}", filename),
+            "proxy_url": format!("https://cdn.example/{}", filename),
+            "content_type": "image/png",
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn failed_downloads_keep_their_order() {
+        // Oversized files fail without being fetched
+        let files = [
+            discord_attachment(1, "a.png", 900),
+            discord_attachment(2, "b.png", 800),
+            discord_attachment(3, "c.png", 700),
+        ];
+        let downloads = download_all(&files, 100, &CancellationToken::new()).await.unwrap();
+        assert!(downloads.files.is_empty());
+        let names: Vec<&str> = downloads.failed.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["a.png", "b.png", "c.png"]);
+        assert!(downloads.failed[0].1.starts_with("too large: 900 B"), "{}", downloads.failed[0].1);
+    }
+}
@@ -0,0 +1,269 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// One audit record. Entries are hash-chained (`prev_hash`) so edits or
+/// deletions inside a month's file are detectable.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub ts: DateTime<Local>,
+    pub user_id: u64,
+    pub user_name: String,
+    pub channel_id: u64,
+    /// "command" or "run"
+    pub kind: String,
+    /// Command name (e.g. "!stop", "/new") or run type ("chat", "plan")
+    pub command: String,
+    /// SHA-256 of the message content (content itself is not stored)
+    pub content_hash: String,
+    /// Verbatim command line for `!run`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command_line: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend: Option<String>,
+    pub outcome: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    #[serde(default)]
+    pub prev_hash: String,
+}
+
+impl AuditEntry {
+    pub fn new(kind: &str, command: &str, user_id: u64, user_name: &str, channel_id: u64, content: &str) -> Self {
+        Self {
+            ts: Local::now(),
+            user_id,
+            user_name: user_name.to_string(),
+            channel_id,
+            kind: kind.to_string(),
+            command: command.to_string(),
+            content_hash: sha256_hex(content),
+            command_line: None,
+            backend: None,
+            outcome: "ok".to_string(),
+            duration_ms: None,
+            prev_hash: String::new(),
+        }
+    }
+
+    pub fn outcome(mut self, outcome: &str) -> Self {
+        self.outcome = outcome.to_string();
+        self
+    }
+
+    pub fn backend(mut self, backend: &str) -> Self {
+        self.backend = Some(backend.to_string());
+        self
+    }
+
+    pub fn duration(mut self, duration: std::time::Duration) -> Self {
+        self.duration_ms = Some(duration.as_millis() as u64);
+        self
+    }
+
+    pub fn command_line(mut self, line: &str) -> Self {
+        self.command_line = Some(line.to_string());
+        self
+    }
+}
+
+/// Hex-encoded SHA-256
+pub fn sha256_hex(content: &str) -> String {
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
+
+/// Directory holding monthly audit files
+fn audit_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("neywa")
+        .join("audit")
+}
+
+/// Audit file for a given month (rotated monthly: audit-YYYY-MM.jsonl)
+fn audit_file_for(date: NaiveDate) -> PathBuf {
+    audit_dir().join(format!("audit-{}.jsonl", date.format("%Y-%m")))
+}
+
+/// Serializes writers and caches the last line hash per file
+static WRITER: Mutex<Option<(PathBuf, String)>> = Mutex::new(None);
+
+/// Append an entry synchronously
+fn append(mut entry: AuditEntry) -> Result<()> {
+    let path = audit_file_for(entry.ts.date_naive());
+    let mut guard = WRITER.lock().unwrap_or_else(|e| e.into_inner());
+
+    let prev_hash = match guard.as_ref() {
+        Some((cached_path, hash)) if *cached_path == path => hash.clone(),
+        _ => std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| content.lines().last().map(sha256_hex))
+            .unwrap_or_default(),
+    };
+    entry.prev_hash = prev_hash;
+
+    let line = serde_json::to_string(&entry)?;
+    std::fs::create_dir_all(audit_dir())?;
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open audit log {:?}", path))?;
+    writeln!(file, "{}", line)?;
+
+    *guard = Some((path, sha256_hex(&line)));
+    Ok(())
+}
+
+/// Record an entry in the background. Best-effort: never blocks the caller
+/// and never fails message processing.
+pub fn record(entry: AuditEntry) {
+    let write = move || {
+        if let Err(e) = append(entry) {
+            tracing::warn!("Failed to write audit log: {}", e);
+        }
+    };
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) => {
+            handle.spawn_blocking(write);
+        }
+        Err(_) => write(),
+    }
+}
+
+/// Parse `--since`: a date (YYYY-MM-DD) or a relative duration (30m, 24h, 7d)
+fn parse_since(since: &str) -> Result<DateTime<Local>> {
+    if let Ok(date) = NaiveDate::parse_from_str(since, "%Y-%m-%d") {
+        return date
+            .and_hms_opt(0, 0, 0)
+            .and_then(|dt| dt.and_local_timezone(Local).earliest())
+            .context("Invalid date");
+    }
+    let split = since.char_indices().last().map(|(i, _)| i).unwrap_or(0);
+    let (num, unit) = since.split_at(split);
+    let n: i64 = num.parse().with_context(|| format!("Invalid --since value '{}'", since))?;
+    let delta = match unit {
+        "m" => chrono::Duration::minutes(n),
+        "h" => chrono::Duration::hours(n),
+        "d" => chrono::Duration::days(n),
+        _ => anyhow::bail!("Invalid --since value '{}' (use YYYY-MM-DD, 30m, 24h, 7d)", since),
+    };
+    Ok(Local::now() - delta)
+}
+
+/// Read entries from all monthly files, oldest first
+fn read_entries() -> Vec<AuditEntry> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(audit_dir())
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|e| e.path())
+                .filter(|p| p.extension().map(|x| x == "jsonl").unwrap_or(false))
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort();
+
+    files
+        .iter()
+        .filter_map(|p| std::fs::read_to_string(p).ok())
+        .flat_map(|content| {
+            content
+                .lines()
+                .filter_map(|line| serde_json::from_str::<AuditEntry>(line).ok())
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Entries recorded on a given local date (only that month's file is read)
+pub fn entries_on(date: NaiveDate) -> Vec<AuditEntry> {
+    std::fs::read_to_string(audit_file_for(date))
+        .map(|content| {
+            content
+                .lines()
+                .filter_map(|line| serde_json::from_str::<AuditEntry>(line).ok())
+                .filter(|e| e.ts.date_naive() == date)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// `neywa audit tail` - print the most recent entries
+pub fn tail(user: Option<&str>, since: Option<&str>, lines: usize) -> Result<()> {
+    let since = since.map(parse_since).transpose()?;
+
+    let entries: Vec<AuditEntry> = read_entries()
+        .into_iter()
+        .filter(|e| since.map(|s| e.ts >= s).unwrap_or(true))
+        .filter(|e| {
+            user.map(|u| e.user_id.to_string() == u || e.user_name.eq_ignore_ascii_case(u))
+                .unwrap_or(true)
+        })
+        .collect();
+
+    if entries.is_empty() {
+        println!("No audit entries found in {:?}", audit_dir());
+        return Ok(());
+    }
+
+    let start = entries.len().saturating_sub(lines);
+    for e in &entries[start..] {
+        let duration = e.duration_ms.map(|ms| format!(" {}ms", ms)).unwrap_or_default();
+        let backend = e.backend.as_deref().map(|b| format!(" [{}]", b)).unwrap_or_default();
+        println!(
+            "{} {:<8} {}({}) #{} {}{} → {}{}",
+            e.ts.format("%Y-%m-%d %H:%M:%S"),
+            e.kind,
+            e.user_name,
+            e.user_id,
+            e.channel_id,
+            e.command,
+            backend,
+            e.outcome,
+            duration
+        );
+        if let Some(line) = &e.command_line {
+            println!("    $ {}", line);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn since_relative_units() {
+        let now = Local::now();
+        for (value, expected) in [
+            ("30m", chrono::Duration::minutes(30)),
+            ("24h", chrono::Duration::hours(24)),
+            ("7d", chrono::Duration::days(7)),
+        ] {
+            let since = parse_since(value).unwrap();
+            let delta = now - since;
+            assert!((delta - expected).num_seconds().abs() < 5, "{}: {:?}", value, delta);
+        }
+    }
+
+    #[test]
+    fn since_date() {
+        let since = parse_since("2024-03-01").unwrap();
+        assert_eq!(since.date_naive(), NaiveDate::from_ymd_opt(2024, 3, 1).unwrap());
+        assert_eq!(since.format("%H:%M:%S").to_string(), "00:00:00");
+    }
+
+    #[test]
+    fn since_rejects_unknown_units() {
+        for value in ["3日", "日", "5w", "h", "", "abc", "2024-13-01"] {
+            assert!(parse_since(value).is_err(), "{} should be rejected", value);
+        }
+    }
+}
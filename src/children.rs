@@ -0,0 +1,135 @@
+use std::collections::HashSet;
+use std::process::{Output, Stdio};
+use std::sync::{Mutex, OnceLock};
+
+/// PIDs of claude/codex processes Neywa spawned that haven't exited yet
+fn registry() -> &'static Mutex<HashSet<u32>> {
+    static LIVE: OnceLock<Mutex<HashSet<u32>>> = OnceLock::new();
+    LIVE.get_or_init(|| Mutex::new(HashSet::new()))
+}
+
+/// Keeps a child's PID registered until dropped. Hold it for as long as the
+/// child is running and drop it after `wait()` returns.
+#[derive(Debug)]
+pub struct ChildGuard(Option<u32>);
+
+impl Drop for ChildGuard {
+    fn drop(&mut self) {
+        if let Some(pid) = self.0 {
+            if let Ok(mut live) = registry().lock() {
+                live.remove(&pid);
+            }
+        }
+    }
+}
+
+/// Register a freshly spawned child (`Child::id()` is None once it has been reaped)
+pub fn register(pid: Option<u32>) -> ChildGuard {
+    if let Some(pid) = pid {
+        if let Ok(mut live) = registry().lock() {
+            live.insert(pid);
+        }
+    }
+    ChildGuard(pid)
+}
+
+/// `Command::output()` for a CLI run: the child stays registered while it
+/// runs, so `!restart` and shutdown stop it like a streaming run
+pub async fn output(cmd: &mut tokio::process::Command) -> std::io::Result<Output> {
+    let child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let _guard = register(child.id());
+    child.wait_with_output().await
+}
+
+/// PIDs of children still running
+pub fn live() -> Vec<u32> {
+    registry()
+        .lock()
+        .map(|live| live.iter().copied().collect())
+        .unwrap_or_default()
+}
+
+/// Send SIGTERM to every registered child, returning how many were signalled
+pub fn kill_all() -> usize {
+    let pids = live();
+    if pids.is_empty() {
+        return 0;
+    }
+    let _ = std::process::Command::new("kill")
+        .arg("-TERM")
+        .args(pids.iter().map(|pid| pid.to_string()))
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status();
+    pids.len()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+
+    const SIGTERM: i32 = 15;
+
+    /// The registry is process-wide, so tests touching it take turns
+    static SERIAL: Mutex<()> = Mutex::new(());
+
+    fn sleeper() -> std::process::Child {
+        std::process::Command::new("sleep").arg("30").spawn().unwrap()
+    }
+
+    #[test]
+    fn kill_all_reaps_registered_children() {
+        let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+        let mut children: Vec<_> = (0..3).map(|_| sleeper()).collect();
+        let guards: Vec<_> = children.iter().map(|c| register(Some(c.id()))).collect();
+
+        assert_eq!(kill_all(), 3);
+        for child in &mut children {
+            assert_eq!(child.wait().unwrap().signal(), Some(SIGTERM));
+        }
+
+        drop(guards);
+        assert!(live().is_empty());
+        assert_eq!(kill_all(), 0);
+    }
+
+    #[test]
+    fn guard_unregisters_on_drop() {
+        let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+        let mut child = sleeper();
+        let guard = register(Some(child.id()));
+        assert_eq!(live(), vec![child.id()]);
+        drop(guard);
+        assert!(live().is_empty());
+
+        // Unregistered children are left alone
+        assert_eq!(kill_all(), 0);
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn output_registers_while_running() {
+        let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let out = rt.block_on(async {
+            let run = tokio::spawn(async {
+                let mut cmd = tokio::process::Command::new("sleep");
+                output(cmd.arg("30")).await
+            });
+            while live().is_empty() {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+            assert_eq!(kill_all(), 1);
+            run.await.unwrap().unwrap()
+        });
+        assert_eq!(out.status.signal(), Some(SIGTERM));
+        assert!(live().is_empty());
+    }
+}
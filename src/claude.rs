@@ -1,10 +1,14 @@
 use anyhow::{Context, Result};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
-use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, BufReader, Lines};
 use tokio::process::Command;
 use tokio::sync::mpsc;
 
+use crate::children;
+use crate::discord_api::ORIGIN_CHANNEL_ENV;
+use crate::stream::ClaudeParser;
+
 /// AI backend selection for each channel
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
 pub enum AiBackend {
@@ -14,6 +18,14 @@ pub enum AiBackend {
 }
 
 impl AiBackend {
+    pub fn cli_name(&self) -> &'static str {
+        match self {
+            AiBackend::Claude => "claude",
+            AiBackend::ClaudeZ => "claude-z",
+            AiBackend::Codex => "codex",
+        }
+    }
+
     pub fn status_line(&self) -> &'static str {
         match self {
             AiBackend::Claude => "🤖 Normal mode (claude)",
@@ -106,10 +118,18 @@ neywa discord create 'My Category' -t category
 # Move a channel to a different category
 neywa discord move dev-logs 'Development'
 neywa discord move 1234567890 'Archive'
+neywa discord move dev-logs 'New Project' --create-category --position 0
 
-# Delete a channel (by name or ID)
+# Delete a channel (by name or ID) - posts Approve/Reject buttons and waits for a user.
+# Never pass --force; report the pending action instead of retrying.
 neywa discord delete old-channel
 neywa discord delete 1234567890
+
+# Delete the last N messages in a channel (same approval flow as delete)
+neywa discord purge bot-test -n 50
+
+# Only when the user gives you the confirmation code from the approval prompt
+neywa discord delete old-channel --confirm K7Q2M9XA
 ```
 
 Use these commands proactively when needed:
@@ -120,7 +140,7 @@ Use these commands proactively when needed:
 "#;
 
 /// Find CLI binary in common locations
-pub(crate) fn find_cli(name: &str) -> Option<PathBuf> {
+pub fn find_cli(name: &str) -> Option<PathBuf> {
     // First try which
     if let Ok(path) = which::which(name) {
         return Some(path);
@@ -148,13 +168,97 @@ pub(crate) fn find_cli(name: &str) -> Option<PathBuf> {
         }
     }
 
-    for path in candidates {
-        if path.exists() && path.is_file() {
-            return Some(path);
+    candidates.into_iter().find(|path| path.exists() && path.is_file())
+}
+
+/// How long `<cli> --version` may take before the CLI counts as unresponsive
+const VERSION_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Outcome of running `<cli> --version`
+#[derive(Debug, Clone)]
+pub enum CliStatus {
+    Missing,
+    Version(String),
+    Failed(String),
+    TimedOut,
+}
+
+/// Availability of one backend's CLI, for `!backends` and `neywa doctor`
+#[derive(Debug, Clone)]
+pub struct CliCheck {
+    pub backend: AiBackend,
+    pub path: Option<PathBuf>,
+    pub status: CliStatus,
+}
+
+impl CliCheck {
+    pub fn ok(&self) -> bool {
+        matches!(self.status, CliStatus::Version(_))
+    }
+
+    /// One line, e.g. "✅ claude 1.0.3 (/usr/local/bin/claude)"
+    pub fn describe(&self) -> String {
+        let name = self.backend.cli_name();
+        let path = self
+            .path
+            .as_ref()
+            .map(|p| format!(" ({})", p.display()))
+            .unwrap_or_default();
+        match &self.status {
+            CliStatus::Missing => format!("❌ {} not found", name),
+            CliStatus::Version(version) => format!("✅ {} {}{}", name, version, path),
+            CliStatus::Failed(error) => format!("❌ {} --version failed: {}{}", name, error, path),
+            CliStatus::TimedOut => format!(
+                "⚠️ {} didn't answer --version within {}s{}",
+                name,
+                VERSION_TIMEOUT.as_secs(),
+                path
+            ),
         }
     }
+}
 
-    None
+/// Locate a backend's CLI and ask it for its version
+pub async fn check_cli(backend: AiBackend) -> CliCheck {
+    let Some(path) = find_cli(backend.cli_name()) else {
+        return CliCheck { backend, path: None, status: CliStatus::Missing };
+    };
+    let output = Command::new(&path)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    let status = match tokio::time::timeout(VERSION_TIMEOUT, output).await {
+        Err(_) => CliStatus::TimedOut,
+        Ok(Err(e)) => CliStatus::Failed(e.to_string()),
+        Ok(Ok(output)) => {
+            let first_line = |bytes: &[u8]| {
+                String::from_utf8_lossy(bytes)
+                    .lines()
+                    .map(str::trim)
+                    .find(|line| !line.is_empty())
+                    .map(str::to_string)
+            };
+            match (output.status.success(), first_line(&output.stdout)) {
+                (true, Some(version)) => CliStatus::Version(version),
+                (true, None) => CliStatus::Version("(no version printed)".to_string()),
+                (false, _) => CliStatus::Failed(
+                    first_line(&output.stderr).unwrap_or_else(|| output.status.to_string()),
+                ),
+            }
+        }
+    };
+    CliCheck { backend, path: Some(path), status }
+}
+
+/// Check every backend's CLI at once
+pub async fn check_backends() -> Vec<CliCheck> {
+    let (claude, claude_z, codex) = tokio::join!(
+        check_cli(AiBackend::Claude),
+        check_cli(AiBackend::ClaudeZ),
+        check_cli(AiBackend::Codex)
+    );
+    vec![claude, claude_z, codex]
 }
 
 /// Common args for all Claude Code calls
@@ -172,6 +276,20 @@ fn base_command(use_z: bool) -> Command {
     cmd
 }
 
+/// Pass `--model` when a channel picked one with `!model` (None = CLI default)
+fn set_model(cmd: &mut Command, model: Option<&str>) {
+    if let Some(model) = model {
+        cmd.arg("--model").arg(model);
+    }
+}
+
+/// Run in the channel's working directory (`!cwd`); None inherits the daemon's
+fn set_workdir(cmd: &mut Command, cwd: Option<&Path>) {
+    if let Some(dir) = cwd {
+        cmd.current_dir(dir);
+    }
+}
+
 /// Command for plan mode (no --dangerously-skip-permissions, uses --permission-mode plan)
 fn plan_command(use_z: bool) -> Command {
     let cli_name = if use_z { "claude-z" } else { "claude" };
@@ -201,7 +319,7 @@ fn verify_cli(use_z: bool) -> Result<PathBuf> {
 }
 
 /// Format tool input for display
-fn format_tool_input(tool_name: &str, input: &serde_json::Value) -> String {
+pub(crate) fn format_tool_input(tool_name: &str, input: &serde_json::Value) -> String {
     match tool_name {
         "Read" => {
             input.get("file_path")
@@ -329,7 +447,7 @@ fn format_tool_input(tool_name: &str, input: &serde_json::Value) -> String {
 }
 
 /// Truncate string for display
-pub(crate) fn truncate_str(s: &str, max_len: usize) -> String {
+pub fn truncate_str(s: &str, max_len: usize) -> String {
     if s.chars().count() <= max_len {
         s.to_string()
     } else {
@@ -339,12 +457,46 @@ pub(crate) fn truncate_str(s: &str, max_len: usize) -> String {
 }
 
 /// Shorten file path for display
-pub(crate) fn shorten_path(path: &str) -> &str {
+pub fn shorten_path(path: &str) -> &str {
     path.rsplit('/').next().unwrap_or(path)
 }
 
+/// Status line for a tool use (the description, or the tool name when there is none)
+pub(crate) fn tool_label(tool_name: &str, detail: &str) -> String {
+    if detail.is_empty() {
+        format!("🔧 {}", tool_name)
+    } else {
+        detail.to_string()
+    }
+}
+
+/// Outcome of a `tool_result` block: (ok, one-line summary). A result fails
+/// when flagged `is_error` or when it reports a non-zero exit code.
+pub(crate) fn tool_result_outcome(item: &serde_json::Value) -> (bool, String) {
+    let text = match item.get("content") {
+        Some(serde_json::Value::String(s)) => s.clone(),
+        Some(serde_json::Value::Array(parts)) => parts
+            .iter()
+            .filter_map(|p| p.get("text").and_then(|v| v.as_str()))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    };
+    let exit_code = text
+        .lines()
+        .find_map(|l| l.trim().strip_prefix("Exit code ")?.trim().parse::<i32>().ok());
+    let is_error = item.get("is_error").and_then(|v| v.as_bool()).unwrap_or(false);
+    let ok = !is_error && exit_code.is_none_or(|code| code == 0);
+
+    let summary = match exit_code {
+        Some(code) if code != 0 => format!("exit {}", code),
+        _ => truncate_str(text.lines().map(str::trim).find(|l| !l.is_empty()).unwrap_or(""), 40),
+    };
+    (ok, summary)
+}
+
 /// Stream event from Claude Code
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum StreamEvent {
     /// Text content update (final response)
     Text(String),
@@ -352,20 +504,54 @@ pub enum StreamEvent {
     SessionId(String),
     /// Tool being used (name, brief description)
     ToolUse(String, String),
+    /// Tool finished; `tool` is the status label shown for its ToolUse
+    ToolResult { tool: String, ok: bool, summary: String },
     /// Plan file written (file_path, content)
     PlanContent(String, String),
+    /// Plan mode only: file read (tool "Read", path) or search (tool "Grep"/"Glob", pattern)
+    Explored(String, String),
+    /// The resumed session doesn't exist anymore (sent before `Done`)
+    SessionNotFound,
+    /// The CLI isn't logged in; carries a message saying how to fix it (sent before `Done`)
+    AuthRequired(String),
+    /// Cost and token counts from the final result (sent before `Done`)
+    Usage(RunUsage),
     /// Processing complete
     Done,
     /// Error occurred
     Error(String),
 }
 
+/// What a run cost, from the `result` event
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RunUsage {
+    pub cost_usd: f64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+/// Next output line, or None at EOF or once the receiver is gone (the run was
+/// stopped or timed out), so the reader can kill the child instead of
+/// letting it run on unseen
+pub(crate) async fn next_line<R>(lines: &mut Lines<R>, tx: &mpsc::Sender<StreamEvent>) -> Option<String>
+where
+    R: AsyncBufRead + Unpin,
+{
+    tokio::select! {
+        line = lines.next_line() => line.ok().flatten(),
+        _ = tx.closed() => None,
+    }
+}
+
 /// Run Claude Code with streaming output
 /// Returns a receiver for stream events
 pub async fn run_streaming(
     message: &str,
     session_id: Option<&str>,
     use_z: bool,
+    model: Option<&str>,
+    cwd: Option<&Path>,
+    channel_id: u64,
 ) -> Result<mpsc::Receiver<StreamEvent>> {
     let cli_path = verify_cli(use_z)?;
     let cli_name = cli_path.to_string_lossy();
@@ -373,6 +559,10 @@ pub async fn run_streaming(
     let (tx, rx) = mpsc::channel(100);
 
     let mut cmd = base_command(use_z);
+    set_model(&mut cmd, model);
+    set_workdir(&mut cmd, cwd);
+    // Lets `neywa discord` post confirmation prompts back to this channel
+    cmd.env(ORIGIN_CHANNEL_ENV, channel_id.to_string());
 
     if let Some(sid) = session_id {
         cmd.arg("--resume").arg(sid);
@@ -386,13 +576,14 @@ pub async fn run_streaming(
         .stderr(Stdio::piped());
 
     let mut child = cmd.spawn().context(format!("Failed to spawn {}", cli_name))?;
+    let guard = children::register(child.id());
 
     let stdout = child.stdout.take().context("Failed to get stdout")?;
     let stderr = child.stderr.take().context("Failed to get stderr")?;
 
     // Spawn task to read stderr in background
     let stderr_tx = tx.clone();
-    tokio::spawn(async move {
+    let stderr_task = tokio::spawn(async move {
         let reader = BufReader::new(stderr);
         let mut lines = reader.lines();
         let mut stderr_buf = String::new();
@@ -400,89 +591,49 @@ pub async fn run_streaming(
             stderr_buf.push_str(&line);
             stderr_buf.push('\n');
         }
-        if !stderr_buf.is_empty() {
-            let lower = stderr_buf.to_lowercase();
-            if lower.contains("prompt is too long") || lower.contains("context window") || lower.contains("too many tokens") {
-                let _ = stderr_tx.send(StreamEvent::Text("Prompt is too long".to_string())).await;
-                let _ = stderr_tx.send(StreamEvent::Done).await;
-            }
+        if ClaudeParser::is_context_overflow(&stderr_buf) {
+            let _ = stderr_tx.send(StreamEvent::Text("Prompt is too long".to_string())).await;
+            let _ = stderr_tx.send(StreamEvent::Done).await;
         }
+        stderr_buf
     });
 
     // Spawn task to read streaming output
+    let resuming = session_id.is_some();
+    let cli_name = cli_name.to_string();
     tokio::spawn(async move {
-        let reader = BufReader::new(stdout);
-        let mut lines = reader.lines();
-        let mut full_text = String::new();
-        let mut session_id_sent = false;
-
-        while let Ok(Some(line)) = lines.next_line().await {
-            // Parse JSON line
-            if let Ok(json) = serde_json::from_str::<serde_json::Value>(&line) {
-                // Extract session_id if present
-                if !session_id_sent {
-                    if let Some(sid) = json.get("session_id").and_then(|v| v.as_str()) {
-                        let _ = tx.send(StreamEvent::SessionId(sid.to_string())).await;
-                        session_id_sent = true;
-                    }
-                }
-
-                // Handle different event types
-                if let Some(event_type) = json.get("type").and_then(|v| v.as_str()) {
-                    match event_type {
-                        "assistant" => {
-                            // Assistant message content
-                            if let Some(message) = json.get("message") {
-                                if let Some(content) = message.get("content") {
-                                    if let Some(arr) = content.as_array() {
-                                        for item in arr {
-                                            // Check for tool_use
-                                            if let Some(item_type) = item.get("type").and_then(|v| v.as_str()) {
-                                                if item_type == "tool_use" {
-                                                    let tool_name = item.get("name")
-                                                        .and_then(|v| v.as_str())
-                                                        .unwrap_or("unknown");
-                                                    let input_str = item.get("input")
-                                                        .map(|v| format_tool_input(tool_name, v))
-                                                        .unwrap_or_default();
-                                                    let _ = tx.send(StreamEvent::ToolUse(
-                                                        tool_name.to_string(),
-                                                        input_str,
-                                                    )).await;
-                                                } else if item_type == "text" {
-                                                    if let Some(text) = item.get("text").and_then(|v| v.as_str()) {
-                                                        // 텍스트 누적 (여러 assistant 이벤트에서 오는 텍스트 합치기)
-                                                        if !full_text.is_empty() {
-                                                            full_text.push_str("\n");
-                                                        }
-                                                        full_text.push_str(text);
-                                                        let _ = tx.send(StreamEvent::Text(full_text.clone())).await;
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        "result" => {
-                            // Final result - use result if available, otherwise keep accumulated text
-                            if let Some(result) = json.get("result").and_then(|v| v.as_str()) {
-                                if !result.is_empty() {
-                                    full_text = result.to_string();
-                                    let _ = tx.send(StreamEvent::Text(full_text.clone())).await;
-                                }
-                            }
-                            let _ = tx.send(StreamEvent::Done).await;
-                        }
-                        _ => {}
-                    }
+        let mut lines = BufReader::new(stdout).lines();
+        let mut parser = ClaudeParser::new(false);
+        let mut answered = false;
+        let mut answer = String::new();
+        while let Some(line) = next_line(&mut lines, &tx).await {
+            for event in parser.parse_line(&line) {
+                answered |= matches!(event, StreamEvent::Text(_) | StreamEvent::Error(_));
+                if let StreamEvent::Text(text) = &event {
+                    answer.clone_from(text);
                 }
+                let _ = tx.send(event).await;
             }
         }
 
+        // Nobody is listening any more: stop the CLI rather than let it finish unseen
+        if tx.is_closed() {
+            let _ = child.kill().await;
+        }
+
         // Wait for process to complete
-        let _ = child.wait().await;
+        let status = child.wait().await.ok();
+        drop(guard);
+        let stderr = stderr_task.await.unwrap_or_default();
+
+        // A resume of a deleted session fails on stderr alone; report it before Done
+        if resuming && ClaudeParser::is_missing_session(&stderr) {
+            let _ = tx.send(StreamEvent::SessionNotFound).await;
+        } else if let Some(hint) = auth_error(get_cli_name(use_z), status, &stderr, &answer) {
+            let _ = tx.send(StreamEvent::AuthRequired(hint)).await;
+        } else if let Some(error) = exit_error(&cli_name, status, answered, &stderr) {
+            let _ = tx.send(StreamEvent::Error(error)).await;
+        }
 
         // Send done if not already sent
         let _ = tx.send(StreamEvent::Done).await;
@@ -491,11 +642,68 @@ pub async fn run_streaming(
     Ok(rx)
 }
 
+/// Longest stderr excerpt carried in an exit error
+const EXIT_STDERR_CHARS: usize = 1500;
+
+/// Error for a CLI that exited non-zero without answering, with the start of
+/// its stderr. None for success, for runs that produced an answer or reported
+/// their own error, and for signals (a `!stop` kill has no exit code).
+pub fn exit_error(cli: &str, status: Option<std::process::ExitStatus>, answered: bool, stderr: &str) -> Option<String> {
+    let code = status?.code()?;
+    if code == 0 || answered || ClaudeParser::is_context_overflow(stderr) {
+        return None;
+    }
+    let stderr = stderr.trim();
+    Some(if stderr.is_empty() {
+        format!("{} exited with code {}", cli, code)
+    } else {
+        format!("{} exited with code {}: {}", cli, code, truncate_str(stderr, EXIT_STDERR_CHARS))
+    })
+}
+
+/// Lowercase output fragments of a CLI that isn't logged in or whose login expired
+const AUTH_FAILURE_MARKERS: &[&str] = &[
+    "not logged in",
+    "invalid api key",
+    "invalid_api_key",
+    "please run /login",
+    "claude login",
+    "codex login",
+    "authentication_error",
+    "oauth token has expired",
+    "401 unauthorized",
+    "status 401",
+];
+
+/// Whether CLI output reads like an authentication failure
+pub fn is_auth_failure(output: &str) -> bool {
+    let lower = output.to_lowercase();
+    AUTH_FAILURE_MARKERS.iter().any(|marker| lower.contains(marker))
+}
+
+/// What to tell the user when `cli` has to log in again
+pub fn login_hint(cli: &str) -> String {
+    format!(
+        "🔑 {} isn't logged in, or its login expired. Run `{} login` on the machine Neywa runs on, then try again.",
+        cli, cli
+    )
+}
+
+/// Login hint for a CLI that exited non-zero because it isn't authenticated,
+/// judged from its stderr and the last text it answered with
+pub fn auth_error(cli: &str, status: Option<std::process::ExitStatus>, stderr: &str, answer: &str) -> Option<String> {
+    let code = status?.code()?;
+    (code != 0 && (is_auth_failure(stderr) || is_auth_failure(answer))).then(|| login_hint(cli))
+}
+
 /// Run Claude Code in plan mode with streaming output
 /// Uses --permission-mode plan instead of --dangerously-skip-permissions
 pub async fn run_streaming_plan(
     message: &str,
     use_z: bool,
+    model: Option<&str>,
+    cwd: Option<&Path>,
+    channel_id: u64,
 ) -> Result<mpsc::Receiver<StreamEvent>> {
     let cli_path = verify_cli(use_z)?;
     let cli_name = cli_path.to_string_lossy();
@@ -503,6 +711,9 @@ pub async fn run_streaming_plan(
     let (tx, rx) = mpsc::channel(100);
 
     let mut cmd = plan_command(use_z);
+    set_model(&mut cmd, model);
+    set_workdir(&mut cmd, cwd);
+    cmd.env(ORIGIN_CHANNEL_ENV, channel_id.to_string());
 
     cmd.arg("--verbose")
         .arg("--output-format")
@@ -513,13 +724,14 @@ pub async fn run_streaming_plan(
         .stderr(Stdio::piped());
 
     let mut child = cmd.spawn().context(format!("Failed to spawn {} (plan mode)", cli_name))?;
+    let guard = children::register(child.id());
 
     let stdout = child.stdout.take().context("Failed to get stdout")?;
     let stderr = child.stderr.take().context("Failed to get stderr")?;
 
     // Spawn stderr reader
     let stderr_tx = tx.clone();
-    tokio::spawn(async move {
+    let stderr_task = tokio::spawn(async move {
         let reader = BufReader::new(stderr);
         let mut lines = reader.lines();
         let mut stderr_buf = String::new();
@@ -527,110 +739,41 @@ pub async fn run_streaming_plan(
             stderr_buf.push_str(&line);
             stderr_buf.push('\n');
         }
-        if !stderr_buf.is_empty() {
-            let lower = stderr_buf.to_lowercase();
-            if lower.contains("prompt is too long") || lower.contains("context window") || lower.contains("too many tokens") {
-                let _ = stderr_tx.send(StreamEvent::Text("Prompt is too long".to_string())).await;
-                let _ = stderr_tx.send(StreamEvent::Done).await;
-            }
+        if ClaudeParser::is_context_overflow(&stderr_buf) {
+            let _ = stderr_tx.send(StreamEvent::Text("Prompt is too long".to_string())).await;
+            let _ = stderr_tx.send(StreamEvent::Done).await;
         }
+        stderr_buf
     });
 
-    // Spawn stdout reader - enhanced to capture plan file writes
+    // Spawn stdout reader - the plan parser also captures plan file writes
+    let cli_name = cli_name.to_string();
     tokio::spawn(async move {
-        let reader = BufReader::new(stdout);
-        let mut lines = reader.lines();
-        let mut full_text = String::new();
-        let mut session_id_sent = false;
-
-        while let Ok(Some(line)) = lines.next_line().await {
-            if let Ok(json) = serde_json::from_str::<serde_json::Value>(&line) {
-                // Extract session_id
-                if !session_id_sent {
-                    if let Some(sid) = json.get("session_id").and_then(|v| v.as_str()) {
-                        let _ = tx.send(StreamEvent::SessionId(sid.to_string())).await;
-                        session_id_sent = true;
-                    }
-                }
-
-                if let Some(event_type) = json.get("type").and_then(|v| v.as_str()) {
-                    match event_type {
-                        "assistant" => {
-                            if let Some(message) = json.get("message") {
-                                if let Some(content) = message.get("content") {
-                                    if let Some(arr) = content.as_array() {
-                                        for item in arr {
-                                            if let Some(item_type) = item.get("type").and_then(|v| v.as_str()) {
-                                                if item_type == "tool_use" {
-                                                    let tool_name = item.get("name")
-                                                        .and_then(|v| v.as_str())
-                                                        .unwrap_or("unknown");
-
-                                                    // Capture Write to plan file
-                                                    if tool_name == "Write" {
-                                                        if let Some(input) = item.get("input") {
-                                                            let file_path = input.get("file_path")
-                                                                .and_then(|v| v.as_str())
-                                                                .unwrap_or("");
-                                                            if file_path.contains("/.claude/plans/") {
-                                                                let plan_content = input.get("content")
-                                                                    .and_then(|v| v.as_str())
-                                                                    .unwrap_or("");
-                                                                if !plan_content.is_empty() {
-                                                                    let _ = tx.send(StreamEvent::PlanContent(
-                                                                        file_path.to_string(),
-                                                                        plan_content.to_string(),
-                                                                    )).await;
-                                                                }
-                                                            }
-                                                        }
-                                                    }
-
-                                                    // Capture ExitPlanMode plan content as fallback
-                                                    if tool_name == "ExitPlanMode" {
-                                                        // ExitPlanMode reads from the plan file, content may be in allowedPrompts or other fields
-                                                        // The plan file was already captured via Write above
-                                                    }
-
-                                                    let input_str = item.get("input")
-                                                        .map(|v| format_tool_input(tool_name, v))
-                                                        .unwrap_or_default();
-                                                    let _ = tx.send(StreamEvent::ToolUse(
-                                                        tool_name.to_string(),
-                                                        input_str,
-                                                    )).await;
-                                                } else if item_type == "text" {
-                                                    if let Some(text) = item.get("text").and_then(|v| v.as_str()) {
-                                                        if !full_text.is_empty() {
-                                                            full_text.push_str("\n");
-                                                        }
-                                                        full_text.push_str(text);
-                                                        let _ = tx.send(StreamEvent::Text(full_text.clone())).await;
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        "result" => {
-                            // In plan mode, result may be empty due to ExitPlanMode denial
-                            if let Some(result) = json.get("result").and_then(|v| v.as_str()) {
-                                if !result.is_empty() {
-                                    full_text = result.to_string();
-                                    let _ = tx.send(StreamEvent::Text(full_text.clone())).await;
-                                }
-                            }
-                            let _ = tx.send(StreamEvent::Done).await;
-                        }
-                        _ => {}
-                    }
+        let mut lines = BufReader::new(stdout).lines();
+        let mut parser = ClaudeParser::new(true);
+        let mut answered = false;
+        let mut answer = String::new();
+        while let Some(line) = next_line(&mut lines, &tx).await {
+            for event in parser.parse_line(&line) {
+                answered |= matches!(event, StreamEvent::Text(_) | StreamEvent::PlanContent(..) | StreamEvent::Error(_));
+                if let StreamEvent::Text(text) = &event {
+                    answer.clone_from(text);
                 }
+                let _ = tx.send(event).await;
             }
         }
 
-        let _ = child.wait().await;
+        if tx.is_closed() {
+            let _ = child.kill().await;
+        }
+        let status = child.wait().await.ok();
+        drop(guard);
+        let stderr = stderr_task.await.unwrap_or_default();
+        if let Some(hint) = auth_error(get_cli_name(use_z), status, &stderr, &answer) {
+            let _ = tx.send(StreamEvent::AuthRequired(hint)).await;
+        } else if let Some(error) = exit_error(&cli_name, status, answered, &stderr) {
+            let _ = tx.send(StreamEvent::Error(error)).await;
+        }
         let _ = tx.send(StreamEvent::Done).await;
     });
 
@@ -638,18 +781,17 @@ pub async fn run_streaming_plan(
 }
 
 /// Run a message through Claude Code and return the response (non-streaming)
-pub async fn run(message: &str, use_z: bool) -> Result<String> {
+pub async fn run(message: &str, use_z: bool, model: Option<&str>, cwd: Option<&Path>) -> Result<String> {
     let cli_path = verify_cli(use_z)?;
     let cli_name = cli_path.to_string_lossy();
 
     tracing::debug!("Sending to {}: {}", cli_name, message);
 
-    let output = base_command(use_z)
-        .arg("--print")
-        .arg(message)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
+    let mut cmd = base_command(use_z);
+    set_model(&mut cmd, model);
+    set_workdir(&mut cmd, cwd);
+    cmd.arg("--print").arg(message);
+    let output = children::output(&mut cmd)
         .await
         .context(format!("Failed to execute {}", cli_name))?;
 
@@ -669,7 +811,7 @@ pub async fn run(message: &str, use_z: bool) -> Result<String> {
 }
 
 /// Run Claude Code with a specific session (for continuing conversations)
-pub async fn run_with_session(message: &str, session_id: &str, use_z: bool) -> Result<String> {
+pub async fn run_with_session(message: &str, session_id: &str, use_z: bool, model: Option<&str>) -> Result<String> {
     let cli_path = verify_cli(use_z)?;
     let cli_name = cli_path.to_string_lossy();
 
@@ -680,14 +822,10 @@ pub async fn run_with_session(message: &str, session_id: &str, use_z: bool) -> R
         message
     );
 
-    let output = base_command(use_z)
-        .arg("--resume")
-        .arg(session_id)
-        .arg("--print")
-        .arg(message)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
+    let mut cmd = base_command(use_z);
+    set_model(&mut cmd, model);
+    cmd.arg("--resume").arg(session_id).arg("--print").arg(message);
+    let output = children::output(&mut cmd)
         .await
         .context(format!("Failed to execute {}", cli_name))?;
 
@@ -705,20 +843,16 @@ pub async fn run_with_session(message: &str, session_id: &str, use_z: bool) -> R
 }
 
 /// Run /compact on an existing session to compress context
-pub async fn compact_session(session_id: &str, use_z: bool) -> Result<()> {
+pub async fn compact_session(session_id: &str, use_z: bool, cwd: Option<&Path>) -> Result<()> {
     let cli_path = verify_cli(use_z)?;
     let cli_name = cli_path.to_string_lossy();
 
     tracing::info!("Compacting session: {}", session_id);
 
-    let output = base_command(use_z)
-        .arg("--resume")
-        .arg(session_id)
-        .arg("--print")
-        .arg("/compact")
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
+    let mut cmd = base_command(use_z);
+    set_workdir(&mut cmd, cwd);
+    cmd.arg("--resume").arg(session_id).arg("--print").arg("/compact");
+    let output = children::output(&mut cmd)
         .await
         .context(format!("Failed to execute {} compact", cli_name))?;
 
@@ -738,6 +872,7 @@ pub async fn run_slash_command(
     command: &str,
     session_id: Option<&str>,
     use_z: bool,
+    cwd: Option<&Path>,
 ) -> Result<String> {
     let _cli_path = verify_cli(use_z)?;
 
@@ -748,18 +883,15 @@ pub async fn run_slash_command(
     };
 
     let mut cmd = base_command(use_z);
+    set_workdir(&mut cmd, cwd);
 
     if let Some(sid) = session_id {
         cmd.arg("--resume").arg(sid);
     }
 
-    cmd.arg("--print")
-        .arg(&cmd_str)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped());
+    cmd.arg("--print").arg(&cmd_str);
 
-    let output = cmd
-        .output()
+    let output = children::output(&mut cmd)
         .await
         .context("Failed to execute slash command")?;
 
@@ -780,14 +912,9 @@ pub async fn run_json(message: &str, use_z: bool) -> Result<ClaudeResponse> {
     let cli_path = verify_cli(use_z)?;
     let cli_name = cli_path.to_string_lossy();
 
-    let output = base_command(use_z)
-        .arg("--print")
-        .arg("--output-format")
-        .arg("json")
-        .arg(message)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
+    let mut cmd = base_command(use_z);
+    cmd.arg("--print").arg("--output-format").arg("json").arg(message);
+    let output = children::output(&mut cmd)
         .await
         .context(format!("Failed to execute {}", cli_name))?;
 
@@ -12,12 +12,24 @@ pub struct Cli {
 #[derive(Subcommand)]
 pub enum Command {
     /// Start the Discord bot daemon (listens for messages)
-    Daemon,
+    Daemon {
+        /// Handle commands normally but echo prompts back instead of running Claude/Codex
+        #[arg(long)]
+        dry_run: bool,
+    },
 
     /// Run a single command through Claude Code
     Run {
         /// The message/command to send to Claude Code
         message: String,
+
+        /// Run in this channel's context (name or ID): its backend, model, language and latest session
+        #[arg(long)]
+        channel: Option<String>,
+
+        /// Also post the response to the channel (requires --channel)
+        #[arg(long, requires = "channel")]
+        post: bool,
     },
 
     /// Initial setup (Discord token, Claude Code hooks)
@@ -26,6 +38,54 @@ pub enum Command {
     /// Show current configuration
     Config,
 
+    /// Diagnose common setup problems (token, Message Content intent, CLIs)
+    Doctor,
+
+    /// Send a notification to a Discord channel (works without the daemon)
+    Notify {
+        /// Channel name (e.g., "logs") or channel ID
+        channel: String,
+
+        /// Message to send (reads stdin if omitted or "-")
+        message: Option<String>,
+    },
+
+    /// Install Claude Code hooks that notify Discord on Stop/Notification
+    InstallHooks,
+
+    /// Inspect the audit log of commands and AI runs
+    Audit {
+        #[command(subcommand)]
+        action: AuditAction,
+    },
+
+    /// Show logs (daemon output goes to /tmp/neywa.log)
+    Logs {
+        /// Full run records: prompt, every tool call, response, duration
+        #[arg(long)]
+        activity: bool,
+
+        /// Only today's runs instead of the last 7 days
+        #[arg(long, requires = "activity")]
+        today: bool,
+    },
+
+    /// Delete old attachment downloads now (the daemon also does this hourly)
+    Clean,
+
+    /// Runs, time, tokens and cost by channel and user
+    Stats {
+        /// The last 7 days with a per-day breakdown instead of today
+        #[arg(long)]
+        week: bool,
+    },
+
+    /// Export tracked sessions
+    Sessions {
+        #[command(subcommand)]
+        action: SessionsAction,
+    },
+
     /// Manage auto-start service (LaunchAgent)
     Service {
         #[command(subcommand)]
@@ -37,6 +97,44 @@ pub enum Command {
         #[command(subcommand)]
         action: DiscordAction,
     },
+
+    /// Find `neywa run` jobs that older versions added to your crontab
+    Cron {
+        #[command(subcommand)]
+        action: CronAction,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum CronAction {
+    /// List crontab lines that run `neywa run`
+    Import,
+
+    /// Move listed entries into Neywa's scheduler and comment them out of the crontab
+    Migrate {
+        /// Entry numbers from `neywa cron import`, or "all"
+        #[arg(required = true)]
+        entries: Vec<String>,
+
+        /// Channel (name or ID) for jobs that don't name one with --channel
+        #[arg(long)]
+        channel: Option<String>,
+
+        /// Actually migrate them (without this, only shows what would happen)
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Delete listed entries from the crontab (the original is backed up first)
+    Remove {
+        /// Entry numbers from `neywa cron import`, or "all"
+        #[arg(required = true)]
+        entries: Vec<String>,
+
+        /// Actually remove them (without this, only shows what would be removed)
+        #[arg(long)]
+        force: bool,
+    },
 }
 
 #[derive(Subcommand)]
@@ -51,6 +149,37 @@ pub enum ServiceAction {
     Status,
 }
 
+#[derive(Subcommand)]
+pub enum SessionsAction {
+    /// Write every session as a markdown transcript plus an index.md
+    Export {
+        /// Output directory
+        dir: std::path::PathBuf,
+
+        /// Only sessions active since this date (YYYY-MM-DD)
+        #[arg(short, long)]
+        since: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum AuditAction {
+    /// Show the most recent audit entries
+    Tail {
+        /// Filter by user ID or name
+        #[arg(short, long)]
+        user: Option<String>,
+
+        /// Only entries since a date (YYYY-MM-DD) or duration (30m, 24h, 7d)
+        #[arg(short, long)]
+        since: Option<String>,
+
+        /// Number of entries to show
+        #[arg(short = 'n', long, default_value = "50")]
+        lines: usize,
+    },
+}
+
 #[derive(Subcommand)]
 pub enum DiscordAction {
     /// List channels in the configured server
@@ -90,6 +219,32 @@ pub enum DiscordAction {
     Delete {
         /// Channel name or ID to delete
         channel: String,
+
+        /// Skip the confirmation (required when `confirm_destructive_discord_ops` is on and there's no terminal)
+        #[arg(long)]
+        force: bool,
+
+        /// Confirmation code from the approval prompt posted to Discord
+        #[arg(long, value_name = "CODE")]
+        confirm: Option<String>,
+    },
+
+    /// Delete the most recent messages in a channel
+    Purge {
+        /// Channel name or ID
+        channel: String,
+
+        /// How many messages to delete (at most 100)
+        #[arg(short = 'n', long, default_value = "100", value_parser = clap::value_parser!(u32).range(1..=100))]
+        limit: u32,
+
+        /// Skip the confirmation (required when `confirm_destructive_discord_ops` is on and there's no terminal)
+        #[arg(long)]
+        force: bool,
+
+        /// Confirmation code from the approval prompt posted to Discord
+        #[arg(long, value_name = "CODE")]
+        confirm: Option<String>,
     },
 
     /// Move a channel to a different category
@@ -99,5 +254,67 @@ pub enum DiscordAction {
 
         /// Target category name or ID
         category: String,
+
+        /// Create the category first if it doesn't exist
+        #[arg(long)]
+        create_category: bool,
+
+        /// Position of the channel within the category
+        #[arg(long)]
+        position: Option<u32>,
     },
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(std::iter::once("neywa").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn discord_move_arguments() {
+        let cli = parse(&["discord", "move", "#dev", "Projects", "--create-category", "--position", "2"]).unwrap();
+        match cli.command {
+            Command::Discord {
+                action: DiscordAction::Move { channel, category, create_category, position },
+            } => {
+                assert_eq!(channel, "#dev");
+                assert_eq!(category, "Projects");
+                assert!(create_category);
+                assert_eq!(position, Some(2));
+            }
+            _ => panic!("expected discord move"),
+        }
+
+        let cli = parse(&["discord", "move", "123", "456"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Discord { action: DiscordAction::Move { create_category: false, position: None, .. } }
+        ));
+    }
+
+    #[test]
+    fn discord_move_requires_category() {
+        assert!(parse(&["discord", "move", "dev"]).is_err());
+        assert!(parse(&["discord", "move", "dev", "cat", "--position", "first"]).is_err());
+    }
+
+    #[test]
+    fn destructive_commands_take_confirm_code() {
+        let cli = parse(&["discord", "purge", "bot-test", "-n", "20", "--confirm", "K7Q2M9XA"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Discord { action: DiscordAction::Purge { limit: 20, force: false, confirm: Some(ref code), .. } }
+                if code == "K7Q2M9XA"
+        ));
+        let cli = parse(&["discord", "purge", "bot-test"]).unwrap();
+        assert!(matches!(cli.command, Command::Discord { action: DiscordAction::Purge { limit: 100, confirm: None, .. } }));
+        assert!(parse(&["discord", "purge", "bot-test", "-n", "500"]).is_err());
+        assert!(parse(&["discord", "purge", "bot-test", "-n", "0"]).is_err());
+
+        let cli = parse(&["discord", "delete", "old", "--confirm", "ABC"]).unwrap();
+        assert!(matches!(cli.command, Command::Discord { action: DiscordAction::Delete { confirm: Some(_), .. } }));
+    }
+}
@@ -1,10 +1,19 @@
 use anyhow::{Context, Result};
+use std::path::Path;
 use std::process::Stdio;
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::process::Command;
+use std::sync::Mutex;
+use tokio::io::{AsyncBufReadExt, BufReader, Lines};
+use tokio::process::{Child, ChildStdout, Command};
 use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 
-use crate::claude::{self, StreamEvent, NEYWA_SYSTEM_PROMPT};
+use crate::claude::{self, StreamEvent};
+use crate::children;
+use crate::discord_api::ORIGIN_CHANNEL_ENV;
+use crate::stream::CodexParser;
+
+/// Model used for codex runs
+pub const MODEL: &str = "gpt-5.2";
 
 /// Build the base codex command
 fn base_command() -> Result<Command> {
@@ -14,22 +23,165 @@ fn base_command() -> Result<Command> {
     let mut cmd = Command::new(cli_path);
     cmd.arg("exec")
         .arg("--model")
-        .arg("gpt-5.2");
+        .arg(MODEL);
     Ok(cmd)
 }
 
-/// Run Codex CLI with streaming output (JSON Lines)
-/// Returns a receiver for stream events
-pub async fn run_streaming(
-    message: &str,
-    session_id: Option<&str>,
-) -> Result<mpsc::Receiver<StreamEvent>> {
-    let (tx, rx) = mpsc::channel(100);
+/// Codex CLI version as (major, minor, patch)
+pub type CodexVersion = (u32, u32, u32);
+
+/// First version where `resume` is a subcommand of `exec`
+const EXEC_RESUME_SINCE: CodexVersion = (0, 30, 0);
+
+/// How to continue an existing thread with `codex exec`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResumeSyntax {
+    /// `codex exec resume <id> ...`
+    Subcommand,
+    /// `codex exec --thread <id> ...`
+    ThreadFlag,
+}
+
+impl ResumeSyntax {
+    /// Syntax expected by a given codex version (newest syntax when unknown)
+    pub fn for_version(version: Option<CodexVersion>) -> Self {
+        match version {
+            Some(v) if v < EXEC_RESUME_SINCE => ResumeSyntax::ThreadFlag,
+            _ => ResumeSyntax::Subcommand,
+        }
+    }
+
+    pub fn alternate(self) -> Self {
+        match self {
+            ResumeSyntax::Subcommand => ResumeSyntax::ThreadFlag,
+            ResumeSyntax::ThreadFlag => ResumeSyntax::Subcommand,
+        }
+    }
+}
+
+/// Arguments placed after `exec --model <m>` to resume a thread
+pub fn resume_args(syntax: ResumeSyntax, thread_id: &str) -> Vec<String> {
+    match syntax {
+        ResumeSyntax::Subcommand => vec!["resume".to_string(), thread_id.to_string()],
+        ResumeSyntax::ThreadFlag => vec!["--thread".to_string(), thread_id.to_string()],
+    }
+}
+
+/// Parse `codex --version` output, e.g. "codex-cli 0.46.0"
+pub fn parse_version(output: &str) -> Option<CodexVersion> {
+    let token = output
+        .split_whitespace()
+        .find(|t| t.chars().next().is_some_and(|c| c.is_ascii_digit()))?;
+    let mut parts = token
+        .trim_start_matches('v')
+        .split(|c: char| !c.is_ascii_digit())
+        .map(|p| p.parse::<u32>().ok());
+    let major = parts.next()??;
+    let minor = parts.next().flatten().unwrap_or(0);
+    let patch = parts.next().flatten().unwrap_or(0);
+    Some((major, minor, patch))
+}
+
+/// Whether stderr looks like a clap usage error rather than a real failure
+pub fn is_usage_error(stderr: &str) -> bool {
+    let lower = stderr.to_lowercase();
+    lower.contains("usage:")
+        && (lower.contains("unexpected argument")
+            || lower.contains("unrecognized subcommand")
+            || lower.contains("unexpected value")
+            || lower.contains("error:"))
+}
+
+/// Probed codex version and the resume syntax known to work (probed once per process)
+struct ResumeState {
+    version: Option<CodexVersion>,
+    syntax: ResumeSyntax,
+}
+
+static RESUME_STATE: Mutex<Option<ResumeState>> = Mutex::new(None);
+
+/// Cached version and resume syntax, probing `codex --version` the first time
+async fn resume_state() -> (Option<CodexVersion>, ResumeSyntax) {
+    if let Some(state) = RESUME_STATE.lock().unwrap().as_ref() {
+        return (state.version, state.syntax);
+    }
 
+    let version = match claude::find_cli("codex") {
+        Some(path) => Command::new(path)
+            .arg("--version")
+            .output()
+            .await
+            .ok()
+            .and_then(|out| parse_version(&String::from_utf8_lossy(&out.stdout))),
+        None => None,
+    };
+    let syntax = ResumeSyntax::for_version(version);
+    tracing::info!("codex version {:?}, using {:?} resume syntax", version, syntax);
+    *RESUME_STATE.lock().unwrap() = Some(ResumeState { version, syntax });
+    (version, syntax)
+}
+
+/// Remember a resume syntax that worked
+fn remember_syntax(syntax: ResumeSyntax) {
+    if let Some(state) = RESUME_STATE.lock().unwrap().as_mut() {
+        state.syntax = syntax;
+    }
+}
+
+fn format_version(version: Option<CodexVersion>) -> String {
+    match version {
+        Some((major, minor, patch)) => format!("{}.{}.{}", major, minor, patch),
+        None => "unknown version".to_string(),
+    }
+}
+
+/// A spawned codex process with its output readers
+struct Spawned {
+    child: Child,
+    /// Keeps the child's PID in the registry `!restart` kills from
+    _guard: children::ChildGuard,
+    lines: Lines<BufReader<ChildStdout>>,
+    /// First stdout line, read up front when resuming to detect usage errors
+    first_line: Option<String>,
+    /// Resolves to everything codex wrote to stderr
+    stderr_task: JoinHandle<String>,
+    /// The task's result once awaited (a JoinHandle can only be awaited once)
+    stderr: Option<String>,
+}
+
+impl Spawned {
+    /// Wait for an early exit and return its stderr
+    async fn exit_stderr(&mut self) -> String {
+        let _ = self.child.wait().await;
+        if self.stderr.is_none() {
+            self.stderr = Some((&mut self.stderr_task).await.unwrap_or_default());
+        }
+        self.stderr.clone().unwrap_or_default()
+    }
+}
+
+/// Spawn `codex exec`, optionally resuming a thread with the given syntax
+async fn spawn(
+    message: &str,
+    resume: Option<(ResumeSyntax, &str)>,
+    images: &[String],
+    cwd: Option<&Path>,
+    channel_id: u64,
+    tx: &mpsc::Sender<StreamEvent>,
+) -> Result<Spawned> {
     let mut cmd = base_command()?;
+    // The channel's working directory (`!cwd`); None inherits the daemon's
+    if let Some(dir) = cwd {
+        cmd.current_dir(dir);
+    }
+    // Lets `neywa discord` post confirmation prompts back to this channel
+    cmd.env(ORIGIN_CHANNEL_ENV, channel_id.to_string());
 
-    if let Some(sid) = session_id {
-        cmd.arg("resume").arg(sid);
+    if let Some((syntax, sid)) = resume {
+        cmd.args(resume_args(syntax, sid));
+    }
+    for image in images {
+        cmd.arg("--image").arg(image);
     }
 
     cmd.arg("--json")
@@ -39,13 +191,14 @@ pub async fn run_streaming(
         .stderr(Stdio::piped());
 
     let mut child = cmd.spawn().context("Failed to spawn codex")?;
+    let guard = children::register(child.id());
 
     let stdout = child.stdout.take().context("Failed to get stdout")?;
     let stderr = child.stderr.take().context("Failed to get stderr")?;
 
     // Spawn task to read stderr in background
     let stderr_tx = tx.clone();
-    tokio::spawn(async move {
+    let stderr_task = tokio::spawn(async move {
         let reader = BufReader::new(stderr);
         let mut lines = reader.lines();
         let mut stderr_buf = String::new();
@@ -53,211 +206,113 @@ pub async fn run_streaming(
             stderr_buf.push_str(&line);
             stderr_buf.push('\n');
         }
-        if !stderr_buf.is_empty() {
-            let lower = stderr_buf.to_lowercase();
-            if lower.contains("context window")
-                || lower.contains("too many tokens")
-                || lower.contains("prompt is too long")
-                || lower.contains("max_tokens")
-            {
-                let _ = stderr_tx
-                    .send(StreamEvent::Text(
-                        "Prompt is too long".to_string(),
-                    ))
-                    .await;
-                let _ = stderr_tx.send(StreamEvent::Done).await;
-            }
+        if CodexParser::is_context_overflow(&stderr_buf) {
+            let _ = stderr_tx
+                .send(StreamEvent::Text(
+                    "Prompt is too long".to_string(),
+                ))
+                .await;
+            let _ = stderr_tx.send(StreamEvent::Done).await;
         }
+        stderr_buf
     });
 
-    // Spawn task to read streaming JSONL output
-    tokio::spawn(async move {
-        let reader = BufReader::new(stdout);
-        let mut lines = reader.lines();
-        let mut full_text = String::new();
-        let mut session_id_sent = false;
+    let mut lines = BufReader::new(stdout).lines();
+    let first_line = if resume.is_some() {
+        lines.next_line().await.ok().flatten()
+    } else {
+        None
+    };
 
-        while let Ok(Some(line)) = lines.next_line().await {
-            if let Ok(json) = serde_json::from_str::<serde_json::Value>(&line) {
-                let event_type = json.get("type").and_then(|v| v.as_str()).unwrap_or("");
-
-                match event_type {
-                    // Thread started → extract session ID (thread_id)
-                    "thread.started" => {
-                        if !session_id_sent {
-                            if let Some(tid) = json.get("thread_id").and_then(|v| v.as_str()) {
-                                let _ =
-                                    tx.send(StreamEvent::SessionId(tid.to_string())).await;
-                                session_id_sent = true;
-                            }
-                        }
-                    }
+    Ok(Spawned { child, _guard: guard, lines, first_line, stderr_task, stderr: None })
+}
 
-                    // Item started → tool use beginning
-                    "item.started" => {
-                        if let Some(item) = json.get("item") {
-                            let item_type =
-                                item.get("type").and_then(|v| v.as_str()).unwrap_or("");
-                            match item_type {
-                                "command_execution" => {
-                                    let cmd_str = item
-                                        .get("command")
-                                        .and_then(|v| v.as_str())
-                                        .unwrap_or("...");
-                                    let short: String = cmd_str.chars().take(50).collect();
-                                    let _ = tx
-                                        .send(StreamEvent::ToolUse(
-                                            "Bash".to_string(),
-                                            format!("💻 {}", short),
-                                        ))
-                                        .await;
-                                }
-                                "file_read" => {
-                                    let path = item
-                                        .get("file_path")
-                                        .and_then(|v| v.as_str())
-                                        .unwrap_or("...");
-                                    let _ = tx
-                                        .send(StreamEvent::ToolUse(
-                                            "Read".to_string(),
-                                            format!("📖 {}", claude::shorten_path(path)),
-                                        ))
-                                        .await;
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
+/// Run Codex CLI with streaming output (JSON Lines)
+/// Returns a receiver for stream events. `images` are attached with `--image`.
+pub async fn run_streaming(
+    message: &str,
+    session_id: Option<&str>,
+    images: &[String],
+    cwd: Option<&Path>,
+    channel_id: u64,
+) -> Result<mpsc::Receiver<StreamEvent>> {
+    let (tx, rx) = mpsc::channel(100);
 
-                    // Item completed → process result
-                    "item.completed" => {
-                        if let Some(item) = json.get("item") {
-                            let item_type =
-                                item.get("type").and_then(|v| v.as_str()).unwrap_or("");
-                            match item_type {
-                                "agent_message" => {
-                                    // Accumulate text from agent messages
-                                    if let Some(content) = item.get("content") {
-                                        if let Some(arr) = content.as_array() {
-                                            for part in arr {
-                                                if let Some(text) =
-                                                    part.get("text").and_then(|v| v.as_str())
-                                                {
-                                                    if !full_text.is_empty() {
-                                                        full_text.push('\n');
-                                                    }
-                                                    full_text.push_str(text);
-                                                }
-                                            }
-                                        } else if let Some(text) = content.as_str() {
-                                            if !full_text.is_empty() {
-                                                full_text.push('\n');
-                                            }
-                                            full_text.push_str(text);
-                                        }
-                                    }
-                                    // Also check for top-level text field
-                                    if let Some(text) =
-                                        item.get("text").and_then(|v| v.as_str())
-                                    {
-                                        if !full_text.is_empty() {
-                                            full_text.push('\n');
-                                        }
-                                        full_text.push_str(text);
-                                    }
-                                    if !full_text.is_empty() {
-                                        let _ = tx
-                                            .send(StreamEvent::Text(full_text.clone()))
-                                            .await;
-                                    }
-                                }
-                                "command_execution" => {
-                                    let cmd_str = item
-                                        .get("command")
-                                        .and_then(|v| v.as_str())
-                                        .unwrap_or("...");
-                                    let short: String = cmd_str.chars().take(50).collect();
-                                    let _ = tx
-                                        .send(StreamEvent::ToolUse(
-                                            "Bash".to_string(),
-                                            format!("💻 {} ✓", short),
-                                        ))
-                                        .await;
-                                }
-                                "file_changes" => {
-                                    let file = item
-                                        .get("file_path")
-                                        .and_then(|v| v.as_str())
-                                        .unwrap_or("files");
-                                    let _ = tx
-                                        .send(StreamEvent::ToolUse(
-                                            "Edit".to_string(),
-                                            format!(
-                                                "✏️ {}",
-                                                claude::shorten_path(file)
-                                            ),
-                                        ))
-                                        .await;
-                                }
-                                "web_searches" => {
-                                    let query = item
-                                        .get("query")
-                                        .and_then(|v| v.as_str())
-                                        .unwrap_or("search");
-                                    let _ = tx
-                                        .send(StreamEvent::ToolUse(
-                                            "WebSearch".to_string(),
-                                            format!(
-                                                "🌐 {}",
-                                                claude::truncate_str(query, 40)
-                                            ),
-                                        ))
-                                        .await;
-                                }
-                                "mcp_tool_calls" => {
-                                    let tool = item
-                                        .get("tool_name")
-                                        .and_then(|v| v.as_str())
-                                        .unwrap_or("tool");
-                                    let _ = tx
-                                        .send(StreamEvent::ToolUse(
-                                            "MCP".to_string(),
-                                            format!("🔌 {}", tool),
-                                        ))
-                                        .await;
-                                }
-                                "reasoning" => {
-                                    // Internal reasoning - ignore
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
+    let mut spawned = match session_id {
+        Some(sid) => {
+            let (version, syntax) = resume_state().await;
+            let mut spawned = spawn(message, Some((syntax, sid)), images, cwd, channel_id, &tx).await?;
 
-                    // Turn completed
-                    "turn.completed" => {
-                        let _ = tx.send(StreamEvent::Done).await;
+            // Exited without output: retry once with the other syntax on a usage error
+            if spawned.first_line.is_none() && is_usage_error(&spawned.exit_stderr().await) {
+                let alternate = syntax.alternate();
+                tracing::warn!("codex rejected {:?} resume syntax, retrying with {:?}", syntax, alternate);
+                spawned = spawn(message, Some((alternate, sid)), images, cwd, channel_id, &tx).await?;
+                if spawned.first_line.is_none() {
+                    let stderr = spawned.exit_stderr().await;
+                    if is_usage_error(&stderr) {
+                        tracing::error!("codex resume failed with both syntaxes: {}", stderr);
+                        anyhow::bail!(
+                            "codex {} doesn't accept either known resume syntax (`exec resume <id>` or `exec --thread <id>`). Update codex or start a new session with !new",
+                            format_version(version)
+                        );
                     }
+                }
+                remember_syntax(alternate);
+            }
+            spawned
+        }
+        None => spawn(message, None, images, cwd, channel_id, &tx).await?,
+    };
 
-                    // Turn failed
-                    "turn.failed" => {
-                        let error_msg = json
-                            .get("error")
-                            .and_then(|v| v.as_str())
-                            .or_else(|| json.get("message").and_then(|v| v.as_str()))
-                            .unwrap_or("Unknown error");
-                        let _ = tx
-                            .send(StreamEvent::Error(error_msg.to_string()))
-                            .await;
-                    }
+    // Spawn task to read streaming JSONL output
+    tokio::spawn(async move {
+        let mut parser = CodexParser::new();
+        let mut pending = spawned.first_line.take();
+        let mut answered = false;
+        let mut answer = String::new();
+        let mut auth_reported = false;
 
-                    _ => {}
-                }
+        loop {
+            let line = match pending.take() {
+                Some(line) => line,
+                None => match claude::next_line(&mut spawned.lines, &tx).await {
+                    Some(line) => line,
+                    None => break,
+                },
+            };
+            for event in parser.parse_line(&line) {
+                answered |= matches!(event, StreamEvent::Text(_) | StreamEvent::Error(_));
+                let event = match event {
+                    StreamEvent::Error(error) if claude::is_auth_failure(&error) => {
+                        auth_reported = true;
+                        StreamEvent::AuthRequired(claude::login_hint("codex"))
+                    }
+                    StreamEvent::Text(text) => {
+                        answer.clone_from(&text);
+                        StreamEvent::Text(text)
+                    }
+                    event => event,
+                };
+                let _ = tx.send(event).await;
             }
         }
 
+        // Nobody is listening any more: stop codex rather than let it finish unseen
+        if tx.is_closed() {
+            let _ = spawned.child.kill().await;
+        }
+
         // Wait for process to complete
-        let _ = child.wait().await;
+        let status = spawned.child.wait().await.ok();
+        let stderr = spawned.exit_stderr().await;
+        if let Some(hint) = claude::auth_error("codex", status, &stderr, &answer).filter(|_| !auth_reported) {
+            let _ = tx.send(StreamEvent::AuthRequired(hint)).await;
+        } else if !CodexParser::is_context_overflow(&stderr) {
+            if let Some(error) = claude::exit_error("codex", status, answered, &stderr) {
+                let _ = tx.send(StreamEvent::Error(error)).await;
+            }
+        }
 
         // Send done if not already sent
         let _ = tx.send(StreamEvent::Done).await;
@@ -266,23 +321,6 @@ pub async fn run_streaming(
     Ok(rx)
 }
 
-/// Build a prompt with system instructions injected (for first message only)
-pub fn build_prompt_with_system(
-    message: &str,
-    channel_system_prompt: &str,
-    username: &str,
-    is_first_message: bool,
-) -> String {
-    if is_first_message {
-        format!(
-            "[SYSTEM INSTRUCTIONS]\n{}\n{}\n[END SYSTEM INSTRUCTIONS]\n\n[{}]: {}",
-            NEYWA_SYSTEM_PROMPT, channel_system_prompt, username, message
-        )
-    } else {
-        format!("[{}]: {}", username, message)
-    }
-}
-
 /// Run Codex CLI and return the response (non-streaming)
 pub async fn run(message: &str) -> Result<String> {
     let cli_path = claude::find_cli("codex")
@@ -290,16 +328,14 @@ pub async fn run(message: &str) -> Result<String> {
 
     tracing::debug!("Sending to codex: {}", message);
 
-    let output = Command::new(cli_path)
-        .arg("exec")
+    let mut cmd = Command::new(cli_path);
+    cmd.arg("exec")
         .arg("--model")
-        .arg("gpt-5.2")
+        .arg(MODEL)
         .arg("--json")
         .arg("--dangerously-bypass-approvals-and-sandbox")
-        .arg(message)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
+        .arg(message);
+    let output = children::output(&mut cmd)
         .await
         .context("Failed to execute codex")?;
 
@@ -348,3 +384,42 @@ pub async fn run(message: &str) -> Result<String> {
 
     Ok(result_text.trim().to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_codex_versions() {
+        assert_eq!(parse_version("codex-cli 0.46.0"), Some((0, 46, 0)));
+        assert_eq!(parse_version("codex 1.2"), Some((1, 2, 0)));
+        assert_eq!(parse_version("codex-cli 0.29.1-alpha.3\n"), Some((0, 29, 1)));
+        assert_eq!(parse_version("codex-cli"), None);
+        assert_eq!(parse_version(""), None);
+    }
+
+    #[test]
+    fn resume_syntax_by_version() {
+        assert_eq!(ResumeSyntax::for_version(Some((0, 29, 9))), ResumeSyntax::ThreadFlag);
+        assert_eq!(ResumeSyntax::for_version(Some((0, 30, 0))), ResumeSyntax::Subcommand);
+        assert_eq!(ResumeSyntax::for_version(Some((1, 0, 0))), ResumeSyntax::Subcommand);
+        assert_eq!(ResumeSyntax::for_version(None), ResumeSyntax::Subcommand);
+        assert_eq!(ResumeSyntax::Subcommand.alternate(), ResumeSyntax::ThreadFlag);
+        assert_eq!(ResumeSyntax::ThreadFlag.alternate(), ResumeSyntax::Subcommand);
+
+        assert_eq!(resume_args(ResumeSyntax::Subcommand, "t-1"), ["resume", "t-1"]);
+        assert_eq!(resume_args(ResumeSyntax::ThreadFlag, "t-1"), ["--thread", "t-1"]);
+        assert_eq!(format_version(Some((0, 46, 2))), "0.46.2");
+        assert_eq!(format_version(None), "unknown version");
+    }
+
+    #[test]
+    fn usage_errors() {
+        assert!(is_usage_error(
+            "error: unexpected argument '--thread' found\n\nUsage: codex exec [OPTIONS] [PROMPT]"
+        ));
+        assert!(is_usage_error("error: unrecognized subcommand 'resume'\n\nUsage: codex exec"));
+        assert!(!is_usage_error("Error: stream disconnected before completion"));
+        assert!(!is_usage_error("Usage: codex exec [OPTIONS]"));
+    }
+}
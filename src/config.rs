@@ -1,16 +1,403 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+use crate::alias::Aliases;
+use crate::quiet::{self, QuietHours};
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
     pub discord_bot_token: Option<String>,
     pub discord_guild_id: Option<u64>,
     #[serde(default)]
     pub allowed_user_ids: Vec<u64>,
+    /// Directories `!get`/`!put` may touch (empty = home directory)
+    #[serde(default)]
+    pub allowed_roots: Vec<String>,
+    /// Max file size for `!get`/`!put` in bytes
+    #[serde(default = "default_max_transfer_bytes")]
+    pub max_transfer_bytes: u64,
+    /// Users exempt from quotas and allowed to run admin commands
+    #[serde(default)]
+    pub admin_user_ids: Vec<u64>,
+    /// Max requests per user per day (None = unlimited)
+    #[serde(default)]
+    pub user_daily_quota: Option<u32>,
+    /// Max spend per user per day in USD (None = unlimited)
+    #[serde(default)]
+    pub user_daily_cost_limit: Option<f64>,
+    /// Most CLI runs at once across all channels; further requests wait for a free slot (0 = no limit)
+    #[serde(default = "default_max_concurrent_tasks")]
+    pub max_concurrent_tasks: usize,
+    /// Stop a run that's still going after this many minutes (0 = never; channels can override with `!timeout`)
+    #[serde(default = "default_run_timeout_mins")]
+    pub run_timeout_mins: u64,
+    /// Max messages per user in any 60-minute window, across channels (None or 0 = unlimited)
+    #[serde(default)]
+    pub max_messages_per_user_per_hour: Option<u32>,
+    /// Send final responses as embeds (channels can override with `!embed`)
+    #[serde(default)]
+    pub embed_responses: bool,
+    /// Show responses while they are being written (channels can override with `!stream`)
+    #[serde(default)]
+    pub stream_responses: bool,
+    /// Attach responses longer than this many messages as a markdown file (0 = never; channels can override with `!longfile`)
+    #[serde(default = "default_attach_responses_over_chunks")]
+    pub attach_responses_over_chunks: u32,
+    /// Mask API keys/tokens in responses and logs
+    #[serde(default = "default_true")]
+    pub redact_secrets: bool,
+    /// Regex patterns for values that should never be redacted
+    #[serde(default)]
+    pub redact_allowlist: Vec<String>,
+    /// Text that also addresses Neywa in `!mention` channels, e.g. "neywa" (matched at the start, any case)
+    #[serde(default)]
+    pub mention_prefix: Option<String>,
+    /// Seconds to wait for follow-up messages from the same author before a run starts (0 = off)
+    #[serde(default = "default_coalesce_window_secs")]
+    pub coalesce_window_secs: u64,
+    /// Days a session can sit unused before it is no longer resumed and gets cleaned up (0 = never)
+    #[serde(default = "default_session_ttl_days")]
+    pub session_ttl_days: u32,
+    /// Seconds after a run starts during which editing its message restarts it
+    #[serde(default = "default_edit_grace_secs")]
+    pub edit_grace_secs: u64,
+    /// Respond to other bots and webhook messages (own messages are always ignored)
+    #[serde(default)]
+    pub respond_to_bots: bool,
+    /// Max responses to bots/webhooks per channel per minute before pausing the channel
+    #[serde(default = "default_bot_loop_limit")]
+    pub bot_loop_limit: u32,
+    /// Channels Neywa responds in (None = every channel)
+    #[serde(default)]
+    pub active_channels: Option<Vec<u64>>,
+    /// Channels Neywa never responds in, by ID or name (`#` optional); threads follow their parent's name
+    #[serde(default)]
+    pub ignored_channels: Vec<String>,
+    /// Context fill (percent) at which Neywa suggests `!compact` once per session (0 = never)
+    #[serde(default = "default_compact_suggest_percent")]
+    pub compact_suggest_percent: u8,
+    /// Require `--force` or approval in Discord for destructive `neywa discord` commands
+    #[serde(default = "default_true")]
+    pub confirm_destructive_discord_ops: bool,
+    /// Local time ("HH:MM") to post the daily digest to #logs (None = off)
+    #[serde(default = "default_digest_time")]
+    pub digest_time: Option<String>,
+    /// Ask Claude for a short natural-language digest alongside the numbers
+    #[serde(default)]
+    pub digest_ai_summary: bool,
+    /// Daily window ("23:00-08:00") during which completion messages are sent silently
+    #[serde(default)]
+    pub quiet_hours: Option<String>,
+    /// Quiet hours per guild ID, overriding `quiet_hours` there ("" = none for that guild)
+    #[serde(default)]
+    pub guild_quiet_hours: HashMap<u64, String>,
+    /// Timezone for `quiet_hours`: an IANA name like "Asia/Seoul", "UTC", or a fixed
+    /// offset like "+09:00" (None = host local time)
+    #[serde(default)]
+    pub quiet_hours_timezone: Option<String>,
+    /// Hold new requests during quiet hours and start them when the window ends
+    #[serde(default)]
+    pub quiet_hours_hold: bool,
+    /// Logs channel, remembered once discovered by name or bound with `!logs here`
+    #[serde(default)]
+    pub logs_channel_id: Option<u64>,
+    /// True when `logs_channel_id` was bound explicitly and overrides name-based discovery
+    #[serde(default)]
+    pub logs_channel_bound: bool,
+    /// Where failed runs are reported in full (None = the logs channel)
+    #[serde(default)]
+    pub errors_channel_id: Option<u64>,
+    /// Post a short introduction the first time Neywa connects to a server
+    #[serde(default = "default_true")]
+    pub onboarding_message: bool,
+    /// Post version and health to the logs channel when the daemon starts
+    #[serde(default = "default_true")]
+    pub startup_announcement: bool,
+    /// `!alias` shortcuts per guild (DMs use guild 0)
+    #[serde(default)]
+    pub aliases: HashMap<u64, Aliases>,
+    /// Port for the read-only status page (None = off)
+    #[serde(default)]
+    pub status_port: Option<u16>,
+    /// Listen on all interfaces instead of 127.0.0.1 (requires `status_token`)
+    #[serde(default)]
+    pub status_bind_all: bool,
+    /// Bearer token the status page requires when set
+    #[serde(default)]
+    pub status_token: Option<String>,
+    /// Warn (and refuse attachment downloads and updates) below this much free disk space
+    #[serde(default = "default_min_free_disk_mb")]
+    pub min_free_disk_mb: u64,
+    /// Largest Discord attachment Neywa downloads for a run, in bytes
+    #[serde(default = "default_max_attachment_bytes")]
+    pub max_attachment_bytes: u64,
+    /// Hours downloaded attachments are kept before cleanup
+    #[serde(default = "default_attachment_retention_hours")]
+    pub attachment_retention_hours: u64,
+    /// Cap on the attachment download directory; the oldest files go first
+    #[serde(default = "default_attachment_cache_mb")]
+    pub attachment_cache_mb: u64,
+    /// How long a `--dry-run` daemon pretends to work before echoing the prompt
+    #[serde(default = "default_dry_run_delay_ms")]
+    pub dry_run_delay_ms: u64,
+    /// How Neywa hints that a human-mode channel is ignoring messages
+    #[serde(default)]
+    pub human_mode_hint: HumanModeHint,
+    /// Seconds the daemon waits for Discord and child processes to stop before exiting anyway
+    #[serde(default = "default_shutdown_grace_secs")]
+    pub shutdown_grace_secs: u64,
+    /// Shell command that prints a transcript of `{file}` (None = `whisper` if installed)
+    #[serde(default)]
+    pub transcribe_command: Option<String>,
+    /// System prompts by channel ID or name (`#` optional), used instead of the built-in channel modes
+    #[serde(default)]
+    pub channel_prompts: HashMap<String, String>,
+}
+
+/// `human_mode_hint` values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HumanModeHint {
+    Off,
+    /// React with 🙋 to a user's first message of the day in each channel
+    #[default]
+    Reaction,
+    /// React with 🙋 to every message
+    OncePerUserMessage,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_max_transfer_bytes() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_max_concurrent_tasks() -> usize {
+    2
+}
+
+fn default_run_timeout_mins() -> u64 {
+    30
+}
+
+fn default_attach_responses_over_chunks() -> u32 {
+    3
+}
+
+fn default_coalesce_window_secs() -> u64 {
+    8
+}
+
+fn default_session_ttl_days() -> u32 {
+    7
+}
+
+fn default_edit_grace_secs() -> u64 {
+    10
+}
+
+fn default_bot_loop_limit() -> u32 {
+    5
+}
+
+fn default_compact_suggest_percent() -> u8 {
+    80
+}
+
+fn default_min_free_disk_mb() -> u64 {
+    1024
+}
+
+fn default_max_attachment_bytes() -> u64 {
+    100 * 1024 * 1024
+}
+
+fn default_attachment_retention_hours() -> u64 {
+    24
+}
+
+fn default_attachment_cache_mb() -> u64 {
+    2048
+}
+
+fn default_dry_run_delay_ms() -> u64 {
+    2000
+}
+
+fn default_shutdown_grace_secs() -> u64 {
+    5
+}
+
+fn default_digest_time() -> Option<String> {
+    Some("23:55".to_string())
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            discord_bot_token: None,
+            discord_guild_id: None,
+            allowed_user_ids: Vec::new(),
+            allowed_roots: Vec::new(),
+            max_transfer_bytes: default_max_transfer_bytes(),
+            admin_user_ids: Vec::new(),
+            user_daily_quota: None,
+            max_messages_per_user_per_hour: None,
+            user_daily_cost_limit: None,
+            embed_responses: false,
+            stream_responses: false,
+            mention_prefix: None,
+            attach_responses_over_chunks: default_attach_responses_over_chunks(),
+            max_concurrent_tasks: default_max_concurrent_tasks(),
+            run_timeout_mins: default_run_timeout_mins(),
+            redact_secrets: true,
+            redact_allowlist: Vec::new(),
+            coalesce_window_secs: default_coalesce_window_secs(),
+            session_ttl_days: default_session_ttl_days(),
+            edit_grace_secs: default_edit_grace_secs(),
+            respond_to_bots: false,
+            bot_loop_limit: default_bot_loop_limit(),
+            active_channels: None,
+            ignored_channels: Vec::new(),
+            compact_suggest_percent: default_compact_suggest_percent(),
+            confirm_destructive_discord_ops: true,
+            digest_time: default_digest_time(),
+            digest_ai_summary: false,
+            quiet_hours: None,
+            guild_quiet_hours: HashMap::new(),
+            quiet_hours_timezone: None,
+            quiet_hours_hold: false,
+            logs_channel_id: None,
+            logs_channel_bound: false,
+            errors_channel_id: None,
+            onboarding_message: true,
+            startup_announcement: true,
+            aliases: HashMap::new(),
+            status_port: None,
+            status_bind_all: false,
+            status_token: None,
+            min_free_disk_mb: default_min_free_disk_mb(),
+            max_attachment_bytes: default_max_attachment_bytes(),
+            attachment_retention_hours: default_attachment_retention_hours(),
+            attachment_cache_mb: default_attachment_cache_mb(),
+            dry_run_delay_ms: default_dry_run_delay_ms(),
+            human_mode_hint: HumanModeHint::default(),
+            shutdown_grace_secs: default_shutdown_grace_secs(),
+            transcribe_command: None,
+            channel_prompts: HashMap::new(),
+        }
+    }
 }
 
 impl Config {
+    /// Check whether a Discord user may use Neywa (empty allowlist = anyone)
+    pub fn is_allowed_user(&self, user_id: u64) -> bool {
+        self.allowed_user_ids.is_empty() || self.allowed_user_ids.contains(&user_id)
+    }
+
+    /// Quiet hours window for a guild: its own entry, else the global `quiet_hours`
+    pub fn quiet_hours_for(&self, guild_id: Option<u64>) -> Option<&str> {
+        match guild_id.and_then(|id| self.guild_quiet_hours.get(&id)) {
+            Some(window) => Some(window.as_str()).filter(|w| !w.trim().is_empty()),
+            None => self.quiet_hours.as_deref(),
+        }
+    }
+
+    /// Whether a guild is inside its quiet hours right now
+    pub fn in_quiet_hours(&self, guild_id: Option<u64>) -> bool {
+        quiet::current_window_end(self.quiet_hours_for(guild_id), self.quiet_hours_timezone.as_deref()).is_some()
+    }
+
+    /// Check whether a Discord user is an admin
+    pub fn is_admin(&self, user_id: u64) -> bool {
+        self.admin_user_ids.contains(&user_id)
+    }
+
+    /// The hourly per-user message limit, if one is set (0 turns it off)
+    pub fn hourly_message_limit(&self) -> Option<u32> {
+        self.max_messages_per_user_per_hour.filter(|limit| *limit > 0)
+    }
+
+    /// Custom system prompt for a channel, matched by ID first and then by name
+    /// (case-insensitive). Returns the matching key and the prompt.
+    pub fn channel_prompt(&self, channel_id: u64, name: &str) -> Option<(&str, &str)> {
+        if let Some((key, prompt)) = self.channel_prompts.get_key_value(&channel_id.to_string()) {
+            return Some((key, prompt));
+        }
+        if name.is_empty() {
+            return None;
+        }
+        self.channel_prompts
+            .iter()
+            .find(|(key, _)| key.trim_start_matches('#').eq_ignore_ascii_case(name))
+            .map(|(key, prompt)| (key.as_str(), prompt.as_str()))
+    }
+
+    /// How long an unused session is kept (None = forever)
+    pub fn session_ttl(&self) -> Option<chrono::Duration> {
+        (self.session_ttl_days > 0).then(|| chrono::Duration::days(self.session_ttl_days as i64))
+    }
+
+    /// Check whether Neywa should respond in a channel
+    pub fn is_active_channel(&self, channel_id: u64) -> bool {
+        self.active_channels
+            .as_ref()
+            .is_none_or(|channels| channels.contains(&channel_id))
+    }
+
+    /// Check whether a channel is listed in `ignored_channels`, by ID or case-insensitive name
+    pub fn is_ignored_channel(&self, channel_id: u64, name: &str) -> bool {
+        let id = channel_id.to_string();
+        self.ignored_channels.iter().any(|entry| {
+            let entry = entry.trim().trim_start_matches('#');
+            entry == id || (!name.is_empty() && entry.eq_ignore_ascii_case(name))
+        })
+    }
+
+    /// Directories file transfer never touches, whatever the allowed roots say:
+    /// Neywa's own config and state (bot token, sessions, audit log)
+    pub fn transfer_denied_roots(&self) -> Vec<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("neywa")).into_iter().collect()
+    }
+
+    /// Resolved allowed roots for file transfer (defaults to home directory)
+    pub fn transfer_roots(&self) -> Vec<PathBuf> {
+        if self.allowed_roots.is_empty() {
+            return dirs::home_dir().into_iter().collect();
+        }
+        self.allowed_roots
+            .iter()
+            .map(|r| match r.strip_prefix("~/") {
+                Some(rest) => dirs::home_dir().map(|h| h.join(rest)).unwrap_or_else(|| PathBuf::from(r)),
+                None => PathBuf::from(r),
+            })
+            .collect()
+    }
+
+    /// `shutdown_grace_secs` as a Duration
+    pub fn shutdown_grace(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.shutdown_grace_secs)
+    }
+
+    /// `min_free_disk_mb` in bytes
+    pub fn min_free_disk_bytes(&self) -> u64 {
+        self.min_free_disk_mb.saturating_mul(1024 * 1024)
+    }
+
+    /// `attachment_retention_hours` as a Duration
+    pub fn attachment_retention(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.attachment_retention_hours.saturating_mul(3600))
+    }
+
+    /// `attachment_cache_mb` in bytes
+    pub fn attachment_cache_bytes(&self) -> u64 {
+        self.attachment_cache_mb.saturating_mul(1024 * 1024)
+    }
+
     /// Get the config file path
     pub fn path() -> Result<PathBuf> {
         let config_dir = dirs::config_dir()
@@ -120,11 +507,27 @@ pub async fn install() -> Result<()> {
     let mut _dummy = String::new();
     std::io::stdin().read_line(&mut _dummy)?;
 
+    // 5. Claude Code hooks (optional)
+    println!("\nStep 5: Claude Code notifications (optional)");
+    println!("  Neywa can ping your #logs channel when a terminal Claude Code session");
+    println!("  finishes or needs attention (writes hooks to ~/.claude/settings.json).\n");
+
+    print!("Install Claude Code hooks? [y/N]: ");
+    std::io::Write::flush(&mut std::io::stdout())?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    if answer.trim().eq_ignore_ascii_case("y") {
+        if let Err(e) = install_hooks() {
+            println!("  Failed to install hooks: {}", e);
+        }
+    }
+
     // Save config
     let config = Config {
         discord_bot_token: Some(token),
         discord_guild_id: guild_id,
         allowed_user_ids: vec![],
+        ..Default::default()
     };
     config.save()?;
 
@@ -175,5 +578,334 @@ pub fn show() -> Result<()> {
         println!("Allowed User IDs: {:?}", config.allowed_user_ids);
     }
 
+    if config.allowed_roots.is_empty() {
+        println!("File Transfer Roots: (home directory)");
+    } else {
+        println!("File Transfer Roots: {:?}", config.allowed_roots);
+    }
+    println!("Max Transfer Size: {} bytes", config.max_transfer_bytes);
+
+    if !config.admin_user_ids.is_empty() {
+        println!("Admin User IDs: {:?}", config.admin_user_ids);
+    }
+    match config.user_daily_quota {
+        Some(quota) => println!("Daily Quota: {} requests/user", quota),
+        None => println!("Daily Quota: (unlimited)"),
+    }
+    if let Some(limit) = config.user_daily_cost_limit {
+        println!("Daily Cost Limit: ${:.2}/user", limit);
+    }
+    if let Some(limit) = config.hourly_message_limit() {
+        println!("Rate Limit: {} messages/user/hour", limit);
+    }
+
+    match config.max_concurrent_tasks {
+        0 => println!("Concurrent Tasks: no limit"),
+        n => println!("Concurrent Tasks: {}", n),
+    }
+    match config.run_timeout_mins {
+        0 => println!("Run Timeout: off"),
+        n => println!("Run Timeout: {} min", n),
+    }
+    println!("Embed Responses: {}", if config.embed_responses { "on" } else { "off" });
+    println!("Stream Responses: {}", if config.stream_responses { "on" } else { "off" });
+    match config.attach_responses_over_chunks {
+        0 => println!("Long Responses: always as messages"),
+        n => println!("Long Responses: attached as a file past {} messages", n),
+    }
+    if !config.channel_prompts.is_empty() {
+        let mut keys: Vec<&String> = config.channel_prompts.keys().collect();
+        keys.sort();
+        println!("Channel Prompts: {:?}", keys);
+    }
+    println!("Secret Redaction: {}", if config.redact_secrets { "on" } else { "off" });
+    if !config.redact_allowlist.is_empty() {
+        println!("Redaction Allowlist: {:?}", config.redact_allowlist);
+    }
+
+    if config.coalesce_window_secs == 0 {
+        println!("Message Coalescing: off");
+    } else {
+        println!("Message Coalescing: {}s", config.coalesce_window_secs);
+    }
+    println!("Edit Restart Window: {}s", config.edit_grace_secs);
+    if let Some(prefix) = &config.mention_prefix {
+        println!("Mention Prefix: {}", prefix);
+    }
+    if config.session_ttl_days == 0 {
+        println!("Session TTL: off");
+    } else {
+        println!("Session TTL: {} day(s)", config.session_ttl_days);
+    }
+    match &config.active_channels {
+        Some(channels) => println!("Active Channels: {:?}", channels),
+        None => println!("Active Channels: (all)"),
+    }
+    if !config.ignored_channels.is_empty() {
+        println!("Ignored Channels: {}", config.ignored_channels.join(", "));
+    }
+    if config.respond_to_bots {
+        println!("Respond to Bots: on (loop limit {}/min)", config.bot_loop_limit);
+    } else {
+        println!("Respond to Bots: off");
+    }
+    if config.compact_suggest_percent == 0 {
+        println!("Compact Suggestion: off");
+    } else {
+        println!("Compact Suggestion: at {}% context", config.compact_suggest_percent);
+    }
+    println!(
+        "Confirm Destructive Discord Ops: {}",
+        if config.confirm_destructive_discord_ops { "on" } else { "off" }
+    );
+    match &config.digest_time {
+        Some(time) => println!(
+            "Daily Digest: {}{}",
+            time,
+            if config.digest_ai_summary { " (with AI summary)" } else { "" }
+        ),
+        None => println!("Daily Digest: off"),
+    }
+    match config.quiet_hours.as_deref() {
+        Some(window) if QuietHours::parse(window).is_some() => println!(
+            "Quiet Hours: {} ({}){}",
+            window,
+            match config.quiet_hours_timezone.as_deref() {
+                Some(tz) => match quiet::Timezone::parse(tz) {
+                    Ok(_) => tz.trim().to_string(),
+                    Err(e) => format!("{}, using local time", e),
+                },
+                None => "local time".to_string(),
+            },
+            if config.quiet_hours_hold { ", requests held until the end" } else { "" }
+        ),
+        Some(window) => println!("Quiet Hours: invalid ({:?}, expected \"HH:MM-HH:MM\")", window),
+        None => println!("Quiet Hours: off"),
+    }
+    let mut guild_quiet: Vec<_> = config.guild_quiet_hours.iter().collect();
+    guild_quiet.sort();
+    for (guild_id, window) in guild_quiet {
+        match window.trim() {
+            "" => println!("  Guild {}: off", guild_id),
+            window if QuietHours::parse(window).is_some() => println!("  Guild {}: {}", guild_id, window),
+            window => println!("  Guild {}: invalid ({:?})", guild_id, window),
+        }
+    }
+    match config.logs_channel_id {
+        Some(id) if config.logs_channel_bound => println!("Logs Channel: {} (bound with !logs here)", id),
+        Some(id) => println!("Logs Channel: {}", id),
+        None => println!("Logs Channel: not found yet (a channel named #logs is picked up automatically)"),
+    }
+    match config.errors_channel_id {
+        Some(id) => println!("Errors Channel: {}", id),
+        None => println!("Errors Channel: (logs channel)"),
+    }
+    println!("Onboarding Message: {}", if config.onboarding_message { "on" } else { "off" });
+    println!("Startup Announcement: {}", if config.startup_announcement { "on" } else { "off" });
+    let alias_count: usize = config.aliases.values().map(|aliases| aliases.len()).sum();
+    println!("Aliases: {}", alias_count);
+    match config.status_port {
+        Some(port) => println!(
+            "Status Page: {}:{}{}",
+            if config.status_bind_all { "0.0.0.0" } else { "127.0.0.1" },
+            port,
+            if config.status_token.is_some() { " (token required)" } else { "" }
+        ),
+        None => println!("Status Page: off"),
+    }
+    println!("Low Disk Warning: below {} MB free", config.min_free_disk_mb);
+    println!("Max Attachment Size: {} bytes", config.max_attachment_bytes);
+    println!(
+        "Attachment Cleanup: after {}h, capped at {} MB",
+        config.attachment_retention_hours, config.attachment_cache_mb
+    );
+    println!("Dry-Run Delay: {} ms (used by `neywa daemon --dry-run`)", config.dry_run_delay_ms);
+    println!("Shutdown Grace Period: {}s", config.shutdown_grace_secs);
+    match &config.transcribe_command {
+        Some(command) => println!("Voice Transcription: {}", command),
+        None => println!("Voice Transcription: whisper (if installed)"),
+    }
+    println!(
+        "Human Mode Hint: {}",
+        match config.human_mode_hint {
+            HumanModeHint::Off => "off",
+            HumanModeHint::Reaction => "🙋 on each user's first message per day",
+            HumanModeHint::OncePerUserMessage => "🙋 on every message",
+        }
+    );
+
     Ok(())
 }
+
+/// Marker used to recognize hook entries Neywa installed
+const HOOK_MARKER: &str = "notify logs";
+
+/// Claude Code hook events Neywa subscribes to, with the message posted for each
+const HOOK_EVENTS: &[(&str, &str)] = &[
+    ("Stop", "✅ Claude Code session finished"),
+    ("Notification", "🔔 Claude Code needs your attention"),
+];
+
+/// Path to Claude Code's user settings
+fn claude_settings_path() -> Result<PathBuf> {
+    let home = dirs::home_dir().context("Could not find home directory")?;
+    Ok(home.join(".claude").join("settings.json"))
+}
+
+/// Merge Neywa's notify hooks into Claude Code settings without touching anything else.
+/// Returns true if the settings were changed (false if hooks were already present).
+pub fn merge_hooks(settings: &mut serde_json::Value, neywa_bin: &str) -> Result<bool> {
+    if settings.is_null() {
+        *settings = serde_json::json!({});
+    }
+    let root = settings
+        .as_object_mut()
+        .context("settings.json is not a JSON object")?;
+    let hooks = root
+        .entry("hooks")
+        .or_insert_with(|| serde_json::json!({}))
+        .as_object_mut()
+        .context("\"hooks\" in settings.json is not an object")?;
+
+    let mut changed = false;
+    for (event, message) in HOOK_EVENTS {
+        let entries = hooks
+            .entry(*event)
+            .or_insert_with(|| serde_json::json!([]))
+            .as_array_mut()
+            .with_context(|| format!("\"hooks.{}\" in settings.json is not an array", event))?;
+
+        let already_installed = entries.iter().any(|entry| {
+            entry["hooks"]
+                .as_array()
+                .map(|hs| {
+                    hs.iter().any(|h| {
+                        h["command"].as_str().map(|c| c.contains(HOOK_MARKER)).unwrap_or(false)
+                    })
+                })
+                .unwrap_or(false)
+        });
+        if already_installed {
+            continue;
+        }
+
+        entries.push(serde_json::json!({
+            "matcher": "",
+            "hooks": [{
+                "type": "command",
+                "command": format!("\"{}\" {} \"{}\"", neywa_bin, HOOK_MARKER, message),
+            }]
+        }));
+        changed = true;
+    }
+
+    Ok(changed)
+}
+
+/// Write Neywa notify hooks into ~/.claude/settings.json
+pub fn install_hooks() -> Result<()> {
+    let path = claude_settings_path()?;
+    let mut settings: serde_json::Value = if path.exists() {
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {:?}", path))?;
+        if content.trim().is_empty() {
+            serde_json::json!({})
+        } else {
+            serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse {:?} (fix it and retry)", path))?
+        }
+    } else {
+        serde_json::json!({})
+    };
+
+    let neywa_bin = std::env::current_exe()
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_else(|_| "neywa".to_string());
+
+    if !merge_hooks(&mut settings, &neywa_bin)? {
+        println!("Claude Code hooks already installed in {:?}", path);
+        return Ok(());
+    }
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    if path.exists() {
+        std::fs::copy(&path, path.with_extension("json.bak"))
+            .context("Failed to back up settings.json")?;
+    }
+    std::fs::write(&path, serde_json::to_string_pretty(&settings)?)
+        .with_context(|| format!("Failed to write {:?}", path))?;
+
+    println!("Installed Claude Code hooks in {:?}", path);
+    println!("  Stop / Notification events will post to your #logs channel.");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_hourly_limit_means_unlimited() {
+        let mut config = Config::default();
+        assert_eq!(config.hourly_message_limit(), None);
+        config.max_messages_per_user_per_hour = Some(0);
+        assert_eq!(config.hourly_message_limit(), None);
+        config.max_messages_per_user_per_hour = Some(20);
+        assert_eq!(config.hourly_message_limit(), Some(20));
+    }
+
+    #[test]
+    fn guild_quiet_hours_override_the_global_window() {
+        let config = Config {
+            quiet_hours: Some("23:00-08:00".to_string()),
+            guild_quiet_hours: HashMap::from([(1, "01:00-06:00".to_string()), (2, String::new())]),
+            ..Config::default()
+        };
+        assert_eq!(config.quiet_hours_for(Some(1)), Some("01:00-06:00"));
+        assert_eq!(config.quiet_hours_for(Some(2)), None);
+        assert_eq!(config.quiet_hours_for(Some(3)), Some("23:00-08:00"));
+        assert_eq!(config.quiet_hours_for(None), Some("23:00-08:00"));
+        assert_eq!(Config::default().quiet_hours_for(Some(1)), None);
+    }
+
+    #[test]
+    fn merge_hooks_into_empty_settings() {
+        let mut settings = serde_json::Value::Null;
+        assert!(merge_hooks(&mut settings, "/usr/local/bin/neywa").unwrap());
+
+        for (event, message) in HOOK_EVENTS {
+            let command = settings["hooks"][event][0]["hooks"][0]["command"].as_str().unwrap();
+            assert_eq!(command, format!("\"/usr/local/bin/neywa\" notify logs \"{}\"", message));
+        }
+    }
+
+    #[test]
+    fn merge_hooks_keeps_existing_entries() {
+        let mut settings = serde_json::json!({
+            "model": "opus",
+            "hooks": {
+                "Stop": [{ "matcher": "", "hooks": [{ "type": "command", "command": "say done" }] }]
+            }
+        });
+        assert!(merge_hooks(&mut settings, "neywa").unwrap());
+        assert_eq!(settings["model"], "opus");
+        let stop = settings["hooks"]["Stop"].as_array().unwrap();
+        assert_eq!(stop.len(), 2);
+        assert_eq!(stop[0]["hooks"][0]["command"], "say done");
+
+        // Second run is a no-op
+        let before = settings.clone();
+        assert!(!merge_hooks(&mut settings, "neywa").unwrap());
+        assert_eq!(settings, before);
+    }
+
+    #[test]
+    fn merge_hooks_rejects_bad_shapes() {
+        let mut not_object = serde_json::json!([1, 2]);
+        assert!(merge_hooks(&mut not_object, "neywa").is_err());
+        let mut bad_hooks = serde_json::json!({ "hooks": { "Stop": "nope" } });
+        assert!(merge_hooks(&mut bad_hooks, "neywa").is_err());
+    }
+}
@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// How long a pending confirmation stays valid
+pub const CONFIRM_WINDOW: Duration = Duration::from_secs(30);
+
+/// Short-lived confirmations for destructive commands, keyed by (user, channel)
+#[derive(Debug, Default)]
+pub struct Confirmations {
+    pending: HashMap<(u64, u64), (String, Instant)>,
+}
+
+impl Confirmations {
+    /// Start waiting for `!<command> confirm` (replaces any earlier request)
+    pub fn request(&mut self, key: (u64, u64), command: &str, now: Instant) {
+        self.pending.insert(key, (command.to_string(), now + CONFIRM_WINDOW));
+    }
+
+    /// Consume a pending confirmation. True only if one exists for the same
+    /// command and hasn't expired; any pending entry for the key is cleared.
+    pub fn confirm(&mut self, key: (u64, u64), command: &str, now: Instant) -> bool {
+        self.pending.retain(|_, (_, expires)| now < *expires);
+        match self.pending.remove(&key) {
+            Some((pending, _)) => pending == command,
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn confirm_matches_command_and_key() {
+        let mut confirmations = Confirmations::default();
+        let now = Instant::now();
+        confirmations.request((1, 10), "codex", now);
+        assert!(!confirmations.confirm((2, 10), "codex", now));
+        assert!(!confirmations.confirm((1, 10), "z", now));
+        // A mismatched confirm clears the pending request
+        assert!(!confirmations.confirm((1, 10), "codex", now));
+
+        confirmations.request((1, 10), "codex", now);
+        assert!(confirmations.confirm((1, 10), "codex", now + Duration::from_secs(5)));
+        // Consumed
+        assert!(!confirmations.confirm((1, 10), "codex", now + Duration::from_secs(6)));
+    }
+
+    #[test]
+    fn confirm_expires() {
+        let mut confirmations = Confirmations::default();
+        let now = Instant::now();
+        confirmations.request((1, 10), "codex", now);
+        assert!(!confirmations.confirm((1, 10), "codex", now + CONFIRM_WINDOW));
+    }
+}
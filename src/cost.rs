@@ -0,0 +1,148 @@
+use chrono::{Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+/// Sessions whose cost is remembered per channel (most recently used kept)
+const SESSIONS_PER_CHANNEL: usize = 20;
+
+/// Spend in one channel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelCost {
+    pub day: NaiveDate,
+    #[serde(default)]
+    pub today_usd: f64,
+    #[serde(default)]
+    pub total_usd: f64,
+    /// (session ID, USD), least recently used first
+    #[serde(default)]
+    sessions: Vec<(String, f64)>,
+}
+
+impl ChannelCost {
+    fn new(day: NaiveDate) -> Self {
+        Self { day, today_usd: 0.0, total_usd: 0.0, sessions: Vec::new() }
+    }
+
+    /// Cost recorded for a session in this channel
+    pub fn session_usd(&self, session_id: &str) -> Option<f64> {
+        self.sessions.iter().find(|(id, _)| id == session_id).map(|(_, usd)| *usd)
+    }
+}
+
+/// Persisted Claude spend per channel and session, from the `total_cost_usd`
+/// of each run's result event
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CostStore {
+    #[serde(default)]
+    channels: HashMap<u64, ChannelCost>,
+    /// Everything ever recorded, including channels since deleted
+    #[serde(default)]
+    all_time_usd: f64,
+}
+
+/// Path for storing cost totals
+fn cost_file_path() -> PathBuf {
+    let config_dir = dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("neywa");
+    config_dir.join("cost.json")
+}
+
+impl CostStore {
+    /// Load cost totals from file
+    pub fn load() -> Self {
+        let path = cost_file_path();
+        if !path.exists() {
+            return Self::default();
+        }
+        match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                tracing::warn!("Failed to parse cost file: {}", e);
+                Self::default()
+            }),
+            Err(e) => {
+                tracing::warn!("Failed to read cost file: {}", e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Save cost totals to file
+    pub fn save(&self) {
+        let path = cost_file_path();
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        match serde_json::to_string_pretty(self) {
+            Ok(json) => {
+                if let Err(e) = std::fs::write(&path, json) {
+                    tracing::warn!("Failed to save cost: {}", e);
+                }
+            }
+            Err(e) => tracing::warn!("Failed to serialize cost: {}", e),
+        }
+    }
+
+    /// Add one run's cost to its channel and session, rolling the channel's
+    /// daily total over when the local date has advanced
+    pub fn record(&mut self, channel_id: u64, session_id: Option<&str>, usd: f64) {
+        let today = Local::now().date_naive();
+        let channel = self.channels.entry(channel_id).or_insert_with(|| ChannelCost::new(today));
+        if today > channel.day {
+            channel.day = today;
+            channel.today_usd = 0.0;
+        }
+        channel.today_usd += usd;
+        channel.total_usd += usd;
+        if let Some(session_id) = session_id {
+            let previous = match channel.sessions.iter().position(|(id, _)| id == session_id) {
+                Some(index) => channel.sessions.remove(index).1,
+                None => 0.0,
+            };
+            channel.sessions.push((session_id.to_string(), previous + usd));
+            if channel.sessions.len() > SESSIONS_PER_CHANNEL {
+                channel.sessions.remove(0);
+            }
+        }
+        self.all_time_usd += usd;
+        self.save();
+    }
+
+    pub fn channel(&self, channel_id: u64) -> Option<&ChannelCost> {
+        self.channels.get(&channel_id)
+    }
+
+    /// A channel's spend today (0 if nothing was recorded today)
+    pub fn channel_today(&self, channel_id: u64) -> f64 {
+        let today = Local::now().date_naive();
+        self.channel(channel_id)
+            .filter(|c| c.day == today)
+            .map(|c| c.today_usd)
+            .unwrap_or(0.0)
+    }
+
+    pub fn all_time(&self) -> f64 {
+        self.all_time_usd
+    }
+
+    /// Forget deleted channels (the all-time total keeps their spend).
+    /// Returns how many were (or would be) removed.
+    pub fn prune(&mut self, deleted: &HashSet<u64>, dry_run: bool) -> usize {
+        let doomed = self.channels.keys().filter(|id| deleted.contains(id)).count();
+        if doomed > 0 && !dry_run {
+            self.channels.retain(|id, _| !deleted.contains(id));
+            self.save();
+        }
+        doomed
+    }
+}
+
+/// "$0.42", with more precision for small amounts
+pub fn format_usd(usd: f64) -> String {
+    if usd > 0.0 && usd < 0.01 {
+        format!("${:.4}", usd)
+    } else {
+        format!("${:.2}", usd)
+    }
+}
@@ -0,0 +1,522 @@
+use anyhow::{Context, Result};
+use chrono::Local;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use crate::schedule::{CronSpec, ScheduleStore, Trigger};
+
+/// A crontab line that runs `neywa run`, written by the old Tasks-channel prompt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronEntry {
+    /// Zero-based line number in the crontab
+    pub line: usize,
+    /// "0 9 * * 1-5" or "@daily"
+    pub schedule: String,
+    pub command: String,
+}
+
+/// Split a crontab job line into (schedule, command). None for comments,
+/// blank lines and environment assignments.
+fn split_job(line: &str) -> Option<(String, String)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let fields = if line.starts_with('@') { 1 } else { 5 };
+    let mut rest = line;
+    let mut schedule = Vec::with_capacity(fields);
+    for _ in 0..fields {
+        let (field, tail) = rest.split_once(char::is_whitespace)?;
+        schedule.push(field);
+        rest = tail.trim_start();
+    }
+    // `NAME=value` lines have no schedule fields with cron characters
+    if fields == 5 && schedule.iter().any(|f| !f.chars().all(|c| c.is_ascii_alphanumeric() || "*/,-".contains(c))) {
+        return None;
+    }
+    if rest.is_empty() {
+        return None;
+    }
+    Some((schedule.join(" "), rest.to_string()))
+}
+
+/// Whether a job command invokes `neywa run` (bare or by path, e.g. `/usr/local/bin/neywa run`)
+fn runs_neywa(command: &str) -> bool {
+    let words: Vec<&str> = command.split_whitespace().collect();
+    words
+        .windows(2)
+        .any(|pair| (pair[0] == "neywa" || pair[0].ends_with("/neywa")) && pair[1] == "run")
+}
+
+/// Every `neywa run` job in a crontab, leaving all other content alone
+pub fn parse(crontab: &str) -> Vec<CronEntry> {
+    crontab
+        .lines()
+        .enumerate()
+        .filter_map(|(line, text)| {
+            let (schedule, command) = split_job(text)?;
+            runs_neywa(&command).then_some(CronEntry { line, schedule, command })
+        })
+        .collect()
+}
+
+/// The crontab without the given lines. Every other line is kept byte for byte.
+pub fn without_lines(crontab: &str, lines: &[usize]) -> String {
+    let mut kept: Vec<&str> = crontab
+        .lines()
+        .enumerate()
+        .filter(|(i, _)| !lines.contains(i))
+        .map(|(_, line)| line)
+        .collect();
+    if crontab.ends_with('\n') {
+        kept.push("");
+    }
+    kept.join("\n")
+}
+
+/// A `neywa run` crontab job as a Neywa schedule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Migration {
+    pub entry: CronEntry,
+    /// Five-field cron expression (`@daily` and friends expanded)
+    pub cron: String,
+    pub prompt: String,
+    /// The job's `--channel` (name or ID), if it had one
+    pub channel: Option<String>,
+}
+
+/// Five-field form of a schedule, expanding cron's `@` macros
+fn expand_schedule(schedule: &str) -> Result<String, String> {
+    let expr = match schedule {
+        "@yearly" | "@annually" => "0 0 1 1 *",
+        "@monthly" => "0 0 1 * *",
+        "@weekly" => "0 0 * * 0",
+        "@daily" | "@midnight" => "0 0 * * *",
+        "@hourly" => "0 * * * *",
+        "@reboot" => return Err("`@reboot` jobs have no time to schedule; keep them in the crontab.".to_string()),
+        other if other.starts_with('@') => return Err(format!("Unknown schedule `{}`.", other)),
+        other => other,
+    };
+    match CronSpec::parse(expr) {
+        Some(spec) if spec.next_after(Local::now()).is_some() => Ok(expr.to_string()),
+        Some(_) => Err(format!("`{}` never fires.", schedule)),
+        None => Err(format!("Neywa's scheduler can't express `{}`.", schedule)),
+    }
+}
+
+/// What the shell gets from a crontab command: cron turns the first unescaped
+/// `%` into a newline and feeds the rest to stdin, and `\%` into a literal `%`
+fn shell_command(command: &str) -> String {
+    let mut out = String::new();
+    let mut chars = command.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'%') => out.push(chars.next().unwrap_or('%')),
+            '%' => break,
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// A shell word, or an unquoted operator (`>`, `&&`, `;` ...) that ends a command
+#[derive(Debug, PartialEq, Eq)]
+enum Word {
+    Text(String),
+    Operator,
+}
+
+/// Split a shell command into words, honouring quotes and backslashes.
+/// None when a quote is left open.
+fn shell_words(command: &str) -> Option<Vec<Word>> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = command.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next()? {
+                        '\'' => break,
+                        c => word.push(c),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        '\\' if chars.peek().is_some_and(|c| "\"\\$`".contains(*c)) => word.push(chars.next()?),
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                if let Some(c) = chars.next() {
+                    word.push(c);
+                }
+            }
+            ';' | '|' | '&' | '<' | '>' => {
+                // `2>&1`: the digits are a file descriptor, not an argument
+                let fd = in_word && matches!(c, '<' | '>') && word.chars().all(|c| c.is_ascii_digit());
+                if in_word && !fd {
+                    words.push(Word::Text(std::mem::take(&mut word)));
+                }
+                word.clear();
+                in_word = false;
+                while chars.peek().is_some_and(|c| ";|&<>".contains(*c)) {
+                    chars.next();
+                }
+                words.push(Word::Operator);
+            }
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(Word::Text(std::mem::take(&mut word)));
+                    in_word = false;
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(Word::Text(word));
+    }
+    Some(words)
+}
+
+/// Work out the schedule, prompt and channel of a `neywa run` job
+pub fn plan_migration(entry: &CronEntry) -> Result<Migration, String> {
+    let cron = expand_schedule(&entry.schedule)?;
+    let words = shell_words(&shell_command(&entry.command)).ok_or("The command has an unclosed quote.")?;
+    let start = words
+        .windows(2)
+        .position(|pair| match pair {
+            [Word::Text(program), Word::Text(run)] => (program == "neywa" || program.ends_with("/neywa")) && run == "run",
+            _ => false,
+        })
+        .ok_or("No `neywa run` command found.")?;
+
+    let mut prompt = None;
+    let mut channel = None;
+    let mut args = words[start + 2..].iter();
+    while let Some(Word::Text(arg)) = args.next() {
+        if arg == "--channel" {
+            match args.next() {
+                Some(Word::Text(value)) => channel = Some(value.clone()),
+                _ => return Err("`--channel` has no value.".to_string()),
+            }
+        } else if let Some(value) = arg.strip_prefix("--channel=") {
+            channel = Some(value.to_string());
+        } else if arg.starts_with('-') && arg.len() > 1 {
+            // `--post` and friends don't matter to a scheduled run
+        } else if prompt.is_none() {
+            prompt = Some(arg.clone());
+        } else {
+            return Err(format!("Unexpected argument `{}`.", arg));
+        }
+    }
+    let prompt = prompt.filter(|p| !p.trim().is_empty()).ok_or("The job has no prompt.")?;
+    Ok(Migration { entry: entry.clone(), cron, prompt, channel })
+}
+
+/// The crontab with the given lines commented out, each noting the schedule
+/// job that replaced it. Every other line is kept byte for byte.
+pub fn commented_out(crontab: &str, lines: &[(usize, u32)]) -> String {
+    let mut out: Vec<String> = crontab
+        .lines()
+        .enumerate()
+        .map(|(i, line)| match lines.iter().find(|(l, _)| *l == i) {
+            Some((_, id)) => format!("# moved to Neywa schedule #{}: {}", id, line),
+            None => line.to_string(),
+        })
+        .collect();
+    if crontab.ends_with('\n') {
+        out.push(String::new());
+    }
+    out.join("\n")
+}
+
+/// `neywa cron import` / `!cron import` listing, numbered from 1
+pub fn describe(entries: &[CronEntry]) -> String {
+    if entries.is_empty() {
+        return "No `neywa run` entries in the crontab.".to_string();
+    }
+    let mut lines = vec![format!("Found {} `neywa run` crontab entr{}:", entries.len(), if entries.len() == 1 { "y" } else { "ies" })];
+    for (i, entry) in entries.iter().enumerate() {
+        lines.push(format!("{}. `{}` {}", i + 1, entry.schedule, entry.command));
+    }
+    lines.join("\n")
+}
+
+/// The current user's crontab (empty when they have none)
+pub fn read_crontab() -> Result<String> {
+    let output = Command::new("crontab").arg("-l").output().context("Failed to run crontab -l")?;
+    if output.status.success() {
+        return Ok(String::from_utf8_lossy(&output.stdout).to_string());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if stderr.to_lowercase().contains("no crontab") {
+        return Ok(String::new());
+    }
+    anyhow::bail!("crontab -l failed: {}", stderr.trim())
+}
+
+/// Save a copy of the crontab under the config dir before changing it
+fn backup(crontab: &str) -> Result<PathBuf> {
+    let dir = dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("neywa")
+        .join("crontab_backups");
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("crontab-{}.txt", Local::now().format("%Y%m%d-%H%M%S")));
+    std::fs::write(&path, crontab)?;
+    Ok(path)
+}
+
+fn write_crontab(crontab: &str) -> Result<()> {
+    let mut child = Command::new("crontab")
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run crontab -")?;
+    child
+        .stdin
+        .take()
+        .context("Failed to open crontab stdin")?
+        .write_all(crontab.as_bytes())?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        anyhow::bail!("crontab - failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+/// Parse `1 3` / `all` against the listed entries
+pub fn select(entries: &[CronEntry], args: &str) -> Result<Vec<CronEntry>, String> {
+    if args.trim() == "all" {
+        return Ok(entries.to_vec());
+    }
+    let mut selected = Vec::new();
+    for arg in args.split(|c: char| c.is_whitespace() || c == ',').filter(|a| !a.is_empty()) {
+        let index: usize = arg.parse().map_err(|_| format!("`{}` isn't an entry number.", arg))?;
+        let entry = index
+            .checked_sub(1)
+            .and_then(|i| entries.get(i))
+            .ok_or_else(|| format!("There is no entry {}.", index))?;
+        if !selected.contains(entry) {
+            selected.push(entry.clone());
+        }
+    }
+    if selected.is_empty() {
+        return Err("Give entry numbers (see `cron import`) or `all`.".to_string());
+    }
+    Ok(selected)
+}
+
+/// Remove entries from the crontab, backing up the original first. The
+/// crontab is re-read and each line re-checked, so only neywa lines are touched.
+/// Returns the backup path.
+pub fn remove(entries: &[CronEntry]) -> Result<PathBuf> {
+    let crontab = read_crontab()?;
+    let current = parse(&crontab);
+    for entry in entries {
+        if !current.contains(entry) {
+            anyhow::bail!("The crontab changed since it was listed; run `cron import` again.");
+        }
+    }
+    let path = backup(&crontab)?;
+    let lines: Vec<usize> = entries.iter().map(|e| e.line).collect();
+    write_crontab(&without_lines(&crontab, &lines))?;
+    Ok(path)
+}
+
+/// Move jobs into Neywa's scheduler: `add` creates the schedule job for a
+/// migration in the given channel and returns its ID, then the crontab lines
+/// are commented out (the original is backed up first). Returns the backup path
+/// and the new job IDs.
+pub fn migrate(
+    planned: &[(Migration, u64)],
+    mut add: impl FnMut(&Migration, u64) -> u32,
+) -> Result<(PathBuf, Vec<u32>)> {
+    let crontab = read_crontab()?;
+    let current = parse(&crontab);
+    for (migration, _) in planned {
+        if !current.contains(&migration.entry) {
+            anyhow::bail!("The crontab changed since it was listed; run `cron import` again.");
+        }
+    }
+    let path = backup(&crontab)?;
+    let ids: Vec<u32> = planned.iter().map(|(migration, channel_id)| add(migration, *channel_id)).collect();
+    let lines: Vec<(usize, u32)> = planned.iter().map(|(m, _)| m.entry.line).zip(ids.iter().copied()).collect();
+    write_crontab(&commented_out(&crontab, &lines))?;
+    Ok((path, ids))
+}
+
+/// One line of a migration preview: the job and what it becomes (or why it can't move)
+pub fn describe_migration(entry: &CronEntry, plan: &Result<(Migration, String), String>) -> String {
+    match plan {
+        Ok((migration, channel)) => format!(
+            "• `{}` {}\n  → `{}` \"{}\" in {}",
+            entry.schedule, entry.command, migration.cron, migration.prompt, channel
+        ),
+        Err(e) => format!("• `{}` {}\n  ✗ {}", entry.schedule, entry.command, e),
+    }
+}
+
+/// `neywa cron migrate`: move jobs into the schedule file. Jobs without a
+/// `--channel` go to `default_channel`. Refuses while the daemon runs, since it
+/// keeps its own copy of the schedule; `!cron migrate` does it there instead.
+pub async fn migrate_from_cli(selected: &[CronEntry], default_channel: Option<&str>, force: bool) -> Result<()> {
+    let mut planned = Vec::new();
+    for entry in selected {
+        let plan = match plan_migration(entry) {
+            Ok(migration) => match migration.channel.as_deref().or(default_channel) {
+                Some(channel) => match crate::discord_api::resolve_channel(channel).await {
+                    Ok((id, name)) => Ok((migration, id, name)),
+                    Err(e) => Err(e.to_string()),
+                },
+                None => Err("The job has no `--channel`; pass `--channel <name>` to pick one.".to_string()),
+            },
+            Err(e) => Err(e),
+        };
+        let preview = plan.as_ref().map(|(m, _, name)| (m.clone(), format!("#{}", name))).map_err(Clone::clone);
+        println!("{}", describe_migration(entry, &preview));
+        if let Ok((migration, id, _)) = plan {
+            planned.push((migration, id));
+        }
+    }
+
+    if planned.is_empty() {
+        anyhow::bail!("Nothing to migrate.");
+    }
+    if !force {
+        println!("\nNothing changed. Re-run with --force to move these into Neywa's scheduler.");
+        return Ok(());
+    }
+    if crate::daemon::is_running() {
+        anyhow::bail!("The daemon is running; use `!cron migrate` in Discord so it picks the jobs up.");
+    }
+
+    let mut store = ScheduleStore::load();
+    let now = Local::now();
+    let (backup, ids) = migrate(&planned, |migration, channel_id| {
+        let next_run = CronSpec::parse(&migration.cron).and_then(|spec| spec.next_after(now)).unwrap_or(now);
+        store.add(Trigger::Cron(migration.cron.clone()), next_run, &migration.prompt, channel_id, 0, "crontab").id
+    })?;
+    let ids: Vec<String> = ids.iter().map(|id| format!("#{}", id)).collect();
+    println!("\nMoved to schedule {}. Original crontab saved to {}", ids.join(", "), backup.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CRONTAB: &str = r#"# m h dom mon dow command
+SHELL=/bin/bash
+PATH = /usr/local/bin:/usr/bin:/bin
+MAILTO=""
+
+0 9 * * 1-5 /usr/local/bin/neywa run --channel tasks "Summarize my inbox" >> /tmp/neywa-cron.log 2>&1
+# 30 7 * * * neywa run "disabled job"
+@reboot neywa daemon
+@daily cd ~/dev && neywa run 'check for 100\% disk usage'%ignored stdin
+*/15 * * * * ~/backup.sh
+0 18 * * 5 neywa run "write the weekly \
+  report"
+"#;
+
+    fn entry(line: usize, schedule: &str, command: &str) -> CronEntry {
+        CronEntry { line, schedule: schedule.to_string(), command: command.to_string() }
+    }
+
+    #[test]
+    fn parse_finds_only_neywa_run_jobs() {
+        let entries = parse(CRONTAB);
+        assert_eq!(
+            entries,
+            vec![
+                entry(5, "0 9 * * 1-5", r#"/usr/local/bin/neywa run --channel tasks "Summarize my inbox" >> /tmp/neywa-cron.log 2>&1"#),
+                entry(8, "@daily", r#"cd ~/dev && neywa run 'check for 100\% disk usage'%ignored stdin"#),
+                // Cron has no line continuation: only the first half is a job
+                entry(10, "0 18 * * 5", r#"neywa run "write the weekly \"#),
+            ]
+        );
+    }
+
+    #[test]
+    fn split_job_skips_comments_env_and_blank_lines() {
+        assert_eq!(split_job("# 0 9 * * * neywa run x"), None);
+        assert_eq!(split_job("   "), None);
+        assert_eq!(split_job("SHELL=/bin/bash"), None);
+        assert_eq!(split_job("PATH = /usr/local/bin:/usr/bin:/bin"), None);
+        assert_eq!(split_job("CRON_TZ=Asia/Seoul 0 9 * * *"), None);
+        assert_eq!(split_job("@reboot neywa daemon"), Some(("@reboot".to_string(), "neywa daemon".to_string())));
+        assert_eq!(split_job("0 9 * * *"), None);
+    }
+
+    #[test]
+    fn plan_reads_channel_prompt_and_schedule() {
+        let entries = parse(CRONTAB);
+        let first = plan_migration(&entries[0]).unwrap();
+        assert_eq!(first.cron, "0 9 * * 1-5");
+        assert_eq!(first.prompt, "Summarize my inbox");
+        assert_eq!(first.channel.as_deref(), Some("tasks"));
+
+        // `@daily` expands; `\%` is a literal percent and the first bare `%` starts stdin
+        let second = plan_migration(&entries[1]).unwrap();
+        assert_eq!(second.cron, "0 0 * * *");
+        assert_eq!(second.prompt, "check for 100% disk usage");
+        assert_eq!(second.channel, None);
+
+        assert_eq!(plan_migration(&entries[2]).unwrap_err(), "The command has an unclosed quote.");
+    }
+
+    #[test]
+    fn plan_rejects_jobs_without_a_schedule_or_prompt() {
+        assert!(plan_migration(&entry(0, "@reboot", "neywa run hi")).unwrap_err().contains("@reboot"));
+        assert!(plan_migration(&entry(0, "0 9 * * *", "neywa run --channel=ops")).is_err());
+        assert!(plan_migration(&entry(0, "0 9 * * *", "neywa run one two")).is_err());
+        let plan = plan_migration(&entry(0, "0 9 * * *", "neywa run --post --channel=ops \"it's time\";echo done")).unwrap();
+        assert_eq!(plan.prompt, "it's time");
+        assert_eq!(plan.channel.as_deref(), Some("ops"));
+    }
+
+    #[test]
+    fn shell_words_handle_quotes_and_redirections() {
+        let words = shell_words(r#"a "b \"c\"" 'd e' 2>&1|f"#).unwrap();
+        assert_eq!(
+            words,
+            vec![
+                Word::Text("a".to_string()),
+                Word::Text("b \"c\"".to_string()),
+                Word::Text("d e".to_string()),
+                Word::Operator,
+                Word::Text("1".to_string()),
+                Word::Operator,
+                Word::Text("f".to_string()),
+            ]
+        );
+        assert_eq!(shell_words("echo 'open"), None);
+    }
+
+    #[test]
+    fn migrated_lines_are_commented_out() {
+        let crontab = "SHELL=/bin/bash\n0 9 * * * neywa run hi\n@daily other\n";
+        assert_eq!(
+            commented_out(crontab, &[(1, 4)]),
+            "SHELL=/bin/bash\n# moved to Neywa schedule #4: 0 9 * * * neywa run hi\n@daily other\n"
+        );
+        assert_eq!(without_lines(crontab, &[1]), "SHELL=/bin/bash\n@daily other\n");
+    }
+}
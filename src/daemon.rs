@@ -0,0 +1,195 @@
+//! The `neywa daemon` process: single-instance PID handling, sleep prevention,
+//! and wiring the Discord bot thread to the tray and the shutdown coordinator.
+
+use anyhow::Result;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use sysinfo::{Pid, System};
+
+use crate::config;
+use crate::discord;
+use crate::lifecycle;
+use crate::shutdown::{self, Shutdown};
+use crate::tray;
+
+/// Get the PID file path
+fn pid_file_path() -> Result<PathBuf> {
+    let config_dir = dirs::config_dir()
+        .ok_or_else(|| anyhow::anyhow!("Could not find config directory"))?
+        .join("neywa");
+    fs::create_dir_all(&config_dir)?;
+    Ok(config_dir.join("neywa.pid"))
+}
+
+/// Kill existing neywa daemon if running
+fn kill_existing_daemon() -> Result<()> {
+    let pid_path = pid_file_path()?;
+    let current_pid = std::process::id();
+
+    if pid_path.exists() {
+        if let Ok(pid_str) = fs::read_to_string(&pid_path) {
+            if let Ok(pid) = pid_str.trim().parse::<u32>() {
+                // Skip if the PID matches our own (happens after exec-based restart)
+                if pid == current_pid {
+                    tracing::info!("PID file contains our own PID ({}), skipping kill", pid);
+                    let _ = fs::remove_file(&pid_path);
+                    return Ok(());
+                }
+
+                let mut sys = System::new();
+                sys.refresh_processes(sysinfo::ProcessesToUpdate::All);
+
+                if let Some(process) = sys.process(Pid::from_u32(pid)) {
+                    // Verify it's actually neywa
+                    if process.name().to_string_lossy().contains("neywa") {
+                        tracing::info!("Stopping existing neywa daemon (PID: {})", pid);
+                        // SIGTERM runs its normal shutdown; kill it only if that takes too long
+                        if process.kill_with(sysinfo::Signal::Term).is_none() {
+                            process.kill();
+                        }
+                        let grace = config::Config::load().unwrap_or_default().shutdown_grace();
+                        let deadline = std::time::Instant::now() + grace + std::time::Duration::from_secs(1);
+                        while std::time::Instant::now() < deadline {
+                            std::thread::sleep(std::time::Duration::from_millis(200));
+                            sys.refresh_processes(sysinfo::ProcessesToUpdate::Some(&[Pid::from_u32(pid)]));
+                            if sys.process(Pid::from_u32(pid)).is_none() {
+                                break;
+                            }
+                        }
+                        if let Some(process) = sys.process(Pid::from_u32(pid)) {
+                            tracing::warn!("Daemon {} ignored SIGTERM, killing it", pid);
+                            process.kill();
+                            std::thread::sleep(std::time::Duration::from_millis(500));
+                        }
+                    }
+                }
+            }
+        }
+        // Remove old PID file
+        let _ = fs::remove_file(&pid_path);
+    }
+
+    Ok(())
+}
+
+/// Whether another neywa daemon is running, per the PID file
+pub fn is_running() -> bool {
+    let Ok(pid_str) = pid_file_path().and_then(|path| Ok(fs::read_to_string(path)?)) else {
+        return false;
+    };
+    let Ok(pid) = pid_str.trim().parse::<u32>() else {
+        return false;
+    };
+    if pid == std::process::id() {
+        return false;
+    }
+    let mut sys = System::new();
+    sys.refresh_processes(sysinfo::ProcessesToUpdate::Some(&[Pid::from_u32(pid)]));
+    sys.process(Pid::from_u32(pid))
+        .is_some_and(|process| process.name().to_string_lossy().contains("neywa"))
+}
+
+/// Write current PID to file
+fn write_pid_file() -> Result<()> {
+    let pid_path = pid_file_path()?;
+    let pid = std::process::id();
+    fs::write(&pid_path, pid.to_string())?;
+    tracing::info!("PID file written: {:?} (PID: {})", pid_path, pid);
+    Ok(())
+}
+
+/// Remove PID file on exit
+fn remove_pid_file() {
+    if let Ok(pid_path) = pid_file_path() {
+        let _ = fs::remove_file(pid_path);
+    }
+}
+
+/// Run the daemon until it's told to stop: replaces any running instance,
+/// keeps the machine awake, and runs the bot next to the tray icon
+pub fn run(dry_run: bool) -> Result<()> {
+    tracing::info!("Starting Neywa daemon{}...", if dry_run { " (dry run)" } else { "" });
+    discord::set_dry_run(dry_run);
+
+    // Kill existing daemon if running
+    kill_existing_daemon()?;
+
+    // Write PID file
+    write_pid_file()?;
+
+    // Record why we're (re)starting; consumes the clean-shutdown marker
+    lifecycle::record_start();
+
+    // Spawn caffeinate to prevent system sleep (display may still sleep)
+    let _caffeinate = std::process::Command::new("/usr/bin/caffeinate")
+        .arg("-s")
+        .arg("-w")
+        .arg(std::process::id().to_string())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .ok();
+    tracing::info!("Sleep prevention: caffeinate started");
+
+    // Run daemon
+    let result = run_daemon_with_tray();
+
+    // Cleanup
+    lifecycle::mark_clean_shutdown();
+    remove_pid_file();
+
+    result
+}
+
+fn run_daemon_with_tray() -> Result<()> {
+    // Create channels for communication between tray and daemon
+    let (status_tx, status_rx) = mpsc::channel();
+    let (quit_tx, quit_rx) = mpsc::channel();
+    discord::set_tray_sender(status_tx.clone());
+
+    // Ctrl+C, SIGTERM and tray quit all stop the daemon through this
+    let shutdown = Shutdown::new();
+    shutdown::bridge_tray_quit(quit_rx, shutdown.clone());
+    let grace = config::Config::load().unwrap_or_default().shutdown_grace();
+
+    // Spawn Discord bot in a separate thread with its own tokio runtime
+    let bot_shutdown = shutdown.clone();
+    let bot_handle = std::thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().expect("Failed to create tokio runtime");
+
+        rt.block_on(async {
+            tokio::spawn(shutdown::listen_for_signals(bot_shutdown.clone()));
+
+            // Send initial status
+            let _ = status_tx.send(tray::TrayCommand::UpdateStatus(discord::tray_connected_status().to_string()));
+
+            match shutdown::run_until(&bot_shutdown, discord::run_bot(bot_shutdown.clone()), grace).await {
+                Some(Err(e)) if bot_shutdown.reason().is_none() => {
+                    tracing::error!("Discord bot error: {}", e);
+                    let _ = status_tx.send(tray::TrayCommand::UpdateStatus("🔴 Disconnected".to_string()));
+                }
+                Some(_) => tracing::info!("Discord bot stopped"),
+                None => tracing::warn!("Discord bot didn't stop within {}s, exiting anyway", grace.as_secs()),
+            }
+        });
+
+        // The macOS tray exits the process as soon as it's told to quit, so clean up first
+        if bot_shutdown.reason().is_some() {
+            lifecycle::mark_clean_shutdown();
+            remove_pid_file();
+            let _ = status_tx.send(tray::TrayCommand::Quit);
+        }
+    });
+
+    // Run tray on main thread (required for macOS)
+    tray::run_tray(status_rx, quit_tx);
+
+    // Tray closed on its own: stop the bot and wait for its cleanup (bounded by the grace period)
+    shutdown.trigger(shutdown::Reason::TrayQuit);
+    let _ = bot_handle.join();
+    tracing::info!("Exiting...");
+    lifecycle::mark_clean_shutdown();
+    remove_pid_file();
+    std::process::exit(0);
+}
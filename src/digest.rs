@@ -0,0 +1,123 @@
+use chrono::{Local, NaiveDate, NaiveTime};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+use crate::audit;
+use crate::usage::UsageStore;
+
+/// One day of Neywa activity, aggregated from the audit log and usage counters
+#[derive(Debug, Default)]
+pub struct DaySummary {
+    pub date: NaiveDate,
+    /// Channel ID -> completed or attempted runs
+    pub runs_per_channel: BTreeMap<u64, u32>,
+    pub errors: u32,
+    pub cancelled: u32,
+    pub users: BTreeSet<String>,
+    pub commands: u32,
+    pub cost_usd: f64,
+}
+
+impl DaySummary {
+    pub fn total_runs(&self) -> u32 {
+        self.runs_per_channel.values().sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total_runs() == 0 && self.commands == 0
+    }
+
+    /// Plain-text description used for the embed and the AI digest prompt
+    pub fn describe(&self) -> String {
+        let mut lines = vec![format!(
+            "Requests: {} ({} errors, {} cancelled)",
+            self.total_runs(),
+            self.errors,
+            self.cancelled
+        )];
+        for (channel_id, runs) in &self.runs_per_channel {
+            lines.push(format!("- <#{}>: {}", channel_id, runs));
+        }
+        if self.commands > 0 {
+            lines.push(format!("Commands: {}", self.commands));
+        }
+        if !self.users.is_empty() {
+            lines.push(format!("Users: {}", self.users.iter().cloned().collect::<Vec<_>>().join(", ")));
+        }
+        if self.cost_usd > 0.0 {
+            lines.push(format!("Cost: ${:.2}", self.cost_usd));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Aggregate a day's audit entries and usage counters
+pub fn summarize(date: NaiveDate) -> DaySummary {
+    let mut summary = DaySummary { date, ..Default::default() };
+    for entry in audit::entries_on(date) {
+        match entry.kind.as_str() {
+            "run" => {
+                *summary.runs_per_channel.entry(entry.channel_id).or_default() += 1;
+                match entry.outcome.as_str() {
+                    "error" => summary.errors += 1,
+                    "cancelled" => summary.cancelled += 1,
+                    _ => {}
+                }
+                summary.users.insert(entry.user_name.clone());
+            }
+            "command" => summary.commands += 1,
+            _ => {}
+        }
+    }
+    summary.cost_usd = UsageStore::load().total_cost(date);
+    summary
+}
+
+/// Parse the configured digest time ("HH:MM")
+pub fn parse_time(value: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(value.trim(), "%H:%M").ok()
+}
+
+/// Whether the scheduled digest for `now`'s date is due
+pub fn is_due(at: NaiveTime, last_sent: Option<NaiveDate>, now: chrono::DateTime<Local>) -> bool {
+    now.time() >= at && last_sent.is_none_or(|last| last < now.date_naive())
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct DigestState {
+    last_date: Option<NaiveDate>,
+}
+
+/// Path for remembering the last digest date
+fn digest_state_file_path() -> PathBuf {
+    let config_dir = dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("neywa");
+    config_dir.join("digest.json")
+}
+
+/// Date of the last scheduled digest
+pub fn last_digest_date() -> Option<NaiveDate> {
+    std::fs::read_to_string(digest_state_file_path())
+        .ok()
+        .and_then(|content| serde_json::from_str::<DigestState>(&content).ok())
+        .and_then(|state| state.last_date)
+}
+
+/// Remember that the digest for `date` was handled
+pub fn save_last_digest_date(date: NaiveDate) {
+    let path = digest_state_file_path();
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    let state = DigestState { last_date: Some(date) };
+    match serde_json::to_string_pretty(&state) {
+        Ok(json) => {
+            if let Err(e) = std::fs::write(&path, json) {
+                tracing::warn!("Failed to save digest state: {}", e);
+            }
+        }
+        Err(e) => tracing::warn!("Failed to serialize digest state: {}", e),
+    }
+}
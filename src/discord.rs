@@ -1,25 +1,77 @@
-use crate::claude::{self, AiBackend, StreamEvent};
+use crate::activity;
+use crate::alias;
+use crate::attachment;
+use crate::children;
+use crate::claude::{self, AiBackend, RunUsage, StreamEvent};
 use crate::codex;
-use crate::config::Config;
+use crate::audit::{self, AuditEntry};
+use crate::config::{Config, HumanModeHint};
+use crate::cron;
+use crate::confirm::{self, Confirmations};
+use crate::cost::{self, CostStore};
+use crate::digest::{self, DaySummary};
 use crate::discord_api;
+use crate::disk;
+use crate::help;
+use crate::dryrun;
+use crate::lifecycle;
+use crate::links;
+use crate::loopguard::{self, LoopGuard, Verdict};
+use crate::metrics::{self, RunMetrics};
+use crate::onboarding;
+use crate::prompt;
+use crate::quiet;
+use crate::ratelimit::RateLimiter;
+use crate::redact::Redactor;
+use crate::schedule::{self, Job, ScheduleStore};
+use crate::shutdown::{self, Shutdown};
+use crate::stats;
+use crate::status_page;
+use crate::timing::{Phase, PhaseTimings};
+use crate::transcribe;
+use crate::transcript;
+use crate::tray;
+use crate::update;
+use crate::usage::{self, QuotaExceeded, UsageStore};
 use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDate};
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use serenity::async_trait;
-use serenity::builder::{CreateAttachment, CreateCommand, CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage, EditMessage};
-use serenity::model::application::Interaction;
-use serenity::model::channel::Message;
+use serenity::builder::{CreateActionRow, CreateAllowedMentions, CreateAttachment, CreateButton, CreateCommand, CreateEmbed, CreateEmbedFooter, CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage, CreateSelectMenu, CreateSelectMenuKind, CreateSelectMenuOption, CreateThread, EditMessage};
+use serenity::model::application::{ButtonStyle, ComponentInteraction, Interaction};
+use serenity::model::channel::{Attachment as DiscordAttachment, Message, MessageFlags};
+use serenity::model::event::MessageUpdateEvent;
+use serenity::model::id::{ChannelId, MessageId};
 use serenity::model::gateway::Ready;
 use serenity::prelude::*;
-use std::collections::{HashMap, VecDeque};
-use std::path::Path;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
 
 /// Current version from Cargo.toml
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// Channel name without the mode emoji Neywa prefixes (so toggling a mode
+/// never changes the detected ChannelType)
+fn base_channel_name(name: &str) -> &str {
+    let mut name = name;
+    loop {
+        let stripped = name
+            .trim_start_matches("🅾️")
+            .trim_start_matches("🙋‍♂️")
+            .trim_start_matches('-');
+        if stripped == name {
+            return name;
+        }
+        name = stripped;
+    }
+}
+
 /// Channel types based on name
 #[derive(Debug, Clone, PartialEq)]
 pub enum ChannelType {
@@ -33,7 +85,7 @@ pub enum ChannelType {
 
 impl ChannelType {
     fn from_name(name: &str) -> Self {
-        match name.to_lowercase().as_str() {
+        match base_channel_name(name).to_lowercase().as_str() {
             "general" | "일반" => ChannelType::General,
             "code" | "코드" | "coding" => ChannelType::Code,
             "research" | "리서치" | "검색" => ChannelType::Research,
@@ -60,10 +112,11 @@ impl ChannelType {
             }
             ChannelType::Tasks => {
                 "You are Neywa in TASKS mode. Help manage schedules and tasks. \
-                 When the user wants to schedule something recurring, create a cron job using: \
-                 crontab -l | { cat; echo \"SCHEDULE neywa run 'COMMAND'\"; } | crontab - \
-                 Replace SCHEDULE with cron syntax and COMMAND with what to do. \
-                 Confirm what you've scheduled."
+                 Do not edit the crontab. Neywa has its own scheduler, driven by chat commands \
+                 the user sends: `!remind [#channel] in 2h PROMPT` for a one-off, \
+                 `!remind [#channel] cron MIN HOUR DOM MON DOW PROMPT` for a recurring job, \
+                 `!schedule list` and `!schedule delete ID`. When the user wants something scheduled, \
+                 reply with the exact command for them to send, and explain when it will run."
             }
             ChannelType::Logs => {
                 "This is a logs channel. Do not respond to messages here."
@@ -75,39 +128,281 @@ impl ChannelType {
     }
 }
 
+/// Where a channel's system prompt comes from
+enum PromptSource {
+    /// A `channel_prompts` entry, with its key
+    Custom(String),
+    BuiltIn,
+}
+
+/// System prompt for a channel: its `channel_prompts` entry (by ID, then name)
+/// or the built-in prompt for its mode. Config is read per call, so edits to
+/// config.json apply to the next message.
+fn channel_system_prompt(config: &Config, channel_id: u64, name: &str, mode: &ChannelType) -> (String, PromptSource) {
+    match config.channel_prompt(channel_id, base_channel_name(name)) {
+        Some((key, prompt)) => (prompt.to_string(), PromptSource::Custom(key.to_string())),
+        None => (mode.get_system_prompt().to_string(), PromptSource::BuiltIn),
+    }
+}
+
+/// Structural kind of the Discord channel a message arrived in (from Discord, not the name)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelKind {
+    Text,
+    /// Announcement channel: responses are posted normally and never crossposted
+    Announcement,
+    /// Text chat of a voice channel
+    Voice,
+    Thread,
+    /// A post inside a forum channel
+    ForumThread,
+    /// A forum channel itself; conversations belong in its posts
+    Forum,
+    Dm,
+    /// Stage chats, categories, directories: ignored
+    Unsupported,
+}
+
+impl ChannelKind {
+    /// Classify from Discord's channel kind and, for threads, the parent's kind
+    fn classify(
+        kind: serenity::model::channel::ChannelType,
+        parent: Option<serenity::model::channel::ChannelType>,
+    ) -> Self {
+        use serenity::model::channel::ChannelType as Discord;
+        match kind {
+            Discord::Text => ChannelKind::Text,
+            Discord::News => ChannelKind::Announcement,
+            Discord::Voice => ChannelKind::Voice,
+            Discord::Private => ChannelKind::Dm,
+            Discord::Forum => ChannelKind::Forum,
+            Discord::PublicThread | Discord::PrivateThread | Discord::NewsThread => {
+                if parent == Some(Discord::Forum) {
+                    ChannelKind::ForumThread
+                } else {
+                    ChannelKind::Thread
+                }
+            }
+            _ => ChannelKind::Unsupported,
+        }
+    }
+
+    fn is_thread(&self) -> bool {
+        matches!(self, ChannelKind::Thread | ChannelKind::ForumThread)
+    }
+
+    /// `!threads` can branch requests off messages here
+    fn can_open_threads(&self) -> bool {
+        matches!(self, ChannelKind::Text | ChannelKind::Announcement)
+    }
+
+    /// What to do with a message posted directly in this kind of channel
+    fn route(&self) -> MessageRoute {
+        match self {
+            ChannelKind::Forum => MessageRoute::ForumNotice,
+            ChannelKind::Unsupported => MessageRoute::Ignore,
+            _ => MessageRoute::Handle,
+        }
+    }
+}
+
+/// How the handler treats a message, by channel kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MessageRoute {
+    Handle,
+    /// Forum parents get a one-time "post inside a thread" notice instead
+    ForumNotice,
+    Ignore,
+}
+
+/// A channel's mode (from its name, or its parent's for threads) and its structural kind
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelInfo {
+    pub mode: ChannelType,
+    pub kind: ChannelKind,
+    /// The name the mode came from (empty for DMs)
+    pub name: String,
+    /// None for DMs and failed lookups
+    pub guild_id: Option<u64>,
+}
+
+impl ChannelInfo {
+    /// Info for a guild channel; a thread takes its mode from `parent`
+    fn of(channel: &serenity::model::channel::GuildChannel, parent: Option<&serenity::model::channel::GuildChannel>) -> Self {
+        let kind = ChannelKind::classify(channel.kind, parent.map(|p| p.kind));
+        let name = match parent {
+            Some(parent) if kind.is_thread() => &parent.name,
+            _ => &channel.name,
+        };
+        ChannelInfo { mode: ChannelType::from_name(name), kind, name: name.clone(), guild_id: Some(channel.guild_id.get()) }
+    }
+}
+
+/// Context window assumed for fill estimates (tokens)
+const CONTEXT_WINDOW_TOKENS: u64 = 200_000;
+
+/// Session JSONL bytes per context token, used when no usage data is recorded.
+/// The JSONL carries metadata and tool results, so it is much larger than the context.
+const SESSION_BYTES_PER_TOKEN: u64 = 12;
+
+/// Estimate how many tokens a Claude Code session occupies in context.
+/// Uses the most recent assistant usage (input + cache tokens); falls back to
+/// the file size divided by a calibration factor.
+fn estimate_context_tokens(jsonl: &str) -> u64 {
+    let latest_usage = jsonl.lines().rev().find_map(|line| {
+        let data = serde_json::from_str::<serde_json::Value>(line).ok()?;
+        if data.get("type").and_then(|v| v.as_str()) != Some("assistant") {
+            return None;
+        }
+        let usage = data.get("message")?.get("usage")?;
+        let field = |name: &str| usage.get(name).and_then(|v| v.as_u64()).unwrap_or(0);
+        Some(
+            field("input_tokens")
+                + field("cache_creation_input_tokens")
+                + field("cache_read_input_tokens")
+                + field("output_tokens"),
+        )
+    });
+    latest_usage.unwrap_or(jsonl.len() as u64 / SESSION_BYTES_PER_TOKEN)
+}
+
+/// Estimated context fill of a Claude Code session, in percent (None if the file is missing)
+fn context_fill_percent(session_id: &str) -> Option<u64> {
+    let path = transcript::find_claude_session_file(session_id)?;
+    let content = std::fs::read_to_string(path).ok()?;
+    let tokens = estimate_context_tokens(&content);
+    Some((tokens * 100 / CONTEXT_WINDOW_TOKENS).min(100))
+}
+
+/// Files read and searches made during a plan run
+#[derive(Debug, Default)]
+struct Exploration {
+    files: Vec<String>,
+    searches: usize,
+}
+
+/// Directories listed in the exploration summary before collapsing to "+N more"
+const EXPLORED_DIRS_SHOWN: usize = 4;
+
+impl Exploration {
+    fn record(&mut self, tool_name: &str, target: &str) {
+        match tool_name {
+            "Read" => {
+                if !self.files.iter().any(|f| f == target) {
+                    self.files.push(target.to_string());
+                }
+            }
+            _ => self.searches += 1,
+        }
+    }
+
+    /// Top-level directories (relative to the files' common root) in first-seen order
+    fn directories(&self) -> Vec<String> {
+        let split: Vec<Vec<&str>> = self
+            .files
+            .iter()
+            .map(|f| f.split('/').filter(|c| !c.is_empty()).collect())
+            .collect();
+        // Common directory prefix (never including a file name)
+        let mut common = split.iter().map(|c| c.len().saturating_sub(1)).min().unwrap_or(0);
+        for parts in &split {
+            common = common.min(
+                parts.iter().zip(&split[0]).take_while(|(a, b)| a == b).count(),
+            );
+        }
+
+        let mut dirs: Vec<String> = Vec::new();
+        for parts in &split {
+            let dir = if parts.len() > common + 1 {
+                format!("{}/", parts[common])
+            } else if common > 0 {
+                format!("{}/", parts[common - 1])
+            } else {
+                "./".to_string()
+            };
+            if !dirs.contains(&dir) {
+                dirs.push(dir);
+            }
+        }
+        dirs
+    }
+
+    /// "🔎 Explored: 14 files across src/, tests/ (3 searches)" (None if nothing was explored)
+    fn summary(&self) -> Option<String> {
+        if self.files.is_empty() && self.searches == 0 {
+            return None;
+        }
+        let mut line = "🔎 Explored:".to_string();
+        if !self.files.is_empty() {
+            let dirs = self.directories();
+            let mut shown = dirs.iter().take(EXPLORED_DIRS_SHOWN).cloned().collect::<Vec<_>>().join(", ");
+            if dirs.len() > EXPLORED_DIRS_SHOWN {
+                shown.push_str(&format!(" +{} more", dirs.len() - EXPLORED_DIRS_SHOWN));
+            }
+            let noun = if self.files.len() == 1 { "file" } else { "files" };
+            line.push_str(&format!(" {} {} across {}", self.files.len(), noun, shown));
+        }
+        if self.searches > 0 {
+            let noun = if self.searches == 1 { "search" } else { "searches" };
+            if self.files.is_empty() {
+                line.push_str(&format!(" {} {}", self.searches, noun));
+            } else {
+                line.push_str(&format!(" ({} {})", self.searches, noun));
+            }
+        }
+        Some(line)
+    }
+}
+
 /// Queued message for processing
 #[derive(Clone)]
 struct QueuedMessage {
     msg: Message,
     content: String,
-    attachment_paths: Vec<String>,
+    /// Discord attachments, downloaded when the message is processed
+    attachments: Vec<DiscordAttachment>,
     channel_type: ChannelType,
     is_plan_mode: bool,
+    /// `!urgent`: mention on completion even during quiet hours
+    urgent: bool,
+    /// From a user in `admin_user_ids`: queued ahead of everyone else's messages
+    priority: bool,
+    /// The "📬 Queued (#N in line)" reply, kept current as the queue drains
+    notice: Option<MessageId>,
+    /// When the message was queued, for the queue-wait timing
+    enqueued_at: Instant,
 }
 
 type SessionKey = (u64, u64);
 
+/// A stored session ID and when it was last used
+#[derive(Debug, Clone)]
+pub(crate) struct SessionEntry {
+    pub(crate) id: String,
+    /// None for entries saved before last-used times were tracked
+    pub(crate) last_used: Option<DateTime<Local>>,
+}
+
+impl SessionEntry {
+    fn new(id: String) -> Self {
+        Self { id, last_used: Some(Local::now()) }
+    }
+}
+
 struct SessionStorage;
 impl TypeMapKey for SessionStorage {
-    type Value = Arc<RwLock<HashMap<SessionKey, String>>>;
+    type Value = Arc<RwLock<HashMap<SessionKey, SessionEntry>>>;
 }
 
 /// Trim old messages from a Claude Code session JSONL file
+/// (looked up in `cwd`'s project folder first)
 /// Removes the oldest ~20% of conversation messages
 /// Returns true if trimming was successful
-fn trim_session_file(session_id: &str) -> bool {
-    let home = match dirs::home_dir() {
-        Some(h) => h,
-        None => return false,
-    };
-    let session_path = home
-        .join(".claude/projects/-")
-        .join(format!("{}.jsonl", session_id));
-
-    if !session_path.exists() {
-        tracing::warn!("Session file not found: {:?}", session_path);
+fn trim_session_file(session_id: &str, cwd: Option<&Path>) -> bool {
+    let Some(session_path) = transcript::find_claude_session_file_in(session_id, cwd) else {
+        tracing::warn!("Session file not found for {}", session_id);
         return false;
-    }
+    };
 
     let content = match std::fs::read_to_string(&session_path) {
         Ok(c) => c,
@@ -126,21 +421,7 @@ fn trim_session_file(session_id: &str) -> bool {
     }
 
     // Separate system/meta lines from conversation lines
-    let mut system_lines = Vec::new();
-    let mut conv_lines = Vec::new();
-
-    for line in &lines {
-        if let Ok(data) = serde_json::from_str::<serde_json::Value>(line) {
-            let msg_type = data.get("type").and_then(|v| v.as_str()).unwrap_or("");
-            if msg_type == "system" || msg_type == "queue-operation" {
-                system_lines.push(*line);
-            } else {
-                conv_lines.push(*line);
-            }
-        } else {
-            conv_lines.push(*line);
-        }
-    }
+    let (system_lines, conv_lines) = transcript::split_claude_lines(&content);
 
     // Keep the last 80% of conversation messages (remove oldest 20%)
     let keep_count = (conv_lines.len() as f64 * 0.8).ceil() as usize;
@@ -181,22 +462,37 @@ fn sessions_file_path() -> std::path::PathBuf {
     config_dir.join("sessions.json")
 }
 
+/// One sessions.json entry: [key1, key2, session_id, last_used] (older files omit last_used)
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredSession {
+    WithLastUsed(u64, u64, String, Option<DateTime<Local>>),
+    Legacy(u64, u64, String),
+}
+
 /// Load sessions from file
-fn load_sessions() -> HashMap<SessionKey, String> {
-    let path = sessions_file_path();
+pub(crate) fn load_sessions() -> HashMap<SessionKey, SessionEntry> {
+    load_sessions_from(&sessions_file_path())
+}
+
+fn load_sessions_from(path: &std::path::Path) -> HashMap<SessionKey, SessionEntry> {
     if !path.exists() {
         return HashMap::new();
     }
 
-    match std::fs::read_to_string(&path) {
+    match std::fs::read_to_string(path) {
         Ok(content) => {
-            // Parse as array of [key1, key2, value] arrays
-            let parsed: Result<Vec<(u64, u64, String)>, _> = serde_json::from_str(&content);
+            // Parse as array of [key1, key2, value, last_used] arrays
+            let parsed: Result<Vec<StoredSession>, _> = serde_json::from_str(&content);
             match parsed {
                 Ok(entries) => {
                     let mut map = HashMap::new();
-                    for (k1, k2, v) in entries {
-                        map.insert((k1, k2), v);
+                    for entry in entries {
+                        let (k1, k2, id, last_used) = match entry {
+                            StoredSession::WithLastUsed(k1, k2, id, last_used) => (k1, k2, id, last_used),
+                            StoredSession::Legacy(k1, k2, id) => (k1, k2, id, None),
+                        };
+                        map.insert((k1, k2), SessionEntry { id, last_used });
                     }
                     tracing::info!("Loaded {} sessions from file", map.len());
                     map
@@ -215,23 +511,25 @@ fn load_sessions() -> HashMap<SessionKey, String> {
 }
 
 /// Save sessions to file
-fn save_sessions(sessions: &HashMap<SessionKey, String>) {
-    let path = sessions_file_path();
+fn save_sessions(sessions: &HashMap<SessionKey, SessionEntry>) {
+    save_sessions_to(&sessions_file_path(), sessions)
+}
 
+fn save_sessions_to(path: &std::path::Path, sessions: &HashMap<SessionKey, SessionEntry>) {
     // Ensure directory exists
     if let Some(parent) = path.parent() {
         let _ = std::fs::create_dir_all(parent);
     }
 
-    // Convert to serializable format: array of [key1, key2, value]
-    let entries: Vec<(u64, u64, &String)> = sessions
+    // Convert to serializable format: array of [key1, key2, value, last_used]
+    let entries: Vec<(u64, u64, &String, Option<DateTime<Local>>)> = sessions
         .iter()
-        .map(|((k1, k2), v)| (*k1, *k2, v))
+        .map(|((k1, k2), v)| (*k1, *k2, &v.id, v.last_used))
         .collect();
 
     match serde_json::to_string_pretty(&entries) {
         Ok(json) => {
-            if let Err(e) = std::fs::write(&path, json) {
+            if let Err(e) = std::fs::write(path, json) {
                 tracing::warn!("Failed to save sessions: {}", e);
             }
         }
@@ -241,11 +539,52 @@ fn save_sessions(sessions: &HashMap<SessionKey, String>) {
     }
 }
 
+/// ChannelInfo per channel, pinned at first sight and refreshed by channel_update
+struct ChannelTypes;
+impl TypeMapKey for ChannelTypes {
+    type Value = Arc<RwLock<HashMap<u64, ChannelInfo>>>;
+}
+
+/// Forum channels that already got the "post inside a thread" notice
+struct ForumNotices;
+impl TypeMapKey for ForumNotices {
+    type Value = Arc<RwLock<HashSet<u64>>>;
+}
+
 struct LogsChannel;
 impl TypeMapKey for LogsChannel {
     type Value = Arc<RwLock<Option<serenity::model::id::ChannelId>>>;
 }
 
+/// Neywa's own user ID (set on ready)
+struct BotUserId;
+impl TypeMapKey for BotUserId {
+    type Value = Arc<RwLock<Option<u64>>>;
+}
+
+/// Watches for the "Message Content intent missing" symptom
+#[derive(Default)]
+struct IntentWatch {
+    /// Consecutive guild messages that arrived completely empty
+    empty_streak: u32,
+    /// Warning already sent this run
+    warned: bool,
+}
+
+struct IntentWatchStorage;
+impl TypeMapKey for IntentWatchStorage {
+    type Value = Arc<RwLock<IntentWatch>>;
+}
+
+/// Empty guild messages in a row before assuming content is being stripped
+const EMPTY_CONTENT_THRESHOLD: u32 = 3;
+
+/// Circuit breaker against bot/webhook feedback loops
+struct LoopGuardStorage;
+impl TypeMapKey for LoopGuardStorage {
+    type Value = Arc<RwLock<LoopGuard>>;
+}
+
 /// Per-channel AI backend selection
 struct ChannelBackends;
 impl TypeMapKey for ChannelBackends {
@@ -258,1574 +597,8359 @@ impl TypeMapKey for MessageQueue {
     type Value = Arc<RwLock<HashMap<u64, VecDeque<QueuedMessage>>>>;
 }
 
-/// Currently processing channels with cancellation tokens
-struct ProcessingChannels;
-impl TypeMapKey for ProcessingChannels {
-    type Value = Arc<RwLock<HashMap<u64, CancellationToken>>>;
+/// Add a message to a channel's queue and return its 1-based position.
+/// Priority messages go after any priority messages already waiting but ahead
+/// of everything else; the rest append.
+fn enqueue(channel_queue: &mut VecDeque<QueuedMessage>, queued: QueuedMessage) -> usize {
+    if !queued.priority {
+        channel_queue.push_back(queued);
+        return channel_queue.len();
+    }
+    let index = channel_queue.iter().take_while(|m| m.priority).count();
+    channel_queue.insert(index, queued);
+    index + 1
 }
 
-/// Channels in human-only mode (Neywa ignores messages)
-struct HumanModeChannels;
-impl TypeMapKey for HumanModeChannels {
-    type Value = Arc<RwLock<std::collections::HashSet<u64>>>;
+/// Take the next queued message, dropping the channel's entry once it's empty
+fn pop_queued(queues: &mut HashMap<u64, VecDeque<QueuedMessage>>, channel_id: u64) -> Option<QueuedMessage> {
+    let channel_queue = queues.get_mut(&channel_id)?;
+    let next = channel_queue.pop_front();
+    if channel_queue.is_empty() {
+        queues.remove(&channel_id);
+    }
+    next
 }
 
-/// Path for storing human mode channel list
-fn human_mode_file_path() -> std::path::PathBuf {
+/// Why `!queue remove` couldn't remove an entry
+#[derive(Debug, PartialEq, Eq)]
+enum QueueRemoveError {
+    /// No entry at that position (it may have started already)
+    NotFound,
+    /// Someone else's request; carries their name
+    NotYours(String),
+}
+
+/// Remove the entry at 1-based `position` from a channel's queue, if it's
+/// `user_id`'s own request or `admin` is set. Runs under the same lock the
+/// queue drains under, so positions match the (renumbered) queue notices.
+fn remove_queued(
+    queues: &mut HashMap<u64, VecDeque<QueuedMessage>>,
+    channel_id: u64,
+    position: usize,
+    user_id: u64,
+    admin: bool,
+) -> Result<QueuedMessage, QueueRemoveError> {
+    let channel_queue = queues.get_mut(&channel_id).ok_or(QueueRemoveError::NotFound)?;
+    let index = position.checked_sub(1).ok_or(QueueRemoveError::NotFound)?;
+    let entry = channel_queue.get(index).ok_or(QueueRemoveError::NotFound)?;
+    if !admin && entry.msg.author.id.get() != user_id {
+        return Err(QueueRemoveError::NotYours(entry.msg.author.name.clone()));
+    }
+    let removed = channel_queue.remove(index).ok_or(QueueRemoveError::NotFound)?;
+    if channel_queue.is_empty() {
+        queues.remove(&channel_id);
+    }
+    Ok(removed)
+}
+
+/// A request held back by quiet hours (`quiet_hours_hold`). Kept on disk so a
+/// restart doesn't lose it; the message itself is fetched again on release.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct HeldRequest {
+    channel_id: u64,
+    message_id: u64,
+    /// The prompt after alias expansion and mention stripping
+    content: String,
+    #[serde(default)]
+    plan: bool,
+}
+
+/// Requests waiting for quiet hours to end, oldest first
+struct HeldRequests;
+impl TypeMapKey for HeldRequests {
+    type Value = Arc<RwLock<Vec<HeldRequest>>>;
+}
+
+/// Path for storing held requests
+fn held_requests_file_path() -> PathBuf {
     let config_dir = dirs::config_dir()
-        .unwrap_or_else(|| std::path::PathBuf::from("."))
+        .unwrap_or_else(|| PathBuf::from("."))
         .join("neywa");
-    config_dir.join("human_mode.json")
+    config_dir.join("held_requests.json")
 }
 
-/// Load human mode channels from file
-fn load_human_mode() -> std::collections::HashSet<u64> {
-    let path = human_mode_file_path();
+/// Load held requests from file
+fn load_held_requests() -> Vec<HeldRequest> {
+    let path = held_requests_file_path();
     if !path.exists() {
-        return std::collections::HashSet::new();
+        return Vec::new();
     }
     match std::fs::read_to_string(&path) {
         Ok(content) => serde_json::from_str(&content).unwrap_or_default(),
-        Err(_) => std::collections::HashSet::new(),
+        Err(_) => Vec::new(),
     }
 }
 
-/// Save human mode channels to file
-fn save_human_mode(channels: &std::collections::HashSet<u64>) {
-    let path = human_mode_file_path();
+/// Save held requests to file
+fn save_held_requests(held: &[HeldRequest]) {
+    let path = held_requests_file_path();
     if let Some(parent) = path.parent() {
         let _ = std::fs::create_dir_all(parent);
     }
-    if let Ok(json) = serde_json::to_string(channels) {
+    if let Ok(json) = serde_json::to_string(held) {
         let _ = std::fs::write(&path, json);
     }
 }
 
-/// Path for storing channel backend selections
-fn channel_backends_file_path() -> std::path::PathBuf {
+/// Channels woken with `!wake`, and when the quiet window they override ends
+struct WokenChannels;
+impl TypeMapKey for WokenChannels {
+    type Value = Arc<RwLock<HashMap<u64, DateTime<chrono::Utc>>>>;
+}
+
+/// Path for storing woken channels
+fn woken_channels_file_path() -> PathBuf {
     let config_dir = dirs::config_dir()
-        .unwrap_or_else(|| std::path::PathBuf::from("."))
+        .unwrap_or_else(|| PathBuf::from("."))
         .join("neywa");
-    config_dir.join("channel_backends.json")
+    config_dir.join("woken_channels.json")
 }
 
-/// Load channel backends from file
-fn load_channel_backends() -> HashMap<u64, AiBackend> {
-    let path = channel_backends_file_path();
+/// Load woken channels from file, dropping expired entries
+fn load_woken_channels() -> HashMap<u64, DateTime<chrono::Utc>> {
+    let path = woken_channels_file_path();
     if !path.exists() {
         return HashMap::new();
     }
-    match std::fs::read_to_string(&path) {
+    let mut woken: HashMap<u64, DateTime<chrono::Utc>> = match std::fs::read_to_string(&path) {
         Ok(content) => serde_json::from_str(&content).unwrap_or_default(),
         Err(_) => HashMap::new(),
-    }
+    };
+    let now = chrono::Utc::now();
+    woken.retain(|_, until| *until > now);
+    woken
 }
 
-/// Save channel backends to file
-fn save_channel_backends(backends: &HashMap<u64, AiBackend>) {
-    let path = channel_backends_file_path();
+/// Save woken channels to file
+fn save_woken_channels(woken: &HashMap<u64, DateTime<chrono::Utc>>) {
+    let path = woken_channels_file_path();
     if let Some(parent) = path.parent() {
         let _ = std::fs::create_dir_all(parent);
     }
-    if let Ok(json) = serde_json::to_string(backends) {
+    if let Ok(json) = serde_json::to_string(woken) {
         let _ = std::fs::write(&path, json);
     }
 }
 
-/// Helper to get the current backend for a channel
-async fn get_channel_backend(ctx: &serenity::client::Context, channel_id: u64) -> AiBackend {
-    let data = ctx.data.read().await;
-    if let Some(backends) = data.get::<ChannelBackends>() {
-        backends
-            .read()
-            .await
-            .get(&channel_id)
-            .copied()
-            .unwrap_or(AiBackend::Claude)
-    } else {
-        AiBackend::Claude
-    }
+/// When the quiet window of a channel's guild ends, or None outside it
+async fn quiet_window_end(ctx: &serenity::client::Context, channel_id: u64) -> Option<DateTime<chrono::FixedOffset>> {
+    let config = Config::load().unwrap_or_default();
+    let guild_id = channel_info_for(ctx, ChannelId::new(channel_id)).await.guild_id;
+    quiet::current_window_end(config.quiet_hours_for(guild_id), config.quiet_hours_timezone.as_deref())
 }
 
-struct Handler;
+/// When quiet hours end for a channel, or None if they don't apply to it right
+/// now (outside the window, or the channel was woken with `!wake`)
+async fn quiet_until(ctx: &serenity::client::Context, channel_id: u64) -> Option<DateTime<chrono::FixedOffset>> {
+    let end = quiet_window_end(ctx, channel_id).await?;
+    let data = ctx.data.read().await;
+    let woken = match data.get::<WokenChannels>() {
+        Some(woken) => woken.read().await.get(&channel_id).is_some_and(|until| *until > chrono::Utc::now()),
+        None => false,
+    };
+    (!woken).then_some(end)
+}
 
-impl Handler {
-    async fn process_message(
-        ctx: &serenity::client::Context,
-        queued: QueuedMessage,
-        cancel_token: CancellationToken,
-    ) {
-        let msg = &queued.msg;
-        let content = &queued.content;
-        let attachment_paths = &queued.attachment_paths;
-        let channel_type = &queued.channel_type;
+/// "🌙 Quiet hours until 08:00 (2 requests held)" while quiet hours apply to a channel
+async fn quiet_status(ctx: &serenity::client::Context, channel_id: u64) -> Option<String> {
+    let until = quiet_until(ctx, channel_id).await?;
+    let data = ctx.data.read().await;
+    let held = match data.get::<HeldRequests>() {
+        Some(held) => held.read().await.iter().filter(|h| h.channel_id == channel_id).count(),
+        None => 0,
+    };
+    Some(match held {
+        0 => format!("🌙 Quiet hours until {}", until.format("%H:%M")),
+        1 => format!("🌙 Quiet hours until {} (1 request held)", until.format("%H:%M")),
+        n => format!("🌙 Quiet hours until {} ({} requests held)", until.format("%H:%M"), n),
+    })
+}
 
-        let user_id = msg.author.id.get();
-        let channel_id = msg.channel_id.get();
-        let session_key = (user_id, channel_id);
-        let user_mention = msg.author.mention().to_string();
+/// Hold a request until quiet hours end, if `quiet_hours_hold` says so.
+/// Returns true when the request was held (and the user told when it will start).
+async fn hold_for_quiet_hours(ctx: &serenity::client::Context, queued: &QueuedMessage) -> bool {
+    if queued.urgent || !Config::load().unwrap_or_default().quiet_hours_hold {
+        return false;
+    }
+    let channel_id = queued.msg.channel_id.get();
+    let Some(until) = quiet_until(ctx, channel_id).await else {
+        return false;
+    };
+    let waiting = {
+        let data = ctx.data.read().await;
+        let Some(held) = data.get::<HeldRequests>() else {
+            return false;
+        };
+        let mut held = held.write().await;
+        held.push(HeldRequest {
+            channel_id,
+            message_id: queued.msg.id.get(),
+            content: queued.content.clone(),
+            plan: queued.is_plan_mode,
+        });
+        save_held_requests(&held);
+        held.iter().filter(|h| h.channel_id == channel_id).count()
+    };
+    let ahead = if waiting > 1 { format!(" ({} waiting here)", waiting) } else { String::new() };
+    let _ = queued
+        .msg
+        .reply(
+            &ctx.http,
+            format!(
+                "🌙 Quiet hours: queued{}, processing will start at {}. `!wake` starts this channel now.",
+                ahead,
+                until.format("%H:%M")
+            ),
+        )
+        .await;
+    true
+}
 
-        // Get existing session
-        let existing_session = {
-            let data = ctx.data.read().await;
-            if let Some(sessions) = data.get::<SessionStorage>() {
-                sessions.read().await.get(&session_key).cloned()
-            } else {
-                None
-            }
+/// Submit the held requests whose quiet hours are over (or only `channel`'s,
+/// after `!wake`), oldest first. Returns how many were released.
+async fn release_held_requests(ctx: &serenity::client::Context, channel: Option<u64>) -> usize {
+    let candidates: HashSet<u64> = {
+        let data = ctx.data.read().await;
+        match data.get::<HeldRequests>() {
+            Some(held) => held.read().await.iter().map(|h| h.channel_id).collect(),
+            None => return 0,
+        }
+    };
+    let hold = Config::load().unwrap_or_default().quiet_hours_hold;
+    let mut ready = HashSet::new();
+    for channel_id in candidates {
+        if channel.is_none_or(|c| c == channel_id) && (!hold || quiet_until(ctx, channel_id).await.is_none()) {
+            ready.insert(channel_id);
+        }
+    }
+    if ready.is_empty() {
+        return 0;
+    }
+    let released: Vec<HeldRequest> = {
+        let data = ctx.data.read().await;
+        let Some(held) = data.get::<HeldRequests>() else {
+            return 0;
         };
+        let mut held = held.write().await;
+        let (released, kept) = held.drain(..).partition(|h| ready.contains(&h.channel_id));
+        *held = kept;
+        save_held_requests(&held);
+        released
+    };
 
-        // Send initial "processing" message
-        let status_msg = match msg.channel_id.say(&ctx.http, "⏳ Processing...").await {
-            Ok(m) => m,
+    let config = Config::load().unwrap_or_default();
+    let mut count = 0;
+    for request in released {
+        let channel_id = ChannelId::new(request.channel_id);
+        let msg = match channel_id.message(&ctx.http, MessageId::new(request.message_id)).await {
+            Ok(msg) => msg,
             Err(e) => {
-                tracing::error!("Failed to send processing message: {}", e);
-                return;
+                tracing::warn!("Dropping held request {} in {}: {}", request.message_id, request.channel_id, e);
+                continue;
             }
         };
-
-        // Build prompt with system context, username, and attachments
-        let system_prompt = channel_type.get_system_prompt();
-        let username = &msg.author.name;
-        let attachment_info = if attachment_paths.is_empty() {
-            String::new()
-        } else {
-            format!("\n\n[Attached files: {}]", attachment_paths.join(", "))
+        let queued = QueuedMessage {
+            attachments: msg.attachments.clone(),
+            channel_type: channel_info_for(ctx, channel_id).await.mode,
+            is_plan_mode: request.plan,
+            urgent: false,
+            priority: is_admin_tier(&config, msg.author.id.get()),
+            notice: None,
+            enqueued_at: Instant::now(),
+            content: request.content,
+            msg,
         };
+        Handler::submit(ctx, queued).await;
+        count += 1;
+    }
+    count
+}
 
-        let user_content = if content.is_empty() {
-            "Analyze this file".to_string()
-        } else {
-            content.to_string()
-        };
+static QUIET_RELEASE_STARTED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
 
-        let full_prompt = if existing_session.is_some() {
-            format!("[{}]: {}{}", username, user_content, attachment_info)
-        } else {
-            format!(
-                "[System: {} Multiple users may participate. Each message is prefixed with [username]. Distinguish users by name in your responses.]\n\n[{}]: {}{}",
-                system_prompt, username, user_content, attachment_info
-            )
-        };
+/// Start held requests once their channel's quiet hours are over. Held requests
+/// are loaded from disk at startup, so this picks up where a restart left off.
+async fn run_quiet_release(ctx: serenity::client::Context) {
+    loop {
+        let released = release_held_requests(&ctx, None).await;
+        if released > 0 {
+            tracing::info!("Quiet hours over: released {} held request(s)", released);
+        }
+        tokio::time::sleep(Duration::from_secs(30)).await;
+    }
+}
 
-        // Get the AI backend for this channel
-        let backend = get_channel_backend(ctx, channel_id).await;
+/// Currently processing channels with cancellation tokens
+struct ProcessingChannels;
+impl TypeMapKey for ProcessingChannels {
+    type Value = Arc<RwLock<HashMap<u64, ActiveRun>>>;
+}
 
-        // Run AI backend with streaming (plan mode or normal)
-        let mut rx = if queued.is_plan_mode {
-            let use_z = backend == AiBackend::ClaudeZ;
-            match claude::run_streaming_plan(&full_prompt, use_z).await {
-                Ok(rx) => rx,
-                Err(e) => {
-                    let _ = msg.channel_id.say(&ctx.http, format!("❌ Error: {}", e)).await;
-                    let _ = status_msg.delete(&ctx.http).await;
-                    return;
-                }
-            }
-        } else {
-            match backend {
-                AiBackend::Codex => {
-                    match codex::run_streaming(&full_prompt, existing_session.as_deref()).await {
-                        Ok(rx) => rx,
-                        Err(e) => {
-                            let _ = msg.channel_id.say(&ctx.http, format!("❌ Error: {}", e)).await;
-                            let _ = status_msg.delete(&ctx.http).await;
-                            return;
-                        }
-                    }
-                }
-                _ => {
-                    let use_z = backend == AiBackend::ClaudeZ;
-                    match claude::run_streaming(&full_prompt, existing_session.as_deref(), use_z).await {
-                        Ok(rx) => rx,
-                        Err(e) => {
-                            let _ = msg.channel_id.say(&ctx.http, format!("❌ Error: {}", e)).await;
-                            let _ = status_msg.delete(&ctx.http).await;
-                            return;
-                        }
-                    }
-                }
-            }
-        };
-
-        // Process stream events with cancellation support
-        let mut final_text = String::new();
-        let mut new_session_id: Option<String> = None;
-        let mut plan_content: Option<String> = None;
-        let mut status_lines: Vec<String> = vec!["⏳ Processing...".to_string()];
-        let mut last_update = Instant::now();
-        let update_interval = Duration::from_millis(800);
-        let mut was_cancelled = false;
-
-        loop {
-            tokio::select! {
-                _ = cancel_token.cancelled() => {
-                    was_cancelled = true;
-                    tracing::info!("Processing cancelled for channel {}", channel_id);
-                    break;
-                }
-                event = rx.recv() => {
-                    match event {
-                        Some(StreamEvent::ToolUse(tool_name, detail)) => {
-                            let status = if detail.is_empty() {
-                                format!("🔧 {}", tool_name)
-                            } else {
-                                detail
-                            };
-                            status_lines.push(status);
-                            if status_lines.len() > 5 {
-                                status_lines.remove(0);
-                            }
-                            if last_update.elapsed() >= update_interval {
-                                let status_text = status_lines.join("\n");
-                                let _ = edit_message(ctx, &status_msg, &status_text).await;
-                                last_update = Instant::now();
-                            }
-                        }
-                        Some(StreamEvent::Text(text)) => {
-                            final_text = text;
-                        }
-                        Some(StreamEvent::PlanContent(_path, content)) => {
-                            // Keep the longest plan content (may get multiple events)
-                            if plan_content.as_ref().map_or(true, |existing| content.len() > existing.len()) {
-                                plan_content = Some(content);
-                            }
-                        }
-                        Some(StreamEvent::SessionId(sid)) => {
-                            new_session_id = Some(sid);
-                        }
-                        Some(StreamEvent::Done) | None => {
-                            break;
-                        }
-                        Some(StreamEvent::Error(e)) => {
-                            let _ = msg.channel_id.say(&ctx.http, format!("❌ Error: {}", e)).await;
-                            let _ = status_msg.delete(&ctx.http).await;
-                            return;
-                        }
-                    }
-                }
+/// The run currently in progress in a channel
+#[derive(Clone)]
+struct ActiveRun {
+    cancel_token: CancellationToken,
+    author_id: u64,
+    author_name: String,
+    /// First ~60 characters of the prompt
+    preview: String,
+    started: Instant,
+    /// The "⏳ Processing..." message, where the Stop button or a 🛑 reaction cancels this run
+    status_message: Option<MessageId>,
+    /// Cancelled by `!steer`: the corrected request is next in the queue
+    steered: bool,
+}
+
+/// Global cap on CLI runs in progress across channels (`max_concurrent_tasks`)
+struct TaskSlots;
+impl TypeMapKey for TaskSlots {
+    type Value = Arc<SlotPool>;
+}
+
+struct SlotPool {
+    semaphore: Arc<tokio::sync::Semaphore>,
+    /// 0 = no limit
+    limit: usize,
+}
+
+impl SlotPool {
+    fn new(limit: usize) -> Self {
+        let permits = if limit == 0 { tokio::sync::Semaphore::MAX_PERMITS } else { limit };
+        Self { semaphore: Arc::new(tokio::sync::Semaphore::new(permits)), limit }
+    }
+
+    fn in_use(&self) -> usize {
+        self.limit.saturating_sub(self.semaphore.available_permits())
+    }
+
+    /// Take a slot, waiting for one to free up. None if `cancel` fires first.
+    async fn acquire(&self, cancel: &CancellationToken) -> Option<tokio::sync::OwnedSemaphorePermit> {
+        tokio::select! {
+            permit = self.semaphore.clone().acquire_owned() => permit.ok(),
+            _ = cancel.cancelled() => None,
+        }
+    }
+}
+
+async fn task_slots(ctx: &serenity::client::Context) -> Option<Arc<SlotPool>> {
+    let data = ctx.data.read().await;
+    data.get::<TaskSlots>().cloned()
+}
+
+/// custom_id of the Stop button on a run's status message
+const STOP_BUTTON_ID: &str = "neywa_stop";
+
+/// How often the typing indicator is renewed (Discord shows it for ~10 seconds)
+const TYPING_INTERVAL: Duration = Duration::from_secs(8);
+
+/// Show "Neywa is typing…" in a channel until `stop` is cancelled
+fn spawn_typing(http: Arc<serenity::http::Http>, channel: ChannelId, stop: CancellationToken) {
+    tokio::spawn(async move {
+        loop {
+            let _ = channel.broadcast_typing(&http).await;
+            tokio::select! {
+                _ = stop.cancelled() => break,
+                _ = tokio::time::sleep(TYPING_INTERVAL) => {}
             }
         }
+    });
+}
 
-        // Delete status message
-        let _ = status_msg.delete(&ctx.http).await;
-
-        if was_cancelled {
-            let _ = msg.channel_id.say(&ctx.http, "🛑 Cancelled.").await;
-            return;
+impl ActiveRun {
+    fn new(cancel_token: CancellationToken, queued: &QueuedMessage) -> Self {
+        let preview = claude::truncate_str(queued.content.lines().next().unwrap_or(""), 60);
+        Self {
+            cancel_token,
+            author_id: queued.msg.author.id.get(),
+            author_name: queued.msg.author.name.clone(),
+            preview,
+            started: Instant::now(),
+            status_message: None,
+            steered: false,
         }
+    }
 
-        // Handle plan mode response separately
-        if queued.is_plan_mode {
-            // Use plan_content if text response is empty (common due to ExitPlanMode denial)
-            let response_text = if !final_text.is_empty() && !final_text.trim().is_empty() {
-                // If we have both, prefer the longer/more complete one
-                if let Some(ref plan) = plan_content {
-                    if plan.len() > final_text.len() { plan.clone() } else { final_text.clone() }
-                } else {
-                    final_text.clone()
-                }
-            } else {
-                plan_content.unwrap_or_else(|| "(No plan generated)".to_string())
-            };
-
-            let full_response = format!("📐 **Plan**\n\n{}", response_text);
-            let chunks = split_for_discord(&full_response);
-            for chunk in chunks {
-                let _ = msg.channel_id.say(&ctx.http, &chunk).await;
-            }
+    /// The requester can cancel their own run, anyone else needs admin
+    fn may_cancel(&self, user_id: u64, tier: Tier) -> bool {
+        may_act_for(self.author_id, user_id, Some(tier))
+    }
 
-            let _ = msg.channel_id.say(&ctx.http, format!("{} ✅ Plan ready!", user_mention)).await;
-            log_activity(ctx, &msg.author.name, channel_type, content, &response_text).await;
-            return;
+    /// "🔄 Processing Alice's request: 'refactor the auth…' (3m elapsed)"
+    fn describe(&self) -> String {
+        let elapsed = self.started.elapsed().as_secs();
+        let elapsed = if elapsed < 60 {
+            format!("{}s", elapsed)
+        } else {
+            format_idle(chrono::Duration::seconds(elapsed as i64))
+        };
+        if self.preview.is_empty() {
+            format!("🔄 Processing {}'s request ({} elapsed)", self.author_name, elapsed)
+        } else {
+            format!("🔄 Processing {}'s request: '{}' ({} elapsed)", self.author_name, self.preview, elapsed)
         }
+    }
+}
 
-        // Save session ID (memory + file)
-        if let Some(ref sid) = new_session_id {
-            let data = ctx.data.read().await;
-            if let Some(sessions) = data.get::<SessionStorage>() {
-                let mut sessions_map = sessions.write().await;
-                sessions_map.insert(session_key, sid.clone());
-                // Persist to file
-                save_sessions(&sessions_map);
-            }
-        }
+/// Messages held open for the coalescing window before their run starts
+struct PendingBatches;
+impl TypeMapKey for PendingBatches {
+    type Value = Arc<RwLock<HashMap<u64, QueuedMessage>>>;
+}
 
-        // Send final response
-        if final_text.is_empty() {
-            final_text = "(No response)".to_string();
-        }
+/// Message currently being processed per channel, with its start time
+struct ActiveMessages;
+impl TypeMapKey for ActiveMessages {
+    type Value = Arc<RwLock<HashMap<u64, (QueuedMessage, Instant)>>>;
+}
 
-        // Auto-compact session if prompt is too long (context window exceeded)
-        let lower_text = final_text.to_lowercase();
-        if lower_text.contains("prompt is too long") || lower_text.contains("context window") || lower_text.contains("too many tokens") {
-            let session_to_compact = new_session_id.as_deref().or(existing_session.as_deref());
-            if let Some(sid) = session_to_compact {
-                // Codex mode: can't compact, reset session
-                if backend == AiBackend::Codex {
-                    let data = ctx.data.read().await;
-                    if let Some(sessions) = data.get::<SessionStorage>() {
-                        let mut sessions_map = sessions.write().await;
-                        sessions_map.remove(&session_key);
-                        save_sessions(&sessions_map);
-                    }
-                    let _ = msg.channel_id.say(&ctx.http, "⚠️ Context window exceeded. 새 세션으로 시작합니다. 메시지를 다시 보내주세요.").await;
-                    return;
-                }
+/// Identical error reports are posted once per this window
+const ERROR_REPORT_WINDOW: Duration = Duration::from_secs(10 * 60);
 
-                let use_z = backend == AiBackend::ClaudeZ;
-                let _ = msg.channel_id.say(&ctx.http, "⚠️ Context window full. Compacting session...").await;
+/// Longest prompt excerpt in an error report
+const ERROR_REPORT_PROMPT_CHARS: usize = 300;
 
-                // Run /compact on the session
-                match claude::compact_session(sid, use_z).await {
-                    Ok(_) => {
-                        let _ = msg.channel_id.say(&ctx.http, "✅ Session compacted. Retrying your message...").await;
+/// Recent error reports: error text -> (last posted, repeats suppressed since). Memory only.
+struct ErrorReports;
+impl TypeMapKey for ErrorReports {
+    type Value = Arc<RwLock<HashMap<String, (Instant, u32)>>>;
+}
 
-                        // Retry the original message with the compacted session
-                        match claude::run_streaming(&full_prompt, Some(sid), use_z).await {
-                            Ok(mut retry_rx) => {
-                                let mut retry_text = String::new();
-                                while let Some(event) = retry_rx.recv().await {
-                                    match event {
-                                        StreamEvent::Text(t) => retry_text.push_str(&t),
-                                        StreamEvent::Done => break,
-                                        _ => {}
-                                    }
-                                }
-                                if !retry_text.is_empty() {
-                                    final_text = retry_text;
-                                    // Fall through to normal response handling below
-                                } else {
-                                    let _ = msg.channel_id.say(&ctx.http, "⚠️ Compact succeeded but retry got empty response. Please send your message again.").await;
-                                    return;
-                                }
-                            }
-                            Err(e) => {
-                                let _ = msg.channel_id.say(&ctx.http, format!("⚠️ Compact succeeded but retry failed: {}. Please send your message again.", e)).await;
-                                return;
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        // Compact failed, try trimming as fallback
-                        tracing::warn!("Compact failed: {}, trying trim fallback", e);
-                        let trimmed = trim_session_file(sid);
-                        if trimmed {
-                            let _ = msg.channel_id.say(&ctx.http, "⚠️ Compact failed. Trimmed old messages instead. Please send your message again.").await;
-                        } else {
-                            let data = ctx.data.read().await;
-                            if let Some(sessions) = data.get::<SessionStorage>() {
-                                let mut sessions_map = sessions.write().await;
-                                sessions_map.remove(&session_key);
-                                save_sessions(&sessions_map);
-                            }
-                            let _ = msg.channel_id.say(&ctx.http, "⚠️ Context window exceeded. Session has been reset. Please send your message again.").await;
-                        }
-                        return;
-                    }
-                }
-            } else {
-                let _ = msg.channel_id.say(&ctx.http, "⚠️ Context window exceeded. Please start a new session with !new.").await;
+/// Post a failed run to `errors_channel_id` (or #logs) with its channel, user,
+/// backend, prompt and error. An error identical to one posted within
+/// `ERROR_REPORT_WINDOW` is only counted, and the count rides along with the
+/// next post, so a crash loop doesn't flood the channel.
+async fn report_run_error(ctx: &serenity::client::Context, queued: &QueuedMessage, backend: AiBackend, error: &str) {
+    let repeats = {
+        let data = ctx.data.read().await;
+        let Some(reports) = data.get::<ErrorReports>() else {
+            return;
+        };
+        let mut reports = reports.write().await;
+        reports.retain(|_, (posted, _)| posted.elapsed() < Duration::from_secs(24 * 3600));
+        match reports.get_mut(error) {
+            Some((posted, suppressed)) if posted.elapsed() < ERROR_REPORT_WINDOW => {
+                *suppressed += 1;
                 return;
             }
-        }
-
-        // Detect file paths in response and send as attachments
-        let file_paths = extract_file_paths(&final_text);
-        tracing::info!("Detected file paths: {:?}", file_paths);
-        let mut sent_files: Vec<String> = Vec::new();
-
-        for path in &file_paths {
-            tracing::info!("Checking path: {} exists={}", path, Path::new(path).exists());
-            if Path::new(path).exists() {
-                if let Ok(attachment) = CreateAttachment::path(path).await {
-                    let builder = CreateMessage::new().add_file(attachment);
-                    if msg.channel_id.send_message(&ctx.http, builder).await.is_ok() {
-                        sent_files.push(path.clone());
-                        tracing::info!("Sent file: {}", path);
-                    }
-                }
+            Some(entry) => std::mem::replace(entry, (Instant::now(), 0)).1,
+            None => {
+                reports.insert(error.to_string(), (Instant::now(), 0));
+                0
             }
         }
+    };
 
-        // Send text response
-        let chunks = split_for_discord(&final_text);
-        for chunk in chunks {
-            let _ = msg.channel_id.say(&ctx.http, &chunk).await;
-        }
+    let config = Config::load().unwrap_or_default();
+    let redactor = Redactor::from_config(&config);
+    let prompt = claude::truncate_str(queued.content.trim(), ERROR_REPORT_PROMPT_CHARS);
+    let mut report = format!(
+        "🚨 **Run failed** in <#{}> · {} (`{}`) · {}\n**Prompt:** {}\n```\n{}\n```",
+        queued.msg.channel_id,
+        queued.msg.author.name,
+        queued.msg.author.id,
+        backend.cli_name(),
+        redactor.redact(&prompt),
+        // A fence inside the error would end the code block early
+        redactor.redact(error).replace("```", "`\u{200b}``"),
+    );
+    if repeats > 0 {
+        report.push_str(&format!("\n(the same error also occurred {} more time(s) since it was last reported)", repeats));
+    }
+    let posted = match config.errors_channel_id {
+        Some(id) => match ChannelId::new(id).say(&ctx.http, &report).await {
+            Ok(_) => true,
+            Err(e) => {
+                tracing::warn!("Failed to post to errors channel {}: {}", id, e);
+                post_to_logs(ctx, &report).await
+            }
+        },
+        None => post_to_logs(ctx, &report).await,
+    };
+    if !posted {
+        tracing::debug!("No errors or logs channel to report the failed run to");
+    }
+}
 
-        // Send completion notification
-        let completion_msg = if sent_files.is_empty() {
-            format!("{} ✅ Done!", user_mention)
-        } else {
-            format!("{} ✅ Done! ({} file(s) attached)", user_mention, sent_files.len())
-        };
-        let _ = msg.channel_id.say(&ctx.http, completion_msg).await;
+/// Last finished request per channel, whatever its outcome (`!retry`). Memory only.
+struct LastRequests;
+impl TypeMapKey for LastRequests {
+    type Value = Arc<RwLock<HashMap<u64, QueuedMessage>>>;
+}
 
-        // Log activity
-        log_activity(ctx, &msg.author.name, channel_type, content, &final_text).await;
-    }
+/// Recent run durations per channel (queue wait estimates)
+struct MetricsStorage;
+impl TypeMapKey for MetricsStorage {
+    type Value = Arc<RwLock<RunMetrics>>;
+}
 
-    async fn process_queue(ctx: serenity::client::Context, channel_id: u64) {
-        loop {
-            // Get next message from queue
-            let next_msg = {
-                let data = ctx.data.read().await;
-                if let Some(queue) = data.get::<MessageQueue>() {
-                    queue.write().await.get_mut(&channel_id).and_then(|q| q.pop_front())
-                } else {
-                    None
-                }
-            };
+/// Phase timings of each channel's last run: (timings, total, outcome), for `!timing`
+struct LastRunTimings;
+impl TypeMapKey for LastRunTimings {
+    type Value = Arc<RwLock<HashMap<u64, (PhaseTimings, Duration, &'static str)>>>;
+}
 
-            match next_msg {
-                Some(queued) => {
-                    // Create new cancellation token for this message
-                    let cancel_token = CancellationToken::new();
+/// Claude spend per channel and session, for `!cost`
+struct CostStorage;
+impl TypeMapKey for CostStorage {
+    type Value = Arc<RwLock<CostStore>>;
+}
 
-                    // Store the token
-                    {
-                        let data = ctx.data.read().await;
-                        if let Some(processing) = data.get::<ProcessingChannels>() {
-                            processing.write().await.insert(channel_id, cancel_token.clone());
-                        }
-                    }
+/// Scheduled prompts (`!remind`, `!schedule`)
+struct ScheduleStorage;
+impl TypeMapKey for ScheduleStorage {
+    type Value = Arc<RwLock<ScheduleStore>>;
+}
 
-                    // Process the message
-                    Self::process_message(&ctx, queued, cancel_token).await;
+/// Per-user sliding-window message counts (`max_messages_per_user_per_hour`)
+struct RateLimitStorage;
+impl TypeMapKey for RateLimitStorage {
+    type Value = Arc<RwLock<RateLimiter>>;
+}
 
-                    // Remove from processing
-                    {
-                        let data = ctx.data.read().await;
-                        if let Some(processing) = data.get::<ProcessingChannels>() {
-                            processing.write().await.remove(&channel_id);
-                        }
-                    }
-                }
-                None => {
-                    // Queue is empty, exit the loop
-                    break;
-                }
-            }
-        }
-    }
+/// Per-user daily usage counters
+struct UsageStorage;
+impl TypeMapKey for UsageStorage {
+    type Value = Arc<RwLock<UsageStore>>;
 }
 
-#[async_trait]
-impl EventHandler for Handler {
-    async fn message(&self, ctx: serenity::client::Context, msg: Message) {
-        if msg.author.bot {
-            return;
-        }
+/// Channels in human-only mode (Neywa ignores messages)
+struct HumanModeChannels;
+impl TypeMapKey for HumanModeChannels {
+    type Value = Arc<RwLock<std::collections::HashSet<u64>>>;
+}
 
-        let channel_type = if let Some(channel) = msg.channel_id.to_channel(&ctx.http).await.ok() {
-            if let Some(guild_channel) = channel.guild() {
-                ChannelType::from_name(&guild_channel.name)
-            } else {
-                ChannelType::General
-            }
-        } else {
-            ChannelType::General
-        };
+/// Path for storing human mode channel list
+fn human_mode_file_path() -> std::path::PathBuf {
+    let config_dir = dirs::config_dir()
+        .unwrap_or_else(|| std::path::PathBuf::from("."))
+        .join("neywa");
+    config_dir.join("human_mode.json")
+}
 
-        if channel_type == ChannelType::Logs {
-            return;
-        }
+/// Load human mode channels from file
+fn load_human_mode() -> std::collections::HashSet<u64> {
+    let path = human_mode_file_path();
+    if !path.exists() {
+        return std::collections::HashSet::new();
+    }
+    match std::fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_default(),
+        Err(_) => std::collections::HashSet::new(),
+    }
+}
 
-        let content = msg.content.trim().to_string();
-        let channel_id = msg.channel_id.get();
+/// Users already hinted today, per (channel, user). Memory only.
+struct HumanModeHints;
+impl TypeMapKey for HumanModeHints {
+    type Value = Arc<RwLock<HashMap<(u64, u64), NaiveDate>>>;
+}
 
-        // Allow !human command even in human mode (to toggle it off)
-        // But block all other messages if human mode is active
-        if content != "!human" && content != "!인간" {
-            let is_human_mode = {
-                let data = ctx.data.read().await;
-                if let Some(human_channels) = data.get::<HumanModeChannels>() {
-                    human_channels.read().await.contains(&channel_id)
-                } else {
-                    false
-                }
-            };
-            if is_human_mode {
-                return;
+/// Record a hint for (channel, user) today; false if they already got one
+fn first_hint_today(hints: &mut HashMap<(u64, u64), NaiveDate>, channel_id: u64, user_id: u64, today: NaiveDate) -> bool {
+    // Yesterday's entries are never needed again
+    hints.retain(|_, day| *day == today);
+    hints.insert((channel_id, user_id), today) != Some(today)
+}
+
+/// Whether a message ignored by human mode should get the 🙋 reaction
+async fn wants_human_mode_hint(ctx: &serenity::client::Context, channel_id: u64, user_id: u64) -> bool {
+    match Config::load().unwrap_or_default().human_mode_hint {
+        HumanModeHint::Off => false,
+        HumanModeHint::OncePerUserMessage => true,
+        HumanModeHint::Reaction => {
+            let data = ctx.data.read().await;
+            match data.get::<HumanModeHints>() {
+                Some(hints) => first_hint_today(&mut *hints.write().await, channel_id, user_id, Local::now().date_naive()),
+                None => false,
             }
         }
-        let user_id = msg.author.id.get();
-        let session_key = (user_id, channel_id);
+    }
+}
 
-        // Download attachments if any
-        let mut attachment_paths: Vec<String> = Vec::new();
-        for attachment in &msg.attachments {
-            if let Ok(path) = download_attachment(&attachment.url, &attachment.filename).await {
-                attachment_paths.push(path);
-            }
+/// Save human mode channels to file
+fn save_human_mode(channels: &std::collections::HashSet<u64>) {
+    let path = human_mode_file_path();
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    if let Ok(json) = serde_json::to_string(channels) {
+        let _ = std::fs::write(&path, json);
+    }
+}
+
+/// Per-channel policy for discarding idle sessions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum AutoReset {
+    /// Start fresh once the session has been idle this many hours
+    Hours(u64),
+    /// Start fresh on the first message after local midnight
+    Daily,
+}
+
+impl AutoReset {
+    /// Parse `!autoreset` argument: hours, "daily", or "off" (Ok(None))
+    fn parse(arg: &str) -> Result<Option<Self>, String> {
+        match arg {
+            "off" => Ok(None),
+            "daily" => Ok(Some(AutoReset::Daily)),
+            _ => match arg.trim_end_matches('h').parse::<u64>() {
+                Ok(hours) if hours > 0 => Ok(Some(AutoReset::Hours(hours))),
+                _ => Err(format!("Invalid value `{}` (use hours, `daily`, or `off`)", arg)),
+            },
         }
+    }
 
-        // Handle commands first (these don't go to queue)
+    fn describe(&self) -> String {
+        match self {
+            AutoReset::Hours(hours) => format!("after {}h idle", hours),
+            AutoReset::Daily => "daily at midnight".to_string(),
+        }
+    }
 
-        // Handle help command
-        if content == "!help" || content == "!도움" {
-            let help_text = format!(
-                "**Neywa v{}** - AI Assistant\n\n\
-                **Commands** (slash `/` or text `!`):\n\
-                `help` - Show this help\n\
-                `status` - Check session status\n\
-                `new` - Start a new conversation\n\
-                `stop` - Stop processing & clear queue\n\
-                `queue` - Show queued messages\n\
-                `compact` - Compact session context window\n\
-                `update` - Update to latest version\n\
-                `longtext` - How to send long text\n\
-                `slash <cmd>` - Run Claude Code slash command\n\n\
-                **Text-only Commands:**\n\
-                `!plan <msg>` - Generate a plan without executing (read-only)\n\
-                `!z` - Toggle Z mode (claude-z)\n\
-                `!codex` - Toggle Codex mode (OpenAI Codex CLI)\n\
-                `!human` - Toggle human-only mode (Neywa stops responding)\n\
-                `!run <cmd>` - Execute terminal command directly\n\
-                `!restart` - Reset all Claude sessions (fixes MCP/connection issues)\n\n\
-                Just type a message to chat with AI.",
-                VERSION
-            );
-            let _ = msg.channel_id.say(&ctx.http, help_text).await;
-            return;
+    /// Whether a session last used at `last_used` should be discarded
+    fn is_expired(&self, last_used: DateTime<Local>, now: DateTime<Local>) -> bool {
+        match self {
+            AutoReset::Hours(hours) => now - last_used >= chrono::Duration::hours(*hours as i64),
+            AutoReset::Daily => last_used.date_naive() < now.date_naive(),
         }
+    }
+}
 
-        // Handle stop command
-        if content == "!stop" || content == "!중단" {
-            let data = ctx.data.read().await;
+/// Per-channel autoreset policies
+struct ChannelAutoReset;
+impl TypeMapKey for ChannelAutoReset {
+    type Value = Arc<RwLock<HashMap<u64, AutoReset>>>;
+}
 
-            // Cancel current processing
-            if let Some(processing) = data.get::<ProcessingChannels>() {
-                if let Some(token) = processing.read().await.get(&channel_id) {
-                    token.cancel();
-                    let _ = msg.channel_id.say(&ctx.http, "🛑 Stop requested...").await;
-                } else {
-                    let _ = msg.channel_id.say(&ctx.http, "Nothing is being processed.").await;
-                }
-            }
+/// Pending `!z confirm` / `!codex confirm` requests
+struct PendingConfirmations;
+impl TypeMapKey for PendingConfirmations {
+    type Value = Arc<RwLock<Confirmations>>;
+}
 
-            // Clear queue for this channel
-            if let Some(queue) = data.get::<MessageQueue>() {
-                let cleared = {
-                    let mut q = queue.write().await;
-                    if let Some(channel_queue) = q.get_mut(&channel_id) {
-                        let count = channel_queue.len();
-                        channel_queue.clear();
-                        count
-                    } else {
-                        0
-                    }
-                };
-                if cleared > 0 {
-                    let _ = msg.channel_id.say(&ctx.http, format!("📭 Cleared {} queued message(s)", cleared)).await;
-                }
-            }
-            return;
-        }
+/// Session IDs that have already been nudged to `!compact`
+struct CompactSuggested;
+impl TypeMapKey for CompactSuggested {
+    type Value = Arc<RwLock<HashSet<String>>>;
+}
 
-        // Handle reset command
-        if content == "!reset" || content == "!새대화" {
-            let data = ctx.data.read().await;
-            if let Some(sessions) = data.get::<SessionStorage>() {
-                let mut sessions_map = sessions.write().await;
-                sessions_map.remove(&session_key);
-                save_sessions(&sessions_map);
-            }
-            let _ = msg.channel_id.say(&ctx.http, "Session reset.").await;
-            return;
-        }
+/// Per-channel completion message (`!done-style`)
+struct ChannelDoneStyles;
+impl TypeMapKey for ChannelDoneStyles {
+    type Value = Arc<RwLock<HashMap<u64, String>>>;
+}
 
-        // Handle !run command - execute terminal command directly
-        if let Some(cmd) = content.strip_prefix("!run ") {
-            let cmd = cmd.trim();
-            if cmd.is_empty() {
-                let _ = msg.channel_id.say(&ctx.http, "Usage: `!run <command>`").await;
-                return;
-            }
+/// Completion message used when a channel has no `!done-style`
+const DEFAULT_DONE_STYLE: &str = "✅ Done!";
 
-            tracing::info!("Executing terminal command: {}", cmd);
-            let _ = msg.channel_id.say(&ctx.http, format!("⏳ Running: `{}`", cmd)).await;
+/// Longest accepted `!done-style`
+const MAX_DONE_STYLE_CHARS: usize = 100;
 
-            // Run command in spawn_blocking to avoid blocking the async runtime
-            let cmd_owned = cmd.to_string();
-            let output = tokio::task::spawn_blocking(move || {
-                Command::new("bash")
-                    .arg("-c")
-                    .arg(&cmd_owned)
-                    .output()
-            }).await;
+/// Path for storing channel completion styles
+fn channel_done_styles_file_path() -> std::path::PathBuf {
+    let config_dir = dirs::config_dir()
+        .unwrap_or_else(|| std::path::PathBuf::from("."))
+        .join("neywa");
+    config_dir.join("channel_done_styles.json")
+}
 
-            let response = match output {
-                Ok(Ok(output)) => {
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-                    let stderr = String::from_utf8_lossy(&output.stderr);
-                    let exit_code = output.status.code().unwrap_or(-1);
+/// Load channel completion styles from file
+fn load_channel_done_styles() -> HashMap<u64, String> {
+    let path = channel_done_styles_file_path();
+    if !path.exists() {
+        return HashMap::new();
+    }
+    match std::fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_default(),
+        Err(_) => HashMap::new(),
+    }
+}
 
-                    let mut result = String::new();
-                    if !stdout.is_empty() {
-                        result.push_str(&format!("**stdout:**\n```\n{}\n```", stdout));
-                    }
-                    if !stderr.is_empty() {
-                        if !result.is_empty() { result.push_str("\n"); }
-                        result.push_str(&format!("**stderr:**\n```\n{}\n```", stderr));
-                    }
-                    result.push_str(&format!("\n*Exit code: {}*", exit_code));
+/// Save channel completion styles to file
+fn save_channel_done_styles(styles: &HashMap<u64, String>) {
+    let path = channel_done_styles_file_path();
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    if let Ok(json) = serde_json::to_string(styles) {
+        let _ = std::fs::write(&path, json);
+    }
+}
 
-                    if result.is_empty() {
-                        format!("✅ Done (exit code: {})", exit_code)
-                    } else {
-                        result
-                    }
-                }
-                Ok(Err(e)) => format!("❌ Failed to execute: {}", e),
-                Err(e) => format!("❌ Task error: {}", e),
-            };
+/// Completion message for a channel
+async fn channel_done_style(ctx: &serenity::client::Context, channel_id: u64) -> String {
+    let data = ctx.data.read().await;
+    match data.get::<ChannelDoneStyles>() {
+        Some(styles) => styles.read().await.get(&channel_id).cloned(),
+        None => None,
+    }
+    .unwrap_or_else(|| DEFAULT_DONE_STYLE.to_string())
+}
 
-            // Discord has 2000 char limit, truncate if needed
-            let response = if response.len() > 1950 {
-                format!("{}...\n*(truncated)*", &response[..1900])
-            } else {
-                response
-            };
+/// Per-channel override of `embed_responses` (`!embed`)
+struct ChannelEmbeds;
+impl TypeMapKey for ChannelEmbeds {
+    type Value = Arc<RwLock<HashMap<u64, bool>>>;
+}
 
-            let _ = msg.channel_id.say(&ctx.http, response).await;
-            return;
-        }
+/// Longest embed description Discord accepts is 4096; leave room for reopened code fences
+const EMBED_DESCRIPTION_MAX: usize = 4000;
 
-        // Handle Z mode toggle command
-        if content == "!z" {
-            let data = ctx.data.read().await;
-            if let Some(backends) = data.get::<ChannelBackends>() {
-                let mut map = backends.write().await;
-                let current = map.get(&channel_id).copied().unwrap_or(AiBackend::Claude);
+/// Path for storing channel embed settings
+fn channel_embeds_file_path() -> std::path::PathBuf {
+    let config_dir = dirs::config_dir()
+        .unwrap_or_else(|| std::path::PathBuf::from("."))
+        .join("neywa");
+    config_dir.join("channel_embeds.json")
+}
 
-                let is_z_mode = if current == AiBackend::ClaudeZ {
-                    map.remove(&channel_id);
-                    false
-                } else {
-                    map.insert(channel_id, AiBackend::ClaudeZ);
-                    true
-                };
-                save_channel_backends(&map);
+/// Load channel embed settings from file
+fn load_channel_embeds() -> HashMap<u64, bool> {
+    let path = channel_embeds_file_path();
+    if !path.exists() {
+        return HashMap::new();
+    }
+    match std::fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_default(),
+        Err(_) => HashMap::new(),
+    }
+}
 
-                if let Some(sessions) = data.get::<SessionStorage>() {
-                    let mut sessions_map = sessions.write().await;
-                    sessions_map.remove(&session_key);
-                    save_sessions(&sessions_map);
-                }
+/// Save channel embed settings to file
+fn save_channel_embeds(embeds: &HashMap<u64, bool>) {
+    let path = channel_embeds_file_path();
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    if let Ok(json) = serde_json::to_string(embeds) {
+        let _ = std::fs::write(&path, json);
+    }
+}
 
-                let mode_msg = if is_z_mode {
-                    "⚡ **Z mode ON** - Using `claude-z` (z.ai API) in this channel"
-                } else {
-                    "🔄 **Normal mode** - Using `claude` (Anthropic API) in this channel"
-                };
-                let _ = msg.channel_id.say(&ctx.http, mode_msg).await;
-            }
-            return;
-        }
+/// Whether a channel gets its responses as embeds: its `!embed` setting, else the config default
+async fn channel_embeds(ctx: &serenity::client::Context, channel_id: u64) -> bool {
+    let data = ctx.data.read().await;
+    match data.get::<ChannelEmbeds>() {
+        Some(embeds) => embeds.read().await.get(&channel_id).copied(),
+        None => None,
+    }
+    .unwrap_or_else(|| Config::load().unwrap_or_default().embed_responses)
+}
 
-        // Handle Codex mode toggle command
-        if content == "!codex" {
-            // Check if codex CLI is available
-            if claude::find_cli("codex").is_none() {
-                let _ = msg.channel_id.say(&ctx.http, "❌ codex CLI not found. Install: `npm install -g @openai/codex`").await;
-                return;
+/// One embed per part of the response: the title goes on the first, the footer on the last
+fn response_embeds(body: &str, title: &str, footer: &str) -> Vec<CreateEmbed> {
+    let parts = split_text(body, EMBED_DESCRIPTION_MAX);
+    let last = parts.len() - 1;
+    parts
+        .into_iter()
+        .enumerate()
+        .map(|(i, part)| {
+            let mut embed = CreateEmbed::new().description(part);
+            if i == 0 {
+                embed = embed.title(title);
             }
+            if i == last {
+                embed = embed.footer(CreateEmbedFooter::new(footer));
+            }
+            embed
+        })
+        .collect()
+}
 
-            let channel_name = if let Ok(channel) = msg.channel_id.to_channel(&ctx.http).await {
-                channel.guild().map(|gc| gc.name.clone())
-            } else {
-                None
-            };
-
-            let data = ctx.data.read().await;
-            if let Some(backends) = data.get::<ChannelBackends>() {
-                let mut map = backends.write().await;
-                let current = map.get(&channel_id).copied().unwrap_or(AiBackend::Claude);
+/// "42s", "3m 05s"
+fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    if secs >= 60 {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
 
-                let is_codex = if current == AiBackend::Codex {
-                    // Turn OFF codex mode
-                    map.remove(&channel_id);
+/// How often the status message is refreshed when no tool events arrive,
+/// so the elapsed time keeps moving without crowding Discord's edit rate limit
+const STATUS_REFRESH: Duration = Duration::from_secs(5);
 
-                    // Remove 🅾️ emoji from channel name
-                    if let Some(name) = &channel_name {
-                        let new_name = name.trim_start_matches("🅾️").trim_start_matches('-').to_string();
-                        let new_name = if new_name.is_empty() { name.clone() } else { new_name };
-                        tokio::spawn({
-                            let channel_id_str = channel_id.to_string();
-                            async move {
-                                if let Err(e) = discord_api::rename_channel(&channel_id_str, &new_name).await {
-                                    tracing::warn!("Failed to rename channel: {}", e);
-                                }
-                            }
-                        });
-                    }
-                    false
-                } else {
-                    // Turn ON codex mode
-                    map.insert(channel_id, AiBackend::Codex);
+/// Live status text: "⏳ Working… 2m 14s · 37 tool calls" over the latest tool lines
+fn status_text(elapsed: Duration, tool_calls: usize, lines: &[String]) -> String {
+    let mut text = format!("⏳ Working… {}", format_elapsed(elapsed));
+    if tool_calls > 0 {
+        text.push_str(&format!(" · {} tool call{}", tool_calls, if tool_calls == 1 { "" } else { "s" }));
+    }
+    for line in lines {
+        text.push('\n');
+        text.push_str(line);
+    }
+    text
+}
 
-                    // Add 🅾️ emoji to channel name
-                    if let Some(name) = &channel_name {
-                        // Remove any existing mode emoji first
-                        let clean_name = name.trim_start_matches("🅾️").trim_start_matches('-').to_string();
-                        let new_name = format!("🅾️{}", clean_name);
-                        tokio::spawn({
-                            let channel_id_str = channel_id.to_string();
-                            async move {
-                                if let Err(e) = discord_api::rename_channel(&channel_id_str, &new_name).await {
-                                    tracing::warn!("Failed to rename channel: {}", e);
-                                }
-                            }
-                        });
-                    }
-                    true
-                };
-                save_channel_backends(&map);
+/// "⏱️ Timed out after 30 minutes" with the run's last tool-status lines
+fn timeout_notice(timeout: Duration, status_lines: &[String]) -> String {
+    let mut text = format!(
+        "⏱️ Timed out after {} minutes. The session is kept, so you can reply to continue from where it stopped.",
+        timeout.as_secs() / 60
+    );
+    if !status_lines.is_empty() {
+        text.push_str("\nLast steps:");
+        for line in status_lines {
+            text.push('\n');
+            text.push_str(line);
+        }
+    }
+    text
+}
 
-                // Reset session on mode change
-                if let Some(sessions) = data.get::<SessionStorage>() {
-                    let mut sessions_map = sessions.write().await;
-                    sessions_map.remove(&session_key);
-                    save_sessions(&sessions_map);
-                }
+/// Per-channel live response setting (`!stream`; absent = config default)
+struct ChannelStreaming;
+impl TypeMapKey for ChannelStreaming {
+    type Value = Arc<RwLock<HashMap<u64, bool>>>;
+}
 
-                let mode_msg = if is_codex {
-                    "🅾️ **Codex mode ON** - Using OpenAI Codex CLI in this channel"
-                } else {
-                    "🔄 **Normal mode** - Using `claude` (Anthropic API) in this channel"
-                };
-                let _ = msg.channel_id.say(&ctx.http, mode_msg).await;
-            }
-            return;
-        }
+/// Path for storing channel streaming settings
+fn channel_streaming_file_path() -> PathBuf {
+    let config_dir = dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("neywa");
+    config_dir.join("channel_streaming.json")
+}
 
-        // Handle human mode toggle
-        if content == "!human" || content == "!인간" {
-            let channel_name = if let Ok(channel) = msg.channel_id.to_channel(&ctx.http).await {
-                channel.guild().map(|gc| gc.name.clone())
-            } else {
-                None
-            };
+/// Load channel streaming settings from file
+fn load_channel_streaming() -> HashMap<u64, bool> {
+    let path = channel_streaming_file_path();
+    if !path.exists() {
+        return HashMap::new();
+    }
+    match std::fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_default(),
+        Err(_) => HashMap::new(),
+    }
+}
 
-            let data = ctx.data.read().await;
-            if let Some(human_channels) = data.get::<HumanModeChannels>() {
-                let mut channels = human_channels.write().await;
-                let is_human_mode = if channels.contains(&channel_id) {
-                    // Turn OFF human mode
-                    channels.remove(&channel_id);
-                    save_human_mode(&channels);
+/// Save channel streaming settings to file
+fn save_channel_streaming(streaming: &HashMap<u64, bool>) {
+    let path = channel_streaming_file_path();
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    if let Ok(json) = serde_json::to_string(streaming) {
+        let _ = std::fs::write(&path, json);
+    }
+}
 
-                    // Remove emoji from channel name
-                    if let Some(name) = &channel_name {
-                        let new_name = name.trim_start_matches("🙋‍♂️").trim_start_matches('-').to_string();
-                        let new_name = if new_name.is_empty() { name.clone() } else { new_name };
-                        tokio::spawn({
-                            let channel_id_str = channel_id.to_string();
-                            async move {
-                                if let Err(e) = discord_api::rename_channel(&channel_id_str, &new_name).await {
-                                    tracing::warn!("Failed to rename channel: {}", e);
-                                }
-                            }
-                        });
-                    }
+/// Whether a channel shows responses while they are written: its `!stream` setting, else the config default
+async fn channel_streaming(ctx: &serenity::client::Context, channel_id: u64) -> bool {
+    let data = ctx.data.read().await;
+    match data.get::<ChannelStreaming>() {
+        Some(streaming) => streaming.read().await.get(&channel_id).copied(),
+        None => None,
+    }
+    .unwrap_or_else(|| Config::load().unwrap_or_default().stream_responses)
+}
 
-                    false
-                } else {
-                    // Turn ON human mode
-                    channels.insert(channel_id);
-                    save_human_mode(&channels);
+/// Per-channel long-response threshold (`!longfile`; absent = config default, 0 = never attach)
+struct ChannelLongFiles;
+impl TypeMapKey for ChannelLongFiles {
+    type Value = Arc<RwLock<HashMap<u64, u32>>>;
+}
 
-                    // Add emoji to channel name
-                    if let Some(name) = &channel_name {
-                        let new_name = format!("🙋‍♂️{}", name);
-                        tokio::spawn({
-                            let channel_id_str = channel_id.to_string();
-                            async move {
-                                if let Err(e) = discord_api::rename_channel(&channel_id_str, &new_name).await {
-                                    tracing::warn!("Failed to rename channel: {}", e);
-                                }
-                            }
-                        });
-                    }
+/// Path for storing channel long-response settings
+fn channel_long_files_file_path() -> PathBuf {
+    let config_dir = dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("neywa");
+    config_dir.join("channel_long_files.json")
+}
 
-                    true
-                };
+/// Load channel long-response settings from file
+fn load_channel_long_files() -> HashMap<u64, u32> {
+    let path = channel_long_files_file_path();
+    if !path.exists() {
+        return HashMap::new();
+    }
+    match std::fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_default(),
+        Err(_) => HashMap::new(),
+    }
+}
 
-                let mode_msg = if is_human_mode {
-                    "🙋‍♂️ **Human mode ON** - Neywa will not respond in this channel.\nType `!human` again to turn off."
-                } else {
-                    "🤖 **Human mode OFF** - Neywa is back online in this channel."
-                };
-                let _ = msg.channel_id.say(&ctx.http, mode_msg).await;
-            }
-            return;
-        }
+/// Save channel long-response settings to file
+fn save_channel_long_files(settings: &HashMap<u64, u32>) {
+    let path = channel_long_files_file_path();
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    if let Ok(json) = serde_json::to_string(settings) {
+        let _ = std::fs::write(&path, json);
+    }
+}
 
-        // Handle status command
-        if content == "!status" || content == "!상태" {
-            let data = ctx.data.read().await;
-            let backend = if let Some(backends) = data.get::<ChannelBackends>() {
-                backends.read().await.get(&channel_id).copied().unwrap_or(AiBackend::Claude)
-            } else {
-                AiBackend::Claude
-            };
-            let is_processing = if let Some(processing) = data.get::<ProcessingChannels>() {
-                processing.read().await.contains_key(&channel_id)
-            } else {
-                false
-            };
-            let queue_size = if let Some(queue) = data.get::<MessageQueue>() {
-                queue.read().await.get(&channel_id).map(|q| q.len()).unwrap_or(0)
-            } else {
-                0
-            };
+/// How many chunks a response may take before it is attached as a file
+/// instead: the channel's `!longfile` setting, else the config default.
+/// None = never attach.
+async fn channel_long_file_limit(ctx: &serenity::client::Context, channel_id: u64) -> Option<usize> {
+    let data = ctx.data.read().await;
+    let limit = match data.get::<ChannelLongFiles>() {
+        Some(settings) => settings.read().await.get(&channel_id).copied(),
+        None => None,
+    }
+    .unwrap_or_else(|| Config::load().unwrap_or_default().attach_responses_over_chunks);
+    (limit > 0).then_some(limit as usize)
+}
 
-            let mode = backend.status_line();
-            let processing_status = if is_processing { "🔄 Processing" } else { "✅ Idle" };
-            let queue_status = if queue_size > 0 { format!("📬 Queue: {}", queue_size) } else { "📭 Queue: empty".to_string() };
+/// Per-channel run timeout in minutes (`!timeout`; absent = config default, 0 = never)
+struct ChannelTimeouts;
+impl TypeMapKey for ChannelTimeouts {
+    type Value = Arc<RwLock<HashMap<u64, u64>>>;
+}
 
-            let _ = msg.channel_id.say(&ctx.http, format!("{}\n{}\n{}", mode, processing_status, queue_status)).await;
-            return;
-        }
+/// Path for storing channel run timeouts
+fn channel_timeouts_file_path() -> PathBuf {
+    let config_dir = dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("neywa");
+    config_dir.join("channel_timeouts.json")
+}
 
-        // Handle queue status command
-        if content == "!queue" || content == "!대기열" {
-            let data = ctx.data.read().await;
-            let queue_size = if let Some(queue) = data.get::<MessageQueue>() {
-                queue.read().await.get(&channel_id).map(|q| q.len()).unwrap_or(0)
-            } else {
-                0
-            };
-            let is_processing = if let Some(processing) = data.get::<ProcessingChannels>() {
-                processing.read().await.contains_key(&channel_id)
-            } else {
-                false
-            };
+/// Load channel run timeouts from file
+fn load_channel_timeouts() -> HashMap<u64, u64> {
+    let path = channel_timeouts_file_path();
+    if !path.exists() {
+        return HashMap::new();
+    }
+    match std::fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_default(),
+        Err(_) => HashMap::new(),
+    }
+}
 
-            let status = if is_processing {
-                format!("🔄 Processing | 📬 Queue: {}", queue_size)
-            } else if queue_size > 0 {
-                format!("📬 Queue: {}", queue_size)
-            } else {
-                "📭 Queue is empty.".to_string()
-            };
-            let _ = msg.channel_id.say(&ctx.http, status).await;
-            return;
-        }
+/// Save channel run timeouts to file
+fn save_channel_timeouts(timeouts: &HashMap<u64, u64>) {
+    let path = channel_timeouts_file_path();
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    if let Ok(json) = serde_json::to_string(timeouts) {
+        let _ = std::fs::write(&path, json);
+    }
+}
 
-        // Handle compact command
-        if content == "!compact" {
-            let current_backend = get_channel_backend(&ctx, channel_id).await;
-            if current_backend == AiBackend::Codex {
-                let _ = msg.channel_id.say(&ctx.http, "⚠️ Codex 모드에서는 compact를 지원하지 않습니다. `!new`로 새 세션을 시작하세요.").await;
-                return;
+/// How long a run may take in a channel: its `!timeout` setting, else the
+/// config default. None = no limit.
+async fn channel_run_timeout(ctx: &serenity::client::Context, channel_id: u64) -> Option<Duration> {
+    let data = ctx.data.read().await;
+    let minutes = match data.get::<ChannelTimeouts>() {
+        Some(timeouts) => timeouts.read().await.get(&channel_id).copied(),
+        None => None,
+    }
+    .unwrap_or_else(|| Config::load().unwrap_or_default().run_timeout_mins);
+    (minutes > 0).then(|| Duration::from_secs(minutes * 60))
+}
+
+/// Resolves at `deadline`, or never without one
+async fn until_deadline(deadline: Option<tokio::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Post a response as chunks. Past `attach_after` chunks, only the first is
+/// posted inline and the full text follows as `response.md`.
+async fn send_response(http: &serenity::http::Http, channel: ChannelId, text: &str, attach_after: Option<usize>) {
+    let chunks = split_for_discord(text);
+    if attach_after.is_some_and(|limit| chunks.len() > limit) {
+        let _ = channel.say(http, &chunks[0]).await;
+        let note = format!(
+            "📎 The full response ({}, {} messages' worth) is attached.",
+            attachment::format_bytes(text.len() as u64),
+            chunks.len()
+        );
+        let file = CreateAttachment::bytes(text.as_bytes().to_vec(), "response.md");
+        if let Err(e) = channel.send_message(http, CreateMessage::new().content(note).add_file(file)).await {
+            // Fall back to the remaining chunks so nothing is lost
+            tracing::warn!("Failed to attach long response: {}", e);
+            for chunk in &chunks[1..] {
+                let _ = channel.say(http, chunk).await;
             }
+        }
+        return;
+    }
+    for chunk in chunks {
+        let _ = channel.say(http, &chunk).await;
+    }
+}
 
-            let existing_session = {
-                let data = ctx.data.read().await;
-                if let Some(sessions) = data.get::<SessionStorage>() {
-                    sessions.read().await.get(&session_key).cloned()
-                } else {
-                    None
-                }
-            };
+/// Minimum gap between writes to a live response
+const STREAM_EDIT_INTERVAL: Duration = Duration::from_millis(1500);
 
-            if let Some(sid) = existing_session {
-                let _ = msg.channel_id.say(&ctx.http, "🗜️ Compacting session...").await;
+/// The messages showing a response while it is still being written (`!stream`)
+#[derive(Default)]
+struct LiveResponse {
+    messages: Vec<Message>,
+    /// What each message currently says
+    shown: Vec<String>,
+    last_write: Option<Instant>,
+}
 
-                let use_z = current_backend == AiBackend::ClaudeZ;
+impl LiveResponse {
+    /// Whether enough time has passed since the last write
+    fn due(&self) -> bool {
+        self.last_write.is_none_or(|at| at.elapsed() >= STREAM_EDIT_INTERVAL)
+    }
 
-                match claude::compact_session(&sid, use_z).await {
-                    Ok(_) => {
-                        let _ = msg.channel_id.say(&ctx.http, "✅ Session compacted.").await;
+    /// Write `text` across the live messages, rolling over to a new message at
+    /// Discord's limit. Only chunks that changed are edited; surplus messages
+    /// (the text shrinks when a new assistant turn starts) are deleted.
+    async fn show(&mut self, http: &serenity::http::Http, channel: ChannelId, text: &str) {
+        let chunks = split_for_discord(text);
+        for (i, chunk) in chunks.iter().enumerate() {
+            if i < self.messages.len() {
+                if self.shown[i] != *chunk
+                    && channel.edit_message(http, self.messages[i].id, EditMessage::new().content(chunk)).await.is_ok()
+                {
+                    self.shown[i] = chunk.clone();
+                }
+            } else {
+                match channel.say(http, chunk).await {
+                    Ok(message) => {
+                        self.messages.push(message);
+                        self.shown.push(chunk.clone());
                     }
                     Err(e) => {
-                        // Try trim as fallback
-                        if trim_session_file(&sid) {
-                            let _ = msg.channel_id.say(&ctx.http, "⚠️ Compact failed, trimmed old messages instead.").await;
-                        } else {
-                            let _ = msg.channel_id.say(&ctx.http, format!("❌ Compact failed: {}", e)).await;
-                        }
+                        tracing::warn!("Failed to post live response: {}", e);
+                        break;
                     }
                 }
-            } else {
-                let _ = msg.channel_id.say(&ctx.http, "No active session. Nothing to compact.").await;
             }
-            return;
         }
-
-        // Handle slash command passthrough
-        if content.starts_with("!slash ") {
-            let current_backend = get_channel_backend(&ctx, channel_id).await;
-            if current_backend == AiBackend::Codex {
-                let _ = msg.channel_id.say(&ctx.http, "ℹ️ Codex 모드에서는 slash 명령을 지원하지 않습니다.").await;
-                return;
+        while self.messages.len() > chunks.len() {
+            if let Some(message) = self.messages.pop() {
+                let _ = message.delete(http).await;
             }
+            self.shown.pop();
+        }
+        self.last_write = Some(Instant::now());
+    }
 
-            let slash_cmd = content.trim_start_matches("!slash ").trim().to_string();
-            if slash_cmd.is_empty() {
-                let _ = msg.channel_id.say(&ctx.http, "Usage: `!slash <command>` (e.g., `!slash compact`, `!slash cost`)").await;
-                return;
-            }
+    /// Delete the live messages (the final response is posted differently)
+    async fn clear(&mut self, http: &serenity::http::Http) {
+        for message in self.messages.drain(..) {
+            let _ = message.delete(http).await;
+        }
+        self.shown.clear();
+    }
+}
 
-            let existing_session = {
-                let data = ctx.data.read().await;
-                if let Some(sessions) = data.get::<SessionStorage>() {
-                    sessions.read().await.get(&session_key).cloned()
-                } else {
-                    None
-                }
-            };
+/// Channels where Neywa only answers messages addressed to it (`!mention`)
+struct MentionChannels;
+impl TypeMapKey for MentionChannels {
+    type Value = Arc<RwLock<HashSet<u64>>>;
+}
 
-            let use_z = current_backend == AiBackend::ClaudeZ;
+/// Path for storing the mention-only channel list
+fn mention_channels_file_path() -> PathBuf {
+    let config_dir = dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("neywa");
+    config_dir.join("mention_channels.json")
+}
 
-            let display_cmd = slash_cmd.trim_start_matches('/');
-            let _ = msg.channel_id.say(&ctx.http, format!("⚡ Running `/{}`...", display_cmd)).await;
+/// Load mention-only channels from file
+fn load_mention_channels() -> HashSet<u64> {
+    let path = mention_channels_file_path();
+    if !path.exists() {
+        return HashSet::new();
+    }
+    match std::fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_default(),
+        Err(_) => HashSet::new(),
+    }
+}
 
-            match claude::run_slash_command(&slash_cmd, existing_session.as_deref(), use_z).await {
-                Ok(result) => {
+/// Save mention-only channels to file
+fn save_mention_channels(channels: &HashSet<u64>) {
+    let path = mention_channels_file_path();
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    if let Ok(json) = serde_json::to_string(channels) {
+        let _ = std::fs::write(&path, json);
+    }
+}
+
+async fn is_mention_channel(ctx: &serenity::client::Context, channel_id: u64) -> bool {
+    let data = ctx.data.read().await;
+    match data.get::<MentionChannels>() {
+        Some(channels) => channels.read().await.contains(&channel_id),
+        None => false,
+    }
+}
+
+/// Whether a message is addressed to Neywa, and its content with the
+/// addressing (`<@bot>` mentions, the `mention_prefix`) taken off
+fn addressed_content(msg: &Message, content: &str, bot_id: Option<u64>, prefix: Option<&str>) -> (bool, String) {
+    let mut addressed = false;
+    let mut content = content.to_string();
+    if let Some(bot_id) = bot_id {
+        if msg.mentions.iter().any(|user| user.id.get() == bot_id) {
+            addressed = true;
+            for mention in [format!("<@{}>", bot_id), format!("<@!{}>", bot_id)] {
+                content = content.replace(&mention, "");
+            }
+            content = content.trim().to_string();
+        }
+        addressed |= msg
+            .referenced_message
+            .as_ref()
+            .is_some_and(|replied| replied.author.id.get() == bot_id);
+    }
+    if let Some(prefix) = prefix.map(str::trim).filter(|p| !p.is_empty()) {
+        let starts = content
+            .get(..prefix.len())
+            .is_some_and(|head| head.eq_ignore_ascii_case(prefix));
+        if starts {
+            addressed = true;
+            content = content[prefix.len()..]
+                .trim_start_matches([',', ':', ' '])
+                .trim()
+                .to_string();
+        }
+    }
+    (addressed, content)
+}
+
+/// Channels where everyone shares one session (`!shared`)
+struct SharedChannels;
+impl TypeMapKey for SharedChannels {
+    type Value = Arc<RwLock<HashSet<u64>>>;
+}
+
+/// User half of a shared channel's session key
+const SHARED_SESSION_USER: u64 = 0;
+
+/// Path for storing the shared-session channel list
+fn shared_channels_file_path() -> PathBuf {
+    let config_dir = dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("neywa");
+    config_dir.join("shared_channels.json")
+}
+
+/// Load shared-session channels from file
+fn load_shared_channels() -> HashSet<u64> {
+    let path = shared_channels_file_path();
+    if !path.exists() {
+        return HashSet::new();
+    }
+    match std::fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_default(),
+        Err(_) => HashSet::new(),
+    }
+}
+
+/// Save shared-session channels to file
+fn save_shared_channels(channels: &HashSet<u64>) {
+    let path = shared_channels_file_path();
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    if let Ok(json) = serde_json::to_string(channels) {
+        let _ = std::fs::write(&path, json);
+    }
+}
+
+async fn is_shared_channel(ctx: &serenity::client::Context, channel_id: u64) -> bool {
+    let data = ctx.data.read().await;
+    match data.get::<SharedChannels>() {
+        Some(channels) => channels.read().await.contains(&channel_id),
+        None => false,
+    }
+}
+
+/// The session a user talks to in a channel: their own, or the channel's in `!shared` mode
+async fn session_key_for(ctx: &serenity::client::Context, user_id: u64, channel_id: u64) -> SessionKey {
+    if is_shared_channel(ctx, channel_id).await {
+        (SHARED_SESSION_USER, channel_id)
+    } else {
+        (user_id, channel_id)
+    }
+}
+
+/// Channels where each request gets its own thread (`!threads`)
+struct ThreadChannels;
+impl TypeMapKey for ThreadChannels {
+    type Value = Arc<RwLock<HashSet<u64>>>;
+}
+
+/// Path for storing the thread-per-request channel list
+fn thread_channels_file_path() -> PathBuf {
+    let config_dir = dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("neywa");
+    config_dir.join("thread_channels.json")
+}
+
+/// Load thread-per-request channels from file
+fn load_thread_channels() -> HashSet<u64> {
+    let path = thread_channels_file_path();
+    if !path.exists() {
+        return HashSet::new();
+    }
+    match std::fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_default(),
+        Err(_) => HashSet::new(),
+    }
+}
+
+/// Save thread-per-request channels to file
+fn save_thread_channels(channels: &HashSet<u64>) {
+    let path = thread_channels_file_path();
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    if let Ok(json) = serde_json::to_string(channels) {
+        let _ = std::fs::write(&path, json);
+    }
+}
+
+async fn channel_threads(ctx: &serenity::client::Context, channel_id: u64) -> bool {
+    let data = ctx.data.read().await;
+    match data.get::<ThreadChannels>() {
+        Some(channels) => channels.read().await.contains(&channel_id),
+        None => false,
+    }
+}
+
+/// Longest thread name taken from a prompt (Discord allows 100)
+const THREAD_NAME_LEN: usize = 80;
+
+/// Thread name for a request: its first line, shortened
+fn thread_name(prompt: &str, author: &str) -> String {
+    match prompt.lines().map(str::trim).find(|line| !line.is_empty()) {
+        Some(line) => claude::truncate_str(line, THREAD_NAME_LEN),
+        None => format!("Request from {}", author),
+    }
+}
+
+/// With `!threads` on, open a public thread off the request and return the
+/// message retargeted to it, so the status, answer, session and follow-ups all
+/// live in the thread. Threads, forum posts and DMs are answered in place, and
+/// if Discord refuses the thread (usually missing permissions) so is the channel.
+async fn thread_for_request(ctx: &serenity::client::Context, msg: &Message, kind: ChannelKind, prompt: &str) -> Message {
+    if !kind.can_open_threads() || !channel_threads(ctx, msg.channel_id.get()).await {
+        return msg.clone();
+    }
+    let builder = CreateThread::new(thread_name(prompt, &msg.author.name));
+    match msg.channel_id.create_thread_from_message(&ctx.http, msg.id, builder).await {
+        Ok(thread) => {
+            tracing::info!("Opened thread #{} for {}'s request", thread.name, msg.author.name);
+            let mut retargeted = msg.clone();
+            retargeted.channel_id = thread.id;
+            retargeted
+        }
+        Err(e) => {
+            tracing::warn!("Failed to create thread in {}: {}", msg.channel_id, e);
+            let _ = msg
+                .channel_id
+                .say(&ctx.http, "⚠️ Couldn't open a thread (does Neywa have the Create Public Threads permission?). Answering here.")
+                .await;
+            msg.clone()
+        }
+    }
+}
+
+/// Per-channel Claude model (`!model`)
+struct ChannelModels;
+impl TypeMapKey for ChannelModels {
+    type Value = Arc<RwLock<HashMap<u64, String>>>;
+}
+
+/// Path for storing channel model selections
+fn channel_models_file_path() -> std::path::PathBuf {
+    let config_dir = dirs::config_dir()
+        .unwrap_or_else(|| std::path::PathBuf::from("."))
+        .join("neywa");
+    config_dir.join("channel_models.json")
+}
+
+/// Load channel models from file
+fn load_channel_models() -> HashMap<u64, String> {
+    let path = channel_models_file_path();
+    if !path.exists() {
+        return HashMap::new();
+    }
+    match std::fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_default(),
+        Err(_) => HashMap::new(),
+    }
+}
+
+/// Save channel models to file
+fn save_channel_models(models: &HashMap<u64, String>) {
+    let path = channel_models_file_path();
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    if let Ok(json) = serde_json::to_string(models) {
+        let _ = std::fs::write(&path, json);
+    }
+}
+
+/// Per-channel working directory for spawned CLIs (`!cwd`)
+struct ChannelWorkdirs;
+impl TypeMapKey for ChannelWorkdirs {
+    type Value = Arc<RwLock<HashMap<u64, PathBuf>>>;
+}
+
+/// Path for storing channel working directories
+fn channel_workdirs_file_path() -> PathBuf {
+    let config_dir = dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("neywa");
+    config_dir.join("channel_workdirs.json")
+}
+
+/// Load channel working directories from file
+fn load_channel_workdirs() -> HashMap<u64, PathBuf> {
+    let path = channel_workdirs_file_path();
+    if !path.exists() {
+        return HashMap::new();
+    }
+    match std::fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_default(),
+        Err(_) => HashMap::new(),
+    }
+}
+
+/// Save channel working directories to file
+fn save_channel_workdirs(workdirs: &HashMap<u64, PathBuf>) {
+    let path = channel_workdirs_file_path();
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    if let Ok(json) = serde_json::to_string(workdirs) {
+        let _ = std::fs::write(&path, json);
+    }
+}
+
+/// Working directory chosen for a channel (None = the daemon's)
+async fn get_channel_workdir(ctx: &serenity::client::Context, channel_id: u64) -> Option<PathBuf> {
+    let data = ctx.data.read().await;
+    let workdir = data.get::<ChannelWorkdirs>()?.read().await.get(&channel_id).cloned();
+    workdir
+}
+
+/// Resolve a `!cwd` argument: `~` expands to the home directory, relative
+/// paths are taken from the home directory, and the result must be an existing directory
+fn resolve_workdir(raw: &str) -> Result<PathBuf, String> {
+    let home = dirs::home_dir().ok_or("Could not find home directory")?;
+    let path = match raw.strip_prefix('~') {
+        Some(rest) => home.join(rest.trim_start_matches('/')),
+        None if Path::new(raw).is_absolute() => PathBuf::from(raw),
+        None => home.join(raw),
+    };
+    match std::fs::canonicalize(&path) {
+        Ok(dir) if dir.is_dir() => Ok(dir),
+        Ok(_) => Err(format!("`{}` is not a directory.", path.display())),
+        Err(e) => Err(format!("`{}`: {}", path.display(), e)),
+    }
+}
+
+/// `!cwd [path|reset]`: show or set the channel's working directory. Changing
+/// it forgets the channel's sessions, since Claude only resumes a session from
+/// the directory it started in.
+async fn set_channel_workdir(ctx: &serenity::client::Context, channel_id: u64, arg: &str) -> String {
+    if arg.is_empty() {
+        return format!("📂 Workdir: `{}`\nUsage: `!cwd <path|reset>`", channel_workdir_label(ctx, channel_id).await);
+    }
+    let workdir = if arg == "reset" {
+        None
+    } else {
+        match resolve_workdir(arg) {
+            Ok(dir) => Some(dir),
+            Err(e) => return format!("❌ {}", e),
+        }
+    };
+    if get_channel_workdir(ctx, channel_id).await == workdir {
+        return format!("📂 Workdir is already `{}`.", channel_workdir_label(ctx, channel_id).await);
+    }
+
+    let data = ctx.data.read().await;
+    if let Some(workdirs) = data.get::<ChannelWorkdirs>() {
+        let mut map = workdirs.write().await;
+        match &workdir {
+            Some(dir) => map.insert(channel_id, dir.clone()),
+            None => map.remove(&channel_id),
+        };
+        save_channel_workdirs(&map);
+    }
+    let mut cleared = 0;
+    if let Some(sessions) = data.get::<SessionStorage>() {
+        let mut map = sessions.write().await;
+        let before = map.len();
+        map.retain(|(_, channel), _| *channel != channel_id);
+        cleared = before - map.len();
+        if cleared > 0 {
+            save_sessions(&map);
+        }
+    }
+    drop(data);
+
+    let mut reply = format!("📂 Workdir set to `{}`.", channel_workdir_label(ctx, channel_id).await);
+    if cleared > 0 {
+        reply.push_str(&format!(" Cleared {} session(s); the next message starts fresh.", cleared));
+    }
+    reply
+}
+
+/// The channel's working directory for display, falling back to the daemon's
+async fn channel_workdir_label(ctx: &serenity::client::Context, channel_id: u64) -> String {
+    match get_channel_workdir(ctx, channel_id).await {
+        Some(dir) => dir.display().to_string(),
+        None => format!("{} (default)", workdir_label()),
+    }
+}
+
+/// Longest accepted `!model` name
+const MAX_MODEL_CHARS: usize = 64;
+
+/// Model aliases and IDs are short and made of letters, digits, `-`, `.`, `_`, `:` and `[]`
+fn is_valid_model_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_MODEL_CHARS
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || "-._:[]".contains(c))
+}
+
+/// Claude model chosen for a channel (None = CLI default)
+async fn get_channel_model(ctx: &serenity::client::Context, channel_id: u64) -> Option<String> {
+    let data = ctx.data.read().await;
+    let model = data.get::<ChannelModels>()?.read().await.get(&channel_id).cloned();
+    model
+}
+
+/// A channel's `!respond-in` setting
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ChannelLanguage {
+    /// None after `!respond-in off`, kept until every pending session has been told
+    language: Option<String>,
+    /// Users whose existing session hasn't heard about the latest change
+    #[serde(default)]
+    pending: HashSet<u64>,
+}
+
+/// Per-channel response language (`!respond-in`)
+struct ChannelLanguages;
+impl TypeMapKey for ChannelLanguages {
+    type Value = Arc<RwLock<HashMap<u64, ChannelLanguage>>>;
+}
+
+/// Longest accepted `!respond-in` language name
+const MAX_LANGUAGE_CHARS: usize = 40;
+
+/// Path for storing channel response languages
+fn channel_languages_file_path() -> std::path::PathBuf {
+    let config_dir = dirs::config_dir()
+        .unwrap_or_else(|| std::path::PathBuf::from("."))
+        .join("neywa");
+    config_dir.join("channel_languages.json")
+}
+
+/// Load channel response languages from file
+fn load_channel_languages() -> HashMap<u64, ChannelLanguage> {
+    let path = channel_languages_file_path();
+    if !path.exists() {
+        return HashMap::new();
+    }
+    match std::fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_default(),
+        Err(_) => HashMap::new(),
+    }
+}
+
+/// Save channel response languages to file
+fn save_channel_languages(languages: &HashMap<u64, ChannelLanguage>) {
+    let path = channel_languages_file_path();
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    if let Ok(json) = serde_json::to_string(languages) {
+        let _ = std::fs::write(&path, json);
+    }
+}
+
+/// A channel's response language, and whether `user_id`'s session still needs
+/// to hear about the latest change (cleared by this call)
+async fn take_channel_language(ctx: &serenity::client::Context, channel_id: u64, user_id: u64) -> (Option<String>, bool) {
+    let data = ctx.data.read().await;
+    let Some(languages) = data.get::<ChannelLanguages>() else {
+        return (None, false);
+    };
+    let mut map = languages.write().await;
+    let Some(setting) = map.get_mut(&channel_id) else {
+        return (None, false);
+    };
+    let language = setting.language.clone();
+    let changed = setting.pending.remove(&user_id);
+    if changed {
+        if setting.language.is_none() && setting.pending.is_empty() {
+            map.remove(&channel_id);
+        }
+        save_channel_languages(&map);
+    }
+    (language, changed)
+}
+
+/// `!status all`: every channel in human mode
+async fn human_mode_summary(ctx: &serenity::client::Context) -> String {
+    let data = ctx.data.read().await;
+    let mut channels: Vec<u64> = match data.get::<HumanModeChannels>() {
+        Some(human) => human.read().await.iter().copied().collect(),
+        None => Vec::new(),
+    };
+    if channels.is_empty() {
+        return "🙋 Human mode: no channels".to_string();
+    }
+    channels.sort_unstable();
+    let mentions: Vec<String> = channels.iter().map(|id| format!("<#{}>", id)).collect();
+    format!("🙋 Human mode: {}", mentions.join(", "))
+}
+
+/// Response language line for `!status` (None if the channel has no setting)
+async fn language_status(ctx: &serenity::client::Context, channel_id: u64) -> Option<String> {
+    let data = ctx.data.read().await;
+    let language = data.get::<ChannelLanguages>()?.read().await.get(&channel_id)?.language.clone()?;
+    Some(format!("🌐 Responds in: {}", language))
+}
+
+/// Path for storing channel autoreset policies
+fn channel_autoreset_file_path() -> std::path::PathBuf {
+    let config_dir = dirs::config_dir()
+        .unwrap_or_else(|| std::path::PathBuf::from("."))
+        .join("neywa");
+    config_dir.join("channel_autoreset.json")
+}
+
+/// Load channel autoreset policies from file
+fn load_channel_autoreset() -> HashMap<u64, AutoReset> {
+    let path = channel_autoreset_file_path();
+    if !path.exists() {
+        return HashMap::new();
+    }
+    match std::fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_default(),
+        Err(_) => HashMap::new(),
+    }
+}
+
+/// Save channel autoreset policies to file
+fn save_channel_autoreset(policies: &HashMap<u64, AutoReset>) {
+    let path = channel_autoreset_file_path();
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    if let Ok(json) = serde_json::to_string(policies) {
+        let _ = std::fs::write(&path, json);
+    }
+}
+
+/// Autoreset line for `!status` (None if the channel has no policy)
+async fn autoreset_status(ctx: &serenity::client::Context, channel_id: u64) -> Option<String> {
+    let data = ctx.data.read().await;
+    let policy = data.get::<ChannelAutoReset>()?.read().await.get(&channel_id).copied()?;
+    Some(format!("♻️ Auto-reset: {}", policy.describe()))
+}
+
+/// Session line for `!status`: its ID, when it was last used and when it expires
+/// (None without a session)
+async fn session_status(ctx: &serenity::client::Context, user_id: u64, channel_id: u64) -> Option<String> {
+    let key = session_key_for(ctx, user_id, channel_id).await;
+    let entry = {
+        let data = ctx.data.read().await;
+        let entry = data.get::<SessionStorage>()?.read().await.get(&key).cloned();
+        entry
+    }?;
+    let short: String = entry.id.chars().take(8).collect();
+    let now = Local::now();
+    let mut line = format!("🗂️ Session: `{}…`", short);
+    if let Some(last) = session_last_used(&entry) {
+        line.push_str(&format!(" · last used {} ago", format_idle(now - last)));
+        if let Some(ttl) = Config::load().unwrap_or_default().session_ttl() {
+            let left = last + ttl - now;
+            if left > chrono::Duration::zero() {
+                line.push_str(&format!(" · expires in {}", format_idle(left)));
+            }
+        }
+    }
+    Some(line)
+}
+
+/// Context line for `!status`: fill level for Claude, thread size for Codex
+/// (None without a session)
+async fn context_status(
+    ctx: &serenity::client::Context,
+    user_id: u64,
+    channel_id: u64,
+    backend: AiBackend,
+) -> Option<String> {
+    let key = session_key_for(ctx, user_id, channel_id).await;
+    let session_id = {
+        let data = ctx.data.read().await;
+        let sessions = data.get::<SessionStorage>()?.read().await;
+        sessions.get(&key)?.id.clone()
+    };
+    match backend {
+        AiBackend::Codex => {
+            let (_, transcript) = transcript::load_transcript(&session_id, backend)?;
+            Some(format!("🧠 Context: {} messages in thread", transcript.message_count))
+        }
+        _ => {
+            let percent = context_fill_percent(&session_id)?;
+            Some(format!("🧠 Context: ~{}% full", percent))
+        }
+    }
+}
+
+/// Active-channel line for `!status` (None when Neywa answers everywhere)
+fn active_channels_status(config: &Config) -> Option<String> {
+    config
+        .active_channels
+        .as_ref()
+        .map(|channels| format!("📍 Whitelist mode: {} active channel(s)", channels.len()))
+}
+
+/// Format an idle duration, e.g. "26h" or "45m"
+fn format_idle(idle: chrono::Duration) -> String {
+    if idle.num_hours() >= 1 {
+        format!("{}h", idle.num_hours())
+    } else {
+        format!("{}m", idle.num_minutes().max(1))
+    }
+}
+
+/// Path for storing channel backend selections
+fn channel_backends_file_path() -> std::path::PathBuf {
+    let config_dir = dirs::config_dir()
+        .unwrap_or_else(|| std::path::PathBuf::from("."))
+        .join("neywa");
+    config_dir.join("channel_backends.json")
+}
+
+/// Load channel backends from file
+pub(crate) fn load_channel_backends() -> HashMap<u64, AiBackend> {
+    let path = channel_backends_file_path();
+    if !path.exists() {
+        return HashMap::new();
+    }
+    match std::fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_default(),
+        Err(_) => HashMap::new(),
+    }
+}
+
+/// Save channel backends to file
+fn save_channel_backends(backends: &HashMap<u64, AiBackend>) {
+    let path = channel_backends_file_path();
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    if let Ok(json) = serde_json::to_string(backends) {
+        let _ = std::fs::write(&path, json);
+    }
+}
+
+/// Helper to get the current backend for a channel
+async fn get_channel_backend(ctx: &serenity::client::Context, channel_id: u64) -> AiBackend {
+    let data = ctx.data.read().await;
+    if let Some(backends) = data.get::<ChannelBackends>() {
+        backends
+            .read()
+            .await
+            .get(&channel_id)
+            .copied()
+            .unwrap_or(AiBackend::Claude)
+    } else {
+        AiBackend::Claude
+    }
+}
+
+/// Commands that act on a server or its channel names, refused in DMs
+const GUILD_ONLY_COMMANDS: &[&str] = &["human", "setup", "logs"];
+
+/// Permission tier required to run a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Tier {
+    /// Anyone on the `allowed_user_ids` allowlist
+    User,
+    /// Users on `admin_user_ids` (destructive/system commands)
+    Admin,
+}
+
+/// A text (`!name`) / slash (`/name`) command and the tier it requires
+struct CommandSpec {
+    name: &'static str,
+    aliases: &'static [&'static str],
+    tier: Tier,
+}
+
+/// Every command and its tier. Text and slash handlers are both gated through this table,
+/// and `!`-prefixed words that aren't listed here are treated as ordinary chat.
+const COMMANDS: &[CommandSpec] = &[
+    CommandSpec { name: "help", aliases: &["도움"], tier: Tier::User },
+    CommandSpec { name: "status", aliases: &["상태"], tier: Tier::User },
+    CommandSpec { name: "queue", aliases: &["대기열"], tier: Tier::User },
+    CommandSpec { name: "plan", aliases: &["계획"], tier: Tier::User },
+    CommandSpec { name: "reset", aliases: &["new", "새대화"], tier: Tier::User },
+    CommandSpec { name: "stop", aliases: &["중단"], tier: Tier::User },
+    CommandSpec { name: "compact", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "slash", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "longtext", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "usage", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "digest", aliases: &[], tier: Tier::Admin },
+    CommandSpec { name: "context", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "prompt", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "history", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "export", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "cost", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "retry", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "undo", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "timing", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "autoreset", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "done-style", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "embed", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "stream", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "longfile", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "timeout", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "threads", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "shared", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "mention", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "wake", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "model", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "cwd", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "respond-in", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "alias", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "moveto", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "copyfrom", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "handoff", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "urgent", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "steer", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "z", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "codex", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "backends", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "backend", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "human", aliases: &["인간"], tier: Tier::User },
+    CommandSpec { name: "get", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "put", aliases: &[], tier: Tier::Admin },
+    CommandSpec { name: "run", aliases: &[], tier: Tier::Admin },
+    CommandSpec { name: "restart", aliases: &["재시작"], tier: Tier::Admin },
+    CommandSpec { name: "activate", aliases: &[], tier: Tier::Admin },
+    CommandSpec { name: "logs", aliases: &["logchannel"], tier: Tier::Admin },
+    CommandSpec { name: "setup", aliases: &[], tier: Tier::Admin },
+    CommandSpec { name: "remind", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "schedule", aliases: &[], tier: Tier::User },
+    CommandSpec { name: "cron", aliases: &[], tier: Tier::Admin },
+    CommandSpec { name: "prune-state", aliases: &[], tier: Tier::Admin },
+    CommandSpec { name: "sessions", aliases: &[], tier: Tier::Admin },
+    CommandSpec { name: "stats", aliases: &[], tier: Tier::Admin },
+    CommandSpec { name: "deactivate", aliases: &[], tier: Tier::Admin },
+    CommandSpec { name: "update", aliases: &[], tier: Tier::Admin },
+];
+
+/// Look up a command by name or alias (without the `!` / `/` prefix)
+fn find_command(name: &str) -> Option<&'static CommandSpec> {
+    COMMANDS
+        .iter()
+        .find(|spec| spec.name == name || spec.aliases.contains(&name))
+}
+
+/// Look up the command a text message invokes, if any
+fn parse_command(content: &str) -> Option<&'static CommandSpec> {
+    let word = content.strip_prefix('!')?.split_whitespace().next()?;
+    find_command(word)
+}
+
+/// Highest tier a user holds (None = not on the allowlist).
+/// With no admins configured, every allowlisted user is treated as admin.
+/// Whether `user_id` may act on (stop, retry, delete) something `author_id`
+/// started: their own, or anyone's for admins. Shared by `!stop`, `!retry` and
+/// `!schedule delete` so they agree when `admin_user_ids` is empty.
+fn may_act_for(author_id: u64, user_id: u64, tier: Option<Tier>) -> bool {
+    author_id == user_id || tier == Some(Tier::Admin)
+}
+
+/// Admin by tier (everyone allowed when `admin_user_ids` is empty)
+fn is_admin_tier(config: &Config, user_id: u64) -> bool {
+    user_tier(config, user_id) == Some(Tier::Admin)
+}
+
+fn user_tier(config: &Config, user_id: u64) -> Option<Tier> {
+    if config.is_admin(user_id) {
+        Some(Tier::Admin)
+    } else if !config.is_allowed_user(user_id) {
+        None
+    } else if config.admin_user_ids.is_empty() {
+        Some(Tier::Admin)
+    } else {
+        Some(Tier::User)
+    }
+}
+
+/// What `/new` / `!new` clears
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ResetScope {
+    /// Only the invoking user's session in this channel
+    Mine,
+    /// Every session keyed to this channel
+    Channel,
+}
+
+impl ResetScope {
+    fn parse(arg: &str) -> Option<Self> {
+        match arg {
+            "" | "mine" => Some(ResetScope::Mine),
+            "channel" => Some(ResetScope::Channel),
+            _ => None,
+        }
+    }
+}
+
+/// Remove every session keyed to a channel, returning how many were removed
+fn clear_channel_sessions(sessions: &mut HashMap<SessionKey, SessionEntry>, channel_id: u64) -> usize {
+    let before = sessions.len();
+    sessions.retain(|(_, cid), _| *cid != channel_id);
+    before - sessions.len()
+}
+
+/// Why a session handoff between channels was refused
+#[derive(Debug, PartialEq, Eq)]
+enum HandoffError {
+    NoSession,
+    SameChannel,
+    /// The target already holds a session for this user; retry with `overwrite`
+    TargetOccupied,
+}
+
+/// Move a session from one key to another (a user's or a `!shared` channel's),
+/// or copy it when `keep_source` is set. An existing session at the target is
+/// only replaced when `overwrite` is set.
+fn migrate_session(
+    sessions: &mut HashMap<SessionKey, SessionEntry>,
+    from: SessionKey,
+    to: SessionKey,
+    keep_source: bool,
+    overwrite: bool,
+) -> Result<(), HandoffError> {
+    if from.1 == to.1 {
+        return Err(HandoffError::SameChannel);
+    }
+    let Some(entry) = sessions.get(&from).cloned() else {
+        return Err(HandoffError::NoSession);
+    };
+    if sessions.contains_key(&to) && !overwrite {
+        return Err(HandoffError::TargetOccupied);
+    }
+    if !keep_source {
+        sessions.remove(&from);
+    }
+    sessions.insert(to, SessionEntry::new(entry.id));
+    Ok(())
+}
+
+/// Codex thread ids only resume under Codex, Claude session ids only under Claude
+fn backends_share_sessions(a: AiBackend, b: AiBackend) -> bool {
+    (a == AiBackend::Codex) == (b == AiBackend::Codex)
+}
+
+/// Resolve a channel argument: `<#id>` mention, raw id, or `#name` in this guild
+async fn resolve_channel_arg(ctx: &serenity::client::Context, msg: &Message, arg: &str) -> Option<ChannelId> {
+    let arg = arg.trim();
+    let id = arg.strip_prefix("<#").and_then(|a| a.strip_suffix('>')).unwrap_or(arg);
+    if let Ok(id) = id.parse::<u64>() {
+        return (id != 0).then(|| ChannelId::new(id));
+    }
+    let name = arg.trim_start_matches('#').to_lowercase();
+    let channels = msg.guild_id?.channels(&ctx.http).await.ok()?;
+    channels
+        .into_iter()
+        .find(|(_, channel)| channel.kind == serenity::model::channel::ChannelType::Text && channel.name == name)
+        .map(|(id, _)| id)
+}
+
+/// Handle `!moveto <channel>` (push this channel's session), `!handoff
+/// <channel> [reset]` (copy it there, keeping it here unless `reset`) and
+/// `!copyfrom <channel>` (pull another channel's session here). Returns the
+/// reply text.
+async fn handoff_session(
+    ctx: &serenity::client::Context,
+    msg: &Message,
+    command: &str,
+    args: &str,
+) -> String {
+    let push = command != "copyfrom";
+    let mut words: Vec<&str> = args.split_whitespace().collect();
+    let mut confirmed = false;
+    let mut reset = command == "moveto";
+    while let Some(&last) = words.last() {
+        match last {
+            "confirm" => confirmed = true,
+            "reset" if command == "handoff" => reset = true,
+            _ => break,
+        }
+        words.pop();
+    }
+    let target = words.join(" ");
+    let target = target.as_str();
+    if target.is_empty() {
+        return if command == "handoff" {
+            "Usage: `!handoff #channel [reset]`".to_string()
+        } else {
+            format!("Usage: `!{} #channel`", command)
+        };
+    }
+    let keep_source = !push || !reset;
+    let Some(other) = resolve_channel_arg(ctx, msg, target).await else {
+        return format!("❌ Couldn't find channel `{}`.", target);
+    };
+    let here = msg.channel_id;
+    let (from, to) = if push { (here, other) } else { (other, here) };
+
+    let from_backend = get_channel_backend(ctx, from.get()).await;
+    let to_backend = get_channel_backend(ctx, to.get()).await;
+    if !backends_share_sessions(from_backend, to_backend) {
+        return format!(
+            "❌ Can't hand off a {} session to a channel in {}.",
+            from_backend.cli_name(),
+            to_backend.status_line()
+        );
+    }
+
+    let data = ctx.data.read().await;
+    let busy = match data.get::<ProcessingChannels>() {
+        Some(processing) => {
+            let processing = processing.read().await;
+            processing.contains_key(&from.get()) || processing.contains_key(&to.get())
+        }
+        None => false,
+    };
+    if busy {
+        return "⏳ One of the channels is busy. Use `!stop` first, then hand off.".to_string();
+    }
+    let Some(sessions) = data.get::<SessionStorage>().cloned() else {
+        return "❌ Session storage unavailable.".to_string();
+    };
+    drop(data);
+
+    let user_id = msg.author.id.get();
+    let from_key = session_key_for(ctx, user_id, from.get()).await;
+    let to_key = session_key_for(ctx, user_id, to.get()).await;
+    {
+        let mut sessions_map = sessions.write().await;
+        match migrate_session(&mut sessions_map, from_key, to_key, keep_source, confirmed) {
+            Ok(()) => save_sessions(&sessions_map),
+            Err(HandoffError::NoSession) => return format!("❌ You have no session in <#{}>.", from),
+            Err(HandoffError::SameChannel) => return "❌ That's this channel.".to_string(),
+            Err(HandoffError::TargetOccupied) => {
+                return format!(
+                    "⚠️ You already have a session in <#{}>; it will be replaced. Send `!{} {} confirm` to continue.",
+                    to, command, args.trim()
+                );
+            }
+        }
+    }
+
+    let note = if keep_source {
+        format!("🔀 {} copied their session here from <#{}>. Pick up where you left off.", msg.author.name, from)
+    } else {
+        format!("🔀 {} moved their session here from <#{}>. Pick up where you left off.", msg.author.name, from)
+    };
+    if !push {
+        return note;
+    }
+    if let Err(e) = to.say(&ctx.http, note).await {
+        tracing::warn!("Failed to post handoff note: {}", e);
+    }
+    if keep_source {
+        format!("➡️ Session copied to <#{}>; it continues here too.", to)
+    } else {
+        format!("➡️ Session moved to <#{}>; your next message here starts fresh.", to)
+    }
+}
+
+/// Reset sessions for `/new` and `!new`, returning the confirmation text.
+/// Clearing a channel that holds other users' sessions requires admin tier.
+async fn reset_sessions(
+    ctx: &serenity::client::Context,
+    user_id: u64,
+    channel_id: u64,
+    scope: ResetScope,
+    tier: Option<Tier>,
+) -> String {
+    let key = session_key_for(ctx, user_id, channel_id).await;
+    let data = ctx.data.read().await;
+    let Some(sessions) = data.get::<SessionStorage>() else {
+        return "Session reset.".to_string();
+    };
+    let mut sessions_map = sessions.write().await;
+
+    match scope {
+        ResetScope::Mine if key.0 == SHARED_SESSION_USER => {
+            let removed = sessions_map.remove(&key).is_some();
+            save_sessions(&sessions_map);
+            if removed {
+                "🔄 Cleared this channel's shared session.".to_string()
+            } else {
+                "🔄 This channel's shared session was already empty; the next message starts fresh.".to_string()
+            }
+        }
+        ResetScope::Mine => {
+            let removed = sessions_map.remove(&key).is_some();
+            save_sessions(&sessions_map);
+            if removed {
+                "🔄 Cleared your session in this channel. Other people's sessions here are unchanged.".to_string()
+            } else {
+                "🔄 You had no session in this channel; your next message starts fresh.".to_string()
+            }
+        }
+        ResetScope::Channel => {
+            let affects_others = sessions_map
+                .keys()
+                .any(|(uid, cid)| *cid == channel_id && *uid != user_id);
+            if affects_others && tier != Some(Tier::Admin) {
+                return "⛔ Clearing other people's sessions needs admin permission. Use `new mine` to clear only yours.".to_string();
+            }
+            let removed = clear_channel_sessions(&mut sessions_map, channel_id);
+            save_sessions(&sessions_map);
+            format!(
+                "🔄 Cleared {} session{} in this channel.",
+                removed,
+                if removed == 1 { "" } else { "s" }
+            )
+        }
+    }
+}
+
+/// `!sessions` listing: every tracked (user, channel) session, grouped by
+/// channel with the most recently used first
+fn sessions_report(sessions: &HashMap<SessionKey, SessionEntry>, now: DateTime<Local>) -> String {
+    if sessions.is_empty() {
+        return "🗂️ No sessions tracked.".to_string();
+    }
+    let mut entries: Vec<(&SessionKey, &SessionEntry)> = sessions.iter().collect();
+    entries.sort_by(|((_, a_channel), a), ((_, b_channel), b)| {
+        a_channel.cmp(b_channel).then(b.last_used.cmp(&a.last_used))
+    });
+    let channels: HashSet<u64> = sessions.keys().map(|(_, channel)| *channel).collect();
+
+    let mut lines = vec![format!(
+        "🗂️ {} session{} in {} channel{}",
+        sessions.len(),
+        if sessions.len() == 1 { "" } else { "s" },
+        channels.len(),
+        if channels.len() == 1 { "" } else { "s" }
+    )];
+    for ((user, channel), entry) in entries {
+        let short: String = entry.id.chars().take(8).collect();
+        let idle = match entry.last_used {
+            Some(last) => format!("last used {} ago", format_idle(now - last)),
+            None => "last use unknown".to_string(),
+        };
+        let owner = if *user == SHARED_SESSION_USER {
+            "shared".to_string()
+        } else {
+            format!("<@{}>", user)
+        };
+        lines.push(format!("• <#{}> {} `{}…` — {}", channel, owner, short, idle));
+    }
+    lines.push("Clear with `!sessions clear` or `!sessions clear #channel`.".to_string());
+    lines.join("\n")
+}
+
+/// Handle `!sessions [clear [#channel]]`. Returns the reply text.
+async fn manage_sessions(ctx: &serenity::client::Context, msg: &Message, args: &str) -> String {
+    let (action, target) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+    let channel = match (action, target.trim()) {
+        ("", _) => None,
+        ("clear", "") => None,
+        ("clear", target) => match resolve_channel_arg(ctx, msg, target).await {
+            Some(channel) => Some(channel),
+            None => return format!("❌ Couldn't find channel `{}`.", target),
+        },
+        _ => return "Usage: `!sessions [clear [#channel]]`".to_string(),
+    };
+
+    let data = ctx.data.read().await;
+    let Some(sessions) = data.get::<SessionStorage>() else {
+        return "❌ Session storage unavailable.".to_string();
+    };
+    if action.is_empty() {
+        return sessions_report(&*sessions.read().await, Local::now());
+    }
+    let mut sessions = sessions.write().await;
+    let (removed, place) = match channel {
+        Some(channel) => (clear_channel_sessions(&mut sessions, channel.get()), format!(" in <#{}>", channel)),
+        None => (std::mem::take(&mut *sessions).len(), String::new()),
+    };
+    save_sessions(&sessions);
+    format!("🔄 Cleared {} session{}{}.", removed, if removed == 1 { "" } else { "s" }, place)
+}
+
+/// Send text that mentions users and channels without pinging anyone
+async fn say_without_pings(http: &serenity::http::Http, channel: ChannelId, text: &str) {
+    for chunk in split_for_discord(text) {
+        let message = CreateMessage::new()
+            .content(chunk)
+            .allowed_mentions(CreateAllowedMentions::new());
+        let _ = channel.send_message(http, message).await;
+    }
+}
+
+/// Tell the operator that the Message Content intent is off: error log, then
+/// the logs channel, or a DM to the application owner if there is none
+async fn warn_missing_message_content(ctx: &serenity::client::Context, reason: &str) {
+    tracing::error!(
+        "Message Content intent appears to be disabled ({}). {}",
+        reason,
+        discord_api::MESSAGE_CONTENT_HELP
+    );
+    let notice = format!(
+        "⚠️ **Neywa can't read messages** — {}.\n{}",
+        reason,
+        discord_api::MESSAGE_CONTENT_HELP
+    );
+
+    if post_to_logs(ctx, &notice).await {
+        return;
+    }
+
+    if let Ok(info) = ctx.http.get_current_application_info().await {
+        if let Some(owner) = info.owner {
+            if let Ok(dm) = owner.create_dm_channel(&ctx.http).await {
+                let _ = dm.say(&ctx.http, &notice).await;
+            }
+        }
+    }
+}
+
+static DIGEST_SCHEDULER_STARTED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Set by `neywa daemon --dry-run`: prompts are echoed back instead of run
+static DRY_RUN: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Route every run to the dry-run stub backend (call before `run_bot`)
+pub fn set_dry_run(enabled: bool) {
+    DRY_RUN.store(enabled, std::sync::atomic::Ordering::SeqCst);
+}
+
+fn is_dry_run() -> bool {
+    DRY_RUN.load(std::sync::atomic::Ordering::SeqCst)
+}
+
+/// Tray status updates, when the daemon runs with a tray
+static TRAY_STATUS: std::sync::OnceLock<std::sync::mpsc::Sender<tray::TrayCommand>> = std::sync::OnceLock::new();
+
+/// Set while a backend CLI is waiting for someone to log in again
+static AUTH_REQUIRED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Send tray status updates to the tray thread (call before `run_bot`)
+pub fn set_tray_sender(sender: std::sync::mpsc::Sender<tray::TrayCommand>) {
+    let _ = TRAY_STATUS.set(sender);
+}
+
+/// Tray status while connected and healthy
+pub fn tray_connected_status() -> &'static str {
+    if is_dry_run() { "🟢 Connected · 🧪 DRY RUN" } else { "🟢 Connected" }
+}
+
+/// Flag (or clear) a backend login problem in the tray. Only changes are sent.
+fn set_auth_required(required: bool) {
+    if AUTH_REQUIRED.swap(required, std::sync::atomic::Ordering::SeqCst) == required {
+        return;
+    }
+    let status = if required { "🟠 Auth required" } else { tray_connected_status() };
+    if let Some(sender) = TRAY_STATUS.get() {
+        let _ = sender.send(tray::TrayCommand::UpdateStatus(status.to_string()));
+    }
+}
+
+/// Banner shown at the top of `!status` while in dry-run mode
+const DRY_RUN_BANNER: &str = "🧪 DRY RUN (prompts are echoed back, no AI runs)";
+
+/// Gateway connection state, for the status page
+static DISCORD_CONNECTED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Status page snapshot from the bot's shared state
+async fn status_snapshot(data: &RwLock<TypeMap>) -> status_page::StatusSnapshot {
+    let data = data.read().await;
+    let processing: HashMap<u64, String> = match data.get::<ProcessingChannels>() {
+        Some(processing) => processing.read().await.iter().map(|(id, run)| (*id, run.describe())).collect(),
+        None => HashMap::new(),
+    };
+    let queued: HashMap<u64, usize> = match data.get::<MessageQueue>() {
+        Some(queue) => queue.read().await.iter().map(|(id, q)| (*id, q.len())).collect(),
+        None => HashMap::new(),
+    };
+    drop(data);
+
+    let mut channel_ids: Vec<u64> = processing.keys().chain(queued.keys()).copied().collect();
+    channel_ids.sort_unstable();
+    channel_ids.dedup();
+    let channels = channel_ids
+        .into_iter()
+        .map(|channel_id| status_page::ChannelStatus {
+            channel_id,
+            processing: processing.get(&channel_id).cloned(),
+            queued: queued.get(&channel_id).copied().unwrap_or(0),
+        })
+        .filter(|channel| channel.processing.is_some() || channel.queued > 0)
+        .collect();
+
+    let today = Local::now().date_naive();
+    let recent_errors = audit::entries_on(today)
+        .into_iter()
+        .rev()
+        .filter(|entry| entry.kind == "run" && entry.outcome == "error")
+        .take(status_page::RECENT_ERRORS)
+        .map(|entry| format!("{} channel {} ({})", entry.ts.format("%H:%M"), entry.channel_id, entry.user_name))
+        .collect();
+
+    status_page::StatusSnapshot {
+        version: VERSION.to_string(),
+        uptime: lifecycle::load_state().map(|state| state.describe()),
+        discord_connected: DISCORD_CONNECTED.load(std::sync::atomic::Ordering::SeqCst),
+        channels,
+        recent_errors,
+        usage_today: digest::summarize(today).describe(),
+    }
+}
+
+/// Detect a channel's mode and kind, from the memo when possible (one HTTP lookup
+/// per channel, two for threads, whose mode comes from the parent's name)
+async fn channel_info_for(ctx: &serenity::client::Context, channel_id: ChannelId) -> ChannelInfo {
+    {
+        let data = ctx.data.read().await;
+        if let Some(types) = data.get::<ChannelTypes>() {
+            if let Some(info) = types.read().await.get(&channel_id.get()) {
+                tracing::debug!("Channel info for {}: {:?} (memo)", channel_id, info);
+                return info.clone();
+            }
+        }
+    }
+
+    let started = Instant::now();
+    let info = match channel_id.to_channel(&ctx.http).await {
+        Ok(channel) => match channel.guild() {
+            Some(guild_channel) => {
+                let parent = match guild_channel.parent_id {
+                    Some(parent_id) if guild_channel.thread_metadata.is_some() => {
+                        parent_id.to_channel(&ctx.http).await.ok().and_then(|c| c.guild())
+                    }
+                    _ => None,
+                };
+                ChannelInfo::of(&guild_channel, parent.as_ref())
+            }
+            None => ChannelInfo { mode: ChannelType::General, kind: ChannelKind::Dm, name: String::new(), guild_id: None },
+        },
+        Err(e) => {
+            // Don't pin a guess; try again on the next message
+            tracing::warn!("Channel lookup for {} failed: {}", channel_id, e);
+            return ChannelInfo { mode: ChannelType::General, kind: ChannelKind::Text, name: String::new(), guild_id: None };
+        }
+    };
+    tracing::debug!(
+        "Channel info for {}: {:?} (http, {}ms)",
+        channel_id,
+        info,
+        started.elapsed().as_millis()
+    );
+
+    let data = ctx.data.read().await;
+    if let Some(types) = data.get::<ChannelTypes>() {
+        types.write().await.insert(channel_id.get(), info.clone());
+    }
+    info
+}
+
+/// Channel info for a guild's channels and active threads, as sent in GUILD_CREATE
+fn guild_channel_infos(
+    channels: &HashMap<ChannelId, serenity::model::channel::GuildChannel>,
+    threads: &[serenity::model::channel::GuildChannel],
+) -> Vec<(u64, ChannelInfo)> {
+    let mut infos: Vec<(u64, ChannelInfo)> = channels
+        .values()
+        .map(|channel| (channel.id.get(), ChannelInfo::of(channel, None)))
+        .collect();
+    for thread in threads {
+        let parent = thread.parent_id.and_then(|id| channels.get(&id));
+        infos.push((thread.id.get(), ChannelInfo::of(thread, parent)));
+    }
+    infos
+}
+
+/// Tell a forum channel, once, that Neywa answers inside its posts
+async fn send_forum_notice(ctx: &serenity::client::Context, channel_id: ChannelId) {
+    let first = {
+        let data = ctx.data.read().await;
+        match data.get::<ForumNotices>() {
+            Some(notices) => notices.write().await.insert(channel_id.get()),
+            None => false,
+        }
+    };
+    if !first {
+        return;
+    }
+    if let Err(e) = channel_id
+        .say(&ctx.http, "💬 This is a forum channel. Please create a post (thread) and talk to Neywa inside it.")
+        .await
+    {
+        tracing::debug!("Couldn't post forum notice in {}: {}", channel_id, e);
+    }
+}
+
+/// The logs channel currently in use
+async fn current_logs_channel(ctx: &serenity::client::Context) -> Option<ChannelId> {
+    let data = ctx.data.read().await;
+    match data.get::<LogsChannel>() {
+        Some(logs) => *logs.read().await,
+        None => None,
+    }
+}
+
+/// The logs channel, or `fallback` when there is none
+async fn logs_channel_or(ctx: &serenity::client::Context, fallback: ChannelId) -> ChannelId {
+    current_logs_channel(ctx).await.unwrap_or(fallback)
+}
+
+/// Discord's "Unknown Channel" error code
+const UNKNOWN_CHANNEL_CODE: isize = 10003;
+
+/// Whether a Discord API error means the channel no longer exists
+fn is_unknown_channel(error: &serenity::Error) -> bool {
+    match error {
+        serenity::Error::Http(serenity::http::HttpError::UnsuccessfulRequest(response)) => {
+            response.error.code == UNKNOWN_CHANNEL_CODE
+        }
+        _ => false,
+    }
+}
+
+/// First channel named like #logs across the bot's guilds
+async fn find_logs_channel_by_name(ctx: &serenity::client::Context) -> Option<ChannelId> {
+    let guilds = match ctx.http.get_guilds(None, None).await {
+        Ok(guilds) => guilds,
+        Err(e) => {
+            tracing::warn!("Failed to list guilds for logs channel discovery: {}", e);
+            return None;
+        }
+    };
+    for guild in guilds {
+        let Ok(channels) = guild.id.channels(&ctx.http).await else {
+            continue;
+        };
+        if let Some((id, channel)) = channels
+            .iter()
+            .find(|(_, channel)| ChannelType::from_name(&channel.name) == ChannelType::Logs)
+        {
+            tracing::info!("Found logs channel: #{}", channel.name);
+            return Some(*id);
+        }
+    }
+    None
+}
+
+/// Standard channels (see `onboarding::STANDARD_CHANNELS`) a guild has no text channel for
+fn missing_standard_channels(
+    channels: &HashMap<ChannelId, serenity::model::channel::GuildChannel>,
+) -> Vec<&'static str> {
+    onboarding::STANDARD_CHANNELS
+        .iter()
+        .copied()
+        .filter(|name| {
+            let wanted = ChannelType::from_name(name);
+            !channels.values().any(|channel| {
+                channel.kind == serenity::model::channel::ChannelType::Text
+                    && ChannelType::from_name(&channel.name) == wanted
+            })
+        })
+        .collect()
+}
+
+/// Post the first-run message in #general (or the system channel), once per guild
+async fn onboard_guild(ctx: &serenity::client::Context, guild_id: serenity::model::id::GuildId) {
+    let channels = match guild_id.channels(&ctx.http).await {
+        Ok(channels) => channels,
+        Err(e) => {
+            tracing::warn!("Onboarding skipped for guild {}: {}", guild_id, e);
+            return;
+        }
+    };
+    let general = channels
+        .values()
+        .find(|channel| {
+            channel.kind == serenity::model::channel::ChannelType::Text
+                && ChannelType::from_name(&channel.name) == ChannelType::General
+        })
+        .map(|channel| channel.id);
+    let target = match general {
+        Some(id) => Some(id),
+        None => guild_id
+            .to_partial_guild(&ctx.http)
+            .await
+            .ok()
+            .and_then(|guild| guild.system_channel_id),
+    };
+    let Some(target) = target else {
+        tracing::info!("Onboarding skipped for guild {}: no #general or system channel", guild_id);
+        return;
+    };
+
+    let text = onboarding::message(&missing_standard_channels(&channels));
+    match target.say(&ctx.http, text).await {
+        Ok(_) => {
+            tracing::info!("Posted onboarding message in guild {}", guild_id);
+            onboarding::mark_onboarded(guild_id.get());
+        }
+        Err(e) => tracing::warn!("Failed to post onboarding message in guild {}: {}", guild_id, e),
+    }
+}
+
+/// Which logs channel to use. `remembered` is the saved channel, if it still exists.
+/// A `!logs here` binding wins; otherwise a channel named like #logs, then the remembered one.
+/// Returns the channel and whether it is still an explicit binding.
+fn choose_logs_channel(remembered: Option<u64>, bound: bool, by_name: impl FnOnce() -> Option<u64>) -> (Option<u64>, bool) {
+    match remembered {
+        Some(id) if bound => (Some(id), true),
+        _ => (by_name().or(remembered), false),
+    }
+}
+
+/// Whether renaming `renamed` to a channel of type `renamed_to` moves the logs
+/// channel. A `!logs here` binding ignores names.
+fn rename_needs_logs_rediscovery(current: Option<u64>, bound: bool, renamed: u64, renamed_to: &ChannelType) -> bool {
+    if bound {
+        return false;
+    }
+    let renamed_away = current == Some(renamed) && *renamed_to != ChannelType::Logs;
+    let renamed_to_logs = current.is_none() && *renamed_to == ChannelType::Logs;
+    renamed_away || renamed_to_logs
+}
+
+/// (Re)discover the logs channel, then remember it in config and for this session
+async fn discover_logs_channel(ctx: &serenity::client::Context) -> Option<ChannelId> {
+    let mut config = Config::load().unwrap_or_default();
+    let remembered = match config.logs_channel_id {
+        Some(id) => match ChannelId::new(id).to_channel(&ctx.http).await {
+            Err(e) if is_unknown_channel(&e) => {
+                tracing::info!("Remembered logs channel {} no longer exists", id);
+                None
+            }
+            // Other errors may be transient; keep the channel
+            _ => Some(id),
+        },
+        None => None,
+    };
+    let by_name = if config.logs_channel_bound && remembered.is_some() {
+        None
+    } else {
+        find_logs_channel_by_name(ctx).await.map(|id| id.get())
+    };
+    let (logs, bound) = choose_logs_channel(remembered, config.logs_channel_bound, || by_name);
+    let logs = logs.map(ChannelId::new);
+    set_logs_channel(ctx, &mut config, logs, bound).await;
+    logs
+}
+
+/// Point logging at `channel` and persist the choice
+async fn set_logs_channel(ctx: &serenity::client::Context, config: &mut Config, channel: Option<ChannelId>, bound: bool) {
+    let id = channel.map(|c| c.get());
+    if config.logs_channel_id != id || config.logs_channel_bound != bound {
+        config.logs_channel_id = id;
+        config.logs_channel_bound = bound;
+        if let Err(e) = config.save() {
+            tracing::warn!("Failed to save logs channel: {}", e);
+        }
+    }
+    let data = ctx.data.read().await;
+    if let Some(logs) = data.get::<LogsChannel>() {
+        *logs.write().await = channel;
+    }
+}
+
+/// Tracked channels missing from the guild listings. Threads and DMs never
+/// appear there, so these still need an API check before being pruned.
+fn unlisted_channels(tracked: &HashSet<u64>, listed: &HashSet<u64>) -> Vec<u64> {
+    let mut unlisted: Vec<u64> = tracked.difference(listed).copied().collect();
+    unlisted.sort_unstable();
+    unlisted
+}
+
+/// Count (and unless `dry_run`, remove) the entries of one per-channel map
+/// that belong to deleted channels
+fn prune_map<K: std::hash::Hash + Eq, V>(
+    map: &mut HashMap<K, V>,
+    deleted: &HashSet<u64>,
+    channel_of: impl Fn(&K) -> u64,
+    dry_run: bool,
+) -> usize {
+    let stale = map.keys().filter(|key| deleted.contains(&channel_of(key))).count();
+    if !dry_run && stale > 0 {
+        map.retain(|key, _| !deleted.contains(&channel_of(key)));
+    }
+    stale
+}
+
+/// What a state prune removed (or would remove), per state map
+#[derive(Debug, Default)]
+struct PruneReport {
+    channels: Vec<u64>,
+    removed: Vec<(&'static str, usize)>,
+}
+
+impl PruneReport {
+    fn is_empty(&self) -> bool {
+        self.removed.is_empty()
+    }
+
+    fn summary(&self, dry_run: bool) -> String {
+        if self.is_empty() {
+            return "🧹 No state left over from deleted channels.".to_string();
+        }
+        let counts: Vec<String> = self.removed.iter().map(|(name, n)| format!("{} {}", n, name)).collect();
+        format!(
+            "🧹 {} state for {} deleted channel(s): {}",
+            if dry_run { "Would prune" } else { "Pruned" },
+            self.channels.len(),
+            counts.join(", ")
+        )
+    }
+}
+
+/// Channels referenced by per-channel state
+async fn tracked_channel_ids(ctx: &serenity::client::Context) -> HashSet<u64> {
+    let data = ctx.data.read().await;
+    let mut ids = HashSet::new();
+    if let Some(sessions) = data.get::<SessionStorage>() {
+        ids.extend(sessions.read().await.keys().map(|(_, channel)| *channel));
+    }
+    if let Some(backends) = data.get::<ChannelBackends>() {
+        ids.extend(backends.read().await.keys());
+    }
+    if let Some(human) = data.get::<HumanModeChannels>() {
+        ids.extend(human.read().await.iter());
+    }
+    if let Some(threads) = data.get::<ThreadChannels>() {
+        ids.extend(threads.read().await.iter());
+    }
+    if let Some(shared) = data.get::<SharedChannels>() {
+        ids.extend(shared.read().await.iter());
+    }
+    if let Some(mention) = data.get::<MentionChannels>() {
+        ids.extend(mention.read().await.iter());
+    }
+    if let Some(policies) = data.get::<ChannelAutoReset>() {
+        ids.extend(policies.read().await.keys());
+    }
+    if let Some(styles) = data.get::<ChannelDoneStyles>() {
+        ids.extend(styles.read().await.keys());
+    }
+    if let Some(models) = data.get::<ChannelModels>() {
+        ids.extend(models.read().await.keys());
+    }
+    if let Some(languages) = data.get::<ChannelLanguages>() {
+        ids.extend(languages.read().await.keys());
+    }
+    if let Some(types) = data.get::<ChannelTypes>() {
+        ids.extend(types.read().await.keys());
+    }
+    if let Some(queue) = data.get::<MessageQueue>() {
+        ids.extend(queue.read().await.keys());
+    }
+    if let Some(held) = data.get::<HeldRequests>() {
+        ids.extend(held.read().await.iter().map(|h| h.channel_id));
+    }
+    if let Some(woken) = data.get::<WokenChannels>() {
+        ids.extend(woken.read().await.keys());
+    }
+    if let Some(timings) = data.get::<LastRunTimings>() {
+        ids.extend(timings.read().await.keys());
+    }
+    ids
+}
+
+/// Tracked channels that Discord says no longer exist. Lookup errors other
+/// than "Unknown Channel" keep the channel.
+async fn find_deleted_channels(ctx: &serenity::client::Context) -> Result<HashSet<u64>> {
+    let tracked = tracked_channel_ids(ctx).await;
+    if tracked.is_empty() {
+        return Ok(HashSet::new());
+    }
+    let mut listed = HashSet::new();
+    for guild in ctx.http.get_guilds(None, None).await? {
+        listed.extend(guild.id.channels(&ctx.http).await?.keys().map(|id| id.get()));
+        if let Ok(threads) = guild.id.get_active_threads(&ctx.http).await {
+            listed.extend(threads.threads.iter().map(|thread| thread.id.get()));
+        }
+    }
+    let mut deleted = HashSet::new();
+    for id in unlisted_channels(&tracked, &listed) {
+        if let Err(e) = ChannelId::new(id).to_channel(&ctx.http).await {
+            if is_unknown_channel(&e) {
+                deleted.insert(id);
+            }
+        }
+    }
+    Ok(deleted)
+}
+
+/// Drop per-channel state for deleted channels and save the persisted maps
+async fn prune_channel_state(ctx: &serenity::client::Context, deleted: &HashSet<u64>, dry_run: bool) -> PruneReport {
+    let mut report = PruneReport { channels: deleted.iter().copied().collect(), removed: Vec::new() };
+    if deleted.is_empty() {
+        return report;
+    }
+    report.channels.sort_unstable();
+    let data = ctx.data.read().await;
+    let mut note = |name: &'static str, n: usize| {
+        if n > 0 {
+            report.removed.push((name, n));
+        }
+    };
+    if let Some(sessions) = data.get::<SessionStorage>() {
+        let mut sessions = sessions.write().await;
+        let n = prune_map(&mut sessions, deleted, |(_, channel)| *channel, dry_run);
+        if n > 0 && !dry_run {
+            save_sessions(&sessions);
+        }
+        note("sessions", n);
+    }
+    if let Some(backends) = data.get::<ChannelBackends>() {
+        let mut backends = backends.write().await;
+        let n = prune_map(&mut backends, deleted, |id| *id, dry_run);
+        if n > 0 && !dry_run {
+            save_channel_backends(&backends);
+        }
+        note("backend modes", n);
+    }
+    if let Some(human) = data.get::<HumanModeChannels>() {
+        let mut human = human.write().await;
+        let n = human.intersection(deleted).count();
+        if n > 0 && !dry_run {
+            human.retain(|id| !deleted.contains(id));
+            save_human_mode(&human);
+        }
+        note("human-mode flags", n);
+    }
+    if let Some(threads) = data.get::<ThreadChannels>() {
+        let mut threads = threads.write().await;
+        let n = threads.intersection(deleted).count();
+        if n > 0 && !dry_run {
+            threads.retain(|id| !deleted.contains(id));
+            save_thread_channels(&threads);
+        }
+        note("thread settings", n);
+    }
+    if let Some(shared) = data.get::<SharedChannels>() {
+        let mut shared = shared.write().await;
+        let n = shared.intersection(deleted).count();
+        if n > 0 && !dry_run {
+            shared.retain(|id| !deleted.contains(id));
+            save_shared_channels(&shared);
+        }
+        note("shared-session settings", n);
+    }
+    if let Some(mention) = data.get::<MentionChannels>() {
+        let mut mention = mention.write().await;
+        let n = mention.intersection(deleted).count();
+        if n > 0 && !dry_run {
+            mention.retain(|id| !deleted.contains(id));
+            save_mention_channels(&mention);
+        }
+        note("mention-only settings", n);
+    }
+    if let Some(policies) = data.get::<ChannelAutoReset>() {
+        let mut policies = policies.write().await;
+        let n = prune_map(&mut policies, deleted, |id| *id, dry_run);
+        if n > 0 && !dry_run {
+            save_channel_autoreset(&policies);
+        }
+        note("autoreset policies", n);
+    }
+    if let Some(styles) = data.get::<ChannelDoneStyles>() {
+        let mut styles = styles.write().await;
+        let n = prune_map(&mut styles, deleted, |id| *id, dry_run);
+        if n > 0 && !dry_run {
+            save_channel_done_styles(&styles);
+        }
+        note("done styles", n);
+    }
+    if let Some(embeds) = data.get::<ChannelEmbeds>() {
+        let mut embeds = embeds.write().await;
+        let n = prune_map(&mut embeds, deleted, |id| *id, dry_run);
+        if n > 0 && !dry_run {
+            save_channel_embeds(&embeds);
+        }
+        note("embed settings", n);
+    }
+    if let Some(settings) = data.get::<ChannelLongFiles>() {
+        let mut settings = settings.write().await;
+        let n = prune_map(&mut settings, deleted, |id| *id, dry_run);
+        if n > 0 && !dry_run {
+            save_channel_long_files(&settings);
+        }
+        note("long-response settings", n);
+    }
+    if let Some(timeouts) = data.get::<ChannelTimeouts>() {
+        let mut timeouts = timeouts.write().await;
+        let n = prune_map(&mut timeouts, deleted, |id| *id, dry_run);
+        if n > 0 && !dry_run {
+            save_channel_timeouts(&timeouts);
+        }
+        note("run timeouts", n);
+    }
+    if let Some(streaming) = data.get::<ChannelStreaming>() {
+        let mut streaming = streaming.write().await;
+        let n = prune_map(&mut streaming, deleted, |id| *id, dry_run);
+        if n > 0 && !dry_run {
+            save_channel_streaming(&streaming);
+        }
+        note("streaming settings", n);
+    }
+    if let Some(models) = data.get::<ChannelModels>() {
+        let mut models = models.write().await;
+        let n = prune_map(&mut models, deleted, |id| *id, dry_run);
+        if n > 0 && !dry_run {
+            save_channel_models(&models);
+        }
+        note("models", n);
+    }
+    if let Some(workdirs) = data.get::<ChannelWorkdirs>() {
+        let mut workdirs = workdirs.write().await;
+        let n = prune_map(&mut workdirs, deleted, |id| *id, dry_run);
+        if n > 0 && !dry_run {
+            save_channel_workdirs(&workdirs);
+        }
+        note("workdirs", n);
+    }
+    if let Some(languages) = data.get::<ChannelLanguages>() {
+        let mut languages = languages.write().await;
+        let n = prune_map(&mut languages, deleted, |id| *id, dry_run);
+        if n > 0 && !dry_run {
+            save_channel_languages(&languages);
+        }
+        note("response languages", n);
+    }
+    if let Some(woken) = data.get::<WokenChannels>() {
+        let mut woken = woken.write().await;
+        let n = prune_map(&mut woken, deleted, |id| *id, dry_run);
+        if n > 0 && !dry_run {
+            save_woken_channels(&woken);
+        }
+        note("wake overrides", n);
+    }
+    if let Some(held) = data.get::<HeldRequests>() {
+        let mut held = held.write().await;
+        let n = held.iter().filter(|h| deleted.contains(&h.channel_id)).count();
+        if n > 0 && !dry_run {
+            held.retain(|h| !deleted.contains(&h.channel_id));
+            save_held_requests(&held);
+        }
+        note("held requests", n);
+    }
+    if let Some(types) = data.get::<ChannelTypes>() {
+        note("channel types", prune_map(&mut *types.write().await, deleted, |id| *id, dry_run));
+    }
+    if let Some(queue) = data.get::<MessageQueue>() {
+        note("queues", prune_map(&mut *queue.write().await, deleted, |id| *id, dry_run));
+    }
+    if let Some(timings) = data.get::<LastRunTimings>() {
+        note("run timings", prune_map(&mut *timings.write().await, deleted, |id| *id, dry_run));
+    }
+    if let Some(costs) = data.get::<CostStorage>() {
+        note("cost totals", costs.write().await.prune(deleted, dry_run));
+    }
+    if let Some(jobs) = data.get::<ScheduleStorage>() {
+        note("scheduled jobs", jobs.write().await.prune(deleted, dry_run));
+    }
+    report
+}
+
+/// Find deleted channels and prune their state, logging what went
+async fn reconcile_channel_state(ctx: &serenity::client::Context, dry_run: bool) -> Result<PruneReport> {
+    let deleted = find_deleted_channels(ctx).await?;
+    let report = prune_channel_state(ctx, &deleted, dry_run).await;
+    if !report.is_empty() && !dry_run {
+        tracing::info!("{} (channels: {:?})", report.summary(false), report.channels);
+    }
+    Ok(report)
+}
+
+/// Post to the logs channel. If it has disappeared, rediscover it and retry once.
+/// Returns false when nothing was posted.
+async fn post_to_logs(ctx: &serenity::client::Context, content: &str) -> bool {
+    let Some(channel) = current_logs_channel(ctx).await else {
+        return false;
+    };
+    match channel.say(&ctx.http, content).await {
+        Ok(_) => true,
+        Err(e) if is_unknown_channel(&e) => {
+            tracing::warn!("Logs channel {} is gone, rediscovering", channel);
+            match discover_logs_channel(ctx).await {
+                Some(channel) => channel.say(&ctx.http, content).await.is_ok(),
+                None => {
+                    // The stored channel is cleared, so this is only logged once
+                    tracing::warn!("No logs channel left; Discord logging is off until one is bound or a #logs channel exists");
+                    false
+                }
+            }
+        }
+        Err(e) => {
+            tracing::warn!("Failed to post to logs channel: {}", e);
+            false
+        }
+    }
+}
+
+/// Set once the startup announcement went out (`ready` fires again on reconnect)
+static STARTUP_ANNOUNCED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Tell the logs channel the daemon is back: version, CLIs, restored state and
+/// the update that was just applied, if any
+async fn announce_startup(ctx: &serenity::client::Context, update: Option<(String, String)>) {
+    let (sessions, backends) = {
+        let data = ctx.data.read().await;
+        let sessions = match data.get::<SessionStorage>() {
+            Some(sessions) => sessions.read().await.len(),
+            None => 0,
+        };
+        let backends = match data.get::<ChannelBackends>() {
+            Some(backends) => backends.read().await.len(),
+            None => 0,
+        };
+        (sessions, backends)
+    };
+    let clis: Vec<String> = claude::check_backends()
+        .await
+        .iter()
+        .map(|check| match &check.status {
+            claude::CliStatus::Version(version) => format!("{} ✅ {}", check.backend.cli_name(), version),
+            _ => format!("{} ❌", check.backend.cli_name()),
+        })
+        .collect();
+    let update = match update {
+        Some((old, new)) if new == VERSION => format!("🎉 Update applied: v{} → v{}", old, new),
+        Some((_, new)) => format!("⚠️ Update expected v{}, running v{}", new, VERSION),
+        None => "No pending update".to_string(),
+    };
+    let mut lines = vec![format!("🟢 **Neywa v{} started**", VERSION)];
+    if is_dry_run() {
+        lines.push(DRY_RUN_BANNER.to_string());
+    }
+    lines.push(format!("🧰 {}", clis.join(" · ")));
+    lines.push(format!(
+        "🗂️ {} session{} loaded · {} channel backend{} restored",
+        sessions,
+        if sessions == 1 { "" } else { "s" },
+        backends,
+        if backends == 1 { "" } else { "s" }
+    ));
+    lines.push(update);
+    post_to_logs(ctx, &lines.join("\n")).await;
+}
+
+/// Post the daily digest at `digest_time`, once per day. Days with no activity post nothing.
+async fn run_digest_scheduler(ctx: serenity::client::Context) {
+    loop {
+        tokio::time::sleep(Duration::from_secs(60)).await;
+
+        let config = Config::load().unwrap_or_default();
+        let Some(at) = config.digest_time.as_deref().and_then(digest::parse_time) else {
+            continue;
+        };
+        let now = Local::now();
+        if !digest::is_due(at, digest::last_digest_date(), now) {
+            continue;
+        }
+        digest::save_last_digest_date(now.date_naive());
+
+        let summary = digest::summarize(now.date_naive());
+        if summary.is_empty() {
+            continue;
+        }
+        match current_logs_channel(&ctx).await {
+            Some(channel) => post_digest(&ctx, channel, &summary).await,
+            None => tracing::warn!("Daily digest skipped: no logs channel"),
+        }
+    }
+}
+
+/// `!schedule [list]` / `!schedule delete <id>`
+async fn manage_schedule(ctx: &serenity::client::Context, msg: &Message, args: &str) -> String {
+    let data = ctx.data.read().await;
+    let Some(jobs) = data.get::<ScheduleStorage>() else {
+        return "❌ Scheduler unavailable.".to_string();
+    };
+    let mut words = args.split_whitespace();
+    match words.next() {
+        None | Some("list") => {
+            let now = Local::now();
+            let store = jobs.read().await;
+            let listed = store.jobs();
+            if listed.is_empty() {
+                return "⏰ Nothing scheduled. Add a job with `!remind in 2h <prompt>`.".to_string();
+            }
+            let mut lines = vec![format!("⏰ **Scheduled jobs** ({})", listed.len())];
+            for job in listed {
+                lines.push(format!(
+                    "`#{}` <#{}> {} — {}: {}",
+                    job.id,
+                    job.channel_id,
+                    job.describe_when(now),
+                    job.user_name,
+                    claude::truncate_str(&job.prompt, 100)
+                ));
+            }
+            lines.join("\n")
+        }
+        Some("delete") | Some("remove") => {
+            let Some(id) = words.next().and_then(|w| w.trim_start_matches('#').parse::<u32>().ok()) else {
+                return "Usage: `!schedule delete <id>`".to_string();
+            };
+            let mut store = jobs.write().await;
+            let Some(job) = store.get(id) else {
+                return format!("❌ No scheduled job #{}.", id);
+            };
+            let config = Config::load().unwrap_or_default();
+            if !may_act_for(job.user_id, msg.author.id.get(), user_tier(&config, msg.author.id.get())) {
+                return format!("⛔ Job #{} belongs to {}; only they or an admin can delete it.", id, job.user_name);
+            }
+            store.remove(id);
+            format!("🗑️ Deleted scheduled job #{}.", id)
+        }
+        Some(_) => "Usage: `!schedule [list]` or `!schedule delete <id>`".to_string(),
+    }
+}
+
+static SCHEDULER_STARTED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Fire due `!remind` jobs. Jobs are persisted, so ones that came due while
+/// the daemon was down fire on the first tick after startup.
+async fn run_scheduler(ctx: serenity::client::Context) {
+    loop {
+        let due = {
+            let data = ctx.data.read().await;
+            match data.get::<ScheduleStorage>() {
+                Some(jobs) => jobs.write().await.take_due(Local::now()),
+                None => Vec::new(),
+            }
+        };
+        for job in due {
+            tokio::spawn(run_scheduled_job(ctx.clone(), job));
+        }
+        tokio::time::sleep(Duration::from_secs(30)).await;
+    }
+}
+
+/// Run a job's prompt with its channel's backend, model and language, and post
+/// the answer there. Each run starts without a session so it doesn't disturb
+/// anyone's conversation.
+async fn run_scheduled_job(ctx: serenity::client::Context, job: Job) {
+    let channel = ChannelId::new(job.channel_id);
+    let backend = get_channel_backend(&ctx, job.channel_id).await;
+    let model = get_channel_model(&ctx, job.channel_id).await;
+    let workdir = get_channel_workdir(&ctx, job.channel_id).await;
+    let language = {
+        let data = ctx.data.read().await;
+        match data.get::<ChannelLanguages>() {
+            Some(languages) => languages.read().await.get(&job.channel_id).and_then(|l| l.language.clone()),
+            None => None,
+        }
+    };
+    let info = channel_info_for(&ctx, channel).await;
+    let (system_prompt, _) = channel_system_prompt(&Config::load().unwrap_or_default(), job.channel_id, &info.name, &info.mode);
+    tracing::info!("Running scheduled job #{} in {}", job.id, job.channel_id);
+
+    let started = Instant::now();
+    let entry = AuditEntry::new("run", "schedule", job.user_id, &job.user_name, job.channel_id, &job.prompt)
+        .backend(backend.cli_name());
+    let (body, outcome) = if is_dry_run() {
+        ("🧪 Dry run: nothing was executed.".to_string(), "ok")
+    } else {
+        let prompt = prompt::build(&prompt::PromptContext {
+            username: &job.user_name,
+            content: &job.prompt,
+            attachments: "",
+            channel_prompt: &system_prompt,
+            first_message: true,
+            language: language.as_deref(),
+            language_changed: false,
+        });
+        // Scheduled runs count against `max_concurrent_tasks` too
+        let _slot = match task_slots(&ctx).await {
+            Some(pool) => pool.acquire(&CancellationToken::new()).await,
+            None => None,
+        };
+        match collect_response(&prompt, None, backend, model.as_deref(), workdir.as_deref(), job.channel_id).await {
+            Ok(Some(text)) if !text.trim().is_empty() => (text, "ok"),
+            Ok(_) => ("(no response)".to_string(), "ok"),
+            Err(e) => (format!("❌ Error: {}", e), "error"),
+        }
+    };
+    audit::record(entry.outcome(outcome).duration(started.elapsed()));
+
+    // The header echoes the prompt, so both go through redaction
+    let redactor = Redactor::from_config(&Config::load().unwrap_or_default());
+    let header = format!(
+        "⏰ **Scheduled #{}** ({}): {}",
+        job.id,
+        job.user_name,
+        claude::truncate_str(&redactor.redact(&job.prompt), 200)
+    );
+    say_without_pings(&ctx.http, channel, &format!("{}\n\n{}", header, redactor.redact(&body))).await;
+}
+
+/// `!remind [#channel] in <delay> <prompt>` / `!remind [#channel] cron <m h dom mon dow> <prompt>`
+async fn add_reminder(ctx: &serenity::client::Context, msg: &Message, args: &str) -> String {
+    const USAGE: &str = "Usage: `!remind [#channel] in <30m|2h|1d> <prompt>` or `!remind [#channel] cron <m h dom mon dow> <prompt>`";
+    let (channel, args) = match args.split_once(char::is_whitespace) {
+        Some((first, rest)) if first.starts_with('#') || first.starts_with("<#") => {
+            match resolve_channel_arg(ctx, msg, first).await {
+                Some(channel) => (channel, rest.trim()),
+                None => return format!("❌ Channel `{}` not found.", first),
+            }
+        }
+        _ => (msg.channel_id, args),
+    };
+    if args.is_empty() {
+        return USAGE.to_string();
+    }
+    let (trigger, next_run, prompt) = match schedule::parse_request(args, Local::now()) {
+        Ok(request) => request,
+        Err(e) => return format!("❌ {}\n{}", e, USAGE),
+    };
+    let data = ctx.data.read().await;
+    let Some(jobs) = data.get::<ScheduleStorage>() else {
+        return "❌ Scheduler unavailable.".to_string();
+    };
+    let job = jobs.write().await.add(trigger, next_run, &prompt, channel.get(), msg.author.id.get(), &msg.author.name);
+    format!(
+        "⏰ Scheduled #{} in <#{}>, {}. `!schedule delete {}` cancels it.",
+        job.id,
+        job.channel_id,
+        job.describe_when(Local::now()),
+        job.id
+    )
+}
+
+/// `!cron migrate <numbers|all> [confirm]`: turn crontab `neywa run` jobs into
+/// schedule jobs and comment them out of the crontab. Jobs without a
+/// `--channel` run in the channel the command was sent from.
+async fn migrate_cron_jobs(ctx: &serenity::client::Context, msg: &Message, entries: &[cron::CronEntry], args: &str) -> String {
+    let (selection, confirmed) = match args.trim().strip_suffix("confirm") {
+        Some(selection) => (selection.trim(), true),
+        None => (args.trim(), false),
+    };
+    let selected = match cron::select(entries, selection) {
+        Ok(selected) => selected,
+        Err(e) => return format!("❌ {}", e),
+    };
+
+    let mut lines = Vec::new();
+    let mut planned = Vec::new();
+    for entry in &selected {
+        let plan = match cron::plan_migration(entry) {
+            Ok(migration) => match migration.channel.as_deref() {
+                Some(channel) => match resolve_channel_arg(ctx, msg, channel).await {
+                    Some(id) => Ok((migration, id)),
+                    None => Err(format!("Channel `{}` not found.", channel)),
+                },
+                None => Ok((migration, msg.channel_id)),
+            },
+            Err(e) => Err(e),
+        };
+        let preview = plan.as_ref().map(|(m, id)| (m.clone(), format!("<#{}>", id))).map_err(Clone::clone);
+        lines.push(cron::describe_migration(entry, &preview));
+        if let Ok((migration, id)) = plan {
+            planned.push((migration, id.get()));
+        }
+    }
+    if planned.is_empty() {
+        return format!("{}\n❌ Nothing to migrate.", lines.join("\n"));
+    }
+    if !confirmed {
+        return format!(
+            "⚠️ This moves into `!schedule` and comments out of the crontab:\n{}\nSend `!cron migrate {} confirm` to continue.",
+            lines.join("\n"),
+            selection
+        );
+    }
+
+    let Some(store) = ctx.data.read().await.get::<ScheduleStorage>().cloned() else {
+        return "❌ Scheduler unavailable.".to_string();
+    };
+    let (user_id, user_name) = (msg.author.id.get(), msg.author.name.clone());
+    let result = tokio::task::spawn_blocking(move || {
+        let mut store = store.blocking_write();
+        let now = Local::now();
+        cron::migrate(&planned, |migration, channel_id| {
+            let next_run = schedule::CronSpec::parse(&migration.cron).and_then(|spec| spec.next_after(now)).unwrap_or(now);
+            store
+                .add(schedule::Trigger::Cron(migration.cron.clone()), next_run, &migration.prompt, channel_id, user_id, &user_name)
+                .id
+        })
+    })
+    .await;
+    match result {
+        Ok(Ok((backup, ids))) => {
+            let ids: Vec<String> = ids.iter().map(|id| format!("#{}", id)).collect();
+            format!(
+                "⏰ Moved to schedule {}. The crontab lines are commented out; the original is saved to `{}`.",
+                ids.join(", "),
+                backup.display()
+            )
+        }
+        Ok(Err(e)) => format!("❌ {}", e),
+        Err(e) => format!("❌ {}", e),
+    }
+}
+
+static SESSION_CLEANUP_STARTED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// How often expired sessions are cleaned up
+const SESSION_CLEANUP_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// When a session was last used. Entries saved before last-used times were
+/// tracked fall back to their transcript's modification time.
+fn session_last_used(entry: &SessionEntry) -> Option<DateTime<Local>> {
+    entry.last_used.or_else(|| {
+        let path = transcript::find_claude_session_file(&entry.id)?;
+        let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+        Some(DateTime::<Local>::from(modified))
+    })
+}
+
+/// Forget sessions unused for `session_ttl_days`, and delete their Claude Code
+/// transcripts unless another entry (e.g. after `!copyfrom`) still uses them
+async fn cleanup_expired_sessions(ctx: &serenity::client::Context) {
+    let Some(ttl) = Config::load().unwrap_or_default().session_ttl() else {
+        return;
+    };
+    let data = ctx.data.read().await;
+    let Some(sessions) = data.get::<SessionStorage>() else {
+        return;
+    };
+    let mut sessions = sessions.write().await;
+    let now = Local::now();
+    let expired: Vec<SessionKey> = sessions
+        .iter()
+        .filter(|(_, entry)| session_last_used(entry).is_some_and(|last| now - last >= ttl))
+        .map(|(key, _)| *key)
+        .collect();
+    if expired.is_empty() {
+        return;
+    }
+    let removed: Vec<SessionEntry> = expired.iter().filter_map(|key| sessions.remove(key)).collect();
+    save_sessions(&sessions);
+    let still_used: HashSet<&str> = sessions.values().map(|entry| entry.id.as_str()).collect();
+    let mut deleted = 0;
+    for entry in &removed {
+        if still_used.contains(entry.id.as_str()) {
+            continue;
+        }
+        if let Some(path) = transcript::find_claude_session_file(&entry.id) {
+            match std::fs::remove_file(&path) {
+                Ok(()) => deleted += 1,
+                Err(e) => tracing::warn!("Failed to delete expired session file {:?}: {}", path, e),
+            }
+        }
+    }
+    tracing::info!("Session cleanup: forgot {} expired session(s), deleted {} transcript(s)", removed.len(), deleted);
+}
+
+/// Clean up expired sessions every hour
+async fn run_session_cleanup(ctx: serenity::client::Context) {
+    loop {
+        cleanup_expired_sessions(&ctx).await;
+        tokio::time::sleep(SESSION_CLEANUP_INTERVAL).await;
+    }
+}
+
+static ATTACHMENT_CLEANUP_STARTED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// How often old attachment downloads are cleaned up
+const ATTACHMENT_CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Delete attachment downloads past `attachment_retention_hours`, and the
+/// oldest ones beyond `attachment_cache_mb`, every hour
+async fn run_attachment_cleanup() {
+    loop {
+        let config = Config::load().unwrap_or_default();
+        let (retention, cap) = (config.attachment_retention(), config.attachment_cache_bytes());
+        match tokio::task::spawn_blocking(move || attachment::clean(retention, cap)).await {
+            Ok(removed) if removed.files > 0 => tracing::info!(
+                "Attachment cleanup: removed {} file(s), freed {}",
+                removed.files,
+                attachment::format_bytes(removed.bytes)
+            ),
+            Ok(_) => {}
+            Err(e) => tracing::warn!("Attachment cleanup failed: {}", e),
+        }
+        tokio::time::sleep(ATTACHMENT_CLEANUP_INTERVAL).await;
+    }
+}
+
+static DISK_MONITOR_STARTED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Check free disk space every few minutes, warning #logs once when it runs
+/// low and again only after it has recovered in between
+async fn run_disk_monitor(ctx: serenity::client::Context) {
+    let mut warned = false;
+    loop {
+        let threshold = Config::load().unwrap_or_default().min_free_disk_bytes();
+        match disk::low_space(threshold) {
+            Some(free) if !warned => {
+                tracing::warn!("Low disk space: {} bytes free", free);
+                warned = post_to_logs(
+                    &ctx,
+                    &format!("{} — Claude Code runs and attachment downloads may fail until space is freed.", disk::banner(free)),
+                )
+                .await;
+            }
+            Some(_) => {}
+            None => warned = false,
+        }
+        tokio::time::sleep(Duration::from_secs(300)).await;
+    }
+}
+
+/// Post a digest embed, with an AI-written summary when `digest_ai_summary` is on
+async fn post_digest(ctx: &serenity::client::Context, channel: ChannelId, summary: &DaySummary) {
+    let stats = summary.describe();
+    let mut description = String::new();
+    if Config::load().unwrap_or_default().digest_ai_summary {
+        let prompt = format!(
+            "Write exactly three short bullet points summarizing this day of bot activity for the operator. Plain text, no preamble.\n\n{}",
+            stats
+        );
+        match claude::run(&prompt, false, None, None).await {
+            Ok(text) if !text.trim().is_empty() => {
+                description.push_str(text.trim());
+                description.push_str("\n\n");
+            }
+            Ok(_) => {}
+            Err(e) => tracing::warn!("AI digest failed: {}", e),
+        }
+    }
+    description.push_str(&stats);
+
+    let embed = CreateEmbed::new()
+        .title(format!("📊 Daily digest — {}", summary.date.format("%Y-%m-%d")))
+        .description(claude::truncate_str(&description, 4000));
+    if let Err(e) = channel.send_message(&ctx.http, CreateMessage::new().embed(embed)).await {
+        tracing::warn!("Failed to post daily digest: {}", e);
+    }
+}
+
+/// Record a refused command in the audit log and logs channel
+async fn log_unauthorized(ctx: &serenity::client::Context, user: &str, user_id: u64, channel_id: u64, command: &str) {
+    tracing::warn!("Unauthorized command {} from {} ({})", command, user, user_id);
+    audit::record(AuditEntry::new("command", command, user_id, user, channel_id, command).outcome("denied"));
+    post_to_logs(ctx, &format!("⛔ **{}** (`{}`) tried `{}` without permission", user, user_id, command)).await;
+}
+
+/// Count a request against the user's daily quota.
+/// Replies with a refusal and returns false when the user is over their limit.
+/// Whether a user skips rate limits and quotas: only the listed `admin_user_ids`.
+/// Unlike `user_tier`, an empty admin list doesn't make everyone an admin here,
+/// or the limits would never apply with the default config.
+fn quota_exempt(config: &Config, user_id: u64) -> bool {
+    config.is_admin(user_id)
+}
+
+async fn check_quota(ctx: &serenity::client::Context, msg: &Message) -> bool {
+    let config = Config::load().unwrap_or_default();
+    let user_id = msg.author.id.get();
+    if quota_exempt(&config, user_id) {
+        return true;
+    }
+
+    // The hourly rate limit comes first so a refused message doesn't use up daily quota
+    if let Some(limit) = config.hourly_message_limit() {
+        let verdict = {
+            let data = ctx.data.read().await;
+            match data.get::<RateLimitStorage>() {
+                Some(limiter) => limiter.write().await.try_acquire(user_id, limit, Instant::now()),
+                None => Ok(()),
+            }
+        };
+        if let Err(wait) = verdict {
+            let minutes = wait.as_secs().div_ceil(60).max(1);
+            let _ = msg.channel_id.say(&ctx.http, format!(
+                "🙏 Sorry {}, that's more than {} messages in an hour. You can send again in {} minute{}.",
+                msg.author.name, limit, minutes, if minutes == 1 { "" } else { "s" }
+            )).await;
+            return false;
+        }
+    }
+
+    let result = {
+        let data = ctx.data.read().await;
+        match data.get::<UsageStorage>() {
+            Some(store) => store.write().await.try_consume(user_id, &config),
+            None => Ok(None),
+        }
+    };
+
+    match result {
+        Ok(_) => true,
+        Err(exceeded) => {
+            let reason = match exceeded {
+                QuotaExceeded::Requests { limit } => {
+                    format!("you've used all {} of today's requests", limit)
+                }
+                QuotaExceeded::Cost { limit, spent } => {
+                    format!("you've reached today's ${:.2} spending limit (${:.2} used)", limit, spent)
+                }
+            };
+            let _ = msg.channel_id.say(&ctx.http, format!(
+                "🙏 Sorry {}, {}. Your quota resets at {}.",
+                msg.author.name, reason, usage::format_reset()
+            )).await;
+            false
+        }
+    }
+}
+
+/// How a single AI run ended
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RunOutcome {
+    Completed,
+    Cancelled,
+    Failed,
+    TimedOut,
+}
+
+impl RunOutcome {
+    fn as_str(&self) -> &'static str {
+        match self {
+            RunOutcome::Completed => "ok",
+            RunOutcome::Cancelled => "cancelled",
+            RunOutcome::Failed => "error",
+            RunOutcome::TimedOut => "timeout",
+        }
+    }
+}
+
+struct Handler;
+
+impl Handler {
+    async fn process_message(
+        ctx: &serenity::client::Context,
+        queued: QueuedMessage,
+        cancel_token: CancellationToken,
+    ) {
+        // Wait for a free run slot; the wait counts as queue time
+        let slot = match task_slots(ctx).await {
+            Some(pool) => match pool.semaphore.clone().try_acquire_owned() {
+                Ok(permit) => Some(permit),
+                Err(_) => Self::wait_for_slot(ctx, &queued, &pool, &cancel_token).await,
+            },
+            None => None,
+        };
+        let started = Instant::now();
+        let mut timings = PhaseTimings::default();
+        timings.record(Phase::QueueWait, queued.enqueued_at.elapsed());
+        let backend = get_channel_backend(ctx, queued.msg.channel_id.get()).await;
+        let entry = AuditEntry::new(
+            "run",
+            if queued.is_plan_mode { "plan" } else { "chat" },
+            queued.msg.author.id.get(),
+            &queued.msg.author.name,
+            queued.msg.channel_id.get(),
+            &queued.content,
+        )
+        .backend(backend.cli_name());
+
+        let channel_id = queued.msg.channel_id.get();
+        let active = {
+            let data = ctx.data.read().await;
+            data.get::<ActiveMessages>().cloned()
+        };
+        if let Some(active) = &active {
+            active.write().await.insert(channel_id, (queued.clone(), started));
+        }
+
+        let span = tracing::info_span!(
+            "process_message",
+            channel = channel_id,
+            user = queued.msg.author.id.get(),
+            backend = backend.cli_name()
+        );
+        let mut usage: Option<RunUsage> = None;
+        let mut trace = activity::RunTrace::default();
+        let timeout = channel_run_timeout(ctx, channel_id).await;
+        let outcome = if cancel_token.is_cancelled() {
+            RunOutcome::Cancelled
+        } else {
+            Self::run_message(ctx, &queued, cancel_token, timeout, &mut timings, &mut usage, &mut trace)
+                .instrument(span)
+                .await
+        };
+        drop(slot);
+
+        if let Some(active) = &active {
+            active.write().await.remove(&channel_id);
+        }
+
+        tracing::info!(
+            channel = channel_id,
+            user = queued.msg.author.id.get(),
+            backend = backend.cli_name(),
+            outcome = outcome.as_str(),
+            total_ms = started.elapsed().as_millis() as u64,
+            "Run finished: {}",
+            timings.summary()
+        );
+        {
+            let data = ctx.data.read().await;
+            if let Some(metrics) = data.get::<MetricsStorage>() {
+                let mut metrics = metrics.write().await;
+                if matches!(outcome, RunOutcome::Completed) {
+                    metrics.record_run(channel_id, started.elapsed());
+                }
+                metrics.record_phases(&timings);
+            }
+            if let Some(last) = data.get::<LastRunTimings>() {
+                last.write().await.insert(channel_id, (timings, started.elapsed(), outcome.as_str()));
+            }
+            if let Some(last) = data.get::<LastRequests>() {
+                last.write().await.insert(channel_id, queued.clone());
+            }
+        }
+
+        stats::record(stats::RunRecord {
+            ts: Local::now(),
+            channel_id,
+            user_id: queued.msg.author.id.get(),
+            user_name: queued.msg.author.name.clone(),
+            backend: backend.cli_name().to_string(),
+            outcome: outcome.as_str().to_string(),
+            duration_ms: started.elapsed().as_millis() as u64,
+            input_tokens: usage.map(|u| u.input_tokens),
+            output_tokens: usage.map(|u| u.output_tokens),
+            cost_usd: usage.map(|u| u.cost_usd),
+        });
+        if let Some(error) = &trace.error {
+            report_run_error(ctx, &queued, backend, error).await;
+        }
+        activity::record(activity::ActivityRecord {
+            ts: Local::now(),
+            user_id: queued.msg.author.id.get(),
+            user_name: queued.msg.author.name.clone(),
+            channel_id,
+            backend: backend.cli_name().to_string(),
+            kind: if queued.is_plan_mode { "plan" } else { "chat" }.to_string(),
+            prompt: queued.content.clone(),
+            tools: trace.tools,
+            response: trace.response,
+            error: trace.error,
+            outcome: outcome.as_str().to_string(),
+            cancelled: matches!(outcome, RunOutcome::Cancelled),
+            duration_ms: started.elapsed().as_millis() as u64,
+        });
+        audit::record(entry.outcome(outcome.as_str()).duration(started.elapsed()));
+    }
+
+    /// Apply a `!steer` correction to the channel's running request: stop it and
+    /// queue the same request, correction first, at the front so it resumes the
+    /// session right away. Returns false when nothing is running.
+    async fn steer(ctx: &serenity::client::Context, msg: &Message, correction: &str) -> bool {
+        let channel_id = msg.channel_id.get();
+        let user_id = msg.author.id.get();
+        let tier = user_tier(&Config::load().unwrap_or_default(), user_id).unwrap_or(Tier::User);
+        let data = ctx.data.read().await;
+        let original = match data.get::<ActiveMessages>() {
+            Some(active) => active.read().await.get(&channel_id).map(|(queued, _)| queued.clone()),
+            None => None,
+        };
+        let (Some(original), Some(processing)) = (original, data.get::<ProcessingChannels>()) else {
+            return false;
+        };
+        {
+            let mut runs = processing.write().await;
+            let Some(run) = runs.get_mut(&channel_id) else {
+                return false;
+            };
+            if !run.may_cancel(user_id, tier) {
+                let owner = run.author_name.clone();
+                drop(runs);
+                drop(data);
+                let _ = msg
+                    .channel_id
+                    .say(&ctx.http, format!("⚠️ The current run is {}'s request; only they or an admin can steer it.", owner))
+                    .await;
+                return true;
+            }
+            run.steered = true;
+            run.cancel_token.cancel();
+        }
+        let steered = QueuedMessage {
+            content: steer_content(correction, &original.content),
+            priority: true,
+            notice: None,
+            enqueued_at: Instant::now(),
+            ..original
+        };
+        if let Some(queue) = data.get::<MessageQueue>() {
+            queue.write().await.entry(channel_id).or_default().push_front(steered);
+        }
+        drop(data);
+        refresh_queue_notices(ctx, channel_id, 1).await;
+        tracing::info!("Steering the run in channel {}", channel_id);
+        let _ = msg.react(&ctx.http, '🧭').await;
+        true
+    }
+
+    /// Wait for a run slot behind a "waiting for a free slot" notice.
+    /// None if the run is cancelled first.
+    async fn wait_for_slot(
+        ctx: &serenity::client::Context,
+        queued: &QueuedMessage,
+        pool: &SlotPool,
+        cancel_token: &CancellationToken,
+    ) -> Option<tokio::sync::OwnedSemaphorePermit> {
+        let notice = queued
+            .msg
+            .reply(
+                &ctx.http,
+                format!(
+                    "⏳ Waiting for a free slot ({}/{} runs in progress across channels). `!stop` cancels.",
+                    pool.in_use(),
+                    pool.limit
+                ),
+            )
+            .await
+            .ok();
+        let permit = pool.acquire(cancel_token).await;
+        if let Some(notice) = notice {
+            let _ = notice.delete(&ctx.http).await;
+        }
+        permit
+    }
+
+    /// Run one queued message through the AI backend and post the response.
+    /// A run still going after `timeout` is stopped; its session is kept.
+    async fn run_message(
+        ctx: &serenity::client::Context,
+        queued: &QueuedMessage,
+        cancel_token: CancellationToken,
+        timeout: Option<Duration>,
+        timings: &mut PhaseTimings,
+        usage_out: &mut Option<RunUsage>,
+        trace: &mut activity::RunTrace,
+    ) -> RunOutcome {
+        let run_started = Instant::now();
+        let deadline = timeout.map(|timeout| tokio::time::Instant::now() + timeout);
+        let msg = &queued.msg;
+        let content = &queued.content;
+        let channel_type = &queued.channel_type;
+
+        let user_id = msg.author.id.get();
+        let channel_id = msg.channel_id.get();
+        let session_key = session_key_for(ctx, user_id, channel_id).await;
+        // Quiet hours send the completion as a silent message (no push notification)
+        // unless the request was `!urgent` or the channel was woken with `!wake`
+        let config = Config::load().unwrap_or_default();
+        let user_mention = format!("{} ", msg.author.mention());
+        let silent = !queued.urgent && quiet_until(ctx, channel_id).await.is_some();
+
+        // Get existing session, unless the channel's autoreset policy or the session TTL says it went stale
+        let (mut existing_session, idle_reset) = {
+            let data = ctx.data.read().await;
+            let entry = match data.get::<SessionStorage>() {
+                Some(sessions) => sessions.read().await.get(&session_key).cloned(),
+                None => None,
+            };
+            let policy = match data.get::<ChannelAutoReset>() {
+                Some(policies) => policies.read().await.get(&channel_id).copied(),
+                None => None,
+            };
+            let now = Local::now();
+            let ttl = config.session_ttl();
+            let stale = |t: &DateTime<Local>| {
+                policy.is_some_and(|policy| policy.is_expired(*t, now)) || ttl.is_some_and(|ttl| now - *t >= ttl)
+            };
+            match entry {
+                Some(entry) => match entry.last_used.filter(stale) {
+                    Some(last_used) => (None, Some(now - last_used)),
+                    None => (Some(entry.id), None),
+                },
+                None => (None, None),
+            }
+        };
+        if let Some(idle) = idle_reset {
+            tracing::info!("Autoreset: starting fresh session for {:?}", session_key);
+            let _ = msg
+                .channel_id
+                .say(&ctx.http, format!("🆕 Started a fresh session — previous one was idle {}", format_idle(idle)))
+                .await;
+        }
+
+        // Send initial "processing" message
+        let stop_button = CreateButton::new(STOP_BUTTON_ID)
+            .label("Stop")
+            .emoji('🛑')
+            .style(ButtonStyle::Danger);
+        let status = CreateMessage::new()
+            .content("⏳ Processing...")
+            .components(vec![CreateActionRow::Buttons(vec![stop_button])]);
+        let status_msg = match msg.channel_id.send_message(&ctx.http, status).await {
+            Ok(m) => m,
+            Err(e) => {
+                tracing::error!("Failed to send processing message: {}", e);
+                return RunOutcome::Failed;
+            }
+        };
+        {
+            let data = ctx.data.read().await;
+            if let Some(processing) = data.get::<ProcessingChannels>() {
+                if let Some(run) = processing.write().await.get_mut(&channel_id) {
+                    run.status_message = Some(status_msg.id);
+                }
+            }
+        }
+
+        // Download attachments now rather than at enqueue time, so queueing stays instant
+        let config = Config::load().unwrap_or_default();
+        if !queued.attachments.is_empty() {
+            let needed: u64 = queued
+                .attachments
+                .iter()
+                .map(|a| a.size as u64)
+                .filter(|size| *size <= config.max_attachment_bytes)
+                .sum();
+            let threshold = config.min_free_disk_bytes();
+            if let Err(e) = disk::ensure_headroom(&std::env::temp_dir(), needed, threshold) {
+                let _ = status_msg.delete(&ctx.http).await;
+                let _ = msg.channel_id.say(&ctx.http, format!("❌ Can't download attachments. {}", e)).await;
+                return RunOutcome::Failed;
+            }
+        }
+        let download_started = Instant::now();
+        let downloads = attachment::download_all(&queued.attachments, config.max_attachment_bytes, &cancel_token)
+            .instrument(tracing::debug_span!("attachments", count = queued.attachments.len()))
+            .await;
+        if !queued.attachments.is_empty() {
+            timings.record_since(Phase::Attachments, download_started);
+        }
+        let downloads = match downloads {
+            Some(downloads) => downloads,
+            None => {
+                let _ = status_msg.delete(&ctx.http).await;
+                let _ = msg.channel_id.say(&ctx.http, "🛑 Cancelled.").await;
+                return RunOutcome::Cancelled;
+            }
+        };
+        if !downloads.failed.is_empty() {
+            let failed: Vec<String> = downloads
+                .failed
+                .iter()
+                .map(|(name, e)| format!("`{}` ({})", name, e))
+                .collect();
+            let _ = msg
+                .channel_id
+                .say(&ctx.http, format!("⚠️ Couldn't download: {}", failed.join(", ")))
+                .await;
+        }
+        // Voice messages go in as text; Claude can't listen to the audio file
+        let (audio, files): (Vec<_>, Vec<_>) = downloads.files.iter().cloned().partition(|a| a.audio);
+        let attachments = &files;
+        let mut transcripts = Vec::new();
+        if !audio.is_empty() {
+            match transcribe::find(Config::load().unwrap_or_default().transcribe_command.as_deref()) {
+                Some(transcriber) => {
+                    for file in &audio {
+                        match transcribe::transcribe(&transcriber, Path::new(&file.path)).await {
+                            Ok(text) => transcripts.push(text),
+                            Err(e) => {
+                                tracing::warn!("Transcription of {} failed: {}", file.path, e);
+                                let _ = msg.channel_id.say(&ctx.http, format!("⚠️ Couldn't transcribe a voice message: {}", e)).await;
+                            }
+                        }
+                    }
+                }
+                None => {
+                    let _ = msg.channel_id.say(&ctx.http, transcribe::INSTALL_HINT).await;
+                }
+            }
+            // Nothing left to send if the message was only audio that couldn't be transcribed
+            if transcripts.is_empty() && content.is_empty() && attachments.is_empty() {
+                let _ = status_msg.delete(&ctx.http).await;
+                return RunOutcome::Failed;
+            }
+        }
+
+        // Build prompt with system context, username, and attachments
+        let channel_name = channel_info_for(ctx, msg.channel_id).await.name;
+        let (system_prompt, _) = channel_system_prompt(&Config::load().unwrap_or_default(), channel_id, &channel_name, channel_type);
+        let username = &msg.author.name;
+        // Get the AI backend for this channel. Codex takes images as `--image`
+        // arguments; Claude opens them with its Read tool.
+        let backend = get_channel_backend(ctx, channel_id).await;
+        let inline_images = backend == AiBackend::Codex && !queued.is_plan_mode;
+        let image_paths = if inline_images { attachment::image_paths(attachments) } else { Vec::new() };
+        let mut attachment_info = attachment::prompt_block(attachments, &downloads.failed, inline_images);
+
+        // Pull in messages linked from other channels
+        let linked = links::resolve(&ctx.http, content, msg.guild_id.map(|g| g.get())).await;
+        if !linked.failed.is_empty() {
+            let failed: Vec<String> = linked
+                .failed
+                .iter()
+                .map(|(url, e)| format!("<{}> ({})", url, e))
+                .collect();
+            let _ = msg
+                .channel_id
+                .say(&ctx.http, format!("⚠️ Couldn't read linked message: {}", failed.join(", ")))
+                .await;
+        }
+        attachment_info.push_str(&linked.block);
+
+        let spoken = transcripts.join("\n\n");
+        let with_transcript;
+        let user_content = if !spoken.is_empty() {
+            with_transcript = if content.is_empty() {
+                spoken
+            } else {
+                format!("{}\n\n[Voice message transcript]: {}", content, spoken)
+            };
+            &with_transcript
+        } else if content.is_empty() {
+            prompt::default_content(!attachments.is_empty() && attachments.iter().all(|a| a.image.is_some()))
+        } else {
+            content
+        };
+
+        let model = get_channel_model(ctx, channel_id).await;
+        let workdir = get_channel_workdir(ctx, channel_id).await;
+
+        // A new session gets the language in its preamble; an existing one is told once after a change
+        let (language, language_changed) = take_channel_language(ctx, channel_id, user_id).await;
+        let mut full_prompt = prompt::build(&prompt::PromptContext {
+            username,
+            content: user_content,
+            attachments: &attachment_info,
+            channel_prompt: &system_prompt,
+            first_message: existing_session.is_none(),
+            language: language.as_deref(),
+            language_changed,
+        });
+
+        // Run AI backend with streaming (dry run, plan mode or normal)
+        let spawn_span = tracing::debug_span!("spawn");
+        let spawn_started = Instant::now();
+        let mut rx = if is_dry_run() {
+            let delay = Duration::from_millis(Config::load().unwrap_or_default().dry_run_delay_ms);
+            dryrun::run_streaming(&full_prompt, delay)
+        } else if queued.is_plan_mode {
+            let use_z = backend == AiBackend::ClaudeZ;
+            match claude::run_streaming_plan(&full_prompt, use_z, model.as_deref(), workdir.as_deref(), channel_id).instrument(spawn_span.clone()).await {
+                Ok(rx) => rx,
+                Err(e) => {
+                    trace.error = Some(e.to_string());
+                    let _ = msg.channel_id.say(&ctx.http, format!("❌ Error: {}", e)).await;
+                    let _ = status_msg.delete(&ctx.http).await;
+                    return RunOutcome::Failed;
+                }
+            }
+        } else {
+            match backend {
+                AiBackend::Codex => {
+                    match codex::run_streaming(&full_prompt, existing_session.as_deref(), &image_paths, workdir.as_deref(), channel_id).instrument(spawn_span.clone()).await {
+                        Ok(rx) => rx,
+                        Err(e) => {
+                            trace.error = Some(e.to_string());
+                            let _ = msg.channel_id.say(&ctx.http, format!("❌ Error: {}", e)).await;
+                            let _ = status_msg.delete(&ctx.http).await;
+                            return RunOutcome::Failed;
+                        }
+                    }
+                }
+                _ => {
+                    let use_z = backend == AiBackend::ClaudeZ;
+                    match claude::run_streaming(&full_prompt, existing_session.as_deref(), use_z, model.as_deref(), workdir.as_deref(), channel_id).instrument(spawn_span.clone()).await {
+                        Ok(rx) => rx,
+                        Err(e) => {
+                            trace.error = Some(e.to_string());
+                            let _ = msg.channel_id.say(&ctx.http, format!("❌ Error: {}", e)).await;
+                            let _ = status_msg.delete(&ctx.http).await;
+                            return RunOutcome::Failed;
+                        }
+                    }
+                }
+            }
+        };
+
+        // Process stream events with cancellation support
+        let mut final_text = String::new();
+        let mut new_session_id: Option<String> = None;
+        let mut plan_content: Option<String> = None;
+        let mut exploration = Exploration::default();
+        let mut status_lines: Vec<String> = Vec::new();
+        let mut tool_calls = 0;
+        let mut last_update = Instant::now();
+        let update_interval = Duration::from_millis(800);
+        let mut refresh = tokio::time::interval_at(tokio::time::Instant::now() + STATUS_REFRESH, STATUS_REFRESH);
+        // `!stream`: answers appear while they are written (plan runs post their plan at the end)
+        let mut live = if !queued.is_plan_mode && channel_streaming(ctx, channel_id).await {
+            Some((LiveResponse::default(), Redactor::from_config(&config)))
+        } else {
+            None
+        };
+        refresh.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut was_cancelled = false;
+        let mut timed_out = false;
+        timings.record_since(Phase::Spawn, spawn_started);
+        let stream_started = Instant::now();
+        let mut first_event = true;
+        let stream_span = tracing::debug_span!("stream");
+        let mut session_recovered = false;
+        let mut run_usage: Option<RunUsage> = None;
+
+        // Typing indicator while events stream in; stops with the run, on
+        // cancellation, or on any early return
+        let typing = cancel_token.child_token();
+        spawn_typing(ctx.http.clone(), msg.channel_id, typing.clone());
+        let typing_guard = typing.drop_guard();
+
+        loop {
+            tokio::select! {
+                _ = cancel_token.cancelled() => {
+                    was_cancelled = true;
+                    tracing::info!("Processing cancelled for channel {}", channel_id);
+                    break;
+                }
+                _ = until_deadline(deadline) => {
+                    timed_out = true;
+                    tracing::warn!("Run in channel {} timed out", channel_id);
+                    break;
+                }
+                _ = refresh.tick() => {
+                    if last_update.elapsed() >= update_interval {
+                        let _ = edit_message(ctx, &status_msg, &status_text(run_started.elapsed(), tool_calls, &status_lines)).await;
+                        last_update = Instant::now();
+                    }
+                    if let Some((response, redactor)) = live.as_mut().filter(|(response, _)| response.due()) {
+                        if !final_text.trim().is_empty() {
+                            response.show(&ctx.http, msg.channel_id, &redactor.redact(&final_text)).await;
+                        }
+                    }
+                }
+                event = rx.recv().instrument(stream_span.clone()) => {
+                    if first_event {
+                        first_event = false;
+                        timings.record_since(Phase::FirstEvent, stream_started);
+                    }
+                    match event {
+                        Some(StreamEvent::ToolUse(tool_name, detail)) => {
+                            trace.tools.push(if detail.is_empty() { tool_name.clone() } else { format!("{}: {}", tool_name, detail) });
+                            tool_calls += 1;
+                            status_lines.push(claude::tool_label(&tool_name, &detail));
+                            if status_lines.len() > 5 {
+                                status_lines.remove(0);
+                            }
+                            if last_update.elapsed() >= update_interval {
+                                let _ = edit_message(ctx, &status_msg, &status_text(run_started.elapsed(), tool_calls, &status_lines)).await;
+                                last_update = Instant::now();
+                            }
+                        }
+                        Some(StreamEvent::ToolResult { tool, ok, summary }) => {
+                            // Flag failures in place (or re-add the line if it scrolled away)
+                            if !ok {
+                                let flagged = format!("{} ❌ ({})", tool, summary);
+                                match status_lines.iter().rposition(|l| *l == tool) {
+                                    Some(i) => status_lines[i] = flagged,
+                                    None => {
+                                        status_lines.push(flagged);
+                                        if status_lines.len() > 5 {
+                                            status_lines.remove(0);
+                                        }
+                                    }
+                                }
+                                let _ = edit_message(ctx, &status_msg, &status_text(run_started.elapsed(), tool_calls, &status_lines)).await;
+                                last_update = Instant::now();
+                            }
+                        }
+                        Some(StreamEvent::Text(text)) => {
+                            final_text = text;
+                            if let Some((response, redactor)) = live.as_mut().filter(|(response, _)| response.due()) {
+                                if !final_text.trim().is_empty() {
+                                    response.show(&ctx.http, msg.channel_id, &redactor.redact(&final_text)).await;
+                                }
+                            }
+                        }
+                        Some(StreamEvent::Explored(tool_name, target)) => {
+                            trace.tools.push(format!("{}: {}", tool_name, target));
+                            exploration.record(&tool_name, &target);
+                        }
+                        Some(StreamEvent::PlanContent(_path, content)) => {
+                            // Keep the longest plan content (may get multiple events)
+                            if plan_content.as_ref().is_none_or(|existing| content.len() > existing.len()) {
+                                plan_content = Some(content);
+                            }
+                        }
+                        Some(StreamEvent::SessionId(sid)) => {
+                            new_session_id = Some(sid);
+                        }
+                        Some(StreamEvent::SessionNotFound) if !session_recovered && existing_session.is_some() => {
+                            // The stored session is gone (e.g. ~/.claude/projects was cleaned): forget it and retry fresh once
+                            tracing::warn!("Session for {:?} no longer exists, starting a new one", session_key);
+                            {
+                                let data = ctx.data.read().await;
+                                if let Some(sessions) = data.get::<SessionStorage>() {
+                                    let mut sessions_map = sessions.write().await;
+                                    sessions_map.remove(&session_key);
+                                    save_sessions(&sessions_map);
+                                }
+                            }
+                            existing_session = None;
+                            session_recovered = true;
+                            full_prompt = prompt::build(&prompt::PromptContext {
+                                username,
+                                content: user_content,
+                                attachments: &attachment_info,
+                                channel_prompt: &system_prompt,
+                                first_message: true,
+                                language: language.as_deref(),
+                                language_changed: false,
+                            });
+                            let use_z = backend == AiBackend::ClaudeZ;
+                            match claude::run_streaming(&full_prompt, None, use_z, model.as_deref(), workdir.as_deref(), channel_id).await {
+                                Ok(fresh) => rx = fresh,
+                                Err(e) => {
+                                    trace.error = Some(e.to_string());
+                                    let _ = msg.channel_id.say(&ctx.http, format!("❌ Error: {}", e)).await;
+                                    let _ = status_msg.delete(&ctx.http).await;
+                                    return RunOutcome::Failed;
+                                }
+                            }
+                        }
+                        Some(StreamEvent::SessionNotFound) => {}
+                        Some(StreamEvent::AuthRequired(hint)) => {
+                            trace.error = Some(hint.clone());
+                            set_auth_required(true);
+                            let _ = msg.channel_id.say(&ctx.http, hint).await;
+                            let _ = status_msg.delete(&ctx.http).await;
+                            return RunOutcome::Failed;
+                        }
+                        Some(StreamEvent::Usage(usage)) => {
+                            run_usage = Some(usage);
+                        }
+                        Some(StreamEvent::Done) | None => {
+                            break;
+                        }
+                        Some(StreamEvent::Error(e)) => {
+                            trace.error = Some(e.to_string());
+                            let _ = msg.channel_id.say(&ctx.http, format!("❌ Error: {}", e)).await;
+                            let _ = status_msg.delete(&ctx.http).await;
+                            return RunOutcome::Failed;
+                        }
+                    }
+                }
+            }
+        }
+
+        drop(typing_guard);
+        trace.response = final_text.clone();
+        set_auth_required(false);
+        timings.record_since(Phase::Stream, stream_started);
+
+        if let Some(usage) = run_usage {
+            let session = new_session_id.as_deref().or(existing_session.as_deref());
+            record_cost(ctx, channel_id, user_id, session, usage).await;
+            *usage_out = Some(usage);
+        }
+
+        // Delete status message
+        let _ = status_msg.delete(&ctx.http).await;
+
+        if was_cancelled {
+            let steered = {
+                let data = ctx.data.read().await;
+                match data.get::<ProcessingChannels>() {
+                    Some(processing) => processing.read().await.get(&channel_id).is_some_and(|run| run.steered),
+                    None => false,
+                }
+            };
+            if steered {
+                // Keep a new session so the corrected request resumes it
+                if let Some(ref sid) = new_session_id.filter(|_| !queued.is_plan_mode) {
+                    let data = ctx.data.read().await;
+                    if let Some(sessions) = data.get::<SessionStorage>() {
+                        let mut sessions_map = sessions.write().await;
+                        sessions_map.insert(session_key, SessionEntry::new(sid.clone()));
+                        save_sessions(&sessions_map);
+                    }
+                }
+                let _ = msg.channel_id.say(&ctx.http, "🧭 Steering applied: continuing with the correction.").await;
+            } else {
+                let _ = msg.channel_id.say(&ctx.http, "🛑 Cancelled.").await;
+            }
+            return RunOutcome::Cancelled;
+        }
+
+        if timed_out {
+            // Closing the stream stops the CLI; the session stays so a reply can continue it
+            drop(rx);
+            if let Some(ref sid) = new_session_id.filter(|_| !queued.is_plan_mode) {
+                let data = ctx.data.read().await;
+                if let Some(sessions) = data.get::<SessionStorage>() {
+                    let mut sessions_map = sessions.write().await;
+                    sessions_map.insert(session_key, SessionEntry::new(sid.clone()));
+                    save_sessions(&sessions_map);
+                }
+            }
+            let notice = timeout_notice(timeout.unwrap_or_default(), &status_lines);
+            trace.error = notice.lines().next().map(str::to_string);
+            let _ = msg.channel_id.say(&ctx.http, notice).await;
+            return RunOutcome::TimedOut;
+        }
+
+        // Handle plan mode response separately
+        if queued.is_plan_mode {
+            // Use plan_content if text response is empty (common due to ExitPlanMode denial)
+            let response_text = if !final_text.is_empty() && !final_text.trim().is_empty() {
+                // If we have both, prefer the longer/more complete one
+                if let Some(ref plan) = plan_content {
+                    if plan.len() > final_text.len() { plan.clone() } else { final_text.clone() }
+                } else {
+                    final_text.clone()
+                }
+            } else {
+                plan_content.unwrap_or_else(|| "(No plan generated)".to_string())
+            };
+
+            let response_text = Redactor::from_config(&Config::load().unwrap_or_default()).redact(&response_text);
+            let full_response = match exploration.summary() {
+                Some(summary) => format!("📐 **Plan**\n{}\n\n{}", summary, response_text),
+                None => format!("📐 **Plan**\n\n{}", response_text),
+            };
+            let send_started = Instant::now();
+            let attach_after = channel_long_file_limit(ctx, channel_id).await;
+            send_response(&ctx.http, msg.channel_id, &full_response, attach_after).await;
+
+            let _ = msg.channel_id.send_message(&ctx.http, completion_message(format!("{}✅ Plan ready!", user_mention), silent)).await;
+            timings.record_since(Phase::DiscordSend, send_started);
+            log_activity(ctx, &msg.author.name, channel_type, content, &response_text).await;
+            return RunOutcome::Completed;
+        }
+
+        // Save session ID (memory + file)
+        if let Some(ref sid) = new_session_id {
+            let data = ctx.data.read().await;
+            if let Some(sessions) = data.get::<SessionStorage>() {
+                let mut sessions_map = sessions.write().await;
+                sessions_map.insert(session_key, SessionEntry::new(sid.clone()));
+                // Persist to file
+                save_sessions(&sessions_map);
+            }
+        }
+
+        // Send final response
+        if final_text.is_empty() {
+            final_text = "(No response)".to_string();
+        }
+
+        // Auto-compact session if prompt is too long (context window exceeded)
+        let lower_text = final_text.to_lowercase();
+        if lower_text.contains("prompt is too long") || lower_text.contains("context window") || lower_text.contains("too many tokens") {
+            let session_to_compact = new_session_id.as_deref().or(existing_session.as_deref());
+            if let Some(sid) = session_to_compact {
+                // Codex mode: can't compact, reset session
+                if backend == AiBackend::Codex {
+                    let data = ctx.data.read().await;
+                    if let Some(sessions) = data.get::<SessionStorage>() {
+                        let mut sessions_map = sessions.write().await;
+                        sessions_map.remove(&session_key);
+                        save_sessions(&sessions_map);
+                    }
+                    let _ = msg.channel_id.say(&ctx.http, "⚠️ Context window exceeded. 새 세션으로 시작합니다. 메시지를 다시 보내주세요.").await;
+                    return RunOutcome::Failed;
+                }
+
+                let use_z = backend == AiBackend::ClaudeZ;
+                let _ = msg.channel_id.say(&ctx.http, "⚠️ Context window full. Compacting session...").await;
+
+                // Run /compact on the session (within what's left of the run's time)
+                let compacted = tokio::select! {
+                    result = claude::compact_session(sid, use_z, workdir.as_deref()) => Some(result),
+                    _ = until_deadline(deadline) => None,
+                };
+                let Some(compacted) = compacted else {
+                    let notice = timeout_notice(timeout.unwrap_or_default(), &status_lines);
+                    trace.error = notice.lines().next().map(str::to_string);
+                    let _ = msg.channel_id.say(&ctx.http, notice).await;
+                    return RunOutcome::TimedOut;
+                };
+                match compacted {
+                    Ok(_) => {
+                        let _ = msg.channel_id.say(&ctx.http, "✅ Session compacted. Retrying your message...").await;
+
+                        // Retry the original message with the compacted session
+                        match claude::run_streaming(&full_prompt, Some(sid), use_z, model.as_deref(), workdir.as_deref(), channel_id).await {
+                            Ok(mut retry_rx) => {
+                                let mut retry_text = String::new();
+                                let finished = tokio::select! {
+                                    _ = async {
+                                        while let Some(event) = retry_rx.recv().await {
+                                            match event {
+                                                StreamEvent::Text(t) => retry_text.push_str(&t),
+                                                StreamEvent::Done => break,
+                                                _ => {}
+                                            }
+                                        }
+                                    } => true,
+                                    _ = until_deadline(deadline) => false,
+                                };
+                                if !finished {
+                                    drop(retry_rx);
+                                    let notice = timeout_notice(timeout.unwrap_or_default(), &status_lines);
+                                    trace.error = notice.lines().next().map(str::to_string);
+                                    let _ = msg.channel_id.say(&ctx.http, notice).await;
+                                    return RunOutcome::TimedOut;
+                                }
+                                if !retry_text.is_empty() {
+                                    final_text = retry_text;
+                                    trace.response = final_text.clone();
+                                    // Fall through to normal response handling below
+                                } else {
+                                    let _ = msg.channel_id.say(&ctx.http, "⚠️ Compact succeeded but retry got empty response. Please send your message again.").await;
+                                    return RunOutcome::Failed;
+                                }
+                            }
+                            Err(e) => {
+                                let _ = msg.channel_id.say(&ctx.http, format!("⚠️ Compact succeeded but retry failed: {}. Please send your message again.", e)).await;
+                                return RunOutcome::Failed;
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        // Compact failed, try trimming as fallback
+                        tracing::warn!("Compact failed: {}, trying trim fallback", e);
+                        let trimmed = trim_session_file(sid, workdir.as_deref());
+                        if trimmed {
+                            let _ = msg.channel_id.say(&ctx.http, "⚠️ Compact failed. Trimmed old messages instead. Please send your message again.").await;
+                        } else {
+                            let data = ctx.data.read().await;
+                            if let Some(sessions) = data.get::<SessionStorage>() {
+                                let mut sessions_map = sessions.write().await;
+                                sessions_map.remove(&session_key);
+                                save_sessions(&sessions_map);
+                            }
+                            let _ = msg.channel_id.say(&ctx.http, "⚠️ Context window exceeded. Session has been reset. Please send your message again.").await;
+                        }
+                        return RunOutcome::Failed;
+                    }
+                }
+            } else {
+                let _ = msg.channel_id.say(&ctx.http, "⚠️ Context window exceeded. Please start a new session with !new.").await;
+                return RunOutcome::Failed;
+            }
+        }
+
+        // Mask secrets before anything is posted
+        if session_recovered {
+            final_text.push_str("\n\n_(previous session was missing; started a new one)_");
+        }
+        let final_text = Redactor::from_config(&Config::load().unwrap_or_default()).redact(&final_text);
+
+        // Detect file paths in response and send as attachments
+        let send_started = Instant::now();
+        let file_paths = extract_file_paths(&final_text);
+        tracing::info!("Detected file paths: {:?}", file_paths);
+        let mut sent_files: Vec<String> = Vec::new();
+
+        for path in &file_paths {
+            tracing::info!("Checking path: {} exists={}", path, Path::new(path).exists());
+            if Path::new(path).exists() {
+                if let Ok(attachment) = CreateAttachment::path(path).await {
+                    let builder = CreateMessage::new().add_file(attachment);
+                    if msg.channel_id.send_message(&ctx.http, builder).await.is_ok() {
+                        sent_files.push(path.clone());
+                        tracing::info!("Sent file: {}", path);
+                    }
+                }
+            }
+        }
+
+        // Send text response
+        if channel_embeds(ctx, channel_id).await {
+            let title = format!("{:?} · {}", channel_type, format_elapsed(run_started.elapsed()));
+            let mut footer = model_label(backend, model.as_deref());
+            if backend != AiBackend::Codex {
+                footer = format!("{} · {}", backend.cli_name(), footer);
+            }
+            if let Some(usage) = run_usage {
+                footer.push_str(&format!(" · {}", cost::format_usd(usage.cost_usd)));
+            }
+            if let Some((response, _)) = live.as_mut() {
+                response.clear(&ctx.http).await;
+            }
+            for embed in response_embeds(&final_text, &title, &footer) {
+                let _ = msg.channel_id.send_message(&ctx.http, CreateMessage::new().embed(embed)).await;
+            }
+        } else {
+            let attach_after = channel_long_file_limit(ctx, channel_id).await;
+            let attach = attach_after.is_some_and(|limit| split_for_discord(&final_text).len() > limit);
+            match live.as_mut() {
+                // Rewrite the live messages with the final chunks, fixing any code block cut mid-stream
+                Some((response, _)) if !attach => response.show(&ctx.http, msg.channel_id, &final_text).await,
+                Some((response, _)) => {
+                    response.clear(&ctx.http).await;
+                    send_response(&ctx.http, msg.channel_id, &final_text, attach_after).await;
+                }
+                None => send_response(&ctx.http, msg.channel_id, &final_text, attach_after).await,
+            }
+        }
+
+        // Send completion notification
+        let done_style = channel_done_style(ctx, channel_id).await;
+        let elapsed = format_elapsed(run_started.elapsed());
+        let completion_msg = if sent_files.is_empty() {
+            format!("{}{} ({})", user_mention, done_style, elapsed)
+        } else {
+            format!("{}{} ({}, {} file(s) attached)", user_mention, done_style, elapsed, sent_files.len())
+        };
+        let _ = msg.channel_id.send_message(&ctx.http, completion_message(completion_msg, silent)).await;
+        timings.record_since(Phase::DiscordSend, send_started);
+
+        // Suggest !compact once per session when the context is getting full
+        if backend != AiBackend::Codex {
+            if let Some(sid) = new_session_id.as_deref().or(existing_session.as_deref()) {
+                suggest_compact(ctx, msg, sid).await;
+            }
+        }
+
+        // Log activity
+        log_activity(ctx, &msg.author.name, channel_type, content, &final_text).await;
+        RunOutcome::Completed
+    }
+
+    /// Detect messages stripped by a missing Message Content intent: guild
+    /// messages from humans with no content, attachments, embeds, or poll.
+    /// Sticker-only messages and messages mentioning the bot (which always
+    /// carry content) are ambiguous and neither count nor reset the streak.
+    async fn watch_message_content(ctx: &serenity::client::Context, msg: &Message) {
+        use serenity::model::channel::MessageType;
+
+        if msg.author.bot || msg.guild_id.is_none() || !msg.sticker_items.is_empty() {
+            return;
+        }
+        if !matches!(msg.kind, MessageType::Regular | MessageType::InlineReply) {
+            return;
+        }
+        let data = ctx.data.read().await;
+        let own_id = match data.get::<BotUserId>() {
+            Some(id) => *id.read().await,
+            None => None,
+        };
+        if own_id.is_some_and(|id| msg.mentions.iter().any(|u| u.id.get() == id)) {
+            return;
+        }
+        let Some(watch) = data.get::<IntentWatchStorage>() else {
+            return;
+        };
+
+        let stripped = msg.content.is_empty()
+            && msg.attachments.is_empty()
+            && msg.embeds.is_empty()
+            && msg.poll.is_none();
+        let should_warn = {
+            let mut watch = watch.write().await;
+            if !stripped {
+                watch.empty_streak = 0;
+                return;
+            }
+            watch.empty_streak += 1;
+            if watch.empty_streak >= EMPTY_CONTENT_THRESHOLD && !watch.warned {
+                watch.warned = true;
+                true
+            } else {
+                false
+            }
+        };
+        drop(data);
+
+        if should_warn {
+            warn_missing_message_content(
+                ctx,
+                &format!("{} messages in a row arrived with no content", EMPTY_CONTENT_THRESHOLD),
+            )
+            .await;
+        }
+    }
+
+    /// Decide whether a message may trigger Neywa at all. Own messages never do;
+    /// bots and webhooks only with `respond_to_bots`, and only until the
+    /// per-channel circuit breaker trips.
+    async fn passes_loop_guard(ctx: &serenity::client::Context, msg: &Message) -> bool {
+        let data = ctx.data.read().await;
+        let own_id = match data.get::<BotUserId>() {
+            Some(id) => *id.read().await,
+            None => None,
+        };
+        if own_id == Some(msg.author.id.get()) {
+            return false;
+        }
+
+        let channel_id = msg.channel_id.get();
+        let Some(guard) = data.get::<LoopGuardStorage>() else {
+            return !msg.author.bot && msg.webhook_id.is_none();
+        };
+
+        if !msg.author.bot && msg.webhook_id.is_none() {
+            guard.write().await.human_message(channel_id);
+            return true;
+        }
+
+        let config = Config::load().unwrap_or_default();
+        if !config.respond_to_bots {
+            return false;
+        }
+
+        let verdict = guard.write().await.bot_message(channel_id, config.bot_loop_limit, Instant::now());
+        match verdict {
+            Verdict::Allow => true,
+            Verdict::Paused => false,
+            Verdict::Tripped => {
+                tracing::warn!("Bot loop detected in channel {}, pausing", channel_id);
+                drop(data);
+                post_to_logs(
+                    ctx,
+                    &format!(
+                        "🔁 Possible bot loop in <#{}>: more than {} bot/webhook messages in a minute with no human in between. Ignoring bots there for {} minutes.",
+                        channel_id,
+                        config.bot_loop_limit,
+                        loopguard::PAUSE.as_secs() / 60
+                    ),
+                )
+                .await;
+                false
+            }
+        }
+    }
+
+    /// Wait out the coalescing window for a channel's pending batch and take it.
+    /// Returns None if cancelled (e.g. `!stop`) before the window closed.
+    async fn close_batch(
+        ctx: &serenity::client::Context,
+        channel_id: u64,
+        window: Duration,
+        cancel_token: &CancellationToken,
+    ) -> Option<QueuedMessage> {
+        let cancelled = tokio::select! {
+            _ = tokio::time::sleep(window) => false,
+            _ = cancel_token.cancelled() => true,
+        };
+
+        let data = ctx.data.read().await;
+        let batch = data.get::<PendingBatches>()?.write().await.remove(&channel_id);
+        if cancelled {
+            None
+        } else {
+            batch
+        }
+    }
+
+    /// Queue a request behind the channel's current run, or start it right away
+    /// (no coalescing window: used for `!plan` and `!retry`)
+    async fn submit(ctx: &serenity::client::Context, queued: QueuedMessage) {
+        if hold_for_quiet_hours(ctx, &queued).await {
+            return;
+        }
+        let channel_id = queued.msg.channel_id.get();
+        let is_processing = {
+            let data = ctx.data.read().await;
+            if let Some(processing) = data.get::<ProcessingChannels>() {
+                processing.read().await.contains_key(&channel_id)
+            } else {
+                false
+            }
+        };
+
+        if is_processing {
+            let msg = queued.msg.clone();
+            let queue_pos = {
+                let data = ctx.data.read().await;
+                if let Some(queue) = data.get::<MessageQueue>() {
+                    let mut q = queue.write().await;
+                    enqueue(q.entry(channel_id).or_default(), queued)
+                } else {
+                    0
+                }
+            };
+            announce_queued(ctx, &msg, queue_pos).await;
+        } else {
+            let cancel_token = CancellationToken::new();
+            {
+                let data = ctx.data.read().await;
+                if let Some(processing) = data.get::<ProcessingChannels>() {
+                    processing.write().await.insert(channel_id, ActiveRun::new(cancel_token.clone(), &queued));
+                }
+            }
+
+            let ctx_clone = ctx.clone();
+            tokio::spawn(async move {
+                Self::process_message(&ctx_clone, queued, cancel_token).await;
+                {
+                    let data = ctx_clone.data.read().await;
+                    if let Some(processing) = data.get::<ProcessingChannels>() {
+                        processing.write().await.remove(&channel_id);
+                    }
+                }
+                Self::process_queue(ctx_clone, channel_id).await;
+            });
+        }
+    }
+
+    async fn process_queue(ctx: serenity::client::Context, channel_id: u64) {
+        loop {
+            // Get next message from queue
+            let next_msg = {
+                let data = ctx.data.read().await;
+                if let Some(queue) = data.get::<MessageQueue>() {
+                    pop_queued(&mut *queue.write().await, channel_id)
+                } else {
+                    None
+                }
+            };
+
+            match next_msg {
+                Some(queued) => {
+                    // Its turn now: the queue notice gives way to "Processing...", the rest move up
+                    if let Some(notice) = queued.notice {
+                        let _ = queued.msg.channel_id.delete_message(&ctx.http, notice).await;
+                    }
+                    refresh_queue_notices(&ctx, channel_id, 0).await;
+
+                    // Create new cancellation token for this message
+                    let cancel_token = CancellationToken::new();
+
+                    // Store the token
+                    {
+                        let data = ctx.data.read().await;
+                        if let Some(processing) = data.get::<ProcessingChannels>() {
+                            processing.write().await.insert(channel_id, ActiveRun::new(cancel_token.clone(), &queued));
+                        }
+                    }
+
+                    // Process the message
+                    Self::process_message(&ctx, queued, cancel_token).await;
+
+                    // Remove from processing
+                    {
+                        let data = ctx.data.read().await;
+                        if let Some(processing) = data.get::<ProcessingChannels>() {
+                            processing.write().await.remove(&channel_id);
+                        }
+                    }
+                }
+                None => {
+                    // Queue is empty, exit the loop
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl EventHandler for Handler {
+    async fn message(&self, ctx: serenity::client::Context, msg: Message) {
+        Self::watch_message_content(&ctx, &msg).await;
+
+        if !Self::passes_loop_guard(&ctx, &msg).await {
+            return;
+        }
+
+        // Channels outside `active_channels` stay completely silent (except an admin's !activate).
+        // The list is about server channels; DMs only need the allowlist.
+        if msg.guild_id.is_some() {
+            let config = Config::load().unwrap_or_default();
+            if !config.is_active_channel(msg.channel_id.get()) {
+                let may_activate = msg.content.trim() == "!activate"
+                    && user_tier(&config, msg.author.id.get()) == Some(Tier::Admin);
+                if !may_activate {
+                    return;
+                }
+            }
+            // `ignored_channels` (by ID or name) are silent even for commands
+            if !config.ignored_channels.is_empty() {
+                let info = channel_info_for(&ctx, msg.channel_id).await;
+                if config.is_ignored_channel(msg.channel_id.get(), &info.name) {
+                    return;
+                }
+            }
+        }
+
+        let channel_info = channel_info_for(&ctx, msg.channel_id).await;
+        match channel_info.kind.route() {
+            MessageRoute::ForumNotice => {
+                send_forum_notice(&ctx, msg.channel_id).await;
+                return;
+            }
+            MessageRoute::Ignore => {
+                tracing::debug!("Ignoring message in unsupported channel {}", msg.channel_id);
+                return;
+            }
+            MessageRoute::Handle => {}
+        }
+        let channel_type = channel_info.mode;
+
+        if channel_type == ChannelType::Logs {
+            return;
+        }
+
+        let content = msg.content.trim().to_string();
+        let channel_id = msg.channel_id.get();
+        let guild_key = msg.guild_id.map(|g| g.get()).unwrap_or(0);
+
+        // User-defined aliases expand once, before any command matching
+        let content = {
+            let config = Config::load().unwrap_or_default();
+            let expanded = config
+                .aliases
+                .get(&guild_key)
+                .and_then(|aliases| alias::expand(aliases, &content, |name| find_command(name).is_some()));
+            match expanded {
+                Some(expanded) => {
+                    tracing::info!("Expanded alias {:?} to {:?}", content, expanded);
+                    expanded
+                }
+                None => content,
+            }
+        };
+
+        // Allow !human command even in human mode (to toggle it off)
+        // But block all other messages if human mode is active
+        if content != "!human" && content != "!인간" {
+            let is_human_mode = {
+                let data = ctx.data.read().await;
+                if let Some(human_channels) = data.get::<HumanModeChannels>() {
+                    human_channels.read().await.contains(&channel_id)
+                } else {
+                    false
+                }
+            };
+            if is_human_mode {
+                if !msg.author.bot && wants_human_mode_hint(&ctx, channel_id, msg.author.id.get()).await {
+                    let _ = msg.react(&ctx.http, '🙋').await;
+                }
+                return;
+            }
+        }
+
+        // Drop the @mention (or trigger prefix) addressing Neywa. In `!mention`
+        // channels, anything not addressed to it is ignored, except commands.
+        let content = {
+            let bot_id = {
+                let data = ctx.data.read().await;
+                match data.get::<BotUserId>() {
+                    Some(id) => *id.read().await,
+                    None => None,
+                }
+            };
+            let prefix = Config::load().unwrap_or_default().mention_prefix;
+            let (addressed, stripped) = addressed_content(&msg, &content, bot_id, prefix.as_deref());
+            if !addressed && !content.starts_with('!') && is_mention_channel(&ctx, channel_id).await {
+                return;
+            }
+            stripped
+        };
+        let user_id = msg.author.id.get();
+        let session_key = session_key_for(&ctx, user_id, channel_id).await;
+
+        // Permission check: chat needs the allowlist, commands need their declared tier
+        {
+            let config = Config::load().unwrap_or_default();
+            let tier = user_tier(&config, user_id);
+            match parse_command(&content) {
+                Some(spec) if tier.is_none_or(|t| t < spec.tier) => {
+                    log_unauthorized(&ctx, &msg.author.name, user_id, channel_id, &format!("!{}", spec.name)).await;
+                    if let Ok(refusal) = msg.reply(&ctx.http, "⛔ You don't have permission for that command.").await {
+                        let http = ctx.http.clone();
+                        tokio::spawn(async move {
+                            tokio::time::sleep(Duration::from_secs(5)).await;
+                            let _ = refusal.delete(&http).await;
+                        });
+                    }
+                    return;
+                }
+                // !run is audited with its command line and exit status once it finishes
+                Some(spec) if spec.name != "run" => {
+                    audit::record(AuditEntry::new(
+                        "command",
+                        &format!("!{}", spec.name),
+                        user_id,
+                        &msg.author.name,
+                        channel_id,
+                        &content,
+                    ));
+                }
+                None if tier.is_none() => {
+                    tracing::debug!("Ignoring message from non-allowlisted user {}", user_id);
+                    return;
+                }
+                _ => {}
+            }
+        }
+
+        // Some commands need a server channel to act on
+        if msg.guild_id.is_none() {
+            if let Some(spec) = parse_command(&content).filter(|spec| GUILD_ONLY_COMMANDS.contains(&spec.name)) {
+                let _ = msg
+                    .channel_id
+                    .say(&ctx.http, format!("❌ `!{}` only works in server channels, not in DMs.", spec.name))
+                    .await;
+                return;
+            }
+        }
+
+        // Attachments are downloaded when the message is processed
+        let attachments = msg.attachments.clone();
+
+        // Handle commands first (these don't go to queue)
+
+        // Handle help command
+        if content == "!help" || content == "!도움" {
+            let help_text = help_text(&ctx, user_id, channel_id).await;
+            for chunk in split_for_discord(&help_text) {
+                let _ = msg.channel_id.say(&ctx.http, &chunk).await;
+            }
+            return;
+        }
+
+        // Handle stop command
+        if content == "!stop" || content == "!중단" || content == "!stop confirm" {
+            let data = ctx.data.read().await;
+
+            // Stopping someone else's run needs `!stop confirm` (admins excepted)
+            let tier = user_tier(&Config::load().unwrap_or_default(), user_id);
+            if content != "!stop confirm" && tier != Some(Tier::Admin) {
+                if let Some(owner) = foreign_run_owner(&data, channel_id, user_id).await {
+                    let _ = msg.channel_id.say(&ctx.http, format!(
+                        "⚠️ The current run is {}'s request. Use `!stop confirm` to stop it anyway.", owner
+                    )).await;
+                    return;
+                }
+            }
+
+            // Cancel current processing
+            if let Some(processing) = data.get::<ProcessingChannels>() {
+                if let Some(run) = processing.read().await.get(&channel_id) {
+                    run.cancel_token.cancel();
+                    let _ = msg.channel_id.say(&ctx.http, "🛑 Stop requested...").await;
+                } else {
+                    let _ = msg.channel_id.say(&ctx.http, "Nothing is being processed.").await;
+                }
+            }
+
+            // Clear queue for this channel
+            if let Some(queue) = data.get::<MessageQueue>() {
+                let cleared = queue.write().await.remove(&channel_id).map_or(0, |q| q.len());
+                if cleared > 0 {
+                    let _ = msg.channel_id.say(&ctx.http, format!("📭 Cleared {} queued message(s)", cleared)).await;
+                }
+            }
+            return;
+        }
+
+        // Handle reset command: !new [mine|channel]
+        if parse_command(&content).is_some_and(|spec| spec.name == "reset") {
+            let arg = content.split_whitespace().nth(1).unwrap_or("").to_lowercase();
+            let Some(scope) = ResetScope::parse(&arg) else {
+                let _ = msg.channel_id.say(&ctx.http, "Usage: `!new [mine|channel]`").await;
+                return;
+            };
+            let tier = user_tier(&Config::load().unwrap_or_default(), user_id);
+            let reply = reset_sessions(&ctx, user_id, channel_id, scope, tier).await;
+            let _ = msg.channel_id.say(&ctx.http, reply).await;
+            return;
+        }
+
+        // Handle !run command - execute terminal command directly
+        if let Some(cmd) = content.strip_prefix("!run ") {
+            let cmd = cmd.trim();
+            if cmd.is_empty() {
+                let _ = msg.channel_id.say(&ctx.http, "Usage: `!run <command>`").await;
+                return;
+            }
+
+            tracing::info!("Executing terminal command: {}", cmd);
+            let _ = msg.channel_id.say(&ctx.http, format!("⏳ Running: `{}`", cmd)).await;
+
+            let started = Instant::now();
+
+            // Run command in spawn_blocking to avoid blocking the async runtime
+            let cmd_owned = cmd.to_string();
+            let output = tokio::task::spawn_blocking(move || {
+                Command::new("bash")
+                    .arg("-c")
+                    .arg(&cmd_owned)
+                    .output()
+            }).await;
+
+            let outcome = match &output {
+                Ok(Ok(output)) => format!("exit {}", output.status.code().unwrap_or(-1)),
+                _ => "error".to_string(),
+            };
+            audit::record(
+                AuditEntry::new("command", "!run", user_id, &msg.author.name, channel_id, cmd)
+                    .command_line(cmd)
+                    .outcome(&outcome)
+                    .duration(started.elapsed()),
+            );
+
+            let response = match output {
+                Ok(Ok(output)) => {
+                    let stdout = String::from_utf8_lossy(&output.stdout);
+                    let stderr = String::from_utf8_lossy(&output.stderr);
+                    let exit_code = output.status.code().unwrap_or(-1);
+
+                    let mut result = String::new();
+                    if !stdout.is_empty() {
+                        result.push_str(&format!("**stdout:**\n```\n{}\n```", stdout));
+                    }
+                    if !stderr.is_empty() {
+                        if !result.is_empty() { result.push('\n'); }
+                        result.push_str(&format!("**stderr:**\n```\n{}\n```", stderr));
+                    }
+                    result.push_str(&format!("\n*Exit code: {}*", exit_code));
+
+                    if result.is_empty() {
+                        format!("✅ Done (exit code: {})", exit_code)
+                    } else {
+                        result
+                    }
+                }
+                Ok(Err(e)) => format!("❌ Failed to execute: {}", e),
+                Err(e) => format!("❌ Task error: {}", e),
+            };
+
+            let response = Redactor::from_config(&Config::load().unwrap_or_default()).redact(&response);
+
+            // Discord has 2000 char limit, truncate if needed
+            let response = if response.len() > 1950 {
+                format!("{}...\n*(truncated)*", &response[..1900])
+            } else {
+                response
+            };
+
+            let _ = msg.channel_id.say(&ctx.http, response).await;
+            return;
+        }
+
+        // Handle !get command - send a local file as an attachment (no AI involved)
+        if content == "!get" || content.starts_with("!get ") {
+            let config = Config::load().unwrap_or_default();
+
+            let raw_path = content.trim_start_matches("!get").trim();
+            if raw_path.is_empty() {
+                let _ = msg.channel_id.say(&ctx.http, "Usage: `!get <path>`").await;
+                return;
+            }
+
+            // The default root is the whole home directory, which only admins get
+            if config.allowed_roots.is_empty() && user_tier(&config, user_id) != Some(Tier::Admin) {
+                let _ = msg.channel_id.say(
+                    &ctx.http,
+                    "🔒 `!get` is admin-only until `allowed_roots` is set in the config.",
+                ).await;
+                return;
+            }
+
+            let base = transfer_base(&ctx, channel_id).await;
+            let path = match resolve_transfer_path(raw_path, &base, &config.transfer_roots(), &config.transfer_denied_roots()) {
+                Ok(p) => p,
+                Err(e) => {
+                    let _ = msg.channel_id.say(&ctx.http, format!("❌ {}", e)).await;
+                    return;
+                }
+            };
+
+            let size = match std::fs::metadata(&path) {
+                Ok(meta) if meta.is_file() => meta.len(),
+                Ok(_) => {
+                    let _ = msg.channel_id.say(&ctx.http, format!("❌ Not a file: `{}`", path.display())).await;
+                    return;
+                }
+                Err(e) => {
+                    let _ = msg.channel_id.say(&ctx.http, format!("❌ Cannot read `{}`: {}", path.display(), e)).await;
+                    return;
+                }
+            };
+
+            if size > config.max_transfer_bytes {
+                let _ = msg.channel_id.say(&ctx.http, format!(
+                    "❌ File too large: {} (limit {})",
+                    attachment::format_bytes(size), attachment::format_bytes(config.max_transfer_bytes)
+                )).await;
+                return;
+            }
+
+            match CreateAttachment::path(&path).await {
+                Ok(attachment) => {
+                    let builder = CreateMessage::new()
+                        .content(format!("📎 `{}` ({})", path.display(), attachment::format_bytes(size)))
+                        .add_file(attachment);
+                    if let Err(e) = msg.channel_id.send_message(&ctx.http, builder).await {
+                        let _ = msg.channel_id.say(&ctx.http, format!("❌ Upload failed: {}", e)).await;
+                    }
+                }
+                Err(e) => {
+                    let _ = msg.channel_id.say(&ctx.http, format!("❌ Cannot read `{}`: {}", path.display(), e)).await;
+                }
+            }
+            return;
+        }
+
+        // Handle !put command - save message attachments to a local path
+        if content == "!put" || content.starts_with("!put ") {
+            let config = Config::load().unwrap_or_default();
+
+            let args: Vec<&str> = content.trim_start_matches("!put").split_whitespace().collect();
+            let force = args.contains(&"--force");
+            let raw_path = args.iter().filter(|a| **a != "--force").copied().collect::<Vec<_>>().join(" ");
+
+            if raw_path.is_empty() || msg.attachments.is_empty() {
+                let _ = msg.channel_id.say(&ctx.http, "Usage: `!put <path> [--force]` with file(s) attached").await;
+                return;
+            }
+
+            let base = transfer_base(&ctx, channel_id).await;
+            let target = match resolve_transfer_path(&raw_path, &base, &config.transfer_roots(), &config.transfer_denied_roots()) {
+                Ok(p) => p,
+                Err(e) => {
+                    let _ = msg.channel_id.say(&ctx.http, format!("❌ {}", e)).await;
+                    return;
+                }
+            };
+
+            // Resolving follows links, so check the path as given: never write through one
+            if expand_transfer_path(&raw_path, &base).is_ok_and(|requested| is_symlink(&requested)) {
+                let _ = msg.channel_id.say(&ctx.http, format!("❌ `{}` is a symlink", raw_path)).await;
+                return;
+            }
+
+            // Treat target as a directory if it is one, looks like one, or several files were sent
+            let into_dir = target.is_dir() || raw_path.ends_with('/') || msg.attachments.len() > 1;
+            if into_dir {
+                if let Err(e) = std::fs::create_dir_all(&target) {
+                    let _ = msg.channel_id.say(&ctx.http, format!("❌ Cannot create `{}`: {}", target.display(), e)).await;
+                    return;
+                }
+            }
+
+            let mut results = Vec::new();
+            for attachment in &msg.attachments {
+                let dest = if into_dir { target.join(&attachment.filename) } else { target.clone() };
+
+                if attachment.size as u64 > config.max_transfer_bytes {
+                    results.push(format!("❌ `{}` too large ({})", attachment.filename, attachment::format_bytes(attachment.size as u64)));
+                    continue;
+                }
+                if is_symlink(&dest) {
+                    results.push(format!("❌ `{}` is a symlink", dest.display()));
+                    continue;
+                }
+                if dest.exists() && !force {
+                    results.push(format!("⚠️ `{}` exists (use `--force` to overwrite)", dest.display()));
+                    continue;
+                }
+
+                match save_attachment_to(&attachment.url, &dest).await {
+                    Ok(written) => results.push(format!("✅ `{}` ({})", dest.display(), attachment::format_bytes(written))),
+                    Err(e) => results.push(format!("❌ `{}`: {}", attachment.filename, e)),
+                }
+            }
+
+            let _ = msg.channel_id.say(&ctx.http, results.join("\n")).await;
+            return;
+        }
+
+        // Handle Z mode toggle command
+        if content == "!z" || content == "!z confirm" {
+            if !confirm_backend_toggle(&ctx, &msg, "z", content != "!z").await {
+                return;
+            }
+            let target = if get_channel_backend(&ctx, channel_id).await == AiBackend::ClaudeZ {
+                AiBackend::Claude
+            } else {
+                AiBackend::ClaudeZ
+            };
+            let mode_msg = switch_backend(&ctx, msg.channel_id, user_id, target).await;
+            let _ = msg.channel_id.say(&ctx.http, mode_msg).await;
+            return;
+        }
+
+        // Handle Codex mode toggle command
+        if content == "!codex" || content == "!codex confirm" {
+            // Check if codex CLI is available
+            if claude::find_cli("codex").is_none() {
+                let _ = msg.channel_id.say(&ctx.http, "❌ codex CLI not found. Install: `npm install -g @openai/codex`").await;
+                return;
+            }
+            if !confirm_backend_toggle(&ctx, &msg, "codex", content != "!codex").await {
+                return;
+            }
+            let target = if get_channel_backend(&ctx, channel_id).await == AiBackend::Codex {
+                AiBackend::Claude
+            } else {
+                AiBackend::Codex
+            };
+            let mode_msg = switch_backend(&ctx, msg.channel_id, user_id, target).await;
+            let _ = msg.channel_id.say(&ctx.http, mode_msg).await;
+            return;
+        }
+
+        // Handle backends command - CLI availability and versions
+        if content == "!backends" {
+            let _ = msg.channel_id.broadcast_typing(&ctx.http).await;
+            let selected = get_channel_backend(&ctx, channel_id).await;
+            let mut lines = vec!["🧰 **Backends**".to_string()];
+            for check in claude::check_backends().await {
+                let mut line = check.describe();
+                if check.backend == selected {
+                    line.push_str(" ◀ this channel");
+                }
+                lines.push(line);
+            }
+            let _ = msg.channel_id.say(&ctx.http, lines.join("\n")).await;
+            return;
+        }
+
+        // Handle human mode toggle
+        if content == "!human" || content == "!인간" {
+            let channel_name = if let Ok(channel) = msg.channel_id.to_channel(&ctx.http).await {
+                channel.guild().map(|gc| gc.name.clone())
+            } else {
+                None
+            };
+
+            let data = ctx.data.read().await;
+            if let Some(human_channels) = data.get::<HumanModeChannels>() {
+                let mut channels = human_channels.write().await;
+                let is_human_mode = if channels.contains(&channel_id) {
+                    // Turn OFF human mode
+                    channels.remove(&channel_id);
+                    save_human_mode(&channels);
+
+                    // Remove emoji from channel name
+                    if let Some(name) = &channel_name {
+                        let new_name = name.trim_start_matches("🙋‍♂️").trim_start_matches('-').to_string();
+                        let new_name = if new_name.is_empty() { name.clone() } else { new_name };
+                        tokio::spawn({
+                            let channel_id_str = channel_id.to_string();
+                            async move {
+                                if let Err(e) = discord_api::rename_channel(&channel_id_str, &new_name).await {
+                                    tracing::warn!("Failed to rename channel: {}", e);
+                                }
+                            }
+                        });
+                    }
+
+                    false
+                } else {
+                    // Turn ON human mode
+                    channels.insert(channel_id);
+                    save_human_mode(&channels);
+
+                    // Add emoji to channel name
+                    if let Some(name) = &channel_name {
+                        let new_name = format!("🙋‍♂️{}", name);
+                        tokio::spawn({
+                            let channel_id_str = channel_id.to_string();
+                            async move {
+                                if let Err(e) = discord_api::rename_channel(&channel_id_str, &new_name).await {
+                                    tracing::warn!("Failed to rename channel: {}", e);
+                                }
+                            }
+                        });
+                    }
+
+                    true
+                };
+
+                let mode_msg = if is_human_mode {
+                    "🙋‍♂️ **Human mode ON** - Neywa will not respond in this channel.\nType `!human` again to turn off."
+                } else {
+                    "🤖 **Human mode OFF** - Neywa is back online in this channel."
+                };
+                let _ = msg.channel_id.say(&ctx.http, mode_msg).await;
+            }
+            return;
+        }
+
+        // Handle status command
+        if content == "!status" || content == "!상태" || content == "!status all" {
+            let data = ctx.data.read().await;
+            let backend = if let Some(backends) = data.get::<ChannelBackends>() {
+                backends.read().await.get(&channel_id).copied().unwrap_or(AiBackend::Claude)
+            } else {
+                AiBackend::Claude
+            };
+            let active_run = if let Some(processing) = data.get::<ProcessingChannels>() {
+                processing.read().await.get(&channel_id).cloned()
+            } else {
+                None
+            };
+            let queue_size = if let Some(queue) = data.get::<MessageQueue>() {
+                queue.read().await.get(&channel_id).map(|q| q.len()).unwrap_or(0)
+            } else {
+                0
+            };
+
+            let mode = backend.status_line();
+            let processing_status = match &active_run {
+                Some(run) => run.describe(),
+                None => "✅ Idle".to_string(),
+            };
+            let queue_status = if queue_size > 0 { format!("📬 Queue: {}", queue_size) } else { "📭 Queue: empty".to_string() };
+            let slots = data.get::<TaskSlots>().cloned();
+
+            drop(data);
+            let mut status = format!("{}\n{}\n{}", mode, processing_status, queue_status);
+            if let Some(pool) = slots.filter(|pool| pool.limit > 0) {
+                status.push_str(&format!("\n🎰 Slots: {}/{} in use across channels", pool.in_use(), pool.limit));
+            }
+            if let Some(model) = get_channel_model(&ctx, channel_id).await.filter(|_| backend != AiBackend::Codex) {
+                status.push_str(&format!("\n🎛️ Model: {}", model));
+            }
+            status.push_str(&format!("\n📂 Workdir: `{}`", channel_workdir_label(&ctx, channel_id).await));
+            status.push_str(if is_shared_channel(&ctx, channel_id).await {
+                "\n👥 Sessions: shared by everyone in this channel"
+            } else {
+                "\n👤 Sessions: personal"
+            });
+            status.push_str(if is_mention_channel(&ctx, channel_id).await {
+                "\n📣 Answers: @mentions and replies only"
+            } else {
+                "\n💬 Answers: every message"
+            });
+            if let Some(line) = quiet_status(&ctx, channel_id).await {
+                status.push('\n');
+                status.push_str(&line);
+            }
+            if let Some(line) = session_status(&ctx, msg.author.id.get(), channel_id).await {
+                status.push('\n');
+                status.push_str(&line);
+            }
+            if let Some(line) = context_status(&ctx, msg.author.id.get(), channel_id, backend).await {
+                status.push('\n');
+                status.push_str(&line);
+            }
+            if let Some(line) = autoreset_status(&ctx, channel_id).await {
+                status.push('\n');
+                status.push_str(&line);
+            }
+            if let Some(line) = language_status(&ctx, channel_id).await {
+                status.push('\n');
+                status.push_str(&line);
+            }
+            if let Some(line) = active_channels_status(&Config::load().unwrap_or_default()) {
+                status.push('\n');
+                status.push_str(&line);
+            }
+            if let Some(state) = lifecycle::load_state() {
+                status.push('\n');
+                status.push_str(&state.describe());
+            }
+            if content == "!status all" && user_tier(&Config::load().unwrap_or_default(), msg.author.id.get()) == Some(Tier::Admin) {
+                status.push('\n');
+                status.push_str(&human_mode_summary(&ctx).await);
+            }
+            if let Some(free) = disk::low_space(Config::load().unwrap_or_default().min_free_disk_bytes()) {
+                status = format!("{}\n{}", disk::banner(free), status);
+            }
+            if is_dry_run() {
+                status = format!("{}\n{}", DRY_RUN_BANNER, status);
+            }
+            let _ = msg.channel_id.say(&ctx.http, status).await;
+            return;
+        }
+
+        // Handle autoreset command
+        if content == "!autoreset" || content.starts_with("!autoreset ") {
+            let arg = content.strip_prefix("!autoreset").unwrap_or("").trim().to_lowercase();
+            if arg.is_empty() {
+                let status = autoreset_status(&ctx, channel_id)
+                    .await
+                    .unwrap_or_else(|| "♻️ Auto-reset: off".to_string());
+                let _ = msg.channel_id.say(&ctx.http, format!("{}\nUsage: `!autoreset <hours|daily|off>`", status)).await;
+                return;
+            }
+            let policy = match AutoReset::parse(&arg) {
+                Ok(policy) => policy,
+                Err(e) => {
+                    let _ = msg.channel_id.say(&ctx.http, format!("❌ {}", e)).await;
+                    return;
+                }
+            };
+
+            let data = ctx.data.read().await;
+            if let Some(policies) = data.get::<ChannelAutoReset>() {
+                let mut map = policies.write().await;
+                match policy {
+                    Some(policy) => map.insert(channel_id, policy),
+                    None => map.remove(&channel_id),
+                };
+                save_channel_autoreset(&map);
+            }
+            let reply = match policy {
+                Some(policy) => format!("♻️ Sessions in this channel will start fresh {}.", policy.describe()),
+                None => "♻️ Auto-reset disabled for this channel.".to_string(),
+            };
+            let _ = msg.channel_id.say(&ctx.http, reply).await;
+            return;
+        }
+
+        // Handle moveto/handoff/copyfrom commands - hand a session off between channels
+        if let Some((command, args)) = ["moveto", "handoff", "copyfrom"].iter().find_map(|command| {
+            let rest = content.strip_prefix('!')?.strip_prefix(command)?;
+            (rest.is_empty() || rest.starts_with(' ')).then_some((*command, rest))
+        }) {
+            let reply = handoff_session(&ctx, &msg, command, args).await;
+            let _ = msg.channel_id.say(&ctx.http, reply).await;
+            return;
+        }
+
+        // Handle alias command - manage `!name` shortcuts for this server
+        if content == "!alias" || content.starts_with("!alias ") {
+            let args = content.strip_prefix("!alias").unwrap_or("").trim();
+            let (action, rest) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+            let mut config = Config::load().unwrap_or_default();
+            let is_builtin = |name: &str| find_command(name).is_some();
+
+            let reply = match action {
+                "" | "list" => alias::describe(config.aliases.get(&guild_key).unwrap_or(&Default::default())),
+                "add" => match alias::parse_add(rest, is_builtin) {
+                    Ok((name, new_alias)) => {
+                        let reply = format!("✅ `!{}` → {}", name, new_alias.expansion);
+                        config.aliases.entry(guild_key).or_default().insert(name, new_alias);
+                        match config.save() {
+                            Ok(()) => reply,
+                            Err(e) => format!("❌ Failed to save config: {}", e),
+                        }
+                    }
+                    Err(e) => format!("❌ {}", e),
+                },
+                "remove" => {
+                    let name = rest.trim().trim_start_matches('!').to_lowercase();
+                    let removed = config
+                        .aliases
+                        .get_mut(&guild_key)
+                        .and_then(|aliases| aliases.remove(&name))
+                        .is_some();
+                    if !removed {
+                        format!("❌ No alias named `!{}`.", name)
+                    } else {
+                        config.aliases.retain(|_, aliases| !aliases.is_empty());
+                        match config.save() {
+                            Ok(()) => format!("🗑️ Removed `!{}`.", name),
+                            Err(e) => format!("❌ Failed to save config: {}", e),
+                        }
+                    }
+                }
+                _ => "Usage: `!alias add <name> \"<prompt>\"`, `!alias add <name> --command \"!<command>\"`, `!alias list`, `!alias remove <name>`".to_string(),
+            };
+            let _ = msg.channel_id.say(&ctx.http, reply).await;
+            return;
+        }
+
+        // Handle respond-in command - pin the language responses in this channel use
+        if content == "!respond-in" || content.starts_with("!respond-in ") {
+            let arg = content.strip_prefix("!respond-in").unwrap_or("").trim();
+            if arg.is_empty() {
+                let status = language_status(&ctx, channel_id)
+                    .await
+                    .unwrap_or_else(|| "🌐 Responds in: any language".to_string());
+                let _ = msg.channel_id.say(&ctx.http, format!("{}\nUsage: `!respond-in <language|off>`", status)).await;
+                return;
+            }
+            if arg.chars().count() > MAX_LANGUAGE_CHARS {
+                let _ = msg.channel_id.say(&ctx.http, format!("❌ Keep it under {} characters.", MAX_LANGUAGE_CHARS)).await;
+                return;
+            }
+
+            let language = (!arg.eq_ignore_ascii_case("off")).then(|| arg.to_string());
+            let data = ctx.data.read().await;
+            // Sessions already running here learn about the change on their next message
+            let pending: HashSet<u64> = match data.get::<SessionStorage>() {
+                Some(sessions) => sessions
+                    .read()
+                    .await
+                    .keys()
+                    .filter(|(_, cid)| *cid == channel_id)
+                    .map(|(uid, _)| *uid)
+                    .collect(),
+                None => HashSet::new(),
+            };
+            if let Some(languages) = data.get::<ChannelLanguages>() {
+                let mut map = languages.write().await;
+                if language.is_none() && pending.is_empty() {
+                    map.remove(&channel_id);
+                } else {
+                    map.insert(channel_id, ChannelLanguage { language: language.clone(), pending });
+                }
+                save_channel_languages(&map);
+            }
+            let reply = match language {
+                Some(language) => format!("🌐 Responses in this channel will be in {}.", language),
+                None => "🌐 Response language cleared; Neywa replies in whatever language you write.".to_string(),
+            };
+            let _ = msg.channel_id.say(&ctx.http, reply).await;
+            return;
+        }
+
+        // Handle model command - pick the Claude model for this channel
+        if content == "!model" || content.starts_with("!model ") {
+            let arg = content.strip_prefix("!model").unwrap_or("").trim();
+            let backend = get_channel_backend(&ctx, channel_id).await;
+            let codex_note = if backend == AiBackend::Codex {
+                "\n_This channel is in Codex mode, which always uses its own model._"
+            } else {
+                ""
+            };
+            if arg.is_empty() {
+                let current = get_channel_model(&ctx, channel_id).await;
+                let _ = msg
+                    .channel_id
+                    .say(&ctx.http, format!("Model: {}\nUsage: `!model <name|reset>`{}", model_label(backend, current.as_deref()), codex_note))
+                    .await;
+                return;
+            }
+            if !is_valid_model_name(arg) {
+                let _ = msg.channel_id.say(&ctx.http, "❌ Give a model alias like `opus` or `haiku`, or a full model ID.").await;
+                return;
+            }
+
+            let reset = arg.eq_ignore_ascii_case("reset");
+            let data = ctx.data.read().await;
+            if let Some(models) = data.get::<ChannelModels>() {
+                let mut map = models.write().await;
+                if reset {
+                    map.remove(&channel_id);
+                } else {
+                    map.insert(channel_id, arg.to_string());
+                }
+                save_channel_models(&map);
+            }
+            let reply = if reset {
+                "Model reset to the CLI default.".to_string()
+            } else {
+                format!("Model set to `{}`. Takes effect on the next message.", arg)
+            };
+            let _ = msg.channel_id.say(&ctx.http, format!("{}{}", reply, codex_note)).await;
+            return;
+        }
+
+        // Handle cwd command - the directory this channel's CLI runs in
+        if content == "!cwd" || content.starts_with("!cwd ") {
+            let arg = content.strip_prefix("!cwd").unwrap_or("").trim();
+            let reply = set_channel_workdir(&ctx, channel_id, arg).await;
+            let _ = msg.channel_id.say(&ctx.http, reply).await;
+            return;
+        }
+
+        // Handle embed command - send this channel's responses as embeds or plain text
+        if content == "!embed" || content.starts_with("!embed ") {
+            let arg = content.strip_prefix("!embed").unwrap_or("").trim();
+            let setting = match arg {
+                "" => {
+                    let on = channel_embeds(&ctx, channel_id).await;
+                    let _ = msg.channel_id.say(&ctx.http, format!(
+                        "Responses here are sent as {}.\nUsage: `!embed <on|off|reset>`",
+                        if on { "embeds" } else { "plain text" }
+                    )).await;
+                    return;
+                }
+                "on" => Some(true),
+                "off" => Some(false),
+                "reset" => None,
+                _ => {
+                    let _ = msg.channel_id.say(&ctx.http, "Usage: `!embed <on|off|reset>`").await;
+                    return;
+                }
+            };
+            {
+                let data = ctx.data.read().await;
+                if let Some(embeds) = data.get::<ChannelEmbeds>() {
+                    let mut map = embeds.write().await;
+                    match setting {
+                        Some(on) => map.insert(channel_id, on),
+                        None => map.remove(&channel_id),
+                    };
+                    save_channel_embeds(&map);
+                }
+            }
+            let on = channel_embeds(&ctx, channel_id).await;
+            let reply = match setting {
+                Some(_) => format!("Responses here will be sent as {}.", if on { "embeds" } else { "plain text" }),
+                None => format!("Embed setting reset to the default ({}).", if on { "embeds" } else { "plain text" }),
+            };
+            let _ = msg.channel_id.say(&ctx.http, reply).await;
+            return;
+        }
+
+        // Handle longfile command - attach long responses as a file
+        if content == "!longfile" || content.starts_with("!longfile ") {
+            let arg = content.strip_prefix("!longfile").unwrap_or("").trim();
+            let describe = |limit: Option<usize>| match limit {
+                Some(limit) => format!("attached as a file past {} message(s)", limit),
+                None => "always posted as messages".to_string(),
+            };
+            let setting = match arg {
+                "" => {
+                    let limit = channel_long_file_limit(&ctx, channel_id).await;
+                    let _ = msg.channel_id.say(&ctx.http, format!(
+                        "Long responses here are {}.\nUsage: `!longfile <messages|off|reset>`",
+                        describe(limit)
+                    )).await;
+                    return;
+                }
+                "off" => Some(0),
+                "reset" => None,
+                arg => match arg.parse::<u32>() {
+                    Ok(limit) if limit > 0 => Some(limit),
+                    _ => {
+                        let _ = msg.channel_id.say(&ctx.http, "Usage: `!longfile <messages|off|reset>`, e.g. `!longfile 3`").await;
+                        return;
+                    }
+                },
+            };
+            {
+                let data = ctx.data.read().await;
+                if let Some(settings) = data.get::<ChannelLongFiles>() {
+                    let mut map = settings.write().await;
+                    match setting {
+                        Some(limit) => map.insert(channel_id, limit),
+                        None => map.remove(&channel_id),
+                    };
+                    save_channel_long_files(&map);
+                }
+            }
+            let limit = channel_long_file_limit(&ctx, channel_id).await;
+            let reply = match setting {
+                Some(_) => format!("📎 Long responses here will be {}.", describe(limit)),
+                None => format!("📎 Long-response setting reset to the default: {}.", describe(limit)),
+            };
+            let _ = msg.channel_id.say(&ctx.http, reply).await;
+            return;
+        }
+
+        // Handle timeout command - per-channel limit on how long one run may take
+        if content == "!timeout" || content.starts_with("!timeout ") {
+            let arg = content.strip_prefix("!timeout").unwrap_or("").trim();
+            let describe = |timeout: Option<Duration>| match timeout {
+                Some(timeout) => format!("stopped after {} minute(s)", timeout.as_secs() / 60),
+                None => "never stopped for taking too long".to_string(),
+            };
+            let setting = match arg {
+                "" => {
+                    let timeout = channel_run_timeout(&ctx, channel_id).await;
+                    let _ = msg.channel_id.say(&ctx.http, format!(
+                        "Runs here are {}.\nUsage: `!timeout <minutes|off|reset>`",
+                        describe(timeout)
+                    )).await;
+                    return;
+                }
+                "off" => Some(0),
+                "reset" => None,
+                arg => match arg.parse::<u64>() {
+                    Ok(minutes) if minutes > 0 => Some(minutes),
+                    _ => {
+                        let _ = msg.channel_id.say(&ctx.http, "Usage: `!timeout <minutes|off|reset>`, e.g. `!timeout 45`").await;
+                        return;
+                    }
+                },
+            };
+            {
+                let data = ctx.data.read().await;
+                if let Some(timeouts) = data.get::<ChannelTimeouts>() {
+                    let mut map = timeouts.write().await;
+                    match setting {
+                        Some(minutes) => map.insert(channel_id, minutes),
+                        None => map.remove(&channel_id),
+                    };
+                    save_channel_timeouts(&map);
+                }
+            }
+            let timeout = channel_run_timeout(&ctx, channel_id).await;
+            let reply = match setting {
+                Some(_) => format!("⏱️ Runs here will be {}.", describe(timeout)),
+                None => format!("⏱️ Timeout reset to the default: runs here are {}.", describe(timeout)),
+            };
+            let _ = msg.channel_id.say(&ctx.http, reply).await;
+            return;
+        }
+
+        // Handle threads command - give each request its own thread
+        if content == "!threads" || content.starts_with("!threads ") {
+            let arg = content.strip_prefix("!threads").unwrap_or("").trim();
+            let on = match arg {
+                "" => {
+                    let on = channel_threads(&ctx, channel_id).await;
+                    let _ = msg.channel_id.say(&ctx.http, format!(
+                        "Thread per request is {} here.\nUsage: `!threads <on|off>`",
+                        if on { "on" } else { "off" }
+                    )).await;
+                    return;
+                }
+                "on" => true,
+                "off" => false,
+                _ => {
+                    let _ = msg.channel_id.say(&ctx.http, "Usage: `!threads <on|off>`").await;
+                    return;
+                }
+            };
+            if on && !channel_info.kind.can_open_threads() {
+                let _ = msg.channel_id.say(&ctx.http, "⚠️ Threads can only be opened from text and announcement channels.").await;
+                return;
+            }
+            {
+                let data = ctx.data.read().await;
+                if let Some(threads) = data.get::<ThreadChannels>() {
+                    let mut channels = threads.write().await;
+                    if on {
+                        channels.insert(channel_id);
+                    } else {
+                        channels.remove(&channel_id);
+                    }
+                    save_thread_channels(&channels);
+                }
+            }
+            let reply = if on {
+                "🧵 Thread per request on: each request gets its own thread, and follow-ups in a thread continue its session."
+            } else {
+                "Thread per request off: answering in the channel."
+            };
+            let _ = msg.channel_id.say(&ctx.http, reply).await;
+            return;
+        }
+
+        // Handle mention command - only answer messages addressed to Neywa
+        if content == "!mention" || content.starts_with("!mention ") {
+            let arg = content.strip_prefix("!mention").unwrap_or("").trim();
+            let on = match arg {
+                "" => {
+                    let on = is_mention_channel(&ctx, channel_id).await;
+                    let _ = msg.channel_id.say(&ctx.http, format!(
+                        "Mention-only mode is {} here.\nUsage: `!mention <on|off>`",
+                        if on { "on" } else { "off" }
+                    )).await;
+                    return;
+                }
+                "on" => true,
+                "off" => false,
+                _ => {
+                    let _ = msg.channel_id.say(&ctx.http, "Usage: `!mention <on|off>`").await;
+                    return;
+                }
+            };
+            {
+                let data = ctx.data.read().await;
+                if let Some(channels) = data.get::<MentionChannels>() {
+                    let mut channels = channels.write().await;
+                    if on {
+                        channels.insert(channel_id);
+                    } else {
+                        channels.remove(&channel_id);
+                    }
+                    save_mention_channels(&channels);
+                }
+            }
+            let reply = if on {
+                let prefix = Config::load().unwrap_or_default().mention_prefix;
+                match prefix.as_deref().map(str::trim).filter(|p| !p.is_empty()) {
+                    Some(prefix) => format!("📣 Mention-only on: Neywa answers @mentions, replies to its messages and messages starting with `{}`. Commands still work.", prefix),
+                    None => "📣 Mention-only on: Neywa answers @mentions and replies to its messages. Commands still work.".to_string(),
+                }
+            } else {
+                "Mention-only off: Neywa answers every message here again.".to_string()
+            };
+            let _ = msg.channel_id.say(&ctx.http, reply).await;
+            return;
+        }
+
+        // `!wake`: ignore quiet hours in this channel until the current window ends
+        if content == "!wake" {
+            let Some(end) = quiet_window_end(&ctx, channel_id).await else {
+                let _ = msg.channel_id.say(&ctx.http, "It isn't quiet hours right now, so there's nothing to wake.").await;
+                return;
+            };
+            {
+                let data = ctx.data.read().await;
+                if let Some(woken) = data.get::<WokenChannels>() {
+                    let mut woken = woken.write().await;
+                    woken.insert(channel_id, end.with_timezone(&chrono::Utc));
+                    save_woken_channels(&woken);
+                }
+            }
+            let released = release_held_requests(&ctx, Some(channel_id)).await;
+            let held = if released > 0 { format!(" Starting {} held request(s) now.", released) } else { String::new() };
+            let _ = msg
+                .channel_id
+                .say(&ctx.http, format!("☀️ Awake: this channel runs and pings as usual until {}.{}", end.format("%H:%M"), held))
+                .await;
+            return;
+        }
+
+        // Handle shared command - one session for the whole channel
+        if content == "!shared" || content.starts_with("!shared ") {
+            let arg = content.strip_prefix("!shared").unwrap_or("").trim();
+            let shared = is_shared_channel(&ctx, channel_id).await;
+            let on = match arg {
+                "" => {
+                    let _ = msg.channel_id.say(&ctx.http, format!(
+                        "Sessions are {} here.\nUsage: `!shared <on|off>`",
+                        if shared { "shared" } else { "personal" }
+                    )).await;
+                    return;
+                }
+                "on" => true,
+                "off" => false,
+                _ => {
+                    let _ = msg.channel_id.say(&ctx.http, "Usage: `!shared <on|off>`").await;
+                    return;
+                }
+            };
+            if on == shared {
+                let _ = msg.channel_id.say(&ctx.http, format!(
+                    "Sessions are already {} here.",
+                    if shared { "shared" } else { "personal" }
+                )).await;
+                return;
+            }
+            let data = ctx.data.read().await;
+            let busy = match data.get::<ProcessingChannels>() {
+                Some(processing) => processing.read().await.contains_key(&channel_id),
+                None => false,
+            };
+            if busy {
+                let _ = msg.channel_id.say(&ctx.http, "⏳ A request is running here; switch session modes once it finishes.").await;
+                return;
+            }
+            // Switching modes starts everyone over, so it needs the same
+            // permission as `!new channel` when other people's sessions go
+            let removed = if let Some(sessions) = data.get::<SessionStorage>() {
+                let mut sessions_map = sessions.write().await;
+                let affects_others = sessions_map
+                    .keys()
+                    .any(|(uid, cid)| *cid == channel_id && *uid != user_id);
+                let tier = user_tier(&Config::load().unwrap_or_default(), user_id);
+                if affects_others && tier != Some(Tier::Admin) {
+                    let _ = msg.channel_id.say(&ctx.http, "⛔ Switching session modes clears other people's sessions here, which needs admin permission.").await;
+                    return;
+                }
+                let removed = clear_channel_sessions(&mut sessions_map, channel_id);
+                save_sessions(&sessions_map);
+                removed
+            } else {
+                0
+            };
+            if let Some(channels) = data.get::<SharedChannels>() {
+                let mut channels = channels.write().await;
+                if on {
+                    channels.insert(channel_id);
+                } else {
+                    channels.remove(&channel_id);
+                }
+                save_shared_channels(&channels);
+            }
+            let cleared = if removed > 0 {
+                format!(" Cleared {} existing session{}.", removed, if removed == 1 { "" } else { "s" })
+            } else {
+                String::new()
+            };
+            let reply = if on {
+                format!("👥 Shared session on: everyone here now talks to one conversation.{}", cleared)
+            } else {
+                format!("👤 Shared session off: each person gets their own session again.{}", cleared)
+            };
+            let _ = msg.channel_id.say(&ctx.http, reply).await;
+            return;
+        }
+
+        // Handle stream command - show responses while they are written
+        if content == "!stream" || content.starts_with("!stream ") {
+            let arg = content.strip_prefix("!stream").unwrap_or("").trim();
+            let setting = match arg {
+                "" => {
+                    let on = channel_streaming(&ctx, channel_id).await;
+                    let _ = msg.channel_id.say(&ctx.http, format!(
+                        "Live responses are {} here.\nUsage: `!stream <on|off|reset>`",
+                        if on { "on" } else { "off" }
+                    )).await;
+                    return;
+                }
+                "on" => Some(true),
+                "off" => Some(false),
+                "reset" => None,
+                _ => {
+                    let _ = msg.channel_id.say(&ctx.http, "Usage: `!stream <on|off|reset>`").await;
+                    return;
+                }
+            };
+            {
+                let data = ctx.data.read().await;
+                if let Some(streaming) = data.get::<ChannelStreaming>() {
+                    let mut map = streaming.write().await;
+                    match setting {
+                        Some(on) => map.insert(channel_id, on),
+                        None => map.remove(&channel_id),
+                    };
+                    save_channel_streaming(&map);
+                }
+            }
+            let on = channel_streaming(&ctx, channel_id).await;
+            let reply = match setting {
+                Some(_) => format!("Live responses {}.", if on { "on: answers appear while they are written" } else { "off" }),
+                None => format!("Stream setting reset to the default ({}).", if on { "on" } else { "off" }),
+            };
+            let _ = msg.channel_id.say(&ctx.http, reply).await;
+            return;
+        }
+
+        // Handle done-style command - customize this channel's completion message
+        if content == "!done-style" || content.starts_with("!done-style ") {
+            let arg = content.strip_prefix("!done-style").unwrap_or("").trim();
+            if arg.is_empty() {
+                let style = channel_done_style(&ctx, channel_id).await;
+                let _ = msg.channel_id.say(&ctx.http, format!("Completion message: {}\nUsage: `!done-style <text|reset>`", style)).await;
+                return;
+            }
+            if arg.chars().count() > MAX_DONE_STYLE_CHARS {
+                let _ = msg.channel_id.say(&ctx.http, format!("❌ Keep it under {} characters.", MAX_DONE_STYLE_CHARS)).await;
+                return;
+            }
+
+            let reset = arg.eq_ignore_ascii_case("reset");
+            let data = ctx.data.read().await;
+            if let Some(styles) = data.get::<ChannelDoneStyles>() {
+                let mut map = styles.write().await;
+                if reset {
+                    map.remove(&channel_id);
+                } else {
+                    map.insert(channel_id, arg.to_string());
+                }
+                save_channel_done_styles(&map);
+            }
+            let reply = if reset {
+                format!("Completion message reset to: {}", DEFAULT_DONE_STYLE)
+            } else {
+                format!("Completion message set to: {}", arg)
+            };
+            let _ = msg.channel_id.say(&ctx.http, reply).await;
+            return;
+        }
+
+        // Handle prune-state command - drop state kept for deleted channels
+        if content == "!prune-state" || content.starts_with("!prune-state ") {
+            let dry_run = match content.strip_prefix("!prune-state").unwrap_or("").trim() {
+                "" => false,
+                "dry-run" => true,
+                _ => {
+                    let _ = msg.channel_id.say(&ctx.http, "Usage: `!prune-state [dry-run]`").await;
+                    return;
+                }
+            };
+            let reply = match reconcile_channel_state(&ctx, dry_run).await {
+                Ok(report) => report.summary(dry_run),
+                Err(e) => format!("❌ Couldn't list channels: {}", e),
+            };
+            let _ = msg.channel_id.say(&ctx.http, reply).await;
+            return;
+        }
+
+        // Handle sessions command - list tracked sessions or clear them
+        if content == "!sessions" || content.starts_with("!sessions ") {
+            let args = content.strip_prefix("!sessions").unwrap_or("").trim();
+            let reply = manage_sessions(&ctx, &msg, args).await;
+            say_without_pings(&ctx.http, msg.channel_id, &reply).await;
+            return;
+        }
+
+        // Handle remind command - schedule a prompt to run later or on a cron schedule
+        if content == "!remind" || content.starts_with("!remind ") {
+            let args = content.strip_prefix("!remind").unwrap_or("").trim();
+            let reply = add_reminder(&ctx, &msg, args).await;
+            say_without_pings(&ctx.http, msg.channel_id, &reply).await;
+            return;
+        }
+
+        // Handle schedule command - list or delete scheduled jobs
+        if content == "!schedule" || content.starts_with("!schedule ") {
+            let args = content.strip_prefix("!schedule").unwrap_or("").trim();
+            let reply = manage_schedule(&ctx, &msg, args).await;
+            say_without_pings(&ctx.http, msg.channel_id, &reply).await;
+            return;
+        }
+
+        // Handle cron command - list or remove `neywa run` jobs older versions put in the crontab
+        if content == "!cron" || content.starts_with("!cron ") {
+            let args = content.strip_prefix("!cron").unwrap_or("").trim();
+            let (action, rest) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+            let reply = match tokio::task::spawn_blocking(cron::read_crontab).await {
+                Ok(Ok(crontab)) => {
+                    let entries = cron::parse(&crontab);
+                    match action {
+                        "import" | "list" => {
+                            let mut reply = cron::describe(&entries);
+                            if !entries.is_empty() {
+                                reply.push_str("\nMove into `!schedule` with `!cron migrate <numbers|all> confirm`, or remove with `!cron remove <numbers|all> confirm`.");
+                            }
+                            reply
+                        }
+                        "migrate" => migrate_cron_jobs(&ctx, &msg, &entries, rest).await,
+                        "remove" => {
+                            let (selection, confirmed) = match rest.trim().strip_suffix("confirm") {
+                                Some(selection) => (selection.trim(), true),
+                                None => (rest.trim(), false),
+                            };
+                            match cron::select(&entries, selection) {
+                                Err(e) => format!("❌ {}", e),
+                                Ok(selected) if !confirmed => {
+                                    let lines: Vec<String> = selected
+                                        .iter()
+                                        .map(|e| format!("• `{}` {}", e.schedule, e.command))
+                                        .collect();
+                                    format!(
+                                        "⚠️ This removes from the crontab:\n{}\nSend `!cron remove {} confirm` to continue.",
+                                        lines.join("\n"),
+                                        selection
+                                    )
+                                }
+                                Ok(selected) => match tokio::task::spawn_blocking(move || cron::remove(&selected)).await {
+                                    Ok(Ok(backup)) => format!("🗑️ Removed. Original crontab saved to `{}`.", backup.display()),
+                                    Ok(Err(e)) => format!("❌ {}", e),
+                                    Err(e) => format!("❌ {}", e),
+                                },
+                            }
+                        }
+                        _ => "Usage: `!cron import`, `!cron migrate <numbers|all> confirm`, `!cron remove <numbers|all> confirm`".to_string(),
+                    }
+                }
+                Ok(Err(e)) => format!("❌ {}", e),
+                Err(e) => format!("❌ {}", e),
+            };
+            let _ = msg.channel_id.say(&ctx.http, reply).await;
+            return;
+        }
+
+        // Handle setup command - create the recommended channels that are missing
+        if content == "!setup" {
+            let Some(guild_id) = msg.guild_id else {
+                let _ = msg.channel_id.say(&ctx.http, "❌ `!setup` only works in a server.").await;
+                return;
+            };
+            let missing = match guild_id.channels(&ctx.http).await {
+                Ok(channels) => missing_standard_channels(&channels),
+                Err(e) => {
+                    let _ = msg.channel_id.say(&ctx.http, format!("❌ Failed to list channels: {}", e)).await;
+                    return;
+                }
+            };
+            if missing.is_empty() {
+                let _ = msg.channel_id.say(&ctx.http, "✅ All recommended channels already exist.").await;
+                return;
+            }
+
+            let mut created = Vec::new();
+            let mut failed = Vec::new();
+            for name in &missing {
+                let builder = serenity::builder::CreateChannel::new(*name)
+                    .kind(serenity::model::channel::ChannelType::Text);
+                match guild_id.create_channel(&ctx.http, builder).await {
+                    Ok(channel) => created.push(format!("<#{}>", channel.id)),
+                    Err(e) => failed.push(format!("#{} ({})", name, e)),
+                }
+            }
+            if missing.contains(&"logs") && current_logs_channel(&ctx).await.is_none() {
+                discover_logs_channel(&ctx).await;
+            }
+
+            let mut reply = String::new();
+            if !created.is_empty() {
+                reply.push_str(&format!("✅ Created {}", created.join(", ")));
+            }
+            if !failed.is_empty() {
+                if !reply.is_empty() {
+                    reply.push('\n');
+                }
+                reply.push_str(&format!("❌ Couldn't create {} (does the bot have Manage Channels?)", failed.join(", ")));
+            }
+            let _ = msg.channel_id.say(&ctx.http, reply).await;
+            return;
+        }
+
+        // Handle logs command - bind this channel as the logs destination, or go back to discovery
+        let logs_arg = ["!logs", "!logchannel"].iter().find_map(|cmd| match content.strip_prefix(cmd) {
+            Some(rest) if rest.is_empty() || rest.starts_with(' ') => Some(rest.trim()),
+            _ => None,
+        });
+        if logs_arg.is_some_and(|arg| arg != "here" && arg != "auto") {
+            let config = Config::load().unwrap_or_default();
+            let reply = match current_logs_channel(&ctx).await {
+                Some(channel) if config.logs_channel_bound => format!("📋 Logs go to <#{}> (bound with `!logs here`).", channel),
+                Some(channel) => format!("📋 Logs go to <#{}> (found by name).", channel),
+                None => "📋 No logs channel; logging to Discord is off.".to_string(),
+            };
+            let _ = msg.channel_id.say(&ctx.http, format!("{}\nUsage: `!logs here` / `!logs auto`", reply)).await;
+            return;
+        }
+        if let Some(arg) = logs_arg {
+            let mut config = Config::load().unwrap_or_default();
+            let reply = if arg == "here" {
+                set_logs_channel(&ctx, &mut config, Some(msg.channel_id), true).await;
+                "📋 Logs will be posted here.".to_string()
+            } else {
+                config.logs_channel_bound = false;
+                if let Err(e) = config.save() {
+                    tracing::warn!("Failed to save logs channel: {}", e);
+                }
+                match discover_logs_channel(&ctx).await {
+                    Some(channel) => format!("📋 Logs channel found by name: <#{}>", channel),
+                    None => "📋 No channel named #logs found; logging is off until one exists.".to_string(),
+                }
+            };
+            let _ = msg.channel_id.say(&ctx.http, reply).await;
+            return;
+        }
+
+        // Handle activate/deactivate commands (edit `active_channels`)
+        if content == "!activate" || content == "!deactivate" {
+            let activate = content == "!activate";
+            let mut config = match Config::load() {
+                Ok(config) => config,
+                Err(e) => {
+                    let _ = msg.channel_id.say(&ctx.http, format!("❌ Failed to load config: {}", e)).await;
+                    return;
+                }
+            };
+
+            let reply = match (&mut config.active_channels, activate) {
+                (None, true) => None,
+                (Some(channels), true) => {
+                    if !channels.contains(&channel_id) {
+                        channels.push(channel_id);
+                    }
+                    Some("✅ Neywa is now active in this channel.".to_string())
+                }
+                (Some(channels), false) => {
+                    channels.retain(|id| *id != channel_id);
+                    Some("🔕 Neywa is now inactive in this channel. Use `!activate` here to turn it back on.".to_string())
+                }
+                (None, false) => {
+                    // Switch to whitelist mode with every other text channel still active
+                    let Some(guild_id) = msg.guild_id else {
+                        let _ = msg.channel_id.say(&ctx.http, "❌ `!deactivate` only works in server channels.").await;
+                        return;
+                    };
+                    match guild_id.channels(&ctx.http).await {
+                        Ok(channels) => {
+                            config.active_channels = Some(
+                                channels
+                                    .values()
+                                    .filter(|c| c.kind == serenity::model::channel::ChannelType::Text)
+                                    .map(|c| c.id.get())
+                                    .filter(|id| *id != channel_id)
+                                    .collect(),
+                            );
+                            Some("🔕 Neywa is now inactive in this channel (whitelist mode enabled; all other channels stay active).".to_string())
+                        }
+                        Err(e) => {
+                            let _ = msg.channel_id.say(&ctx.http, format!("❌ Failed to list channels: {}", e)).await;
+                            return;
+                        }
+                    }
+                }
+            };
+
+            let Some(reply) = reply else {
+                let _ = msg.channel_id.say(&ctx.http, "✅ Neywa is already active in every channel.").await;
+                return;
+            };
+            if let Err(e) = config.save() {
+                let _ = msg.channel_id.say(&ctx.http, format!("❌ Failed to save config: {}", e)).await;
+                return;
+            }
+            let _ = msg.channel_id.say(&ctx.http, reply).await;
+            return;
+        }
+
+        // Handle queue commands: !queue [list], !queue remove <n>, !queue clear [confirm]
+        if content == "!queue" || content == "!대기열" || content.starts_with("!queue ") {
+            let args: Vec<&str> = content.split_whitespace().skip(1).collect();
+            let is_admin = is_admin_tier(&Config::load().unwrap_or_default(), user_id);
+            let reply = match args.as_slice() {
+                [] | ["list"] => queue_status(&ctx, channel_id).await,
+                ["remove", position] => match position.trim_start_matches('#').parse::<usize>() {
+                    Ok(position) if position > 0 => {
+                        let removed = {
+                            let data = ctx.data.read().await;
+                            match data.get::<MessageQueue>() {
+                                Some(queue) => remove_queued(&mut *queue.write().await, channel_id, position, user_id, is_admin),
+                                None => Err(QueueRemoveError::NotFound),
+                            }
+                        };
+                        match removed {
+                            Ok(queued) => {
+                                if let Some(notice) = queued.notice {
+                                    let _ = msg.channel_id.delete_message(&ctx.http, notice).await;
+                                }
+                                refresh_queue_notices(&ctx, channel_id, position - 1).await;
+                                format!(
+                                    "🗑️ Removed #{} ({}: {})",
+                                    position,
+                                    queued.msg.author.name,
+                                    claude::truncate_str(&queued.content.replace('\n', " "), 60)
+                                )
+                            }
+                            Err(QueueRemoveError::NotFound) => {
+                                format!("There's no #{} in the queue; it may have started already. See `!queue`.", position)
+                            }
+                            Err(QueueRemoveError::NotYours(author)) => {
+                                format!("⚠️ #{} is {}'s request; only they or an admin can remove it.", position, author)
+                            }
+                        }
+                    }
+                    _ => "Usage: `!queue remove <n>`, with the position shown in `!queue`".to_string(),
+                },
+                ["clear"] | ["clear", "confirm"] => {
+                    let confirmed = args.len() == 2 || is_admin;
+                    let cleared = {
+                        let data = ctx.data.read().await;
+                        match data.get::<MessageQueue>() {
+                            Some(queue) => {
+                                let mut queues = queue.write().await;
+                                let foreign = queues
+                                    .get(&channel_id)
+                                    .is_some_and(|q| q.iter().any(|m| m.msg.author.id.get() != user_id));
+                                if foreign && !confirmed {
+                                    None
+                                } else {
+                                    Some(queues.remove(&channel_id).unwrap_or_default())
+                                }
+                            }
+                            None => Some(VecDeque::new()),
+                        }
+                    };
+                    match cleared {
+                        None => "⚠️ The queue has other people's requests too. Use `!queue clear confirm` to clear it anyway.".to_string(),
+                        Some(cleared) if cleared.is_empty() => "📭 Queue is already empty.".to_string(),
+                        Some(cleared) => {
+                            for notice in cleared.iter().filter_map(|m| m.notice) {
+                                let _ = msg.channel_id.delete_message(&ctx.http, notice).await;
+                            }
+                            format!("📭 Cleared {} queued message(s). The running request continues; `!stop` stops it.", cleared.len())
+                        }
+                    }
+                }
+                _ => "Usage: `!queue [list]`, `!queue remove <n>`, `!queue clear`".to_string(),
+            };
+            let _ = msg.channel_id.say(&ctx.http, reply).await;
+            return;
+        }
+
+        // Handle compact command
+        if content == "!compact" {
+            let current_backend = get_channel_backend(&ctx, channel_id).await;
+            if current_backend == AiBackend::Codex {
+                let _ = msg.channel_id.say(&ctx.http, "⚠️ Codex 모드에서는 compact를 지원하지 않습니다. `!new`로 새 세션을 시작하세요.").await;
+                return;
+            }
+
+            let existing_session = {
+                let data = ctx.data.read().await;
+                if let Some(sessions) = data.get::<SessionStorage>() {
+                    sessions.read().await.get(&session_key).map(|s| s.id.clone())
+                } else {
+                    None
+                }
+            };
+
+            if let Some(sid) = existing_session {
+                let _ = msg.channel_id.say(&ctx.http, "🗜️ Compacting session...").await;
+
+                let use_z = current_backend == AiBackend::ClaudeZ;
+                let workdir = get_channel_workdir(&ctx, channel_id).await;
+
+                match claude::compact_session(&sid, use_z, workdir.as_deref()).await {
+                    Ok(_) => {
+                        let _ = msg.channel_id.say(&ctx.http, "✅ Session compacted.").await;
+                    }
+                    Err(e) => {
+                        // Try trim as fallback
+                        if trim_session_file(&sid, workdir.as_deref()) {
+                            let _ = msg.channel_id.say(&ctx.http, "⚠️ Compact failed, trimmed old messages instead.").await;
+                        } else {
+                            let _ = msg.channel_id.say(&ctx.http, format!("❌ Compact failed: {}", e)).await;
+                        }
+                    }
+                }
+            } else {
+                let _ = msg.channel_id.say(&ctx.http, "No active session. Nothing to compact.").await;
+            }
+            return;
+        }
+
+        // Handle slash command passthrough
+        if content.starts_with("!slash ") {
+            let current_backend = get_channel_backend(&ctx, channel_id).await;
+            if current_backend == AiBackend::Codex {
+                let _ = msg.channel_id.say(&ctx.http, "ℹ️ Codex 모드에서는 slash 명령을 지원하지 않습니다.").await;
+                return;
+            }
+
+            let slash_cmd = content.trim_start_matches("!slash ").trim().to_string();
+            if slash_cmd.is_empty() {
+                let _ = msg.channel_id.say(&ctx.http, "Usage: `!slash <command>` (e.g., `!slash compact`, `!slash cost`)").await;
+                return;
+            }
+
+            let existing_session = {
+                let data = ctx.data.read().await;
+                if let Some(sessions) = data.get::<SessionStorage>() {
+                    sessions.read().await.get(&session_key).map(|s| s.id.clone())
+                } else {
+                    None
+                }
+            };
+
+            let use_z = current_backend == AiBackend::ClaudeZ;
+            let workdir = get_channel_workdir(&ctx, channel_id).await;
+
+            let display_cmd = slash_cmd.trim_start_matches('/');
+            let _ = msg.channel_id.say(&ctx.http, format!("⚡ Running `/{}`...", display_cmd)).await;
+
+            match claude::run_slash_command(&slash_cmd, existing_session.as_deref(), use_z, workdir.as_deref()).await {
+                Ok(result) => {
                     let chunks = split_for_discord(&result);
                     for chunk in chunks {
                         let _ = msg.channel_id.say(&ctx.http, &chunk).await;
                     }
                 }
-                Err(e) => {
-                    let _ = msg.channel_id.say(&ctx.http, format!("❌ Error: {}", e)).await;
+                Err(e) => {
+                    let _ = msg.channel_id.say(&ctx.http, format!("❌ Error: {}", e)).await;
+                }
+            }
+            return;
+        }
+
+        // Handle plan command - run Claude in plan-only mode
+        if content.starts_with("!plan ") || content.starts_with("!계획 ") {
+            let current_backend = get_channel_backend(&ctx, channel_id).await;
+            if current_backend == AiBackend::Codex {
+                let _ = msg.channel_id.say(&ctx.http, "⚠️ Codex 모드에서는 plan mode를 지원하지 않습니다.").await;
+                return;
+            }
+            let plan_msg = content
+                .strip_prefix("!plan ")
+                .or_else(|| content.strip_prefix("!계획 "))
+                .unwrap_or("")
+                .trim()
+                .to_string();
+
+            if plan_msg.is_empty() {
+                let _ = msg.channel_id.say(&ctx.http, "Usage: `!plan <request>`").await;
+                return;
+            }
+
+            if !check_quota(&ctx, &msg).await {
+                return;
+            }
+
+            let msg = thread_for_request(&ctx, &msg, channel_info.kind, &plan_msg).await;
+            let queued = QueuedMessage {
+                msg: msg.clone(),
+                content: plan_msg,
+                attachments,
+                channel_type,
+                is_plan_mode: true,
+                urgent: false,
+                priority: is_admin_tier(&Config::load().unwrap_or_default(), user_id),
+                notice: None,
+                enqueued_at: Instant::now(),
+            };
+
+            // Use same queue/processing logic as normal messages
+            Self::submit(&ctx, queued).await;
+            return;
+        }
+
+        // Handle retry command - run this channel's last request again
+        if content == "!retry" || content.starts_with("!retry ") {
+            let arg = content.strip_prefix("!retry").unwrap_or("").trim();
+            if !arg.is_empty() && arg != "new" {
+                let _ = msg.channel_id.say(&ctx.http, "Usage: `!retry [new]`").await;
+                return;
+            }
+            let last = {
+                let data = ctx.data.read().await;
+                match data.get::<LastRequests>() {
+                    Some(last) => last.read().await.get(&channel_id).cloned(),
+                    None => None,
+                }
+            };
+            let Some(mut queued) = last else {
+                let _ = msg.channel_id.say(&ctx.http, "Nothing to retry in this channel yet.").await;
+                return;
+            };
+            // Same rule as !stop: your own request, or anyone's for admins
+            let author_id = queued.msg.author.id.get();
+            if !may_act_for(author_id, user_id, user_tier(&Config::load().unwrap_or_default(), user_id)) {
+                let _ = msg.channel_id.say(&ctx.http, format!(
+                    "⛔ The last request here was {}'s; only they or an admin can retry it.",
+                    queued.msg.author.name
+                )).await;
+                return;
+            }
+            if !check_quota(&ctx, &queued.msg).await {
+                return;
+            }
+            let fresh = arg == "new";
+            if fresh {
+                let key = session_key_for(&ctx, author_id, channel_id).await;
+                let data = ctx.data.read().await;
+                if let Some(sessions) = data.get::<SessionStorage>() {
+                    let mut sessions = sessions.write().await;
+                    sessions.remove(&key);
+                    save_sessions(&sessions);
+                }
+            }
+            queued.notice = None;
+            queued.enqueued_at = Instant::now();
+            let _ = msg.channel_id.say(&ctx.http, format!(
+                "🔁 Retrying{}: {}",
+                if fresh { " in a fresh session" } else { "" },
+                claude::truncate_str(queued.content.lines().next().unwrap_or(""), 80)
+            )).await;
+            Self::submit(&ctx, queued).await;
+            return;
+        }
+
+        // Handle timing command - phase breakdown of this channel's last run
+        if content == "!timing" {
+            let reply = timing_report(&ctx, channel_id).await;
+            let _ = msg.channel_id.say(&ctx.http, reply).await;
+            return;
+        }
+
+        // Handle history command - recent messages of this channel's session
+        if content == "!history" || content.starts_with("!history ") {
+            let args = content.strip_prefix("!history").unwrap_or("").trim();
+            let reply = session_history(&ctx, user_id, channel_id, args).await;
+            for chunk in split_for_discord(&reply) {
+                let _ = msg.channel_id.say(&ctx.http, &chunk).await;
+            }
+            return;
+        }
+
+        // Handle undo command - drop the last exchange from the session
+        if content == "!undo" {
+            let reply = undo_exchange(&ctx, user_id, channel_id).await;
+            let _ = msg.channel_id.say(&ctx.http, reply).await;
+            return;
+        }
+
+        // Handle export command - the session as a markdown document
+        if content == "!export" {
+            export_session(&ctx, &msg).await;
+            return;
+        }
+
+        // Handle prompt command - show the channel's system prompt and its source
+        if content == "!prompt" {
+            let reply = prompt_report(&ctx, msg.channel_id).await;
+            for chunk in split_for_discord(&reply) {
+                let _ = msg.channel_id.say(&ctx.http, &chunk).await;
+            }
+            return;
+        }
+
+        // Handle context command - preview what the next message will send
+        if content == "!context" {
+            let preview = context_preview(&ctx, &msg, &channel_type).await;
+            for chunk in split_for_discord(&preview) {
+                let _ = msg.channel_id.say(&ctx.http, &chunk).await;
+            }
+            return;
+        }
+
+        // Handle digest command - post today's digest now (for testing)
+        if content == "!digest now" {
+            let summary = digest::summarize(Local::now().date_naive());
+            if summary.is_empty() {
+                let _ = msg.channel_id.say(&ctx.http, "📊 No activity today, nothing to digest.").await;
+                return;
+            }
+            let target = logs_channel_or(&ctx, msg.channel_id).await;
+            post_digest(&ctx, target, &summary).await;
+            if target != msg.channel_id {
+                let _ = msg.channel_id.say(&ctx.http, "📊 Digest posted to #logs.").await;
+            }
+            return;
+        }
+
+        // Handle stats command - runs, time, tokens and cost by channel and user
+        if content == "!stats" || content.starts_with("!stats ") {
+            let args = content.strip_prefix("!stats").unwrap_or("").trim();
+            let reply = match stats::Period::parse(args) {
+                Some(period) => tokio::task::spawn_blocking(move || stats::report(period, |id| format!("<#{}>", id)))
+                    .await
+                    .unwrap_or_else(|e| format!("❌ Couldn't read stats: {}", e)),
+                None => "Usage: `!stats [day|week]`".to_string(),
+            };
+            for chunk in split_for_discord(&reply) {
+                let _ = msg.channel_id.say(&ctx.http, &chunk).await;
+            }
+            return;
+        }
+
+        // Handle cost command - spend for this session, channel and overall
+        if content == "!cost" {
+            let reply = cost_report(&ctx, user_id, channel_id).await;
+            let _ = msg.channel_id.say(&ctx.http, reply).await;
+            return;
+        }
+
+        // Handle usage command - show today's quota for the invoking user
+        if content == "!usage" || content == "!usage me" {
+            let config = Config::load().unwrap_or_default();
+            let usage = {
+                let data = ctx.data.read().await;
+                match data.get::<UsageStorage>() {
+                    Some(store) => Some(store.write().await.today(user_id).clone()),
+                    None => None,
+                }
+            };
+            let Some(usage) = usage else {
+                return;
+            };
+
+            let mut lines = vec![format!("📊 **Usage today** ({})", msg.author.name)];
+            match config.user_daily_quota {
+                Some(limit) if !quota_exempt(&config, user_id) => lines.push(format!(
+                    "Requests: {} / {} ({} remaining)",
+                    usage.requests, limit, limit.saturating_sub(usage.requests)
+                )),
+                _ => lines.push(format!("Requests: {} (no limit)", usage.requests)),
+            }
+            if usage.cost_usd > 0.0 || config.user_daily_cost_limit.is_some() {
+                match config.user_daily_cost_limit {
+                    Some(limit) if !quota_exempt(&config, user_id) => {
+                        lines.push(format!("Cost: ${:.2} / ${:.2}", usage.cost_usd, limit))
+                    }
+                    _ => lines.push(format!("Cost: ${:.2}", usage.cost_usd)),
+                }
+            }
+            lines.push(format!("Resets at {}", usage::format_reset()));
+
+            let _ = msg.channel_id.say(&ctx.http, lines.join("\n")).await;
+            return;
+        }
+
+        // Handle restart command - kills Neywa's Claude Code/Codex processes and resets state.
+        // `--all` also sweeps every matching process on the machine, including ones started by hand.
+        if content == "!restart" || content == "!재시작" || content == "!restart --all" {
+            let sweep_all = content.ends_with("--all");
+            let _ = msg.channel_id.say(&ctx.http, "🔄 Restarting all sessions...").await;
+
+            let data = ctx.data.read().await;
+            let mut cancelled_count = 0u32;
+            let mut cleared_count = 0u32;
+
+            // 1. Cancel all active processing (triggers CancellationToken)
+            if let Some(processing) = data.get::<ProcessingChannels>() {
+                let runs = processing.read().await;
+                for run in runs.values() {
+                    run.cancel_token.cancel();
+                    cancelled_count += 1;
+                }
+            }
+
+            // 2. Clear all message queues
+            if let Some(queue) = data.get::<MessageQueue>() {
+                let mut q = queue.write().await;
+                for (_ch, channel_queue) in q.drain() {
+                    cleared_count += channel_queue.len() as u32;
+                }
+            }
+
+            // 3. Clear all session IDs (forces fresh Claude Code sessions)
+            if let Some(sessions) = data.get::<SessionStorage>() {
+                let mut sessions_map = sessions.write().await;
+                sessions_map.clear();
+                save_sessions(&sessions_map);
+            }
+
+            drop(data);
+
+            // 4. Kill the claude/claude-z/codex processes Neywa spawned
+            let killed_count = children::kill_all();
+            if sweep_all {
+                let _ = Command::new("pkill")
+                    .arg("-f")
+                    .arg("claude.*--dangerously-skip-permissions")
+                    .stdin(std::process::Stdio::null())
+                    .stdout(std::process::Stdio::null())
+                    .stderr(std::process::Stdio::null())
+                    .status();
+
+                let _ = Command::new("pkill")
+                    .arg("-f")
+                    .arg("codex exec")
+                    .stdin(std::process::Stdio::null())
+                    .stdout(std::process::Stdio::null())
+                    .stderr(std::process::Stdio::null())
+                    .status();
+            }
+
+            // Brief wait for processes to clean up
+            tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+            let processes = if sweep_all {
+                "• ⚠️ Every matching claude/codex process on this machine terminated, including ones not started by Neywa".to_string()
+            } else {
+                format!("• Terminated {} Neywa-started Claude Code/Codex process(es)", killed_count)
+            };
+            let _ = msg.channel_id.say(&ctx.http, format!(
+                "✅ Sessions restarted.\n\
+                 • Cancelled {} active task(s)\n\
+                 • Cleared {} queued message(s)\n\
+                 • All session history reset\n\
+                 {}\n\n\
+                 Ready for new messages!",
+                cancelled_count, cleared_count, processes
+            )).await;
+            return;
+        }
+
+        // Handle update command
+        if content == "!update" {
+            let _ = msg.channel_id.say(&ctx.http, "🔄 Checking for updates...").await;
+
+            // Fetch remote version
+            let remote_version = match update::fetch_remote_version().await {
+                Ok(v) => v,
+                Err(e) => {
+                    let _ = msg.channel_id.say(&ctx.http, format!("❌ Failed to check version: {}", e)).await;
+                    return;
+                }
+            };
+
+            // Compare versions
+            if remote_version == VERSION {
+                let _ = msg.channel_id.say(&ctx.http, format!("✅ Already on the latest version (v{})", VERSION)).await;
+                return;
+            }
+
+            let _ = msg.channel_id.say(&ctx.http, format!("📥 New version available: v{} → v{}", VERSION, remote_version)).await;
+
+            match update::self_update().await {
+                Ok(()) => {
+                    // Save pending update info for notification after restart
+                    let pending = update::PendingUpdate {
+                        channel_id: msg.channel_id.get(),
+                        old_version: VERSION.to_string(),
+                        new_version: remote_version.clone(),
+                    };
+                    if let Err(e) = update::save_pending(&pending) {
+                        tracing::warn!("Failed to save update pending info: {}", e);
+                    }
+
+                    let _ = msg.channel_id.say(&ctx.http, "✅ Update downloaded. Restarting...").await;
+                    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+                    update::restart();
+                }
+                Err(e) => {
+                    let _ = msg.channel_id.say(&ctx.http, format!("❌ Update failed: {}", e)).await;
+                }
+            }
+            return;
+        }
+
+        // `!steer <correction>`: redirect the running request instead of queueing
+        // behind it. With nothing running it's an ordinary message.
+        let content = match strip_command(&content, "!steer") {
+            Some("") => {
+                let _ = msg.channel_id.say(&ctx.http, "Usage: `!steer <correction>`, e.g. `!steer use the v2 API instead`").await;
+                return;
+            }
+            Some(correction) => {
+                if Self::steer(&ctx, &msg, correction).await {
+                    return;
+                }
+                correction.to_string()
+            }
+            None => content,
+        };
+
+        // `!urgent <request>`: an ordinary request that still pings during quiet hours
+        let (content, urgent) = match strip_urgent(&content) {
+            Some(rest) => (rest.to_string(), true),
+            None => (content, false),
+        };
+
+        // Skip if empty content and no attachments
+        if content.is_empty() && attachments.is_empty() {
+            return;
+        }
+
+        // Merge into a pending batch from the same author (coalescing window)
+        {
+            let data = ctx.data.read().await;
+            if let Some(batches) = data.get::<PendingBatches>() {
+                let mut batches = batches.write().await;
+                if let Some(batch) = batches.get_mut(&channel_id) {
+                    if batch.msg.author.id == msg.author.id {
+                        if !content.is_empty() {
+                            if !batch.content.is_empty() {
+                                batch.content.push('\n');
+                            }
+                            batch.content.push_str(&content);
+                        }
+                        batch.attachments.extend(attachments);
+                        batch.urgent |= urgent;
+                        tracing::info!("Coalesced message from {} into pending batch", msg.author.name);
+                        return;
+                    }
+                }
+            }
+        }
+
+        tracing::info!("Message from {} in {:?}: {}", msg.author.name, channel_type, content);
+
+        if !check_quota(&ctx, &msg).await {
+            return;
+        }
+
+        // `!threads`: from here on the request lives in its own thread
+        let msg = thread_for_request(&ctx, &msg, channel_info.kind, &content).await;
+        let channel_id = msg.channel_id.get();
+
+        // Create queued message
+        let queued = QueuedMessage {
+            msg: msg.clone(),
+            content,
+            attachments,
+            channel_type,
+            is_plan_mode: false,
+            urgent,
+            priority: is_admin_tier(&Config::load().unwrap_or_default(), user_id),
+            notice: None,
+            enqueued_at: Instant::now(),
+        };
+
+        if hold_for_quiet_hours(&ctx, &queued).await {
+            return;
+        }
+
+        // Check if channel is currently processing
+        let is_processing = {
+            let data = ctx.data.read().await;
+            if let Some(processing) = data.get::<ProcessingChannels>() {
+                processing.read().await.contains_key(&channel_id)
+            } else {
+                false
+            }
+        };
+
+        if is_processing {
+            // Add to queue
+            let queue_pos = {
+                let data = ctx.data.read().await;
+                if let Some(queue) = data.get::<MessageQueue>() {
+                    let mut q = queue.write().await;
+                    enqueue(q.entry(channel_id).or_default(), queued)
+                } else {
+                    0
+                }
+            };
+            announce_queued(&ctx, &msg, queue_pos).await;
+        } else {
+            // Start processing immediately
+            let cancel_token = CancellationToken::new();
+
+            // Mark as processing
+            {
+                let data = ctx.data.read().await;
+                if let Some(processing) = data.get::<ProcessingChannels>() {
+                    processing.write().await.insert(channel_id, ActiveRun::new(cancel_token.clone(), &queued));
+                }
+            }
+
+            // Hold the message open so follow-ups from the same author can be merged
+            let window = Duration::from_secs(Config::load().unwrap_or_default().coalesce_window_secs);
+            let mut immediate = Some(queued);
+            if !window.is_zero() {
+                let data = ctx.data.read().await;
+                if let Some(batches) = data.get::<PendingBatches>() {
+                    batches.write().await.insert(channel_id, immediate.take().unwrap());
+                }
+            }
+
+            // Spawn processing task
+            let ctx_clone = ctx.clone();
+            tokio::spawn(async move {
+                let batch = match immediate {
+                    Some(queued) => Some(queued),
+                    None => Self::close_batch(&ctx_clone, channel_id, window, &cancel_token).await,
+                };
+                if let Some(queued) = batch {
+                    Self::process_message(&ctx_clone, queued, cancel_token).await;
+                }
+
+                // Remove from processing
+                {
+                    let data = ctx_clone.data.read().await;
+                    if let Some(processing) = data.get::<ProcessingChannels>() {
+                        processing.write().await.remove(&channel_id);
+                    }
+                }
+
+                // Process remaining queue
+                Self::process_queue(ctx_clone, channel_id).await;
+            });
+        }
+    }
+
+    async fn channel_update(
+        &self,
+        ctx: serenity::client::Context,
+        _old: Option<serenity::model::channel::GuildChannel>,
+        new: serenity::model::channel::GuildChannel,
+    ) {
+        // Re-detect on rename; Neywa's own mode emoji are ignored by from_name
+        let info = ChannelInfo::of(&new, None);
+        let channel_type = info.mode.clone();
+        let data = ctx.data.read().await;
+        if let Some(types) = data.get::<ChannelTypes>() {
+            let mut types = types.write().await;
+            if types.get(&new.id.get()).is_some_and(|known| *known != info) {
+                tracing::info!("Channel {} re-detected as {:?}", new.id, info);
+            }
+            types.insert(new.id.get(), info);
+        }
+        drop(data);
+
+        // A name-discovered logs channel follows renames: renaming #logs away or a channel to #logs rediscovers
+        let config = Config::load().unwrap_or_default();
+        let current = current_logs_channel(&ctx).await.map(|id| id.get());
+        if rename_needs_logs_rediscovery(current, config.logs_channel_bound, new.id.get(), &channel_type) {
+            discover_logs_channel(&ctx).await;
+        }
+    }
+
+    async fn guild_create(&self, ctx: serenity::client::Context, guild: serenity::model::guild::Guild, _is_new: Option<bool>) {
+        // The gateway sends every channel and active thread here, so the first
+        // message in a channel doesn't need an HTTP lookup. Known entries stay pinned.
+        let data = ctx.data.read().await;
+        let Some(types) = data.get::<ChannelTypes>() else {
+            return;
+        };
+        let mut types = types.write().await;
+        let known = types.len();
+        for (id, info) in guild_channel_infos(&guild.channels, &guild.threads) {
+            types.entry(id).or_insert(info);
+        }
+        tracing::debug!("Memoized {} channel(s) from guild {}", types.len() - known, guild.id);
+    }
+
+    async fn shard_stage_update(&self, _ctx: serenity::client::Context, event: serenity::gateway::ShardStageUpdateEvent) {
+        let connected = event.new == serenity::gateway::ConnectionStage::Connected;
+        DISCORD_CONNECTED.store(connected, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    async fn channel_delete(
+        &self,
+        ctx: serenity::client::Context,
+        channel: serenity::model::channel::GuildChannel,
+        _messages: Option<Vec<Message>>,
+    ) {
+        let report = prune_channel_state(&ctx, &HashSet::from([channel.id.get()]), false).await;
+        if !report.is_empty() {
+            tracing::info!("Channel #{} deleted: {}", channel.name, report.summary(false));
+        }
+        if current_logs_channel(&ctx).await == Some(channel.id) {
+            tracing::info!("Logs channel #{} was deleted, rediscovering", channel.name);
+            discover_logs_channel(&ctx).await;
+        }
+    }
+
+    async fn reaction_add(&self, ctx: serenity::client::Context, reaction: serenity::model::channel::Reaction) {
+        // 🛑 or ❌ on a run's status message cancels just that run; the queue keeps going
+        let serenity::model::channel::ReactionType::Unicode(emoji) = &reaction.emoji else {
+            return;
+        };
+        if !matches!(emoji.as_str(), "🛑" | "❌") {
+            return;
+        }
+        let Some(user_id) = reaction.user_id.map(|id| id.get()) else {
+            return;
+        };
+        let channel_id = reaction.channel_id.get();
+        let data = ctx.data.read().await;
+        let own_id = match data.get::<BotUserId>() {
+            Some(id) => *id.read().await,
+            None => None,
+        };
+        if own_id == Some(user_id) {
+            return;
+        }
+        let Some(tier) = user_tier(&Config::load().unwrap_or_default(), user_id) else {
+            return;
+        };
+        let Some(processing) = data.get::<ProcessingChannels>() else {
+            return;
+        };
+        let processing = processing.read().await;
+        let Some(run) = processing.get(&channel_id).filter(|run| run.status_message == Some(reaction.message_id)) else {
+            return;
+        };
+        if !run.may_cancel(user_id, tier) {
+            return;
+        }
+        tracing::info!("Run in channel {} cancelled by {} reaction from {}", channel_id, emoji, user_id);
+        run.cancel_token.cancel();
+    }
+
+    async fn thread_update(
+        &self,
+        ctx: serenity::client::Context,
+        _old: Option<serenity::model::channel::GuildChannel>,
+        new: serenity::model::channel::GuildChannel,
+    ) {
+        // An archived thread's conversation is over; a message after unarchiving starts fresh
+        if !new.thread_metadata.is_some_and(|meta| meta.archived) {
+            return;
+        }
+        let data = ctx.data.read().await;
+        if let Some(sessions) = data.get::<SessionStorage>() {
+            let mut sessions = sessions.write().await;
+            let removed = clear_channel_sessions(&mut sessions, new.id.get());
+            if removed > 0 {
+                save_sessions(&sessions);
+                tracing::info!("Thread #{} archived, dropped {} session(s)", new.name, removed);
+            }
+        }
+    }
+
+    async fn thread_delete(
+        &self,
+        ctx: serenity::client::Context,
+        thread: serenity::model::channel::PartialGuildChannel,
+        _full_thread_data: Option<serenity::model::channel::GuildChannel>,
+    ) {
+        let report = prune_channel_state(&ctx, &HashSet::from([thread.id.get()]), false).await;
+        if !report.is_empty() {
+            tracing::info!("Thread {} deleted: {}", thread.id, report.summary(false));
+        }
+    }
+
+    async fn message_update(
+        &self,
+        ctx: serenity::client::Context,
+        _old_if_available: Option<Message>,
+        _new: Option<Message>,
+        event: MessageUpdateEvent,
+    ) {
+        // Only content edits from humans matter
+        let Some(new_content) = event.content.as_deref() else {
+            return;
+        };
+        if event.author.as_ref().is_some_and(|a| a.bot) {
+            return;
+        }
+        let data = ctx.data.read().await;
+
+        // A request answered in its own thread (`!threads`) runs under the thread,
+        // which shares its ID with the message it was opened from
+        let in_thread = match data.get::<ProcessingChannels>() {
+            Some(processing) => processing.read().await.contains_key(&event.id.get()),
+            None => false,
+        };
+        let channel_id = if in_thread { event.id.get() } else { event.channel_id.get() };
+
+        // Still waiting in the queue: just swap the content
+        if let Some(queue) = data.get::<MessageQueue>() {
+            if let Some(channel_queue) = queue.write().await.get_mut(&channel_id) {
+                if replace_queued_content(channel_queue, event.id, new_content) {
+                    tracing::info!("Updated queued message {} with edit", event.id);
+                    return;
+                }
+            }
+        }
+
+        // Still inside the coalescing window
+        if let Some(batches) = data.get::<PendingBatches>() {
+            if let Some(batch) = batches.write().await.get_mut(&channel_id) {
+                if batch.msg.id == event.id {
+                    if let Some(edited) = edited_content(batch, new_content) {
+                        let original = batch.msg.content.trim().to_string();
+                        batch.content = batch.content.replacen(&original, &edited, 1);
+                        batch.msg.content = edited;
+                    }
+                    return;
+                }
+            }
+        }
+
+        // Currently running: restart with the edit if it started recently
+        let grace = Duration::from_secs(Config::load().unwrap_or_default().edit_grace_secs);
+        let requeued = match data.get::<ActiveMessages>() {
+            Some(active) => {
+                let mut active = active.write().await;
+                let restart = active.get(&channel_id).and_then(|(queued, started)| {
+                    if queued.msg.id != event.id || started.elapsed() >= grace {
+                        return None;
+                    }
+                    let content = edited_content(queued, new_content)?;
+                    let mut requeued = queued.clone();
+                    requeued.msg.content = new_content.to_string();
+                    requeued.content = content;
+                    Some(requeued)
+                });
+                if restart.is_some() {
+                    // Forget the run so a second quick edit doesn't restart twice
+                    active.remove(&channel_id);
+                }
+                restart
+            }
+            None => None,
+        };
+        let Some(requeued) = requeued else {
+            return;
+        };
+
+        if let Some(queue) = data.get::<MessageQueue>() {
+            queue.write().await.entry(channel_id).or_insert_with(VecDeque::new).push_front(requeued);
+        }
+        if let Some(processing) = data.get::<ProcessingChannels>() {
+            if let Some(run) = processing.read().await.get(&channel_id) {
+                run.cancel_token.cancel();
+            }
+        }
+        drop(data);
+
+        let _ = ChannelId::new(channel_id).say(&ctx.http, "♻️ Re-running with your edit").await;
+    }
+
+    async fn ready(&self, ctx: serenity::client::Context, ready: Ready) {
+        tracing::info!("{} is connected!", ready.user.name);
+        DISCORD_CONNECTED.store(true, std::sync::atomic::Ordering::SeqCst);
+        {
+            let data = ctx.data.read().await;
+            if let Some(bot_id) = data.get::<BotUserId>() {
+                *bot_id.write().await = Some(ready.user.id.get());
+            }
+        }
+
+        // Start the daily digest scheduler (ready fires again on reconnect)
+        if !DIGEST_SCHEDULER_STARTED.swap(true, std::sync::atomic::Ordering::SeqCst) {
+            tokio::spawn(run_digest_scheduler(ctx.clone()));
+        }
+        if !SCHEDULER_STARTED.swap(true, std::sync::atomic::Ordering::SeqCst) {
+            tokio::spawn(run_scheduler(ctx.clone()));
+        }
+        if !DISK_MONITOR_STARTED.swap(true, std::sync::atomic::Ordering::SeqCst) {
+            tokio::spawn(run_disk_monitor(ctx.clone()));
+        }
+        if !SESSION_CLEANUP_STARTED.swap(true, std::sync::atomic::Ordering::SeqCst) {
+            tokio::spawn(run_session_cleanup(ctx.clone()));
+        }
+        if !ATTACHMENT_CLEANUP_STARTED.swap(true, std::sync::atomic::Ordering::SeqCst) {
+            tokio::spawn(run_attachment_cleanup());
+        }
+        if !QUIET_RELEASE_STARTED.swap(true, std::sync::atomic::Ordering::SeqCst) {
+            tokio::spawn(run_quiet_release(ctx.clone()));
+        }
+
+        // Check for pending update notification
+        let update_pending = update::take_pending();
+        if let Some(pending) = update_pending.clone() {
+            tracing::info!(
+                "Pending update: {} -> {} (running: v{})",
+                pending.old_version, pending.new_version, VERSION
+            );
+
+            let http = ctx.http.clone();
+            tokio::spawn(async move {
+                // Wait for Discord connection to stabilize
+                tokio::time::sleep(tokio::time::Duration::from_secs(3)).await;
+
+                let channel = serenity::model::id::ChannelId::new(pending.channel_id);
+                let msg = pending.completion_message(VERSION);
+
+                tracing::info!("Sending update notification to channel {}...", pending.channel_id);
+                match channel.say(&http, &msg).await {
+                    Ok(_) => tracing::info!("Update notification sent"),
+                    Err(e) => tracing::error!("Failed to send update notification: {}", e),
+                }
+            });
+        }
+
+        // Register slash commands globally
+        let command_defs: Vec<(&str, &str)> = vec![
+            ("help", "Show available commands"),
+            ("status", "Check session status, processing state, queue"),
+            ("stop", "Stop current processing and clear queue"),
+            ("queue", "Show queued messages"),
+            ("compact", "Compact session context window"),
+            ("update", "Self-update to latest version"),
+            ("longtext", "Get a link to paste long text (over 2000 chars)"),
+            ("sessions", "List tracked sessions (admin)"),
+            ("backend", "Choose this channel's AI backend (Claude, Claude-Z, Codex)"),
+        ];
+
+        for (name, desc) in &command_defs {
+            let cmd = CreateCommand::new(*name).description(*desc);
+            if let Err(e) = serenity::model::application::Command::create_global_command(&ctx.http, cmd).await {
+                tracing::error!("Failed to register /{}: {}", name, e);
+            }
+        }
+
+        // Register /slash with a required string option
+        {
+            use serenity::model::application::CommandOptionType;
+            let slash_cmd = CreateCommand::new("slash")
+                .description("Run a Claude Code slash command")
+                .add_option(
+                    serenity::builder::CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "command",
+                        "The slash command to run (e.g., compact, cost, doctor)",
+                    )
+                    .required(true),
+                );
+            if let Err(e) = serenity::model::application::Command::create_global_command(&ctx.http, slash_cmd).await {
+                tracing::error!("Failed to register /slash: {}", e);
+            }
+        }
+
+        // Register /new with an optional scope
+        {
+            use serenity::model::application::CommandOptionType;
+            let new_cmd = CreateCommand::new("new")
+                .description("Start a new conversation session")
+                .add_option(
+                    serenity::builder::CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "scope",
+                        "Clear only your session (default) or every session in this channel",
+                    )
+                    .add_string_choice("mine", "mine")
+                    .add_string_choice("channel", "channel")
+                    .required(false),
+                );
+            if let Err(e) = serenity::model::application::Command::create_global_command(&ctx.http, new_cmd).await {
+                tracing::error!("Failed to register /new: {}", e);
+            }
+        }
+
+        tracing::info!("Registered {} slash commands", command_defs.len() + 2);
+
+        discover_logs_channel(&ctx).await;
+
+        if !STARTUP_ANNOUNCED.swap(true, std::sync::atomic::Ordering::SeqCst)
+            && Config::load().unwrap_or_default().startup_announcement
+        {
+            let ctx = ctx.clone();
+            let update = update_pending.map(|pending| (pending.old_version, pending.new_version));
+            tokio::spawn(async move { announce_startup(&ctx, update).await });
+        }
+
+        // Forget state for channels deleted while Neywa was offline
+        {
+            let ctx = ctx.clone();
+            tokio::spawn(async move {
+                if let Err(e) = reconcile_channel_state(&ctx, false).await {
+                    tracing::warn!("Failed to reconcile channel state: {}", e);
+                }
+            });
+        }
+
+        // First-run onboarding, once per guild
+        if Config::load().unwrap_or_default().onboarding_message {
+            for guild in &ready.guilds {
+                if !onboarding::is_onboarded(guild.id.get()) {
+                    onboard_guild(&ctx, guild.id).await;
                 }
             }
-            return;
         }
 
-        // Handle plan command - run Claude in plan-only mode
-        if content.starts_with("!plan ") || content.starts_with("!계획 ") {
-            let current_backend = get_channel_backend(&ctx, channel_id).await;
-            if current_backend == AiBackend::Codex {
-                let _ = msg.channel_id.say(&ctx.http, "⚠️ Codex 모드에서는 plan mode를 지원하지 않습니다.").await;
-                return;
+        // Catch the most common misconfiguration: Message Content intent disabled
+        match ctx.http.get_current_application_info().await {
+            Ok(info) => {
+                use serenity::model::application::ApplicationFlags;
+                let content_flags = ApplicationFlags::GATEWAY_MESSAGE_CONTENT
+                    | ApplicationFlags::GATEWAY_MESSAGE_CONTENT_LIMITED;
+                if info.flags.is_some_and(|flags| !flags.intersects(content_flags)) {
+                    let first_warning = {
+                        let data = ctx.data.read().await;
+                        match data.get::<IntentWatchStorage>() {
+                            Some(watch) => !std::mem::replace(&mut watch.write().await.warned, true),
+                            None => true,
+                        }
+                    };
+                    if first_warning {
+                        warn_missing_message_content(&ctx, "the application's Message Content intent is disabled").await;
+                    }
+                }
             }
-            let plan_msg = content
-                .strip_prefix("!plan ")
-                .or_else(|| content.strip_prefix("!계획 "))
-                .unwrap_or("")
-                .trim()
-                .to_string();
+            Err(e) => tracing::warn!("Failed to fetch application info: {}", e),
+        }
+    }
 
-            if plan_msg.is_empty() {
-                let _ = msg.channel_id.say(&ctx.http, "Usage: `!plan <request>`").await;
-                return;
+    async fn interaction_create(&self, ctx: serenity::client::Context, interaction: Interaction) {
+        if let Interaction::Component(component) = &interaction {
+            if component.data.custom_id.starts_with(discord_api::PENDING_ACTION_PREFIX) {
+                handle_pending_action(&ctx, component).await;
+            } else if component.data.custom_id == STOP_BUTTON_ID {
+                handle_stop_button(&ctx, component).await;
+            } else if component.data.custom_id == BACKEND_MENU_ID {
+                handle_backend_menu(&ctx, component).await;
             }
+            return;
+        }
 
-            let queued = QueuedMessage {
-                msg: msg.clone(),
-                content: plan_msg,
-                attachment_paths,
-                channel_type,
-                is_plan_mode: true,
-            };
+        if let Interaction::Command(command) = interaction {
+            let channel_id = command.channel_id.get();
+            let user_id = command.user.id.get();
+            let session_key = session_key_for(&ctx, user_id, channel_id).await;
 
-            // Use same queue/processing logic as normal messages
-            let is_processing = {
-                let data = ctx.data.read().await;
-                if let Some(processing) = data.get::<ProcessingChannels>() {
-                    processing.read().await.contains_key(&channel_id)
-                } else {
-                    false
+            // Slash commands share the text command tier table
+            let config = Config::load().unwrap_or_default();
+            if !config.is_active_channel(channel_id) {
+                return;
+            }
+            if !config.ignored_channels.is_empty() && command.guild_id.is_some() {
+                let info = channel_info_for(&ctx, command.channel_id).await;
+                if config.is_ignored_channel(channel_id, &info.name) {
+                    let response = CreateInteractionResponse::Message(
+                        CreateInteractionResponseMessage::new()
+                            .content("🔕 Neywa ignores this channel (`ignored_channels` in the config).")
+                            .ephemeral(true),
+                    );
+                    let _ = command.create_response(&ctx.http, response).await;
+                    return;
                 }
-            };
+            }
+            let tier = user_tier(&config, user_id);
+            let required = find_command(&command.data.name).map(|spec| spec.tier).unwrap_or(Tier::Admin);
+            if tier.is_none_or(|t| t < required) {
+                log_unauthorized(&ctx, &command.user.name, user_id, channel_id, &format!("/{}", command.data.name)).await;
+                let response = CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::new()
+                        .content("⛔ You don't have permission for that command.")
+                        .ephemeral(true)
+                );
+                let _ = command.create_response(&ctx.http, response).await;
+                return;
+            }
+            audit::record(AuditEntry::new(
+                "command",
+                &format!("/{}", command.data.name),
+                user_id,
+                &command.user.name,
+                channel_id,
+                &command.data.name,
+            ));
 
-            if is_processing {
-                let queue_pos = {
+            let response_msg = match command.data.name.as_str() {
+                "help" => help_text(&ctx, user_id, channel_id).await,
+                "status" => {
                     let data = ctx.data.read().await;
-                    if let Some(queue) = data.get::<MessageQueue>() {
-                        let mut q = queue.write().await;
-                        let channel_queue = q.entry(channel_id).or_insert_with(VecDeque::new);
-                        channel_queue.push_back(queued);
-                        channel_queue.len()
-                    } else {
-                        0
+                    let backend = if let Some(backends) = data.get::<ChannelBackends>() {
+                        backends.read().await.get(&channel_id).copied().unwrap_or(AiBackend::Claude)
+                    } else { AiBackend::Claude };
+                    let active_run = if let Some(processing) = data.get::<ProcessingChannels>() {
+                        processing.read().await.get(&channel_id).cloned()
+                    } else { None };
+                    let queue_size = if let Some(queue) = data.get::<MessageQueue>() {
+                        queue.read().await.get(&channel_id).map(|q| q.len()).unwrap_or(0)
+                    } else { 0 };
+
+                    let mode = backend.status_line();
+                    let proc = match &active_run {
+                        Some(run) => run.describe(),
+                        None => "✅ Idle".to_string(),
+                    };
+                    let queue = if queue_size > 0 { format!("📬 Queue: {}", queue_size) } else { "📭 Queue: empty".to_string() };
+                    drop(data);
+                    let mut status = format!("**v{}**\n{}\n{}\n{}", VERSION, mode, proc, queue);
+                    status.push_str(&format!("\n📂 Workdir: `{}`", channel_workdir_label(&ctx, channel_id).await));
+                    if let Some(line) = context_status(&ctx, user_id, channel_id, backend).await {
+                        status.push('\n');
+                        status.push_str(&line);
                     }
-                };
-                let _ = msg.channel_id.say(&ctx.http, format!("📬 Queued (#{} in line)", queue_pos)).await;
-            } else {
-                let cancel_token = CancellationToken::new();
-                {
+                    if let Some(line) = autoreset_status(&ctx, channel_id).await {
+                        status.push('\n');
+                        status.push_str(&line);
+                    }
+                    if let Some(line) = language_status(&ctx, channel_id).await {
+                        status.push('\n');
+                        status.push_str(&line);
+                    }
+                    if let Some(line) = active_channels_status(&config) {
+                        status.push('\n');
+                        status.push_str(&line);
+                    }
+                    if let Some(state) = lifecycle::load_state() {
+                        status.push('\n');
+                        status.push_str(&state.describe());
+                    }
+                    if let Some(free) = disk::low_space(config.min_free_disk_bytes()) {
+                        status = format!("{}\n{}", disk::banner(free), status);
+                    }
+                    if is_dry_run() {
+                        status = format!("{}\n{}", DRY_RUN_BANNER, status);
+                    }
+                    status
+                }
+                "new" => {
+                    let scope = command.data.options.iter()
+                        .find(|opt| opt.name == "scope")
+                        .and_then(|opt| opt.value.as_str())
+                        .and_then(ResetScope::parse)
+                        .unwrap_or(ResetScope::Mine);
+                    reset_sessions(&ctx, user_id, channel_id, scope, tier).await
+                }
+                "stop" => 'stop: {
                     let data = ctx.data.read().await;
+                    let mut cancelled = false;
+                    let mut cleared = 0usize;
+
+                    if tier != Some(Tier::Admin) {
+                        if let Some(owner) = foreign_run_owner(&data, channel_id, user_id).await {
+                            break 'stop format!("⚠️ The current run is {}'s request. Use `!stop confirm` to stop it anyway.", owner);
+                        }
+                    }
+
                     if let Some(processing) = data.get::<ProcessingChannels>() {
-                        processing.write().await.insert(channel_id, cancel_token.clone());
+                        if let Some(run) = processing.read().await.get(&channel_id) {
+                            run.cancel_token.cancel();
+                            cancelled = true;
+                        }
+                    }
+                    if let Some(queue) = data.get::<MessageQueue>() {
+                        if let Some(channel_queue) = queue.write().await.remove(&channel_id) {
+                            cleared = channel_queue.len();
+                        }
+                    }
+
+                    let mut parts = Vec::new();
+                    if cancelled { parts.push("🛑 Processing stopped".to_string()); }
+                    if cleared > 0 { parts.push(format!("📭 {} queued message(s) cleared", cleared)); }
+                    if parts.is_empty() { parts.push("Nothing to stop.".to_string()); }
+                    parts.join("\n")
+                }
+                "queue" => queue_status(&ctx, channel_id).await,
+                "update" => {
+                    // Respond immediately, then handle update asynchronously
+                    let response = CreateInteractionResponse::Message(
+                        CreateInteractionResponseMessage::new()
+                            .content("🔄 Checking for updates...")
+                    );
+                    let _ = command.create_response(&ctx.http, response).await;
+
+                    // Do the update in the channel as regular messages
+                    let channel = command.channel_id;
+                    let http = ctx.http.clone();
+
+                    tokio::spawn(async move {
+                        let remote_version = match update::fetch_remote_version().await {
+                            Ok(v) => v,
+                            Err(e) => {
+                                let _ = channel.say(&http, format!("❌ Failed to check version: {}", e)).await;
+                                return;
+                            }
+                        };
+
+                        if remote_version == VERSION {
+                            let _ = channel.say(&http, format!("✅ Already on the latest version (v{})", VERSION)).await;
+                            return;
+                        }
+
+                        let _ = channel.say(&http, format!("📥 v{} → v{}", VERSION, remote_version)).await;
+
+                        match update::self_update().await {
+                            Ok(()) => {
+                                let pending = update::PendingUpdate {
+                                    channel_id: channel.get(),
+                                    old_version: VERSION.to_string(),
+                                    new_version: remote_version.clone(),
+                                };
+                                if let Err(e) = update::save_pending(&pending) {
+                                    tracing::warn!("Failed to save update pending: {}", e);
+                                }
+
+                                let _ = channel.say(&http, "✅ Update downloaded. Restarting...").await;
+                                tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+                                update::restart();
+                            }
+                            Err(e) => {
+                                let _ = channel.say(&http, format!("❌ Update failed: {}", e)).await;
+                            }
+                        }
+                    });
+                    return; // Already responded
+                }
+                "compact" => {
+                    // Respond immediately, then handle async
+                    let response = CreateInteractionResponse::Message(
+                        CreateInteractionResponseMessage::new()
+                            .content("🗜️ Compacting session...")
+                    );
+                    let _ = command.create_response(&ctx.http, response).await;
+
+                    let channel = command.channel_id;
+                    let http = ctx.http.clone();
+                    let data_arc = ctx.data.clone();
+
+                    let existing_session = {
+                        let data = data_arc.read().await;
+                        if let Some(sessions) = data.get::<SessionStorage>() {
+                            sessions.read().await.get(&session_key).map(|s| s.id.clone())
+                        } else {
+                            None
+                        }
+                    };
+
+                    let use_z = {
+                        let data = data_arc.read().await;
+                        if let Some(backends) = data.get::<ChannelBackends>() {
+                            backends.read().await.get(&channel_id).copied() == Some(AiBackend::ClaudeZ)
+                        } else {
+                            false
+                        }
+                    };
+
+                    let workdir = get_channel_workdir(&ctx, channel_id).await;
+
+                    tokio::spawn(async move {
+                        if let Some(sid) = existing_session {
+                            match claude::compact_session(&sid, use_z, workdir.as_deref()).await {
+                                Ok(_) => {
+                                    let _ = channel.say(&http, "✅ Session compacted.").await;
+                                }
+                                Err(e) => {
+                                    if trim_session_file(&sid, workdir.as_deref()) {
+                                        let _ = channel.say(&http, "⚠️ Compact failed, trimmed old messages instead.").await;
+                                    } else {
+                                        let _ = channel.say(&http, format!("❌ Compact failed: {}", e)).await;
+                                    }
+                                }
+                            }
+                        } else {
+                            let _ = channel.say(&http, "No active session. Nothing to compact.").await;
+                        }
+                    });
+                    return; // Already responded
+                }
+                "slash" => {
+                    let slash_cmd = command.data.options.first()
+                        .and_then(|opt| opt.value.as_str())
+                        .unwrap_or("")
+                        .to_string();
+
+                    if slash_cmd.is_empty() {
+                        "Usage: `/slash <command>` (e.g., `/slash compact`, `/slash cost`)".to_string()
+                    } else {
+                        // Respond immediately, then handle async
+                        let display_cmd = slash_cmd.trim_start_matches('/').to_string();
+                        let response = CreateInteractionResponse::Message(
+                            CreateInteractionResponseMessage::new()
+                                .content(format!("⚡ Running `/{}`...", display_cmd))
+                        );
+                        let _ = command.create_response(&ctx.http, response).await;
+
+                        let channel = command.channel_id;
+                        let http = ctx.http.clone();
+                        let data_arc = ctx.data.clone();
+
+                        let existing_session = {
+                            let data = data_arc.read().await;
+                            if let Some(sessions) = data.get::<SessionStorage>() {
+                                sessions.read().await.get(&session_key).map(|s| s.id.clone())
+                            } else {
+                                None
+                            }
+                        };
+
+                        let use_z = {
+                            let data = data_arc.read().await;
+                            if let Some(backends) = data.get::<ChannelBackends>() {
+                                backends.read().await.get(&channel_id).copied() == Some(AiBackend::ClaudeZ)
+                            } else {
+                                false
+                            }
+                        };
+
+                        let workdir = get_channel_workdir(&ctx, channel_id).await;
+
+                        tokio::spawn(async move {
+                            match claude::run_slash_command(&slash_cmd, existing_session.as_deref(), use_z, workdir.as_deref()).await {
+                                Ok(result) => {
+                                    let chunks = split_for_discord(&result);
+                                    for chunk in chunks {
+                                        let _ = channel.say(&http, &chunk).await;
+                                    }
+                                }
+                                Err(e) => {
+                                    let _ = channel.say(&http, format!("❌ Error: {}", e)).await;
+                                }
+                            }
+                        });
+                        return; // Already responded
                     }
                 }
-
-                let ctx_clone = ctx.clone();
-                tokio::spawn(async move {
-                    Self::process_message(&ctx_clone, queued, cancel_token).await;
-                    {
-                        let data = ctx_clone.data.read().await;
-                        if let Some(processing) = data.get::<ProcessingChannels>() {
-                            processing.write().await.remove(&channel_id);
+                "backend" => {
+                    let menu = backend_menu(&ctx, channel_id).await;
+                    let _ = command.create_response(&ctx.http, CreateInteractionResponse::Message(menu)).await;
+                    return; // Already responded
+                }
+                "sessions" => {
+                    let report = {
+                        let data = ctx.data.read().await;
+                        match data.get::<SessionStorage>() {
+                            Some(sessions) => sessions_report(&*sessions.read().await, Local::now()),
+                            None => "❌ Session storage unavailable.".to_string(),
                         }
+                    };
+                    let mut chunks = split_for_discord(&report).into_iter();
+                    let response = CreateInteractionResponse::Message(
+                        CreateInteractionResponseMessage::new()
+                            .content(chunks.next().unwrap_or_default())
+                            .allowed_mentions(CreateAllowedMentions::new())
+                    );
+                    let _ = command.create_response(&ctx.http, response).await;
+                    for chunk in chunks {
+                        say_without_pings(&ctx.http, command.channel_id, &chunk).await;
                     }
-                    Self::process_queue(ctx_clone, channel_id).await;
-                });
-            }
-            return;
-        }
-
-        // Handle restart command - kills all Claude Code sessions and resets state
-        if content == "!restart" || content == "!재시작" {
-            let _ = msg.channel_id.say(&ctx.http, "🔄 Restarting all sessions...").await;
-
-            let data = ctx.data.read().await;
-            let mut cancelled_count = 0u32;
-            let mut cleared_count = 0u32;
-
-            // 1. Cancel all active processing (triggers CancellationToken)
-            if let Some(processing) = data.get::<ProcessingChannels>() {
-                let tokens = processing.read().await;
-                for (_ch, token) in tokens.iter() {
-                    token.cancel();
-                    cancelled_count += 1;
+                    return; // Already responded
                 }
-            }
-
-            // 2. Clear all message queues
-            if let Some(queue) = data.get::<MessageQueue>() {
-                let mut q = queue.write().await;
-                for (_ch, channel_queue) in q.iter_mut() {
-                    cleared_count += channel_queue.len() as u32;
-                    channel_queue.clear();
+                "longtext" => {
+                    "📝 **Long Text Input**\n\n\
+                    Discord has a 2000 character limit.\n\
+                    Use this tool to send longer text:\n\n\
+                    👉 **https://copy-once.cc**\n\n\
+                    1. Paste your long text there\n\
+                    2. Copy the generated link\n\
+                    3. Paste the link here with your message".to_string()
                 }
-            }
+                _ => return,
+            };
 
-            // 3. Clear all session IDs (forces fresh Claude Code sessions)
-            if let Some(sessions) = data.get::<SessionStorage>() {
-                let mut sessions_map = sessions.write().await;
-                sessions_map.clear();
-                save_sessions(&sessions_map);
+            let response = CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(response_msg)
+                    .ephemeral(matches!(command.data.name.as_str(), "help" | "longtext"))
+            );
+
+            if let Err(e) = command.create_response(&ctx.http, response).await {
+                tracing::error!("Failed to respond to /{}: {}", command.data.name, e);
             }
+        }
+    }
+}
 
-            drop(data);
+/// Download attachment to temp directory
+/// Recent run durations for a channel
+async fn recent_durations(ctx: &serenity::client::Context, channel_id: u64) -> Vec<Duration> {
+    let data = ctx.data.read().await;
+    match data.get::<MetricsStorage>() {
+        Some(metrics) => metrics.read().await.recent(channel_id),
+        None => Vec::new(),
+    }
+}
 
-            // 4. Kill any lingering claude/claude-z/codex child processes
-            let _ = Command::new("pkill")
-                .arg("-f")
-                .arg("claude.*--dangerously-skip-permissions")
-                .stdin(std::process::Stdio::null())
-                .stdout(std::process::Stdio::null())
-                .stderr(std::process::Stdio::null())
-                .status();
-
-            let _ = Command::new("pkill")
-                .arg("-f")
-                .arg("codex exec")
-                .stdin(std::process::Stdio::null())
-                .stdout(std::process::Stdio::null())
-                .stderr(std::process::Stdio::null())
-                .status();
+/// "📬 Queued (#3 in line, ~12 min based on recent tasks)"
+async fn queue_ack(ctx: &serenity::client::Context, channel_id: u64, queue_pos: usize) -> String {
+    let recent = recent_durations(ctx, channel_id).await;
+    // Everything queued ahead plus the run in progress
+    match metrics::estimate_wait(&recent, queue_pos) {
+        Some(wait) => format!(
+            "📬 Queued (#{} in line, {} based on recent tasks)",
+            queue_pos,
+            metrics::format_estimate(wait)
+        ),
+        None => format!("📬 Queued (#{} in line)", queue_pos),
+    }
+}
 
-            // Brief wait for processes to clean up
-            tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+/// Add a run's cost to the channel/session totals and the user's daily usage
+async fn record_cost(ctx: &serenity::client::Context, channel_id: u64, user_id: u64, session_id: Option<&str>, usage: RunUsage) {
+    tracing::debug!(
+        cost_usd = usage.cost_usd,
+        input_tokens = usage.input_tokens,
+        output_tokens = usage.output_tokens,
+        "Run usage"
+    );
+    let data = ctx.data.read().await;
+    if let Some(costs) = data.get::<CostStorage>() {
+        costs.write().await.record(channel_id, session_id, usage.cost_usd);
+    }
+    if let Some(store) = data.get::<UsageStorage>() {
+        let mut store = store.write().await;
+        store.today(user_id).cost_usd += usage.cost_usd;
+        store.save();
+    }
+}
 
-            let _ = msg.channel_id.say(&ctx.http, format!(
-                "✅ Sessions restarted.\n\
-                 • Cancelled {} active task(s)\n\
-                 • Cleared {} queued message(s)\n\
-                 • All session history reset\n\
-                 • Claude Code processes terminated\n\n\
-                 Ready for new messages!",
-                cancelled_count, cleared_count
-            )).await;
-            return;
+/// `!cost`: the caller's session in this channel, the channel today, and all channels ever
+async fn cost_report(ctx: &serenity::client::Context, user_id: u64, channel_id: u64) -> String {
+    if get_channel_backend(ctx, channel_id).await == AiBackend::Codex {
+        return "💰 Cost: n/a — Codex doesn't report cost.".to_string();
+    }
+    let key = session_key_for(ctx, user_id, channel_id).await;
+    let data = ctx.data.read().await;
+    let session = match data.get::<SessionStorage>() {
+        Some(sessions) => sessions.read().await.get(&key).map(|entry| entry.id.clone()),
+        None => None,
+    };
+    let Some(costs) = data.get::<CostStorage>() else {
+        return "❌ Cost tracking unavailable.".to_string();
+    };
+    let costs = costs.read().await;
+    let session_line = match &session {
+        Some(id) => {
+            let short: String = id.chars().take(8).collect();
+            let spent = costs.channel(channel_id).and_then(|c| c.session_usd(id)).unwrap_or(0.0);
+            format!("Your session (`{}…`): {}", short, cost::format_usd(spent))
         }
+        None => "Your session: none yet".to_string(),
+    };
+    [
+        "💰 **Cost**".to_string(),
+        session_line,
+        format!("This channel today: {}", cost::format_usd(costs.channel_today(channel_id))),
+        format!("This channel all-time: {}", cost::format_usd(costs.channel(channel_id).map(|c| c.total_usd).unwrap_or(0.0))),
+        format!("All channels all-time: {}", cost::format_usd(costs.all_time())),
+    ]
+    .join("\n")
+}
 
-        // Handle update command
-        if content == "!update" {
-            let _ = msg.channel_id.say(&ctx.http, "🔄 Checking for updates...").await;
+/// A completion notice, sent silently (no push notification) during quiet hours
+fn completion_message(text: String, silent: bool) -> CreateMessage {
+    let message = CreateMessage::new().content(text);
+    if silent {
+        message.flags(MessageFlags::SUPPRESS_NOTIFICATIONS)
+    } else {
+        message
+    }
+}
 
-            // Fetch remote version
-            let remote_version = match fetch_remote_version().await {
-                Ok(v) => v,
-                Err(e) => {
-                    let _ = msg.channel_id.say(&ctx.http, format!("❌ Failed to check version: {}", e)).await;
-                    return;
+/// Reply to a message that was just queued at `queue_pos` and remember the
+/// reply so it can be updated. A priority message also moves everything
+/// behind it down one place.
+async fn announce_queued(ctx: &serenity::client::Context, msg: &Message, queue_pos: usize) {
+    let channel_id = msg.channel_id.get();
+    let Ok(notice) = msg.channel_id.say(&ctx.http, queue_ack(ctx, channel_id, queue_pos).await).await else {
+        return;
+    };
+    let (stored, behind) = {
+        let data = ctx.data.read().await;
+        match data.get::<MessageQueue>() {
+            Some(queue) => match queue.write().await.get_mut(&channel_id) {
+                Some(channel_queue) => {
+                    let behind = channel_queue.len().saturating_sub(queue_pos);
+                    match channel_queue.iter_mut().find(|m| m.msg.id == msg.id) {
+                        Some(queued) => {
+                            queued.notice = Some(notice.id);
+                            (true, behind)
+                        }
+                        None => (false, behind),
+                    }
                 }
-            };
+                None => (false, 0),
+            },
+            None => (false, 0),
+        }
+    };
+    if !stored {
+        // Already picked up (or cancelled) before the reply went out
+        let _ = notice.delete(&ctx.http).await;
+    } else if behind > 0 {
+        refresh_queue_notices(ctx, channel_id, queue_pos).await;
+    }
+}
 
-            // Compare versions
-            if remote_version == VERSION {
-                let _ = msg.channel_id.say(&ctx.http, format!("✅ Already on the latest version (v{})", VERSION)).await;
-                return;
-            }
+/// Rewrite the queue notices from index `from` on with their current
+/// positions: one edit per waiting message, edit failures ignored
+async fn refresh_queue_notices(ctx: &serenity::client::Context, channel_id: u64, from: usize) {
+    let notices: Vec<(usize, MessageId)> = {
+        let data = ctx.data.read().await;
+        match data.get::<MessageQueue>() {
+            Some(queue) => queue
+                .read()
+                .await
+                .get(&channel_id)
+                .map(|q| {
+                    q.iter()
+                        .enumerate()
+                        .skip(from)
+                        .filter_map(|(i, m)| m.notice.map(|notice| (i + 1, notice)))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            None => Vec::new(),
+        }
+    };
+    let channel = ChannelId::new(channel_id);
+    for (position, notice) in notices {
+        let text = queue_ack(ctx, channel_id, position).await;
+        let _ = channel.edit_message(&ctx.http, notice, EditMessage::new().content(text)).await;
+    }
+}
 
-            let _ = msg.channel_id.say(&ctx.http, format!("📥 New version available: v{} → v{}", VERSION, remote_version)).await;
+/// Queue summary for `!queue` / `/queue`, one line per waiting message
+async fn queue_status(ctx: &serenity::client::Context, channel_id: u64) -> String {
+    let recent = recent_durations(ctx, channel_id).await;
+    let quiet = quiet_status(ctx, channel_id).await;
+    let data = ctx.data.read().await;
+    let entries: Vec<(String, String, bool)> = match data.get::<MessageQueue>() {
+        Some(queue) => queue
+            .read()
+            .await
+            .get(&channel_id)
+            .map(|q| {
+                q.iter()
+                    .map(|m| (m.msg.author.name.clone(), claude::truncate_str(&m.content.replace('\n', " "), 60), m.priority))
+                    .collect()
+            })
+            .unwrap_or_default(),
+        None => Vec::new(),
+    };
+    let is_processing = match data.get::<ProcessingChannels>() {
+        Some(processing) => processing.read().await.contains_key(&channel_id),
+        None => false,
+    };
 
-            match self_update().await {
-                Ok(()) => {
-                    // Save pending update info for notification after restart
-                    if let Err(e) = save_update_pending(msg.channel_id.get(), VERSION, &remote_version) {
-                        tracing::warn!("Failed to save update pending info: {}", e);
-                    }
+    let mut status = if is_processing {
+        format!("🔄 Processing | 📬 Queue: {}", entries.len())
+    } else if !entries.is_empty() {
+        format!("📬 Queue: {}", entries.len())
+    } else {
+        "📭 Queue is empty.".to_string()
+    };
 
-                    let _ = msg.channel_id.say(&ctx.http, "✅ Update downloaded. Restarting...").await;
-                    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+    for (i, (author, preview, priority)) in entries.iter().enumerate() {
+        let runs_ahead = i + usize::from(is_processing);
+        let estimate = metrics::estimate_wait(&recent, runs_ahead)
+            .map(|wait| format!(" ({})", metrics::format_estimate(wait)))
+            .unwrap_or_default();
+        let marker = if *priority { "⚡ " } else { "" };
+        status.push_str(&format!("\n`#{}` {}{}: {}{}", i + 1, marker, author, preview, estimate));
+    }
+    if !recent.is_empty() && !entries.is_empty() {
+        status.push_str("\n_Estimates are approximate, based on recent tasks._");
+    }
+    if let Some(line) = quiet {
+        status.push('\n');
+        status.push_str(&line);
+    }
+    status
+}
 
-                    restart_after_update();
-                }
-                Err(e) => {
-                    let _ = msg.channel_id.say(&ctx.http, format!("❌ Update failed: {}", e)).await;
-                }
-            }
-            return;
-        }
+/// The text after a `command` prefix (None if the message doesn't start with it)
+fn strip_command<'a>(content: &'a str, command: &str) -> Option<&'a str> {
+    let rest = content.strip_prefix(command)?;
+    if rest.is_empty() || rest.starts_with(char::is_whitespace) {
+        Some(rest.trim())
+    } else {
+        None
+    }
+}
 
-        // Skip if empty content and no attachments
-        if content.is_empty() && attachment_paths.is_empty() {
-            return;
-        }
+/// The request after an `!urgent` prefix (None if the message doesn't start with one)
+fn strip_urgent(content: &str) -> Option<&str> {
+    strip_command(content, "!urgent")
+}
 
-        tracing::info!("Message from {} in {:?}: {}", msg.author.name, channel_type, content);
+/// Prompt for a steered request: the correction first, then the request it changes
+fn steer_content(correction: &str, original: &str) -> String {
+    format!(
+        "[Correction, sent while you were working on the request below. That attempt was stopped; \
+         continue from where you were, following this correction.]\n{}\n\n[Original request]\n{}",
+        correction, original
+    )
+}
 
-        // Create queued message
-        let queued = QueuedMessage {
-            msg: msg.clone(),
-            content,
-            attachment_paths,
-            channel_type,
-            is_plan_mode: false,
-        };
+/// Content an edited message would have been queued with (plan prefix stripped).
+/// None if the edit no longer fits the original kind of request.
+fn edited_content(queued: &QueuedMessage, new_content: &str) -> Option<String> {
+    let trimmed = new_content.trim();
+    let content = if queued.is_plan_mode {
+        trimmed
+            .strip_prefix("!plan ")
+            .or_else(|| trimmed.strip_prefix("!계획 "))?
+            .trim()
+    } else if queued.urgent {
+        strip_urgent(trimmed)?
+    } else if trimmed.starts_with('!') {
+        return None;
+    } else {
+        trimmed
+    };
+    if content.is_empty() && queued.attachments.is_empty() {
+        return None;
+    }
+    Some(content.to_string())
+}
 
-        // Check if channel is currently processing
-        let is_processing = {
-            let data = ctx.data.read().await;
-            if let Some(processing) = data.get::<ProcessingChannels>() {
-                processing.read().await.contains_key(&channel_id)
-            } else {
-                false
-            }
-        };
+/// Replace the content of a queued message in place. Returns true if it was found.
+fn replace_queued_content(queue: &mut VecDeque<QueuedMessage>, message_id: MessageId, new_content: &str) -> bool {
+    let Some(queued) = queue.iter_mut().find(|q| q.msg.id == message_id) else {
+        return false;
+    };
+    if let Some(content) = edited_content(queued, new_content) {
+        queued.msg.content = new_content.to_string();
+        queued.content = content;
+    }
+    true
+}
 
-        if is_processing {
-            // Add to queue
-            let queue_pos = {
-                let data = ctx.data.read().await;
-                if let Some(queue) = data.get::<MessageQueue>() {
-                    let mut q = queue.write().await;
-                    let channel_queue = q.entry(channel_id).or_insert_with(VecDeque::new);
-                    channel_queue.push_back(queued);
-                    channel_queue.len()
-                } else {
-                    0
-                }
-            };
-            let _ = msg.channel_id.say(&ctx.http, format!("📬 Queued (#{} in line)", queue_pos)).await;
-        } else {
-            // Start processing immediately
-            let cancel_token = CancellationToken::new();
+/// Download an attachment directly to a destination path, returning bytes written
+async fn save_attachment_to(url: &str, dest: &Path) -> Result<u64> {
+    let response = reqwest::get(url).await?;
+    if !response.status().is_success() {
+        anyhow::bail!("download failed: HTTP {}", response.status());
+    }
+    let bytes = response.bytes().await?;
+    std::fs::write(dest, &bytes)?;
+    Ok(bytes.len() as u64)
+}
 
-            // Mark as processing
-            {
-                let data = ctx.data.read().await;
-                if let Some(processing) = data.get::<ProcessingChannels>() {
-                    processing.write().await.insert(channel_id, cancel_token.clone());
-                }
-            }
+/// Directory relative `!get`/`!put` paths start from: the channel's `!cwd`,
+/// or the daemon's own working directory when none is set
+async fn transfer_base(ctx: &serenity::client::Context, channel_id: u64) -> PathBuf {
+    match get_channel_workdir(ctx, channel_id).await {
+        Some(dir) => dir,
+        None => std::env::current_dir().unwrap_or_else(|_| PathBuf::from("/")),
+    }
+}
 
-            // Spawn processing task
-            let ctx_clone = ctx.clone();
-            tokio::spawn(async move {
-                // Process current message
-                Self::process_message(&ctx_clone, queued, cancel_token).await;
+/// A user-supplied `!get`/`!put` path made absolute: `~` expanded and
+/// relative paths joined onto `base`. Nothing is resolved or checked yet.
+fn expand_transfer_path(raw: &str, base: &Path) -> Result<PathBuf> {
+    let expanded = if raw == "~" || raw.starts_with("~/") {
+        let home = dirs::home_dir().context("Could not find home directory")?;
+        home.join(raw.trim_start_matches('~').trim_start_matches('/'))
+    } else {
+        PathBuf::from(raw)
+    };
 
-                // Remove from processing
-                {
-                    let data = ctx_clone.data.read().await;
-                    if let Some(processing) = data.get::<ProcessingChannels>() {
-                        processing.write().await.remove(&channel_id);
-                    }
-                }
+    Ok(if expanded.is_absolute() {
+        expanded
+    } else {
+        base.join(expanded)
+    })
+}
 
-                // Process remaining queue
-                Self::process_queue(ctx_clone, channel_id).await;
-            });
+/// Whether `path` itself is a symlink (its target isn't looked at)
+fn is_symlink(path: &Path) -> bool {
+    std::fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_symlink())
+}
+
+/// Resolve a user-supplied path for `!get`/`!put`.
+/// Expands `~`, resolves relative paths against `base`,
+/// and refuses anything outside the allowed roots or inside a denied one.
+fn resolve_transfer_path(raw: &str, base: &Path, roots: &[PathBuf], denied: &[PathBuf]) -> Result<PathBuf> {
+    let absolute = expand_transfer_path(raw, base)?;
+
+    // Canonicalize the deepest existing ancestor so `..` and symlinks can't escape the roots
+    let mut existing = absolute.as_path();
+    let mut rest = Vec::new();
+    while !existing.exists() {
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                rest.push(name.to_owned());
+                existing = parent;
+            }
+            _ => anyhow::bail!("Invalid path: {}", raw),
+        }
+    }
+    let mut resolved = std::fs::canonicalize(existing)?;
+    for part in rest.iter().rev() {
+        if part == ".." || part == "." {
+            anyhow::bail!("Invalid path: {}", raw);
         }
+        resolved.push(part);
     }
 
-    async fn ready(&self, ctx: serenity::client::Context, ready: Ready) {
-        tracing::info!("{} is connected!", ready.user.name);
+    let allowed = roots.iter().any(|root| {
+        std::fs::canonicalize(root)
+            .map(|r| resolved.starts_with(r))
+            .unwrap_or(false)
+    });
+    if !allowed {
+        anyhow::bail!("Path `{}` is outside the allowed roots", resolved.display());
+    }
 
-        // Check for pending update notification
-        if let Some((channel_id, old_version, new_version)) = load_update_pending() {
-            tracing::info!(
-                "Pending update: {} -> {} (running: v{})",
-                old_version, new_version, VERSION
-            );
+    let denied = denied.iter().any(|dir| {
+        let dir = std::fs::canonicalize(dir).unwrap_or_else(|_| dir.clone());
+        resolved.starts_with(dir)
+    });
+    if denied {
+        anyhow::bail!("Path `{}` is in Neywa's own config directory", resolved.display());
+    }
 
-            let http = ctx.http.clone();
-            tokio::spawn(async move {
-                // Wait for Discord connection to stabilize
-                tokio::time::sleep(tokio::time::Duration::from_secs(3)).await;
+    Ok(resolved)
+}
 
-                let channel = serenity::model::id::ChannelId::new(channel_id);
-                let msg = if VERSION == new_version {
-                    format!("🎉 **Update complete!** v{} → v{}", old_version, new_version)
-                } else {
-                    format!(
-                        "⚠️ Update done. Expected v{}, running v{}",
-                        new_version, VERSION
-                    )
-                };
+/// custom_id of the `/backend` select menu
+const BACKEND_MENU_ID: &str = "neywa_backend";
 
-                tracing::info!("Sending update notification to channel {}...", channel_id);
-                match channel.say(&http, &msg).await {
-                    Ok(_) => tracing::info!("Update notification sent"),
-                    Err(e) => tracing::error!("Failed to send update notification: {}", e),
+/// Every backend, in menu order
+const BACKENDS: [AiBackend; 3] = [AiBackend::Claude, AiBackend::ClaudeZ, AiBackend::Codex];
+
+/// Channel name with the 🅾️ prefix added for Codex and removed otherwise
+fn backend_channel_name(name: &str, backend: AiBackend) -> String {
+    let clean = name.trim_start_matches("🅾️").trim_start_matches('-');
+    let clean = if clean.is_empty() { name } else { clean };
+    match backend {
+        AiBackend::Codex => format!("🅾️{}", clean),
+        _ => clean.to_string(),
+    }
+}
+
+/// Switch a channel's backend: persist it, reset the user's session there, and
+/// keep the 🅾️ channel-name prefix in step. Shared by `!z`, `!codex` and
+/// `/backend`. Returns the announcement.
+async fn switch_backend(ctx: &serenity::client::Context, channel: ChannelId, user_id: u64, target: AiBackend) -> String {
+    let channel_id = channel.get();
+    let key = session_key_for(ctx, user_id, channel_id).await;
+    {
+        let data = ctx.data.read().await;
+        if let Some(backends) = data.get::<ChannelBackends>() {
+            let mut map = backends.write().await;
+            if target == AiBackend::Claude {
+                map.remove(&channel_id);
+            } else {
+                map.insert(channel_id, target);
+            }
+            save_channel_backends(&map);
+        }
+        // Reset session on mode change
+        if let Some(sessions) = data.get::<SessionStorage>() {
+            let mut sessions_map = sessions.write().await;
+            sessions_map.remove(&key);
+            save_sessions(&sessions_map);
+        }
+    }
+
+    // DMs have no name to mark
+    if let Ok(Some(guild_channel)) = channel.to_channel(&ctx.http).await.map(|c| c.guild()) {
+        let new_name = backend_channel_name(&guild_channel.name, target);
+        if new_name != guild_channel.name {
+            tokio::spawn(async move {
+                if let Err(e) = discord_api::rename_channel(&channel_id.to_string(), &new_name).await {
+                    tracing::warn!("Failed to rename channel: {}", e);
                 }
             });
         }
+    }
 
-        // Register slash commands globally
-        let command_defs: Vec<(&str, &str)> = vec![
-            ("help", "Show available commands"),
-            ("status", "Check session status, processing state, queue"),
-            ("new", "Start a new conversation session"),
-            ("stop", "Stop current processing and clear queue"),
-            ("queue", "Show queued messages"),
-            ("compact", "Compact session context window"),
-            ("update", "Self-update to latest version"),
-            ("longtext", "Get a link to paste long text (over 2000 chars)"),
-        ];
+    match target {
+        AiBackend::Claude => "🔄 **Normal mode** - Using `claude` (Anthropic API) in this channel",
+        AiBackend::ClaudeZ => "⚡ **Z mode ON** - Using `claude-z` (z.ai API) in this channel",
+        AiBackend::Codex => "🅾️ **Codex mode ON** - Using OpenAI Codex CLI in this channel",
+    }
+    .to_string()
+}
 
-        for (name, desc) in &command_defs {
-            let cmd = CreateCommand::new(*name).description(*desc);
-            if let Err(e) = serenity::model::application::Command::create_global_command(&ctx.http, cmd).await {
-                tracing::error!("Failed to register /{}: {}", name, e);
-            }
-        }
+/// `/backend`: a select menu of the backends whose CLI is installed
+async fn backend_menu(ctx: &serenity::client::Context, channel_id: u64) -> CreateInteractionResponseMessage {
+    let current = get_channel_backend(ctx, channel_id).await;
+    let options: Vec<CreateSelectMenuOption> = BACKENDS
+        .iter()
+        .filter(|backend| claude::find_cli(backend.cli_name()).is_some())
+        .map(|backend| {
+            let label = match backend {
+                AiBackend::Claude => "Claude",
+                AiBackend::ClaudeZ => "Claude-Z",
+                AiBackend::Codex => "Codex",
+            };
+            CreateSelectMenuOption::new(label, backend.cli_name())
+                .description(backend.status_line())
+                .default_selection(*backend == current)
+        })
+        .collect();
+    if options.is_empty() {
+        return CreateInteractionResponseMessage::new()
+            .content("❌ No backend CLI found (claude, claude-z or codex).")
+            .ephemeral(true);
+    }
+    let menu = CreateSelectMenu::new(BACKEND_MENU_ID, CreateSelectMenuKind::String { options })
+        .placeholder("Choose a backend");
+    CreateInteractionResponseMessage::new()
+        .content(format!("Current: {}\nSwitching resets your session in this channel.", current.status_line()))
+        .components(vec![CreateActionRow::SelectMenu(menu)])
+        .ephemeral(true)
+}
 
-        // Register /slash with a required string option
-        {
-            use serenity::model::application::CommandOptionType;
-            let slash_cmd = CreateCommand::new("slash")
-                .description("Run a Claude Code slash command")
-                .add_option(
-                    serenity::builder::CreateCommandOption::new(
-                        CommandOptionType::String,
-                        "command",
-                        "The slash command to run (e.g., compact, cost, doctor)",
-                    )
-                    .required(true),
-                );
-            if let Err(e) = serenity::model::application::Command::create_global_command(&ctx.http, slash_cmd).await {
-                tracing::error!("Failed to register /slash: {}", e);
-            }
+/// A choice from the `/backend` menu
+async fn handle_backend_menu(ctx: &serenity::client::Context, component: &ComponentInteraction) {
+    use serenity::model::application::ComponentInteractionDataKind;
+
+    let user = &component.user;
+    let channel_id = component.channel_id.get();
+    let config = Config::load().unwrap_or_default();
+    let required = find_command("backend").map(|spec| spec.tier).unwrap_or(Tier::Admin);
+    let target = match &component.data.kind {
+        ComponentInteractionDataKind::StringSelect { values } => values
+            .first()
+            .and_then(|value| BACKENDS.iter().copied().find(|b| b.cli_name() == value)),
+        _ => None,
+    };
+    let busy = {
+        let data = ctx.data.read().await;
+        match data.get::<ProcessingChannels>() {
+            Some(processing) => processing.read().await.contains_key(&channel_id),
+            None => false,
         }
+    };
 
-        tracing::info!("Registered {} slash commands", command_defs.len() + 1);
+    let reply = if user_tier(&config, user.id.get()).is_none_or(|t| t < required) {
+        log_unauthorized(ctx, &user.name, user.id.get(), channel_id, "/backend").await;
+        "⛔ You don't have permission for that command.".to_string()
+    } else if busy {
+        "⏳ This channel is busy. Use `!stop` first, then switch backends.".to_string()
+    } else if let Some(target) = target.filter(|b| claude::find_cli(b.cli_name()).is_some()) {
+        audit::record(AuditEntry::new("command", "/backend", user.id.get(), &user.name, channel_id, target.cli_name()));
+        let mode_msg = switch_backend(ctx, component.channel_id, user.id.get(), target).await;
+        let _ = component.channel_id.say(&ctx.http, &mode_msg).await;
+        format!("Switched to {}.", target.status_line())
+    } else {
+        "❌ That backend isn't available.".to_string()
+    };
 
-        for guild in &ready.guilds {
-            if let Ok(channels) = guild.id.channels(&ctx.http).await {
-                for (id, channel) in channels {
-                    if ChannelType::from_name(&channel.name) == ChannelType::Logs {
-                        let data = ctx.data.read().await;
-                        if let Some(logs_channel) = data.get::<LogsChannel>() {
-                            *logs_channel.write().await = Some(id);
-                            tracing::info!("Found logs channel: #{}", channel.name);
-                        }
-                        break;
-                    }
-                }
-            }
+    let response = CreateInteractionResponse::UpdateMessage(
+        CreateInteractionResponseMessage::new().content(reply).components(Vec::new()),
+    );
+    let _ = component.create_response(&ctx.http, response).await;
+}
+
+/// Gate for `!z` / `!codex`. Refuses while the channel is processing, and asks
+/// for `!<command> confirm` when the user's session or queued messages are at
+/// stake. Returns true when the toggle may go ahead.
+async fn confirm_backend_toggle(ctx: &serenity::client::Context, msg: &Message, command: &str, confirmed: bool) -> bool {
+    let user_id = msg.author.id.get();
+    let channel_id = msg.channel_id.get();
+    let key = (user_id, channel_id);
+    let session = session_key_for(ctx, user_id, channel_id).await;
+
+    let data = ctx.data.read().await;
+    let is_processing = match data.get::<ProcessingChannels>() {
+        Some(processing) => processing.read().await.contains_key(&channel_id),
+        None => false,
+    };
+    if is_processing {
+        drop(data);
+        let _ = msg.channel_id.say(&ctx.http, "⏳ This channel is busy. Use `!stop` first, then switch backends.").await;
+        return false;
+    }
+
+    let has_session = match data.get::<SessionStorage>() {
+        Some(sessions) => sessions.read().await.contains_key(&session),
+        None => false,
+    };
+    let queued = match data.get::<MessageQueue>() {
+        Some(queue) => queue.read().await.get(&channel_id).map(|q| q.len()).unwrap_or(0),
+        None => 0,
+    };
+    if !has_session && queued == 0 {
+        return true;
+    }
+
+    let Some(confirmations) = data.get::<PendingConfirmations>().cloned() else {
+        return true;
+    };
+    drop(data);
+    let now = Instant::now();
+
+    if confirmed {
+        if confirmations.write().await.confirm(key, command, now) {
+            return true;
         }
+        let _ = msg
+            .channel_id
+            .say(&ctx.http, format!("Nothing to confirm (it may have expired). Send `!{}` again.", command))
+            .await;
+        return false;
     }
 
-    async fn interaction_create(&self, ctx: serenity::client::Context, interaction: Interaction) {
-        if let Interaction::Command(command) = interaction {
-            let channel_id = command.channel_id.get();
-            let user_id = command.user.id.get();
-            let session_key = (user_id, channel_id);
+    confirmations.write().await.request(key, command, now);
+    let stake = match (has_session, queued) {
+        (true, 0) => "This will reset your session.".to_string(),
+        (true, n) => format!("This will reset your session, and {} queued message(s) will run on the new backend without context.", n),
+        (false, n) => format!("{} queued message(s) will run on the new backend without context.", n),
+    };
+    let _ = msg
+        .channel_id
+        .say(&ctx.http, format!(
+            "⚠️ {} Type `!{} confirm` within {}s to switch.",
+            stake, command, confirm::CONFIRM_WINDOW.as_secs()
+        ))
+        .await;
+    false
+}
 
-            let response_msg = match command.data.name.as_str() {
-                "help" => {
-                    format!(
-                        "**Neywa v{}** - AI Assistant\n\n\
-                        **Commands** (slash `/` or text `!`):\n\
-                        `help` - Show this help\n\
-                        `status` - Check session status\n\
-                        `new` - Start a new conversation\n\
-                        `stop` - Stop processing & clear queue\n\
-                        `queue` - Show queued messages\n\
-                        `compact` - Compact session context window\n\
-                        `update` - Update to latest version\n\
-                        `longtext` - How to send long text\n\
-                        `slash <cmd>` - Run Claude Code slash command\n\n\
-                        **Text-only Commands:**\n\
-                        `!plan <msg>` - Generate a plan without executing (read-only)\n\
-                        `!z` - Toggle Z mode (claude-z)\n\
-                        `!codex` - Toggle Codex mode (OpenAI Codex CLI)\n\
-                        `!human` - Toggle human-only mode (Neywa stops responding)\n\
-                        `!restart` - Reset all Claude sessions (fixes MCP/connection issues)\n\n\
-                        Just type a message to chat with AI.",
-                        VERSION
-                    )
-                }
-                "status" => {
-                    let data = ctx.data.read().await;
-                    let backend = if let Some(backends) = data.get::<ChannelBackends>() {
-                        backends.read().await.get(&channel_id).copied().unwrap_or(AiBackend::Claude)
-                    } else { AiBackend::Claude };
-                    let is_processing = if let Some(processing) = data.get::<ProcessingChannels>() {
-                        processing.read().await.contains_key(&channel_id)
-                    } else { false };
-                    let queue_size = if let Some(queue) = data.get::<MessageQueue>() {
-                        queue.read().await.get(&channel_id).map(|q| q.len()).unwrap_or(0)
-                    } else { 0 };
+/// Name of the user whose run is in progress, if it isn't `user_id`'s
+async fn foreign_run_owner(data: &TypeMap, channel_id: u64, user_id: u64) -> Option<String> {
+    let processing = data.get::<ProcessingChannels>()?.read().await;
+    let run = processing.get(&channel_id)?;
+    (run.author_id != user_id).then(|| run.author_name.clone())
+}
 
-                    let mode = backend.status_line();
-                    let proc = if is_processing { "🔄 Processing" } else { "✅ Idle" };
-                    let queue = if queue_size > 0 { format!("📬 Queue: {}", queue_size) } else { "📭 Queue: empty".to_string() };
-                    format!("**v{}**\n{}\n{}\n{}", VERSION, mode, proc, queue)
-                }
-                "new" => {
-                    let data = ctx.data.read().await;
-                    if let Some(sessions) = data.get::<SessionStorage>() {
-                        let mut sessions_map = sessions.write().await;
-                        sessions_map.remove(&session_key);
-                        save_sessions(&sessions_map);
-                    }
-                    "🔄 New session started.".to_string()
-                }
-                "stop" => {
-                    let data = ctx.data.read().await;
-                    let mut cancelled = false;
-                    let mut cleared = 0usize;
+/// The Stop button on a status message: cancel that run (the queue keeps going)
+async fn handle_stop_button(ctx: &serenity::client::Context, component: &ComponentInteraction) {
+    let user = &component.user;
+    let channel_id = component.channel_id.get();
+    let tier = user_tier(&Config::load().unwrap_or_default(), user.id.get());
+    let refusal = {
+        let data = ctx.data.read().await;
+        let processing = match data.get::<ProcessingChannels>() {
+            Some(processing) => processing.read().await,
+            None => return,
+        };
+        match processing.get(&channel_id).filter(|run| run.status_message == Some(component.message.id)) {
+            None => Some("This run has already finished.".to_string()),
+            Some(run) if !tier.is_some_and(|tier| run.may_cancel(user.id.get(), tier)) => {
+                Some(format!("⛔ Only {} or an admin can stop this run.", run.author_name))
+            }
+            Some(run) => {
+                tracing::info!("Run in channel {} cancelled by Stop button from {}", channel_id, user.name);
+                run.cancel_token.cancel();
+                None
+            }
+        }
+    };
 
-                    if let Some(processing) = data.get::<ProcessingChannels>() {
-                        if let Some(token) = processing.read().await.get(&channel_id) {
-                            token.cancel();
-                            cancelled = true;
-                        }
-                    }
-                    if let Some(queue) = data.get::<MessageQueue>() {
-                        let mut q = queue.write().await;
-                        if let Some(channel_queue) = q.get_mut(&channel_id) {
-                            cleared = channel_queue.len();
-                            channel_queue.clear();
-                        }
-                    }
+    let response = match refusal {
+        Some(text) => CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new().content(text).ephemeral(true),
+        ),
+        None => CreateInteractionResponse::UpdateMessage(
+            CreateInteractionResponseMessage::new()
+                .content(format!("🛑 Stopping ({})...", user.name))
+                .components(Vec::new()),
+        ),
+    };
+    let _ = component.create_response(&ctx.http, response).await;
+}
 
-                    let mut parts = Vec::new();
-                    if cancelled { parts.push("🛑 Processing stopped".to_string()); }
-                    if cleared > 0 { parts.push(format!("📭 {} queued message(s) cleared", cleared)); }
-                    if parts.is_empty() { parts.push("Nothing to stop.".to_string()); }
-                    parts.join("\n")
-                }
-                "queue" => {
-                    let data = ctx.data.read().await;
-                    let queue_size = if let Some(queue) = data.get::<MessageQueue>() {
-                        queue.read().await.get(&channel_id).map(|q| q.len()).unwrap_or(0)
-                    } else { 0 };
-                    let is_processing = if let Some(processing) = data.get::<ProcessingChannels>() {
-                        processing.read().await.contains_key(&channel_id)
-                    } else { false };
-
-                    if is_processing {
-                        format!("🔄 Processing | 📬 Queue: {}", queue_size)
-                    } else if queue_size > 0 {
-                        format!("📬 Queue: {}", queue_size)
-                    } else {
-                        "📭 Queue is empty.".to_string()
-                    }
-                }
-                "update" => {
-                    // Respond immediately, then handle update asynchronously
-                    let response = CreateInteractionResponse::Message(
-                        CreateInteractionResponseMessage::new()
-                            .content("🔄 Checking for updates...")
-                    );
-                    let _ = command.create_response(&ctx.http, response).await;
+/// Approve/Reject button on a destructive `neywa discord` action
+async fn handle_pending_action(ctx: &serenity::client::Context, component: &ComponentInteraction) {
+    let mut parts = component.data.custom_id.splitn(3, ':').skip(1);
+    let (Some(choice), Some(id)) = (parts.next(), parts.next()) else {
+        return;
+    };
 
-                    // Do the update in the channel as regular messages
-                    let channel = command.channel_id;
-                    let http = ctx.http.clone();
-                    let data = ctx.data.clone();
+    let user = &component.user;
+    let config = Config::load().unwrap_or_default();
+    if user_tier(&config, user.id.get()).is_none() {
+        log_unauthorized(ctx, &user.name, user.id.get(), component.channel_id.get(), "approve action").await;
+        let response = CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new()
+                .content("⛔ You don't have permission to approve this.")
+                .ephemeral(true),
+        );
+        let _ = component.create_response(&ctx.http, response).await;
+        return;
+    }
 
-                    tokio::spawn(async move {
-                        let remote_version = match fetch_remote_version().await {
-                            Ok(v) => v,
-                            Err(e) => {
-                                let _ = channel.say(&http, format!("❌ Failed to check version: {}", e)).await;
-                                return;
-                            }
-                        };
+    let result = match discord_api::take_pending_action(id) {
+        None => "⌛ This action expired or was already handled.".to_string(),
+        Some(_) if choice == "reject" => format!("❌ Rejected by {}.", user.name),
+        Some(action) => {
+            audit::record(AuditEntry::new(
+                "command",
+                "approve",
+                user.id.get(),
+                &user.name,
+                component.channel_id.get(),
+                &format!("{:?}", action.op),
+            ));
+            match discord_api::execute_pending(&action).await {
+                Ok(done) => format!("✅ {} (approved by {}).", done, user.name),
+                Err(e) => format!("❌ Approved by {}, but it failed: {}", user.name, e),
+            }
+        }
+    };
 
-                        if remote_version == VERSION {
-                            let _ = channel.say(&http, format!("✅ Already on the latest version (v{})", VERSION)).await;
-                            return;
-                        }
+    let response = CreateInteractionResponse::UpdateMessage(
+        CreateInteractionResponseMessage::new()
+            .content(result)
+            .components(Vec::new()),
+    );
+    let _ = component.create_response(&ctx.http, response).await;
+}
 
-                        let _ = channel.say(&http, format!("📥 v{} → v{}", VERSION, remote_version)).await;
+/// `!timing`: where the channel's last run spent its time, next to recent medians
+async fn timing_report(ctx: &serenity::client::Context, channel_id: u64) -> String {
+    let data = ctx.data.read().await;
+    let last = match data.get::<LastRunTimings>() {
+        Some(last) => last.read().await.get(&channel_id).cloned(),
+        None => None,
+    };
+    let Some((timings, total, outcome)) = last else {
+        return "⏱ No runs in this channel since Neywa started.".to_string();
+    };
+    let metrics = data.get::<MetricsStorage>().cloned();
+    drop(data);
+    let metrics = match &metrics {
+        Some(metrics) => Some(metrics.read().await),
+        None => None,
+    };
 
-                        match self_update().await {
-                            Ok(()) => {
-                                if let Err(e) = save_update_pending(channel.get(), VERSION, &remote_version) {
-                                    tracing::warn!("Failed to save update pending: {}", e);
-                                }
+    let mut lines = vec![format!("⏱ **Last run** ({}, {:.1}s total)", outcome, total.as_secs_f64())];
+    for (phase, duration) in timings.iter() {
+        let median = metrics
+            .as_ref()
+            .and_then(|m| m.phase_median(phase))
+            .map(|m| format!(" (recent median {:.1}s)", m.as_secs_f64()))
+            .unwrap_or_default();
+        lines.push(format!("`{}`: {:.1}s{}", phase.name(), duration.as_secs_f64(), median));
+    }
+    lines.join("\n")
+}
 
-                                let _ = channel.say(&http, "✅ Update downloaded. Restarting...").await;
-                                tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+/// Model a backend runs with, for `!context`, `!help` and `!status`.
+/// Codex always uses its own model; `!model` only applies to Claude.
+fn model_label(backend: AiBackend, channel_model: Option<&str>) -> String {
+    match (backend, channel_model) {
+        (AiBackend::Codex, _) => codex::MODEL.to_string(),
+        (_, Some(model)) => model.to_string(),
+        (_, None) => "CLI default".to_string(),
+    }
+}
 
-                                restart_after_update();
-                            }
-                            Err(e) => {
-                                let _ = channel.say(&http, format!("❌ Update failed: {}", e)).await;
-                            }
-                        }
-                    });
-                    return; // Already responded
-                }
-                "compact" => {
-                    // Respond immediately, then handle async
-                    let response = CreateInteractionResponse::Message(
-                        CreateInteractionResponseMessage::new()
-                            .content("🗜️ Compacting session...")
-                    );
-                    let _ = command.create_response(&ctx.http, response).await;
+/// The most recently used session in a channel, whoever it belongs to
+fn latest_channel_session(sessions: &HashMap<SessionKey, SessionEntry>, channel_id: u64) -> Option<&SessionEntry> {
+    sessions
+        .iter()
+        .filter(|((_, channel), _)| *channel == channel_id)
+        .map(|(_, entry)| entry)
+        .max_by_key(|entry| entry.last_used)
+}
 
-                    let channel = command.channel_id;
-                    let http = ctx.http.clone();
-                    let data_arc = ctx.data.clone();
+/// Run one prompt to completion. None when the resumed session no longer exists.
+async fn collect_response(
+    prompt: &str,
+    session_id: Option<&str>,
+    backend: AiBackend,
+    model: Option<&str>,
+    cwd: Option<&Path>,
+    channel_id: u64,
+) -> Result<Option<String>> {
+    let mut rx = match backend {
+        AiBackend::Codex => codex::run_streaming(prompt, session_id, &[], cwd, channel_id).await?,
+        _ => claude::run_streaming(prompt, session_id, backend == AiBackend::ClaudeZ, model, cwd, channel_id).await?,
+    };
+    let mut text = String::new();
+    while let Some(event) = rx.recv().await {
+        match event {
+            StreamEvent::Text(t) => text = t,
+            StreamEvent::SessionNotFound => return Ok(None),
+            StreamEvent::Error(e) | StreamEvent::AuthRequired(e) => anyhow::bail!("{}", e),
+            StreamEvent::Done => break,
+            _ => {}
+        }
+    }
+    Ok(Some(text))
+}
 
-                    let existing_session = {
-                        let data = data_arc.read().await;
-                        if let Some(sessions) = data.get::<SessionStorage>() {
-                            sessions.read().await.get(&session_key).cloned()
-                        } else {
-                            None
-                        }
-                    };
+/// `neywa run --channel`: answer a prompt with a channel's backend, model, language,
+/// working directory and latest session. The daemon's state files are only read, never written, so a
+/// session this advances isn't recorded and the daemon keeps resuming its own.
+pub async fn run_in_channel(channel: &str, message: &str, post: bool) -> Result<String> {
+    let (channel_id, channel_name) = discord_api::resolve_channel(channel).await?;
+    let channel_type = ChannelType::from_name(&channel_name);
+    if channel_type == ChannelType::Logs {
+        anyhow::bail!("#{} is a logs channel; pick a conversation channel", channel_name);
+    }
+    let (system_prompt, _) = channel_system_prompt(&Config::load().unwrap_or_default(), channel_id, &channel_name, &channel_type);
+    let backend = load_channel_backends().get(&channel_id).copied().unwrap_or(AiBackend::Claude);
+    let language = load_channel_languages().remove(&channel_id).and_then(|l| l.language);
+    let model = load_channel_models().remove(&channel_id);
+    let mut workdir = load_channel_workdirs().remove(&channel_id);
+    let session = latest_channel_session(&load_sessions(), channel_id).map(|entry| entry.id.clone());
+
+    if let Some(sid) = &session {
+        eprintln!(
+            "Resuming #{}'s {} session {}. The daemon isn't told about this run and may keep using its own session ID.",
+            channel_name,
+            backend.cli_name(),
+            sid
+        );
+        // Claude keeps sessions per project directory
+        if backend != AiBackend::Codex {
+            if let Some(dir) = transcript::claude_session_cwd(sid) {
+                workdir = Some(dir);
+            }
+        }
+    }
 
-                    let use_z = {
-                        let data = data_arc.read().await;
-                        if let Some(backends) = data.get::<ChannelBackends>() {
-                            backends.read().await.get(&channel_id).copied() == Some(AiBackend::ClaudeZ)
-                        } else {
-                            false
-                        }
-                    };
+    let build = |first_message: bool| {
+        prompt::build(&prompt::PromptContext {
+            username: "neywa run",
+            content: message,
+            attachments: "",
+            channel_prompt: &system_prompt,
+            first_message,
+            language: language.as_deref(),
+            language_changed: false,
+        })
+    };
+    let response = match collect_response(&build(session.is_none()), session.as_deref(), backend, model.as_deref(), workdir.as_deref(), channel_id).await? {
+        Some(text) => text,
+        None => {
+            eprintln!("That session no longer exists; running without it.");
+            collect_response(&build(true), None, backend, model.as_deref(), workdir.as_deref(), channel_id).await?.unwrap_or_default()
+        }
+    };
 
-                    tokio::spawn(async move {
-                        if let Some(sid) = existing_session {
-                            match claude::compact_session(&sid, use_z).await {
-                                Ok(_) => {
-                                    let _ = channel.say(&http, "✅ Session compacted.").await;
-                                }
-                                Err(e) => {
-                                    if trim_session_file(&sid) {
-                                        let _ = channel.say(&http, "⚠️ Compact failed, trimmed old messages instead.").await;
-                                    } else {
-                                        let _ = channel.say(&http, format!("❌ Compact failed: {}", e)).await;
-                                    }
-                                }
-                            }
-                        } else {
-                            let _ = channel.say(&http, "No active session. Nothing to compact.").await;
-                        }
-                    });
-                    return; // Already responded
-                }
-                "slash" => {
-                    let slash_cmd = command.data.options.first()
-                        .and_then(|opt| opt.value.as_str())
-                        .unwrap_or("")
-                        .to_string();
+    if post && !response.trim().is_empty() {
+        // Only the Discord copy is redacted; the terminal gets the full answer
+        let redacted = Redactor::from_config(&Config::load().unwrap_or_default()).redact(&response);
+        for chunk in split_for_discord(&redacted) {
+            discord_api::post_message(channel_id, &chunk).await?;
+        }
+    }
+    Ok(response)
+}
 
-                    if slash_cmd.is_empty() {
-                        "Usage: `/slash <command>` (e.g., `/slash compact`, `/slash cost`)".to_string()
-                    } else {
-                        // Respond immediately, then handle async
-                        let display_cmd = slash_cmd.trim_start_matches('/').to_string();
-                        let response = CreateInteractionResponse::Message(
-                            CreateInteractionResponseMessage::new()
-                                .content(format!("⚡ Running `/{}`...", display_cmd))
-                        );
-                        let _ = command.create_response(&ctx.http, response).await;
+/// The daemon's working directory, which runs inherit
+fn workdir_label() -> String {
+    std::env::current_dir()
+        .map(|d| d.display().to_string())
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+/// `!help` / `/help` for one user in one channel: only what they can run here
+async fn help_text(ctx: &serenity::client::Context, user_id: u64, channel_id: u64) -> String {
+    let config = Config::load().unwrap_or_default();
+    let tier = user_tier(&config, user_id);
+    let backend = get_channel_backend(ctx, channel_id).await;
+    let info = channel_info_for(ctx, ChannelId::new(channel_id)).await;
+    let in_dm = info.kind == ChannelKind::Dm;
+    let allowed = COMMANDS
+        .iter()
+        .filter(|spec| tier.is_some_and(|tier| tier >= spec.tier))
+        .filter(|spec| !(in_dm && GUILD_ONLY_COMMANDS.contains(&spec.name)))
+        .map(|spec| spec.name)
+        .collect();
+    help::render(&help::Capabilities {
+        version: VERSION,
+        backend,
+        model: model_label(backend, get_channel_model(ctx, channel_id).await.as_deref()),
+        workdir: channel_workdir_label(ctx, channel_id).await,
+        quiet_hours: config.quiet_hours_for(info.guild_id).and_then(quiet::QuietHours::parse).is_some(),
+        allowed,
+        aliases: config.aliases.get(&info.guild_id.unwrap_or(0)).map(|aliases| aliases.keys().cloned().collect()).unwrap_or_default(),
+    })
+}
 
-                        let channel = command.channel_id;
-                        let http = ctx.http.clone();
-                        let data_arc = ctx.data.clone();
+/// Messages `!history` shows by default, and at most
+const HISTORY_DEFAULT: usize = 5;
+const HISTORY_MAX: usize = 20;
+
+/// Characters of each message `!history` shows
+const HISTORY_PREVIEW_CHARS: usize = 200;
+
+/// `!history [n]`: the last n exchanges of the caller's session in this channel
+async fn session_history(ctx: &serenity::client::Context, user_id: u64, channel_id: u64, args: &str) -> String {
+    let count = match args {
+        "" => HISTORY_DEFAULT,
+        n => match n.parse::<usize>() {
+            Ok(n) if (1..=HISTORY_MAX).contains(&n) => n,
+            _ => return format!("Usage: `!history [1-{}]`", HISTORY_MAX),
+        },
+    };
+    if get_channel_backend(ctx, channel_id).await == AiBackend::Codex {
+        return "📜 History isn't available for Codex sessions.".to_string();
+    }
+    let key = session_key_for(ctx, user_id, channel_id).await;
+    let session = {
+        let data = ctx.data.read().await;
+        match data.get::<SessionStorage>() {
+            Some(sessions) => sessions.read().await.get(&key).map(|entry| entry.id.clone()),
+            None => None,
+        }
+    };
+    let Some(session_id) = session else {
+        return "📜 No session in this channel yet.".to_string();
+    };
+    let short: String = session_id.chars().take(8).collect();
+    let Some(path) = transcript::find_claude_session_file(&session_id) else {
+        return format!("❌ Session file for `{}…` not found.", short);
+    };
+    let jsonl = match std::fs::read_to_string(&path) {
+        Ok(jsonl) => jsonl,
+        Err(e) => return format!("❌ Couldn't read session file: {}", e),
+    };
+    let messages = transcript::recent_claude_messages(&jsonl, count);
+    if messages.is_empty() {
+        return format!("📜 Session `{}…` has no messages yet.", short);
+    }
 
-                        let existing_session = {
-                            let data = data_arc.read().await;
-                            if let Some(sessions) = data.get::<SessionStorage>() {
-                                sessions.read().await.get(&session_key).cloned()
-                            } else {
-                                None
-                            }
-                        };
+    let mut lines = vec![format!("📜 Last {} message(s) of session `{}…`:", messages.len(), short)];
+    for message in messages {
+        let role = if message.from_user { "👤" } else { "🤖" };
+        let flat = message.text.split_whitespace().collect::<Vec<_>>().join(" ");
+        let mut preview: String = flat.chars().take(HISTORY_PREVIEW_CHARS).collect();
+        if flat.chars().count() > HISTORY_PREVIEW_CHARS {
+            preview.push('…');
+        }
+        lines.push(format!("{} {}", role, preview));
+    }
+    lines.join("\n")
+}
 
-                        let use_z = {
-                            let data = data_arc.read().await;
-                            if let Some(backends) = data.get::<ChannelBackends>() {
-                                backends.read().await.get(&channel_id).copied() == Some(AiBackend::ClaudeZ)
-                            } else {
-                                false
-                            }
-                        };
+/// `!undo`: remove the caller's last prompt and everything after it from their
+/// Claude session file, so the next message continues from before that exchange
+async fn undo_exchange(ctx: &serenity::client::Context, user_id: u64, channel_id: u64) -> String {
+    {
+        let data = ctx.data.read().await;
+        if let Some(processing) = data.get::<ProcessingChannels>() {
+            if processing.read().await.contains_key(&channel_id) {
+                return "⏳ A run is in progress here. Wait for it to finish (or `!stop` it) before undoing.".to_string();
+            }
+        }
+    }
+    if get_channel_backend(ctx, channel_id).await == AiBackend::Codex {
+        return "⚠️ Undo isn't available for Codex sessions: Codex keeps its own transcript. Nothing was changed.".to_string();
+    }
+    let key = session_key_for(ctx, user_id, channel_id).await;
+    let session = {
+        let data = ctx.data.read().await;
+        match data.get::<SessionStorage>() {
+            Some(sessions) => sessions.read().await.get(&key).map(|entry| entry.id.clone()),
+            None => None,
+        }
+    };
+    let Some(session_id) = session else {
+        return "↩️ No session in this channel, nothing to undo.".to_string();
+    };
+    let short: String = session_id.chars().take(8).collect();
+    let workdir = get_channel_workdir(ctx, channel_id).await;
+    let Some(path) = transcript::find_claude_session_file_in(&session_id, workdir.as_deref()) else {
+        return format!("❌ Session file for `{}…` not found.", short);
+    };
+    let jsonl = match std::fs::read_to_string(&path) {
+        Ok(jsonl) => jsonl,
+        Err(e) => return format!("❌ Couldn't read session file: {}", e),
+    };
+    let Some((kept, removed)) = transcript::undo_last_claude_turn(&jsonl) else {
+        return format!("↩️ Session `{}…` has no prompt to undo.", short);
+    };
+    if let Err(e) = std::fs::write(&path, kept) {
+        return format!("❌ Couldn't write session file: {}", e);
+    }
+    tracing::info!("Undid last exchange of session {} ({} messages)", session_id, removed);
+    format!(
+        "↩️ Removed the last exchange ({} message{}) from session `{}…`.",
+        removed,
+        if removed == 1 { "" } else { "s" },
+        short
+    )
+}
 
-                        tokio::spawn(async move {
-                            match claude::run_slash_command(&slash_cmd, existing_session.as_deref(), use_z).await {
-                                Ok(result) => {
-                                    let chunks = split_for_discord(&result);
-                                    for chunk in chunks {
-                                        let _ = channel.say(&http, &chunk).await;
-                                    }
-                                }
-                                Err(e) => {
-                                    let _ = channel.say(&http, format!("❌ Error: {}", e)).await;
-                                }
-                            }
-                        });
-                        return; // Already responded
-                    }
-                }
-                "longtext" => {
-                    "📝 **Long Text Input**\n\n\
-                    Discord has a 2000 character limit.\n\
-                    Use this tool to send longer text:\n\n\
-                    👉 **https://copy-once.cc**\n\n\
-                    1. Paste your long text there\n\
-                    2. Copy the generated link\n\
-                    3. Paste the link here with your message".to_string()
-                }
-                _ => return,
-            };
+/// `!export`: the caller's session in this channel as a markdown document.
+/// Small transcripts are posted inline; anything longer is uploaded as a file.
+async fn export_session(ctx: &serenity::client::Context, msg: &Message) {
+    let user_id = msg.author.id.get();
+    let channel_id = msg.channel_id.get();
+    let key = session_key_for(ctx, user_id, channel_id).await;
+    let session = {
+        let data = ctx.data.read().await;
+        match data.get::<SessionStorage>() {
+            Some(sessions) => sessions.read().await.get(&key).map(|entry| entry.id.clone()),
+            None => None,
+        }
+    };
+    let Some(session_id) = session else {
+        let _ = msg.channel_id.say(&ctx.http, "📤 No session in this channel yet.").await;
+        return;
+    };
+    let short: String = session_id.chars().take(8).collect();
+    let backend = get_channel_backend(ctx, channel_id).await;
+    let Some((_, transcript)) = transcript::load_transcript(&session_id, backend) else {
+        let _ = msg.channel_id.say(&ctx.http, format!("❌ Session file for `{}…` not found.", short)).await;
+        return;
+    };
+    if transcript.message_count == 0 {
+        let _ = msg.channel_id.say(&ctx.http, format!("📤 Session `{}…` has no messages yet.", short)).await;
+        return;
+    }
 
-            let response = CreateInteractionResponse::Message(
-                CreateInteractionResponseMessage::new()
-                    .content(response_msg)
-                    .ephemeral(matches!(command.data.name.as_str(), "help" | "longtext"))
-            );
+    let channel_name = match msg.channel_id.to_channel(&ctx.http).await.map(|c| c.guild()) {
+        Ok(Some(channel)) => format!("#{}", channel.name),
+        _ => "DM".to_string(),
+    };
+    let title = format!("{} — {}", channel_name, msg.author.name);
+    let document = transcript::header(&title, &session_id, &transcript) + &transcript.markdown;
 
-            if let Err(e) = command.create_response(&ctx.http, response).await {
-                tracing::error!("Failed to respond to /{}: {}", command.data.name, e);
+    if document.len() <= 1900 {
+        let _ = msg.channel_id.say(&ctx.http, &document).await;
+        return;
+    }
+
+    let path = std::env::temp_dir().join(format!("neywa-export-{}.md", short));
+    if let Err(e) = std::fs::write(&path, &document) {
+        let _ = msg.channel_id.say(&ctx.http, format!("❌ Couldn't write export: {}", e)).await;
+        return;
+    }
+    match CreateAttachment::path(&path).await {
+        Ok(attachment) => {
+            let builder = CreateMessage::new()
+                .content(format!(
+                    "📤 Session `{}…`: {} messages ({})",
+                    short, transcript.message_count, attachment::format_bytes(document.len() as u64)
+                ))
+                .add_file(attachment);
+            if let Err(e) = msg.channel_id.send_message(&ctx.http, builder).await {
+                let _ = msg.channel_id.say(&ctx.http, format!("❌ Upload failed: {}", e)).await;
             }
         }
+        Err(e) => {
+            let _ = msg.channel_id.say(&ctx.http, format!("❌ Couldn't read export: {}", e)).await;
+        }
     }
+    let _ = std::fs::remove_file(&path);
 }
 
-/// Download attachment to temp directory
-async fn download_attachment(url: &str, filename: &str) -> Result<String> {
-    let response = reqwest::get(url).await?;
-    let bytes = response.bytes().await?;
+/// `!prompt`: the system prompt this channel runs with, and where it comes from
+async fn prompt_report(ctx: &serenity::client::Context, channel: ChannelId) -> String {
+    let info = channel_info_for(ctx, channel).await;
+    let (prompt, source) = channel_system_prompt(&Config::load().unwrap_or_default(), channel.get(), &info.name, &info.mode);
+    let origin = match source {
+        PromptSource::Custom(key) => format!("custom, `channel_prompts[\"{}\"]` in config.json", key),
+        PromptSource::BuiltIn => format!("built-in for {:?} channels", info.mode),
+    };
+    format!("📝 **Channel prompt** ({})\n```\n{}\n```", origin, prompt)
+}
+
+/// `!context`: what Neywa would send for the user's next message, without running anything
+async fn context_preview(ctx: &serenity::client::Context, msg: &Message, channel_type: &ChannelType) -> String {
+    let user_id = msg.author.id.get();
+    let channel_id = msg.channel_id.get();
+    let backend = get_channel_backend(ctx, channel_id).await;
+    let key = session_key_for(ctx, user_id, channel_id).await;
+    let session = {
+        let data = ctx.data.read().await;
+        match data.get::<SessionStorage>() {
+            Some(sessions) => sessions.read().await.get(&key).cloned(),
+            None => None,
+        }
+    };
+
+    let channel_name = channel_info_for(ctx, msg.channel_id).await.name;
+    let (system_prompt, _) = channel_system_prompt(&Config::load().unwrap_or_default(), channel_id, &channel_name, channel_type);
+    let model = model_label(backend, get_channel_model(ctx, channel_id).await.as_deref());
+    let workdir = channel_workdir_label(ctx, channel_id).await;
+    let session_line = match &session {
+        Some(entry) => {
+            let short: String = entry.id.chars().take(8).collect();
+            match entry.last_used {
+                Some(last) => format!("`{}…` (last used {} ago)", short, format_idle(Local::now() - last)),
+                None => format!("`{}…`", short),
+            }
+        }
+        None => "none — the next message starts a new session".to_string(),
+    };
+    let tools = match backend {
+        AiBackend::Codex => "unrestricted (`--dangerously-bypass-approvals-and-sandbox`)",
+        _ => "unrestricted (`--dangerously-skip-permissions`); `!plan` runs read-only",
+    };
+    let extra_prompt = match backend {
+        AiBackend::Codex => "",
+        _ => "\nNeywa's own instructions are also passed via `--append-system-prompt` (not shown).",
+    };
+    let (language, language_changed) = {
+        let data = ctx.data.read().await;
+        match data.get::<ChannelLanguages>() {
+            Some(languages) => languages
+                .read()
+                .await
+                .get(&channel_id)
+                .map(|setting| (setting.language.clone(), setting.pending.contains(&user_id)))
+                .unwrap_or_default(),
+            None => (None, false),
+        }
+    };
+    let envelope = prompt::build(&prompt::PromptContext {
+        username: &msg.author.name,
+        content: "hello",
+        attachments: "",
+        channel_prompt: &system_prompt,
+        first_message: session.is_none(),
+        language: language.as_deref(),
+        language_changed,
+    });
 
-    let temp_dir = std::env::temp_dir().join("neywa_attachments");
-    std::fs::create_dir_all(&temp_dir)?;
+    let preview = format!(
+        "🔍 **Context for your next message**\n\
+        Channel type: {:?}\n\
+        Channel prompt: {}\n\
+        {}\n\
+        Model: {}\n\
+        Workdir: `{}`\n\
+        Session: {}\n\
+        Tools: {}{}\n\n\
+        Prompt for \"hello\":\n```\n{}\n```",
+        channel_type, system_prompt, backend.status_line(), model, workdir, session_line, tools, extra_prompt, envelope
+    );
+    Redactor::from_config(&Config::load().unwrap_or_default()).redact(&preview)
+}
 
-    let file_path = temp_dir.join(filename);
-    std::fs::write(&file_path, &bytes)?;
+/// Nudge toward `!compact` the first time a session crosses `compact_suggest_percent`
+async fn suggest_compact(ctx: &serenity::client::Context, msg: &Message, session_id: &str) {
+    let threshold = Config::load().unwrap_or_default().compact_suggest_percent;
+    if threshold == 0 {
+        return;
+    }
+    let Some(percent) = context_fill_percent(session_id) else {
+        return;
+    };
+    if percent < threshold as u64 {
+        return;
+    }
 
-    Ok(file_path.to_string_lossy().to_string())
+    let data = ctx.data.read().await;
+    let Some(suggested) = data.get::<CompactSuggested>() else {
+        return;
+    };
+    if !suggested.write().await.insert(session_id.to_string()) {
+        return;
+    }
+    drop(data);
+    let _ = msg
+        .channel_id
+        .say(&ctx.http, format!("🧠 This session's context is ~{}% full. Run `!compact` to free up space before it runs out.", percent))
+        .await;
 }
 
 /// Extract file paths from response text
@@ -1878,37 +9002,43 @@ fn extract_file_paths(text: &str) -> Vec<String> {
 }
 
 /// Split text into chunks for Discord's 2000 char limit
-fn split_for_discord(text: &str) -> Vec<String> {
-    const MAX_LEN: usize = 1900;
+pub fn split_for_discord(text: &str) -> Vec<String> {
+    split_text(text, 1900)
+}
+
+/// Split text into chunks of at most `max_len` bytes (plus a closing fence),
+/// breaking at line boundaries. A code block cut in two is closed at the end
+/// of one chunk and reopened, with its language, at the start of the next.
+/// Lines too long for a chunk are broken at a space where there is one.
+fn split_text(text: &str, max_len: usize) -> Vec<String> {
     let mut chunks = Vec::new();
     let mut current = String::new();
+    // Opening line of the code block `current` is inside, if any
+    let mut open_fence: Option<String> = None;
 
     for line in text.lines() {
-        if current.len() + line.len() + 1 > MAX_LEN {
-            if !current.is_empty() {
-                chunks.push(current);
-                current = String::new();
-            }
-            if line.len() > MAX_LEN {
-                let chars: Vec<char> = line.chars().collect();
-                let mut i = 0;
-                while i < chars.len() {
-                    let end = std::cmp::min(i + MAX_LEN, chars.len());
-                    chunks.push(chars[i..end].iter().collect());
-                    i = end;
+        // Leave room for the fence reopened above a piece and closed below it
+        let reserve = open_fence.as_ref().map_or(0, |fence| 2 * fence.len() + 2);
+        for piece in break_line(line, max_len.saturating_sub(reserve).max(max_len / 2)) {
+            if !current.is_empty() && current.len() + piece.len() + 1 > max_len {
+                if let Some(fence) = &open_fence {
+                    current.push('\n');
+                    current.push_str(fence_marker(fence));
+                }
+                chunks.push(std::mem::take(&mut current));
+                if let Some(fence) = &open_fence {
+                    current = fence.clone();
                 }
-            } else {
-                current = line.to_string();
             }
-        } else {
             if !current.is_empty() {
                 current.push('\n');
             }
-            current.push_str(line);
+            current.push_str(piece);
         }
+        open_fence = fence_after(open_fence, line);
     }
 
-    if !current.is_empty() {
+    if !current.is_empty() && open_fence.as_deref() != Some(current.as_str()) {
         chunks.push(current);
     }
 
@@ -1919,6 +9049,59 @@ fn split_for_discord(text: &str) -> Vec<String> {
     chunks
 }
 
+/// The backtick run of a fence line ("```rust" -> "```")
+fn fence_marker(fence: &str) -> &str {
+    let ticks = fence.len() - fence.trim_start_matches('`').len();
+    &fence[..ticks]
+}
+
+/// The code block open after `line`, given the opening line of the one open
+/// before it. Blocks open with three or more backticks and an optional
+/// language, and close only on a bare run of at least as many backticks, so a
+/// "```rust" line inside a block is content and four-backtick blocks can hold
+/// three-backtick ones.
+fn fence_after(open: Option<String>, line: &str) -> Option<String> {
+    let trimmed = line.trim();
+    let marker = fence_marker(trimmed);
+    match open {
+        Some(fence) => {
+            let closes = marker.len() == trimmed.len() && marker.len() >= fence_marker(&fence).len();
+            (!closes).then_some(fence)
+        }
+        None if marker.len() >= 3 && !trimmed[marker.len()..].contains('`') => Some(trimmed.to_string()),
+        None => None,
+    }
+}
+
+/// Break a line into pieces of at most `limit` bytes, at the last space
+/// before the limit when there is one in its second half (the space becomes
+/// the line break)
+fn break_line(line: &str, limit: usize) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut rest = line;
+    while rest.len() > limit {
+        let mut end = limit;
+        while end > 0 && !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        if end == 0 {
+            end = rest.chars().next().map_or(rest.len(), char::len_utf8);
+        }
+        match rest[..end].rfind(' ').filter(|space| *space > limit / 2) {
+            Some(space) => {
+                pieces.push(&rest[..space]);
+                rest = &rest[space + 1..];
+            }
+            None => {
+                pieces.push(&rest[..end]);
+                rest = &rest[end..];
+            }
+        }
+    }
+    pieces.push(rest);
+    pieces
+}
+
 /// Edit a message
 async fn edit_message(ctx: &serenity::client::Context, msg: &Message, content: &str) -> Result<()> {
     msg.channel_id
@@ -1935,43 +9118,110 @@ async fn log_activity(
     request: &str,
     response: &str,
 ) {
-    let data = ctx.data.read().await;
-    if let Some(logs_channel) = data.get::<LogsChannel>() {
-        if let Some(channel_id) = *logs_channel.read().await {
-            let truncated_req: String = request.chars().take(100).collect();
-            let truncated_req = if request.chars().count() > 100 {
-                format!("{}...", truncated_req)
-            } else {
-                truncated_req
-            };
-            let truncated_resp: String = response.chars().take(200).collect();
-            let truncated_resp = if response.chars().count() > 200 {
-                format!("{}...", truncated_resp)
-            } else {
-                truncated_resp
-            };
+    let redactor = Redactor::from_config(&Config::load().unwrap_or_default());
+    let request = &redactor.redact(request);
+    let response = &redactor.redact(response);
 
-            let log_msg = format!(
-                "**{}** in `{:?}`\n> {}\n```\n{}\n```",
-                user, channel_type, truncated_req, truncated_resp
-            );
+    let truncated_req: String = request.chars().take(100).collect();
+    let truncated_req = if request.chars().count() > 100 {
+        format!("{}...", truncated_req)
+    } else {
+        truncated_req
+    };
+    let truncated_resp: String = response.chars().take(200).collect();
+    let truncated_resp = if response.chars().count() > 200 {
+        format!("{}...", truncated_resp)
+    } else {
+        truncated_resp
+    };
+
+    let log_msg = format!(
+        "**{}** in `{:?}`\n> {}\n```\n{}\n```",
+        user, channel_type, truncated_req, truncated_resp
+    );
+    post_to_logs(ctx, &log_msg).await;
+}
+
+/// Longest the shutdown notices may hold up the exit (also capped at half the grace period)
+const SHUTDOWN_NOTICE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Tell the logs channel, and every channel with a running or queued request,
+/// that Neywa is going away and what will be lost
+async fn announce_shutdown(data: &RwLock<TypeMap>, http: Arc<serenity::http::Http>, reason: shutdown::Reason) {
+    let (logs, queued, running) = {
+        let data = data.read().await;
+        let logs = match data.get::<LogsChannel>() {
+            Some(logs) => *logs.read().await,
+            None => None,
+        };
+        let queued: HashMap<u64, usize> = match data.get::<MessageQueue>() {
+            Some(queue) => queue
+                .read()
+                .await
+                .iter()
+                .filter(|(_, q)| !q.is_empty())
+                .map(|(channel, q)| (*channel, q.len()))
+                .collect(),
+            None => HashMap::new(),
+        };
+        let running: HashSet<u64> = match data.get::<ProcessingChannels>() {
+            Some(processing) => processing.read().await.keys().copied().collect(),
+            None => HashSet::new(),
+        };
+        (logs, queued, running)
+    };
+
+    let total: usize = queued.values().sum();
+    let mut notices: Vec<(ChannelId, String)> = Vec::new();
+    if let Some(logs) = logs {
+        notices.push((logs, format!(
+            "🔌 Neywa is shutting down ({}) — {} queued message(s) will be dropped",
+            reason.describe(),
+            total
+        )));
+    }
+    for channel in running.iter().chain(queued.keys()).copied().collect::<HashSet<u64>>() {
+        if logs.is_some_and(|logs| logs.get() == channel) {
+            continue;
+        }
+        let dropped = queued.get(&channel).copied().unwrap_or(0);
+        let text = match (running.contains(&channel), dropped) {
+            (true, 0) => "🔌 Neywa is shutting down — the running request here is stopped".to_string(),
+            (true, n) => format!("🔌 Neywa is shutting down — the running request here is stopped and {} queued message(s) will be dropped", n),
+            (false, n) => format!("🔌 Neywa is shutting down — {} queued message(s) here will be dropped", n),
+        };
+        notices.push((ChannelId::new(channel), text));
+    }
 
-            let _ = channel_id.say(&ctx.http, log_msg).await;
+    let mut sends = tokio::task::JoinSet::new();
+    for (channel, text) in notices {
+        let http = http.clone();
+        sends.spawn(async move { channel.say(&http, text).await });
+    }
+    while let Some(result) = sends.join_next().await {
+        if let Ok(Err(e)) = result {
+            tracing::warn!("Failed to post shutdown notice: {}", e);
         }
     }
 }
 
-pub async fn run_bot() -> Result<()> {
+/// Run the bot until it fails or `shutdown` triggers. On shutdown the gateway
+/// connections are closed and running claude/codex processes are stopped.
+pub async fn run_bot(shutdown: Shutdown) -> Result<()> {
     let config = Config::load()?;
 
     let token = config
         .discord_bot_token
+        .clone()
         .context("Discord bot token not configured. Run 'neywa install' first.")?;
 
     tracing::info!("Starting Discord bot...");
 
-    let intents = GatewayIntents::GUILD_MESSAGES
+    let intents = GatewayIntents::GUILDS
+        | GatewayIntents::GUILD_MESSAGES
         | GatewayIntents::DIRECT_MESSAGES
+        | GatewayIntents::GUILD_MESSAGE_REACTIONS
+        | GatewayIntents::DIRECT_MESSAGE_REACTIONS
         | GatewayIntents::MESSAGE_CONTENT;
 
     let mut client = Client::builder(&token, intents)
@@ -1984,184 +9234,646 @@ pub async fn run_bot() -> Result<()> {
         // Load persisted sessions from file
         let sessions = load_sessions();
         data.insert::<SessionStorage>(Arc::new(RwLock::new(sessions)));
-        data.insert::<LogsChannel>(Arc::new(RwLock::new(None)));
+        data.insert::<LogsChannel>(Arc::new(RwLock::new(config.logs_channel_id.map(ChannelId::new))));
+        data.insert::<BotUserId>(Arc::new(RwLock::new(None)));
+        data.insert::<LoopGuardStorage>(Arc::new(RwLock::new(LoopGuard::default())));
+        data.insert::<IntentWatchStorage>(Arc::new(RwLock::new(IntentWatch::default())));
         data.insert::<ChannelBackends>(Arc::new(RwLock::new(load_channel_backends())));
         data.insert::<MessageQueue>(Arc::new(RwLock::new(HashMap::new())));
         data.insert::<ProcessingChannels>(Arc::new(RwLock::new(HashMap::new())));
+        data.insert::<PendingBatches>(Arc::new(RwLock::new(HashMap::new())));
+        data.insert::<ActiveMessages>(Arc::new(RwLock::new(HashMap::new())));
+        data.insert::<LastRequests>(Arc::new(RwLock::new(HashMap::new())));
+        data.insert::<ErrorReports>(Arc::new(RwLock::new(HashMap::new())));
+        data.insert::<ChannelAutoReset>(Arc::new(RwLock::new(load_channel_autoreset())));
+        data.insert::<ChannelDoneStyles>(Arc::new(RwLock::new(load_channel_done_styles())));
+        data.insert::<ChannelEmbeds>(Arc::new(RwLock::new(load_channel_embeds())));
+        data.insert::<ChannelStreaming>(Arc::new(RwLock::new(load_channel_streaming())));
+        data.insert::<ChannelLongFiles>(Arc::new(RwLock::new(load_channel_long_files())));
+        data.insert::<ChannelTimeouts>(Arc::new(RwLock::new(load_channel_timeouts())));
+        data.insert::<TaskSlots>(Arc::new(SlotPool::new(config.max_concurrent_tasks)));
+        data.insert::<HeldRequests>(Arc::new(RwLock::new(load_held_requests())));
+        data.insert::<WokenChannels>(Arc::new(RwLock::new(load_woken_channels())));
+        data.insert::<ThreadChannels>(Arc::new(RwLock::new(load_thread_channels())));
+        data.insert::<SharedChannels>(Arc::new(RwLock::new(load_shared_channels())));
+        data.insert::<MentionChannels>(Arc::new(RwLock::new(load_mention_channels())));
+        data.insert::<ChannelModels>(Arc::new(RwLock::new(load_channel_models())));
+        data.insert::<ChannelWorkdirs>(Arc::new(RwLock::new(load_channel_workdirs())));
+        data.insert::<ChannelLanguages>(Arc::new(RwLock::new(load_channel_languages())));
+        data.insert::<PendingConfirmations>(Arc::new(RwLock::new(Confirmations::default())));
+        data.insert::<ChannelTypes>(Arc::new(RwLock::new(HashMap::new())));
+        data.insert::<ForumNotices>(Arc::new(RwLock::new(HashSet::new())));
+        data.insert::<CompactSuggested>(Arc::new(RwLock::new(HashSet::new())));
+        data.insert::<MetricsStorage>(Arc::new(RwLock::new(RunMetrics::load())));
+        data.insert::<LastRunTimings>(Arc::new(RwLock::new(HashMap::new())));
         data.insert::<HumanModeChannels>(Arc::new(RwLock::new(load_human_mode())));
+        data.insert::<HumanModeHints>(Arc::new(RwLock::new(HashMap::new())));
+        data.insert::<UsageStorage>(Arc::new(RwLock::new(UsageStore::load())));
+        data.insert::<RateLimitStorage>(Arc::new(RwLock::new(RateLimiter::default())));
+        data.insert::<CostStorage>(Arc::new(RwLock::new(CostStore::load())));
+        data.insert::<ScheduleStorage>(Arc::new(RwLock::new(ScheduleStore::load())));
+    }
+
+    // Optional read-only status page
+    if let Some(addr) = status_page::bind_address(&config) {
+        let data = client.data.clone();
+        let snapshot: status_page::SnapshotFn = Arc::new(move || {
+            let data = data.clone();
+            Box::pin(async move { status_snapshot(&data).await })
+        });
+        tokio::spawn(status_page::serve(addr, config.status_token.clone(), snapshot));
     }
 
-    client.start().await.context("Discord client error")?;
+    let shard_manager = client.shard_manager.clone();
+    let data = client.data.clone();
+    let http = client.http.clone();
+    let notice_timeout = SHUTDOWN_NOTICE_TIMEOUT.min(config.shutdown_grace() / 2);
+    tokio::spawn(async move {
+        let reason = shutdown.wait().await;
+        tracing::info!("Shutting down Discord bot ({})...", reason.describe());
+        // Say goodbye before the gateway closes; a slow Discord can't hold up the exit
+        if tokio::time::timeout(notice_timeout, announce_shutdown(&data, http, reason)).await.is_err() {
+            tracing::warn!("Shutdown notices didn't finish within {}ms", notice_timeout.as_millis());
+        }
+        shard_manager.shutdown_all().await;
+    });
+
+    let result = client.start().await;
+    let stopped = children::kill_all();
+    if stopped > 0 {
+        tracing::info!("Stopped {} running AI process(es)", stopped);
+    }
+    if let Err(e) = result {
+        if matches!(
+            e,
+            serenity::Error::Gateway(serenity::gateway::GatewayError::DisallowedGatewayIntents)
+        ) {
+            anyhow::bail!("Discord rejected the gateway intents. {}", discord_api::MESSAGE_CONTENT_HELP);
+        }
+        return Err(e).context("Discord client error");
+    }
 
     Ok(())
 }
 
-/// Fetch remote version from neywa.ai/version.txt
-async fn fetch_remote_version() -> Result<String> {
-    let url = "https://neywa.ai/version.txt";
-    let response = reqwest::get(url).await?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    if !response.status().is_success() {
-        anyhow::bail!("Failed to fetch version: HTTP {}", response.status());
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("neywa-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::canonicalize(dir).unwrap()
     }
 
-    let version = response.text().await?.trim().to_string();
-    Ok(version)
-}
-
-/// Path for storing pending update info
-fn update_pending_path() -> std::path::PathBuf {
-    let config_dir = dirs::config_dir()
-        .unwrap_or_else(|| std::path::PathBuf::from("."))
-        .join("neywa");
-    config_dir.join("update_pending.json")
-}
+    fn queued(message_id: u64, user_id: u64, content: &str) -> QueuedMessage {
+        let mut msg = Message::default();
+        msg.id = MessageId::new(message_id);
+        msg.channel_id = ChannelId::new(1);
+        msg.author.id = serenity::model::id::UserId::new(user_id);
+        msg.content = content.to_string();
+        QueuedMessage {
+            msg,
+            content: content.to_string(),
+            attachments: Vec::new(),
+            channel_type: ChannelType::General,
+            is_plan_mode: false,
+            urgent: false,
+            priority: false,
+            notice: None,
+            enqueued_at: Instant::now(),
+        }
+    }
 
-/// Save pending update info before restart
-fn save_update_pending(channel_id: u64, old_version: &str, new_version: &str) -> Result<()> {
-    let config_dir = dirs::config_dir()
-        .unwrap_or_else(|| std::path::PathBuf::from("."))
-        .join("neywa");
-    std::fs::create_dir_all(&config_dir)?;
+    #[test]
+    fn transfer_path_relative_to_base() {
+        let root = scratch_dir("transfer-base");
+        let workdir = root.join("project");
+        std::fs::create_dir_all(&workdir).unwrap();
+        let roots = vec![root.clone()];
 
-    let info = serde_json::json!({
-        "channel_id": channel_id,
-        "old_version": old_version,
-        "new_version": new_version
-    });
+        let resolved = resolve_transfer_path("notes.txt", &workdir, &roots, &[]).unwrap();
+        assert_eq!(resolved, workdir.join("notes.txt"));
 
-    std::fs::write(update_pending_path(), serde_json::to_string(&info)?)?;
-    Ok(())
-}
+        let nested = resolve_transfer_path("out/log.txt", &workdir, &roots, &[]).unwrap();
+        assert_eq!(nested, workdir.join("out").join("log.txt"));
 
-/// Load and delete pending update info
-fn load_update_pending() -> Option<(u64, String, String)> {
-    let path = update_pending_path();
-    if !path.exists() {
-        return None;
+        std::fs::remove_dir_all(root).unwrap();
     }
 
-    let content = std::fs::read_to_string(&path).ok()?;
-    let _ = std::fs::remove_file(&path); // Delete after reading
+    #[test]
+    fn transfer_path_outside_roots_rejected() {
+        let root = scratch_dir("transfer-roots");
+        let allowed = root.join("allowed");
+        std::fs::create_dir_all(&allowed).unwrap();
+        let roots = vec![allowed.clone()];
 
-    let info: serde_json::Value = serde_json::from_str(&content).ok()?;
-    let channel_id = info["channel_id"].as_u64()?;
-    let old_version = info["old_version"].as_str()?.to_string();
-    let new_version = info["new_version"].as_str()?.to_string();
+        assert!(resolve_transfer_path("../secret.txt", &allowed, &roots, &[]).is_err());
+        assert!(resolve_transfer_path("missing/../../x", &allowed, &roots, &[]).is_err());
+        assert!(resolve_transfer_path(root.join("x").to_str().unwrap(), &allowed, &roots, &[]).is_err());
 
-    Some((channel_id, old_version, new_version))
-}
+        std::fs::remove_dir_all(root).unwrap();
+    }
 
-/// Self-update neywa binary from neywa.ai
-async fn self_update() -> Result<()> {
-    // Detect architecture
-    let arch = if cfg!(target_arch = "aarch64") {
-        "arm64"
-    } else if cfg!(target_arch = "x86_64") {
-        "x86_64"
-    } else {
-        anyhow::bail!("Unsupported architecture");
-    };
+    #[test]
+    fn transfer_path_in_denied_dir_rejected() {
+        let root = scratch_dir("transfer-denied");
+        let state = root.join("neywa");
+        std::fs::create_dir_all(&state).unwrap();
+        std::fs::write(state.join("config.json"), "{}").unwrap();
+        let roots = vec![root.clone()];
+        let denied = vec![state.clone()];
+
+        assert!(resolve_transfer_path("neywa/config.json", &root, &roots, &denied).is_err());
+        assert!(resolve_transfer_path("neywa/new.txt", &root, &roots, &denied).is_err());
+        assert!(resolve_transfer_path("other.txt", &root, &roots, &denied).is_ok());
+
+        // A link pointing into the denied dir resolves to it and is refused too
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&state, root.join("link")).unwrap();
+            assert!(resolve_transfer_path("link/config.json", &root, &roots, &denied).is_err());
+        }
 
-    let download_url = format!("https://neywa.ai/neywa-{}", arch);
-    tracing::info!("Downloading from: {}", download_url);
+        std::fs::remove_dir_all(root).unwrap();
+    }
 
-    // Download new binary
-    let response = reqwest::get(&download_url).await?;
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_detected_without_following() {
+        let root = scratch_dir("transfer-symlink");
+        let real = root.join("real.txt");
+        std::fs::write(&real, "x").unwrap();
+        std::os::unix::fs::symlink(&real, root.join("link.txt")).unwrap();
+        std::os::unix::fs::symlink(root.join("gone.txt"), root.join("dangling.txt")).unwrap();
+
+        assert!(!is_symlink(&real));
+        assert!(is_symlink(&root.join("link.txt")));
+        assert!(is_symlink(&root.join("dangling.txt")));
+        assert!(!is_symlink(&root.join("missing.txt")));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
 
-    if !response.status().is_success() {
-        anyhow::bail!("Failed to download: HTTP {}", response.status());
+    #[test]
+    fn edited_content_follows_message_kind() {
+        let plain = queued(10, 2, "fix the bug");
+        assert_eq!(edited_content(&plain, "  fix the other bug ").as_deref(), Some("fix the other bug"));
+        // Editing a plain request into a command doesn't rewrite the queued request
+        assert_eq!(edited_content(&plain, "!stop"), None);
+        assert_eq!(edited_content(&plain, "   "), None);
+
+        let mut plan = queued(11, 2, "!plan migrate db");
+        plan.is_plan_mode = true;
+        assert_eq!(edited_content(&plan, "!plan migrate the db").as_deref(), Some("migrate the db"));
+        assert_eq!(edited_content(&plan, "migrate the db"), None);
+
+        let mut urgent = queued(12, 2, "!urgent deploy");
+        urgent.urgent = true;
+        assert_eq!(edited_content(&urgent, "!urgent deploy now").as_deref(), Some("deploy now"));
+        assert_eq!(edited_content(&urgent, "deploy now"), None);
     }
 
-    let bytes = response.bytes().await?;
+    #[test]
+    fn replace_queued_content_by_id() {
+        let mut queue: VecDeque<QueuedMessage> = [queued(10, 2, "one"), queued(11, 2, "two")].into_iter().collect();
+        assert!(replace_queued_content(&mut queue, MessageId::new(11), "two, edited"));
+        assert_eq!(queue[1].content, "two, edited");
+        assert_eq!(queue[1].msg.content, "two, edited");
+        assert_eq!(queue[0].content, "one");
 
-    // Find current binary path
-    let current_exe = std::env::current_exe()
-        .context("Failed to get current executable path")?;
+        // Found but not applicable: left as is
+        assert!(replace_queued_content(&mut queue, MessageId::new(10), "!help"));
+        assert_eq!(queue[0].content, "one");
 
-    tracing::info!("Updating binary at: {:?}", current_exe);
+        assert!(!replace_queued_content(&mut queue, MessageId::new(99), "x"));
+    }
 
-    // Write to temp file first
-    let temp_path = current_exe.with_extension("new");
-    std::fs::write(&temp_path, &bytes)
-        .context("Failed to write new binary")?;
+    #[test]
+    fn priority_jumps_the_queue_in_arrival_order() {
+        let mut queue = VecDeque::new();
+        let admin = |id: u64| {
+            let mut m = queued(id, 1, &id.to_string());
+            m.priority = true;
+            m
+        };
+        let (p1, p2) = (admin(20), admin(21));
+        // Urgent only affects the completion ping, not the position
+        let mut urgent = queued(12, 2, "12");
+        urgent.urgent = true;
+
+        assert_eq!(enqueue(&mut queue, queued(10, 2, "10")), 1);
+        assert_eq!(enqueue(&mut queue, queued(11, 3, "11")), 2);
+        assert_eq!(enqueue(&mut queue, p1), 1);
+        assert_eq!(enqueue(&mut queue, urgent), 4);
+        assert_eq!(enqueue(&mut queue, p2), 2);
+        let order: Vec<&str> = queue.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(order, vec!["20", "21", "10", "11", "12"]);
+
+        // Positions reported match the queue after reordering
+        let mut p3 = admin(22);
+        p3.urgent = true;
+        assert_eq!(enqueue(&mut queue, p3), 3);
+        assert_eq!(queue[2].content, "22");
+    }
 
-    // Make executable
-    #[cfg(unix)]
-    {
-        use std::os::unix::fs::PermissionsExt;
-        let mut perms = std::fs::metadata(&temp_path)?.permissions();
-        perms.set_mode(0o755);
-        std::fs::set_permissions(&temp_path, perms)?;
-    }
-
-    // Replace current binary
-    std::fs::rename(&temp_path, &current_exe)
-        .context("Failed to replace binary")?;
-
-    // Also update the .app bundle binary (preserves FDA permissions)
-    // Skip if current_exe IS the app binary (self-copy truncates to 0 bytes!)
-    let app_bundle = std::path::PathBuf::from("/Applications/Neywa.app");
-    let app_binary = app_bundle.join("Contents/MacOS/neywa");
-    if app_binary.exists() {
-        let is_same = std::fs::canonicalize(&current_exe).ok()
-            == std::fs::canonicalize(&app_binary).ok();
-        if !is_same {
-            if let Err(e) = std::fs::copy(&current_exe, &app_binary) {
-                tracing::warn!("Failed to update Neywa.app binary: {}", e);
-            } else {
-                tracing::info!("Updated Neywa.app binary");
-            }
-        } else {
-            tracing::info!("Binary already at app bundle path, skipping copy");
-        }
+    #[test]
+    fn remove_queued_checks_owner_and_position() {
+        let mut queues = HashMap::from([(1, VecDeque::from([queued(10, 2, "a"), queued(11, 3, "b")]))]);
+        assert!(matches!(remove_queued(&mut queues, 1, 0, 2, false), Err(QueueRemoveError::NotFound)));
+        assert!(matches!(remove_queued(&mut queues, 1, 3, 2, false), Err(QueueRemoveError::NotFound)));
+        assert!(matches!(remove_queued(&mut queues, 1, 2, 2, false), Err(QueueRemoveError::NotYours(_))));
+        assert_eq!(remove_queued(&mut queues, 1, 2, 2, true).unwrap().content, "b");
+        assert_eq!(remove_queued(&mut queues, 1, 1, 2, false).unwrap().content, "a");
+        // The emptied queue is dropped
+        assert!(!queues.contains_key(&1));
     }
 
-    // Re-sign the .app bundle after binary replacement.
-    // Without this, macOS may refuse to launch the updated binary due to
-    // code signature mismatch, causing LaunchAgent KeepAlive to crash-loop.
-    #[cfg(target_os = "macos")]
-    {
-        let sign_output = std::process::Command::new("codesign")
-            .args(["--force", "--sign", "-", app_bundle.to_str().unwrap_or("/Applications/Neywa.app")])
-            .output();
-        match sign_output {
-            Ok(out) if out.status.success() => {
-                tracing::info!("Re-signed Neywa.app successfully");
+    #[test]
+    fn remove_queued_renumbers_after_a_drain() {
+        let mut queues = HashMap::from([(1, (10..14).map(|id| queued(id, 2, &id.to_string())).collect::<VecDeque<_>>())]);
+        // The head starts running: what was #3 is now #2
+        assert_eq!(pop_queued(&mut queues, 1).unwrap().content, "10");
+        assert_eq!(remove_queued(&mut queues, 1, 2, 2, false).unwrap().content, "12");
+        let left: Vec<&str> = queues[&1].iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(left, vec!["11", "13"]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn remove_queued_while_draining() {
+        let queues: Arc<RwLock<HashMap<u64, VecDeque<QueuedMessage>>>> = Arc::new(RwLock::new(HashMap::new()));
+        {
+            let mut q = queues.write().await;
+            for id in 0..200 {
+                enqueue(q.entry(1).or_default(), queued(1000 + id, 2, &id.to_string()));
             }
-            Ok(out) => {
-                tracing::warn!("codesign warning: {}", String::from_utf8_lossy(&out.stderr));
+        }
+
+        let drainer = tokio::spawn({
+            let queues = queues.clone();
+            async move {
+                let mut ran = Vec::new();
+                loop {
+                    let next = pop_queued(&mut *queues.write().await, 1);
+                    let Some(next) = next else { break };
+                    ran.push(next.msg.id.get());
+                    tokio::task::yield_now().await;
+                }
+                ran
             }
-            Err(e) => {
-                tracing::warn!("Failed to run codesign: {}", e);
+        });
+        let remover = tokio::spawn({
+            let queues = queues.clone();
+            async move {
+                let mut removed = Vec::new();
+                loop {
+                    let result = remove_queued(&mut *queues.write().await, 1, 2, 2, false);
+                    match result {
+                        Ok(entry) => removed.push(entry.msg.id.get()),
+                        Err(QueueRemoveError::NotFound) if !queues.read().await.contains_key(&1) => break,
+                        Err(QueueRemoveError::NotFound) => {}
+                        Err(e) => panic!("{:?}", e),
+                    }
+                    tokio::task::yield_now().await;
+                }
+                removed
             }
+        });
+
+        let ran = drainer.await.unwrap();
+        let removed = remover.await.unwrap();
+        // Every message either ran or was removed, exactly once, and the
+        // drained channel left no empty queue behind
+        let mut all: Vec<u64> = ran.iter().chain(&removed).copied().collect();
+        all.sort_unstable();
+        assert_eq!(all, (1000..1200).collect::<Vec<_>>());
+        assert!(ran.windows(2).all(|w| w[0] < w[1]), "drain order changed");
+        assert!(queues.read().await.is_empty());
+    }
+
+    fn session(id: &str) -> SessionEntry {
+        SessionEntry { id: id.to_string(), last_used: None }
+    }
+
+    #[test]
+    fn reset_scope_parse() {
+        assert_eq!(ResetScope::parse(""), Some(ResetScope::Mine));
+        assert_eq!(ResetScope::parse("mine"), Some(ResetScope::Mine));
+        assert_eq!(ResetScope::parse("channel"), Some(ResetScope::Channel));
+        assert_eq!(ResetScope::parse("everyone"), None);
+    }
+
+    #[test]
+    fn clear_channel_sessions_only_touches_that_channel() {
+        let mut sessions: HashMap<SessionKey, SessionEntry> = HashMap::new();
+        sessions.insert((1, 100), session("a"));
+        sessions.insert((2, 100), session("b"));
+        sessions.insert((1, 200), session("c"));
+        assert_eq!(clear_channel_sessions(&mut sessions, 100), 2);
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[&(1, 200)].id, "c");
+        assert_eq!(clear_channel_sessions(&mut sessions, 100), 0);
+    }
+
+    #[test]
+    fn context_tokens_from_latest_assistant_usage() {
+        let jsonl = [
+            r#"{"type":"user","message":{"content":"hi"}}"#,
+            r#"{"type":"assistant","message":{"usage":{"input_tokens":10,"output_tokens":5}}}"#,
+            r#"{"type":"assistant","message":{"usage":{"input_tokens":3,"cache_creation_input_tokens":100,"cache_read_input_tokens":2000,"output_tokens":40}}}"#,
+            r#"{"type":"user","message":{"content":"thanks"}}"#,
+        ]
+        .join("\n");
+        assert_eq!(estimate_context_tokens(&jsonl), 2143);
+    }
+
+    #[test]
+    fn context_tokens_fall_back_to_size() {
+        let jsonl = format!("{}\nnot json", r#"{"type":"user","message":{"content":"hello there"}}"#);
+        assert_eq!(estimate_context_tokens(&jsonl), jsonl.len() as u64 / SESSION_BYTES_PER_TOKEN);
+        assert_eq!(estimate_context_tokens(""), 0);
+    }
+
+    #[test]
+    fn exploration_summary() {
+        let mut exploration = Exploration::default();
+        assert_eq!(exploration.summary(), None);
+
+        exploration.record("Grep", "TODO");
+        assert_eq!(exploration.summary().as_deref(), Some("🔎 Explored: 1 search"));
+
+        exploration.record("Read", "/repo/src/main.rs");
+        assert_eq!(exploration.summary().as_deref(), Some("🔎 Explored: 1 file across src/ (1 search)"));
+
+        exploration.record("Read", "/repo/src/lib.rs");
+        exploration.record("Read", "/repo/src/lib.rs");
+        exploration.record("Read", "/repo/tests/cli.rs");
+        exploration.record("Glob", "**/*.rs");
+        assert_eq!(
+            exploration.summary().as_deref(),
+            Some("🔎 Explored: 3 files across src/, tests/ (2 searches)")
+        );
+    }
+
+    #[test]
+    fn exploration_collapses_many_directories() {
+        let mut exploration = Exploration::default();
+        for dir in ["a", "b", "c", "d", "e", "f"] {
+            exploration.record("Read", &format!("/repo/{}/mod.rs", dir));
         }
+        assert_eq!(exploration.directories().len(), 6);
+        assert_eq!(
+            exploration.summary().as_deref(),
+            Some("🔎 Explored: 6 files across a/, b/, c/, d/ +2 more")
+        );
     }
 
-    tracing::info!("Binary updated successfully");
+    fn guild_channel(id: u64, name: &str, kind: serenity::model::channel::ChannelType, parent: Option<u64>) -> serenity::model::channel::GuildChannel {
+        let mut channel = serenity::model::channel::GuildChannel::default();
+        channel.id = ChannelId::new(id);
+        channel.name = name.to_string();
+        channel.kind = kind;
+        channel.parent_id = parent.map(ChannelId::new);
+        channel
+    }
 
-    Ok(())
-}
+    #[test]
+    fn guild_create_memoizes_channels_and_threads() {
+        use serenity::model::channel::ChannelType as Discord;
+        let channels: HashMap<ChannelId, _> = [
+            guild_channel(1, "general", Discord::Text, None),
+            guild_channel(2, "code", Discord::Text, None),
+            guild_channel(3, "ideas", Discord::Forum, None),
+        ]
+        .into_iter()
+        .map(|c| (c.id, c))
+        .collect();
+        let threads = [
+            guild_channel(10, "refactor", Discord::PublicThread, Some(2)),
+            guild_channel(11, "post", Discord::PublicThread, Some(3)),
+        ];
+        let infos: HashMap<u64, ChannelInfo> = guild_channel_infos(&channels, &threads).into_iter().collect();
+        assert_eq!(infos.len(), 5);
+        assert_eq!(infos[&1].kind, ChannelKind::Text);
+        assert_eq!(infos[&3].kind, ChannelKind::Forum);
+        // Threads take their parent's name (and so its mode)
+        assert_eq!(infos[&10].kind, ChannelKind::Thread);
+        assert_eq!(infos[&10].name, "code");
+        assert_eq!(infos[&10].mode, ChannelType::from_name("code"));
+        assert_eq!(infos[&11].kind, ChannelKind::ForumThread);
+        assert_eq!(infos[&11].name, "ideas");
+    }
+
+    #[test]
+    fn channel_kinds_decide_how_messages_are_handled() {
+        use serenity::model::channel::ChannelType as Discord;
+        let cases = [
+            (Discord::Text, None, ChannelKind::Text, MessageRoute::Handle, true),
+            (Discord::News, None, ChannelKind::Announcement, MessageRoute::Handle, true),
+            (Discord::Voice, None, ChannelKind::Voice, MessageRoute::Handle, false),
+            (Discord::Private, None, ChannelKind::Dm, MessageRoute::Handle, false),
+            (Discord::Forum, None, ChannelKind::Forum, MessageRoute::ForumNotice, false),
+            (Discord::PublicThread, Some(Discord::Forum), ChannelKind::ForumThread, MessageRoute::Handle, false),
+            (Discord::PublicThread, Some(Discord::Text), ChannelKind::Thread, MessageRoute::Handle, false),
+            (Discord::NewsThread, Some(Discord::News), ChannelKind::Thread, MessageRoute::Handle, false),
+            (Discord::PrivateThread, None, ChannelKind::Thread, MessageRoute::Handle, false),
+            (Discord::Stage, None, ChannelKind::Unsupported, MessageRoute::Ignore, false),
+            (Discord::Category, None, ChannelKind::Unsupported, MessageRoute::Ignore, false),
+        ];
+        for (discord, parent, kind, route, threads) in cases {
+            let classified = ChannelKind::classify(discord, parent);
+            assert_eq!(classified, kind, "{:?} in {:?}", discord, parent);
+            assert_eq!(classified.route(), route, "{:?}", kind);
+            assert_eq!(classified.can_open_threads(), threads, "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn announcement_and_voice_channels_keep_their_own_mode() {
+        use serenity::model::channel::ChannelType as Discord;
+        let news = ChannelInfo::of(&guild_channel(1, "code", Discord::News, None), None);
+        assert_eq!((news.kind, news.mode), (ChannelKind::Announcement, ChannelType::from_name("code")));
+        // Only threads inherit the parent's name
+        let parent = guild_channel(2, "code", Discord::Category, None);
+        let voice = ChannelInfo::of(&guild_channel(3, "general", Discord::Voice, Some(2)), Some(&parent));
+        assert_eq!((voice.kind, voice.name.as_str()), (ChannelKind::Voice, "general"));
+    }
 
-/// Restart Neywa after update.
-/// Uses _exit(0) to bypass atexit handlers (tray cleanup etc.) that may hang.
-/// LaunchAgent's KeepAlive=true will auto-restart the process within ThrottleInterval.
-/// Note: exec() doesn't work on macOS because replacing the binary invalidates the
-/// code signature, causing SIGKILL from the kernel.
-fn restart_after_update() -> ! {
-    tracing::info!("Exiting for KeepAlive restart...");
+    #[test]
+    fn logs_channel_choice() {
+        // A binding survives while its channel exists, even with a #logs around
+        assert_eq!(choose_logs_channel(Some(5), true, || Some(9)), (Some(5), true));
+        // Bound channel deleted: back to name-based discovery, unbound
+        assert_eq!(choose_logs_channel(None, true, || Some(9)), (Some(9), false));
+        assert_eq!(choose_logs_channel(None, true, || None), (None, false));
+        // Unbound: a channel named like #logs wins, else the remembered one
+        assert_eq!(choose_logs_channel(Some(5), false, || Some(9)), (Some(9), false));
+        assert_eq!(choose_logs_channel(Some(5), false, || None), (Some(5), false));
+        assert_eq!(choose_logs_channel(None, false, || None), (None, false));
+    }
 
-    // Safety net: if _exit somehow doesn't work, force kill after 5 seconds
-    std::thread::spawn(|| {
-        std::thread::sleep(std::time::Duration::from_secs(5));
-        std::process::exit(1);
-    });
+    #[test]
+    fn logs_channel_follows_renames_unless_bound() {
+        let logs = ChannelType::from_name("logs");
+        let general = ChannelType::from_name("general");
+        // #logs renamed to something else
+        assert!(rename_needs_logs_rediscovery(Some(5), false, 5, &general));
+        // Another channel renamed; or still named like logs
+        assert!(!rename_needs_logs_rediscovery(Some(5), false, 6, &general));
+        assert!(!rename_needs_logs_rediscovery(Some(5), false, 5, &logs));
+        // No logs channel yet, and one gets named like it
+        assert!(rename_needs_logs_rediscovery(None, false, 6, &logs));
+        assert!(!rename_needs_logs_rediscovery(None, false, 6, &general));
+        // A binding ignores names
+        assert!(!rename_needs_logs_rediscovery(Some(5), true, 5, &general));
+        assert!(!rename_needs_logs_rediscovery(None, true, 6, &logs));
+    }
+
+    #[test]
+    fn fences_open_and_close_by_backtick_run() {
+        assert_eq!(fence_after(None, "```rust"), Some("```rust".to_string()));
+        assert_eq!(fence_after(None, "  ```"), Some("```".to_string()));
+        // Inline code and prose aren't fences
+        assert_eq!(fence_after(None, "``` inline ```"), None);
+        assert_eq!(fence_after(None, "`x`"), None);
+        // Inside a block, only a bare run at least as long closes it
+        assert_eq!(fence_after(Some("```rust".to_string()), "```"), None);
+        assert_eq!(fence_after(Some("```rust".to_string()), "```python"), Some("```rust".to_string()));
+        assert_eq!(fence_after(Some("````md".to_string()), "```"), Some("````md".to_string()));
+        assert_eq!(fence_after(Some("````md".to_string()), "````"), None);
+    }
+
+    #[test]
+    fn long_lines_break_at_spaces_and_char_boundaries() {
+        assert_eq!(break_line("aaaa bbbb cccc", 10), vec!["aaaa bbbb", "cccc"]);
+        // No space past the halfway point: a hard break
+        assert_eq!(break_line("abcdefghijkl", 5), vec!["abcde", "fghij", "kl"]);
+        // Never inside a multibyte character
+        let pieces = break_line("ééééé", 3);
+        assert!(pieces.iter().all(|piece| piece.len() <= 3));
+        assert_eq!(pieces.concat(), "ééééé");
+    }
+
+    #[test]
+    fn split_reopens_nested_and_repeated_fences() {
+        // A four-backtick block holding a three-backtick one is one block
+        let text = "intro\n````md\n```rust\nlet a = 1;\n```\nmore text here\n````\nend";
+        let chunks = split_text(text, 30);
+        for chunk in &chunks {
+            let fences = chunk.lines().filter(|l| l.starts_with("````")).count();
+            assert_eq!(fences % 2, 0, "{:?}", chunks);
+        }
+        assert!(chunks[1..chunks.len() - 1].iter().all(|chunk| chunk.starts_with("````md")), "{:?}", chunks);
+
+        // Two blocks, each reopened with its own language
+        let text = "```rust\nfn a() {}\nfn b() {}\n```\n```py\nx = 1\ny = 2\n```";
+        let chunks = split_text(text, 24);
+        assert_eq!(
+            chunks,
+            vec!["```rust\nfn a() {}\n```", "```rust\nfn b() {}\n```", "```py\nx = 1\ny = 2\n```"]
+        );
+    }
+
+    #[test]
+    fn split_breaks_a_code_line_longer_than_a_chunk() {
+        let line = "x".repeat(100);
+        let text = format!("```\n{}\n```", line);
+        let chunks = split_text(&text, 40);
+        for chunk in &chunks {
+            assert!(chunk.starts_with("```\n") && chunk.ends_with("\n```"), "{:?}", chunks);
+            assert!(chunk.len() <= 40 + 4, "{:?}", chunks);
+        }
+        let body: String = chunks.iter().map(|c| c.trim_start_matches("```\n").trim_end_matches("\n```").replace('\n', "")).collect();
+        assert_eq!(body, line);
+    }
+
+    #[test]
+    fn stop_and_retry_share_one_rule() {
+        let mut config = Config::default();
+        // No admins configured: everyone is one, for every command
+        assert!(may_act_for(1, 2, user_tier(&config, 2)));
+        assert!(is_admin_tier(&config, 2));
+        config.admin_user_ids = vec![9];
+        assert!(may_act_for(1, 1, user_tier(&config, 1)));
+        assert!(!may_act_for(1, 2, user_tier(&config, 2)));
+        assert!(may_act_for(1, 9, user_tier(&config, 9)));
+        assert!(!is_admin_tier(&config, 2));
+    }
+
+    #[test]
+    fn quotas_apply_when_no_admins_are_configured() {
+        let mut config = Config::default();
+        // Everyone may run admin commands, but nobody skips the limits
+        assert_eq!(user_tier(&config, 7), Some(Tier::Admin));
+        assert!(!quota_exempt(&config, 7));
+        config.admin_user_ids = vec![7];
+        assert!(quota_exempt(&config, 7));
+        assert!(!quota_exempt(&config, 8));
+    }
+
+    #[test]
+    fn reconciliation_prunes_only_deleted_channels_from_session_files() {
+        let dir = scratch_dir("prune-sessions");
+        let path = dir.join("sessions.json");
+
+        // No sessions file yet: nothing to prune
+        let mut sessions = load_sessions_from(&path);
+        assert!(sessions.is_empty());
+        assert_eq!(prune_map(&mut sessions, &HashSet::from([1]), |(_, channel)| *channel, false), 0);
+
+        // Live sessions in channels 10 and 20; stale ones in 30 (deleted) and
+        // 40 (a thread, missing from the guild listing but still there)
+        sessions.insert((1, 10), session("live-a"));
+        sessions.insert((2, 20), session("live-b"));
+        sessions.insert((1, 30), session("stale-a"));
+        sessions.insert((2, 30), session("stale-b"));
+        sessions.insert((1, 40), session("thread"));
+        save_sessions_to(&path, &sessions);
+
+        let mut sessions = load_sessions_from(&path);
+        let tracked: HashSet<u64> = sessions.keys().map(|(_, channel)| *channel).collect();
+        let listed = HashSet::from([10, 20, 50]);
+        assert_eq!(unlisted_channels(&tracked, &listed), vec![30, 40]);
+
+        // Only 30 fails the API check
+        let deleted = HashSet::from([30]);
+        assert_eq!(prune_map(&mut sessions, &deleted, |(_, channel)| *channel, true), 2);
+        assert_eq!(sessions.len(), 5, "dry run changed the map");
+        assert_eq!(prune_map(&mut sessions, &deleted, |(_, channel)| *channel, false), 2);
+        save_sessions_to(&path, &sessions);
+
+        let mut ids: Vec<String> = load_sessions_from(&path).into_values().map(|e| e.id).collect();
+        ids.sort();
+        assert_eq!(ids, vec!["live-a", "live-b", "thread"]);
+        let _ = std::fs::remove_dir_all(dir);
+    }
 
-    // Use _exit(0) to terminate immediately without running atexit handlers.
-    // This avoids potential hangs from tray/NSApplication cleanup.
-    // KeepAlive=true in LaunchAgent will restart us automatically.
-    extern "C" {
-        fn _exit(status: i32) -> !;
+    #[test]
+    fn migrate_session_moves_copies_and_guards_the_target() {
+        let mut sessions = HashMap::from([((1, 10), session("a")), ((1, 20), session("b"))]);
+
+        // Move: the source is gone and the target holds the session, freshly used
+        assert_eq!(migrate_session(&mut sessions, (1, 10), (1, 30), false, false), Ok(()));
+        assert!(!sessions.contains_key(&(1, 10)));
+        assert_eq!(sessions[&(1, 30)].id, "a");
+        assert!(sessions[&(1, 30)].last_used.is_some());
+
+        // Copy keeps the source
+        assert_eq!(migrate_session(&mut sessions, (1, 30), (2, 40), true, false), Ok(()));
+        assert_eq!(sessions[&(1, 30)].id, "a");
+        assert_eq!(sessions[&(2, 40)].id, "a");
+
+        // Missing source, and the same channel on both sides
+        assert_eq!(migrate_session(&mut sessions, (1, 99), (1, 50), false, false), Err(HandoffError::NoSession));
+        assert_eq!(migrate_session(&mut sessions, (1, 20), (2, 20), false, false), Err(HandoffError::SameChannel));
+
+        // Existing destination: refused and left alone unless overwriting
+        assert_eq!(migrate_session(&mut sessions, (1, 20), (1, 30), false, false), Err(HandoffError::TargetOccupied));
+        assert_eq!(sessions[&(1, 20)].id, "b");
+        assert_eq!(sessions[&(1, 30)].id, "a");
+        assert_eq!(migrate_session(&mut sessions, (1, 20), (1, 30), false, true), Ok(()));
+        assert_eq!(sessions[&(1, 30)].id, "b");
+        assert!(!sessions.contains_key(&(1, 20)));
     }
-    unsafe { _exit(0) }
 }
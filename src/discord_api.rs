@@ -1,6 +1,9 @@
 use crate::config::Config;
 use anyhow::{Context, Result};
-use serde::Deserialize;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::io::IsTerminal;
+use std::path::PathBuf;
 
 const DISCORD_API_BASE: &str = "https://discord.com/api/v10";
 
@@ -35,6 +38,28 @@ impl Channel {
     }
 }
 
+/// Application flags meaning the Message Content intent is enabled (full / limited)
+const GATEWAY_MESSAGE_CONTENT: u64 = 1 << 18;
+const GATEWAY_MESSAGE_CONTENT_LIMITED: u64 = 1 << 19;
+
+/// Where to enable the Message Content intent
+pub const MESSAGE_CONTENT_HELP: &str = "Enable **Message Content Intent** under Bot → Privileged Gateway Intents in the Discord developer portal (https://discord.com/developers/applications), then restart Neywa.";
+
+#[derive(Debug, Deserialize)]
+pub struct Application {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub flags: u64,
+}
+
+impl Application {
+    /// Whether the privileged Message Content intent is turned on
+    pub fn has_message_content_intent(&self) -> bool {
+        self.flags & (GATEWAY_MESSAGE_CONTENT | GATEWAY_MESSAGE_CONTENT_LIMITED) != 0
+    }
+}
+
 fn build_client(token: &str) -> reqwest::Client {
     let mut headers = reqwest::header::HeaderMap::new();
     headers.insert(
@@ -58,6 +83,26 @@ fn load_token_and_guild() -> Result<(String, u64)> {
     Ok((token, guild_id))
 }
 
+/// Fetch the bot's application (flags tell which privileged intents are enabled)
+pub async fn fetch_application() -> Result<Application> {
+    let config = Config::load()?;
+    let token = config
+        .discord_bot_token
+        .context("Discord bot token not configured. Run 'neywa install' first.")?;
+    let client = build_client(&token);
+
+    let url = format!("{}/applications/@me", DISCORD_API_BASE);
+    let response = client.get(&url).send().await?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        anyhow::bail!("Discord API error ({}): {}", status, body);
+    }
+
+    Ok(response.json().await?)
+}
+
 /// List all channels in the guild
 pub async fn list_channels() -> Result<()> {
     let (token, guild_id) = load_token_and_guild()?;
@@ -73,7 +118,7 @@ pub async fn list_channels() -> Result<()> {
     }
 
     let mut channels: Vec<Channel> = response.json().await?;
-    channels.sort_by(|a, b| a.position.cmp(&b.position));
+    channels.sort_by_key(|c| c.position);
 
     // Group by category
     let categories: Vec<&Channel> = channels
@@ -119,8 +164,9 @@ pub async fn list_channels() -> Result<()> {
     Ok(())
 }
 
-/// Send a message to a channel (by name or ID)
-pub async fn send_message(channel: &str, message: &str) -> Result<()> {
+/// Send a message to a channel by name or ID. `silent` suppresses push and
+/// desktop notifications for it, as `@silent` does.
+pub async fn send_message(channel: &str, message: &str, silent: bool) -> Result<()> {
     let (token, guild_id) = load_token_and_guild()?;
     let client = build_client(&token);
 
@@ -133,8 +179,26 @@ pub async fn send_message(channel: &str, message: &str) -> Result<()> {
         resolve_channel_by_name(&client, guild_id, name).await?
     };
 
+    post(&client, &channel_id, message, silent).await?;
+    println!("Message sent to channel {}", channel_id);
+    Ok(())
+}
+
+/// Send a message to a channel by ID without printing anything
+pub async fn post_message(channel_id: u64, message: &str) -> Result<()> {
+    let (token, _guild_id) = load_token_and_guild()?;
+    post(&build_client(&token), &channel_id.to_string(), message, false).await
+}
+
+/// Message flag that suppresses push and desktop notifications
+const SUPPRESS_NOTIFICATIONS: u64 = 1 << 12;
+
+async fn post(client: &reqwest::Client, channel_id: &str, message: &str, silent: bool) -> Result<()> {
     let url = format!("{}/channels/{}/messages", DISCORD_API_BASE, channel_id);
-    let body = serde_json::json!({ "content": message });
+    let mut body = serde_json::json!({ "content": message });
+    if silent {
+        body["flags"] = SUPPRESS_NOTIFICATIONS.into();
+    }
 
     let response = client.post(&url).json(&body).send().await?;
 
@@ -143,11 +207,23 @@ pub async fn send_message(channel: &str, message: &str) -> Result<()> {
         let body = response.text().await.unwrap_or_default();
         anyhow::bail!("Failed to send message ({}): {}", status, body);
     }
-
-    println!("Message sent to channel {}", channel_id);
     Ok(())
 }
 
+/// Resolve a channel given by name (with or without #) or ID to its ID and name
+pub async fn resolve_channel(channel: &str) -> Result<(u64, String)> {
+    let names = fetch_channel_names().await?;
+    let wanted = channel.strip_prefix('#').unwrap_or(channel);
+    if let Ok(id) = wanted.parse::<u64>() {
+        let name = names.get(&id).with_context(|| format!("Channel {} not found", id))?;
+        return Ok((id, name.clone()));
+    }
+    names
+        .into_iter()
+        .find(|(_, name)| name.to_lowercase() == wanted.to_lowercase())
+        .with_context(|| format!("Channel '{}' not found", wanted))
+}
+
 /// Show guild info
 pub async fn show_guild() -> Result<()> {
     let (token, guild_id) = load_token_and_guild()?;
@@ -187,7 +263,7 @@ pub async fn create_channel(
     channel_type: &str,
     category: Option<&str>,
     topic: Option<&str>,
-) -> Result<()> {
+) -> Result<Channel> {
     let (token, guild_id) = load_token_and_guild()?;
     let client = build_client(&token);
 
@@ -248,11 +324,247 @@ pub async fn create_channel(
         created.id,
         created.type_name()
     );
+    Ok(created)
+}
+
+/// Env var holding the Discord channel a Claude/Codex run was started from
+pub const ORIGIN_CHANNEL_ENV: &str = "NEYWA_CHANNEL_ID";
+
+/// How long a pending destructive action can be approved
+const PENDING_ACTION_TTL_MINS: i64 = 10;
+
+/// Button custom_id prefix for pending action approvals
+pub const PENDING_ACTION_PREFIX: &str = "neywa_action";
+
+/// Characters in a confirmation code
+const CONFIRM_CODE_LEN: usize = 8;
+
+/// A destructive operation waiting for approval in Discord
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PendingOp {
+    DeleteChannel { channel_id: String, label: String },
+    PurgeMessages { channel_id: String, label: String, limit: u32 },
+}
+
+impl PendingOp {
+    fn describe(&self) -> String {
+        match self {
+            PendingOp::DeleteChannel { label, .. } => format!("delete channel **#{}**", label),
+            PendingOp::PurgeMessages { label, limit, .. } => {
+                format!("delete the last {} messages in **#{}**", limit, label)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingAction {
+    pub id: String,
+    pub op: PendingOp,
+    pub created_at: DateTime<Local>,
+    /// SHA-256 of the confirmation code shown in Discord. Only the hash is
+    /// stored, so the code can't be read back from this file.
+    #[serde(default)]
+    pub code_hash: String,
+}
+
+impl PendingAction {
+    /// A new pending action with a random ID, plus the confirmation code for `--confirm`
+    fn new(op: PendingOp, now: DateTime<Local>) -> (Self, String) {
+        let code = random_code();
+        let action = PendingAction {
+            id: format!("{:016x}", rand::random::<u64>()),
+            op,
+            created_at: now,
+            code_hash: crate::audit::sha256_hex(&code),
+        };
+        (action, code)
+    }
+
+    fn is_expired(&self, now: DateTime<Local>) -> bool {
+        now - self.created_at > chrono::Duration::minutes(PENDING_ACTION_TTL_MINS)
+    }
+}
+
+/// Random confirmation code (uppercase letters and digits)
+fn random_code() -> String {
+    use rand::Rng;
+    const CHARS: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+    let mut rng = rand::thread_rng();
+    (0..CONFIRM_CODE_LEN)
+        .map(|_| CHARS[rng.gen_range(0..CHARS.len())] as char)
+        .collect()
+}
+
+/// Path for storing pending actions (shared between the CLI and the bot)
+fn pending_actions_file_path() -> PathBuf {
+    let config_dir = dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("neywa");
+    config_dir.join("pending_actions.json")
+}
+
+fn load_pending_actions() -> Vec<PendingAction> {
+    std::fs::read_to_string(pending_actions_file_path())
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_pending_actions(actions: &[PendingAction]) -> Result<()> {
+    let path = pending_actions_file_path();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, serde_json::to_string_pretty(actions)?)?;
+    Ok(())
+}
+
+/// Remove and return the first unexpired action matching `pred`, dropping expired ones
+fn take_matching(
+    actions: &mut Vec<PendingAction>,
+    now: DateTime<Local>,
+    pred: impl Fn(&PendingAction) -> bool,
+) -> Option<PendingAction> {
+    actions.retain(|a| !a.is_expired(now));
+    let index = actions.iter().position(pred)?;
+    Some(actions.remove(index))
+}
+
+/// Take the pending action for `op` whose confirmation code is `code`
+fn take_confirmed(
+    actions: &mut Vec<PendingAction>,
+    op: &PendingOp,
+    code: &str,
+    now: DateTime<Local>,
+) -> Option<PendingAction> {
+    let hash = crate::audit::sha256_hex(code.trim().to_uppercase().as_str());
+    take_matching(actions, now, |a| &a.op == op && a.code_hash == hash)
+}
+
+/// Remove and return a pending action (None if unknown or expired).
+/// Expired actions are dropped along the way.
+pub fn take_pending_action(id: &str) -> Option<PendingAction> {
+    let mut actions = load_pending_actions();
+    let taken = take_matching(&mut actions, Local::now(), |a| a.id == id);
+    if let Err(e) = save_pending_actions(&actions) {
+        tracing::warn!("Failed to save pending actions: {}", e);
+    }
+    taken
+}
+
+/// Carry out an approved action
+pub async fn execute_pending(action: &PendingAction) -> Result<String> {
+    match &action.op {
+        PendingOp::DeleteChannel { channel_id, label } => {
+            let (token, _) = load_token_and_guild()?;
+            delete_channel_by_id(&build_client(&token), channel_id).await?;
+            Ok(format!("Channel #{} deleted", label))
+        }
+        PendingOp::PurgeMessages { channel_id, label, limit } => {
+            let (token, _) = load_token_and_guild()?;
+            let deleted = purge_messages_by_id(&build_client(&token), channel_id, *limit).await?;
+            Ok(format!("Deleted {} messages in #{}", deleted, label))
+        }
+    }
+}
+
+/// Gate a destructive operation. Runs it directly with `--force`, when
+/// confirmations are off, with `--confirm <code>` matching a pending action
+/// for the same operation, or after a yes on an interactive terminal. Without a
+/// terminal (Claude's Bash tool) it posts Approve/Reject buttons and a
+/// confirmation code to the originating channel and returns without executing.
+async fn confirm_or_defer(op: PendingOp, force: bool, confirm: Option<&str>) -> Result<bool> {
+    let config = Config::load()?;
+    if force || !config.confirm_destructive_discord_ops {
+        return Ok(true);
+    }
+
+    let now = Local::now();
+    if let Some(code) = confirm {
+        let mut actions = load_pending_actions();
+        let taken = take_confirmed(&mut actions, &op, code, now);
+        save_pending_actions(&actions)?;
+        return match taken {
+            Some(_) => Ok(true),
+            None => anyhow::bail!(
+                "Confirmation code doesn't match a pending request to {} (it may have expired)",
+                op.describe().replace("**", "")
+            ),
+        };
+    }
+
+    if std::io::stdin().is_terminal() {
+        print!("About to {}. Continue? [y/N]: ", op.describe().replace("**", ""));
+        std::io::Write::flush(&mut std::io::stdout())?;
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        if answer.trim().eq_ignore_ascii_case("y") {
+            return Ok(true);
+        }
+        println!("Cancelled.");
+        return Ok(false);
+    }
+
+    let origin = std::env::var(ORIGIN_CHANNEL_ENV)
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .context("Refusing a destructive action without --force: no terminal to confirm on and no originating Discord channel")?;
+
+    let (action, code) = PendingAction::new(op, now);
+    let mut actions = load_pending_actions();
+    actions.retain(|a| !a.is_expired(now));
+    actions.push(action.clone());
+    save_pending_actions(&actions)?;
+
+    let token = config
+        .discord_bot_token
+        .context("Discord bot token not configured. Run 'neywa install' first.")?;
+    let client = build_client(&token);
+    let url = format!("{}/channels/{}/messages", DISCORD_API_BASE, origin);
+    let body = serde_json::json!({
+        "content": format!(
+            "⚠️ Neywa was asked to {}. An allowed user must approve within {} minutes \
+             (or tell Neywa the confirmation code `{}`).",
+            action.op.describe(), PENDING_ACTION_TTL_MINS, code
+        ),
+        "components": [{
+            "type": 1,
+            "components": [
+                { "type": 2, "style": 4, "label": "Approve", "custom_id": format!("{}:approve:{}", PENDING_ACTION_PREFIX, action.id) },
+                { "type": 2, "style": 2, "label": "Reject", "custom_id": format!("{}:reject:{}", PENDING_ACTION_PREFIX, action.id) },
+            ],
+        }],
+    });
+    let response = client.post(&url).json(&body).send().await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        anyhow::bail!("Failed to post confirmation prompt ({}): {}", status, body);
+    }
+
+    println!(
+        "Pending action {}: waiting for approval in Discord (expires in {} minutes). Not executed yet.",
+        action.id, PENDING_ACTION_TTL_MINS
+    );
+    Ok(false)
+}
+
+async fn delete_channel_by_id(client: &reqwest::Client, channel_id: &str) -> Result<()> {
+    let url = format!("{}/channels/{}", DISCORD_API_BASE, channel_id);
+    let response = client.delete(&url).send().await?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        anyhow::bail!("Failed to delete channel ({}): {}", status, body);
+    }
     Ok(())
 }
 
-/// Delete a channel from the guild
-pub async fn delete_channel(channel: &str) -> Result<()> {
+/// Delete a channel from the guild (requires confirmation unless `force`)
+pub async fn delete_channel(channel: &str, force: bool, confirm: Option<&str>) -> Result<()> {
     let (token, guild_id) = load_token_and_guild()?;
     let client = build_client(&token);
 
@@ -264,25 +576,76 @@ pub async fn delete_channel(channel: &str) -> Result<()> {
         resolve_channel_by_name(&client, guild_id, name).await?
     };
 
-    let url = format!("{}/channels/{}", DISCORD_API_BASE, channel_id);
-    let response = client.delete(&url).send().await?;
+    let op = PendingOp::DeleteChannel {
+        channel_id: channel_id.clone(),
+        label: channel.trim_start_matches('#').to_string(),
+    };
+    if !confirm_or_defer(op, force, confirm).await? {
+        return Ok(());
+    }
+
+    delete_channel_by_id(&client, &channel_id).await?;
+    println!("Channel deleted: {}", channel);
+    Ok(())
+}
+
+/// Messages younger than this can be bulk-deleted (a Discord limit)
+const BULK_DELETE_MAX_AGE_DAYS: i64 = 14;
 
+/// Delete up to `limit` of the most recent messages in a channel, returning how many were deleted
+async fn purge_messages_by_id(client: &reqwest::Client, channel_id: &str, limit: u32) -> Result<usize> {
+    let url = format!("{}/channels/{}/messages?limit={}", DISCORD_API_BASE, channel_id, limit.clamp(1, 100));
+    let response = client.get(&url).send().await?;
     if !response.status().is_success() {
         let status = response.status();
         let body = response.text().await.unwrap_or_default();
-        anyhow::bail!("Failed to delete channel ({}): {}", status, body);
+        anyhow::bail!("Failed to fetch messages ({}): {}", status, body);
+    }
+    let messages: Vec<serde_json::Value> = response.json().await?;
+
+    let cutoff = chrono::Utc::now() - chrono::Duration::days(BULK_DELETE_MAX_AGE_DAYS);
+    let (recent, old): (Vec<&serde_json::Value>, Vec<&serde_json::Value>) = messages.iter().partition(|m| {
+        m["timestamp"]
+            .as_str()
+            .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+            .is_some_and(|t| t > cutoff)
+    });
+    let recent_ids: Vec<&str> = recent.iter().filter_map(|m| m["id"].as_str()).collect();
+    let mut single_ids: Vec<&str> = old.iter().filter_map(|m| m["id"].as_str()).collect();
+
+    let mut deleted = 0;
+    if recent_ids.len() >= 2 {
+        let url = format!("{}/channels/{}/messages/bulk-delete", DISCORD_API_BASE, channel_id);
+        let response = client.post(&url).json(&serde_json::json!({ "messages": recent_ids })).send().await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            anyhow::bail!("Failed to delete messages ({}): {}", status, body);
+        }
+        deleted += recent_ids.len();
+    } else {
+        single_ids.extend(recent_ids);
     }
 
-    println!("Channel deleted: {}", channel);
-    Ok(())
+    // Older messages have to go one at a time
+    for id in single_ids {
+        let url = format!("{}/channels/{}/messages/{}", DISCORD_API_BASE, channel_id, id);
+        let response = client.delete(&url).send().await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            anyhow::bail!("Failed to delete message {} after deleting {} ({}): {}", id, deleted, status, body);
+        }
+        deleted += 1;
+    }
+    Ok(deleted)
 }
 
-/// Move a channel to a different category
-pub async fn move_channel(channel: &str, category: &str) -> Result<()> {
+/// Delete the most recent messages in a channel (requires confirmation unless `force`)
+pub async fn purge_channel(channel: &str, limit: u32, force: bool, confirm: Option<&str>) -> Result<()> {
     let (token, guild_id) = load_token_and_guild()?;
     let client = build_client(&token);
 
-    // Resolve channel
     let channel_id = if channel.parse::<u64>().is_ok() {
         channel.to_string()
     } else {
@@ -290,24 +653,91 @@ pub async fn move_channel(channel: &str, category: &str) -> Result<()> {
         resolve_channel_by_name(&client, guild_id, name).await?
     };
 
-    // Resolve category
-    let category_id = if category.parse::<u64>().is_ok() {
-        category.to_string()
-    } else {
-        let url = format!("{}/guilds/{}/channels", DISCORD_API_BASE, guild_id);
-        let response = client.get(&url).send().await?;
-        let channels: Vec<Channel> = response.json().await?;
-        let lower_cat = category.to_lowercase();
+    let op = PendingOp::PurgeMessages {
+        channel_id: channel_id.clone(),
+        label: channel.trim_start_matches('#').to_string(),
+        limit,
+    };
+    if !confirm_or_defer(op, force, confirm).await? {
+        return Ok(());
+    }
+
+    let deleted = purge_messages_by_id(&client, &channel_id, limit).await?;
+    println!("Deleted {} messages in {}", deleted, channel);
+    Ok(())
+}
+
+/// Where `discord move` puts a channel
+#[derive(Debug, PartialEq)]
+enum MoveTarget {
+    Category(String),
+    /// A category to create first (`--create-category`), by name
+    NewCategory(String),
+}
+
+/// Resolve both sides of a move against the guild's channels. The channel is
+/// resolved first, so a typo there fails before any category is created.
+fn plan_move(channels: &[Channel], channel: &str, category: &str, create_category: bool) -> Result<(String, MoveTarget)> {
+    let named = |name: &str, kind: Option<u8>| {
+        let lower = name.to_lowercase();
         channels
             .iter()
-            .find(|c| c.channel_type == 4 && c.name.as_ref().map(|n| n.to_lowercase() == lower_cat).unwrap_or(false))
+            .find(|c| kind.is_none_or(|k| c.channel_type == k) && c.name.as_ref().is_some_and(|n| n.to_lowercase() == lower))
             .map(|c| c.id.clone())
-            .context(format!("Category '{}' not found", category))?
+    };
+    let channel_id = if channel.parse::<u64>().is_ok() {
+        channel.to_string()
+    } else {
+        let name = channel.strip_prefix('#').unwrap_or(channel);
+        named(name, None).context(format!("Channel '{}' not found", name))?
+    };
+    let target = if category.parse::<u64>().is_ok() {
+        MoveTarget::Category(category.to_string())
+    } else {
+        match named(category, Some(4)) {
+            Some(id) => MoveTarget::Category(id),
+            None if create_category => MoveTarget::NewCategory(category.to_string()),
+            None => anyhow::bail!("Category '{}' not found (use --create-category to create it)", category),
+        }
+    };
+    Ok((channel_id, target))
+}
+
+/// PATCH body that moves a channel under `category_id`
+fn move_body(category_id: &str, position: Option<u32>) -> serde_json::Value {
+    let mut body = serde_json::json!({ "parent_id": category_id });
+    if let Some(position) = position {
+        body["position"] = serde_json::json!(position);
+    }
+    body
+}
+
+/// Move a channel to a different category
+pub async fn move_channel(
+    channel: &str,
+    category: &str,
+    create_category: bool,
+    position: Option<u32>,
+) -> Result<()> {
+    let (token, guild_id) = load_token_and_guild()?;
+    let client = build_client(&token);
+
+    let url = format!("{}/guilds/{}/channels", DISCORD_API_BASE, guild_id);
+    let response = client.get(&url).send().await?;
+    if !response.status().is_success() {
+        anyhow::bail!("Failed to fetch channels");
+    }
+    let channels: Vec<Channel> = response.json().await?;
+
+    // Resolve both sides, creating the category if asked to
+    let (channel_id, target) = plan_move(&channels, channel, category, create_category)?;
+    let category_id = match target {
+        MoveTarget::Category(id) => id,
+        MoveTarget::NewCategory(name) => create_channel(&name, "category", None, None).await?.id,
     };
 
     let url = format!("{}/channels/{}", DISCORD_API_BASE, channel_id);
-    let body = serde_json::json!({ "parent_id": category_id });
-    let response = client.patch(&url).json(&body).send().await?;
+    let response = client.patch(&url).json(&move_body(&category_id, position)).send().await?;
 
     if !response.status().is_success() {
         let status = response.status();
@@ -315,7 +745,11 @@ pub async fn move_channel(channel: &str, category: &str) -> Result<()> {
         anyhow::bail!("Failed to move channel ({}): {}", status, body);
     }
 
-    println!("Channel '{}' moved to category '{}'", channel, category);
+    let position = position.map(|p| format!(" at position {}", p)).unwrap_or_default();
+    println!(
+        "Channel '{}' (ID: {}) moved to category '{}' (ID: {}){}",
+        channel, channel_id, category, category_id, position
+    );
     Ok(())
 }
 
@@ -337,6 +771,27 @@ pub async fn rename_channel(channel_id: &str, new_name: &str) -> Result<()> {
     Ok(())
 }
 
+/// Fetch a map of channel ID -> name for the configured guild
+pub async fn fetch_channel_names() -> Result<std::collections::HashMap<u64, String>> {
+    let (token, guild_id) = load_token_and_guild()?;
+    let client = build_client(&token);
+
+    let url = format!("{}/guilds/{}/channels", DISCORD_API_BASE, guild_id);
+    let response = client.get(&url).send().await?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        anyhow::bail!("Discord API error ({}): {}", status, body);
+    }
+
+    let channels: Vec<Channel> = response.json().await?;
+    Ok(channels
+        .into_iter()
+        .filter_map(|c| Some((c.id.parse().ok()?, c.name?)))
+        .collect())
+}
+
 /// Resolve channel name to ID
 async fn resolve_channel_by_name(
     client: &reqwest::Client,
@@ -364,3 +819,110 @@ async fn resolve_channel_by_name(
         .map(|c| c.id.clone())
         .context(format!("Channel '{}' not found", name))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delete_op(channel_id: &str) -> PendingOp {
+        PendingOp::DeleteChannel { channel_id: channel_id.to_string(), label: "old".to_string() }
+    }
+
+    #[test]
+    fn new_actions_get_random_ids_and_codes() {
+        let now = Local::now();
+        let (a, code_a) = PendingAction::new(delete_op("1"), now);
+        let (b, code_b) = PendingAction::new(delete_op("1"), now);
+        assert_ne!(a.id, b.id);
+        assert_ne!(code_a, code_b);
+        assert_eq!(code_a.len(), CONFIRM_CODE_LEN);
+        // Only the hash is kept
+        assert_ne!(a.code_hash, code_a);
+        assert_eq!(a.code_hash, crate::audit::sha256_hex(&code_a));
+    }
+
+    #[test]
+    fn take_by_id_drops_expired() {
+        let now = Local::now();
+        let (fresh, _) = PendingAction::new(delete_op("1"), now);
+        let (mut stale, _) = PendingAction::new(delete_op("2"), now);
+        stale.created_at = now - chrono::Duration::minutes(PENDING_ACTION_TTL_MINS + 1);
+        let stale_id = stale.id.clone();
+        let mut actions = vec![stale, fresh.clone()];
+
+        assert!(take_matching(&mut actions, now, |a| a.id == stale_id).is_none());
+        assert_eq!(actions.len(), 1);
+        let taken = take_matching(&mut actions, now, |a| a.id == fresh.id).unwrap();
+        assert_eq!(taken.op, delete_op("1"));
+        assert!(actions.is_empty());
+        // Already handled
+        assert!(take_matching(&mut actions, now, |a| a.id == fresh.id).is_none());
+    }
+
+    #[test]
+    fn confirm_code_must_match_the_same_op() {
+        let now = Local::now();
+        let (action, code) = PendingAction::new(delete_op("1"), now);
+        let mut actions = vec![action];
+
+        assert!(take_confirmed(&mut actions, &delete_op("1"), "WRONG123", now).is_none());
+        assert!(take_confirmed(&mut actions, &delete_op("2"), &code, now).is_none());
+        let purge = PendingOp::PurgeMessages { channel_id: "1".to_string(), label: "old".to_string(), limit: 10 };
+        assert!(take_confirmed(&mut actions, &purge, &code, now).is_none());
+        assert_eq!(actions.len(), 1);
+
+        let later = now + chrono::Duration::minutes(PENDING_ACTION_TTL_MINS + 1);
+        let mut expired = actions.clone();
+        assert!(take_confirmed(&mut expired, &delete_op("1"), &code, later).is_none());
+
+        // Codes are case-insensitive
+        assert!(take_confirmed(&mut actions, &delete_op("1"), &code.to_lowercase(), now).is_some());
+        assert!(actions.is_empty());
+    }
+
+    fn channel(id: &str, name: &str, channel_type: u8) -> Channel {
+        Channel { id: id.to_string(), name: Some(name.to_string()), channel_type, position: None, parent_id: None }
+    }
+
+    fn guild() -> Vec<Channel> {
+        vec![channel("1", "general", 0), channel("2", "Projects", 4), channel("3", "projects", 0)]
+    }
+
+    #[test]
+    fn move_resolves_both_sides() {
+        let channels = guild();
+        // Category names only match categories, case-insensitively
+        assert_eq!(plan_move(&channels, "#general", "PROJECTS", false).unwrap(), ("1".to_string(), MoveTarget::Category("2".to_string())));
+        // IDs are taken as they are
+        assert_eq!(plan_move(&channels, "77", "88", false).unwrap(), ("77".to_string(), MoveTarget::Category("88".to_string())));
+        assert_eq!(move_body("2", None), serde_json::json!({ "parent_id": "2" }));
+    }
+
+    #[test]
+    fn move_creates_a_missing_category_only_when_asked() {
+        let channels = guild();
+        let err = plan_move(&channels, "general", "Archive", false).unwrap_err();
+        assert!(err.to_string().contains("--create-category"));
+
+        let (channel_id, target) = plan_move(&channels, "general", "Archive", true).unwrap();
+        assert_eq!((channel_id.as_str(), &target), ("1", &MoveTarget::NewCategory("Archive".to_string())));
+        // The created category's ID goes into the move that follows
+        assert_eq!(move_body("99", Some(3)), serde_json::json!({ "parent_id": "99", "position": 3 }));
+
+        // An unknown channel fails before anything would be created
+        let err = plan_move(&channels, "nope", "Archive", true).unwrap_err();
+        assert_eq!(err.to_string(), "Channel 'nope' not found");
+    }
+
+    #[test]
+    fn pending_actions_without_code_hash_still_load() {
+        let json = r#"[{"id":"1a2b","op":{"type":"delete_channel","channel_id":"9","label":"x"},"created_at":"2026-01-01T00:00:00+00:00"}]"#;
+        let actions: Vec<PendingAction> = serde_json::from_str(json).unwrap();
+        assert_eq!(actions[0].code_hash, "");
+        // An empty hash never matches a code
+        let mut actions = actions;
+        let now = actions[0].created_at;
+        let op = PendingOp::DeleteChannel { channel_id: "9".to_string(), label: "x".to_string() };
+        assert!(take_confirmed(&mut actions, &op, "", now).is_none());
+    }
+}
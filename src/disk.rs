@@ -0,0 +1,57 @@
+use std::path::{Path, PathBuf};
+use sysinfo::Disks;
+
+use crate::attachment::format_bytes;
+
+/// Directories whose filesystems Neywa needs writable: sessions and config, and
+/// downloaded attachments
+pub fn watched_paths() -> Vec<PathBuf> {
+    vec![
+        dirs::config_dir().unwrap_or_else(|| PathBuf::from(".")),
+        std::env::temp_dir(),
+    ]
+}
+
+/// Free bytes on the filesystem holding `path` (the disk with the longest
+/// matching mount point). None if no mounted disk matches.
+pub fn available_space(path: &Path) -> Option<u64> {
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let disks = Disks::new_with_refreshed_list();
+    disks
+        .list()
+        .iter()
+        .filter(|disk| path.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        .map(|disk| disk.available_space())
+}
+
+/// The emptiest watched filesystem, if it's below `threshold` bytes free
+pub fn low_space(threshold: u64) -> Option<u64> {
+    watched_paths()
+        .iter()
+        .filter_map(|path| available_space(path))
+        .min()
+        .filter(|free| *free < threshold)
+}
+
+/// "⚠️ Low disk space (1.2 GB free)"
+pub fn banner(free: u64) -> String {
+    format!("⚠️ Low disk space ({} free)", format_bytes(free))
+}
+
+/// Refuse a write of `needed` bytes under `path` that would leave less than
+/// `threshold` bytes free. Unknown free space is allowed through.
+pub fn ensure_headroom(path: &Path, needed: u64, threshold: u64) -> Result<(), String> {
+    let Some(free) = available_space(path) else {
+        return Ok(());
+    };
+    if free < needed.saturating_add(threshold) {
+        return Err(format!(
+            "Not enough disk space: {} needed, {} free (keeping {} in reserve).",
+            format_bytes(needed),
+            format_bytes(free),
+            format_bytes(threshold)
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use anyhow::Result;
+
+use crate::claude::{self, AiBackend};
+use crate::config::Config;
+use crate::discord_api;
+
+/// `neywa doctor` - check the setup problems that make Neywa look silently broken
+pub async fn run() -> Result<()> {
+    println!("Neywa Doctor");
+    println!("============");
+    let mut problems = 0;
+
+    let config = Config::load()?;
+    if config.discord_bot_token.is_some() {
+        println!("✅ Discord bot token configured");
+    } else {
+        println!("❌ Discord bot token not configured (run 'neywa install')");
+        problems += 1;
+    }
+    if config.discord_guild_id.is_some() {
+        println!("✅ Discord guild ID configured");
+    } else {
+        println!("⚠️  Discord guild ID not configured (needed for 'neywa discord' commands)");
+    }
+
+    if config.discord_bot_token.is_some() {
+        match discord_api::fetch_application().await {
+            Ok(app) => {
+                println!("✅ Bot token valid (application: {})", app.name);
+                if app.has_message_content_intent() {
+                    println!("✅ Message Content intent enabled");
+                } else {
+                    println!("❌ Message Content intent is disabled — Neywa cannot read messages");
+                    println!(
+                        "   Enable it at https://discord.com/developers/applications/{}/bot (Privileged Gateway Intents)",
+                        app.id
+                    );
+                    problems += 1;
+                }
+            }
+            Err(e) => {
+                println!("❌ Could not fetch application info: {}", e);
+                problems += 1;
+            }
+        }
+    }
+
+    for check in claude::check_backends().await {
+        match (check.backend, &check.status) {
+            (AiBackend::Claude, claude::CliStatus::Missing) => {
+                println!("❌ claude CLI not found (https://docs.anthropic.com/en/docs/claude-code)");
+                problems += 1;
+            }
+            (AiBackend::ClaudeZ, claude::CliStatus::Missing) => {
+                println!("⚠️  claude-z CLI not found (only needed for !z mode)");
+            }
+            (AiBackend::Codex, claude::CliStatus::Missing) => {
+                println!("⚠️  codex CLI not found (only needed for !codex mode)");
+            }
+            (backend, _) => {
+                println!("{}", check.describe());
+                if backend == AiBackend::Claude && !check.ok() {
+                    problems += 1;
+                }
+            }
+        }
+    }
+
+    println!();
+    if problems == 0 {
+        println!("All checks passed.");
+    } else {
+        println!("{} problem(s) found.", problems);
+    }
+    Ok(())
+}
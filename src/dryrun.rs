@@ -0,0 +1,38 @@
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::claude::{self, truncate_str, StreamEvent};
+
+/// Longest prompt excerpt echoed back
+const MAX_ECHO_CHARS: usize = 1500;
+
+/// Stand-in backend for `neywa daemon --dry-run`: after `delay`, emits a couple
+/// of synthetic tool events and echoes the prompt envelope back. Never reports
+/// a session id, so no session is stored.
+pub fn run_streaming(prompt: &str, delay: Duration) -> mpsc::Receiver<StreamEvent> {
+    let (tx, rx) = mpsc::channel(10);
+    let echo = format!(
+        "🧪 **DRY RUN**: no AI was run. This is the prompt that would have been sent:\n```\n{}\n```",
+        truncate_str(&prompt.replace("```", "'''"), MAX_ECHO_CHARS)
+    );
+    tokio::spawn(async move {
+        let step = delay / 4;
+        let read = claude::format_tool_input("Read", &serde_json::json!({ "file_path": "/tmp/neywa-dry-run.txt" }));
+        let bash = claude::format_tool_input("Bash", &serde_json::json!({ "command": "echo dry run" }));
+        let events = [
+            StreamEvent::ToolUse("Read".to_string(), read.clone()),
+            StreamEvent::ToolResult { tool: claude::tool_label("Read", &read), ok: true, summary: String::new() },
+            StreamEvent::ToolUse("Bash".to_string(), bash),
+        ];
+        for event in events {
+            tokio::time::sleep(step).await;
+            if tx.send(event).await.is_err() {
+                return;
+            }
+        }
+        tokio::time::sleep(step).await;
+        let _ = tx.send(StreamEvent::Text(echo)).await;
+        let _ = tx.send(StreamEvent::Done).await;
+    });
+    rx
+}
@@ -0,0 +1,228 @@
+use crate::claude::AiBackend;
+
+/// Help section a command is listed under
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    Chat,
+    Session,
+    Modes,
+    Admin,
+}
+
+impl Section {
+    fn title(&self) -> &'static str {
+        match self {
+            Section::Chat => "Chat",
+            Section::Session => "Session",
+            Section::Modes => "Modes",
+            Section::Admin => "Admin",
+        }
+    }
+}
+
+/// What a command needs from the channel beyond the user's tier
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Needs {
+    Nothing,
+    /// Runs through the Claude CLI (plan mode, compaction, slash commands)
+    Claude,
+    /// Only meaningful with `quiet_hours` configured
+    QuietHours,
+}
+
+/// One `!help` line
+struct HelpEntry {
+    /// Name in the command table, used for the tier check
+    command: &'static str,
+    usage: &'static str,
+    description: &'static str,
+    section: Section,
+    needs: Needs,
+}
+
+const ENTRIES: &[HelpEntry] = &[
+    HelpEntry { command: "plan", usage: "!plan <msg>", description: "Generate a plan without executing (read-only)", section: Section::Chat, needs: Needs::Claude },
+    HelpEntry { command: "urgent", usage: "!urgent <msg>", description: "Run now and notify you on completion even during quiet hours", section: Section::Chat, needs: Needs::QuietHours },
+    HelpEntry { command: "steer", usage: "!steer <correction>", description: "Redirect the running request instead of queueing behind it", section: Section::Chat, needs: Needs::Nothing },
+    HelpEntry { command: "wake", usage: "!wake", description: "Ignore quiet hours in this channel until they end", section: Section::Chat, needs: Needs::QuietHours },
+    HelpEntry { command: "slash", usage: "slash <cmd>", description: "Run Claude Code slash command", section: Section::Chat, needs: Needs::Claude },
+    HelpEntry { command: "stop", usage: "stop", description: "Stop processing & clear queue (`!stop confirm` for someone else's run)", section: Section::Chat, needs: Needs::Nothing },
+    HelpEntry { command: "queue", usage: "queue", description: "Show queued messages", section: Section::Chat, needs: Needs::Nothing },
+    HelpEntry { command: "queue", usage: "!queue remove <n>|clear", description: "Remove one queued request (yours, or any as admin) or clear the queue", section: Section::Chat, needs: Needs::Nothing },
+    HelpEntry { command: "longtext", usage: "longtext", description: "How to send long text", section: Section::Chat, needs: Needs::Nothing },
+    HelpEntry { command: "alias", usage: "!alias add|list|remove", description: "Define `!name` shortcuts for prompts or commands", section: Section::Chat, needs: Needs::Nothing },
+    HelpEntry { command: "get", usage: "!get <path>", description: "Download a file from this machine", section: Section::Chat, needs: Needs::Nothing },
+    HelpEntry { command: "status", usage: "status", description: "Check session status", section: Section::Session, needs: Needs::Nothing },
+    HelpEntry { command: "reset", usage: "new [mine|channel]", description: "Start a new conversation (yours, or everyone's here)", section: Section::Session, needs: Needs::Nothing },
+    HelpEntry { command: "compact", usage: "compact", description: "Compact session context window", section: Section::Session, needs: Needs::Claude },
+    HelpEntry { command: "context", usage: "!context", description: "Preview what your next message will send", section: Section::Session, needs: Needs::Nothing },
+    HelpEntry { command: "prompt", usage: "!prompt", description: "Show this channel's system prompt and where it comes from", section: Section::Session, needs: Needs::Nothing },
+    HelpEntry { command: "history", usage: "!history [n]", description: "Show the last n messages of your session (default 5)", section: Section::Session, needs: Needs::Claude },
+    HelpEntry { command: "export", usage: "!export", description: "Download your session as a markdown transcript", section: Section::Session, needs: Needs::Nothing },
+    HelpEntry { command: "cost", usage: "!cost", description: "Spend of your session, this channel today, and overall", section: Section::Session, needs: Needs::Claude },
+    HelpEntry { command: "retry", usage: "!retry [new]", description: "Run this channel's last request again (`new`: in a fresh session)", section: Section::Session, needs: Needs::Nothing },
+    HelpEntry { command: "undo", usage: "!undo", description: "Remove your last exchange from this channel's session", section: Section::Session, needs: Needs::Claude },
+    HelpEntry { command: "timing", usage: "!timing", description: "Show where this channel's last run spent its time", section: Section::Session, needs: Needs::Nothing },
+    HelpEntry { command: "autoreset", usage: "!autoreset <hours|daily|off>", description: "Start fresh sessions after idle time", section: Section::Session, needs: Needs::Nothing },
+    HelpEntry { command: "moveto", usage: "!moveto #channel` / `!copyfrom #channel", description: "Hand your session off between channels", section: Section::Session, needs: Needs::Nothing },
+    HelpEntry { command: "handoff", usage: "!handoff #channel [reset]", description: "Continue your session in another channel too", section: Section::Session, needs: Needs::Nothing },
+    HelpEntry { command: "usage", usage: "!usage me", description: "Show your remaining daily quota", section: Section::Session, needs: Needs::Nothing },
+    HelpEntry { command: "z", usage: "!z", description: "Toggle Z mode (claude-z), asks to confirm if a session would be lost", section: Section::Modes, needs: Needs::Nothing },
+    HelpEntry { command: "codex", usage: "!codex", description: "Toggle Codex mode (OpenAI Codex CLI), asks to confirm if a session would be lost", section: Section::Modes, needs: Needs::Nothing },
+    HelpEntry { command: "backends", usage: "!backends", description: "Check which AI CLIs are installed and their versions", section: Section::Modes, needs: Needs::Nothing },
+    HelpEntry { command: "backend", usage: "/backend", description: "Pick Claude, Claude-Z or Codex from a menu (slash command)", section: Section::Modes, needs: Needs::Nothing },
+    HelpEntry { command: "human", usage: "!human", description: "Toggle human-only mode (Neywa stops responding)", section: Section::Modes, needs: Needs::Nothing },
+    HelpEntry { command: "model", usage: "!model [name|reset]", description: "Show or set this channel's Claude model", section: Section::Modes, needs: Needs::Claude },
+    HelpEntry { command: "cwd", usage: "!cwd [path|reset]", description: "Show or set the directory this channel's CLI runs in", section: Section::Modes, needs: Needs::Nothing },
+    HelpEntry { command: "respond-in", usage: "!respond-in <language|off>", description: "Make AI responses in this channel use one language", section: Section::Modes, needs: Needs::Nothing },
+    HelpEntry { command: "done-style", usage: "!done-style <text|reset>", description: "Set this channel's completion message", section: Section::Modes, needs: Needs::Nothing },
+    HelpEntry { command: "embed", usage: "!embed <on|off|reset>", description: "Send this channel's responses as embeds or plain text", section: Section::Modes, needs: Needs::Nothing },
+    HelpEntry { command: "stream", usage: "!stream <on|off|reset>", description: "Show responses in this channel while they are being written", section: Section::Modes, needs: Needs::Nothing },
+    HelpEntry { command: "longfile", usage: "!longfile <messages|off|reset>", description: "Attach responses longer than this many messages as a file", section: Section::Modes, needs: Needs::Nothing },
+    HelpEntry { command: "timeout", usage: "!timeout <minutes|off|reset>", description: "Stop runs in this channel that take longer than this", section: Section::Modes, needs: Needs::Nothing },
+    HelpEntry { command: "threads", usage: "!threads <on|off>", description: "Answer each request in its own thread", section: Section::Modes, needs: Needs::Nothing },
+    HelpEntry { command: "shared", usage: "!shared <on|off>", description: "Share one session between everyone in this channel", section: Section::Modes, needs: Needs::Nothing },
+    HelpEntry { command: "mention", usage: "!mention <on|off>", description: "Only answer @mentions and replies to Neywa in this channel", section: Section::Modes, needs: Needs::Nothing },
+    HelpEntry { command: "run", usage: "!run <cmd>", description: "Execute terminal command directly", section: Section::Admin, needs: Needs::Nothing },
+    HelpEntry { command: "put", usage: "!put <path> [--force]", description: "Save attached file(s) to this machine", section: Section::Admin, needs: Needs::Nothing },
+    HelpEntry { command: "digest", usage: "!digest now", description: "Post today's activity digest to #logs", section: Section::Admin, needs: Needs::Nothing },
+    HelpEntry { command: "activate", usage: "!activate` / `!deactivate", description: "Turn Neywa on/off in this channel", section: Section::Admin, needs: Needs::Nothing },
+    HelpEntry { command: "logs", usage: "!logs here` / `!logs auto", description: "Post logs to this channel, or back to #logs discovery", section: Section::Admin, needs: Needs::Nothing },
+    HelpEntry { command: "setup", usage: "!setup", description: "Create the recommended channels that are missing", section: Section::Admin, needs: Needs::Nothing },
+    HelpEntry { command: "remind", usage: "!remind [#channel] in <delay>|cron <expr> <prompt>", description: "Run a prompt later or on a schedule and post the answer", section: Section::Chat, needs: Needs::Nothing },
+    HelpEntry { command: "schedule", usage: "!schedule [list]` / `!schedule delete <id>", description: "List or delete scheduled jobs", section: Section::Chat, needs: Needs::Nothing },
+    HelpEntry { command: "cron", usage: "!cron import` / `!cron remove <n|all>", description: "List or delete old `neywa run` crontab jobs", section: Section::Admin, needs: Needs::Nothing },
+    HelpEntry { command: "prune-state", usage: "!prune-state [dry-run]", description: "Forget settings and sessions of deleted channels", section: Section::Admin, needs: Needs::Nothing },
+    HelpEntry { command: "sessions", usage: "!sessions [clear [#channel]]", description: "List tracked sessions, or clear them all or one channel's", section: Section::Admin, needs: Needs::Nothing },
+    HelpEntry { command: "stats", usage: "!stats [day|week]", description: "Runs, time, tokens and cost by channel and user", section: Section::Admin, needs: Needs::Nothing },
+    HelpEntry { command: "restart", usage: "!restart [--all]", description: "Reset all Claude sessions (fixes MCP/connection issues)", section: Section::Admin, needs: Needs::Nothing },
+    HelpEntry { command: "update", usage: "update", description: "Update to latest version", section: Section::Admin, needs: Needs::Nothing },
+];
+
+/// Everything `!help` needs to know about the caller and channel
+#[derive(Debug, Clone)]
+pub struct Capabilities {
+    pub version: &'static str,
+    pub backend: AiBackend,
+    pub model: String,
+    pub workdir: String,
+    pub quiet_hours: bool,
+    /// Commands the caller's tier allows, by command-table name
+    pub allowed: Vec<&'static str>,
+    /// This server's `!alias` names (without `!`)
+    pub aliases: Vec<String>,
+}
+
+/// Build the help text for one user in one channel. Commands the user can't
+/// run here are left out; admin-only commands collapse to a single line.
+pub fn render(caps: &Capabilities) -> String {
+    let available = |entry: &HelpEntry| match entry.needs {
+        Needs::Nothing => true,
+        Needs::Claude => caps.backend != AiBackend::Codex,
+        Needs::QuietHours => caps.quiet_hours,
+    };
+
+    let mut text = format!(
+        "**Neywa v{}** - AI Assistant\n{} · Model: {} · Workdir: `{}`\n\
+        Just type a message to chat with AI. Commands without `!` also work as slash commands.",
+        caps.version,
+        caps.backend.status_line(),
+        caps.model,
+        caps.workdir
+    );
+    for section in [Section::Chat, Section::Session, Section::Modes, Section::Admin] {
+        let in_section: Vec<&HelpEntry> = ENTRIES
+            .iter()
+            .filter(|entry| entry.section == section && available(entry))
+            .collect();
+        let lines: Vec<String> = in_section
+            .iter()
+            .filter(|entry| caps.allowed.contains(&entry.command))
+            .map(|entry| format!("`{}` - {}", entry.usage, entry.description))
+            .collect();
+        if section == Section::Admin && lines.is_empty() && !in_section.is_empty() {
+            text.push_str(&format!(
+                "\n\n**{}**: {} more commands for admins (`admin_user_ids`)",
+                section.title(),
+                in_section.len()
+            ));
+            continue;
+        }
+        if lines.is_empty() {
+            continue;
+        }
+        text.push_str(&format!("\n\n**{}**\n{}", section.title(), lines.join("\n")));
+    }
+    if !caps.aliases.is_empty() {
+        let names: Vec<String> = caps.aliases.iter().map(|name| format!("`!{}`", name)).collect();
+        text.push_str(&format!("\n\n**Aliases**: {}", names.join(", ")));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADMIN_ONLY: &[&str] = &["run", "put", "digest", "activate", "logs", "setup", "cron", "prune-state", "sessions", "stats", "restart", "update"];
+
+    fn caps(backend: AiBackend, admin: bool) -> Capabilities {
+        let allowed = ENTRIES
+            .iter()
+            .map(|entry| entry.command)
+            .filter(|command| admin || !ADMIN_ONLY.contains(command))
+            .collect();
+        Capabilities {
+            version: "0.0.0",
+            backend,
+            model: "sonnet".to_string(),
+            workdir: "/tmp".to_string(),
+            quiet_hours: false,
+            allowed,
+            aliases: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn users_see_admin_commands_collapsed() {
+        let text = render(&caps(AiBackend::Claude, false));
+        assert!(text.contains("`!plan <msg>`"));
+        assert!(!text.contains("`!run <cmd>`"));
+        assert!(!text.contains("`update`"));
+        let hidden = ENTRIES.iter().filter(|entry| entry.section == Section::Admin).count();
+        assert!(text.contains(&format!("**Admin**: {} more commands for admins", hidden)));
+    }
+
+    #[test]
+    fn admins_see_admin_commands() {
+        let text = render(&caps(AiBackend::Claude, true));
+        assert!(text.contains("**Admin**\n"));
+        assert!(text.contains("`!run <cmd>` - Execute terminal command directly"));
+        assert!(!text.contains("more commands for admins"));
+    }
+
+    #[test]
+    fn channel_capabilities_filter_commands() {
+        // Codex channels have no Claude-only commands
+        let text = render(&caps(AiBackend::Codex, true));
+        assert!(!text.contains("`!plan <msg>`"));
+        assert!(!text.contains("`compact`"));
+        assert!(text.contains("`!codex`"));
+
+        // Quiet-hours commands only once quiet hours are set
+        let mut quiet = caps(AiBackend::Claude, false);
+        assert!(!render(&quiet).contains("`!urgent <msg>`"));
+        quiet.quiet_hours = true;
+        assert!(render(&quiet).contains("`!urgent <msg>`"));
+    }
+
+    #[test]
+    fn settings_and_aliases_are_shown() {
+        let mut user = caps(AiBackend::Claude, false);
+        user.aliases = vec!["deploy".to_string(), "st".to_string()];
+        let text = render(&user);
+        assert!(text.contains("Model: sonnet · Workdir: `/tmp`"));
+        assert!(text.ends_with("**Aliases**: `!deploy`, `!st`"));
+        assert!(!render(&caps(AiBackend::Claude, false)).contains("**Aliases**"));
+    }
+}
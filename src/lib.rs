@@ -0,0 +1,50 @@
+//! Neywa: a Discord front end for the Claude Code and Codex CLIs.
+//!
+//! The `neywa` binary is a thin CLI over this crate. The backend runners
+//! (`claude`, `codex`), the Discord REST helpers (`discord_api`), `config` and
+//! `prompt` are usable on their own, as are the CLI output parsers in `stream`.
+//! `daemon` runs the bot process and `update` handles self-updates; `dryrun`
+//! stands in for the backends in demos and integration tests. The remaining
+//! public modules exist for the binary's subcommands.
+
+pub mod activity;
+pub mod attachment;
+pub mod audit;
+pub mod claude;
+pub mod codex;
+pub mod config;
+pub mod cron;
+pub mod daemon;
+pub mod discord;
+pub mod discord_api;
+pub mod doctor;
+pub mod dryrun;
+pub mod lifecycle;
+pub mod prompt;
+pub mod service;
+pub mod shutdown;
+pub mod stats;
+pub mod stream;
+pub mod transcript;
+pub mod tray;
+pub mod update;
+
+mod alias;
+mod children;
+mod confirm;
+mod cost;
+mod digest;
+mod disk;
+mod help;
+mod links;
+mod loopguard;
+mod metrics;
+mod onboarding;
+mod quiet;
+mod ratelimit;
+mod redact;
+mod schedule;
+mod status_page;
+mod timing;
+mod transcribe;
+mod usage;
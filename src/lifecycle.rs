@@ -0,0 +1,169 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Why the daemon is running now, inferred at boot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RestartReason {
+    /// No earlier run recorded
+    FirstStart,
+    /// The previous run shut down gracefully
+    CleanStart,
+    /// The previous run ended without a clean shutdown (launchd KeepAlive after a crash or kill)
+    Crash,
+    /// Restarted after `!update`
+    SelfUpdate,
+}
+
+impl RestartReason {
+    pub fn label(&self) -> &'static str {
+        match self {
+            RestartReason::FirstStart => "first start",
+            RestartReason::CleanStart => "clean start",
+            RestartReason::Crash => "recovered after crash",
+            RestartReason::SelfUpdate => "self-update",
+        }
+    }
+}
+
+/// Infer the restart reason from what the previous run left behind. A pending
+/// update wins (the updater exits without the clean marker); otherwise the
+/// marker separates a graceful stop from a crash.
+pub fn infer_reason(update_pending: bool, clean_marker: bool, ran_before: bool) -> RestartReason {
+    if update_pending {
+        RestartReason::SelfUpdate
+    } else if clean_marker {
+        RestartReason::CleanStart
+    } else if ran_before {
+        RestartReason::Crash
+    } else {
+        RestartReason::FirstStart
+    }
+}
+
+/// The running daemon, as recorded at boot (also read by `neywa service status`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DaemonState {
+    pub started_at: DateTime<Local>,
+    pub reason: RestartReason,
+    pub pid: u32,
+    pub version: String,
+}
+
+impl DaemonState {
+    /// "⏱ Up 2d 4h, last restart: self-update"
+    pub fn describe(&self) -> String {
+        format!(
+            "⏱ Up {}, last restart: {}",
+            format_uptime(Local::now() - self.started_at),
+            self.reason.label()
+        )
+    }
+}
+
+/// Compact uptime: "2d 4h", "3h 12m", "5m"
+pub fn format_uptime(uptime: chrono::Duration) -> String {
+    let days = uptime.num_days();
+    let hours = uptime.num_hours() % 24;
+    let minutes = uptime.num_minutes() % 60;
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else {
+        format!("{}m", minutes.max(0))
+    }
+}
+
+fn neywa_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("neywa")
+}
+
+/// Daemon state file (start time, restart reason)
+fn state_file_path() -> PathBuf {
+    neywa_dir().join("daemon_state.json")
+}
+
+/// Written by the graceful-shutdown path, consumed at the next boot
+fn clean_shutdown_marker_path() -> PathBuf {
+    neywa_dir().join("clean_shutdown")
+}
+
+/// Pending self-update info, written before restarting into a new version
+pub(crate) fn update_pending_path() -> PathBuf {
+    neywa_dir().join("update_pending.json")
+}
+
+/// Record this boot: infer why we're running, consume the clean-shutdown marker
+/// and save the daemon state. Call once at daemon start, before the bot connects.
+pub fn record_start() -> DaemonState {
+    let marker = clean_shutdown_marker_path();
+    let reason = infer_reason(update_pending_path().exists(), marker.exists(), state_file_path().exists());
+    let _ = std::fs::remove_file(&marker);
+
+    let state = DaemonState {
+        started_at: Local::now(),
+        reason,
+        pid: std::process::id(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+    };
+    let _ = std::fs::create_dir_all(neywa_dir());
+    match serde_json::to_string_pretty(&state) {
+        Ok(json) => {
+            if let Err(e) = std::fs::write(state_file_path(), json) {
+                tracing::warn!("Failed to save daemon state: {}", e);
+            }
+        }
+        Err(e) => tracing::warn!("Failed to serialize daemon state: {}", e),
+    }
+    tracing::info!("Daemon started ({})", reason.label());
+    state
+}
+
+/// The current (or last) daemon's recorded state
+pub fn load_state() -> Option<DaemonState> {
+    let content = std::fs::read_to_string(state_file_path()).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// Mark a graceful shutdown so the next boot isn't reported as a crash
+pub fn mark_clean_shutdown() {
+    let _ = std::fs::create_dir_all(neywa_dir());
+    if let Err(e) = std::fs::write(clean_shutdown_marker_path(), Local::now().to_rfc3339()) {
+        tracing::warn!("Failed to write clean shutdown marker: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn infer_reason_covers_every_combination() {
+        use RestartReason::*;
+        // (update_pending, clean_marker, ran_before) -> reason
+        let cases = [
+            ((false, false, false), FirstStart),
+            ((false, false, true), Crash),
+            ((false, true, false), CleanStart),
+            ((false, true, true), CleanStart),
+            ((true, false, false), SelfUpdate),
+            ((true, false, true), SelfUpdate),
+            ((true, true, false), SelfUpdate),
+            ((true, true, true), SelfUpdate),
+        ];
+        for ((update_pending, clean_marker, ran_before), expected) in cases {
+            assert_eq!(
+                infer_reason(update_pending, clean_marker, ran_before),
+                expected,
+                "update_pending={} clean_marker={} ran_before={}",
+                update_pending,
+                clean_marker,
+                ran_before
+            );
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use chrono::{DateTime, Local};
+use regex::Regex;
+use serenity::builder::GetMessages;
+use serenity::http::Http;
+use serenity::model::channel::Message;
+use serenity::model::id::{ChannelId, MessageId};
+
+/// Links resolved per message
+const MAX_LINKS: usize = 5;
+
+/// Messages fetched for a link to a channel or thread (no message ID)
+const THREAD_MESSAGES: u8 = 20;
+
+/// Total characters of linked content added to the prompt
+const MAX_CONTEXT_CHARS: usize = 8000;
+
+/// A `discord.com/channels/<guild>/<channel>[/<message>]` link
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageLink {
+    pub url: String,
+    /// None for DM links (`@me`)
+    pub guild_id: Option<u64>,
+    pub channel_id: u64,
+    /// None for links to a channel or thread rather than a single message
+    pub message_id: Option<u64>,
+}
+
+/// Find Discord message/channel links (discord.com, discordapp.com, canary., ptb.)
+pub fn parse_links(content: &str) -> Vec<MessageLink> {
+    let re = Regex::new(
+        r"https?://(?:(?:canary|ptb)\.)?discord(?:app)?\.com/channels/(\d+|@me)/(\d+)(?:/(\d+))?",
+    )
+    .unwrap();
+    let mut links: Vec<MessageLink> = Vec::new();
+    for caps in re.captures_iter(content) {
+        let link = MessageLink {
+            url: caps[0].to_string(),
+            guild_id: caps[1].parse().ok(),
+            channel_id: match caps[2].parse() {
+                Ok(id) => id,
+                Err(_) => continue,
+            },
+            message_id: caps.get(3).and_then(|m| m.as_str().parse().ok()),
+        };
+        if !links.contains(&link) {
+            links.push(link);
+        }
+    }
+    links
+}
+
+/// One message as a quoted block with author attribution
+fn quote(msg: &Message) -> String {
+    let when = DateTime::from_timestamp(msg.timestamp.unix_timestamp(), 0)
+        .map(|t| t.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default();
+    let mut block = format!("{} ({}):\n", msg.author.name, when);
+    for line in msg.content.lines() {
+        block.push_str("> ");
+        block.push_str(line);
+        block.push('\n');
+    }
+    for attachment in &msg.attachments {
+        block.push_str(&format!("> [attachment: {}]\n", attachment.filename));
+    }
+    block
+}
+
+/// Fetch the message (or recent channel/thread messages) a link points to
+async fn fetch(http: &Http, link: &MessageLink) -> serenity::Result<String> {
+    let channel = ChannelId::new(link.channel_id);
+    match link.message_id {
+        Some(message_id) => {
+            let msg = channel.message(http, MessageId::new(message_id)).await?;
+            Ok(quote(&msg))
+        }
+        None => {
+            let mut messages = channel.messages(http, GetMessages::new().limit(THREAD_MESSAGES)).await?;
+            // The API returns newest first
+            messages.reverse();
+            Ok(messages.iter().map(quote).collect::<Vec<_>>().join("\n"))
+        }
+    }
+}
+
+/// Result of resolving the links in a message
+#[derive(Debug, Default)]
+pub struct LinkedContext {
+    /// Prompt block with the fetched content (empty if there were no links)
+    pub block: String,
+    /// (url, error) for links that couldn't be read
+    pub failed: Vec<(String, String)>,
+}
+
+/// Resolve links to messages in the same guild into a quoted context block,
+/// capped at `MAX_CONTEXT_CHARS`. Links to other guilds are reported as failures.
+pub async fn resolve(http: &Http, content: &str, guild_id: Option<u64>) -> LinkedContext {
+    let mut context = LinkedContext::default();
+    let mut remaining = MAX_CONTEXT_CHARS;
+
+    for link in parse_links(content).into_iter().take(MAX_LINKS) {
+        if link.guild_id != guild_id {
+            context.failed.push((link.url, "not in this server".to_string()));
+            continue;
+        }
+        let text = match fetch(http, &link).await {
+            Ok(text) => text,
+            Err(e) => {
+                tracing::warn!("Failed to fetch linked message {}: {}", link.url, e);
+                context.failed.push((link.url, e.to_string()));
+                continue;
+            }
+        };
+        if remaining == 0 {
+            context.failed.push((link.url, "context size limit reached".to_string()));
+            continue;
+        }
+
+        let mut text: String = text.chars().take(remaining).collect();
+        remaining -= text.chars().count();
+        if remaining == 0 {
+            text.push_str("\n[...truncated]");
+        }
+        let what = if link.message_id.is_some() { "Linked message" } else { "Recent messages from linked channel" };
+        context.block.push_str(&format!("\n\n[{} {}]\n{}", what, link.url, text.trim_end()));
+    }
+
+    for (url, error) in &context.failed {
+        context.block.push_str(&format!("\n\n[Could not read {}: {}]", url, error));
+    }
+    context
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_message_channel_and_dm_links() {
+        let content = "see https://discord.com/channels/11/22/33 and \
+                       https://canary.discordapp.com/channels/11/44 plus \
+                       https://ptb.discord.com/channels/@me/55/66";
+        let links = parse_links(content);
+        assert_eq!(links.len(), 3);
+        assert_eq!(links[0].guild_id, Some(11));
+        assert_eq!(links[0].channel_id, 22);
+        assert_eq!(links[0].message_id, Some(33));
+        assert_eq!(links[0].url, "https://discord.com/channels/11/22/33");
+        assert_eq!(links[1].channel_id, 44);
+        assert_eq!(links[1].message_id, None);
+        assert_eq!(links[2].guild_id, None);
+        assert_eq!(links[2].message_id, Some(66));
+    }
+
+    #[test]
+    fn ignores_duplicates_and_other_urls() {
+        let link = "https://discord.com/channels/1/2/3";
+        assert_eq!(parse_links(&format!("{} {}", link, link)).len(), 1);
+        assert!(parse_links("https://example.com/channels/1/2/3").is_empty());
+        assert!(parse_links("https://discord.com/invite/abc").is_empty());
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Window in which responses to bots/webhooks are counted
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// How long a channel ignores bots/webhooks once the breaker trips
+pub const PAUSE: Duration = Duration::from_secs(10 * 60);
+
+/// Result of letting a non-human message through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// Respond normally
+    Allow,
+    /// Channel is paused; stay silent
+    Paused,
+    /// This message tripped the breaker; the channel is now paused
+    Tripped,
+}
+
+#[derive(Debug, Default)]
+struct ChannelState {
+    /// Times Neywa accepted a bot/webhook message since the last human message
+    responses: VecDeque<Instant>,
+    paused_until: Option<Instant>,
+}
+
+/// Per-channel circuit breaker against bot-to-bot feedback loops
+#[derive(Debug, Default)]
+pub struct LoopGuard {
+    channels: HashMap<u64, ChannelState>,
+}
+
+impl LoopGuard {
+    /// A human spoke: whatever was happening isn't a pure bot loop
+    pub fn human_message(&mut self, channel_id: u64) {
+        if let Some(state) = self.channels.get_mut(&channel_id) {
+            state.responses.clear();
+        }
+    }
+
+    /// Record a response to a bot/webhook message. Trips when more than
+    /// `limit` such responses happen within a minute with no human in between.
+    pub fn bot_message(&mut self, channel_id: u64, limit: u32, now: Instant) -> Verdict {
+        let state = self.channels.entry(channel_id).or_default();
+
+        if let Some(until) = state.paused_until {
+            if now < until {
+                return Verdict::Paused;
+            }
+            state.paused_until = None;
+        }
+
+        while state.responses.front().is_some_and(|t| now.duration_since(*t) > WINDOW) {
+            state.responses.pop_front();
+        }
+        state.responses.push_back(now);
+
+        if state.responses.len() > limit as usize {
+            state.responses.clear();
+            state.paused_until = Some(now + PAUSE);
+            Verdict::Tripped
+        } else {
+            Verdict::Allow
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trips_after_limit_within_window() {
+        let mut guard = LoopGuard::default();
+        let start = Instant::now();
+        for i in 0..3 {
+            assert_eq!(guard.bot_message(1, 3, start + Duration::from_secs(i)), Verdict::Allow);
+        }
+        assert_eq!(guard.bot_message(1, 3, start + Duration::from_secs(3)), Verdict::Tripped);
+        assert_eq!(guard.bot_message(1, 3, start + Duration::from_secs(4)), Verdict::Paused);
+        // Other channels are unaffected
+        assert_eq!(guard.bot_message(2, 3, start), Verdict::Allow);
+        // The pause ends
+        assert_eq!(guard.bot_message(1, 3, start + Duration::from_secs(3) + PAUSE), Verdict::Allow);
+    }
+
+    #[test]
+    fn old_responses_leave_the_window() {
+        let mut guard = LoopGuard::default();
+        let start = Instant::now();
+        for i in 0..10u64 {
+            let now = start + Duration::from_secs(i * 40);
+            assert_eq!(guard.bot_message(1, 2, now), Verdict::Allow);
+        }
+    }
+
+    #[test]
+    fn human_message_resets_count() {
+        let mut guard = LoopGuard::default();
+        let start = Instant::now();
+        for i in 0..20u64 {
+            if i % 2 == 0 {
+                guard.human_message(1);
+            }
+            assert_eq!(guard.bot_message(1, 2, start + Duration::from_secs(i)), Verdict::Allow);
+        }
+    }
+}
@@ -1,82 +1,11 @@
 mod cli;
-mod claude;
-mod codex;
-mod config;
-mod discord;
-mod discord_api;
-mod service;
-mod tray;
 
 use anyhow::Result;
 use clap::Parser;
-use cli::{Cli, Command, DiscordAction, ServiceAction};
-use std::fs;
-use std::path::PathBuf;
-use std::sync::mpsc;
-use sysinfo::{Pid, System};
+use cli::{AuditAction, Cli, Command, CronAction, DiscordAction, ServiceAction, SessionsAction};
+use neywa::{activity, attachment, audit, claude, config, cron, daemon, discord, discord_api, doctor, service, stats, transcript};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-/// Get the PID file path
-fn pid_file_path() -> Result<PathBuf> {
-    let config_dir = dirs::config_dir()
-        .ok_or_else(|| anyhow::anyhow!("Could not find config directory"))?
-        .join("neywa");
-    fs::create_dir_all(&config_dir)?;
-    Ok(config_dir.join("neywa.pid"))
-}
-
-/// Kill existing neywa daemon if running
-fn kill_existing_daemon() -> Result<()> {
-    let pid_path = pid_file_path()?;
-    let current_pid = std::process::id();
-
-    if pid_path.exists() {
-        if let Ok(pid_str) = fs::read_to_string(&pid_path) {
-            if let Ok(pid) = pid_str.trim().parse::<u32>() {
-                // Skip if the PID matches our own (happens after exec-based restart)
-                if pid == current_pid {
-                    tracing::info!("PID file contains our own PID ({}), skipping kill", pid);
-                    let _ = fs::remove_file(&pid_path);
-                    return Ok(());
-                }
-
-                let mut sys = System::new();
-                sys.refresh_processes(sysinfo::ProcessesToUpdate::All);
-
-                if let Some(process) = sys.process(Pid::from_u32(pid)) {
-                    // Verify it's actually neywa
-                    if process.name().to_string_lossy().contains("neywa") {
-                        tracing::info!("Killing existing neywa daemon (PID: {})", pid);
-                        process.kill();
-                        // Give it a moment to terminate
-                        std::thread::sleep(std::time::Duration::from_millis(500));
-                    }
-                }
-            }
-        }
-        // Remove old PID file
-        let _ = fs::remove_file(&pid_path);
-    }
-
-    Ok(())
-}
-
-/// Write current PID to file
-fn write_pid_file() -> Result<()> {
-    let pid_path = pid_file_path()?;
-    let pid = std::process::id();
-    fs::write(&pid_path, pid.to_string())?;
-    tracing::info!("PID file written: {:?} (PID: {})", pid_path, pid);
-    Ok(())
-}
-
-/// Remove PID file on exit
-fn remove_pid_file() {
-    if let Ok(pid_path) = pid_file_path() {
-        let _ = fs::remove_file(pid_path);
-    }
-}
-
 fn main() -> Result<()> {
     // Initialize logging
     tracing_subscriber::registry()
@@ -89,40 +18,18 @@ fn main() -> Result<()> {
     let cli = Cli::parse();
 
     match cli.command {
-        Command::Daemon => {
-            tracing::info!("Starting Neywa daemon...");
-
-            // Kill existing daemon if running
-            kill_existing_daemon()?;
-
-            // Write PID file
-            write_pid_file()?;
-
-            // Spawn caffeinate to prevent system sleep (display may still sleep)
-            let _caffeinate = std::process::Command::new("/usr/bin/caffeinate")
-                .arg("-s")
-                .arg("-w")
-                .arg(std::process::id().to_string())
-                .stdout(std::process::Stdio::null())
-                .stderr(std::process::Stdio::null())
-                .spawn()
-                .ok();
-            tracing::info!("Sleep prevention: caffeinate started");
-
-            // Run daemon
-            let result = run_daemon_with_tray();
-
-            // Cleanup
-            remove_pid_file();
-
-            result?;
+        Command::Daemon { dry_run } => {
+            daemon::run(dry_run)?;
         }
-        Command::Run { message } => {
+        Command::Run { message, channel, post } => {
             // For non-daemon commands, use tokio runtime
             let rt = tokio::runtime::Runtime::new()?;
             rt.block_on(async {
                 tracing::info!("Running single command...");
-                let response = claude::run(&message, false).await?;
+                let response = match channel {
+                    Some(channel) => discord::run_in_channel(&channel, &message, post).await?,
+                    None => claude::run(&message, false, None, None).await?,
+                };
                 println!("{}", response);
                 Ok::<_, anyhow::Error>(())
             })?;
@@ -138,6 +45,95 @@ fn main() -> Result<()> {
         Command::Config => {
             config::show()?;
         }
+        Command::Doctor => {
+            let rt = tokio::runtime::Runtime::new()?;
+            rt.block_on(doctor::run())?;
+        }
+        Command::Notify { channel, message } => {
+            let message = match message.as_deref() {
+                None | Some("-") => {
+                    let mut buf = String::new();
+                    std::io::Read::read_to_string(&mut std::io::stdin(), &mut buf)?;
+                    buf
+                }
+                Some(m) => m.to_string(),
+            };
+            let message = message.trim();
+            if message.is_empty() {
+                anyhow::bail!("Nothing to send (empty message)");
+            }
+            // Discord's 2000 char message limit
+            let message = claude::truncate_str(message, 1990);
+            // Hook notifications arrive silently during the guild's quiet hours
+            let config = config::Config::load()?;
+            let silent = config.in_quiet_hours(config.discord_guild_id);
+            let rt = tokio::runtime::Runtime::new()?;
+            rt.block_on(discord_api::send_message(&channel, &message, silent))?;
+        }
+        Command::InstallHooks => {
+            config::install_hooks()?;
+        }
+        Command::Audit { action } => match action {
+            AuditAction::Tail { user, since, lines } => {
+                audit::tail(user.as_deref(), since.as_deref(), lines)?;
+            }
+        },
+        Command::Logs { activity: true, today } => {
+            activity::print(today)?;
+        }
+        Command::Logs { activity: false, .. } => {
+            println!("Daemon output: /tmp/neywa.log");
+            println!("Full run records: neywa logs --activity [--today]");
+        }
+        Command::Clean => {
+            let config = config::Config::load()?;
+            let removed = attachment::clean(config.attachment_retention(), config.attachment_cache_bytes());
+            println!(
+                "Removed {} file(s) from {}, freed {}",
+                removed.files,
+                attachment::attachments_root().display(),
+                attachment::format_bytes(removed.bytes)
+            );
+        }
+        Command::Stats { week } => {
+            let period = if week { stats::Period::Week } else { stats::Period::Day };
+            println!("{}", stats::report(period, |id| format!("#{}", id)).replace("**", ""));
+        }
+        Command::Sessions { action } => match action {
+            SessionsAction::Export { dir, since } => {
+                let rt = tokio::runtime::Runtime::new()?;
+                rt.block_on(transcript::export_all(&dir, since.as_deref()))?;
+            }
+        },
+        Command::Cron { action } => {
+            let entries = cron::parse(&cron::read_crontab()?);
+            match action {
+                CronAction::Import => {
+                    println!("{}", cron::describe(&entries));
+                    if !entries.is_empty() {
+                        println!("\nMove into Neywa's scheduler with: neywa cron migrate <numbers|all> --force");
+                        println!("Remove with: neywa cron remove <numbers|all> --force");
+                    }
+                }
+                CronAction::Migrate { entries: args, channel, force } => {
+                    let selected = cron::select(&entries, &args.join(" ")).map_err(anyhow::Error::msg)?;
+                    let rt = tokio::runtime::Runtime::new()?;
+                    rt.block_on(cron::migrate_from_cli(&selected, channel.as_deref(), force))?;
+                }
+                CronAction::Remove { entries: args, force } => {
+                    let selected = cron::select(&entries, &args.join(" ")).map_err(anyhow::Error::msg)?;
+                    for entry in &selected {
+                        println!("- {} {}", entry.schedule, entry.command);
+                    }
+                    if !force {
+                        println!("\nNothing changed. Re-run with --force to remove these lines.");
+                    } else {
+                        let backup = cron::remove(&selected)?;
+                        println!("\nRemoved. Original crontab saved to {}", backup.display());
+                    }
+                }
+            }
+        }
         Command::Service { action } => match action {
             ServiceAction::Install => {
                 service::install()?;
@@ -155,7 +151,7 @@ fn main() -> Result<()> {
                 match action {
                     DiscordAction::Channels => discord_api::list_channels().await?,
                     DiscordAction::Send { channel, message } => {
-                        discord_api::send_message(&channel, &message).await?
+                        discord_api::send_message(&channel, &message, false).await?
                     }
                     DiscordAction::Guild => discord_api::show_guild().await?,
                     DiscordAction::Create { name, channel_type, category, topic } => {
@@ -164,13 +160,16 @@ fn main() -> Result<()> {
                             &channel_type,
                             category.as_deref(),
                             topic.as_deref(),
-                        ).await?
+                        ).await?;
+                    }
+                    DiscordAction::Delete { channel, force, confirm } => {
+                        discord_api::delete_channel(&channel, force, confirm.as_deref()).await?
                     }
-                    DiscordAction::Delete { channel } => {
-                        discord_api::delete_channel(&channel).await?
+                    DiscordAction::Purge { channel, limit, force, confirm } => {
+                        discord_api::purge_channel(&channel, limit, force, confirm.as_deref()).await?
                     }
-                    DiscordAction::Move { channel, category } => {
-                        discord_api::move_channel(&channel, &category).await?
+                    DiscordAction::Move { channel, category, create_category, position } => {
+                        discord_api::move_channel(&channel, &category, create_category, position).await?
                     }
                 }
                 Ok::<_, anyhow::Error>(())
@@ -180,67 +179,3 @@ fn main() -> Result<()> {
 
     Ok(())
 }
-
-fn run_daemon_with_tray() -> Result<()> {
-    // Create channels for communication between tray and daemon
-    let (status_tx, status_rx) = mpsc::channel();
-    let (quit_tx, quit_rx) = mpsc::channel();
-
-    // Clone quit_tx for Ctrl+C handler
-    let ctrlc_quit_tx = quit_tx.clone();
-
-    // Set up Ctrl+C handler
-    ctrlc::set_handler(move || {
-        tracing::info!("Received Ctrl+C, shutting down...");
-        let _ = ctrlc_quit_tx.send(());
-        // Force exit after a short delay
-        std::thread::spawn(|| {
-            std::thread::sleep(std::time::Duration::from_secs(1));
-            tracing::info!("Exiting...");
-            remove_pid_file();
-            std::process::exit(0);
-        });
-    })?;
-
-    // Spawn Discord bot in a separate thread with its own tokio runtime
-    let bot_handle = std::thread::spawn(move || {
-        let rt = tokio::runtime::Runtime::new().expect("Failed to create tokio runtime");
-
-        rt.block_on(async {
-            // Send initial status
-            let _ = status_tx.send(tray::TrayCommand::UpdateStatus("🟢 Connected".to_string()));
-
-            // Create a future that completes when quit signal is received
-            let quit_future = async {
-                loop {
-                    if quit_rx.try_recv().is_ok() {
-                        tracing::info!("Received quit signal");
-                        break;
-                    }
-                    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-                }
-            };
-
-            // Run bot with quit signal
-            tokio::select! {
-                result = discord::run_bot() => {
-                    if let Err(e) = result {
-                        tracing::error!("Discord bot error: {}", e);
-                        let _ = status_tx.send(tray::TrayCommand::UpdateStatus("🔴 Disconnected".to_string()));
-                    }
-                }
-                _ = quit_future => {
-                    tracing::info!("Shutting down Discord bot...");
-                }
-            }
-        });
-    });
-
-    // Run tray on main thread (required for macOS)
-    tray::run_tray(status_rx, quit_tx);
-
-    // Tray exited, force cleanup and exit
-    tracing::info!("Tray closed, cleaning up...");
-    remove_pid_file();
-    std::process::exit(0);
-}
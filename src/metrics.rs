@@ -0,0 +1,174 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::timing::{Phase, PhaseTimings};
+
+/// Completed run durations kept per channel
+const HISTORY_LEN: usize = 20;
+
+/// Rolling run-duration history per channel, used for queue wait estimates
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RunMetrics {
+    /// Channel ID -> recent run durations in seconds (oldest first)
+    #[serde(default)]
+    durations: HashMap<u64, VecDeque<u64>>,
+    /// Phase name -> recent durations in milliseconds across all channels (oldest first)
+    #[serde(default)]
+    phases: HashMap<String, VecDeque<u64>>,
+}
+
+/// Path for storing run metrics
+fn metrics_file_path() -> PathBuf {
+    let config_dir = dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("neywa");
+    config_dir.join("metrics.json")
+}
+
+impl RunMetrics {
+    /// Load metrics from file
+    pub fn load() -> Self {
+        let path = metrics_file_path();
+        if !path.exists() {
+            return Self::default();
+        }
+        match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                tracing::warn!("Failed to parse metrics file: {}", e);
+                Self::default()
+            }),
+            Err(e) => {
+                tracing::warn!("Failed to read metrics file: {}", e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Save metrics to file
+    pub fn save(&self) {
+        let path = metrics_file_path();
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        match serde_json::to_string_pretty(self) {
+            Ok(json) => {
+                if let Err(e) = std::fs::write(&path, json) {
+                    tracing::warn!("Failed to save metrics: {}", e);
+                }
+            }
+            Err(e) => tracing::warn!("Failed to serialize metrics: {}", e),
+        }
+    }
+
+    /// Record a completed run for a channel
+    pub fn record_run(&mut self, channel_id: u64, duration: Duration) {
+        let history = self.durations.entry(channel_id).or_default();
+        history.push_back(duration.as_secs());
+        while history.len() > HISTORY_LEN {
+            history.pop_front();
+        }
+        self.save();
+    }
+
+    /// Record the phase breakdown of a finished run (any outcome)
+    pub fn record_phases(&mut self, timings: &PhaseTimings) {
+        for (phase, duration) in timings.iter() {
+            let history = self.phases.entry(phase.name().to_string()).or_default();
+            history.push_back(duration.as_millis() as u64);
+            while history.len() > HISTORY_LEN {
+                history.pop_front();
+            }
+        }
+        self.save();
+    }
+
+    /// Median of a phase's recent durations
+    pub fn phase_median(&self, phase: Phase) -> Option<Duration> {
+        let mut recent: Vec<u64> = self.phases.get(phase.name())?.iter().copied().collect();
+        if recent.is_empty() {
+            return None;
+        }
+        recent.sort_unstable();
+        Some(Duration::from_millis(recent[recent.len() / 2]))
+    }
+
+    /// Recent run durations for a channel
+    pub fn recent(&self, channel_id: u64) -> Vec<Duration> {
+        self.durations
+            .get(&channel_id)
+            .map(|h| h.iter().map(|s| Duration::from_secs(*s)).collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Rough wait for a message with `runs_ahead` runs before it (including the
+/// one in progress), using the median of recent durations. None without history.
+pub fn estimate_wait(recent: &[Duration], runs_ahead: usize) -> Option<Duration> {
+    if recent.is_empty() || runs_ahead == 0 {
+        return None;
+    }
+    let mut sorted = recent.to_vec();
+    sorted.sort();
+    let median = sorted[sorted.len() / 2];
+    Some(median * runs_ahead as u32)
+}
+
+/// Format an estimate so it clearly reads as approximate ("~12 min")
+pub fn format_estimate(wait: Duration) -> String {
+    let minutes = (wait.as_secs() + 30) / 60;
+    if minutes == 0 {
+        "<1 min".to_string()
+    } else if minutes < 90 {
+        format!("~{} min", minutes)
+    } else {
+        format!("~{:.1} h", minutes as f64 / 60.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(values: &[u64]) -> Vec<Duration> {
+        values.iter().map(|s| Duration::from_secs(*s)).collect()
+    }
+
+    #[test]
+    fn estimate_uses_median() {
+        let recent = secs(&[60, 600, 120, 90, 3600]);
+        assert_eq!(estimate_wait(&recent, 1), Some(Duration::from_secs(120)));
+        assert_eq!(estimate_wait(&recent, 3), Some(Duration::from_secs(360)));
+        assert_eq!(estimate_wait(&recent, 0), None);
+        assert_eq!(estimate_wait(&[], 2), None);
+    }
+
+    #[test]
+    fn estimate_formatting() {
+        assert_eq!(format_estimate(Duration::from_secs(10)), "<1 min");
+        assert_eq!(format_estimate(Duration::from_secs(30)), "~1 min");
+        assert_eq!(format_estimate(Duration::from_secs(12 * 60)), "~12 min");
+        assert_eq!(format_estimate(Duration::from_secs(89 * 60)), "~89 min");
+        assert_eq!(format_estimate(Duration::from_secs(150 * 60)), "~2.5 h");
+    }
+
+    #[test]
+    fn recent_and_phase_median() {
+        let mut metrics = RunMetrics::default();
+        metrics.durations.insert(7, [30, 45].into_iter().collect());
+        assert_eq!(metrics.recent(7), secs(&[30, 45]));
+        assert!(metrics.recent(8).is_empty());
+
+        assert_eq!(metrics.phase_median(Phase::Spawn), None);
+        metrics.phases.insert(Phase::Spawn.name().to_string(), [300, 100, 200].into_iter().collect());
+        assert_eq!(metrics.phase_median(Phase::Spawn), Some(Duration::from_millis(200)));
+
+        // Even counts take the upper middle; phases are kept apart
+        metrics.phases.insert(Phase::Stream.name().to_string(), [4000, 1000, 3000, 2000].into_iter().collect());
+        assert_eq!(metrics.phase_median(Phase::Stream), Some(Duration::from_millis(3000)));
+        assert_eq!(metrics.phase_median(Phase::Spawn), Some(Duration::from_millis(200)));
+        metrics.phases.insert(Phase::QueueWait.name().to_string(), VecDeque::new());
+        assert_eq!(metrics.phase_median(Phase::QueueWait), None);
+    }
+}
@@ -0,0 +1,68 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+/// Channels `!setup` creates, in order. Names match what channel-mode detection expects.
+pub const STANDARD_CHANNELS: &[&str] = &["general", "code", "research", "tasks", "logs"];
+
+/// Path for remembering which guilds have seen the onboarding message
+fn onboarded_file_path() -> PathBuf {
+    let config_dir = dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("neywa");
+    config_dir.join("onboarded_guilds.json")
+}
+
+fn load() -> HashSet<u64> {
+    std::fs::read_to_string(onboarded_file_path())
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Whether the onboarding message was already posted in a guild
+pub fn is_onboarded(guild_id: u64) -> bool {
+    load().contains(&guild_id)
+}
+
+/// Remember that a guild has been onboarded
+pub fn mark_onboarded(guild_id: u64) {
+    let mut guilds = load();
+    if !guilds.insert(guild_id) {
+        return;
+    }
+    let path = onboarded_file_path();
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    match serde_json::to_string(&guilds) {
+        Ok(json) => {
+            if let Err(e) = std::fs::write(&path, json) {
+                tracing::warn!("Failed to save onboarding state: {}", e);
+            }
+        }
+        Err(e) => tracing::warn!("Failed to serialize onboarding state: {}", e),
+    }
+}
+
+/// The first-run message. `missing` lists standard channels the guild doesn't have.
+pub fn message(missing: &[&str]) -> String {
+    let mut text = String::from(
+        "👋 **Hi, I'm Neywa** — Claude Code (and Codex) running on this server's host machine, driven from Discord.\n\n\
+        **Get started**\n\
+        • Just type a message to chat; each channel keeps its own session\n\
+        • `!plan <request>` — plan a change without touching anything\n\
+        • `!stop` — cancel the running task\n\n\
+        **Channel modes**: #code, #research and #tasks get specialized instructions, and #logs receives activity logs. \
+        Other channels get the general assistant.\n\
+        Only users on the allowlist (`allowed_user_ids` in the Neywa config) get answers.",
+    );
+    if !missing.is_empty() {
+        let names: Vec<String> = missing.iter().map(|name| format!("#{}", name)).collect();
+        text.push_str(&format!(
+            "\n\nMissing channels: {}. Run `!setup` to create the recommended channels.",
+            names.join(", ")
+        ));
+    }
+    text.push_str("\n\nSee `!help` for everything else.");
+    text
+}
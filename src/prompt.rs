@@ -0,0 +1,108 @@
+/// Everything that goes into the text sent to a backend for one message
+#[derive(Debug, Clone)]
+pub struct PromptContext<'a> {
+    pub username: &'a str,
+    /// What the user typed (already defaulted for attachment-only messages)
+    pub content: &'a str,
+    /// Attachment and linked-message blocks appended after the content
+    pub attachments: &'a str,
+    /// The channel type's system prompt
+    pub channel_prompt: &'a str,
+    /// True when no session exists yet, so the preamble has to be included
+    pub first_message: bool,
+    /// The channel's `!respond-in` language
+    pub language: Option<&'a str>,
+    /// The language setting changed since this session last heard about it
+    pub language_changed: bool,
+}
+
+/// Build the exact prompt for a message. A fresh session gets the channel's
+/// system preamble; every message is prefixed with `[username]:`.
+/// The text is the same for every backend.
+pub fn build(ctx: &PromptContext) -> String {
+    let message = format!("[{}]: {}{}", ctx.username, ctx.content, ctx.attachments);
+    if !ctx.first_message {
+        if ctx.language_changed {
+            return format!("[System: {}]\n\n{}", language_instruction(ctx.language), message);
+        }
+        return message;
+    }
+
+    let language = match ctx.language {
+        Some(_) => format!(" {}", language_instruction(ctx.language)),
+        None => String::new(),
+    };
+    format!(
+        "[System: {}{} Multiple users may participate. Each message is prefixed with [username]. Distinguish users by name in your responses.]\n\n{}",
+        ctx.channel_prompt, language, message
+    )
+}
+
+/// Instruction for a channel's response language (None = the setting was cleared)
+pub fn language_instruction(language: Option<&str>) -> String {
+    match language {
+        Some(language) => format!("Always respond in {}.", language),
+        None => "The response language is no longer fixed; reply in the language each user writes in.".to_string(),
+    }
+}
+
+/// Content used when a message has attachments but no text
+pub fn default_content(all_images: bool) -> &'static str {
+    if all_images {
+        "Analyze this image"
+    } else {
+        "Analyze this file"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context<'a>(first_message: bool) -> PromptContext<'a> {
+        PromptContext {
+            username: "alice",
+            content: "fix the build",
+            attachments: "\n\n[Attached files: /tmp/log.txt (10 B)]",
+            channel_prompt: "You are a coding assistant.",
+            first_message,
+            language: None,
+            language_changed: false,
+        }
+    }
+
+    // Golden strings: the text built before prompt construction moved into this module
+    #[test]
+    fn first_message_matches_previous_envelope() {
+        assert_eq!(
+            build(&context(true)),
+            "[System: You are a coding assistant. Multiple users may participate. Each message is prefixed with [username]. \
+             Distinguish users by name in your responses.]\n\n[alice]: fix the build\n\n[Attached files: /tmp/log.txt (10 B)]"
+        );
+    }
+
+    #[test]
+    fn follow_up_is_just_the_message() {
+        assert_eq!(build(&context(false)), "[alice]: fix the build\n\n[Attached files: /tmp/log.txt (10 B)]");
+    }
+
+    #[test]
+    fn language_instructions() {
+        let ctx = PromptContext { language: Some("Korean"), ..context(true) };
+        assert!(build(&ctx).starts_with("[System: You are a coding assistant. Always respond in Korean. Multiple users"));
+
+        let ctx = PromptContext { language: Some("Korean"), language_changed: true, ..context(false) };
+        assert_eq!(
+            build(&ctx),
+            "[System: Always respond in Korean.]\n\n[alice]: fix the build\n\n[Attached files: /tmp/log.txt (10 B)]"
+        );
+        let ctx = PromptContext { language_changed: true, ..context(false) };
+        assert!(build(&ctx).starts_with("[System: The response language is no longer fixed;"));
+    }
+
+    #[test]
+    fn default_content_for_attachments() {
+        assert_eq!(default_content(true), "Analyze this image");
+        assert_eq!(default_content(false), "Analyze this file");
+    }
+}
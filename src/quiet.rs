@@ -0,0 +1,232 @@
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+/// A daily window ("23:00-08:00") during which completions don't ping anyone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuietHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl QuietHours {
+    /// Parse "HH:MM-HH:MM"
+    pub fn parse(value: &str) -> Option<Self> {
+        let (start, end) = value.split_once('-')?;
+        Some(Self {
+            start: NaiveTime::parse_from_str(start.trim(), "%H:%M").ok()?,
+            end: NaiveTime::parse_from_str(end.trim(), "%H:%M").ok()?,
+        })
+    }
+
+    /// Whether `time` falls inside the window. The start is inclusive and the
+    /// end exclusive; windows crossing midnight wrap, equal bounds mean never.
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+/// Timezone quiet hours are read in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timezone {
+    /// The daemon host's local time
+    Local,
+    /// "UTC" or a fixed offset like "+09:00" (no daylight saving time)
+    Fixed(FixedOffset),
+    /// An IANA zone like "Europe/Berlin", which follows daylight saving time
+    Named(Tz),
+}
+
+impl Timezone {
+    /// Parse a `quiet_hours_timezone` value: an IANA zone name, "UTC", or a fixed offset
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let value = value.trim();
+        if let Some(offset) = parse_offset(value) {
+            return Ok(Self::Fixed(offset));
+        }
+        value.parse::<Tz>().map(Self::Named).map_err(|_| {
+            format!(
+                "unknown timezone {:?} (expected an IANA name like \"Asia/Seoul\", \"UTC\" or an offset like \"+09:00\")",
+                value
+            )
+        })
+    }
+
+    /// `utc` as a local time here
+    pub fn at(&self, utc: DateTime<Utc>) -> DateTime<FixedOffset> {
+        match self {
+            Self::Local => utc.with_timezone(&Local).fixed_offset(),
+            Self::Fixed(offset) => utc.with_timezone(offset),
+            Self::Named(tz) => utc.with_timezone(tz).fixed_offset(),
+        }
+    }
+
+    /// A wall-clock time here as an instant. A time skipped by a DST jump
+    /// resolves to the first valid minute after it; a repeated one to its first occurrence.
+    pub fn resolve(&self, local: NaiveDateTime) -> Option<DateTime<FixedOffset>> {
+        fn first<T: TimeZone>(tz: &T, local: NaiveDateTime) -> Option<DateTime<FixedOffset>> {
+            (0..=120)
+                .find_map(|minutes| tz.from_local_datetime(&(local + Duration::minutes(minutes))).earliest())
+                .map(|time| time.fixed_offset())
+        }
+        match self {
+            Self::Local => first(&Local, local),
+            Self::Fixed(offset) => first(offset, local),
+            Self::Named(tz) => first(tz, local),
+        }
+    }
+}
+
+/// "UTC" or a fixed offset like "+09:00"
+fn parse_offset(value: &str) -> Option<FixedOffset> {
+    if value.eq_ignore_ascii_case("utc") || value == "Z" {
+        return FixedOffset::east_opt(0);
+    }
+    let (sign, rest) = match value.as_bytes().first()? {
+        b'+' => (1, &value[1..]),
+        b'-' => (-1, &value[1..]),
+        _ => return None,
+    };
+    let (hours, minutes) = rest.split_once(':').unwrap_or((rest, "0"));
+    let hours: i32 = hours.parse().ok()?;
+    let minutes: i32 = minutes.parse().ok()?;
+    if hours > 14 || minutes > 59 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+/// The configured timezone, or the host's local time when it's unset or invalid
+fn timezone(value: Option<&str>) -> Timezone {
+    match value.map(Timezone::parse) {
+        Some(Ok(tz)) => tz,
+        Some(Err(e)) => {
+            tracing::debug!("quiet_hours_timezone: {}, using local time", e);
+            Timezone::Local
+        }
+        None => Timezone::Local,
+    }
+}
+
+/// When the quiet window covering `now` ends, or None outside quiet hours
+pub fn window_end_at(window: QuietHours, tz: Timezone, now: DateTime<Utc>) -> Option<DateTime<FixedOffset>> {
+    let local = tz.at(now);
+    if !window.contains(local.time()) {
+        return None;
+    }
+    let mut end = local.date_naive().and_time(window.end);
+    if end <= local.naive_local() {
+        end += Duration::days(1);
+    }
+    // Resolved in the zone itself, so a DST change inside the window moves the end with it
+    tz.resolve(end)
+}
+
+/// When the quiet window covering now ends, or None outside quiet hours
+pub fn current_window_end(quiet_hours: Option<&str>, timezone_name: Option<&str>) -> Option<DateTime<FixedOffset>> {
+    let window = quiet_hours.and_then(QuietHours::parse)?;
+    window_end_at(window, timezone(timezone_name), Utc::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hhmm: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(hhmm, "%H:%M").unwrap()
+    }
+
+    #[test]
+    fn same_day_window() {
+        let window = QuietHours::parse("13:00-14:30").unwrap();
+        assert!(!window.contains(at("12:59")));
+        assert!(window.contains(at("13:00")));
+        assert!(window.contains(at("14:29")));
+        assert!(!window.contains(at("14:30")));
+    }
+
+    #[test]
+    fn window_crossing_midnight() {
+        let window = QuietHours::parse("23:00-08:00").unwrap();
+        assert!(!window.contains(at("22:59")));
+        assert!(window.contains(at("23:00")));
+        assert!(window.contains(at("00:00")));
+        assert!(window.contains(at("07:59")));
+        assert!(!window.contains(at("08:00")));
+        assert!(!window.contains(at("12:00")));
+    }
+
+    #[test]
+    fn equal_bounds_are_never_quiet() {
+        let window = QuietHours::parse("08:00-08:00").unwrap();
+        for time in ["00:00", "07:59", "08:00", "08:01", "23:59"] {
+            assert!(!window.contains(at(time)), "{}", time);
+        }
+    }
+
+    #[test]
+    fn parse_rejects_malformed_windows() {
+        assert_eq!(QuietHours::parse(" 23:00 - 08:00 "), QuietHours::parse("23:00-08:00"));
+        assert!(QuietHours::parse("23:00").is_none());
+        assert!(QuietHours::parse("25:00-08:00").is_none());
+        assert!(QuietHours::parse("11pm-8am").is_none());
+    }
+
+    fn utc(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn timezones_parse() {
+        assert_eq!(Timezone::parse("UTC"), Ok(Timezone::Fixed(FixedOffset::east_opt(0).unwrap())));
+        assert_eq!(Timezone::parse("+09:00"), Ok(Timezone::Fixed(FixedOffset::east_opt(9 * 3600).unwrap())));
+        assert_eq!(Timezone::parse(" Europe/Berlin "), Ok(Timezone::Named(chrono_tz::Europe::Berlin)));
+        let err = Timezone::parse("Mars/Olympus").unwrap_err();
+        assert!(err.contains("unknown timezone \"Mars/Olympus\""), "{}", err);
+        assert!(Timezone::parse("+15:00").is_err());
+    }
+
+    #[test]
+    fn named_zone_follows_dst_where_a_fixed_offset_drifts() {
+        let window = QuietHours::parse("23:00-08:00").unwrap();
+        // 22:30 UTC is 23:30 in Berlin in winter (+01:00) but 00:30 in summer (+02:00)
+        let berlin = Timezone::Named(chrono_tz::Europe::Berlin);
+        let winter = window_end_at(window, berlin, utc("2026-01-15T22:30:00Z")).unwrap();
+        let summer = window_end_at(window, berlin, utc("2026-07-15T22:30:00Z")).unwrap();
+        assert_eq!(winter.to_rfc3339(), "2026-01-16T08:00:00+01:00");
+        assert_eq!(summer.to_rfc3339(), "2026-07-16T08:00:00+02:00");
+
+        // The same window at a fixed +01:00 ends an hour late in summer
+        let fixed = Timezone::parse("+01:00").unwrap();
+        let drifted = window_end_at(window, fixed, utc("2026-07-15T22:30:00Z")).unwrap();
+        assert_eq!(drifted.with_timezone(&Utc), utc("2026-07-16T07:00:00Z"));
+        assert_eq!(summer.with_timezone(&Utc), utc("2026-07-16T06:00:00Z"));
+    }
+
+    #[test]
+    fn window_end_across_a_dst_transition() {
+        let new_york = Timezone::Named(chrono_tz::America::New_York);
+        let window = QuietHours::parse("23:00-08:00").unwrap();
+
+        // Clocks spring forward at 02:00 on 2026-03-08: the window starts in EST and ends in EDT
+        let end = window_end_at(window, new_york, utc("2026-03-08T05:00:00Z")).unwrap();
+        assert_eq!(end.to_rfc3339(), "2026-03-08T08:00:00-04:00");
+        assert!(window_end_at(window, new_york, utc("2026-03-08T12:00:00Z")).is_none());
+
+        // And fall back at 02:00 on 2026-11-01: 08:00 EST is 13:00 UTC
+        let end = window_end_at(window, new_york, utc("2026-11-01T05:00:00Z")).unwrap();
+        assert_eq!(end.with_timezone(&Utc), utc("2026-11-01T13:00:00Z"));
+    }
+
+    #[test]
+    fn window_ending_in_a_skipped_hour() {
+        // 02:30 doesn't exist on 2026-03-08 in New York; the window ends at 03:00 EDT
+        let new_york = Timezone::Named(chrono_tz::America::New_York);
+        let window = QuietHours::parse("23:00-02:30").unwrap();
+        let end = window_end_at(window, new_york, utc("2026-03-08T05:00:00Z")).unwrap();
+        assert_eq!(end.to_rfc3339(), "2026-03-08T03:00:00-04:00");
+    }
+}
@@ -0,0 +1,74 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Window `max_messages_per_user_per_hour` is counted over
+const WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// Sliding-window limit on how many messages each user may send to the AI,
+/// counted across all channels
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    /// User ID -> times of accepted messages within the window (oldest first)
+    users: HashMap<u64, VecDeque<Instant>>,
+}
+
+impl RateLimiter {
+    /// Count a message if the user has sent fewer than `limit` in the last
+    /// hour. Otherwise returns how long until the oldest one leaves the window.
+    pub fn try_acquire(&mut self, user_id: u64, limit: u32, now: Instant) -> Result<(), Duration> {
+        // Forget users whose messages have all aged out
+        self.users.retain(|_, times| {
+            while times.front().is_some_and(|t| now.duration_since(*t) >= WINDOW) {
+                times.pop_front();
+            }
+            !times.is_empty()
+        });
+
+        let times = self.users.entry(user_id).or_default();
+        if times.len() >= limit as usize {
+            let oldest = times.front().copied().unwrap_or(now);
+            return Err(WINDOW.saturating_sub(now.duration_since(oldest)));
+        }
+        times.push_back(now);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limit_resets_as_messages_leave_the_window() {
+        let mut limiter = RateLimiter::default();
+        let start = Instant::now();
+        let minute = Duration::from_secs(60);
+        assert_eq!(limiter.try_acquire(1, 2, start), Ok(()));
+        assert_eq!(limiter.try_acquire(1, 2, start + 10 * minute), Ok(()));
+        // Full: wait until the first message is an hour old
+        assert_eq!(limiter.try_acquire(1, 2, start + 20 * minute), Err(40 * minute));
+        // Refused messages don't count, so the first slot frees at exactly an hour
+        assert_eq!(limiter.try_acquire(1, 2, start + WINDOW - Duration::from_secs(1)), Err(Duration::from_secs(1)));
+        assert_eq!(limiter.try_acquire(1, 2, start + WINDOW), Ok(()));
+        assert_eq!(limiter.try_acquire(1, 2, start + WINDOW + minute), Err(9 * minute));
+    }
+
+    #[test]
+    fn users_are_limited_independently() {
+        let mut limiter = RateLimiter::default();
+        let now = Instant::now();
+        assert_eq!(limiter.try_acquire(1, 1, now), Ok(()));
+        assert!(limiter.try_acquire(1, 1, now).is_err());
+        assert_eq!(limiter.try_acquire(2, 1, now), Ok(()));
+        assert!(limiter.try_acquire(2, 1, now).is_err());
+    }
+
+    #[test]
+    fn aged_out_users_are_forgotten() {
+        let mut limiter = RateLimiter::default();
+        let now = Instant::now();
+        limiter.try_acquire(1, 5, now).unwrap();
+        limiter.try_acquire(2, 5, now + WINDOW).unwrap();
+        assert!(!limiter.users.contains_key(&1));
+    }
+}
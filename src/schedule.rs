@@ -0,0 +1,337 @@
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDateTime, TimeZone, Timelike};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+/// How far ahead a cron expression is searched for its next match
+const CRON_HORIZON_DAYS: i64 = 366 * 4;
+
+/// A parsed five-field cron expression (minute hour day-of-month month day-of-week),
+/// in the host's local time. Fields take `*`, numbers, ranges, lists and `/step`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSpec {
+    minutes: u64,
+    hours: u32,
+    days: u32,
+    months: u16,
+    weekdays: u8,
+    /// Whether day-of-month / day-of-week were restricted (cron ORs them when both are)
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+/// Parse one field into a bitmask of allowed values in `min..=max`
+fn parse_field(field: &str, min: u32, max: u32) -> Option<u64> {
+    let mut mask = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|s| *s > 0)?),
+            None => (part, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((a, b)) = range.split_once('-') {
+            (a.parse().ok()?, b.parse().ok()?)
+        } else {
+            let value = range.parse().ok()?;
+            // "5/15" means from 5 to the end, stepping by 15
+            (value, if part.contains('/') { max } else { value })
+        };
+        if start < min || end > max || start > end {
+            return None;
+        }
+        for value in (start..=end).step_by(step as usize) {
+            mask |= 1 << value;
+        }
+    }
+    Some(mask)
+}
+
+impl CronSpec {
+    pub fn parse(expr: &str) -> Option<Self> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return None;
+        };
+        let mut weekdays = parse_field(weekday, 0, 7)?;
+        // 7 is Sunday too
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & 0x7f;
+        }
+        Some(Self {
+            minutes: parse_field(minute, 0, 59)?,
+            hours: parse_field(hour, 0, 23)? as u32,
+            days: parse_field(day, 1, 31)? as u32,
+            months: parse_field(month, 1, 12)? as u16,
+            weekdays: weekdays as u8,
+            days_restricted: day != "*",
+            weekdays_restricted: weekday != "*",
+        })
+    }
+
+    fn matches_day(&self, t: &NaiveDateTime) -> bool {
+        let day = self.days & (1 << t.day()) != 0;
+        let weekday = self.weekdays & (1 << t.weekday().num_days_from_sunday()) != 0;
+        let day_ok = match (self.days_restricted, self.weekdays_restricted) {
+            (true, true) => day || weekday,
+            (true, false) => day,
+            (false, true) => weekday,
+            (false, false) => true,
+        };
+        day_ok && self.months & (1 << t.month()) != 0
+    }
+
+    /// The first matching minute strictly after `after`
+    pub fn next_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        let start = after.naive_local().with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let limit = start + Duration::days(CRON_HORIZON_DAYS);
+        let mut t = start;
+        while t < limit {
+            if !self.matches_day(&t) {
+                t = (t.date() + Duration::days(1)).and_hms_opt(0, 0, 0)?;
+                continue;
+            }
+            if self.hours & (1 << t.hour()) == 0 {
+                t = t.with_minute(0)? + Duration::hours(1);
+                continue;
+            }
+            if self.minutes & (1 << t.minute()) != 0 {
+                // Skipped by a DST gap: keep looking
+                if let Some(local) = Local.from_local_datetime(&t).earliest() {
+                    return Some(local);
+                }
+            }
+            t += Duration::minutes(1);
+        }
+        None
+    }
+}
+
+/// Parse a relative delay such as "2h", "90m", "1d", "1h30m" (units s, m, h, d)
+pub fn parse_delay(text: &str) -> Option<Duration> {
+    let mut total = Duration::zero();
+    let mut number = String::new();
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let n: i64 = number.parse().ok()?;
+        number.clear();
+        total += match c {
+            's' => Duration::seconds(n),
+            'm' => Duration::minutes(n),
+            'h' => Duration::hours(n),
+            'd' => Duration::days(n),
+            _ => return None,
+        };
+    }
+    (number.is_empty() && total > Duration::zero()).then_some(total)
+}
+
+/// Parse `in <delay> <prompt>` or `cron <m h dom mon dow> <prompt>` into the
+/// trigger, first run and prompt
+pub fn parse_request(args: &str, now: DateTime<Local>) -> Result<(Trigger, DateTime<Local>, String), String> {
+    let mut words = args.split_whitespace();
+    let (trigger, next_run) = match words.next() {
+        Some("in") => {
+            let delay = words.next().and_then(parse_delay).ok_or("Delay must look like `30m`, `2h` or `1d12h`.")?;
+            (Trigger::Once, now + delay)
+        }
+        Some("cron") => {
+            let expr = words.by_ref().take(5).collect::<Vec<_>>().join(" ");
+            let spec = CronSpec::parse(&expr).ok_or_else(|| format!("Invalid cron expression `{}`.", expr))?;
+            let next = spec.next_after(now).ok_or_else(|| format!("`{}` never fires.", expr))?;
+            (Trigger::Cron(expr), next)
+        }
+        _ => return Err("Start with `in <delay>` or `cron <m h dom mon dow>`.".to_string()),
+    };
+    let prompt = words.collect::<Vec<_>>().join(" ");
+    if prompt.is_empty() {
+        return Err("What should I do then? Add a prompt after the time.".to_string());
+    }
+    Ok((trigger, next_run, prompt))
+}
+
+/// When a job fires
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Trigger {
+    /// Once, at `next_run`
+    Once,
+    /// Repeatedly, per a five-field cron expression
+    Cron(String),
+}
+
+/// A scheduled prompt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub id: u32,
+    pub trigger: Trigger,
+    pub prompt: String,
+    /// Where the prompt runs and its answer is posted
+    pub channel_id: u64,
+    pub user_id: u64,
+    pub user_name: String,
+    pub created: DateTime<Local>,
+    pub next_run: DateTime<Local>,
+}
+
+impl Job {
+    /// "in 2h 5m (10-15 14:00)", "`0 9 * * 1-5` (next in 14h 0m)"
+    pub fn describe_when(&self, now: DateTime<Local>) -> String {
+        let until = format_until(self.next_run - now);
+        match &self.trigger {
+            Trigger::Once => format!("{} ({})", until, self.next_run.format("%m-%d %H:%M")),
+            Trigger::Cron(expr) => format!("`{}` (next {})", expr, until),
+        }
+    }
+}
+
+/// "in 2h 5m", "in 3d 4h", "now"
+fn format_until(d: Duration) -> String {
+    let minutes = d.num_minutes();
+    if minutes < 1 {
+        "now".to_string()
+    } else if minutes < 60 {
+        format!("in {}m", minutes)
+    } else if minutes < 24 * 60 {
+        format!("in {}h {}m", minutes / 60, minutes % 60)
+    } else {
+        format!("in {}d {}h", minutes / (24 * 60), minutes % (24 * 60) / 60)
+    }
+}
+
+/// Persisted scheduled jobs
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ScheduleStore {
+    #[serde(default)]
+    next_id: u32,
+    #[serde(default)]
+    jobs: Vec<Job>,
+}
+
+/// Path for storing scheduled jobs
+fn schedule_file_path() -> PathBuf {
+    let config_dir = dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("neywa");
+    config_dir.join("schedule.json")
+}
+
+impl ScheduleStore {
+    /// Load jobs from file
+    pub fn load() -> Self {
+        let path = schedule_file_path();
+        if !path.exists() {
+            return Self::default();
+        }
+        match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                tracing::warn!("Failed to parse schedule file: {}", e);
+                Self::default()
+            }),
+            Err(e) => {
+                tracing::warn!("Failed to read schedule file: {}", e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Save jobs to file
+    pub fn save(&self) {
+        let path = schedule_file_path();
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        match serde_json::to_string_pretty(self) {
+            Ok(json) => {
+                if let Err(e) = std::fs::write(&path, json) {
+                    tracing::warn!("Failed to save schedule: {}", e);
+                }
+            }
+            Err(e) => tracing::warn!("Failed to serialize schedule: {}", e),
+        }
+    }
+
+    /// Add a job and return it
+    pub fn add(
+        &mut self,
+        trigger: Trigger,
+        next_run: DateTime<Local>,
+        prompt: &str,
+        channel_id: u64,
+        user_id: u64,
+        user_name: &str,
+    ) -> Job {
+        self.next_id += 1;
+        let job = Job {
+            id: self.next_id,
+            trigger,
+            prompt: prompt.to_string(),
+            channel_id,
+            user_id,
+            user_name: user_name.to_string(),
+            created: Local::now(),
+            next_run,
+        };
+        self.jobs.push(job.clone());
+        self.save();
+        job
+    }
+
+    /// All jobs, soonest first
+    pub fn jobs(&self) -> Vec<&Job> {
+        let mut jobs: Vec<&Job> = self.jobs.iter().collect();
+        jobs.sort_by_key(|job| job.next_run);
+        jobs
+    }
+
+    pub fn get(&self, id: u32) -> Option<&Job> {
+        self.jobs.iter().find(|job| job.id == id)
+    }
+
+    pub fn remove(&mut self, id: u32) -> Option<Job> {
+        let index = self.jobs.iter().position(|job| job.id == id)?;
+        let job = self.jobs.remove(index);
+        self.save();
+        Some(job)
+    }
+
+    /// Take the jobs due at `now`. One-shot jobs are removed; cron jobs move to
+    /// their next run. Jobs missed while the daemon was down fire once.
+    pub fn take_due(&mut self, now: DateTime<Local>) -> Vec<Job> {
+        let mut due = Vec::new();
+        self.jobs.retain_mut(|job| {
+            if job.next_run > now {
+                return true;
+            }
+            due.push(job.clone());
+            match &job.trigger {
+                Trigger::Once => false,
+                Trigger::Cron(expr) => match CronSpec::parse(expr).and_then(|spec| spec.next_after(now)) {
+                    Some(next) => {
+                        job.next_run = next;
+                        true
+                    }
+                    None => false,
+                },
+            }
+        });
+        if !due.is_empty() {
+            self.save();
+        }
+        due
+    }
+
+    /// Forget jobs targeting deleted channels.
+    /// Returns how many were (or would be) removed.
+    pub fn prune(&mut self, deleted: &HashSet<u64>, dry_run: bool) -> usize {
+        let doomed = self.jobs.iter().filter(|job| deleted.contains(&job.channel_id)).count();
+        if doomed > 0 && !dry_run {
+            self.jobs.retain(|job| !deleted.contains(&job.channel_id));
+            self.save();
+        }
+        doomed
+    }
+}
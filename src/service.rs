@@ -1,7 +1,9 @@
 use anyhow::{Context, Result};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
+use crate::lifecycle;
+
 const PLIST_NAME: &str = "com.neywa.daemon.plist";
 const APP_BUNDLE_PATH: &str = "/Applications/Neywa.app";
 const BUNDLE_ID: &str = "com.neywa.daemon";
@@ -209,7 +211,7 @@ fn detect_path() -> String {
 /// Generate the plist content - launches neywa directly from .app bundle.
 /// This ensures Neywa.app is the "responsible process" for TCC/FDA,
 /// so child processes (like node) inherit Neywa.app's Full Disk Access.
-fn generate_plist(exe: &PathBuf) -> String {
+fn generate_plist(exe: &Path) -> String {
     let home = dirs::home_dir()
         .map(|h| h.display().to_string())
         .unwrap_or_else(|| "/Users/unknown".to_string());
@@ -350,6 +352,15 @@ pub fn status() -> Result<()> {
     println!("LaunchAgent path: {:?}", plist);
     println!("Installed: {}", plist.exists());
     println!("CLI version: {}", env!("CARGO_PKG_VERSION"));
+    if let Some(state) = lifecycle::load_state() {
+        println!(
+            "Daemon started: {} (PID {}, v{}, {})",
+            state.started_at.format("%Y-%m-%d %H:%M:%S"),
+            state.pid,
+            state.version,
+            state.reason.label()
+        );
+    }
     println!("App binary path: {}", app_exe.display());
     println!("App binary exists: {}", app_exe.exists());
 
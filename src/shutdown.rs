@@ -0,0 +1,174 @@
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// Why the daemon is stopping
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    /// Ctrl+C (SIGINT)
+    Interrupt,
+    /// SIGTERM, e.g. from launchd or a newer daemon replacing this one
+    Terminate,
+    /// "Quit Neywa" in the tray menu
+    TrayQuit,
+}
+
+impl Reason {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Reason::Interrupt => "Ctrl+C",
+            Reason::Terminate => "SIGTERM",
+            Reason::TrayQuit => "tray quit",
+        }
+    }
+}
+
+/// One shutdown signal shared by every stop path. The first trigger wins;
+/// later ones are ignored.
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    tx: watch::Sender<Option<Reason>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self { tx: watch::Sender::new(None) }
+    }
+
+    /// Start shutting down. Returns false if a shutdown was already under way.
+    pub fn trigger(&self, reason: Reason) -> bool {
+        self.tx.send_if_modified(|current| {
+            if current.is_some() {
+                return false;
+            }
+            *current = Some(reason);
+            true
+        })
+    }
+
+    /// The reason, once triggered
+    pub fn reason(&self) -> Option<Reason> {
+        *self.tx.borrow()
+    }
+
+    /// Wait until a shutdown is triggered
+    pub async fn wait(&self) -> Reason {
+        let mut rx = self.tx.subscribe();
+        loop {
+            if let Some(reason) = *rx.borrow_and_update() {
+                return reason;
+            }
+            // The sender lives in self, so this can't fail while we're waiting
+            let _ = rx.changed().await;
+        }
+    }
+}
+
+/// Turn a message on the tray's std quit channel into a shutdown. A blocking
+/// thread waits on it, so nothing polls.
+pub fn bridge_tray_quit(quit_rx: std::sync::mpsc::Receiver<()>, shutdown: Shutdown) {
+    std::thread::spawn(move || {
+        // recv() fails once the tray exits and drops its sender
+        if quit_rx.recv().is_ok() {
+            shutdown.trigger(Reason::TrayQuit);
+        }
+    });
+}
+
+/// Trigger a shutdown on SIGINT or SIGTERM (runs until one arrives)
+pub async fn listen_for_signals(shutdown: Shutdown) {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let (Ok(mut interrupt), Ok(mut terminate)) = (signal(SignalKind::interrupt()), signal(SignalKind::terminate())) else {
+            tracing::warn!("Failed to install signal handlers");
+            return;
+        };
+        let reason = tokio::select! {
+            _ = interrupt.recv() => Reason::Interrupt,
+            _ = terminate.recv() => Reason::Terminate,
+        };
+        shutdown.trigger(reason);
+    }
+    #[cfg(not(unix))]
+    {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            shutdown.trigger(Reason::Interrupt);
+        }
+    }
+}
+
+/// Drive `work` to completion. `work` has to stop by itself once `shutdown`
+/// triggers; after that it gets at most `grace` to finish, and None means it
+/// didn't.
+pub async fn run_until<F: Future>(shutdown: &Shutdown, work: F, grace: Duration) -> Option<F::Output> {
+    tokio::pin!(work);
+    tokio::select! {
+        output = &mut work => return Some(output),
+        _ = shutdown.wait() => {}
+    }
+    tokio::time::timeout(grace, work).await.ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_trigger_wins() {
+        let shutdown = Shutdown::new();
+        assert_eq!(shutdown.reason(), None);
+        assert!(shutdown.trigger(Reason::Terminate));
+        assert!(!shutdown.clone().trigger(Reason::Interrupt));
+        assert!(!shutdown.trigger(Reason::TrayQuit));
+        assert_eq!(shutdown.reason(), Some(Reason::Terminate));
+    }
+
+    #[tokio::test]
+    async fn wait_returns_the_reason() {
+        let shutdown = Shutdown::new();
+        let waiter = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.wait().await }
+        });
+        tokio::task::yield_now().await;
+        shutdown.trigger(Reason::TrayQuit);
+        assert_eq!(waiter.await.unwrap(), Reason::TrayQuit);
+
+        // Already triggered: returns at once, with the first reason
+        shutdown.trigger(Reason::Interrupt);
+        let reason = tokio::time::timeout(Duration::from_millis(100), shutdown.wait()).await;
+        assert_eq!(reason.ok(), Some(Reason::TrayQuit));
+    }
+
+    #[tokio::test]
+    async fn run_until_finishes_work_within_grace() {
+        let shutdown = Shutdown::new();
+        // Work that ends on its own before any shutdown
+        assert_eq!(run_until(&shutdown, async { 7 }, Duration::from_millis(10)).await, Some(7));
+
+        // Work that needs a moment after the trigger, inside the grace period
+        shutdown.trigger(Reason::Terminate);
+        let work = async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            "done"
+        };
+        assert_eq!(run_until(&shutdown, work, Duration::from_secs(5)).await, Some("done"));
+    }
+
+    #[tokio::test]
+    async fn run_until_gives_up_after_grace() {
+        let shutdown = Shutdown::new();
+        shutdown.trigger(Reason::Interrupt);
+        let started = std::time::Instant::now();
+        let stuck = std::future::pending::<()>();
+        assert_eq!(run_until(&shutdown, stuck, Duration::from_millis(50)).await, None);
+        assert!(started.elapsed() >= Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,266 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Datelike, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Monthly files kept; older ones are deleted when a new month starts
+const RETENTION_MONTHS: i32 = 12;
+
+/// Rows shown per section of a report
+const TOP_N: usize = 10;
+
+/// One completed run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub ts: DateTime<Local>,
+    pub channel_id: u64,
+    pub user_id: u64,
+    pub user_name: String,
+    pub backend: String,
+    /// "completed", "failed", "cancelled", ...
+    pub outcome: String,
+    pub duration_ms: u64,
+    /// Token counts and cost, when the backend reports them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_tokens: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_tokens: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
+}
+
+/// Directory holding monthly stats files
+fn stats_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("neywa")
+        .join("stats")
+}
+
+/// Stats file for a given month (rotated monthly: stats-YYYY-MM.jsonl)
+fn stats_file_for(date: NaiveDate) -> PathBuf {
+    stats_dir().join(format!("stats-{}.jsonl", date.format("%Y-%m")))
+}
+
+/// Serializes writers
+static WRITER: Mutex<()> = Mutex::new(());
+
+/// Month index (year * 12 + month) of a `stats-YYYY-MM.jsonl` file name
+fn file_month(name: &str) -> Option<i32> {
+    let stem = name.strip_prefix("stats-")?.strip_suffix(".jsonl")?;
+    let (year, month) = stem.split_once('-')?;
+    Some(year.parse::<i32>().ok()? * 12 + month.parse::<i32>().ok()?)
+}
+
+/// Delete monthly files older than the retention window
+fn remove_expired(today: NaiveDate) {
+    let current = today.year() * 12 + today.month() as i32;
+    let Ok(entries) = std::fs::read_dir(stats_dir()) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if file_month(&name).is_some_and(|month| current - month >= RETENTION_MONTHS) {
+            if let Err(e) = std::fs::remove_file(entry.path()) {
+                tracing::warn!("Failed to remove old stats file {}: {}", name, e);
+            }
+        }
+    }
+}
+
+/// Append a record synchronously
+fn append(record: &RunRecord) -> Result<()> {
+    let path = stats_file_for(record.ts.date_naive());
+    let _guard = WRITER.lock().unwrap_or_else(|e| e.into_inner());
+
+    let new_month = !path.exists();
+    std::fs::create_dir_all(stats_dir())?;
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open stats log {:?}", path))?;
+    writeln!(file, "{}", serde_json::to_string(record)?)?;
+
+    if new_month {
+        remove_expired(record.ts.date_naive());
+    }
+    Ok(())
+}
+
+/// Record a run in the background. Best-effort, like the audit log.
+pub fn record(record: RunRecord) {
+    let write = move || {
+        if let Err(e) = append(&record) {
+            tracing::warn!("Failed to write stats: {}", e);
+        }
+    };
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) => {
+            handle.spawn_blocking(write);
+        }
+        Err(_) => write(),
+    }
+}
+
+/// Reporting window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    /// Since local midnight
+    Day,
+    /// Today and the six days before
+    Week,
+}
+
+impl Period {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "" | "day" | "today" => Some(Period::Day),
+            "week" => Some(Period::Week),
+            _ => None,
+        }
+    }
+
+    fn first_day(&self, today: NaiveDate) -> NaiveDate {
+        match self {
+            Period::Day => today,
+            Period::Week => today - chrono::Duration::days(6),
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            Period::Day => "today",
+            Period::Week => "last 7 days",
+        }
+    }
+}
+
+/// Records from `first_day` on (only the months involved are read)
+fn records_since(first_day: NaiveDate, today: NaiveDate) -> Vec<RunRecord> {
+    let mut months = vec![stats_file_for(first_day)];
+    let current = stats_file_for(today);
+    if !months.contains(&current) {
+        months.push(current);
+    }
+    months
+        .iter()
+        .filter_map(|path| std::fs::read_to_string(path).ok())
+        .flat_map(|content| {
+            content
+                .lines()
+                .filter_map(|line| serde_json::from_str::<RunRecord>(line).ok())
+                .collect::<Vec<_>>()
+        })
+        .filter(|r| r.ts.date_naive() >= first_day)
+        .collect()
+}
+
+/// Totals over a set of runs
+#[derive(Debug, Clone, Copy, Default)]
+struct Rollup {
+    runs: usize,
+    duration_ms: u64,
+    tokens: u64,
+    cost_usd: f64,
+}
+
+impl Rollup {
+    fn add(&mut self, r: &RunRecord) {
+        self.runs += 1;
+        self.duration_ms += r.duration_ms;
+        self.tokens += r.input_tokens.unwrap_or(0) + r.output_tokens.unwrap_or(0);
+        self.cost_usd += r.cost_usd.unwrap_or(0.0);
+    }
+
+    fn describe(&self) -> String {
+        let mut parts = vec![
+            format!("{} run{}", self.runs, if self.runs == 1 { "" } else { "s" }),
+            format_duration(self.duration_ms),
+        ];
+        if self.tokens > 0 {
+            parts.push(format!("{} tokens", format_tokens(self.tokens)));
+        }
+        if self.cost_usd > 0.0 {
+            parts.push(format!("${:.2}", self.cost_usd));
+        }
+        parts.join(", ")
+    }
+}
+
+/// "1h 23m", "4m 10s", "12s"
+fn format_duration(ms: u64) -> String {
+    let secs = ms / 1000;
+    if secs >= 3600 {
+        format!("{}h {}m", secs / 3600, secs % 3600 / 60)
+    } else if secs >= 60 {
+        format!("{}m {}s", secs / 60, secs % 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+/// "1.2M", "34k", "950"
+fn format_tokens(tokens: u64) -> String {
+    if tokens >= 1_000_000 {
+        format!("{:.1}M", tokens as f64 / 1_000_000.0)
+    } else if tokens >= 1_000 {
+        format!("{}k", tokens / 1_000)
+    } else {
+        tokens.to_string()
+    }
+}
+
+/// Roll records up by key, busiest (by wall-clock time) first
+fn rollup_by<K: std::hash::Hash + Eq>(records: &[RunRecord], key: impl Fn(&RunRecord) -> K) -> Vec<(K, Rollup)> {
+    let mut groups: HashMap<K, Rollup> = HashMap::new();
+    for r in records {
+        groups.entry(key(r)).or_default().add(r);
+    }
+    let mut groups: Vec<(K, Rollup)> = groups.into_iter().collect();
+    groups.sort_by_key(|(_, rollup)| std::cmp::Reverse(rollup.duration_ms));
+    groups
+}
+
+/// Report for a period: totals, then per day (weekly only), per channel and
+/// per user. `channel_label` renders a channel ID (a mention on Discord).
+pub fn report(period: Period, channel_label: impl Fn(u64) -> String) -> String {
+    let today = Local::now().date_naive();
+    let records = records_since(period.first_day(today), today);
+    if records.is_empty() {
+        return format!("📈 No runs recorded {}.", period.title());
+    }
+
+    let mut total = Rollup::default();
+    for r in &records {
+        total.add(r);
+    }
+    let mut lines = vec![
+        format!("📈 **Stats — {}**", period.title()),
+        format!("Total: {}", total.describe()),
+    ];
+
+    if period == Period::Week {
+        lines.push("\n**By day**".to_string());
+        let mut days = rollup_by(&records, |r| r.ts.date_naive());
+        days.sort_by_key(|(day, _)| *day);
+        for (day, rollup) in days {
+            lines.push(format!("{}: {}", day.format("%a %m-%d"), rollup.describe()));
+        }
+    }
+
+    lines.push("\n**By channel**".to_string());
+    for (channel, rollup) in rollup_by(&records, |r| r.channel_id).into_iter().take(TOP_N) {
+        lines.push(format!("{}: {}", channel_label(channel), rollup.describe()));
+    }
+
+    lines.push("\n**By user**".to_string());
+    let names: HashMap<u64, &str> = records.iter().map(|r| (r.user_id, r.user_name.as_str())).collect();
+    for (user, rollup) in rollup_by(&records, |r| r.user_id).into_iter().take(TOP_N) {
+        lines.push(format!("{}: {}", names.get(&user).copied().unwrap_or("unknown"), rollup.describe()));
+    }
+    lines.join("\n")
+}
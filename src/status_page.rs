@@ -0,0 +1,161 @@
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Method, Request, Response, Server, StatusCode};
+use serde::Serialize;
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Arc;
+
+use crate::config::Config;
+
+/// Errors listed on the page
+pub const RECENT_ERRORS: usize = 5;
+
+/// One channel with a running task or queued messages
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelStatus {
+    pub channel_id: u64,
+    /// "Processing X's request: …" line, None when idle
+    pub processing: Option<String>,
+    pub queued: usize,
+}
+
+/// Everything the status page shows, built fresh for every request
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusSnapshot {
+    pub version: String,
+    /// "Up 2d 4h, last restart: self-update"
+    pub uptime: Option<String>,
+    pub discord_connected: bool,
+    pub channels: Vec<ChannelStatus>,
+    /// Today's failed runs, newest first ("14:02 #123 user")
+    pub recent_errors: Vec<String>,
+    /// Today's activity summary (requests, errors, cost)
+    pub usage_today: String,
+}
+
+impl StatusSnapshot {
+    /// Plain read-only HTML view of the snapshot
+    pub fn to_html(&self) -> String {
+        let mut channels = String::new();
+        for channel in &self.channels {
+            channels.push_str(&format!(
+                "<li>{}: {} ({} queued)</li>",
+                channel.channel_id,
+                escape(channel.processing.as_deref().unwrap_or("idle")),
+                channel.queued
+            ));
+        }
+        if channels.is_empty() {
+            channels.push_str("<li>All channels idle</li>");
+        }
+        let errors = if self.recent_errors.is_empty() {
+            "<li>None today</li>".to_string()
+        } else {
+            self.recent_errors.iter().map(|e| format!("<li>{}</li>", escape(e))).collect()
+        };
+        format!(
+            "<!doctype html><html><head><meta charset=\"utf-8\"><meta name=\"viewport\" content=\"width=device-width\">\
+            <meta http-equiv=\"refresh\" content=\"30\"><title>Neywa status</title>\
+            <style>body{{font-family:sans-serif;max-width:40em;margin:1em auto;padding:0 1em}}</style></head><body>\
+            <h1>Neywa v{}</h1><p>{}<br>Discord: {}</p>\
+            <h2>Channels</h2><ul>{}</ul><h2>Recent errors</h2><ul>{}</ul>\
+            <h2>Today</h2><pre>{}</pre></body></html>",
+            escape(&self.version),
+            escape(self.uptime.as_deref().unwrap_or("Uptime unknown")),
+            if self.discord_connected { "connected" } else { "disconnected" },
+            channels,
+            errors,
+            escape(&self.usage_today)
+        )
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Builds a snapshot from the bot's shared state
+pub type SnapshotFn = Arc<dyn Fn() -> Pin<Box<dyn Future<Output = StatusSnapshot> + Send>> + Send + Sync>;
+
+/// Where to listen, from config. None when the page is off or misconfigured.
+/// Binding beyond localhost requires `status_token`.
+pub fn bind_address(config: &Config) -> Option<SocketAddr> {
+    let port = config.status_port?;
+    if !config.status_bind_all {
+        return Some(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port));
+    }
+    if config.status_token.as_deref().is_none_or(|t| t.trim().is_empty()) {
+        tracing::error!("Status page not started: status_bind_all needs a status_token");
+        return None;
+    }
+    Some(SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port))
+}
+
+/// Whether a request carries the configured bearer token (always true without one)
+fn authorized(request: &Request<Body>, token: Option<&str>) -> bool {
+    let Some(token) = token else {
+        return true;
+    };
+    request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|given| given.trim() == token)
+}
+
+async fn handle(request: Request<Body>, token: Option<Arc<str>>, snapshot: SnapshotFn) -> Response<Body> {
+    let respond = |status: StatusCode, content_type: &str, body: String| {
+        Response::builder()
+            .status(status)
+            .header(header::CONTENT_TYPE, content_type)
+            .header(header::CACHE_CONTROL, "no-store")
+            .body(Body::from(body))
+            .unwrap_or_default()
+    };
+
+    if request.method() != Method::GET {
+        return respond(StatusCode::METHOD_NOT_ALLOWED, "text/plain", "Read-only\n".to_string());
+    }
+    if !authorized(&request, token.as_deref()) {
+        return respond(StatusCode::UNAUTHORIZED, "text/plain", "Bearer token required\n".to_string());
+    }
+    match request.uri().path() {
+        "/" => respond(StatusCode::OK, "text/html; charset=utf-8", snapshot().await.to_html()),
+        "/status.json" => match serde_json::to_string_pretty(&snapshot().await) {
+            Ok(json) => respond(StatusCode::OK, "application/json", json),
+            Err(e) => respond(StatusCode::INTERNAL_SERVER_ERROR, "text/plain", format!("{}\n", e)),
+        },
+        _ => respond(StatusCode::NOT_FOUND, "text/plain", "Not found\n".to_string()),
+    }
+}
+
+/// Serve `/` (HTML) and `/status.json` until the process exits
+pub async fn serve(addr: SocketAddr, token: Option<String>, snapshot: SnapshotFn) {
+    let token: Option<Arc<str>> = token.filter(|t| !t.trim().is_empty()).map(|t| Arc::from(t.trim()));
+    let make_service = make_service_fn(move |_conn| {
+        let token = token.clone();
+        let snapshot = snapshot.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let token = token.clone();
+                let snapshot = snapshot.clone();
+                async move { Ok::<_, Infallible>(handle(request, token, snapshot).await) }
+            }))
+        }
+    });
+
+    let server = match Server::try_bind(&addr) {
+        Ok(builder) => builder.serve(make_service),
+        Err(e) => {
+            tracing::error!("Status page failed to bind {}: {}", addr, e);
+            return;
+        }
+    };
+    tracing::info!("Status page listening on http://{}", addr);
+    if let Err(e) = server.await {
+        tracing::error!("Status page stopped: {}", e);
+    }
+}
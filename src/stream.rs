@@ -0,0 +1,303 @@
+use std::collections::HashMap;
+
+use crate::claude::{self, RunUsage, StreamEvent};
+
+/// Turns Claude Code `--output-format stream-json` lines into stream events.
+/// Pure: the spawn path feeds it child stdout, `replay` feeds it captured lines.
+#[derive(Debug, Default)]
+pub struct ClaudeParser {
+    /// Plan mode also reports plan file writes and explored files/searches
+    plan: bool,
+    full_text: String,
+    session_id_sent: bool,
+    /// tool_use id -> status label, to match results back to their line
+    tool_labels: HashMap<String, String>,
+}
+
+impl ClaudeParser {
+    pub fn new(plan: bool) -> Self {
+        Self { plan, ..Default::default() }
+    }
+
+    /// Events produced by one line of output (non-JSON lines produce none)
+    pub fn parse_line(&mut self, line: &str) -> Vec<StreamEvent> {
+        let mut events = Vec::new();
+        let Ok(json) = serde_json::from_str::<serde_json::Value>(line) else {
+            return events;
+        };
+
+        if !self.session_id_sent {
+            if let Some(sid) = json.get("session_id").and_then(|v| v.as_str()) {
+                events.push(StreamEvent::SessionId(sid.to_string()));
+                self.session_id_sent = true;
+            }
+        }
+
+        match json.get("type").and_then(|v| v.as_str()) {
+            Some("assistant") => {
+                let blocks = json
+                    .get("message")
+                    .and_then(|m| m.get("content"))
+                    .and_then(|c| c.as_array());
+                for item in blocks.into_iter().flatten() {
+                    match item.get("type").and_then(|v| v.as_str()) {
+                        Some("tool_use") => self.tool_use(item, &mut events),
+                        Some("text") => {
+                            if let Some(text) = item.get("text").and_then(|v| v.as_str()) {
+                                // Text from several assistant events is joined
+                                if !self.full_text.is_empty() {
+                                    self.full_text.push('\n');
+                                }
+                                self.full_text.push_str(text);
+                                events.push(StreamEvent::Text(self.full_text.clone()));
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Some("user") if !self.plan => {
+                // Tool results come back as user messages
+                let blocks = json
+                    .get("message")
+                    .and_then(|m| m.get("content"))
+                    .and_then(|c| c.as_array());
+                for item in blocks.into_iter().flatten() {
+                    if item.get("type").and_then(|v| v.as_str()) != Some("tool_result") {
+                        continue;
+                    }
+                    let Some(tool) = item
+                        .get("tool_use_id")
+                        .and_then(|v| v.as_str())
+                        .and_then(|id| self.tool_labels.remove(id))
+                    else {
+                        continue;
+                    };
+                    let (ok, summary) = claude::tool_result_outcome(item);
+                    events.push(StreamEvent::ToolResult { tool, ok, summary });
+                }
+            }
+            Some("result") => {
+                // Use the final result if present (plan runs may end with an
+                // empty one after ExitPlanMode is denied), otherwise keep the text
+                if let Some(result) = json.get("result").and_then(|v| v.as_str()) {
+                    if !result.is_empty() {
+                        self.full_text = result.to_string();
+                        events.push(StreamEvent::Text(self.full_text.clone()));
+                    }
+                }
+                if let Some(usage) = Self::run_usage(&json) {
+                    events.push(StreamEvent::Usage(usage));
+                }
+                events.push(StreamEvent::Done);
+            }
+            _ => {}
+        }
+        events
+    }
+
+    /// Cost and token counts of a `result` event (None if it reports no cost)
+    fn run_usage(json: &serde_json::Value) -> Option<RunUsage> {
+        let cost_usd = json.get("total_cost_usd").or_else(|| json.get("cost_usd"))?.as_f64()?;
+        let usage = json.get("usage");
+        let tokens = |name: &str| usage.and_then(|u| u.get(name)).and_then(|v| v.as_u64()).unwrap_or(0);
+        Some(RunUsage {
+            cost_usd,
+            input_tokens: tokens("input_tokens") + tokens("cache_creation_input_tokens") + tokens("cache_read_input_tokens"),
+            output_tokens: tokens("output_tokens"),
+        })
+    }
+
+    fn tool_use(&mut self, item: &serde_json::Value, events: &mut Vec<StreamEvent>) {
+        let tool_name = item.get("name").and_then(|v| v.as_str()).unwrap_or("unknown");
+        let input = item.get("input");
+
+        if self.plan {
+            // Capture the plan file write
+            if tool_name == "Write" {
+                let file_path = input.and_then(|i| i.get("file_path")).and_then(|v| v.as_str()).unwrap_or("");
+                let plan_content = input.and_then(|i| i.get("content")).and_then(|v| v.as_str()).unwrap_or("");
+                if file_path.contains("/.claude/plans/") && !plan_content.is_empty() {
+                    events.push(StreamEvent::PlanContent(file_path.to_string(), plan_content.to_string()));
+                }
+            }
+
+            // Report what the plan run looked at for the final summary
+            if matches!(tool_name, "Read" | "Grep" | "Glob") {
+                let key = if tool_name == "Read" { "file_path" } else { "pattern" };
+                if let Some(target) = input.and_then(|i| i.get(key)).and_then(|v| v.as_str()) {
+                    events.push(StreamEvent::Explored(tool_name.to_string(), target.to_string()));
+                }
+            }
+        }
+
+        let input_str = input
+            .map(|v| claude::format_tool_input(tool_name, v))
+            .unwrap_or_default();
+        if !self.plan {
+            if let Some(id) = item.get("id").and_then(|v| v.as_str()) {
+                self.tool_labels.insert(id.to_string(), claude::tool_label(tool_name, &input_str));
+            }
+        }
+        events.push(StreamEvent::ToolUse(tool_name.to_string(), input_str));
+    }
+
+    /// Whether stderr reports a context overflow
+    pub fn is_context_overflow(stderr: &str) -> bool {
+        let lower = stderr.to_lowercase();
+        lower.contains("prompt is too long") || lower.contains("context window") || lower.contains("too many tokens")
+    }
+
+    /// Whether stderr says the `--resume` session no longer exists, e.g.
+    /// `No conversation found with session ID: 7f3c2a9e-…` after `~/.claude/projects` was cleaned
+    pub fn is_missing_session(stderr: &str) -> bool {
+        let lower = stderr.to_lowercase();
+        lower.contains("no conversation found") || lower.contains("session not found")
+    }
+
+    /// Events for a captured run, ending with the `Done` sent when stdout closes
+    pub fn replay(lines: &[&str], plan: bool) -> Vec<StreamEvent> {
+        let mut parser = Self::new(plan);
+        let mut events: Vec<StreamEvent> = lines.iter().flat_map(|line| parser.parse_line(line)).collect();
+        events.push(StreamEvent::Done);
+        events
+    }
+}
+
+/// Turns `codex exec --json` lines into stream events
+#[derive(Debug, Default)]
+pub struct CodexParser {
+    full_text: String,
+    session_id_sent: bool,
+}
+
+impl CodexParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Events produced by one line of output (non-JSON lines produce none)
+    pub fn parse_line(&mut self, line: &str) -> Vec<StreamEvent> {
+        let mut events = Vec::new();
+        let Ok(json) = serde_json::from_str::<serde_json::Value>(line) else {
+            return events;
+        };
+        let item = json.get("item");
+        let item_type = item
+            .and_then(|i| i.get("type"))
+            .and_then(|v| v.as_str())
+            .unwrap_or("");
+        let field = |key: &str| item.and_then(|i| i.get(key)).and_then(|v| v.as_str());
+
+        match json.get("type").and_then(|v| v.as_str()).unwrap_or("") {
+            // Thread started → the thread ID is the session ID
+            "thread.started" if !self.session_id_sent => {
+                if let Some(tid) = json.get("thread_id").and_then(|v| v.as_str()) {
+                    events.push(StreamEvent::SessionId(tid.to_string()));
+                    self.session_id_sent = true;
+                }
+            }
+
+            // Item started → tool use beginning
+            "item.started" => match item_type {
+                "command_execution" => {
+                    let short: String = field("command").unwrap_or("...").chars().take(50).collect();
+                    events.push(StreamEvent::ToolUse("Bash".to_string(), format!("💻 {}", short)));
+                }
+                "file_read" => {
+                    let path = field("file_path").unwrap_or("...");
+                    events.push(StreamEvent::ToolUse(
+                        "Read".to_string(),
+                        format!("📖 {}", claude::shorten_path(path)),
+                    ));
+                }
+                _ => {}
+            },
+
+            // Item completed → process result
+            "item.completed" => match item_type {
+                "agent_message" => {
+                    let item = item.unwrap_or(&serde_json::Value::Null);
+                    match item.get("content") {
+                        Some(serde_json::Value::Array(parts)) => {
+                            for text in parts.iter().filter_map(|p| p.get("text").and_then(|v| v.as_str())) {
+                                self.push_text(text);
+                            }
+                        }
+                        Some(serde_json::Value::String(text)) => self.push_text(text),
+                        _ => {}
+                    }
+                    // Also check for a top-level text field
+                    if let Some(text) = item.get("text").and_then(|v| v.as_str()) {
+                        self.push_text(text);
+                    }
+                    if !self.full_text.is_empty() {
+                        events.push(StreamEvent::Text(self.full_text.clone()));
+                    }
+                }
+                "command_execution" => {
+                    let short: String = field("command").unwrap_or("...").chars().take(50).collect();
+                    events.push(StreamEvent::ToolUse("Bash".to_string(), format!("💻 {} ✓", short)));
+                }
+                "file_changes" => {
+                    let file = field("file_path").unwrap_or("files");
+                    events.push(StreamEvent::ToolUse(
+                        "Edit".to_string(),
+                        format!("✏️ {}", claude::shorten_path(file)),
+                    ));
+                }
+                "web_searches" => {
+                    let query = field("query").unwrap_or("search");
+                    events.push(StreamEvent::ToolUse(
+                        "WebSearch".to_string(),
+                        format!("🌐 {}", claude::truncate_str(query, 40)),
+                    ));
+                }
+                "mcp_tool_calls" => {
+                    let tool = field("tool_name").unwrap_or("tool");
+                    events.push(StreamEvent::ToolUse("MCP".to_string(), format!("🔌 {}", tool)));
+                }
+                // Internal reasoning and anything else is ignored
+                _ => {}
+            },
+
+            "turn.completed" => events.push(StreamEvent::Done),
+
+            "turn.failed" => {
+                let error_msg = json
+                    .get("error")
+                    .and_then(|v| v.as_str())
+                    .or_else(|| json.get("message").and_then(|v| v.as_str()))
+                    .unwrap_or("Unknown error");
+                events.push(StreamEvent::Error(error_msg.to_string()));
+            }
+
+            _ => {}
+        }
+        events
+    }
+
+    fn push_text(&mut self, text: &str) {
+        if !self.full_text.is_empty() {
+            self.full_text.push('\n');
+        }
+        self.full_text.push_str(text);
+    }
+
+    /// Whether stderr reports a context overflow
+    pub fn is_context_overflow(stderr: &str) -> bool {
+        let lower = stderr.to_lowercase();
+        lower.contains("context window")
+            || lower.contains("too many tokens")
+            || lower.contains("prompt is too long")
+            || lower.contains("max_tokens")
+    }
+
+    /// Events for a captured run, ending with the `Done` sent when stdout closes
+    pub fn replay(lines: &[&str]) -> Vec<StreamEvent> {
+        let mut parser = Self::new();
+        let mut events: Vec<StreamEvent> = lines.iter().flat_map(|line| parser.parse_line(line)).collect();
+        events.push(StreamEvent::Done);
+        events
+    }
+}
@@ -0,0 +1,113 @@
+use std::time::{Duration, Instant};
+
+/// A stage of handling one message, from queueing to the last Discord send
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Enqueue to dequeue, including the coalescing window
+    QueueWait,
+    /// Downloading attachments
+    Attachments,
+    /// Starting the claude/codex process
+    Spawn,
+    /// Spawn to the first stream event (model latency)
+    FirstEvent,
+    /// Spawn to the end of the stream
+    Stream,
+    /// Posting files, response chunks and the completion message
+    DiscordSend,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 6] = [
+        Phase::QueueWait,
+        Phase::Attachments,
+        Phase::Spawn,
+        Phase::FirstEvent,
+        Phase::Stream,
+        Phase::DiscordSend,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::QueueWait => "queue_wait",
+            Phase::Attachments => "attachments",
+            Phase::Spawn => "spawn",
+            Phase::FirstEvent => "first_event",
+            Phase::Stream => "stream",
+            Phase::DiscordSend => "discord_send",
+        }
+    }
+}
+
+/// Per-phase durations for one run. Recording a phase twice adds up (e.g. a
+/// retry after compaction spawns again).
+#[derive(Debug, Clone, Default)]
+pub struct PhaseTimings {
+    phases: Vec<(Phase, Duration)>,
+}
+
+impl PhaseTimings {
+    pub fn record(&mut self, phase: Phase, duration: Duration) {
+        match self.phases.iter_mut().find(|(p, _)| *p == phase) {
+            Some((_, total)) => *total += duration,
+            None => self.phases.push((phase, duration)),
+        }
+    }
+
+    /// Record the time since `start`
+    pub fn record_since(&mut self, phase: Phase, start: Instant) {
+        self.record(phase, start.elapsed());
+    }
+
+    pub fn get(&self, phase: Phase) -> Option<Duration> {
+        self.phases.iter().find(|(p, _)| *p == phase).map(|(_, d)| *d)
+    }
+
+    /// Recorded phases in pipeline order
+    pub fn iter(&self) -> impl Iterator<Item = (Phase, Duration)> + '_ {
+        Phase::ALL.into_iter().filter_map(|phase| self.get(phase).map(|d| (phase, d)))
+    }
+
+    /// One line for logs: "queue_wait=0.2s spawn=0.1s stream=12.4s"
+    pub fn summary(&self) -> String {
+        self.iter()
+            .map(|(phase, d)| format!("{}={:.1}s", phase.name(), d.as_secs_f64()))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn repeated_phases_add_up() {
+        let mut timings = PhaseTimings::default();
+        assert_eq!(timings.get(Phase::Spawn), None);
+        timings.record(Phase::Spawn, ms(100));
+        timings.record(Phase::Stream, ms(4000));
+        // A retry after compaction spawns and streams again
+        timings.record(Phase::Spawn, ms(50));
+        timings.record(Phase::Stream, ms(1000));
+        assert_eq!(timings.get(Phase::Spawn), Some(ms(150)));
+        assert_eq!(timings.get(Phase::Stream), Some(ms(5000)));
+        assert_eq!(timings.get(Phase::QueueWait), None);
+    }
+
+    #[test]
+    fn phases_are_listed_in_pipeline_order() {
+        let mut timings = PhaseTimings::default();
+        timings.record(Phase::DiscordSend, ms(300));
+        timings.record(Phase::QueueWait, ms(200));
+        timings.record(Phase::Stream, ms(12_400));
+        let phases: Vec<Phase> = timings.iter().map(|(phase, _)| phase).collect();
+        assert_eq!(phases, vec![Phase::QueueWait, Phase::Stream, Phase::DiscordSend]);
+        assert_eq!(timings.summary(), "queue_wait=0.2s stream=12.4s discord_send=0.3s");
+        assert_eq!(PhaseTimings::default().summary(), "");
+    }
+}
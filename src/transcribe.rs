@@ -0,0 +1,97 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+
+use crate::claude;
+
+/// Longest a single transcription may take
+const TRANSCRIBE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Shown when a voice message arrives and no transcriber is available
+pub const INSTALL_HINT: &str = "🎙️ Can't transcribe voice messages: no transcriber found. \
+Install OpenAI Whisper (`pip install openai-whisper`) or set `transcribe_command` in the config \
+(e.g. `\"whisper-cli -m ~/models/ggml-base.bin -nt -f {file}\"`).";
+
+/// How voice messages are turned into text
+#[derive(Debug, Clone)]
+pub enum Transcriber {
+    /// `transcribe_command` from the config; `{file}` is replaced with the audio path
+    /// and the transcript is read from stdout
+    Command(String),
+    /// The `whisper` CLI (openai-whisper), which writes a .txt next to its other outputs
+    Whisper(PathBuf),
+}
+
+/// The configured command, else `whisper` on the PATH
+pub fn find(command: Option<&str>) -> Option<Transcriber> {
+    if let Some(command) = command.map(str::trim).filter(|c| !c.is_empty()) {
+        return Some(Transcriber::Command(command.to_string()));
+    }
+    claude::find_cli("whisper").map(Transcriber::Whisper)
+}
+
+/// Quote a path for `sh -c`
+fn shell_quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', r"'\''"))
+}
+
+/// Transcribe one audio file
+pub async fn transcribe(transcriber: &Transcriber, audio: &Path) -> Result<String> {
+    let text = tokio::time::timeout(TRANSCRIBE_TIMEOUT, run(transcriber, audio))
+        .await
+        .context("Transcription timed out")??;
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.is_empty() {
+        anyhow::bail!("No speech recognized");
+    }
+    Ok(text)
+}
+
+async fn run(transcriber: &Transcriber, audio: &Path) -> Result<String> {
+    match transcriber {
+        Transcriber::Command(template) => {
+            let command = if template.contains("{file}") {
+                template.replace("{file}", &shell_quote(audio))
+            } else {
+                format!("{} {}", template, shell_quote(audio))
+            };
+            let output = Command::new("sh")
+                .arg("-c")
+                .arg(&command)
+                .stdin(Stdio::null())
+                .kill_on_drop(true)
+                .output()
+                .await
+                .context("Failed to run transcribe_command")?;
+            if !output.status.success() {
+                anyhow::bail!("transcribe_command failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+            }
+            Ok(String::from_utf8_lossy(&output.stdout).to_string())
+        }
+        Transcriber::Whisper(cli) => {
+            let out_dir = audio.with_extension("transcript");
+            tokio::fs::create_dir_all(&out_dir).await?;
+            let output = Command::new(cli)
+                .arg(audio)
+                .args(["--model", "base", "--output_format", "txt", "--verbose", "False", "--fp16", "False"])
+                .arg("--output_dir")
+                .arg(&out_dir)
+                .stdin(Stdio::null())
+                .kill_on_drop(true)
+                .output()
+                .await
+                .context("Failed to run whisper")?;
+            if !output.status.success() {
+                anyhow::bail!("whisper failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+            }
+            let stem = audio.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+            let text = tokio::fs::read_to_string(out_dir.join(format!("{}.txt", stem)))
+                .await
+                .context("whisper wrote no transcript")?;
+            let _ = tokio::fs::remove_dir_all(&out_dir).await;
+            Ok(text)
+        }
+    }
+}
@@ -0,0 +1,413 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDate};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::claude::{self, AiBackend};
+
+/// A session converted to markdown
+pub struct Transcript {
+    pub markdown: String,
+    /// Number of user + assistant messages
+    pub message_count: usize,
+    pub first_ts: Option<DateTime<Local>>,
+    pub last_ts: Option<DateTime<Local>>,
+}
+
+/// Locate a Claude Code session file under ~/.claude/projects/*/<session_id>.jsonl
+pub fn find_claude_session_file(session_id: &str) -> Option<PathBuf> {
+    let projects = dirs::home_dir()?.join(".claude/projects");
+    let file_name = format!("{}.jsonl", session_id);
+    std::fs::read_dir(&projects)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path().join(&file_name))
+        .find(|path| path.is_file())
+}
+
+/// Claude Code's project folder for a working directory: ~/.claude/projects/
+/// plus the path with every non-alphanumeric character replaced by `-`
+pub fn claude_project_dir(cwd: &Path) -> Option<PathBuf> {
+    let encoded: String = cwd
+        .to_string_lossy()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    Some(dirs::home_dir()?.join(".claude/projects").join(encoded))
+}
+
+/// Locate a session file, looking in the project folder for `cwd` first
+pub fn find_claude_session_file_in(session_id: &str, cwd: Option<&Path>) -> Option<PathBuf> {
+    cwd.and_then(claude_project_dir)
+        .map(|dir| dir.join(format!("{}.jsonl", session_id)))
+        .filter(|path| path.is_file())
+        .or_else(|| find_claude_session_file(session_id))
+}
+
+/// The directory a Claude Code session was started in, from the `cwd` its
+/// transcript records. `--resume` only finds the session from that directory.
+pub fn claude_session_cwd(session_id: &str) -> Option<PathBuf> {
+    let content = std::fs::read_to_string(find_claude_session_file(session_id)?).ok()?;
+    content
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .find_map(|json| json.get("cwd")?.as_str().map(PathBuf::from))
+}
+
+/// Locate a Codex thread transcript under ~/.codex/sessions (rollout-*-<thread_id>.jsonl)
+pub fn find_codex_session_file(thread_id: &str) -> Option<PathBuf> {
+    let root = dirs::home_dir()?.join(".codex/sessions");
+    find_file_containing(&root, thread_id, 4)
+}
+
+fn find_file_containing(dir: &Path, needle: &str, depth: usize) -> Option<PathBuf> {
+    for entry in std::fs::read_dir(dir).ok()?.flatten() {
+        let path = entry.path();
+        if path.is_dir() && depth > 0 {
+            if let Some(found) = find_file_containing(&path, needle, depth - 1) {
+                return Some(found);
+            }
+        } else if path
+            .file_name()
+            .map(|n| n.to_string_lossy().contains(needle))
+            .unwrap_or(false)
+        {
+            return Some(path);
+        }
+    }
+    None
+}
+
+fn parse_ts(json: &serde_json::Value) -> Option<DateTime<Local>> {
+    json.get("timestamp")
+        .and_then(|v| v.as_str())
+        .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+        .map(|dt| dt.with_timezone(&Local))
+}
+
+/// Collect displayable parts of a message `content` (string or block array)
+fn content_parts(content: &serde_json::Value) -> (Vec<String>, Vec<String>) {
+    let mut texts = Vec::new();
+    let mut tools = Vec::new();
+    if let Some(text) = content.as_str() {
+        texts.push(text.to_string());
+    } else if let Some(arr) = content.as_array() {
+        for block in arr {
+            match block.get("type").and_then(|v| v.as_str()).unwrap_or("") {
+                "text" | "input_text" | "output_text" => {
+                    if let Some(text) = block.get("text").and_then(|v| v.as_str()) {
+                        texts.push(text.to_string());
+                    }
+                }
+                "tool_use" => {
+                    let name = block.get("name").and_then(|v| v.as_str()).unwrap_or("tool");
+                    let detail = block
+                        .get("input")
+                        .map(|input| claude::format_tool_input(name, input))
+                        .unwrap_or_default();
+                    if detail.is_empty() {
+                        tools.push(format!("🔧 {}", name));
+                    } else {
+                        tools.push(detail);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    (texts, tools)
+}
+
+/// Append one turn to the transcript
+fn push_turn(out: &mut String, role: &str, ts: Option<DateTime<Local>>, texts: &[String], tools: &[String]) {
+    let when = ts.map(|t| format!(" · {}", t.format("%Y-%m-%d %H:%M"))).unwrap_or_default();
+    if !texts.is_empty() {
+        out.push_str(&format!("### {}{}\n\n", role, when));
+        for text in texts {
+            out.push_str(text.trim());
+            out.push_str("\n\n");
+        }
+    }
+    for tool in tools {
+        out.push_str(&format!("- {}\n", tool));
+    }
+    if !tools.is_empty() {
+        out.push('\n');
+    }
+}
+
+/// Split a Claude Code session JSONL into system/meta lines and conversation
+/// lines. Lines that aren't valid JSON count as conversation.
+pub fn split_claude_lines(jsonl: &str) -> (Vec<&str>, Vec<&str>) {
+    let mut system = Vec::new();
+    let mut conversation = Vec::new();
+    for line in jsonl.lines() {
+        let kind = serde_json::from_str::<serde_json::Value>(line)
+            .ok()
+            .and_then(|json| json.get("type").and_then(|v| v.as_str()).map(str::to_string));
+        match kind.as_deref() {
+            Some("system") | Some("queue-operation") => system.push(line),
+            _ => conversation.push(line),
+        }
+    }
+    (system, conversation)
+}
+
+/// One message of a session, for `!history`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    /// false for the assistant
+    pub from_user: bool,
+    pub text: String,
+}
+
+/// The last `n` user/assistant messages of a Claude Code session that carry
+/// text (tool calls and results are skipped, as are unparseable lines)
+pub fn recent_claude_messages(jsonl: &str, n: usize) -> Vec<HistoryEntry> {
+    let (_, conversation) = split_claude_lines(jsonl);
+    let mut entries: Vec<HistoryEntry> = conversation
+        .iter()
+        .filter_map(|line| {
+            let json = serde_json::from_str::<serde_json::Value>(line).ok()?;
+            let from_user = match json.get("type")?.as_str()? {
+                "user" => true,
+                "assistant" => false,
+                _ => return None,
+            };
+            let (texts, _) = content_parts(json.get("message")?.get("content")?);
+            let text = texts.join("\n").trim().to_string();
+            (!text.is_empty()).then_some(HistoryEntry { from_user, text })
+        })
+        .collect();
+    let skip = entries.len().saturating_sub(n);
+    entries.drain(..skip);
+    entries
+}
+
+/// Drop the last user prompt of a Claude Code session and everything after it
+/// (the answer, tool calls and results). Returns the remaining JSONL and how many
+/// user/assistant lines were removed, or None if there is no prompt to undo.
+pub fn undo_last_claude_turn(jsonl: &str) -> Option<(String, usize)> {
+    let lines: Vec<&str> = jsonl.lines().collect();
+    let parsed: Vec<Option<serde_json::Value>> = lines.iter().map(|line| serde_json::from_str(line).ok()).collect();
+    // A prompt is a user line with text; tool results are user lines too
+    let start = parsed.iter().rposition(|json| {
+        json.as_ref().is_some_and(|json| {
+            json.get("type").and_then(|v| v.as_str()) == Some("user")
+                && !json.get("isMeta").and_then(|v| v.as_bool()).unwrap_or(false)
+                && json
+                    .get("message")
+                    .and_then(|m| m.get("content"))
+                    .is_some_and(|content| !content_parts(content).0.is_empty())
+        })
+    })?;
+    let removed = parsed[start..]
+        .iter()
+        .flatten()
+        .filter(|json| matches!(json.get("type").and_then(|v| v.as_str()), Some("user") | Some("assistant")))
+        .count();
+    let mut kept = lines[..start].join("\n");
+    if !kept.is_empty() {
+        kept.push('\n');
+    }
+    Some((kept, removed))
+}
+
+/// Convert a Claude Code session JSONL into a markdown transcript.
+/// Tool uses are summarized as bullet lines; tool results are omitted.
+pub fn claude_to_markdown(jsonl: &str) -> Transcript {
+    let mut body = String::new();
+    let mut message_count = 0;
+    let mut first_ts = None;
+    let mut last_ts = None;
+
+    for line in jsonl.lines() {
+        let Ok(json) = serde_json::from_str::<serde_json::Value>(line) else {
+            continue;
+        };
+        let role = match json.get("type").and_then(|v| v.as_str()) {
+            Some("user") => "👤 User",
+            Some("assistant") => "🤖 Assistant",
+            _ => continue,
+        };
+        let Some(content) = json.get("message").and_then(|m| m.get("content")) else {
+            continue;
+        };
+
+        let (texts, tools) = content_parts(content);
+        if texts.is_empty() && tools.is_empty() {
+            continue;
+        }
+
+        let ts = parse_ts(&json);
+        first_ts = first_ts.or(ts);
+        last_ts = ts.or(last_ts);
+        if !texts.is_empty() {
+            message_count += 1;
+        }
+        push_turn(&mut body, role, ts, &texts, &tools);
+    }
+
+    Transcript { markdown: body, message_count, first_ts, last_ts }
+}
+
+/// Convert a Codex rollout JSONL into a markdown transcript
+pub fn codex_to_markdown(jsonl: &str) -> Transcript {
+    let mut body = String::new();
+    let mut message_count = 0;
+    let mut first_ts = None;
+    let mut last_ts = None;
+
+    for line in jsonl.lines() {
+        let Ok(json) = serde_json::from_str::<serde_json::Value>(line) else {
+            continue;
+        };
+        let payload = json.get("payload").unwrap_or(&json);
+        let ts = parse_ts(&json);
+
+        match payload.get("type").and_then(|v| v.as_str()).unwrap_or("") {
+            "message" => {
+                let role = match payload.get("role").and_then(|v| v.as_str()) {
+                    Some("user") => "👤 User",
+                    Some("assistant") => "🤖 Assistant",
+                    _ => continue,
+                };
+                let (texts, _) = payload.get("content").map(content_parts).unwrap_or_default();
+                if texts.is_empty() {
+                    continue;
+                }
+                first_ts = first_ts.or(ts);
+                last_ts = ts.or(last_ts);
+                message_count += 1;
+                push_turn(&mut body, role, ts, &texts, &[]);
+            }
+            "function_call" | "local_shell_call" => {
+                let name = payload.get("name").and_then(|v| v.as_str()).unwrap_or("shell");
+                body.push_str(&format!("- 💻 {}\n\n", name));
+            }
+            _ => {}
+        }
+    }
+
+    Transcript { markdown: body, message_count, first_ts, last_ts }
+}
+
+/// Load and convert a session for the given backend
+pub fn load_transcript(session_id: &str, backend: AiBackend) -> Option<(PathBuf, Transcript)> {
+    let (path, is_codex) = match backend {
+        AiBackend::Codex => (find_codex_session_file(session_id)?, true),
+        _ => match find_claude_session_file(session_id) {
+            Some(p) => (p, false),
+            None => (find_codex_session_file(session_id)?, true),
+        },
+    };
+    let content = std::fs::read_to_string(&path).ok()?;
+    let transcript = if is_codex { codex_to_markdown(&content) } else { claude_to_markdown(&content) };
+    Some((path, transcript))
+}
+
+/// Header for an exported transcript
+pub fn header(title: &str, session_id: &str, t: &Transcript) -> String {
+    let range = match (t.first_ts, t.last_ts) {
+        (Some(a), Some(b)) => format!("{} → {}", a.format("%Y-%m-%d %H:%M"), b.format("%Y-%m-%d %H:%M")),
+        _ => "unknown".to_string(),
+    };
+    format!(
+        "# {}\n\n- Session: `{}`\n- Dates: {}\n- Messages: {}\n\n---\n\n",
+        title, session_id, range, t.message_count
+    )
+}
+
+/// Make a channel name safe for use as a directory name
+fn sanitize(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '-' })
+        .collect();
+    let trimmed = cleaned.trim_matches('-');
+    if trimmed.is_empty() { "channel".to_string() } else { trimmed.to_string() }
+}
+
+/// `neywa sessions export <dir>` - write every tracked session as markdown plus an index
+pub async fn export_all(dir: &Path, since: Option<&str>) -> Result<()> {
+    let since = since
+        .map(|s| {
+            NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .with_context(|| format!("Invalid --since date '{}' (use YYYY-MM-DD)", s))
+        })
+        .transpose()?;
+
+    let sessions = crate::discord::load_sessions();
+    let backends = crate::discord::load_channel_backends();
+
+    // Resolve channel names when the bot token is configured
+    let channel_names: HashMap<u64, String> = match crate::discord_api::fetch_channel_names().await {
+        Ok(names) => names,
+        Err(e) => {
+            eprintln!("warning: channel names unavailable ({}), using IDs", e);
+            HashMap::new()
+        }
+    };
+
+    std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+
+    let mut index_rows = Vec::new();
+    let mut entries: Vec<_> = sessions.iter().collect();
+    entries.sort_by_key(|((user_id, channel_id), _)| (*channel_id, *user_id));
+
+    for ((user_id, channel_id), session) in entries {
+        let session_id = &session.id;
+        let backend = backends.get(channel_id).copied().unwrap_or(AiBackend::Claude);
+        let Some((_path, transcript)) = load_transcript(session_id, backend) else {
+            eprintln!("warning: session file for {} not found, skipping", session_id);
+            continue;
+        };
+
+        if let (Some(since), Some(last)) = (since, transcript.last_ts) {
+            if last.date_naive() < since {
+                continue;
+            }
+        }
+
+        let channel_label = channel_names
+            .get(channel_id)
+            .map(|n| sanitize(n))
+            .unwrap_or_else(|| channel_id.to_string());
+        let channel_dir = dir.join(&channel_label);
+        std::fs::create_dir_all(&channel_dir)?;
+
+        let date = transcript
+            .first_ts
+            .map(|t| t.format("%Y-%m-%d").to_string())
+            .unwrap_or_else(|| "undated".to_string());
+        let short_sid: String = session_id.chars().take(8).collect();
+        let file_name = format!("{}-{}-{}.md", date, user_id, short_sid);
+
+        let title = format!("#{} — user {}", channel_label, user_id);
+        let content = header(&title, session_id, &transcript) + &transcript.markdown;
+        std::fs::write(channel_dir.join(&file_name), content)?;
+
+        let last = transcript
+            .last_ts
+            .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|| "?".to_string());
+        index_rows.push(format!(
+            "| #{} | {} | {} | {} | {} | [{}]({}/{}) |",
+            channel_label, user_id, backend.cli_name(), date, last,
+            transcript.message_count, channel_label, file_name
+        ));
+    }
+
+    let mut index = String::from("# Neywa session archive\n\n");
+    index.push_str(&format!("Exported {}\n\n", Local::now().format("%Y-%m-%d %H:%M")));
+    index.push_str("| Channel | User | Backend | Started | Last message | Messages |\n");
+    index.push_str("|---|---|---|---|---|---|\n");
+    for row in &index_rows {
+        index.push_str(row);
+        index.push('\n');
+    }
+    std::fs::write(dir.join("index.md"), index)?;
+
+    println!("Exported {} session(s) to {:?}", index_rows.len(), dir);
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use std::sync::mpsc;
+use super::{TrayCommand, ICON_BYTES};
+
+/// StatusNotifierItem tray (GNOME with AppIndicator extension, KDE, etc.)
+struct NeywaTray {
+    status: String,
+    quit_tx: mpsc::Sender<()>,
+    icon: Option<ksni::Icon>,
+}
+
+impl ksni::Tray for NeywaTray {
+    fn id(&self) -> String {
+        env!("CARGO_PKG_NAME").into()
+    }
+
+    fn title(&self) -> String {
+        "Neywa".into()
+    }
+
+    fn icon_pixmap(&self) -> Vec<ksni::Icon> {
+        self.icon.iter().cloned().collect()
+    }
+
+    fn tool_tip(&self) -> ksni::ToolTip {
+        ksni::ToolTip {
+            title: "Neywa - AI Personal OS".into(),
+            description: self.status.clone(),
+            ..Default::default()
+        }
+    }
+
+    fn menu(&self) -> Vec<ksni::MenuItem<Self>> {
+        use ksni::menu::*;
+        vec![
+            StandardItem {
+                label: "🤖 Neywa".into(),
+                enabled: false,
+                ..Default::default()
+            }
+            .into(),
+            StandardItem {
+                label: format!("   v{}", env!("CARGO_PKG_VERSION")),
+                enabled: false,
+                ..Default::default()
+            }
+            .into(),
+            MenuItem::Separator,
+            StandardItem {
+                label: "Status".into(),
+                enabled: false,
+                ..Default::default()
+            }
+            .into(),
+            StandardItem {
+                label: format!("   {}", self.status),
+                enabled: false,
+                ..Default::default()
+            }
+            .into(),
+            MenuItem::Separator,
+            StandardItem {
+                label: "Open Discord".into(),
+                activate: Box::new(|_: &mut Self| {
+                    let _ = std::process::Command::new("xdg-open")
+                        .arg("discord://")
+                        .spawn();
+                }),
+                ..Default::default()
+            }
+            .into(),
+            MenuItem::Separator,
+            StandardItem {
+                label: "Quit Neywa".into(),
+                activate: Box::new(|this: &mut Self| {
+                    let _ = this.quit_tx.send(());
+                }),
+                ..Default::default()
+            }
+            .into(),
+        ]
+    }
+}
+
+pub fn run_tray(status_rx: mpsc::Receiver<TrayCommand>, quit_tx: mpsc::Sender<()>) {
+    let service = ksni::TrayService::new(NeywaTray {
+        status: "🟢 Connected to Discord".to_string(),
+        quit_tx,
+        icon: load_icon(),
+    });
+    let handle = service.handle();
+
+    // No tray host (e.g. plain GNOME without the AppIndicator extension) is not fatal
+    std::thread::spawn(move || {
+        if let Err(e) = service.run() {
+            tracing::warn!("Tray unavailable, running headless: {}", e);
+        }
+    });
+
+    // recv() fails once the bot thread exits and drops its sender
+    while let Ok(cmd) = status_rx.recv() {
+        match cmd {
+            TrayCommand::UpdateStatus(status) => {
+                handle.update(|tray| tray.status = status);
+            }
+            TrayCommand::Quit => break,
+        }
+    }
+
+    handle.shutdown();
+}
+
+/// Decode the PNG icon into ARGB32 (network byte order) as SNI expects
+fn load_icon() -> Option<ksni::Icon> {
+    let image = image::load_from_memory(ICON_BYTES).ok()?.into_rgba8();
+    let (width, height) = image.dimensions();
+    let data = image
+        .into_raw()
+        .chunks_exact(4)
+        .flat_map(|px| [px[3], px[0], px[1], px[2]])
+        .collect();
+
+    Some(ksni::Icon {
+        width: width as i32,
+        height: height as i32,
+        data,
+    })
+}
@@ -1,18 +1,11 @@
 use std::sync::mpsc;
+use super::{TrayCommand, ICON_BYTES};
 use tao::event_loop::{ControlFlow, EventLoopBuilder};
 use tray_icon::{
     menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem},
     TrayIconBuilder, TrayIconEvent,
 };
 
-const ICON_BYTES: &[u8] = include_bytes!("../assets/tray-icon.png");
-
-#[derive(Debug, Clone)]
-pub enum TrayCommand {
-    UpdateStatus(String),
-    Quit,
-}
-
 /// Set macOS app to run as menu bar only (no dock icon, no app menu)
 #[cfg(target_os = "macos")]
 fn set_macos_accessory_mode() {
@@ -80,27 +73,33 @@ pub fn run_tray(status_rx: mpsc::Receiver<TrayCommand>, quit_tx: mpsc::Sender<()
     let menu_channel = MenuEvent::receiver();
     let tray_channel = TrayIconEvent::receiver();
 
+    // Set once the bot thread has exited (e.g. after a Discord error)
+    let mut daemon_gone = false;
+
     event_loop.run(move |_event, _, control_flow| {
         *control_flow = ControlFlow::Poll;
 
         // Check for status updates from the daemon
-        if let Ok(cmd) = status_rx.try_recv() {
-            match cmd {
-                TrayCommand::UpdateStatus(status) => {
-                    let status_text = format!("   {}", status);
-                    status_item.set_text(&status_text);
-                }
-                TrayCommand::Quit => {
-                    *control_flow = ControlFlow::Exit;
-                }
+        match status_rx.try_recv() {
+            Ok(TrayCommand::UpdateStatus(status)) => {
+                let status_text = format!("   {}", status);
+                status_item.set_text(&status_text);
+            }
+            Ok(TrayCommand::Quit) => {
+                *control_flow = ControlFlow::Exit;
             }
+            Err(mpsc::TryRecvError::Disconnected) => daemon_gone = true,
+            Err(mpsc::TryRecvError::Empty) => {}
         }
 
         // Handle menu clicks
         if let Ok(event) = menu_channel.try_recv() {
             if event.id == quit_item_id {
+                // The daemon sends TrayCommand::Quit once it has shut down
                 let _ = quit_tx.send(());
-                *control_flow = ControlFlow::Exit;
+                if daemon_gone {
+                    *control_flow = ControlFlow::Exit;
+                }
             } else if event.id == open_discord_id {
                 // Open Discord app
                 let _ = std::process::Command::new("open")
@@ -0,0 +1,37 @@
+use std::sync::mpsc;
+
+#[cfg(all(feature = "tray", target_os = "linux"))]
+mod linux;
+#[cfg(all(feature = "menubar", not(target_os = "linux")))]
+mod menubar;
+
+#[cfg(any(all(feature = "tray", target_os = "linux"), all(feature = "menubar", not(target_os = "linux"))))]
+const ICON_BYTES: &[u8] = include_bytes!("../../assets/tray-icon.png");
+
+#[derive(Debug, Clone)]
+pub enum TrayCommand {
+    UpdateStatus(String),
+    Quit,
+}
+
+/// Run the tray on the current thread until quit.
+/// macOS uses the menu bar (tray-icon), Linux uses StatusNotifierItem (ksni).
+#[cfg(any(all(feature = "tray", target_os = "linux"), all(feature = "menubar", not(target_os = "linux"))))]
+pub fn run_tray(status_rx: mpsc::Receiver<TrayCommand>, quit_tx: mpsc::Sender<()>) {
+    #[cfg(target_os = "linux")]
+    linux::run_tray(status_rx, quit_tx);
+    #[cfg(not(target_os = "linux"))]
+    menubar::run_tray(status_rx, quit_tx);
+}
+
+/// Headless build (no `menubar` feature, or Linux without `tray`): block until the daemon stops
+#[cfg(not(any(all(feature = "tray", target_os = "linux"), all(feature = "menubar", not(target_os = "linux")))))]
+pub fn run_tray(status_rx: mpsc::Receiver<TrayCommand>, _quit_tx: mpsc::Sender<()>) {
+    // recv() fails once the bot thread exits and drops its sender
+    while let Ok(cmd) = status_rx.recv() {
+        match cmd {
+            TrayCommand::UpdateStatus(status) => tracing::info!("Status: {}", status),
+            TrayCommand::Quit => break,
+        }
+    }
+}
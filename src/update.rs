@@ -0,0 +1,188 @@
+//! Self-update: check neywa.ai for a newer release, replace the running
+//! binary, and carry a note across the restart so the daemon can announce it.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::disk;
+use crate::lifecycle;
+
+/// Where releases are published
+const RELEASE_BASE_URL: &str = "https://neywa.ai";
+
+/// An update applied before a restart, announced once the daemon is back
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingUpdate {
+    /// Channel `!update` was run from
+    pub channel_id: u64,
+    pub old_version: String,
+    pub new_version: String,
+}
+
+impl PendingUpdate {
+    /// Message posted after the restart; `running` is the version now running
+    pub fn completion_message(&self, running: &str) -> String {
+        if running == self.new_version {
+            format!("🎉 **Update complete!** v{} → v{}", self.old_version, self.new_version)
+        } else {
+            format!("⚠️ Update done. Expected v{}, running v{}", self.new_version, running)
+        }
+    }
+}
+
+/// Release name of the binary for an architecture (None if no build is published for it)
+pub fn binary_name(arch: &str) -> Option<String> {
+    match arch {
+        "aarch64" => Some("neywa-arm64".to_string()),
+        "x86_64" => Some("neywa-x86_64".to_string()),
+        _ => None,
+    }
+}
+
+/// Fetch remote version from neywa.ai/version.txt
+pub async fn fetch_remote_version() -> Result<String> {
+    let url = format!("{}/version.txt", RELEASE_BASE_URL);
+    let response = reqwest::get(&url).await?;
+
+    if !response.status().is_success() {
+        anyhow::bail!("Failed to fetch version: HTTP {}", response.status());
+    }
+
+    let version = response.text().await?.trim().to_string();
+    Ok(version)
+}
+
+/// Save pending update info before restart
+pub fn save_pending(update: &PendingUpdate) -> Result<()> {
+    let path = lifecycle::update_pending_path();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, serde_json::to_string(update)?)?;
+    Ok(())
+}
+
+/// Load and delete pending update info
+pub fn take_pending() -> Option<PendingUpdate> {
+    let path = lifecycle::update_pending_path();
+    if !path.exists() {
+        return None;
+    }
+
+    let content = std::fs::read_to_string(&path).ok()?;
+    let _ = std::fs::remove_file(&path); // Delete after reading
+
+    serde_json::from_str(&content).ok()
+}
+
+/// Self-update neywa binary from neywa.ai
+pub async fn self_update() -> Result<()> {
+    let binary = binary_name(std::env::consts::ARCH).context("Unsupported architecture")?;
+    let download_url = format!("{}/{}", RELEASE_BASE_URL, binary);
+    tracing::info!("Downloading from: {}", download_url);
+
+    // Download new binary
+    let response = reqwest::get(&download_url).await?;
+
+    if !response.status().is_success() {
+        anyhow::bail!("Failed to download: HTTP {}", response.status());
+    }
+
+    let bytes = response.bytes().await?;
+
+    // Find current binary path
+    let current_exe = std::env::current_exe()
+        .context("Failed to get current executable path")?;
+
+    let threshold = Config::load().unwrap_or_default().min_free_disk_bytes();
+    if let Some(dir) = current_exe.parent() {
+        disk::ensure_headroom(dir, bytes.len() as u64, threshold).map_err(anyhow::Error::msg)?;
+    }
+
+    tracing::info!("Updating binary at: {:?}", current_exe);
+
+    // Write to temp file first
+    let temp_path = current_exe.with_extension("new");
+    std::fs::write(&temp_path, &bytes)
+        .context("Failed to write new binary")?;
+
+    // Make executable
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut perms = std::fs::metadata(&temp_path)?.permissions();
+        perms.set_mode(0o755);
+        std::fs::set_permissions(&temp_path, perms)?;
+    }
+
+    // Replace current binary
+    std::fs::rename(&temp_path, &current_exe)
+        .context("Failed to replace binary")?;
+
+    // Also update the .app bundle binary (preserves FDA permissions)
+    // Skip if current_exe IS the app binary (self-copy truncates to 0 bytes!)
+    let app_bundle = std::path::PathBuf::from("/Applications/Neywa.app");
+    let app_binary = app_bundle.join("Contents/MacOS/neywa");
+    if app_binary.exists() {
+        let is_same = std::fs::canonicalize(&current_exe).ok()
+            == std::fs::canonicalize(&app_binary).ok();
+        if !is_same {
+            if let Err(e) = std::fs::copy(&current_exe, &app_binary) {
+                tracing::warn!("Failed to update Neywa.app binary: {}", e);
+            } else {
+                tracing::info!("Updated Neywa.app binary");
+            }
+        } else {
+            tracing::info!("Binary already at app bundle path, skipping copy");
+        }
+    }
+
+    // Re-sign the .app bundle after binary replacement.
+    // Without this, macOS may refuse to launch the updated binary due to
+    // code signature mismatch, causing LaunchAgent KeepAlive to crash-loop.
+    #[cfg(target_os = "macos")]
+    {
+        let sign_output = std::process::Command::new("codesign")
+            .args(["--force", "--sign", "-", app_bundle.to_str().unwrap_or("/Applications/Neywa.app")])
+            .output();
+        match sign_output {
+            Ok(out) if out.status.success() => {
+                tracing::info!("Re-signed Neywa.app successfully");
+            }
+            Ok(out) => {
+                tracing::warn!("codesign warning: {}", String::from_utf8_lossy(&out.stderr));
+            }
+            Err(e) => {
+                tracing::warn!("Failed to run codesign: {}", e);
+            }
+        }
+    }
+
+    tracing::info!("Binary updated successfully");
+
+    Ok(())
+}
+
+/// Restart Neywa after update.
+/// Uses _exit(0) to bypass atexit handlers (tray cleanup etc.) that may hang.
+/// LaunchAgent's KeepAlive=true will auto-restart the process within ThrottleInterval.
+/// Note: exec() doesn't work on macOS because replacing the binary invalidates the
+/// code signature, causing SIGKILL from the kernel.
+pub fn restart() -> ! {
+    tracing::info!("Exiting for KeepAlive restart...");
+
+    // Safety net: if _exit somehow doesn't work, force kill after 5 seconds
+    std::thread::spawn(|| {
+        std::thread::sleep(std::time::Duration::from_secs(5));
+        std::process::exit(1);
+    });
+
+    // Use _exit(0) to terminate immediately without running atexit handlers.
+    // This avoids potential hangs from tray/NSApplication cleanup.
+    // KeepAlive=true in LaunchAgent will restart us automatically.
+    extern "C" {
+        fn _exit(status: i32) -> !;
+    }
+    unsafe { _exit(0) }
+}
@@ -0,0 +1,209 @@
+use chrono::{Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::config::Config;
+
+/// One user's counters for a single local day
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserUsage {
+    pub day: NaiveDate,
+    #[serde(default)]
+    pub requests: u32,
+    #[serde(default)]
+    pub cost_usd: f64,
+}
+
+impl UserUsage {
+    fn new(day: NaiveDate) -> Self {
+        Self { day, requests: 0, cost_usd: 0.0 }
+    }
+}
+
+/// Why a request was refused
+#[derive(Debug, Clone)]
+pub enum QuotaExceeded {
+    Requests { limit: u32 },
+    Cost { limit: f64, spent: f64 },
+}
+
+/// Persisted per-user usage counters with daily rollover at local midnight
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UsageStore {
+    #[serde(default)]
+    users: HashMap<u64, UserUsage>,
+}
+
+/// Path for storing usage counters
+fn usage_file_path() -> PathBuf {
+    let config_dir = dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("neywa");
+    config_dir.join("usage.json")
+}
+
+impl UsageStore {
+    /// Load usage counters from file
+    pub fn load() -> Self {
+        let path = usage_file_path();
+        if !path.exists() {
+            return Self::default();
+        }
+        match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                tracing::warn!("Failed to parse usage file: {}", e);
+                Self::default()
+            }),
+            Err(e) => {
+                tracing::warn!("Failed to read usage file: {}", e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Save usage counters to file
+    pub fn save(&self) {
+        let path = usage_file_path();
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        match serde_json::to_string_pretty(self) {
+            Ok(json) => {
+                if let Err(e) = std::fs::write(&path, json) {
+                    tracing::warn!("Failed to save usage: {}", e);
+                }
+            }
+            Err(e) => tracing::warn!("Failed to serialize usage: {}", e),
+        }
+    }
+
+    /// Today's counters for a user, rolling over when the local date has advanced.
+    /// A clock that jumps backwards never resets counters (only forward date changes do).
+    pub fn today(&mut self, user_id: u64) -> &mut UserUsage {
+        self.on(user_id, Local::now().date_naive())
+    }
+
+    fn on(&mut self, user_id: u64, day: NaiveDate) -> &mut UserUsage {
+        let entry = self.users.entry(user_id).or_insert_with(|| UserUsage::new(day));
+        if day > entry.day {
+            *entry = UserUsage::new(day);
+        }
+        entry
+    }
+
+    /// Total cost recorded across users for a date
+    pub fn total_cost(&self, day: NaiveDate) -> f64 {
+        self.users.values().filter(|u| u.day == day).map(|u| u.cost_usd).sum()
+    }
+
+    /// Check a user against the configured quotas and count the request if allowed.
+    /// Returns the remaining request count (None = unlimited).
+    pub fn try_consume(&mut self, user_id: u64, config: &Config) -> Result<Option<u32>, QuotaExceeded> {
+        let remaining = self.consume(user_id, config, Local::now().date_naive())?;
+        self.save();
+        Ok(remaining)
+    }
+
+    fn consume(&mut self, user_id: u64, config: &Config, day: NaiveDate) -> Result<Option<u32>, QuotaExceeded> {
+        let usage = self.on(user_id, day).clone();
+
+        if let Some(limit) = config.user_daily_quota {
+            if usage.requests >= limit {
+                return Err(QuotaExceeded::Requests { limit });
+            }
+        }
+        if let Some(limit) = config.user_daily_cost_limit {
+            if usage.cost_usd >= limit {
+                return Err(QuotaExceeded::Cost { limit, spent: usage.cost_usd });
+            }
+        }
+
+        let entry = self.on(user_id, day);
+        entry.requests += 1;
+        Ok(config.user_daily_quota.map(|limit| limit.saturating_sub(entry.requests)))
+    }
+}
+
+/// Next local midnight, when daily counters reset
+pub fn next_reset() -> chrono::DateTime<Local> {
+    let tomorrow = Local::now().date_naive().succ_opt().unwrap_or(NaiveDate::MAX);
+    tomorrow
+        .and_hms_opt(0, 0, 0)
+        .and_then(|dt| dt.and_local_timezone(Local).earliest())
+        .unwrap_or_else(Local::now)
+}
+
+/// Format the time until the next reset, e.g. "00:00 (in 5h 12m)"
+pub fn format_reset() -> String {
+    let reset = next_reset();
+    let remaining = reset - Local::now();
+    let minutes = remaining.num_minutes().max(0);
+    format!("{} (in {}h {}m)", reset.format("%H:%M"), minutes / 60, minutes % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 3, d).unwrap()
+    }
+
+    fn quota(requests: Option<u32>, cost: Option<f64>) -> Config {
+        Config { user_daily_quota: requests, user_daily_cost_limit: cost, ..Config::default() }
+    }
+
+    #[test]
+    fn requests_count_down_to_the_limit() {
+        let mut store = UsageStore::default();
+        let config = quota(Some(2), None);
+        assert_eq!(store.consume(1, &config, day(1)).unwrap(), Some(1));
+        assert_eq!(store.consume(1, &config, day(1)).unwrap(), Some(0));
+        // At the limit: refused, and the refusal isn't counted
+        assert!(matches!(store.consume(1, &config, day(1)), Err(QuotaExceeded::Requests { limit: 2 })));
+        assert_eq!(store.on(1, day(1)).requests, 2);
+        // Other users have their own counters
+        assert_eq!(store.consume(2, &config, day(1)).unwrap(), Some(1));
+    }
+
+    #[test]
+    fn unlimited_without_quotas() {
+        let mut store = UsageStore::default();
+        for _ in 0..100 {
+            assert_eq!(store.consume(1, &Config::default(), day(1)).unwrap(), None);
+        }
+        assert_eq!(store.on(1, day(1)).requests, 100);
+    }
+
+    #[test]
+    fn cost_limit_applies_once_reached() {
+        let mut store = UsageStore::default();
+        let config = quota(None, Some(1.0));
+        store.on(1, day(1)).cost_usd = 0.99;
+        assert_eq!(store.consume(1, &config, day(1)).unwrap(), None);
+        store.on(1, day(1)).cost_usd = 1.0;
+        match store.consume(1, &config, day(1)) {
+            Err(QuotaExceeded::Cost { limit, spent }) => assert_eq!((limit, spent), (1.0, 1.0)),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn counters_roll_over_daily_but_not_backwards() {
+        let mut store = UsageStore::default();
+        let config = quota(Some(1), Some(5.0));
+        store.consume(1, &config, day(1)).unwrap();
+        store.on(1, day(1)).cost_usd = 5.0;
+        assert!(store.consume(1, &config, day(1)).is_err());
+        assert_eq!(store.total_cost(day(1)), 5.0);
+
+        // A clock set back a day keeps today's counters
+        assert!(store.consume(1, &config, NaiveDate::from_ymd_opt(2026, 2, 28).unwrap()).is_err());
+
+        // The next day starts fresh
+        assert_eq!(store.consume(1, &config, day(2)).unwrap(), Some(0));
+        assert_eq!(store.on(1, day(2)).cost_usd, 0.0);
+        assert_eq!(store.total_cost(day(1)), 0.0);
+    }
+}
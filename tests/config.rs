@@ -0,0 +1,41 @@
+use neywa::config::Config;
+
+#[test]
+fn config_round_trips_through_json() {
+    let config = Config {
+        discord_bot_token: Some("token".to_string()),
+        discord_guild_id: Some(42),
+        redact_allowlist: vec!["^AKIAEXAMPLE".to_string()],
+        ..Config::default()
+    };
+    let json = serde_json::to_string_pretty(&config).unwrap();
+    let loaded: Config = serde_json::from_str(&json).unwrap();
+    assert_eq!(serde_json::to_string_pretty(&loaded).unwrap(), json);
+    assert_eq!(loaded.discord_guild_id, Some(42));
+}
+
+#[test]
+fn missing_keys_take_defaults() {
+    // A config written by an older version only has the token and guild
+    let loaded: Config = serde_json::from_str(r#"{"discord_bot_token":"token","discord_guild_id":7}"#).unwrap();
+    let defaults = Config {
+        discord_bot_token: Some("token".to_string()),
+        discord_guild_id: Some(7),
+        ..Config::default()
+    };
+    assert_eq!(
+        serde_json::to_value(&loaded).unwrap(),
+        serde_json::to_value(&defaults).unwrap()
+    );
+}
+
+#[test]
+fn logs_channel_binding_persists() {
+    let config = Config { logs_channel_id: Some(99), logs_channel_bound: true, ..Config::default() };
+    let loaded: Config = serde_json::from_str(&serde_json::to_string(&config).unwrap()).unwrap();
+    assert_eq!((loaded.logs_channel_id, loaded.logs_channel_bound), (Some(99), true));
+
+    // Configs from before the binding existed load as unbound
+    let loaded: Config = serde_json::from_str(r#"{"logs_channel_id":99}"#).unwrap();
+    assert_eq!((loaded.logs_channel_id, loaded.logs_channel_bound), (Some(99), false));
+}
@@ -0,0 +1,76 @@
+//! The dry-run backend standing in for claude/codex: a request goes through
+//! prompt building, the stream and Discord splitting without running any AI
+
+use std::time::Duration;
+
+use neywa::claude::StreamEvent;
+use neywa::discord::split_for_discord;
+use neywa::prompt::{self, PromptContext};
+
+/// Run a prompt through the test backend and collect everything it streams
+async fn run(prompt: &str) -> Vec<StreamEvent> {
+    let mut rx = neywa::dryrun::run_streaming(prompt, Duration::from_millis(4));
+    let mut events = Vec::new();
+    while let Some(event) = rx.recv().await {
+        events.push(event);
+    }
+    events
+}
+
+fn request(content: &str, first_message: bool) -> String {
+    prompt::build(&PromptContext {
+        username: "bob",
+        content,
+        attachments: "",
+        channel_prompt: "You are a helpful assistant.",
+        first_message,
+        language: None,
+        language_changed: false,
+    })
+}
+
+#[tokio::test]
+async fn request_round_trip() {
+    let prompt = request("List the files in ```src```", true);
+    let events = run(&prompt).await;
+
+    let tools: Vec<&str> = events
+        .iter()
+        .filter_map(|event| match event {
+            StreamEvent::ToolUse(name, _) => Some(name.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(tools, vec!["Read", "Bash"]);
+    assert!(matches!(&events[1], StreamEvent::ToolResult { ok: true, .. }));
+    assert_eq!(events.last(), Some(&StreamEvent::Done));
+    // No session id, so nothing would be stored
+    assert!(!events.iter().any(|event| matches!(event, StreamEvent::SessionId(_))));
+
+    // The answer echoes the prompt envelope, with fences defused so the
+    // reply's own code block stays intact
+    let Some(StreamEvent::Text(answer)) = events.iter().rev().nth(1) else {
+        panic!("no answer in {:?}", events);
+    };
+    assert!(answer.starts_with("🧪 **DRY RUN**"));
+    assert!(answer.contains("[bob]: List the files in '''src'''"));
+    assert!(answer.contains("You are a helpful assistant."));
+    assert_eq!(answer.matches("```").count(), 2);
+
+    let chunks = split_for_discord(answer);
+    assert_eq!(chunks, vec![answer.clone()]);
+}
+
+#[tokio::test]
+async fn long_requests_are_truncated_and_split() {
+    let long = "word ".repeat(2000);
+    let events = run(&request(&long, false)).await;
+    let Some(StreamEvent::Text(answer)) = events.iter().rev().nth(1) else {
+        panic!("no answer in {:?}", events);
+    };
+    assert!(answer.chars().count() < long.len());
+    for chunk in split_for_discord(answer) {
+        assert!(chunk.chars().count() <= 2000);
+        assert_eq!(chunk.matches("```").count() % 2, 0, "{}", chunk);
+    }
+}
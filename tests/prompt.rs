@@ -0,0 +1,28 @@
+use neywa::prompt::{self, PromptContext};
+
+fn context(first_message: bool) -> PromptContext<'static> {
+    PromptContext {
+        username: "bob",
+        content: prompt::default_content(true),
+        attachments: "\n\n[Attached images: /tmp/a.png (1.0 KB)]",
+        channel_prompt: "You are a helpful assistant.",
+        first_message,
+        language: None,
+        language_changed: false,
+    }
+}
+
+#[test]
+fn fresh_session_gets_the_preamble() {
+    let text = prompt::build(&context(true));
+    assert!(text.starts_with("[System: You are a helpful assistant. Multiple users may participate."));
+    assert!(text.ends_with("\n\n[bob]: Analyze this image\n\n[Attached images: /tmp/a.png (1.0 KB)]"));
+}
+
+#[test]
+fn follow_ups_are_prefixed_with_the_user() {
+    assert_eq!(
+        prompt::build(&context(false)),
+        "[bob]: Analyze this image\n\n[Attached images: /tmp/a.png (1.0 KB)]"
+    );
+}
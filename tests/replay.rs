@@ -0,0 +1,143 @@
+//! Captured CLI transcripts replayed through the stream parsers
+
+use neywa::claude::{RunUsage, StreamEvent};
+use neywa::stream::{ClaudeParser, CodexParser};
+
+fn lines(fixture: &str) -> Vec<&str> {
+    fixture.lines().collect()
+}
+
+fn text(s: &str) -> StreamEvent {
+    StreamEvent::Text(s.to_string())
+}
+
+fn tool(name: &str, detail: &str) -> StreamEvent {
+    StreamEvent::ToolUse(name.to_string(), detail.to_string())
+}
+
+fn result(tool: &str, ok: bool, summary: &str) -> StreamEvent {
+    StreamEvent::ToolResult { tool: tool.to_string(), ok, summary: summary.to_string() }
+}
+
+fn usage(cost_usd: f64, input_tokens: u64, output_tokens: u64) -> StreamEvent {
+    StreamEvent::Usage(RunUsage { cost_usd, input_tokens, output_tokens })
+}
+
+fn session(id: &str) -> StreamEvent {
+    StreamEvent::SessionId(id.to_string())
+}
+
+#[test]
+fn normal_run() {
+    let events = ClaudeParser::replay(&lines(include_str!("fixtures/claude_normal.jsonl")), false);
+    assert_eq!(
+        events,
+        vec![
+            session("0b8e4c1a-5f3d-4c2e-9a7b-1d2e3f4a5b6c"),
+            text("Hi! What should we work on today?"),
+            text("Hi! What should we work on today?"),
+            usage(0.0042, 9203, 12),
+            StreamEvent::Done,
+            StreamEvent::Done,
+        ]
+    );
+}
+
+#[test]
+fn plan_run_reports_the_plan_file_and_exploration() {
+    let plan = "# Plan\n\n1. Split run_bot into setup and event loop\n2. Add tests";
+    let events = ClaudeParser::replay(&lines(include_str!("fixtures/claude_plan.jsonl")), true);
+    assert_eq!(
+        events,
+        vec![
+            session("5c1d7e2f-0a9b-4c3d-8e7f-6a5b4c3d2e1f"),
+            StreamEvent::Explored("Read".to_string(), "/Users/me/project/src/main.rs".to_string()),
+            tool("Read", "📖 main.rs"),
+            StreamEvent::Explored("Grep".to_string(), "fn run_bot".to_string()),
+            tool("Grep", "🔎 fn run_bot"),
+            StreamEvent::Explored("Glob".to_string(), "src/**/*.rs".to_string()),
+            tool("Glob", "🔍 src/**/*.rs"),
+            StreamEvent::PlanContent("/Users/me/.claude/plans/quiet-river.md".to_string(), plan.to_string()),
+            tool("Write", "✏️ quiet-river.md"),
+            tool("ExitPlanMode", "📐 Exiting plan mode"),
+            // The denied ExitPlanMode leaves an empty result: no Text
+            usage(0.0871, 35040, 900),
+            StreamEvent::Done,
+            StreamEvent::Done,
+        ]
+    );
+}
+
+#[test]
+fn tool_heavy_run_matches_results_to_their_tools() {
+    let events = ClaudeParser::replay(&lines(include_str!("fixtures/claude_tools.jsonl")), false);
+    assert_eq!(
+        events,
+        vec![
+            session("9f8e7d6c-5b4a-4321-8fed-cba987654321"),
+            text("Let me run the tests."),
+            tool("Bash", "💻 cargo test"),
+            tool("Read", "📖 lib.rs"),
+            result("💻 cargo test", false, "exit 101"),
+            result("📖 lib.rs", true, "pub mod parse;"),
+            tool("mcp__github__create_issue", "🔌 github:create_issue"),
+            // toolu_99 was never announced, so its result is dropped
+            result("🔌 github:create_issue", true, "Created issue #12"),
+            tool("TodoWrite", ""),
+            result("🔧 TodoWrite", true, "Todos updated"),
+            text("Let me run the tests.\nThe parse test fails; I opened issue #12."),
+            text("The parse test fails; I opened issue #12."),
+            usage(0.0315, 12020, 300),
+            StreamEvent::Done,
+            StreamEvent::Done,
+        ]
+    );
+}
+
+#[test]
+fn context_overflow_surfaces_as_text() {
+    let events = ClaudeParser::replay(&lines(include_str!("fixtures/claude_context_overflow.jsonl")), false);
+    assert_eq!(
+        events,
+        vec![
+            session("1a2b3c4d-5e6f-4a7b-8c9d-0e1f2a3b4c5d"),
+            text("Prompt is too long"),
+            usage(0.0, 0, 0),
+            StreamEvent::Done,
+            StreamEvent::Done,
+        ]
+    );
+    assert!(ClaudeParser::is_context_overflow("Prompt is too long"));
+}
+
+#[test]
+fn missing_session_is_recognized_from_stderr() {
+    // `claude --resume <id>` prints this and exits 1, with nothing on stdout
+    let stderr = include_str!("fixtures/claude_missing_session.stderr");
+    assert!(ClaudeParser::is_missing_session(stderr));
+    assert_eq!(ClaudeParser::replay(&[], false), vec![StreamEvent::Done]);
+
+    assert!(!ClaudeParser::is_missing_session(include_str!("fixtures/claude_logged_out.stderr")));
+    assert!(!ClaudeParser::is_missing_session("Prompt is too long"));
+    assert!(!ClaudeParser::is_context_overflow(stderr));
+}
+
+#[test]
+fn codex_thread() {
+    let events = CodexParser::replay(&lines(include_str!("fixtures/codex_thread.jsonl")));
+    assert_eq!(
+        events,
+        vec![
+            session("0199a213-81c0-7800-8aa1-bbab2a035a53"),
+            tool("Bash", "💻 bash -lc ls"),
+            tool("Bash", "💻 bash -lc ls ✓"),
+            tool("Read", "📖 Cargo.toml"),
+            tool("Edit", "✏️ README.md"),
+            tool("WebSearch", "🌐 serenity 0.12 message edit event"),
+            tool("MCP", "🔌 github.search_issues"),
+            text("Updated the README build section."),
+            StreamEvent::Done,
+            StreamEvent::Done,
+        ]
+    );
+}
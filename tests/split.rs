@@ -0,0 +1,54 @@
+use neywa::discord::split_for_discord;
+
+#[test]
+fn short_text_is_one_chunk() {
+    assert_eq!(split_for_discord("hello"), vec!["hello".to_string()]);
+    assert_eq!(split_for_discord(""), vec!["(No response)".to_string()]);
+}
+
+#[test]
+fn long_text_fits_discord_limit() {
+    let text = (0..500).map(|i| format!("line number {}", i)).collect::<Vec<_>>().join("\n");
+    let chunks = split_for_discord(&text);
+    assert!(chunks.len() > 1);
+    assert!(chunks.iter().all(|c| c.chars().count() <= 2000));
+    assert_eq!(chunks.join("\n"), text);
+}
+
+#[test]
+fn code_blocks_are_reopened() {
+    let body = (0..300).map(|i| format!("let x{} = {};", i, i)).collect::<Vec<_>>().join("\n");
+    let text = format!("```rust\n{}\n```", body);
+    let chunks = split_for_discord(&text);
+    assert!(chunks.len() > 1);
+    for chunk in &chunks {
+        assert!(chunk.starts_with("```rust"), "{}", &chunk[..20]);
+        assert!(chunk.ends_with("```"));
+        assert!(chunk.len() <= 2000);
+    }
+
+    // Prose first, so the block opens partway into the first chunk, then a
+    // second block in another language
+    let prose = (0..40).map(|i| format!("sentence {}", i)).collect::<Vec<_>>().join("\n");
+    let python = (0..300).map(|i| format!("y{} = {}", i, i)).collect::<Vec<_>>().join("\n");
+    let text = format!("{}\n```rust\n{}\n```\nbetween\n```python\n{}\n```\nafter", prose, body, python);
+    let chunks = split_for_discord(&text);
+    assert!(chunks.len() > 3);
+    assert!(chunks[0].starts_with("sentence 0"));
+    assert!(chunks[0].contains("\n```rust\n"));
+    for chunk in &chunks {
+        assert!(chunk.len() <= 2000);
+        // Every chunk closes what it opens
+        assert_eq!(chunk.lines().filter(|l| l.starts_with("```")).count() % 2, 0, "{}", chunk);
+        // A chunk continuing a block reopens it with the same language
+        if chunk.contains("let x") && !chunk.contains("\n```rust\n") {
+            assert!(chunk.starts_with("```rust\n"), "{}", &chunk[..20]);
+        }
+        if chunk.contains("\ny1") && !chunk.contains("\n```python\n") {
+            assert!(chunk.starts_with("```python\n"), "{}", &chunk[..20]);
+        }
+    }
+    assert!(chunks.iter().any(|c| c.starts_with("```python")));
+    assert!(chunks.last().unwrap().ends_with("after"));
+    assert!(text.lines().all(|line| chunks.iter().any(|c| c.lines().any(|l| l == line))));
+}
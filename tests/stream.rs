@@ -0,0 +1,116 @@
+//! Stream parsers fed line by line, the way the spawn path drives them
+
+use neywa::claude::StreamEvent;
+use neywa::stream::{ClaudeParser, CodexParser};
+
+fn text(s: &str) -> StreamEvent {
+    StreamEvent::Text(s.to_string())
+}
+
+fn tool(name: &str, detail: &str) -> StreamEvent {
+    StreamEvent::ToolUse(name.to_string(), detail.to_string())
+}
+
+#[test]
+fn claude_ignores_non_json_lines() {
+    let mut parser = ClaudeParser::new(false);
+    assert!(parser.parse_line("").is_empty());
+    assert!(parser.parse_line("Warning: something on stdout").is_empty());
+    assert!(parser.parse_line("{not json").is_empty());
+}
+
+#[test]
+fn claude_session_id_is_sent_once() {
+    let mut parser = ClaudeParser::new(false);
+    assert_eq!(
+        parser.parse_line(r#"{"type":"system","subtype":"init","session_id":"abc"}"#),
+        vec![StreamEvent::SessionId("abc".to_string())]
+    );
+    assert!(parser.parse_line(r#"{"type":"system","session_id":"abc"}"#).is_empty());
+}
+
+#[test]
+fn claude_text_accumulates_across_assistant_events() {
+    let mut parser = ClaudeParser::new(false);
+    let first = r#"{"type":"assistant","message":{"content":[{"type":"text","text":"one"}]}}"#;
+    let second = r#"{"type":"assistant","message":{"content":[{"type":"text","text":"two"}]}}"#;
+    assert_eq!(parser.parse_line(first), vec![text("one")]);
+    assert_eq!(parser.parse_line(second), vec![text("one\ntwo")]);
+}
+
+#[test]
+fn claude_tool_results_match_their_tool_use() {
+    let mut parser = ClaudeParser::new(false);
+    let use_line = r#"{"type":"assistant","message":{"content":[{"type":"tool_use","id":"t1","name":"Bash","input":{"command":"cargo test"}}]}}"#;
+    let result_line = r#"{"type":"user","message":{"content":[{"type":"tool_result","tool_use_id":"t1","content":"Exit code 101\nerror"}]}}"#;
+    assert_eq!(parser.parse_line(use_line), vec![tool("Bash", "💻 cargo test")]);
+    assert_eq!(
+        parser.parse_line(result_line),
+        vec![StreamEvent::ToolResult { tool: "💻 cargo test".to_string(), ok: false, summary: "exit 101".to_string() }]
+    );
+    // Matched once: a repeated result for the same id is dropped
+    assert!(parser.parse_line(result_line).is_empty());
+}
+
+#[test]
+fn claude_plan_mode_skips_tool_results() {
+    let mut parser = ClaudeParser::new(true);
+    let result_line = r#"{"type":"user","message":{"content":[{"type":"tool_result","tool_use_id":"t1","content":"ok"}]}}"#;
+    assert!(parser.parse_line(result_line).is_empty());
+}
+
+#[test]
+fn claude_empty_result_keeps_streamed_text() {
+    let mut parser = ClaudeParser::new(true);
+    parser.parse_line(r#"{"type":"assistant","message":{"content":[{"type":"text","text":"plan ready"}]}}"#);
+    assert_eq!(parser.parse_line(r#"{"type":"result","result":""}"#), vec![StreamEvent::Done]);
+}
+
+#[test]
+fn claude_stderr_classification() {
+    assert!(ClaudeParser::is_context_overflow("Error: Prompt is too long"));
+    assert!(!ClaudeParser::is_context_overflow("Error: rate limited"));
+    assert!(ClaudeParser::is_missing_session("No conversation found with session ID: abc"));
+    assert!(!ClaudeParser::is_missing_session("Error: Prompt is too long"));
+}
+
+#[test]
+fn codex_thread_id_is_the_session_id() {
+    let mut parser = CodexParser::new();
+    let line = r#"{"type":"thread.started","thread_id":"th_1"}"#;
+    assert_eq!(parser.parse_line(line), vec![StreamEvent::SessionId("th_1".to_string())]);
+    assert!(parser.parse_line(line).is_empty());
+}
+
+#[test]
+fn codex_commands_and_messages() {
+    let mut parser = CodexParser::new();
+    assert_eq!(
+        parser.parse_line(r#"{"type":"item.started","item":{"type":"command_execution","command":"ls"}}"#),
+        vec![tool("Bash", "💻 ls")]
+    );
+    assert_eq!(
+        parser.parse_line(r#"{"type":"item.completed","item":{"type":"command_execution","command":"ls"}}"#),
+        vec![tool("Bash", "💻 ls ✓")]
+    );
+    assert!(parser.parse_line(r#"{"type":"item.completed","item":{"type":"reasoning","text":"hmm"}}"#).is_empty());
+    assert_eq!(
+        parser.parse_line(r#"{"type":"item.completed","item":{"type":"agent_message","text":"done"}}"#),
+        vec![text("done")]
+    );
+    assert_eq!(parser.parse_line(r#"{"type":"turn.completed"}"#), vec![StreamEvent::Done]);
+}
+
+#[test]
+fn codex_turn_failure_is_an_error() {
+    let mut parser = CodexParser::new();
+    assert_eq!(
+        parser.parse_line(r#"{"type":"turn.failed","message":"boom"}"#),
+        vec![StreamEvent::Error("boom".to_string())]
+    );
+    assert_eq!(
+        parser.parse_line(r#"{"type":"turn.failed"}"#),
+        vec![StreamEvent::Error("Unknown error".to_string())]
+    );
+    assert!(CodexParser::is_context_overflow("exceeded max_tokens"));
+}
@@ -0,0 +1,28 @@
+use neywa::update::{self, PendingUpdate};
+
+#[test]
+fn pending_update_keeps_its_file_format() {
+    // Written by older versions before restarting
+    let json = r#"{"channel_id":123,"old_version":"0.4.2","new_version":"0.4.3"}"#;
+    let pending: PendingUpdate = serde_json::from_str(json).unwrap();
+    assert_eq!(pending.channel_id, 123);
+    assert_eq!(serde_json::to_string(&pending).unwrap(), json);
+}
+
+#[test]
+fn completion_message_checks_the_running_version() {
+    let pending = PendingUpdate {
+        channel_id: 1,
+        old_version: "0.4.2".to_string(),
+        new_version: "0.4.3".to_string(),
+    };
+    assert_eq!(pending.completion_message("0.4.3"), "🎉 **Update complete!** v0.4.2 → v0.4.3");
+    assert_eq!(pending.completion_message("0.4.2"), "⚠️ Update done. Expected v0.4.3, running v0.4.2");
+}
+
+#[test]
+fn binaries_per_architecture() {
+    assert_eq!(update::binary_name("aarch64").as_deref(), Some("neywa-arm64"));
+    assert_eq!(update::binary_name("x86_64").as_deref(), Some("neywa-x86_64"));
+    assert_eq!(update::binary_name("riscv64"), None);
+}